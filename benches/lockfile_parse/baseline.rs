@@ -0,0 +1,148 @@
+//! The `lockfile_parser` graph builder as it was before names and versions
+//! were interned, kept so that the bench can compare against it.
+//!
+//! It parses the whole lockfile into a `toml::Value`, walks `[[package]]`
+//! twice and allocates a `String` and a `Version` for every package and
+//! every dependency edge. Only the printing of skipped packages is left out.
+
+use anyhow::{Context, Result};
+use semver::Version;
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PackageInfo {
+    pub name: String,
+    pub version: Version,
+    pub dependencies: Vec<DependencyInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DependencyInfo {
+    pub name: String,
+    pub version: Version,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DependencyGraph {
+    packages: BTreeMap<(String, Version), PackageInfo>,
+}
+
+impl DependencyGraph {
+    fn add_package(&mut self, package: PackageInfo) {
+        let key = (package.name.clone(), package.version.clone());
+        self.packages.insert(key, package);
+    }
+
+    pub fn len(&self) -> usize {
+        self.packages.len()
+    }
+}
+
+pub fn parse_lockfile_str(content: &str) -> Result<DependencyGraph> {
+    let lockfile: toml::Value =
+        toml::de::from_str(content).context("Failed to parse Cargo.lock as TOML")?;
+    build_dependency_graph_from_toml(&lockfile)
+}
+
+fn build_dependency_graph_from_toml(lockfile: &toml::Value) -> Result<DependencyGraph> {
+    let packages = lockfile
+        .get("package")
+        .and_then(|v| v.as_array())
+        .ok_or_else(|| anyhow::anyhow!("Cargo.lock missing 'package' array"))?;
+
+    // First pass: name -> versions of the registry packages
+    let mut name_to_versions: HashMap<String, Vec<Version>> = HashMap::new();
+    let mut skipped_packages = Vec::new();
+
+    for package in packages {
+        let name = package
+            .get("name")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow::anyhow!("Package missing 'name' field"))?;
+
+        let version_str = package
+            .get("version")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow::anyhow!("Package missing 'version' field"))?;
+
+        if let Some(source) = package.get("source").and_then(|v| v.as_str()) {
+            if !source.starts_with("registry+") {
+                skipped_packages.push(format!("{} {} (source: {})", name, version_str, source));
+                continue;
+            }
+        } else {
+            continue;
+        }
+
+        let version = Version::parse(version_str)
+            .with_context(|| format!("Failed to parse version for package '{}'", name))?;
+
+        name_to_versions
+            .entry(name.to_string())
+            .or_default()
+            .push(version);
+    }
+
+    // Second pass: the graph with resolved dependency versions
+    let mut graph = DependencyGraph::default();
+
+    for package in packages {
+        let name = package.get("name").and_then(|v| v.as_str()).unwrap();
+        let version_str = package.get("version").and_then(|v| v.as_str()).unwrap();
+
+        if let Some(source) = package.get("source").and_then(|v| v.as_str()) {
+            if !source.starts_with("registry+") {
+                continue;
+            }
+        } else {
+            continue;
+        }
+
+        let version = Version::parse(version_str).unwrap();
+
+        let mut dependencies = Vec::new();
+        if let Some(deps_array) = package.get("dependencies").and_then(|v| v.as_array()) {
+            for dep in deps_array {
+                if let Some(dep_str) = dep.as_str() {
+                    let parts: Vec<&str> = dep_str.split_whitespace().collect();
+                    let dep_name = parts[0];
+
+                    let dep_version = if parts.len() > 1 {
+                        Version::parse(parts[1]).ok()
+                    } else {
+                        None
+                    };
+
+                    let dep_version = dep_version.or_else(|| {
+                        name_to_versions.get(dep_name).and_then(|versions| {
+                            if versions.len() == 1 {
+                                Some(versions[0].clone())
+                            } else {
+                                versions.iter().max().cloned()
+                            }
+                        })
+                    });
+
+                    if let Some(version) = dep_version {
+                        dependencies.push(DependencyInfo {
+                            name: dep_name.to_string(),
+                            version,
+                        });
+                    }
+                }
+            }
+        }
+
+        dependencies.sort();
+        dependencies.dedup();
+
+        graph.add_package(PackageInfo {
+            name: name.to_string(),
+            version,
+            dependencies,
+        });
+    }
+
+    std::hint::black_box(skipped_packages);
+    Ok(graph)
+}
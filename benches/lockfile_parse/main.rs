@@ -0,0 +1,104 @@
+//! Times `lockfile_parser` on a large Cargo.lock, against the parser it
+//! replaced (see `baseline.rs`).
+//!
+//! ```text
+//! cargo bench --bench lockfile_parse                  # bevy-app.lock and a synthetic lockfile
+//! cargo bench --bench lockfile_parse -- path/to/Cargo.lock
+//! ```
+//!
+//! Real application lockfiles are the interesting case: every dependency
+//! edge used to allocate its own name and version. `bevy-app.lock` is the
+//! lockfile of an application depending on bevy 0.19 (553 packages).
+
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+use takopack::lockfile_parser::parse_lockfile_str;
+
+mod baseline;
+
+const ITERATIONS: u32 = 20;
+
+/// Lockfile of a real application, timed when no path is given.
+const BEVY_APP_LOCK: &str = include_str!("bevy-app.lock");
+
+/// Average time `parse` takes on `content`.
+fn time<T>(content: &str, parse: impl Fn(&str) -> anyhow::Result<T>) -> Duration {
+    let mut total = Duration::ZERO;
+    for _ in 0..ITERATIONS {
+        let start = Instant::now();
+        let graph = parse(content).expect("failed to parse lockfile");
+        total += start.elapsed();
+        std::hint::black_box(graph);
+    }
+    total / ITERATIONS
+}
+
+fn synthetic_lockfile(packages: usize) -> String {
+    let mut lock = String::from("version = 3\n");
+    for i in 0..packages {
+        let _ = write!(
+            lock,
+            "\n[[package]]\nname = \"crate-{}\"\nversion = \"1.{}.0\"\n\
+             source = \"registry+https://github.com/rust-lang/crates.io-index\"\n",
+            i % (packages / 2).max(1),
+            i / (packages / 2).max(1)
+        );
+        let deps: Vec<String> = (1..=8)
+            .filter(|offset| i >= *offset)
+            .map(|offset| {
+                let dep = i - offset;
+                format!(
+                    "\"crate-{} 1.{}.0\"",
+                    dep % (packages / 2).max(1),
+                    dep / (packages / 2).max(1)
+                )
+            })
+            .collect();
+        if !deps.is_empty() {
+            let _ = writeln!(lock, "dependencies = [{}]", deps.join(", "));
+        }
+    }
+    lock
+}
+
+fn main() {
+    let inputs = match std::env::args().skip(1).find(|arg| !arg.starts_with('-')) {
+        Some(path) => {
+            let content = std::fs::read_to_string(&path).expect("failed to read lockfile");
+            vec![(path, content)]
+        }
+        None => vec![
+            ("bevy-app.lock".to_string(), BEVY_APP_LOCK.to_string()),
+            (
+                "synthetic (5000 packages)".to_string(),
+                synthetic_lockfile(5000),
+            ),
+        ],
+    };
+    for (label, content) in inputs {
+        bench(&label, &content);
+    }
+}
+
+/// Time both parsers on `content` and print the results.
+fn bench(label: &str, content: &str) {
+    // Warm up and report the graph size once.
+    let graph = parse_lockfile_str(content).expect("failed to parse lockfile");
+    let edges: usize = graph.packages().map(|p| p.dependencies.len()).sum();
+    let old_graph = baseline::parse_lockfile_str(content).expect("failed to parse lockfile");
+    assert_eq!(graph.len(), old_graph.len(), "the parsers disagree");
+
+    let old = time(content, baseline::parse_lockfile_str);
+    let new = time(content, parse_lockfile_str);
+
+    println!("lockfile: {}", label);
+    println!("  packages: {}  edges: {}", graph.len(), edges);
+    println!("  per iteration ({} iterations):", ITERATIONS);
+    println!("    baseline: {:?}", old);
+    println!(
+        "    current:  {:?} ({:.2}x)",
+        new,
+        old.as_secs_f64() / new.as_secs_f64()
+    );
+}
@@ -0,0 +1,55 @@
+%global crate_name bitflags
+%global full_version 2.0.2
+%global pkgname bitflags-2
+
+Name:           rust-bitflags-2
+Version:        2.0.2
+Release:        %autorelease
+Summary:        Rust crate "bitflags"
+License:        MIT OR Apache-2.0
+URL:            https://github.com/bitflags/bitflags
+#!RemoteAsset:  sha256:487f1e0fcbe47deb8b0574e646def1c903389d95241dd1bbcc6ce4a715dfc0c1
+Source:         https://static.crates.io/crates/%{crate_name}/%{full_version}/download#/%{name}-%{version}.tar.gz
+BuildArch:      noarch
+BuildSystem:    rustcrates
+
+# Rust edition 2021, MSRV 1.56.0
+BuildRequires:  rust-rpm-macros
+BuildRequires:  rust >= 1.56.0
+
+Provides:       crate(%{pkgname}) = %{version}
+Provides:       crate(%{pkgname}/compiler-builtins) = %{version}
+Provides:       crate(%{pkgname}/core) = %{version}
+Provides:       crate(%{pkgname}/default) = %{version}
+Provides:       crate(%{pkgname}/example-generated) = %{version}
+Provides:       crate(%{pkgname}/std) = %{version}
+
+%description
+Source code for takopackized Rust crate "bitflags"
+
+%package     -n %{name}+rustc-dep-of-std
+Summary:        Macro to generate structures which behave like bitflags - feature "rustc-dep-of-std"
+Requires:       crate(%{pkgname}) = %{version}
+Requires:       crate(%{pkgname}/compiler-builtins) = %{version}
+Requires:       crate(%{pkgname}/core) = %{version}
+Provides:       crate(%{pkgname}/rustc-dep-of-std) = %{version}
+
+%description -n %{name}+rustc-dep-of-std
+This metapackage enables feature "rustc-dep-of-std" for the Rust bitflags crate, by pulling in any additional dependencies needed by that feature.
+
+%package     -n %{name}+serde
+Summary:        Macro to generate structures which behave like bitflags - feature "serde"
+Requires:       crate(%{pkgname}) = %{version}
+Requires:       crate(serde-1) >= 1.0.0
+Provides:       crate(%{pkgname}/serde) = %{version}
+
+%description -n %{name}+serde
+This metapackage enables feature "serde" for the Rust bitflags crate, by pulling in any additional dependencies needed by that feature.
+
+%files
+%license LICENSE-APACHE
+%license LICENSE-MIT
+%{_datadir}/cargo/registry/%{crate_name}-%{version}/
+
+%changelog
+%autochangelog
@@ -0,0 +1,75 @@
+%global crate_name imagequant-sys
+%global full_version 4.0.3
+%global pkgname imagequant-sys-4
+
+Name:           rust-imagequant-sys-4
+Version:        4.0.3
+Release:        %autorelease
+Summary:        Rust crate "imagequant-sys"
+License:        GPL-3.0-or-later
+URL:            https://pngquant.org/lib
+#!RemoteAsset:  sha256:399d307ead010ceffc6f45346b325f9de672da0fe05cfdfa5dec0e5551925e52
+Source:         https://static.crates.io/crates/%{crate_name}/%{full_version}/download#/%{name}-%{version}.tar.gz
+BuildSystem:    rustcrates
+
+# Rust edition 2021, MSRV 1.63
+BuildRequires:  rust-rpm-macros
+BuildRequires:  rust >= 1.63
+
+Requires:       crate(bitflags-2/default) >= 2.0.0
+Requires:       crate(imagequant-4/internal-c-ffi) >= 4.2.1
+Requires:       crate(libc-0.2/default) >= 0.2.112
+Provides:       crate(%{pkgname}) = %{version}
+Provides:       crate(%{pkgname}/capi) = %{version}
+
+%description
+C API/FFI libimagequant that powers pngquant lossy PNG compressor.
+Dual-licensed like pngquant. See https://pngquant.org for details.
+Source code for takopackized Rust crate "imagequant-sys"
+
+%package     -n %{name}+default
+Summary:        Convert 24/32-bit images to 8-bit palette with alpha channel - feature "default"
+Requires:       crate(%{pkgname}) = %{version}
+Requires:       crate(imagequant-4/default) >= 4.2.1
+Requires:       crate(imagequant-4/internal-c-ffi) >= 4.2.1
+Provides:       crate(%{pkgname}/default) = %{version}
+
+%description -n %{name}+default
+C API/FFI libimagequant that powers pngquant lossy PNG compressor.
+Dual-licensed like pngquant. See https://pngquant.org for details.
+This metapackage enables feature "default" for the Rust imagequant-sys crate, by pulling in any additional dependencies needed by that feature.
+
+%package     -n %{name}+threads
+Summary:        Convert 24/32-bit images to 8-bit palette with alpha channel - feature "threads"
+Requires:       crate(%{pkgname}) = %{version}
+Requires:       crate(imagequant-4/internal-c-ffi) >= 4.2.1
+Requires:       crate(imagequant-4/threads) >= 4.2.1
+Provides:       crate(%{pkgname}/threads) = %{version}
+
+%description -n %{name}+threads
+C API/FFI libimagequant that powers pngquant lossy PNG compressor.
+Dual-licensed like pngquant. See https://pngquant.org for details.
+This metapackage enables feature "threads" for the Rust imagequant-sys crate, by pulling in any additional dependencies needed by that feature.
+
+%package     -n libimagequant_sys-devel
+Summary:        Convert 24/32-bit images to 8-bit palette with alpha channel - C library development files
+
+%description -n libimagequant_sys-devel
+C API/FFI libimagequant that powers pngquant lossy PNG compressor.
+Dual-licensed like pngquant. See https://pngquant.org for details.
+This package contains the files for linking against libimagequant_sys.
+
+%build -a
+cargo build --release --offline --lib
+
+%install -a
+install -Dpm 0644 target/release/libimagequant_sys.a %{buildroot}%{_libdir}/libimagequant_sys.a
+
+%files
+%{_datadir}/cargo/registry/%{crate_name}-%{version}/
+
+%files -n libimagequant_sys-devel
+%{_libdir}/libimagequant_sys.a
+
+%changelog
+%autochangelog
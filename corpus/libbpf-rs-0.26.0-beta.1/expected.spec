@@ -0,0 +1,87 @@
+%global crate_name libbpf-rs
+%global full_version 0.26.0-beta.1
+%global pkgname libbpf-rs-0.26.0-beta.1
+
+Name:           rust-libbpf-rs-0.26.0-beta.1
+Version:        0.26.0
+Release:        %autorelease
+Summary:        Rust crate "libbpf-rs"
+License:        LGPL-2.1-only OR BSD-2-Clause
+URL:            https://github.com/libbpf/libbpf-rs
+#!RemoteAsset:  sha256:7129813a2bcf27f1751fa7bf88bee2e2e2cd81a41c0b9cf6a5aab16ab150ab2f
+Source:         https://static.crates.io/crates/%{crate_name}/%{full_version}/download#/%{name}-%{version}.tar.gz
+BuildArch:      noarch
+BuildSystem:    rustcrates
+
+# Rust edition 2021, MSRV 1.82
+BuildRequires:  rust-rpm-macros
+BuildRequires:  crate(libbpf-sys-1) >= 1.5.0
+BuildRequires:  crate(libbpf-sys-1/vendored-libbpf) >= 1.5.0
+BuildRequires:  rust >= 1.82
+
+Requires:       crate(bitflags-2/default) >= 2.0.0
+Requires:       crate(libbpf-sys-1) >= 1.5.0
+Requires:       crate(libc-0.2/default) >= 0.2.0
+Requires:       crate(vsprintf-2/default) >= 2.0.0
+Provides:       crate(%{pkgname}) = %{version}
+Provides:       crate(%{pkgname}/dont-generate-test-files) = %{version}
+
+%description
+Source code for takopackized Rust crate "libbpf-rs"
+
+%package     -n %{name}+default
+Summary:        Safe, idiomatic, and opinionated wrapper around libbpf-sys - feature "default" and 1 more
+Requires:       crate(%{pkgname}) = %{version}
+Requires:       crate(%{pkgname}/libbpf-sys) = %{version}
+Requires:       crate(libbpf-sys-1/vendored-libbpf) >= 1.5.0
+Provides:       crate(%{pkgname}/default) = %{version}
+Provides:       crate(%{pkgname}/generate-test-files) = %{version}
+
+%description -n %{name}+default
+Feature "default": By default the crate uses a vendored libbpf, but requires other necessary libs to be present on the system.
+
+This metapackage enables feature "default" for the Rust libbpf-rs crate, by pulling in any additional dependencies needed by that feature.
+
+Additionally, this package also provides the "generate-test-files" feature.
+
+%package     -n %{name}+libbpf-sys
+Summary:        Safe, idiomatic, and opinionated wrapper around libbpf-sys - feature "libbpf-sys"
+Requires:       crate(%{pkgname}) = %{version}
+Requires:       crate(libbpf-sys-1) >= 1.5.0
+Provides:       crate(%{pkgname}/libbpf-sys) = %{version}
+
+%description -n %{name}+libbpf-sys
+This metapackage enables feature "libbpf-sys" for the Rust libbpf-rs crate, by pulling in any additional dependencies needed by that feature.
+
+%package     -n %{name}+static
+Summary:        Safe, idiomatic, and opinionated wrapper around libbpf-sys - feature "static"
+Requires:       crate(%{pkgname}) = %{version}
+Requires:       crate(%{pkgname}/libbpf-sys) = %{version}
+Requires:       crate(libbpf-sys-1/static) >= 1.5.0
+Provides:       crate(%{pkgname}/static) = %{version}
+
+%description -n %{name}+static
+Feature "static": Link all required libraries statically.
+
+This metapackage enables feature "static" for the Rust libbpf-rs crate, by pulling in any additional dependencies needed by that feature.
+
+%package     -n %{name}+vendored
+Summary:        Safe, idiomatic, and opinionated wrapper around libbpf-sys - feature "vendored"
+Requires:       crate(%{pkgname}) = %{version}
+Requires:       crate(%{pkgname}/libbpf-sys) = %{version}
+Requires:       crate(libbpf-sys-1/vendored) >= 1.5.0
+Provides:       crate(%{pkgname}/vendored) = %{version}
+
+%description -n %{name}+vendored
+Feature "vendored": Use vendored versions of all required libraries.
+
+This metapackage enables feature "vendored" for the Rust libbpf-rs crate, by pulling in any additional dependencies needed by that feature.
+
+%files
+%license LICENSE
+%license LICENSE.BSD-2-Clause
+%license LICENSE.LGPL-2.1
+%{_datadir}/cargo/registry/%{crate_name}-%{version}/
+
+%changelog
+%autochangelog
@@ -0,0 +1,45 @@
+%global crate_name toml_datetime
+%global full_version 0.7.5+spec-1.1.0
+%global pkgname toml-datetime-0.7
+
+Name:           rust-toml-datetime-0.7
+Version:        0.7.5
+Release:        %autorelease
+Summary:        Rust crate "toml_datetime"
+License:        MIT OR Apache-2.0
+URL:            https://github.com/toml-rs/toml
+#!RemoteAsset:  sha256:92e1cfed4a3038bc5a127e35a2d360f145e1f4b971b551a2ba5fd7aedf7e1347
+Source:         https://static.crates.io/crates/%{crate_name}/%{full_version}/download#/%{name}-%{version}.tar.gz
+BuildArch:      noarch
+BuildSystem:    rustcrates
+
+# Rust edition 2021, MSRV 1.76
+BuildRequires:  rust-rpm-macros
+BuildRequires:  rust >= 1.76
+
+Requires:       (crate(serde-core-1/alloc) >= 1.0.225 if crate(serde-core-1))
+Requires:       crate(serde-core-1/std) >= 1.0.225
+Provides:       crate(%{pkgname}) = %{version}
+Provides:       crate(%{pkgname}/alloc) = %{version}
+Provides:       crate(%{pkgname}/default) = %{version}
+Provides:       crate(%{pkgname}/std) = %{version}
+
+%description
+Source code for takopackized Rust crate "toml_datetime"
+
+%package     -n %{name}+serde
+Summary:        TOML-compatible datetime type - feature "serde"
+Requires:       crate(%{pkgname}) = %{version}
+Requires:       crate(serde-core-1) >= 1.0.225
+Provides:       crate(%{pkgname}/serde) = %{version}
+
+%description -n %{name}+serde
+This metapackage enables feature "serde" for the Rust toml_datetime crate, by pulling in any additional dependencies needed by that feature.
+
+%files
+%license LICENSE-APACHE
+%license LICENSE-MIT
+%{_datadir}/cargo/registry/%{crate_name}-%{version}/
+
+%changelog
+%autochangelog
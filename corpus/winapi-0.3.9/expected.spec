@@ -0,0 +1,430 @@
+%global crate_name winapi
+%global full_version 0.3.9
+%global pkgname winapi-0.3
+
+Name:           rust-winapi-0.3
+Version:        0.3.9
+Release:        %autorelease
+Summary:        Rust crate "winapi"
+License:        MIT OR Apache-2.0
+URL:            https://github.com/retep998/winapi-rs
+#!RemoteAsset:  sha256:5c839a674fcd7a98952e593242ea400abe93992746761e38641405d28b00f419
+Source:         https://static.crates.io/crates/%{crate_name}/%{full_version}/download#/%{name}-%{version}.tar.gz
+BuildArch:      noarch
+BuildSystem:    rustcrates
+
+# Rust edition 2015
+BuildRequires:  rust-rpm-macros
+
+Provides:       crate(%{pkgname}) = %{version}
+Provides:       crate(%{pkgname}/accctrl) = %{version}
+Provides:       crate(%{pkgname}/aclapi) = %{version}
+Provides:       crate(%{pkgname}/activation) = %{version}
+Provides:       crate(%{pkgname}/adhoc) = %{version}
+Provides:       crate(%{pkgname}/appmgmt) = %{version}
+Provides:       crate(%{pkgname}/audioclient) = %{version}
+Provides:       crate(%{pkgname}/audiosessiontypes) = %{version}
+Provides:       crate(%{pkgname}/avrt) = %{version}
+Provides:       crate(%{pkgname}/basetsd) = %{version}
+Provides:       crate(%{pkgname}/bcrypt) = %{version}
+Provides:       crate(%{pkgname}/bits) = %{version}
+Provides:       crate(%{pkgname}/bits1-5) = %{version}
+Provides:       crate(%{pkgname}/bits10-1) = %{version}
+Provides:       crate(%{pkgname}/bits2-0) = %{version}
+Provides:       crate(%{pkgname}/bits2-5) = %{version}
+Provides:       crate(%{pkgname}/bits3-0) = %{version}
+Provides:       crate(%{pkgname}/bits4-0) = %{version}
+Provides:       crate(%{pkgname}/bits5-0) = %{version}
+Provides:       crate(%{pkgname}/bitscfg) = %{version}
+Provides:       crate(%{pkgname}/bitsmsg) = %{version}
+Provides:       crate(%{pkgname}/bluetoothapis) = %{version}
+Provides:       crate(%{pkgname}/bluetoothleapis) = %{version}
+Provides:       crate(%{pkgname}/bthdef) = %{version}
+Provides:       crate(%{pkgname}/bthioctl) = %{version}
+Provides:       crate(%{pkgname}/bthledef) = %{version}
+Provides:       crate(%{pkgname}/bthsdpdef) = %{version}
+Provides:       crate(%{pkgname}/bugcodes) = %{version}
+Provides:       crate(%{pkgname}/cderr) = %{version}
+Provides:       crate(%{pkgname}/cfg) = %{version}
+Provides:       crate(%{pkgname}/cfgmgr32) = %{version}
+Provides:       crate(%{pkgname}/cguid) = %{version}
+Provides:       crate(%{pkgname}/combaseapi) = %{version}
+Provides:       crate(%{pkgname}/coml2api) = %{version}
+Provides:       crate(%{pkgname}/commapi) = %{version}
+Provides:       crate(%{pkgname}/commctrl) = %{version}
+Provides:       crate(%{pkgname}/commdlg) = %{version}
+Provides:       crate(%{pkgname}/commoncontrols) = %{version}
+Provides:       crate(%{pkgname}/consoleapi) = %{version}
+Provides:       crate(%{pkgname}/corecrt) = %{version}
+Provides:       crate(%{pkgname}/corsym) = %{version}
+Provides:       crate(%{pkgname}/d2d1) = %{version}
+Provides:       crate(%{pkgname}/d2d1-1) = %{version}
+Provides:       crate(%{pkgname}/d2d1-2) = %{version}
+Provides:       crate(%{pkgname}/d2d1-3) = %{version}
+Provides:       crate(%{pkgname}/d2d1effectauthor) = %{version}
+Provides:       crate(%{pkgname}/d2d1effects) = %{version}
+Provides:       crate(%{pkgname}/d2d1effects-1) = %{version}
+Provides:       crate(%{pkgname}/d2d1effects-2) = %{version}
+Provides:       crate(%{pkgname}/d2d1svg) = %{version}
+Provides:       crate(%{pkgname}/d2dbasetypes) = %{version}
+Provides:       crate(%{pkgname}/d3d) = %{version}
+Provides:       crate(%{pkgname}/d3d10) = %{version}
+Provides:       crate(%{pkgname}/d3d10-1) = %{version}
+Provides:       crate(%{pkgname}/d3d10-1shader) = %{version}
+Provides:       crate(%{pkgname}/d3d10effect) = %{version}
+Provides:       crate(%{pkgname}/d3d10misc) = %{version}
+Provides:       crate(%{pkgname}/d3d10sdklayers) = %{version}
+Provides:       crate(%{pkgname}/d3d10shader) = %{version}
+Provides:       crate(%{pkgname}/d3d11) = %{version}
+Provides:       crate(%{pkgname}/d3d11-1) = %{version}
+Provides:       crate(%{pkgname}/d3d11-2) = %{version}
+Provides:       crate(%{pkgname}/d3d11-3) = %{version}
+Provides:       crate(%{pkgname}/d3d11-4) = %{version}
+Provides:       crate(%{pkgname}/d3d11on12) = %{version}
+Provides:       crate(%{pkgname}/d3d11sdklayers) = %{version}
+Provides:       crate(%{pkgname}/d3d11shader) = %{version}
+Provides:       crate(%{pkgname}/d3d11tokenizedprogramformat) = %{version}
+Provides:       crate(%{pkgname}/d3d12) = %{version}
+Provides:       crate(%{pkgname}/d3d12sdklayers) = %{version}
+Provides:       crate(%{pkgname}/d3d12shader) = %{version}
+Provides:       crate(%{pkgname}/d3d9) = %{version}
+Provides:       crate(%{pkgname}/d3d9caps) = %{version}
+Provides:       crate(%{pkgname}/d3d9types) = %{version}
+Provides:       crate(%{pkgname}/d3dcommon) = %{version}
+Provides:       crate(%{pkgname}/d3dcompiler) = %{version}
+Provides:       crate(%{pkgname}/d3dcsx) = %{version}
+Provides:       crate(%{pkgname}/d3dkmdt) = %{version}
+Provides:       crate(%{pkgname}/d3dkmthk) = %{version}
+Provides:       crate(%{pkgname}/d3dukmdt) = %{version}
+Provides:       crate(%{pkgname}/d3dx10core) = %{version}
+Provides:       crate(%{pkgname}/d3dx10math) = %{version}
+Provides:       crate(%{pkgname}/d3dx10mesh) = %{version}
+Provides:       crate(%{pkgname}/datetimeapi) = %{version}
+Provides:       crate(%{pkgname}/davclnt) = %{version}
+Provides:       crate(%{pkgname}/dbghelp) = %{version}
+Provides:       crate(%{pkgname}/dbt) = %{version}
+Provides:       crate(%{pkgname}/dcommon) = %{version}
+Provides:       crate(%{pkgname}/dcomp) = %{version}
+Provides:       crate(%{pkgname}/dcompanimation) = %{version}
+Provides:       crate(%{pkgname}/dcomptypes) = %{version}
+Provides:       crate(%{pkgname}/dde) = %{version}
+Provides:       crate(%{pkgname}/ddraw) = %{version}
+Provides:       crate(%{pkgname}/ddrawi) = %{version}
+Provides:       crate(%{pkgname}/ddrawint) = %{version}
+Provides:       crate(%{pkgname}/debug) = %{version}
+Provides:       crate(%{pkgname}/debugapi) = %{version}
+Provides:       crate(%{pkgname}/default) = %{version}
+Provides:       crate(%{pkgname}/devguid) = %{version}
+Provides:       crate(%{pkgname}/devicetopology) = %{version}
+Provides:       crate(%{pkgname}/devpkey) = %{version}
+Provides:       crate(%{pkgname}/devpropdef) = %{version}
+Provides:       crate(%{pkgname}/dinput) = %{version}
+Provides:       crate(%{pkgname}/dinputd) = %{version}
+Provides:       crate(%{pkgname}/dispex) = %{version}
+Provides:       crate(%{pkgname}/dmksctl) = %{version}
+Provides:       crate(%{pkgname}/dmusicc) = %{version}
+Provides:       crate(%{pkgname}/docobj) = %{version}
+Provides:       crate(%{pkgname}/documenttarget) = %{version}
+Provides:       crate(%{pkgname}/dot1x) = %{version}
+Provides:       crate(%{pkgname}/dpa-dsa) = %{version}
+Provides:       crate(%{pkgname}/dpapi) = %{version}
+Provides:       crate(%{pkgname}/dsgetdc) = %{version}
+Provides:       crate(%{pkgname}/dsound) = %{version}
+Provides:       crate(%{pkgname}/dsrole) = %{version}
+Provides:       crate(%{pkgname}/dvp) = %{version}
+Provides:       crate(%{pkgname}/dwmapi) = %{version}
+Provides:       crate(%{pkgname}/dwrite) = %{version}
+Provides:       crate(%{pkgname}/dwrite-1) = %{version}
+Provides:       crate(%{pkgname}/dwrite-2) = %{version}
+Provides:       crate(%{pkgname}/dwrite-3) = %{version}
+Provides:       crate(%{pkgname}/dxdiag) = %{version}
+Provides:       crate(%{pkgname}/dxfile) = %{version}
+Provides:       crate(%{pkgname}/dxgi) = %{version}
+Provides:       crate(%{pkgname}/dxgi1-2) = %{version}
+Provides:       crate(%{pkgname}/dxgi1-3) = %{version}
+Provides:       crate(%{pkgname}/dxgi1-4) = %{version}
+Provides:       crate(%{pkgname}/dxgi1-5) = %{version}
+Provides:       crate(%{pkgname}/dxgi1-6) = %{version}
+Provides:       crate(%{pkgname}/dxgidebug) = %{version}
+Provides:       crate(%{pkgname}/dxgiformat) = %{version}
+Provides:       crate(%{pkgname}/dxgitype) = %{version}
+Provides:       crate(%{pkgname}/dxva2api) = %{version}
+Provides:       crate(%{pkgname}/dxvahd) = %{version}
+Provides:       crate(%{pkgname}/eaptypes) = %{version}
+Provides:       crate(%{pkgname}/enclaveapi) = %{version}
+Provides:       crate(%{pkgname}/endpointvolume) = %{version}
+Provides:       crate(%{pkgname}/errhandlingapi) = %{version}
+Provides:       crate(%{pkgname}/everything) = %{version}
+Provides:       crate(%{pkgname}/evntcons) = %{version}
+Provides:       crate(%{pkgname}/evntprov) = %{version}
+Provides:       crate(%{pkgname}/evntrace) = %{version}
+Provides:       crate(%{pkgname}/excpt) = %{version}
+Provides:       crate(%{pkgname}/exdisp) = %{version}
+Provides:       crate(%{pkgname}/fibersapi) = %{version}
+Provides:       crate(%{pkgname}/fileapi) = %{version}
+Provides:       crate(%{pkgname}/functiondiscoverykeys-devpkey) = %{version}
+Provides:       crate(%{pkgname}/gl-gl) = %{version}
+Provides:       crate(%{pkgname}/guiddef) = %{version}
+Provides:       crate(%{pkgname}/handleapi) = %{version}
+Provides:       crate(%{pkgname}/heapapi) = %{version}
+Provides:       crate(%{pkgname}/hidclass) = %{version}
+Provides:       crate(%{pkgname}/hidpi) = %{version}
+Provides:       crate(%{pkgname}/hidsdi) = %{version}
+Provides:       crate(%{pkgname}/hidusage) = %{version}
+Provides:       crate(%{pkgname}/highlevelmonitorconfigurationapi) = %{version}
+Provides:       crate(%{pkgname}/hstring) = %{version}
+Provides:       crate(%{pkgname}/http) = %{version}
+Provides:       crate(%{pkgname}/ifdef) = %{version}
+Provides:       crate(%{pkgname}/ifmib) = %{version}
+Provides:       crate(%{pkgname}/imm) = %{version}
+Provides:       crate(%{pkgname}/impl-debug) = %{version}
+Provides:       crate(%{pkgname}/impl-default) = %{version}
+Provides:       crate(%{pkgname}/in6addr) = %{version}
+Provides:       crate(%{pkgname}/inaddr) = %{version}
+Provides:       crate(%{pkgname}/inspectable) = %{version}
+Provides:       crate(%{pkgname}/interlockedapi) = %{version}
+Provides:       crate(%{pkgname}/intsafe) = %{version}
+Provides:       crate(%{pkgname}/ioapiset) = %{version}
+Provides:       crate(%{pkgname}/ipexport) = %{version}
+Provides:       crate(%{pkgname}/iphlpapi) = %{version}
+Provides:       crate(%{pkgname}/ipifcons) = %{version}
+Provides:       crate(%{pkgname}/ipmib) = %{version}
+Provides:       crate(%{pkgname}/iprtrmib) = %{version}
+Provides:       crate(%{pkgname}/iptypes) = %{version}
+Provides:       crate(%{pkgname}/jobapi) = %{version}
+Provides:       crate(%{pkgname}/jobapi2) = %{version}
+Provides:       crate(%{pkgname}/knownfolders) = %{version}
+Provides:       crate(%{pkgname}/ks) = %{version}
+Provides:       crate(%{pkgname}/ksmedia) = %{version}
+Provides:       crate(%{pkgname}/ktmtypes) = %{version}
+Provides:       crate(%{pkgname}/ktmw32) = %{version}
+Provides:       crate(%{pkgname}/l2cmn) = %{version}
+Provides:       crate(%{pkgname}/libloaderapi) = %{version}
+Provides:       crate(%{pkgname}/limits) = %{version}
+Provides:       crate(%{pkgname}/lmaccess) = %{version}
+Provides:       crate(%{pkgname}/lmalert) = %{version}
+Provides:       crate(%{pkgname}/lmapibuf) = %{version}
+Provides:       crate(%{pkgname}/lmat) = %{version}
+Provides:       crate(%{pkgname}/lmcons) = %{version}
+Provides:       crate(%{pkgname}/lmdfs) = %{version}
+Provides:       crate(%{pkgname}/lmerrlog) = %{version}
+Provides:       crate(%{pkgname}/lmjoin) = %{version}
+Provides:       crate(%{pkgname}/lmmsg) = %{version}
+Provides:       crate(%{pkgname}/lmremutl) = %{version}
+Provides:       crate(%{pkgname}/lmrepl) = %{version}
+Provides:       crate(%{pkgname}/lmserver) = %{version}
+Provides:       crate(%{pkgname}/lmshare) = %{version}
+Provides:       crate(%{pkgname}/lmstats) = %{version}
+Provides:       crate(%{pkgname}/lmsvc) = %{version}
+Provides:       crate(%{pkgname}/lmuse) = %{version}
+Provides:       crate(%{pkgname}/lmwksta) = %{version}
+Provides:       crate(%{pkgname}/lowlevelmonitorconfigurationapi) = %{version}
+Provides:       crate(%{pkgname}/lsalookup) = %{version}
+Provides:       crate(%{pkgname}/memoryapi) = %{version}
+Provides:       crate(%{pkgname}/minschannel) = %{version}
+Provides:       crate(%{pkgname}/minwinbase) = %{version}
+Provides:       crate(%{pkgname}/minwindef) = %{version}
+Provides:       crate(%{pkgname}/mmdeviceapi) = %{version}
+Provides:       crate(%{pkgname}/mmeapi) = %{version}
+Provides:       crate(%{pkgname}/mmreg) = %{version}
+Provides:       crate(%{pkgname}/mmsystem) = %{version}
+Provides:       crate(%{pkgname}/mprapidef) = %{version}
+Provides:       crate(%{pkgname}/msaatext) = %{version}
+Provides:       crate(%{pkgname}/mscat) = %{version}
+Provides:       crate(%{pkgname}/mschapp) = %{version}
+Provides:       crate(%{pkgname}/mssip) = %{version}
+Provides:       crate(%{pkgname}/mstcpip) = %{version}
+Provides:       crate(%{pkgname}/mswsock) = %{version}
+Provides:       crate(%{pkgname}/mswsockdef) = %{version}
+Provides:       crate(%{pkgname}/namedpipeapi) = %{version}
+Provides:       crate(%{pkgname}/namespaceapi) = %{version}
+Provides:       crate(%{pkgname}/nb30) = %{version}
+Provides:       crate(%{pkgname}/ncrypt) = %{version}
+Provides:       crate(%{pkgname}/netioapi) = %{version}
+Provides:       crate(%{pkgname}/nldef) = %{version}
+Provides:       crate(%{pkgname}/ntddndis) = %{version}
+Provides:       crate(%{pkgname}/ntddscsi) = %{version}
+Provides:       crate(%{pkgname}/ntddser) = %{version}
+Provides:       crate(%{pkgname}/ntdef) = %{version}
+Provides:       crate(%{pkgname}/ntlsa) = %{version}
+Provides:       crate(%{pkgname}/ntsecapi) = %{version}
+Provides:       crate(%{pkgname}/ntstatus) = %{version}
+Provides:       crate(%{pkgname}/oaidl) = %{version}
+Provides:       crate(%{pkgname}/objbase) = %{version}
+Provides:       crate(%{pkgname}/objidl) = %{version}
+Provides:       crate(%{pkgname}/objidlbase) = %{version}
+Provides:       crate(%{pkgname}/ocidl) = %{version}
+Provides:       crate(%{pkgname}/ole2) = %{version}
+Provides:       crate(%{pkgname}/oleauto) = %{version}
+Provides:       crate(%{pkgname}/olectl) = %{version}
+Provides:       crate(%{pkgname}/oleidl) = %{version}
+Provides:       crate(%{pkgname}/opmapi) = %{version}
+Provides:       crate(%{pkgname}/pdh) = %{version}
+Provides:       crate(%{pkgname}/perflib) = %{version}
+Provides:       crate(%{pkgname}/physicalmonitorenumerationapi) = %{version}
+Provides:       crate(%{pkgname}/playsoundapi) = %{version}
+Provides:       crate(%{pkgname}/portabledevice) = %{version}
+Provides:       crate(%{pkgname}/portabledeviceapi) = %{version}
+Provides:       crate(%{pkgname}/portabledevicetypes) = %{version}
+Provides:       crate(%{pkgname}/powerbase) = %{version}
+Provides:       crate(%{pkgname}/powersetting) = %{version}
+Provides:       crate(%{pkgname}/powrprof) = %{version}
+Provides:       crate(%{pkgname}/processenv) = %{version}
+Provides:       crate(%{pkgname}/processsnapshot) = %{version}
+Provides:       crate(%{pkgname}/processthreadsapi) = %{version}
+Provides:       crate(%{pkgname}/processtopologyapi) = %{version}
+Provides:       crate(%{pkgname}/profileapi) = %{version}
+Provides:       crate(%{pkgname}/propidl) = %{version}
+Provides:       crate(%{pkgname}/propkey) = %{version}
+Provides:       crate(%{pkgname}/propkeydef) = %{version}
+Provides:       crate(%{pkgname}/propsys) = %{version}
+Provides:       crate(%{pkgname}/prsht) = %{version}
+Provides:       crate(%{pkgname}/psapi) = %{version}
+Provides:       crate(%{pkgname}/qos) = %{version}
+Provides:       crate(%{pkgname}/realtimeapiset) = %{version}
+Provides:       crate(%{pkgname}/reason) = %{version}
+Provides:       crate(%{pkgname}/restartmanager) = %{version}
+Provides:       crate(%{pkgname}/restrictederrorinfo) = %{version}
+Provides:       crate(%{pkgname}/rmxfguid) = %{version}
+Provides:       crate(%{pkgname}/roapi) = %{version}
+Provides:       crate(%{pkgname}/robuffer) = %{version}
+Provides:       crate(%{pkgname}/roerrorapi) = %{version}
+Provides:       crate(%{pkgname}/rpc) = %{version}
+Provides:       crate(%{pkgname}/rpcdce) = %{version}
+Provides:       crate(%{pkgname}/rpcndr) = %{version}
+Provides:       crate(%{pkgname}/rtinfo) = %{version}
+Provides:       crate(%{pkgname}/sapi) = %{version}
+Provides:       crate(%{pkgname}/sapi51) = %{version}
+Provides:       crate(%{pkgname}/sapi53) = %{version}
+Provides:       crate(%{pkgname}/sapiddk) = %{version}
+Provides:       crate(%{pkgname}/sapiddk51) = %{version}
+Provides:       crate(%{pkgname}/schannel) = %{version}
+Provides:       crate(%{pkgname}/sddl) = %{version}
+Provides:       crate(%{pkgname}/securityappcontainer) = %{version}
+Provides:       crate(%{pkgname}/securitybaseapi) = %{version}
+Provides:       crate(%{pkgname}/servprov) = %{version}
+Provides:       crate(%{pkgname}/setupapi) = %{version}
+Provides:       crate(%{pkgname}/shellapi) = %{version}
+Provides:       crate(%{pkgname}/shellscalingapi) = %{version}
+Provides:       crate(%{pkgname}/shlobj) = %{version}
+Provides:       crate(%{pkgname}/shobjidl) = %{version}
+Provides:       crate(%{pkgname}/shobjidl-core) = %{version}
+Provides:       crate(%{pkgname}/shtypes) = %{version}
+Provides:       crate(%{pkgname}/softpub) = %{version}
+Provides:       crate(%{pkgname}/spapidef) = %{version}
+Provides:       crate(%{pkgname}/spellcheck) = %{version}
+Provides:       crate(%{pkgname}/sporder) = %{version}
+Provides:       crate(%{pkgname}/sql) = %{version}
+Provides:       crate(%{pkgname}/sqlext) = %{version}
+Provides:       crate(%{pkgname}/sqltypes) = %{version}
+Provides:       crate(%{pkgname}/sqlucode) = %{version}
+Provides:       crate(%{pkgname}/sspi) = %{version}
+Provides:       crate(%{pkgname}/std) = %{version}
+Provides:       crate(%{pkgname}/stralign) = %{version}
+Provides:       crate(%{pkgname}/stringapiset) = %{version}
+Provides:       crate(%{pkgname}/strmif) = %{version}
+Provides:       crate(%{pkgname}/subauth) = %{version}
+Provides:       crate(%{pkgname}/synchapi) = %{version}
+Provides:       crate(%{pkgname}/sysinfoapi) = %{version}
+Provides:       crate(%{pkgname}/systemtopologyapi) = %{version}
+Provides:       crate(%{pkgname}/taskschd) = %{version}
+Provides:       crate(%{pkgname}/tcpestats) = %{version}
+Provides:       crate(%{pkgname}/tcpmib) = %{version}
+Provides:       crate(%{pkgname}/textstor) = %{version}
+Provides:       crate(%{pkgname}/threadpoolapiset) = %{version}
+Provides:       crate(%{pkgname}/threadpoollegacyapiset) = %{version}
+Provides:       crate(%{pkgname}/timeapi) = %{version}
+Provides:       crate(%{pkgname}/timezoneapi) = %{version}
+Provides:       crate(%{pkgname}/tlhelp32) = %{version}
+Provides:       crate(%{pkgname}/transportsettingcommon) = %{version}
+Provides:       crate(%{pkgname}/tvout) = %{version}
+Provides:       crate(%{pkgname}/udpmib) = %{version}
+Provides:       crate(%{pkgname}/unknwnbase) = %{version}
+Provides:       crate(%{pkgname}/urlhist) = %{version}
+Provides:       crate(%{pkgname}/urlmon) = %{version}
+Provides:       crate(%{pkgname}/usb) = %{version}
+Provides:       crate(%{pkgname}/usbioctl) = %{version}
+Provides:       crate(%{pkgname}/usbiodef) = %{version}
+Provides:       crate(%{pkgname}/usbscan) = %{version}
+Provides:       crate(%{pkgname}/usbspec) = %{version}
+Provides:       crate(%{pkgname}/userenv) = %{version}
+Provides:       crate(%{pkgname}/usp10) = %{version}
+Provides:       crate(%{pkgname}/utilapiset) = %{version}
+Provides:       crate(%{pkgname}/uxtheme) = %{version}
+Provides:       crate(%{pkgname}/vadefs) = %{version}
+Provides:       crate(%{pkgname}/vcruntime) = %{version}
+Provides:       crate(%{pkgname}/vsbackup) = %{version}
+Provides:       crate(%{pkgname}/vss) = %{version}
+Provides:       crate(%{pkgname}/vsserror) = %{version}
+Provides:       crate(%{pkgname}/vswriter) = %{version}
+Provides:       crate(%{pkgname}/wbemads) = %{version}
+Provides:       crate(%{pkgname}/wbemcli) = %{version}
+Provides:       crate(%{pkgname}/wbemdisp) = %{version}
+Provides:       crate(%{pkgname}/wbemprov) = %{version}
+Provides:       crate(%{pkgname}/wbemtran) = %{version}
+Provides:       crate(%{pkgname}/wct) = %{version}
+Provides:       crate(%{pkgname}/werapi) = %{version}
+Provides:       crate(%{pkgname}/winbase) = %{version}
+Provides:       crate(%{pkgname}/wincodec) = %{version}
+Provides:       crate(%{pkgname}/wincodecsdk) = %{version}
+Provides:       crate(%{pkgname}/wincon) = %{version}
+Provides:       crate(%{pkgname}/wincontypes) = %{version}
+Provides:       crate(%{pkgname}/wincred) = %{version}
+Provides:       crate(%{pkgname}/wincrypt) = %{version}
+Provides:       crate(%{pkgname}/windef) = %{version}
+Provides:       crate(%{pkgname}/windot11) = %{version}
+Provides:       crate(%{pkgname}/windowsceip) = %{version}
+Provides:       crate(%{pkgname}/windowsx) = %{version}
+Provides:       crate(%{pkgname}/winefs) = %{version}
+Provides:       crate(%{pkgname}/winerror) = %{version}
+Provides:       crate(%{pkgname}/winevt) = %{version}
+Provides:       crate(%{pkgname}/wingdi) = %{version}
+Provides:       crate(%{pkgname}/winhttp) = %{version}
+Provides:       crate(%{pkgname}/wininet) = %{version}
+Provides:       crate(%{pkgname}/winineti) = %{version}
+Provides:       crate(%{pkgname}/winioctl) = %{version}
+Provides:       crate(%{pkgname}/winnetwk) = %{version}
+Provides:       crate(%{pkgname}/winnls) = %{version}
+Provides:       crate(%{pkgname}/winnt) = %{version}
+Provides:       crate(%{pkgname}/winreg) = %{version}
+Provides:       crate(%{pkgname}/winsafer) = %{version}
+Provides:       crate(%{pkgname}/winscard) = %{version}
+Provides:       crate(%{pkgname}/winsmcrd) = %{version}
+Provides:       crate(%{pkgname}/winsock2) = %{version}
+Provides:       crate(%{pkgname}/winspool) = %{version}
+Provides:       crate(%{pkgname}/winstring) = %{version}
+Provides:       crate(%{pkgname}/winsvc) = %{version}
+Provides:       crate(%{pkgname}/wintrust) = %{version}
+Provides:       crate(%{pkgname}/winusb) = %{version}
+Provides:       crate(%{pkgname}/winusbio) = %{version}
+Provides:       crate(%{pkgname}/winuser) = %{version}
+Provides:       crate(%{pkgname}/winver) = %{version}
+Provides:       crate(%{pkgname}/wlanapi) = %{version}
+Provides:       crate(%{pkgname}/wlanihv) = %{version}
+Provides:       crate(%{pkgname}/wlanihvtypes) = %{version}
+Provides:       crate(%{pkgname}/wlantypes) = %{version}
+Provides:       crate(%{pkgname}/wlclient) = %{version}
+Provides:       crate(%{pkgname}/wmistr) = %{version}
+Provides:       crate(%{pkgname}/wnnc) = %{version}
+Provides:       crate(%{pkgname}/wow64apiset) = %{version}
+Provides:       crate(%{pkgname}/wpdmtpextensions) = %{version}
+Provides:       crate(%{pkgname}/ws2bth) = %{version}
+Provides:       crate(%{pkgname}/ws2def) = %{version}
+Provides:       crate(%{pkgname}/ws2ipdef) = %{version}
+Provides:       crate(%{pkgname}/ws2spi) = %{version}
+Provides:       crate(%{pkgname}/ws2tcpip) = %{version}
+Provides:       crate(%{pkgname}/wtsapi32) = %{version}
+Provides:       crate(%{pkgname}/wtypes) = %{version}
+Provides:       crate(%{pkgname}/wtypesbase) = %{version}
+Provides:       crate(%{pkgname}/xinput) = %{version}
+
+%description
+Source code for takopackized Rust crate "winapi"
+
+%files
+%license LICENSE-APACHE
+%license LICENSE-MIT
+%{_datadir}/cargo/registry/%{crate_name}-%{version}/
+
+%changelog
+%autochangelog
@@ -0,0 +1,219 @@
+//! Generating specs from other programs.
+//!
+//! The commands print as they go, read the takopack.toml of the current
+//! directory and keep caches in the directories of the user, which suits a
+//! terminal but not a service generating specs on demand. [`generate_spec`]
+//! runs the pipeline of `pkg` for one crate and returns the spec as a
+//! value: what the run would have printed is returned with it, the crate is
+//! unpacked in a temporary directory that is removed afterwards, and the
+//! caches, `CARGO_HOME` included, go to the state directory of the request,
+//! a temporary one unless it names one. The current directory is left
+//! alone, and the proxy, certificates and mirror of the request's config
+//! only apply to its own thread.
+
+use std::path::PathBuf;
+
+use anyhow::Context;
+use semver::Version;
+
+use crate::errors::*;
+use crate::http;
+use crate::package::{
+    DownloadArgs, PackageExecuteArgs, PackageExtractArgs, PackageInitArgs, PackageProcess,
+};
+use crate::paths::{self, Isolation};
+use crate::progress;
+use crate::takopack::changelog::ChangelogSource;
+use crate::test_mode::TestMode;
+
+/// The crate [`generate_spec`] packages, and how.
+#[derive(Debug, Clone, Default)]
+pub struct SpecRequest {
+    /// Name of the crate
+    pub crate_name: String,
+    /// Version of the crate; the latest without one
+    pub version: Option<String>,
+    /// Local crates mirror to read the crate from instead of the network
+    pub mirror: Option<PathBuf>,
+    /// Cargo registry to download the crate from instead of crates.io
+    pub registry: Option<String>,
+    /// takopack.toml to package the crate with; the defaults without one
+    pub config: Option<PathBuf>,
+    /// Target distro profile, as `--profile` selects
+    pub profile: Option<String>,
+    /// Add `BuildRequires:` for the dev-dependencies the tests need
+    pub with_tests: bool,
+    /// Directory for the caches and `CARGO_HOME`; reusing one between
+    /// requests saves downloads. A temporary directory without one.
+    pub state_dir: Option<PathBuf>,
+}
+
+/// The spec [`generate_spec`] generated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpecOutput {
+    /// Name of the crate
+    pub crate_name: String,
+    /// Version of the crate
+    pub version: Version,
+    /// Directory the package goes in, like `rust-serde-1`
+    pub package_dir: String,
+    /// File name of the spec, like `rust-serde-1.spec`
+    pub spec_file: String,
+    /// Text of the spec
+    pub spec: String,
+    /// Warnings and notes the run would have printed
+    pub messages: Vec<String>,
+}
+
+/// Generate the spec of the crate of `request` without printing, changing
+/// the current directory or writing to the directories of the user.
+pub fn generate_spec(request: SpecRequest) -> Result<SpecOutput> {
+    if let Some(path) = &request.config {
+        if !path.is_file() {
+            takopack_bail!("{} is not a takopack.toml", path.display());
+        }
+    }
+    let temp_state;
+    let state_dir = match &request.state_dir {
+        Some(dir) => dir.clone(),
+        None => {
+            temp_state = tempfile::Builder::new()
+                .prefix("takopack-state-")
+                .tempdir()
+                .context("Failed to create temporary directory")?;
+            temp_state.path().to_path_buf()
+        }
+    };
+    let isolation = Isolation {
+        state_dir,
+        config: request.config.clone(),
+    };
+    let (output, messages) =
+        progress::captured(|| paths::isolated(isolation, || http::scoped(|| generate(request))));
+    let mut output = output?;
+    output.messages = messages;
+    Ok(output)
+}
+
+fn generate(request: SpecRequest) -> Result<SpecOutput> {
+    let workdir = tempfile::Builder::new()
+        .prefix("takopack-spec-")
+        .tempdir()
+        .context("Failed to create temporary directory")?;
+    let init = PackageInitArgs {
+        crate_name: request.crate_name,
+        version: request.version,
+        offline: request.mirror.is_some(),
+        mirror: request.mirror,
+        registry: request.registry,
+        index_url: None,
+        allow_yanked: false,
+        crate_file: None,
+        download: DownloadArgs::default(),
+    };
+    let finish = PackageExecuteArgs {
+        changelog_ready: false,
+        changelog_from: ChangelogSource::None,
+        copyright_guess_harder: false,
+        no_overlay_write_back: true,
+        with_spdx: false,
+        with_tests: request.with_tests,
+        template_dir: None,
+        profile: request.profile,
+        lockfile_deps: None,
+        test_mode: TestMode::default(),
+    };
+
+    let mut process = PackageProcess::init(init, finish.profile.as_deref(), finish.test_mode)?;
+    let crate_name = process.crate_info().crate_name().to_string();
+    let version = process.crate_info().version().clone();
+    let names = process.config.output_names(&crate_name, &version);
+    // The orig tarball is written next to the package directory
+    let package_dir = workdir.path().join(&names.directory);
+    process.extract(PackageExtractArgs {
+        directory: Some(package_dir.clone()),
+    })?;
+    process.apply_overrides()?;
+    process.prepare_orig_tarball()?;
+    process.prepare_takopack_folder(finish)?;
+    let spec_path = package_dir.join("takopack").join(&names.spec_file);
+    let spec = std::fs::read_to_string(&spec_path)
+        .with_context(|| format!("Failed to read {}", spec_path.display()))?;
+    Ok(SpecOutput {
+        crate_name,
+        version,
+        package_dir: names.directory,
+        spec_file: names.spec_file,
+        spec,
+        messages: Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate_spec, SpecRequest};
+    use crate::paths::{crate_cache_dir, isolated, Isolation};
+    use crate::progress::{captured, eprint_line};
+    use std::fs;
+
+    #[test]
+    fn requests_are_isolated_and_quiet() {
+        let state = tempfile::tempdir().unwrap();
+        let isolation = Isolation {
+            state_dir: state.path().to_path_buf(),
+            config: None,
+        };
+        let cache = isolated(isolation, || crate_cache_dir().unwrap());
+        assert!(cache.starts_with(state.path()));
+        assert!(!crate_cache_dir().unwrap().starts_with(state.path()));
+
+        let ((), lines) = captured(|| eprint_line("kept"));
+        assert_eq!(lines, ["kept"]);
+
+        let missing = state.path().join("missing.toml");
+        let error = generate_spec(SpecRequest {
+            crate_name: "demo".to_string(),
+            config: Some(missing),
+            ..SpecRequest::default()
+        })
+        .unwrap_err();
+        assert!(error.to_string().contains("missing.toml"));
+        assert_eq!(fs::read_dir(state.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn specs_are_generated_from_a_local_mirror() {
+        let mirror = tempfile::tempdir().unwrap();
+        let dir = mirror.path().join("demo-1.2.0");
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("src/lib.rs"), "").unwrap();
+        fs::write(
+            dir.join("Cargo.toml"),
+            "[package]\nname = \"demo\"\nversion = \"1.2.0\"\nedition = \"2021\"\n\
+             license = \"MIT\"\ndescription = \"A demo crate\"\n",
+        )
+        .unwrap();
+        let state = tempfile::tempdir().unwrap();
+
+        let output = generate_spec(SpecRequest {
+            crate_name: "demo".to_string(),
+            mirror: Some(mirror.path().to_path_buf()),
+            state_dir: Some(state.path().to_path_buf()),
+            ..SpecRequest::default()
+        })
+        .unwrap();
+        assert_eq!(output.crate_name, "demo");
+        assert_eq!(output.version.to_string(), "1.2.0");
+        assert_eq!(output.spec_file, "rust-demo-1.spec");
+        assert!(
+            output.spec.contains("Version:        1.2.0\n"),
+            "{}",
+            output.spec
+        );
+        assert!(
+            output.spec.contains("License:        MIT\n"),
+            "{}",
+            output.spec
+        );
+    }
+}
@@ -0,0 +1,244 @@
+//! cargo-vet audit status of packaged crates.
+//!
+//! Reads a cargo-vet `supply-chain/` directory: `audits.toml` for the
+//! project's own audits, `imports.lock` for audits imported from peers and
+//! `config.toml` for exemptions. A version counts as audited when it has a
+//! full audit, or is reachable from a fully audited version through a chain
+//! of delta audits.
+
+use anyhow::{Context, Result};
+use semver::Version;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AuditStatus {
+    Audited,
+    Exempted,
+    Unaudited,
+}
+
+impl fmt::Display for AuditStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AuditStatus::Audited => "audited",
+            AuditStatus::Exempted => "exempted",
+            AuditStatus::Unaudited => "unaudited",
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+struct CrateAudits {
+    full: HashSet<Version>,
+    deltas: Vec<(Version, Version)>,
+    exemptions: HashSet<Version>,
+}
+
+/// Audits and exemptions loaded from a cargo-vet store.
+#[derive(Debug, Default)]
+pub struct VetStore {
+    crates: HashMap<String, CrateAudits>,
+}
+
+impl VetStore {
+    /// Load the cargo-vet store in `dir` (usually `supply-chain/`). Missing
+    /// files are skipped, but at least one must exist.
+    pub fn load(dir: &Path) -> Result<Self> {
+        let mut store = VetStore::default();
+        let mut found = false;
+
+        if let Some(audits) = read_toml(&dir.join("audits.toml"))? {
+            found = true;
+            if let Some(audits) = audits.get("audits") {
+                store.add_audits(audits);
+            }
+        }
+        if let Some(imports) = read_toml(&dir.join("imports.lock"))? {
+            found = true;
+            let peers = imports.get("audits").and_then(toml::Value::as_table);
+            for peer in peers.into_iter().flat_map(|p| p.values()) {
+                if let Some(audits) = peer.get("audits") {
+                    store.add_audits(audits);
+                }
+            }
+        }
+        if let Some(config) = read_toml(&dir.join("config.toml"))? {
+            found = true;
+            // "unaudited" is what cargo-vet called exemptions before 0.4
+            for key in ["exemptions", "unaudited"] {
+                let Some(exemptions) = config.get(key).and_then(toml::Value::as_table) else {
+                    continue;
+                };
+                for (crate_name, entries) in exemptions {
+                    for entry in entries.as_array().into_iter().flatten() {
+                        if let Some(version) = entry_version(entry, "version") {
+                            store.entry(crate_name).exemptions.insert(version);
+                        }
+                    }
+                }
+            }
+        }
+
+        if !found {
+            anyhow::bail!(
+                "{} does not look like a cargo-vet store (no audits.toml, imports.lock or config.toml)",
+                dir.display()
+            );
+        }
+        Ok(store)
+    }
+
+    fn entry(&mut self, crate_name: &str) -> &mut CrateAudits {
+        self.crates.entry(crate_name.to_string()).or_default()
+    }
+
+    /// `audits` is a table of crate name to an array of audit entries.
+    fn add_audits(&mut self, audits: &toml::Value) {
+        let Some(audits) = audits.as_table() else {
+            return;
+        };
+        for (crate_name, entries) in audits {
+            for entry in entries.as_array().into_iter().flatten() {
+                if let Some(version) = entry_version(entry, "version") {
+                    self.entry(crate_name).full.insert(version);
+                } else if let Some(delta) = entry.get("delta").and_then(toml::Value::as_str) {
+                    if let Some((from, to)) = parse_delta(delta) {
+                        self.entry(crate_name).deltas.push((from, to));
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn status(&self, crate_name: &str, version: &Version) -> AuditStatus {
+        let Some(audits) = self.crates.get(crate_name) else {
+            return AuditStatus::Unaudited;
+        };
+        if audits.full.contains(version) || delta_reachable(audits, version) {
+            AuditStatus::Audited
+        } else if audits.exemptions.contains(version) {
+            AuditStatus::Exempted
+        } else {
+            AuditStatus::Unaudited
+        }
+    }
+}
+
+fn read_toml(path: &Path) -> Result<Option<toml::Value>> {
+    match fs::read_to_string(path) {
+        Ok(text) => text
+            .parse::<toml::Value>()
+            .map(Some)
+            .with_context(|| format!("Failed to parse {}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+fn entry_version(entry: &toml::Value, key: &str) -> Option<Version> {
+    entry
+        .get(key)
+        .and_then(toml::Value::as_str)
+        .and_then(|v| Version::parse(v.trim()).ok())
+}
+
+/// `"1.0.0 -> 1.1.0"`
+fn parse_delta(delta: &str) -> Option<(Version, Version)> {
+    let (from, to) = delta.split_once("->")?;
+    Some((
+        Version::parse(from.trim()).ok()?,
+        Version::parse(to.trim()).ok()?,
+    ))
+}
+
+/// Whether `version` can be reached from a fully audited version by
+/// following delta audits.
+fn delta_reachable(audits: &CrateAudits, version: &Version) -> bool {
+    let mut reached: HashSet<&Version> = audits.full.iter().collect();
+    let mut queue: Vec<&Version> = reached.iter().copied().collect();
+    while let Some(current) = queue.pop() {
+        if current == version {
+            return true;
+        }
+        for (from, to) in &audits.deltas {
+            if from == current && reached.insert(to) {
+                queue.push(to);
+            }
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AuditStatus, VetStore};
+    use std::fs;
+
+    #[test]
+    fn audit_status_follows_full_delta_and_exemption_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("audits.toml"),
+            r#"
+[[audits.foo]]
+who = "Alice <alice@example.com>"
+criteria = "safe-to-deploy"
+version = "1.0.0"
+
+[[audits.foo]]
+who = "Alice <alice@example.com>"
+criteria = "safe-to-deploy"
+delta = "1.0.0 -> 1.1.0"
+
+[[audits.foo]]
+who = "Alice <alice@example.com>"
+criteria = "safe-to-deploy"
+delta = "1.1.0 -> 1.2.0"
+
+[[audits.orphan]]
+who = "Alice <alice@example.com>"
+criteria = "safe-to-deploy"
+delta = "0.1.0 -> 0.2.0"
+"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("imports.lock"),
+            r#"
+[[audits.peer.audits.bar]]
+who = "Bob <bob@example.com>"
+criteria = "safe-to-run"
+version = "0.3.1"
+"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("config.toml"),
+            r#"
+[[exemptions.baz]]
+version = "2.0.0"
+criteria = "safe-to-deploy"
+"#,
+        )
+        .unwrap();
+
+        let store = VetStore::load(dir.path()).unwrap();
+        let v = |s: &str| semver::Version::parse(s).unwrap();
+        assert_eq!(store.status("foo", &v("1.0.0")), AuditStatus::Audited);
+        assert_eq!(store.status("foo", &v("1.2.0")), AuditStatus::Audited);
+        assert_eq!(store.status("foo", &v("1.3.0")), AuditStatus::Unaudited);
+        assert_eq!(store.status("orphan", &v("0.2.0")), AuditStatus::Unaudited);
+        assert_eq!(store.status("bar", &v("0.3.1")), AuditStatus::Audited);
+        assert_eq!(store.status("baz", &v("2.0.0")), AuditStatus::Exempted);
+        assert_eq!(store.status("qux", &v("1.0.0")), AuditStatus::Unaudited);
+    }
+
+    #[test]
+    fn empty_directory_is_not_a_store() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(VetStore::load(dir.path()).is_err());
+    }
+}
@@ -0,0 +1,231 @@
+//! Backups of the Cargo.toml and Cargo.lock files of packaged crates.
+//!
+//! Unpacking a crate keeps a copy of its original Cargo.toml, and lockfiles
+//! are kept the same way, for `db gc` to find later. They always went to
+//! `takopack/cargo_back` in the data directory of the user. The `[backup]`
+//! table of takopack.toml now picks the directory or turns backups off,
+//! and the global `--backup-dir` and `--no-backup` flags override it for
+//! one run. Like the [`crate::verbosity`], the flags are installed once with
+//! [`BackupArgs::install`].
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use crate::errors::*;
+
+/// Where backups go, set in the `[backup]` table of takopack.toml.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackupPolicy {
+    /// Whether backups are written; `true` by default.
+    pub enabled: bool,
+    /// Directory of the backups, relative to takopack.toml;
+    /// `takopack/cargo_back` in the data directory of the user by default.
+    pub dir: Option<PathBuf>,
+}
+
+impl Default for BackupPolicy {
+    fn default() -> Self {
+        BackupPolicy {
+            enabled: true,
+            dir: None,
+        }
+    }
+}
+
+/// Backup settings given on the command line, overriding the `[backup]`
+/// table of takopack.toml.
+#[derive(Debug, Clone, Default, PartialEq, Eq, clap::Args)]
+pub struct BackupArgs {
+    /// Back up Cargo.toml and Cargo.lock files to DIR. Overrides
+    /// [backup].dir in takopack.toml
+    #[arg(long, global = true, value_name = "DIR")]
+    pub backup_dir: Option<PathBuf>,
+    /// Do not back up Cargo.toml and Cargo.lock files. Overrides
+    /// [backup].enabled in takopack.toml
+    #[arg(long, global = true, conflicts_with = "backup_dir")]
+    pub no_backup: bool,
+}
+
+static CURRENT: Mutex<BackupArgs> = Mutex::new(BackupArgs {
+    backup_dir: None,
+    no_backup: false,
+});
+
+impl BackupArgs {
+    /// Use these settings from now on.
+    pub fn install(self) {
+        *CURRENT.lock().unwrap_or_else(|e| e.into_inner()) = self;
+    }
+
+    /// The installed settings; none unless the flags were given.
+    pub fn current() -> Self {
+        CURRENT.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Apply the settings given on the command line to `policy`.
+    pub fn apply(&self, policy: &mut BackupPolicy) {
+        if let Some(dir) = &self.backup_dir {
+            policy.enabled = true;
+            policy.dir = Some(dir.clone());
+        }
+        if self.no_backup {
+            policy.enabled = false;
+        }
+    }
+}
+
+impl BackupPolicy {
+    /// The directory backups go to with the installed [`BackupArgs`]
+    /// applied, `None` when they are off. `config_path` is the
+    /// takopack.toml the policy was read from.
+    pub fn dir(&self, config_path: Option<&Path>) -> Result<Option<PathBuf>> {
+        let cli = BackupArgs::current();
+        let mut policy = self.clone();
+        cli.apply(&mut policy);
+        if !policy.enabled {
+            return Ok(None);
+        }
+        match policy.dir {
+            // given on the command line, relative to the current directory
+            Some(dir) if cli.backup_dir.is_some() => Ok(Some(dir)),
+            Some(dir) => Ok(Some(match config_path.and_then(Path::parent) {
+                Some(config_dir) => config_dir.join(dir),
+                None => dir,
+            })),
+            None => crate::paths::backup_dir().map(Some),
+        }
+    }
+
+    /// Back up `cargo_toml` as `<crate>-<version>.toml`, in `subdir` of the
+    /// backup directory if given. A missing file is only logged.
+    pub fn backup_cargo_toml(
+        &self,
+        config_path: Option<&Path>,
+        cargo_toml: &Path,
+        crate_name: &str,
+        version: &str,
+        subdir: Option<&str>,
+    ) -> Result<Option<PathBuf>> {
+        if !cargo_toml.exists() {
+            log::warn!("Cargo.toml not found at: {:?}", cargo_toml);
+            return Ok(None);
+        }
+        self.backup(config_path, cargo_toml, crate_name, version, subdir, "toml")
+    }
+
+    /// Back up `cargo_lock` as `<crate>-<version>.lock`, in `subdir` of the
+    /// backup directory if given.
+    pub fn backup_cargo_lock(
+        &self,
+        config_path: Option<&Path>,
+        cargo_lock: &Path,
+        crate_name: &str,
+        version: &str,
+        subdir: Option<&str>,
+    ) -> Result<Option<PathBuf>> {
+        if !cargo_lock.is_file() {
+            takopack_bail!("Cargo.lock not found at {}", cargo_lock.display());
+        }
+        self.backup(config_path, cargo_lock, crate_name, version, subdir, "lock")
+    }
+
+    fn backup(
+        &self,
+        config_path: Option<&Path>,
+        file: &Path,
+        crate_name: &str,
+        version: &str,
+        subdir: Option<&str>,
+        extension: &str,
+    ) -> Result<Option<PathBuf>> {
+        let Some(mut backup_dir) = self.dir(config_path)? else {
+            return Ok(None);
+        };
+        if let Some(sub) = subdir {
+            backup_dir = backup_dir.join(sub);
+        }
+        fs::create_dir_all(&backup_dir)
+            .with_context(|| format!("Failed to create backup directory: {:?}", backup_dir))?;
+
+        let backup_path = backup_dir.join(format!(
+            "{}-{}.{}",
+            crate_name.replace('_', "-"),
+            version,
+            extension
+        ));
+        fs::copy(file, &backup_path)
+            .with_context(|| format!("Failed to back up {:?} to {:?}", file, backup_path))?;
+        log::info!("Backed up {:?} to: {:?}", file, backup_path);
+        Ok(Some(backup_path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BackupArgs, BackupPolicy};
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn backups_follow_the_policy() {
+        let temp = tempfile::tempdir().unwrap();
+        let cargo_toml = temp.path().join("Cargo.toml");
+        fs::write(&cargo_toml, "[package]\n").unwrap();
+        let config_path = temp.path().join("takopack.toml");
+
+        let policy: BackupPolicy = toml::from_str("dir = \"backups\"").unwrap();
+        assert_eq!(
+            policy.dir(Some(&config_path)).unwrap(),
+            Some(temp.path().join("backups"))
+        );
+        let copy = policy
+            .backup_cargo_toml(
+                Some(&config_path),
+                &cargo_toml,
+                "foo_bar",
+                "1.0.0",
+                Some("origin"),
+            )
+            .unwrap();
+        assert_eq!(
+            copy,
+            Some(temp.path().join("backups/origin/foo-bar-1.0.0.toml"))
+        );
+        assert!(policy
+            .backup_cargo_lock(
+                Some(&config_path),
+                &temp.path().join("Cargo.lock"),
+                "foo",
+                "1.0.0",
+                None
+            )
+            .is_err());
+
+        let off: BackupPolicy = toml::from_str("enabled = false").unwrap();
+        assert_eq!(off.dir(None).unwrap(), None);
+        assert_eq!(
+            off.backup_cargo_toml(None, &cargo_toml, "foo", "1.0.0", None)
+                .unwrap(),
+            None
+        );
+
+        let mut overridden = policy.clone();
+        BackupArgs {
+            backup_dir: Some(Path::new("elsewhere").to_path_buf()),
+            no_backup: false,
+        }
+        .apply(&mut overridden);
+        assert_eq!(overridden.dir, Some(Path::new("elsewhere").to_path_buf()));
+        BackupArgs {
+            backup_dir: None,
+            no_backup: true,
+        }
+        .apply(&mut overridden);
+        assert!(!overridden.enabled);
+    }
+}
@@ -4,6 +4,16 @@ use std::fs;
 use std::io::{BufRead, BufReader};
 use std::path::PathBuf;
 
+use crate::cancel::{self, CancellationToken};
+use crate::config::Config;
+use crate::copr::{self, CoprCredentials, CoprProject};
+use crate::events::{self, Event};
+use crate::failure_report::{self, Failure, Phase};
+use crate::name_collision::NameRegistry;
+use crate::package::DownloadArgs;
+use crate::rpm_build::{self, BuildTool};
+use crate::test_mode::TestMode;
+
 /// Information about a failed package
 #[derive(Debug, Clone)]
 pub struct FailedPackage {
@@ -12,8 +22,37 @@ pub struct FailedPackage {
     pub error: String,
 }
 
+/// What happens to each generated spec besides being written.
+#[derive(Debug, Clone, Default)]
+pub struct BatchOptions {
+    /// Build the spec right after generating it.
+    pub build: Option<BuildTool>,
+    /// COPR project (`owner/project`) to upload the built source RPMs to.
+    pub copr: Option<String>,
+    /// Download settings, overriding takopack.toml.
+    pub download: DownloadArgs,
+    /// Test mode to generate every spec in.
+    pub test_mode: TestMode,
+}
+
 /// Process batch file with crate list
-pub fn process_batch_file(file_path: &PathBuf, output_base: Option<PathBuf>) -> Result<()> {
+pub fn process_batch_file(
+    file_path: &PathBuf,
+    output_base: Option<PathBuf>,
+    options: &BatchOptions,
+) -> Result<()> {
+    process_batch_file_with_cancellation(file_path, output_base, options, &CancellationToken::new())
+}
+
+/// Process batch file with crate list, stopping at the next crate or pipeline
+/// phase once `cancel` is triggered. Crates finished so far are kept and the
+/// summary is still printed; the run then returns a cancellation error.
+pub fn process_batch_file_with_cancellation(
+    file_path: &PathBuf,
+    output_base: Option<PathBuf>,
+    options: &BatchOptions,
+    cancel: &CancellationToken,
+) -> Result<()> {
     // Create output directory (timestamp or specified)
     let base_dir = if let Some(path) = output_base {
         path
@@ -47,8 +86,8 @@ pub fn process_batch_file(file_path: &PathBuf, output_base: Option<PathBuf>) ->
         // now,the clean_flag has been removed.
         let parts: Vec<&str> = line.split_whitespace().collect();
         if parts.len() < 2 {
-            eprintln!(
-                "Warning: Invalid line format (expected 'crate_name version'): {}",
+            takopack_warn!(
+                "Invalid line format (expected 'crate_name version'): {}",
                 line
             );
             continue;
@@ -63,10 +102,36 @@ pub fn process_batch_file(file_path: &PathBuf, output_base: Option<PathBuf>) ->
     let total_count = crate_list.len();
     log::info!("Found {} crates to process\n", total_count);
 
+    let build = options.build;
+    let mock_root = match build {
+        Some(BuildTool::Mock) => Config::load()?.1.mock_root,
+        _ => None,
+    };
+    // Checked before packaging starts rather than after the whole run
+    let copr = match &options.copr {
+        Some(project) => {
+            let credentials = CoprCredentials::load(None)?;
+            let project = CoprProject::parse(project, &credentials.username)?;
+            Some((credentials, project))
+        }
+        None => None,
+    };
+
     let mut succeeded = 0;
     let mut failed_packages: Vec<FailedPackage> = Vec::new();
+    let mut built = 0;
+    let mut failed_builds: Vec<FailedPackage> = Vec::new();
+    let mut srpms = Vec::new();
+    let mut cancelled = false;
+    // Every failure, packaging and build alike, for the failure report
+    let mut failures: Vec<Failure> = Vec::new();
+    let mut names = NameRegistry::for_run(&base_dir)?;
 
     for (idx, (crate_name, version)) in crate_list.iter().enumerate() {
+        if cancel.is_cancelled() {
+            cancelled = true;
+            break;
+        }
         log::info!(
             "[{}/{}] Processing: {} {}",
             idx + 1,
@@ -76,19 +141,60 @@ pub fn process_batch_file(file_path: &PathBuf, output_base: Option<PathBuf>) ->
         );
 
         // Process this crate
-        match crate::util::process_single_crate(crate_name, version, &base_dir, None) {
-            Ok(_) => {
+        match crate::util::process_single_crate_cancellable(
+            crate_name,
+            version,
+            &base_dir,
+            None,
+            &options.download,
+            cancel,
+            &mut names,
+            options.test_mode,
+        ) {
+            Ok(package_dir) => {
                 succeeded += 1;
-                println!("✓ Successfully packaged {} {}", crate_name, version);
+                events::emit(Event::CratePackaged {
+                    crate_name: crate_name.to_string(),
+                    version: version.to_string(),
+                    package_dir: Some(package_dir.clone()),
+                });
+                let Some(tool) = build else {
+                    continue;
+                };
+                match rpm_build::build_package_dir(&package_dir, tool, mock_root.as_deref()) {
+                    Ok(result) => {
+                        built += 1;
+                        rpm_build::print_result(&result);
+                        srpms.push(result.srpm);
+                    }
+                    Err(e) => {
+                        let e = e.context(Phase::Build);
+                        events::emit(Event::CrateFailed {
+                            crate_name: crate_name.to_string(),
+                            version: version.to_string(),
+                            error: format!("{:#}", e),
+                        });
+                        failures.push(Failure::new(crate_name, version, &e));
+                        failed_builds.push(FailedPackage {
+                            crate_name: crate_name.to_string(),
+                            version: version.to_string(),
+                            error: format!("{:#}", e),
+                        });
+                    }
+                }
+            }
+            Err(e) if cancel::is_cancelled(&e) => {
+                cancelled = true;
+                break;
             }
             Err(e) => {
                 let error_msg = format!("{:?}", e);
-                log::error!(
-                    "✗ Failed to package {} {}: {}",
-                    crate_name,
-                    version,
-                    error_msg
-                );
+                events::emit(Event::CrateFailed {
+                    crate_name: crate_name.to_string(),
+                    version: version.to_string(),
+                    error: error_msg.clone(),
+                });
+                failures.push(Failure::new(crate_name, version, &e));
                 failed_packages.push(FailedPackage {
                     crate_name: crate_name.to_string(),
                     version: version.to_string(),
@@ -98,6 +204,31 @@ pub fn process_batch_file(file_path: &PathBuf, output_base: Option<PathBuf>) ->
         }
     }
 
+    let uploads = match copr {
+        Some((credentials, project)) if !cancelled && !srpms.is_empty() => {
+            Some(copr::upload_srpms(&credentials, &project, &srpms))
+        }
+        _ => None,
+    };
+
+    failure_report::write_failure_report(&base_dir, &failures)?;
+    if !events::is_human() {
+        if let Some(report) = &uploads {
+            events::emit(Event::note(report.to_string()));
+        }
+        events::emit(Event::Summary {
+            attempted: total_count,
+            packaged: succeeded,
+            failed: failed_packages.len() + failed_builds.len(),
+            skipped: 0,
+            output_dir: base_dir,
+        });
+        if cancelled {
+            return Err(cancel::Cancelled.into());
+        }
+        return Ok(());
+    }
+
     // Print summary
     println!("\n{}", "=".repeat(60));
     println!("Batch Processing Summary");
@@ -105,6 +236,16 @@ pub fn process_batch_file(file_path: &PathBuf, output_base: Option<PathBuf>) ->
     println!("Total packages attempted: {}", total_count);
     println!("Successfully packaged:    {}", succeeded);
     println!("Failed:                   {}", failed_packages.len());
+    if let Some(tool) = build {
+        println!("Built ({}):             {}", tool, built);
+        println!("Build failed:             {}", failed_builds.len());
+    }
+    if cancelled {
+        println!(
+            "Not attempted:            {} (cancelled)",
+            total_count - succeeded - failed_packages.len()
+        );
+    }
 
     if !failed_packages.is_empty() {
         println!("\nFailed packages:");
@@ -113,8 +254,32 @@ pub fn process_batch_file(file_path: &PathBuf, output_base: Option<PathBuf>) ->
         }
     }
 
+    if !failed_builds.is_empty() {
+        println!("\nFailed builds:");
+        for pkg in &failed_builds {
+            println!("  - {} {}: {}", pkg.crate_name, pkg.version, pkg.error);
+        }
+    }
+
+    if let Some(report) = &uploads {
+        println!();
+        print!("{}", report);
+    }
+    names.print_collisions();
+
+    if !failures.is_empty() {
+        println!(
+            "\nFailure report: {} (retry with: takopack cargo batch {})",
+            base_dir.join(failure_report::FAILURES_JSON).display(),
+            base_dir.join(failure_report::FAILURES_TXT).display()
+        );
+    }
     println!("\nOutput directory: {}", base_dir.display());
     println!("{}", "=".repeat(60));
 
+    if cancelled {
+        return Err(cancel::Cancelled.into());
+    }
+
     Ok(())
 }
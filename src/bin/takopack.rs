@@ -1,13 +1,17 @@
+use anyhow::Context;
 use clap::Parser;
 use nu_ansi_term::Color::Red;
 
-use takopack::cli::{CargoOpt, Cli, Opt, PyOpt};
-use takopack::crates::invalidate_crates_io_cache;
+use takopack::cli::{CargoOpt, Cli, DbOpt, Opt, PyOpt, SelfOpt};
 use takopack::errors::Result;
+use takopack::events::{self, Event};
+use takopack::git_package::GitReference;
 use takopack::package::*;
 use takopack::range_audit::{self, RangeCapabilityPolicy};
 use takopack::recursive_package::RecursivePackager;
 use takopack::spec_from_toml::parse_dependencies_from_toml;
+use takopack::test_mode::TestMode;
+use takopack::verbosity::Verbosity;
 
 #[test]
 fn verify_app() {
@@ -17,27 +21,71 @@ fn verify_app() {
 
 fn real_main() -> Result<i32> {
     let m = Cli::parse();
+    let verbosity = Verbosity::from_flags(m.quiet, m.verbose);
+    let mut logger = env_logger::Builder::from_default_env();
+    if let Some(level) = verbosity.log_level() {
+        logger.filter_level(level);
+    }
+    logger.init();
+    verbosity.install();
+    let test_mode = if m.test_mode {
+        TestMode::integration()
+    } else {
+        TestMode::default()
+    };
+    verbosity.output_format(m.output_format).install();
+    m.backup.clone().install();
+    // Downloads outside a packaging run (index lookups, the crates.io API)
+    // follow the [download] settings too; a broken takopack.toml is
+    // reported by the commands that read it
+    if let Ok((config_path, config)) = takopack::config::Config::load() {
+        takopack::http::configure(&config.download)?;
+        // `self check` reports a mismatch instead of failing on it, and
+        // completions do not depend on the version
+        if !m.ignore_version_check
+            && !matches!(m.command, Opt::SelfCmd(_) | Opt::Completions { .. })
+        {
+            takopack::self_check::check_required_version(&config, config_path.as_deref())?;
+        }
+    }
     use Opt::*;
     match m.command {
         Cargo(cargo_opt) => {
-            match cargo_opt {
-                CargoOpt::Update => invalidate_crates_io_cache().map(|_| 0),
+            match *cargo_opt {
+                CargoOpt::Update {
+                    crates,
+                    auto_refresh,
+                    status,
+                    crate_cache,
+                } => {
+                    let options = takopack::index_cache::UpdateOptions {
+                        crates,
+                        auto_refresh,
+                        status,
+                        crate_cache,
+                    };
+                    takopack::index_cache::run_update(&options).map(|_| 0)
+                }
                 CargoOpt::Package {
                     init,
                     mut extract,
-                    finish,
+                    mut finish,
                     range_capability_policy,
+                    diff_from,
+                    build,
                 } => {
                     use std::fs;
 
                     log::info!("preparing crate info");
-                    let mut process = PackageProcess::init(init)?;
+                    finish.test_mode = test_mode;
+                    let mut process =
+                        PackageProcess::init(init, finish.profile.as_deref(), finish.test_mode)?;
 
                     // Get crate name and version
                     let crate_name = process.crate_info().crate_name();
                     let version = process.crate_info().version();
 
-                    let output_names = takopack::util::rust_crate_output_names(crate_name, version);
+                    let output_names = process.config.output_names(crate_name, version);
                     let final_output = takopack::util::package_final_output_dir(
                         extract.directory.as_deref(),
                         &output_names,
@@ -50,6 +98,7 @@ fn real_main() -> Result<i32> {
                         let warnings = range_audit::audit_cargo_dependencies(
                             process.crate_info().dependencies(),
                             Some(&output_names.directory),
+                            process.config.crate_namespace(),
                         );
                         if range_audit::emit_warnings(&warnings, range_capability_policy) {
                             anyhow::bail!("range capability audit failed (policy: error)");
@@ -76,8 +125,14 @@ fn real_main() -> Result<i32> {
                             output_path,
                             &final_output,
                         )?;
+                        let final_copyright = takopack::util::copy_copyright_report_to_dir(
+                            output_path,
+                            &final_output,
+                        )?;
                         log::info!("Spec file saved to: {}", final_spec.display());
-                        println!("Spec file: {}", final_spec.display());
+                        events::emit(Event::SpecWritten {
+                            spec: final_spec.clone(),
+                        });
 
                         // Now cleanup: remove the extraction directory (which has the same name as final_output)
                         // We need to do this carefully to not delete the final spec file
@@ -90,7 +145,10 @@ fn real_main() -> Result<i32> {
                             for entry in fs::read_dir(output_path)? {
                                 let entry = entry?;
                                 let path = entry.path();
-                                if path != final_spec && path != final_cargo_toml {
+                                if path != final_spec
+                                    && path != final_cargo_toml
+                                    && Some(&path) != final_copyright.as_ref()
+                                {
                                     if path.is_dir() {
                                         fs::remove_dir_all(&path)?;
                                     } else {
@@ -109,13 +167,79 @@ fn real_main() -> Result<i32> {
                         eprintln!("ERROR: Spec file not found!");
                     };
 
+                    if let Some(old_version) = diff_from {
+                        let diff = takopack::crate_diff::diff_crate_versions(
+                            process.crate_info().crate_name(),
+                            &old_version,
+                            &process.crate_info().version().to_string(),
+                        )?;
+                        let diff_path = final_output.join("crate-diff.txt");
+                        fs::write(&diff_path, diff.to_string())?;
+                        events::emit(Event::note(format!("Crate diff: {}", diff_path.display())));
+                    }
+                    process.config.permissions.apply_to_dir(&final_output)?;
+
+                    if let Some(tool) = build {
+                        let result = takopack::rpm_build::build_package_dir(
+                            &final_output,
+                            tool,
+                            process.config.mock_root.as_deref(),
+                        )?;
+                        takopack::rpm_build::print_result(&result);
+                    }
+
                     Ok(0)
                 }
                 CargoOpt::Vendor { args } => {
                     log::info!("starting vendor operation (recursive packaging)");
-                    let mut packager = RecursivePackager::new(args.output)?;
+                    let mirror = if args.offline || args.mirror.is_some() {
+                        Some(takopack::config::resolve_mirror_dir(
+                            args.mirror.as_deref(),
+                        )?)
+                    } else {
+                        None
+                    };
+                    let (_, config) =
+                        takopack::config::Config::load_with_profile(args.profile.as_deref())?;
+                    let skip_list = args.skip_list()?;
+                    let packager = match &args.resume {
+                        Some(dir) => RecursivePackager::resume(dir)?,
+                        None if args.dry_run => RecursivePackager::new_dry_run(args.output),
+                        None => RecursivePackager::new(args.output)?,
+                    };
+                    let mut packager = packager
+                        .with_limits(args.max_depth, args.max_crates)
+                        .with_skip_list(skip_list, args.skip_subtrees)
+                        .with_mirror(mirror)
+                        .with_download(args.download)
+                        .with_allow_yanked(args.allow_yanked)
+                        .with_profile(args.profile)
+                        .with_test_mode(test_mode)
+                        .with_locked(args.locked)
+                        .with_skip_existing(args.skip_existing)
+                        .with_progress(args.progress)
+                        .with_configured_filters(&config)?;
+                    if args.check_repo {
+                        packager = packager.with_repo_check(&config)?;
+                    }
+                    if args.check_owners {
+                        let store =
+                            takopack::owners::OwnerStore::load(&takopack::paths::owners_file()?)?;
+                        packager = packager.with_owner_store(Some(store));
+                    }
+                    if let Some(dir) = &args.vet_store {
+                        let store = takopack::audit::VetStore::load(dir)?;
+                        packager = packager.with_vet_store(Some(store), args.require_audit);
+                    }
                     packager.process_crate_recursive(&args.crate_name, args.version.as_deref())?;
+                    if !args.dry_run {
+                        packager.save_owners()?;
+                        packager.write_failure_report()?;
+                        packager.write_build_order()?;
+                    }
                     packager.print_summary();
+                    packager.check_yanked()?;
+                    packager.check_audits()?;
                     Ok(0)
                 }
                 CargoOpt::ParseToml { toml_path, output } => {
@@ -123,18 +247,47 @@ fn real_main() -> Result<i32> {
                     parse_dependencies_from_toml(&toml_path, output)?;
                     Ok(0)
                 }
-                CargoOpt::Batch { file, output } => {
+                CargoOpt::Batch {
+                    file,
+                    output,
+                    build,
+                    copr,
+                    download,
+                } => {
                     log::info!("starting batch operation from file: {:?}", file);
-                    takopack::batch_package::process_batch_file(&file, output)?;
+                    let options = takopack::batch_package::BatchOptions {
+                        build,
+                        copr,
+                        download,
+                        test_mode,
+                    };
+                    takopack::batch_package::process_batch_file(&file, output, &options)?;
                     Ok(0)
                 }
+                CargoOpt::BuildSpec {
+                    dir,
+                    tool,
+                    mock_root,
+                } => {
+                    let mock_root = match mock_root {
+                        Some(root) => Some(root),
+                        None => takopack::config::Config::load()?.1.mock_root,
+                    };
+                    takopack::rpm_build::run_build_spec(&dir, tool, mock_root.as_deref())
+                }
+                CargoOpt::CoprUpload {
+                    dir,
+                    project,
+                    credentials,
+                } => takopack::copr::run_copr_upload(&dir, &project, credentials.as_deref()),
                 CargoOpt::LocalPackage {
                     path,
                     output,
-                    finish,
+                    mut finish,
                     range_capability_policy,
                 } => {
                     log::info!("packaging from local directory: {:?}", path);
+                    finish.test_mode = test_mode;
                     takopack::local_package::process_local_package(
                         &path,
                         output,
@@ -143,10 +296,153 @@ fn real_main() -> Result<i32> {
                     )?;
                     Ok(0)
                 }
+                CargoOpt::GitPackage {
+                    url,
+                    rev,
+                    tag,
+                    branch,
+                    path,
+                    output,
+                    mut finish,
+                    range_capability_policy,
+                } => {
+                    log::info!("packaging from git repository: {}", url);
+                    finish.test_mode = test_mode;
+                    let reference = GitReference::from_args(rev, tag, branch);
+                    takopack::git_package::process_git_package(
+                        &url,
+                        &reference,
+                        path.as_deref(),
+                        output,
+                        finish,
+                        range_capability_policy,
+                    )?;
+                    Ok(0)
+                }
+                CargoOpt::DiffSpec {
+                    init,
+                    mut finish,
+                    against,
+                    output,
+                } => {
+                    finish.test_mode = test_mode;
+                    let diff = takopack::spec_diff::diff_against(init, finish, &against)?;
+                    match output {
+                        Some(path) => std::fs::write(&path, diff.to_string())
+                            .with_context(|| format!("Failed to write {}", path.display()))?,
+                        None => print!("{}", diff),
+                    }
+                    Ok(0)
+                }
+                CargoOpt::UpdateSpec {
+                    dir,
+                    version,
+                    download,
+                    mut finish,
+                } => {
+                    finish.test_mode = test_mode;
+                    takopack::update_spec::update_spec_dir(&dir, version, download, finish)?;
+                    Ok(0)
+                }
+                CargoOpt::Lint { path } => {
+                    let errors = takopack::lint::run_lint(&path)?;
+                    Ok(if errors > 0 { 1 } else { 0 })
+                }
+                CargoOpt::Verify { dir } => {
+                    let unsatisfied = takopack::verify::run_verify(&dir)?;
+                    Ok(if unsatisfied > 0 { 1 } else { 0 })
+                }
+                CargoOpt::Deps {
+                    path,
+                    features,
+                    all_features,
+                    no_default_features,
+                    style,
+                } => {
+                    let selection = takopack::deps::FeatureSelection {
+                        features,
+                        all_features,
+                        no_default_features,
+                    };
+                    takopack::deps::run_deps(&path, &selection, style)
+                }
+                CargoOpt::CrateDiff {
+                    crate_name,
+                    old_version,
+                    new_version,
+                    output,
+                } => {
+                    let diff = takopack::crate_diff::diff_crate_versions(
+                        &crate_name,
+                        &old_version,
+                        &new_version,
+                    )?;
+                    match output {
+                        Some(path) => std::fs::write(&path, diff.to_string())
+                            .with_context(|| format!("Failed to write {}", path.display()))?,
+                        None => print!("{}", diff),
+                    }
+                    Ok(0)
+                }
+                CargoOpt::BuildOrder {
+                    crate_name,
+                    version,
+                    from_lockfile,
+                    output,
+                } => takopack::build_order::run_build_order(
+                    crate_name.as_deref(),
+                    version.as_deref(),
+                    from_lockfile.as_deref(),
+                    output.as_deref(),
+                ),
                 CargoOpt::RegistrySync { dry_run, jobs } => {
                     log::info!("starting registry sync");
                     takopack::registry_sync::run_registry_sync(dry_run, jobs)
                 }
+                CargoOpt::Db(DbOpt::Gc {
+                    graphs,
+                    ruyispec,
+                    dry_run,
+                }) => takopack::stream_gc::run_gc(&graphs, ruyispec.as_deref(), dry_run),
+                CargoOpt::Db(DbOpt::List { ruyispec }) => {
+                    takopack::stream_db::run_list(ruyispec.as_deref())
+                }
+                CargoOpt::Db(DbOpt::Search { pattern, ruyispec }) => {
+                    takopack::stream_db::run_search(&pattern, ruyispec.as_deref())
+                }
+                CargoOpt::Db(DbOpt::Show {
+                    crate_name,
+                    ruyispec,
+                }) => takopack::stream_db::run_show(&crate_name, ruyispec.as_deref()),
+                CargoOpt::Db(DbOpt::Remove {
+                    crate_name,
+                    ruyispec,
+                    dry_run,
+                }) => takopack::stream_db::run_remove(&crate_name, ruyispec.as_deref(), dry_run),
+                CargoOpt::Db(DbOpt::Export {
+                    format,
+                    output,
+                    ruyispec,
+                }) => {
+                    takopack::stream_db::run_export(format, output.as_deref(), ruyispec.as_deref())
+                }
+                CargoOpt::Db(DbOpt::Import {
+                    file,
+                    format,
+                    batch,
+                    ruyispec,
+                }) => takopack::stream_db::run_import(
+                    &file,
+                    format,
+                    batch.as_deref(),
+                    ruyispec.as_deref(),
+                ),
+                CargoOpt::Db(DbOpt::Outdated { batch, ruyispec }) => {
+                    takopack::stream_db::run_outdated(batch.as_deref(), ruyispec.as_deref())
+                }
+                CargoOpt::Db(DbOpt::Stats { ruyispec }) => {
+                    takopack::stream_db::run_stats(ruyispec.as_deref())
+                }
                 CargoOpt::ResolveCheck { path, registry } => {
                     log::info!("starting resolve check");
                     takopack::resolve_check::run_resolve_check(&path, registry.as_deref())
@@ -155,6 +451,20 @@ fn real_main() -> Result<i32> {
                     log::info!("generating dynamic BuildRequires");
                     takopack::dynamic_buildreqs::run_buildreqs(&path, registry.as_deref())
                 }
+                CargoOpt::Repro {
+                    cases,
+                    corpus,
+                    bless,
+                    list,
+                } => {
+                    let options = takopack::repro::ReproOptions {
+                        cases,
+                        corpus,
+                        bless,
+                        list,
+                    };
+                    takopack::repro::run_repro(&options)
+                }
             }
         }
         Opt::Py(py_opt) => match py_opt {
@@ -172,11 +482,15 @@ fn real_main() -> Result<i32> {
                 Ok(0)
             }
         },
+        Opt::SelfCmd(SelfOpt::Check) => takopack::self_check::run_self_check(),
+        Opt::Completions { shell } => {
+            takopack::cli::write_completions(shell, &mut std::io::stdout());
+            Ok(0)
+        }
     }
 }
 
 fn main() {
-    env_logger::init();
     match real_main() {
         Ok(code) => std::process::exit(code),
         Err(e) => {
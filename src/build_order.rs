@@ -0,0 +1,1120 @@
+//! Order in which the crates of a dependency closure have to be packaged,
+//! for `takopack cargo build-order`.
+//!
+//! The graph comes either from the crates.io index, following normal and
+//! build dependencies from a root crate, or from the registry packages of a
+//! `Cargo.lock`. Optional dependencies are part of the closure (feature
+//! subpackages require them) but do not constrain the order.
+//!
+//! When crates depend on each other in a cycle, the smallest set of
+//! dependency edges whose removal breaks every cycle is worked out, and
+//! turned into a bootstrap plan: the crates losing an edge are packaged once
+//! without that dependency and rebuilt after it.
+//!
+//! Dev-dependencies do not constrain the order, but the crates of the
+//! closure often use each other in their tests (serde and serde_derive do).
+//! Cycles that only close through such a dev-dependency are listed apart
+//! from the hard ones: they are harmless unless the tests are built, e.g.
+//! with `[test_build_requires]`, which would make them unbuildable.
+//!
+//! `vendor` also writes the order of the crates it packaged to
+//! [`BUILD_ORDER_TXT`] in its output root, in the input format of `batch`,
+//! so that `takopack cargo batch build-order.txt --build` builds them in
+//! turn. A bootstrap plan is only listed there in comments, since `batch`
+//! cannot package a crate without one of its dependencies.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use anyhow::Context;
+use semver::{Version, VersionReq};
+
+use crate::crates::{select_index_version, IndexVersion, SparseIndex};
+use crate::errors::*;
+use crate::lockfile_parser::{self, DependencyGraph};
+use crate::util;
+
+/// A crate at one version.
+pub type Node = (String, Version);
+
+/// Build order of a `vendor` run, written to its output root.
+pub const BUILD_ORDER_TXT: &str = "build-order.txt";
+
+/// Upper bound on the cycles listed; large strongly connected components
+/// have far more of them than anyone wants to read.
+const MAX_CYCLES: usize = 20;
+
+/// Feedback edge sets are searched exhaustively only in groups of crates
+/// with at most this many edges between them, and only up to
+/// [`MAX_EXACT_CUT`] edges; larger groups fall back to a heuristic.
+const MAX_EXACT_EDGES: usize = 24;
+const MAX_EXACT_CUT: usize = 3;
+
+/// A dependency of one crate on another.
+pub type Edge = (Node, Node);
+
+/// A packaged crate with the dependencies it was packaged with, as (name,
+/// version requirement) pairs.
+pub type PackagedNode = (Node, Vec<(String, Option<String>)>);
+
+/// Dependency graph: every crate of the closure, mapped to the crates it
+/// needs first.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DepGraph {
+    pub deps: BTreeMap<Node, BTreeSet<Node>>,
+    /// Edges that only come from `[build-dependencies]`. They are preferred
+    /// when cycles have to be broken.
+    pub build_only: BTreeSet<Edge>,
+    /// Edges that only come from `[dev-dependencies]`, between crates of
+    /// the closure. They are not part of `deps`.
+    pub dev_only: BTreeSet<Edge>,
+}
+
+/// One step of a bootstrap plan.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BootstrapStep {
+    /// Package the crate.
+    Build(Node),
+    /// Package the crate without the listed dependencies.
+    Bootstrap(Node, Vec<Node>),
+    /// Package the crate again, now that its dependencies are available.
+    Rebuild(Node),
+}
+
+/// Result of sorting a dependency graph.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct BuildOrder {
+    /// Crates whose dependencies all come earlier in the list.
+    pub order: Vec<Node>,
+    /// Dependency cycles, each listed from its smallest crate.
+    pub cycles: Vec<Vec<Node>>,
+    /// Cycles that need a dev-dependency to close, listed the same way.
+    pub dev_cycles: Vec<Vec<Node>>,
+    /// Crates outside any cycle that depend on a crate in one.
+    pub blocked: Vec<Node>,
+    /// Dependencies to leave out at first so that every cycle is broken.
+    pub dropped: Vec<Edge>,
+    /// How to package the crates of `cycles` and `blocked`, after `order`.
+    pub bootstrap: Vec<BootstrapStep>,
+}
+
+impl BuildOrder {
+    pub fn has_cycles(&self) -> bool {
+        !self.cycles.is_empty()
+    }
+
+    /// Every step: the crates of `order`, then the bootstrap plan.
+    pub fn steps(&self) -> Vec<BootstrapStep> {
+        self.order
+            .iter()
+            .cloned()
+            .map(BootstrapStep::Build)
+            .chain(self.bootstrap.iter().cloned())
+            .collect()
+    }
+
+    /// The crates of `order` as a `batch` input file. `batch` cannot leave
+    /// a dependency out, so the bootstrap plan follows in comments, to be
+    /// worked through by hand.
+    pub fn batch_list(&self) -> String {
+        let mut list =
+            String::from("# Build order; build with `takopack cargo batch <this file> --build`\n");
+        for (name, version) in &self.order {
+            list.push_str(&format!("{} {}\n", name, version));
+        }
+        if !self.bootstrap.is_empty() {
+            list.push_str(
+                "# Dependency cycles: package these by hand, in this order, after the \
+                 crates above\n",
+            );
+            for (i, step) in self.bootstrap.iter().enumerate() {
+                list.push_str(&format!("# {}. {}\n", i + 1, step_text(step)));
+            }
+        }
+        list
+    }
+}
+
+fn node_name((name, version): &Node) -> String {
+    format!("{} {}", name, version)
+}
+
+fn step_text(step: &BootstrapStep) -> String {
+    match step {
+        BootstrapStep::Build(node) => node_name(node),
+        BootstrapStep::Bootstrap(node, without) => format!(
+            "{} (bootstrap, without {})",
+            node_name(node),
+            without.iter().map(node_name).collect::<Vec<_>>().join(", ")
+        ),
+        BootstrapStep::Rebuild(node) => format!("{} (rebuild)", node_name(node)),
+    }
+}
+
+impl fmt::Display for BuildOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Build order ({} crates):", self.order.len())?;
+        let width = self.order.len().to_string().len();
+        for (i, node) in self.order.iter().enumerate() {
+            writeln!(f, "  {:>width$}. {}", i + 1, node_name(node), width = width)?;
+        }
+        if !self.dev_cycles.is_empty() {
+            writeln!(f)?;
+            writeln!(
+                f,
+                "Cycles through dev-dependencies ({}):",
+                self.dev_cycles.len()
+            )?;
+            for cycle in &self.dev_cycles {
+                let path: Vec<String> = cycle.iter().chain(cycle.first()).map(node_name).collect();
+                writeln!(f, "  - {}", path.join(" -> "))?;
+            }
+            writeln!(
+                f,
+                "These only matter when the tests are built: with their \
+                 dev-dependencies as BuildRequires the crates cannot be built."
+            )?;
+        }
+        if self.cycles.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(f)?;
+        writeln!(f, "Hard dependency cycles ({}):", self.cycles.len())?;
+        for cycle in &self.cycles {
+            let path: Vec<String> = cycle.iter().chain(cycle.first()).map(node_name).collect();
+            writeln!(f, "  - {}", path.join(" -> "))?;
+        }
+        if !self.blocked.is_empty() {
+            writeln!(f)?;
+            writeln!(
+                f,
+                "Waiting for a cycle to be broken ({}):",
+                self.blocked.len()
+            )?;
+            for node in &self.blocked {
+                writeln!(f, "  - {}", node_name(node))?;
+            }
+        }
+
+        writeln!(f)?;
+        writeln!(
+            f,
+            "Dependencies to drop for a first build ({}):",
+            self.dropped.len()
+        )?;
+        for (from, to) in &self.dropped {
+            writeln!(f, "  - {} -> {}", node_name(from), node_name(to))?;
+        }
+        writeln!(
+            f,
+            "Bootstrap builds leave these dependencies out (e.g. with %check off, or \
+             the feature that pulls them in disabled); the crate is rebuilt once \
+             they are packaged."
+        )?;
+        writeln!(f)?;
+        writeln!(f, "Bootstrap plan (after the build order above):")?;
+        let width = self.bootstrap.len().to_string().len();
+        for (i, step) in self.bootstrap.iter().enumerate() {
+            writeln!(f, "  {:>width$}. {}", i + 1, step_text(step), width = width)?;
+        }
+        Ok(())
+    }
+}
+
+/// Print the build order of `crate_name` (or of everything in `lockfile`),
+/// and write it to `output` as a `batch` input file if given. Returns 1
+/// when the graph has cycles.
+pub fn run_build_order(
+    crate_name: Option<&str>,
+    version: Option<&str>,
+    lockfile: Option<&Path>,
+    output: Option<&Path>,
+) -> Result<i32> {
+    let deps = match lockfile {
+        Some(lockfile) => {
+            let graph = lockfile_parser::parse_lockfile(lockfile)
+                .with_context(|| format!("Failed to parse {}", lockfile.display()))?;
+            let version = version
+                .map(|v| Version::parse(v).with_context(|| format!("Invalid version: {}", v)))
+                .transpose()?;
+            graph_from_lockfile(&graph, crate_name, version.as_ref())?
+        }
+        None => {
+            let Some(crate_name) = crate_name else {
+                takopack_bail!("a crate name or --from-lockfile is required");
+            };
+            let index = SparseIndex::crates_io()?;
+            graph_from_index(&index, crate_name, version)?
+        }
+    };
+    let order = build_order(deps);
+    print!("{}", order);
+    if let Some(output) = output {
+        fs::write(output, order.batch_list())
+            .with_context(|| format!("Failed to write {}", output.display()))?;
+    }
+    Ok(if order.has_cycles() { 1 } else { 0 })
+}
+
+/// Dependency graph of a set of packaged crates. A requirement is met by the highest packaged version satisfying it;
+/// dependencies that were not packaged are left out.
+pub fn graph_from_packaged(packaged: &[PackagedNode]) -> DepGraph {
+    let normalize = |name: &str| name.replace('_', "-");
+    let mut versions: HashMap<String, Vec<&Node>> = HashMap::new();
+    for (node, _) in packaged {
+        versions.entry(normalize(&node.0)).or_default().push(node);
+    }
+
+    let mut deps: BTreeMap<Node, BTreeSet<Node>> = BTreeMap::new();
+    for (node, dependencies) in packaged {
+        let edges = dependencies.iter().filter_map(|(name, req)| {
+            let req = match req {
+                Some(req) => VersionReq::parse(req).ok()?,
+                None => VersionReq::STAR,
+            };
+            versions
+                .get(&normalize(name))?
+                .iter()
+                .filter(|(_, version)| req.matches(version))
+                .max_by(|a, b| a.1.cmp(&b.1))
+                .map(|dep| (*dep).clone())
+        });
+        let edges: BTreeSet<Node> = edges.filter(|dep| dep != node).collect();
+        deps.entry(node.clone()).or_default().extend(edges);
+    }
+    DepGraph {
+        deps,
+        ..DepGraph::default()
+    }
+}
+
+/// Dependency graph of `crate_name` `version` (latest if `None`) from the
+/// index.
+pub fn graph_from_index(
+    index: &SparseIndex,
+    crate_name: &str,
+    version: Option<&str>,
+) -> Result<DepGraph> {
+    let mut entries: HashMap<String, Vec<IndexVersion>> = HashMap::new();
+    let mut versions_of = |name: &str| -> Result<Vec<IndexVersion>> {
+        if let Some(versions) = entries.get(name) {
+            return Ok(versions.clone());
+        }
+        let versions = index.versions(name)?;
+        entries.insert(name.to_string(), versions.clone());
+        Ok(versions)
+    };
+
+    let req = version.map(|v| format!("={}", v)).unwrap_or_default();
+    let root = select_index_version(crate_name, versions_of(crate_name)?, &req)?;
+    let seed = [(root.name.clone(), root.version.clone())];
+
+    let mut build_edges = BTreeSet::new();
+    let mut normal_edges = BTreeSet::new();
+    let mut dev_deps: Vec<(Node, String, String)> = Vec::new();
+    let deps = util::graph_from_succ(
+        seed,
+        &mut |(name, version): &Node| -> Result<(Vec<Node>, Vec<Node>)> {
+            let entry = versions_of(name)?
+                .into_iter()
+                .find(|v| &v.version == version)
+                .ok_or_else(|| format_err!("{} {} is not in the index", name, version))?;
+            let mut hard = Vec::new();
+            let mut soft = Vec::new();
+            for dep in entry.deps.iter().filter(|d| d.is_dev()) {
+                let from = (name.clone(), version.clone());
+                dev_deps.push((from, dep.crate_name().to_string(), dep.req.clone()));
+            }
+            for dep in entry.deps.iter().filter(|d| !d.is_dev()) {
+                let resolved = select_index_version(
+                    dep.crate_name(),
+                    versions_of(dep.crate_name())?,
+                    &dep.req,
+                )
+                .with_context(|| format!("{} {} depends on it", name, version));
+                let resolved = match resolved {
+                    Ok(resolved) => (resolved.name, resolved.version),
+                    Err(e) if dep.optional => {
+                        takopack_warn!("skipping optional dependency {}: {:#}", dep.name, e);
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                };
+                if dep.optional {
+                    soft.push(resolved);
+                    continue;
+                }
+                let edge = ((name.clone(), version.clone()), resolved.clone());
+                if dep.kind.as_deref() == Some("build") {
+                    build_edges.insert(edge);
+                } else {
+                    normal_edges.insert(edge);
+                }
+                hard.push(resolved);
+            }
+            Ok((hard, soft))
+        },
+        &mut |remain, graph| {
+            log::debug!(
+                "build-order: {} resolved, {} queued",
+                graph.len(),
+                remain.len()
+            );
+            Ok(())
+        },
+    )?;
+    let build_only = build_edges.difference(&normal_edges).cloned().collect();
+
+    // Dev-dependencies only count between crates the closure has anyway
+    let mut dev_only = BTreeSet::new();
+    for (from, name, req) in dev_deps {
+        if !deps.keys().any(|(n, _)| *n == name) {
+            continue;
+        }
+        let Ok(resolved) = select_index_version(&name, versions_of(&name)?, &req) else {
+            continue;
+        };
+        let to = (resolved.name, resolved.version);
+        let edge = (from, to);
+        if edge.0 != edge.1 && deps.contains_key(&edge.1) && !deps[&edge.0].contains(&edge.1) {
+            dev_only.insert(edge);
+        }
+    }
+    Ok(DepGraph {
+        deps,
+        build_only,
+        dev_only,
+    })
+}
+
+/// Dependency graph from a parsed `Cargo.lock`, limited to the closure of
+/// `crate_name` (any version unless `version` is given) when one is named.
+pub fn graph_from_lockfile(
+    graph: &DependencyGraph,
+    crate_name: Option<&str>,
+    version: Option<&Version>,
+) -> Result<DepGraph> {
+    let mut deps: BTreeMap<Node, BTreeSet<Node>> = BTreeMap::new();
+    for package in graph.packages() {
+        let node = (package.name.to_string(), (*package.version).clone());
+        let edges = package
+            .dependencies
+            .iter()
+            .map(|d| (d.name.to_string(), (*d.version).clone()));
+        deps.entry(node).or_default().extend(edges);
+    }
+    // dependencies missing their own [[package]] entry are still crates to build
+    let targets: Vec<Node> = deps.values().flatten().cloned().collect();
+    for target in targets {
+        deps.entry(target).or_default();
+    }
+
+    let Some(crate_name) = crate_name else {
+        return Ok(DepGraph {
+            deps,
+            ..DepGraph::default()
+        });
+    };
+    let roots: Vec<Node> = deps
+        .keys()
+        .filter(|(name, v)| name == crate_name && version.iter().all(|version| v == *version))
+        .cloned()
+        .collect();
+    if roots.is_empty() {
+        match version {
+            Some(version) => takopack_bail!("{} {} is not in the lockfile", crate_name, version),
+            None => takopack_bail!("{} is not in the lockfile", crate_name),
+        }
+    }
+    let mut closure = BTreeSet::new();
+    let mut queue = roots;
+    while let Some(node) = queue.pop() {
+        if closure.insert(node.clone()) {
+            queue.extend(deps[&node].iter().cloned());
+        }
+    }
+    deps.retain(|node, _| closure.contains(node));
+    Ok(DepGraph {
+        deps,
+        ..DepGraph::default()
+    })
+}
+
+/// Sort `graph` so that dependencies come first. Crates that cannot be
+/// placed are reported as cycles or as blocked, with a plan to bootstrap
+/// them.
+pub fn build_order(graph: DepGraph) -> BuildOrder {
+    let DepGraph {
+        deps,
+        build_only,
+        dev_only,
+    } = graph;
+    let dev_cycles = dev_cycles(&deps, &dev_only);
+    match sort(&deps) {
+        Ok(order) => BuildOrder {
+            order,
+            dev_cycles,
+            ..BuildOrder::default()
+        },
+        Err(remaining) => {
+            let acyclic: BTreeMap<Node, BTreeSet<Node>> = deps
+                .iter()
+                .filter(|(node, _)| !remaining.contains_key(*node))
+                .map(|(node, d)| (node.clone(), d.clone()))
+                .collect();
+            let order = sort(&acyclic).unwrap_or_default();
+            let cycles = find_cycles(&remaining);
+            let in_cycle: BTreeSet<&Node> = cycles.iter().flatten().collect();
+            let blocked = remaining
+                .keys()
+                .filter(|node| !in_cycle.contains(node))
+                .cloned()
+                .collect();
+            let dropped: Vec<Edge> = components(&remaining)
+                .iter()
+                .flat_map(|component| feedback_edges(component, &build_only))
+                .collect();
+            let bootstrap = bootstrap_plan(&remaining, &dropped);
+            BuildOrder {
+                order,
+                cycles,
+                dev_cycles,
+                blocked,
+                dropped,
+                bootstrap,
+            }
+        }
+    }
+}
+
+/// `util::topo_sort` with the edges pointing from a dependency to the crates
+/// that need it. On failure, returns the crates left with their unbuilt
+/// dependencies.
+fn sort(
+    deps: &BTreeMap<Node, BTreeSet<Node>>,
+) -> std::result::Result<Vec<Node>, BTreeMap<Node, BTreeSet<Node>>> {
+    let seed: Vec<Node> = deps
+        .iter()
+        .filter(|(_, d)| d.is_empty())
+        .map(|(node, _)| node.clone())
+        .collect();
+    util::topo_sort(seed, util::succ_to_pred(deps), deps.clone())
+}
+
+/// Cycles among `remaining`, at least one through every group of crates
+/// that depend on each other, each rotated to start at its smallest crate.
+/// At most [`MAX_CYCLES`] are returned.
+fn find_cycles(remaining: &BTreeMap<Node, BTreeSet<Node>>) -> Vec<Vec<Node>> {
+    let mut cycles = BTreeSet::new();
+    let mut done = BTreeSet::new();
+    'search: for start in remaining.keys() {
+        if done.contains(start) {
+            continue;
+        }
+        // A work stack rather than recursion, so that long dependency chains
+        // cannot exhaust the call stack: the path from `start`, with the
+        // dependencies of each crate on it still to be visited.
+        let mut path = vec![(start, deps_of(remaining, start))];
+        let mut on_path = BTreeMap::from([(start, 0)]);
+        while let Some((node, deps)) = path.last_mut() {
+            let node = *node;
+            let Some(dep) = deps.find(|dep| remaining.contains_key(*dep)) else {
+                on_path.remove(node);
+                done.insert(node);
+                path.pop();
+                continue;
+            };
+            if done.contains(dep) {
+                continue;
+            }
+            if let Some(&pos) = on_path.get(dep) {
+                let mut cycle: Vec<Node> = path[pos..].iter().map(|(n, _)| (*n).clone()).collect();
+                let smallest = (0..cycle.len()).min_by_key(|&i| &cycle[i]).unwrap_or(0);
+                cycle.rotate_left(smallest);
+                cycles.insert(cycle);
+                if cycles.len() >= MAX_CYCLES {
+                    break 'search;
+                }
+                continue;
+            }
+            on_path.insert(dep, path.len());
+            path.push((dep, deps_of(remaining, dep)));
+        }
+    }
+    cycles.into_iter().collect()
+}
+
+/// The dependencies of `node` in `graph`.
+fn deps_of<'a>(
+    graph: &'a BTreeMap<Node, BTreeSet<Node>>,
+    node: &Node,
+) -> impl Iterator<Item = &'a Node> {
+    graph.get(node).into_iter().flatten()
+}
+
+/// For every edge of `dev_only` that closes a cycle with the edges of `deps`,
+/// the shortest such cycle, rotated to start at its smallest crate. At most
+/// [`MAX_CYCLES`] are returned.
+fn dev_cycles(deps: &BTreeMap<Node, BTreeSet<Node>>, dev_only: &BTreeSet<Edge>) -> Vec<Vec<Node>> {
+    let mut all = deps.clone();
+    for (from, to) in dev_only {
+        all.entry(from.clone()).or_default().insert(to.clone());
+    }
+
+    let mut cycles = BTreeSet::new();
+    for (from, to) in dev_only {
+        if cycles.len() >= MAX_CYCLES {
+            break;
+        }
+        // breadth-first from `to` back to `from`
+        let mut parent: BTreeMap<&Node, &Node> = BTreeMap::new();
+        let mut queue = std::collections::VecDeque::from([to]);
+        while let Some(node) = queue.pop_front() {
+            if node == from {
+                break;
+            }
+            for dep in all.get(node).into_iter().flatten() {
+                if dep != to && !parent.contains_key(dep) {
+                    parent.insert(dep, node);
+                    queue.push_back(dep);
+                }
+            }
+        }
+        if !parent.contains_key(from) {
+            continue;
+        }
+        let mut cycle = vec![from.clone()];
+        let mut node = from;
+        while node != to {
+            node = parent[node];
+            cycle.push(node.clone());
+        }
+        // `cycle` runs backwards: from, ..., to
+        cycle[1..].reverse();
+        let smallest = (0..cycle.len()).min_by_key(|&i| &cycle[i]).unwrap_or(0);
+        cycle.rotate_left(smallest);
+        cycles.insert(cycle);
+    }
+    cycles.into_iter().collect()
+}
+
+/// Groups of crates among `remaining` that all depend on each other, directly
+/// or not, each with the edges between its members.
+fn components(remaining: &BTreeMap<Node, BTreeSet<Node>>) -> Vec<BTreeMap<Node, BTreeSet<Node>>> {
+    let reachable: BTreeMap<&Node, BTreeSet<&Node>> = remaining
+        .keys()
+        .map(|start| {
+            let mut seen = BTreeSet::new();
+            let mut queue: Vec<&Node> = remaining[start].iter().collect();
+            while let Some(node) = queue.pop() {
+                if seen.insert(node) {
+                    queue.extend(remaining.get(node).into_iter().flatten());
+                }
+            }
+            (start, seen)
+        })
+        .collect();
+
+    let mut assigned = BTreeSet::new();
+    let mut components = Vec::new();
+    for (node, reach) in &reachable {
+        if assigned.contains(node) || !reach.contains(node) {
+            continue;
+        }
+        let members: BTreeSet<&Node> = reach
+            .iter()
+            .filter(|member| reachable[*member].contains(node))
+            .copied()
+            .collect();
+        assigned.extend(members.iter().copied());
+        components.push(
+            members
+                .iter()
+                .map(|member| {
+                    let deps = remaining[*member]
+                        .iter()
+                        .filter(|dep| members.contains(dep))
+                        .cloned()
+                        .collect();
+                    ((*member).clone(), deps)
+                })
+                .collect(),
+        );
+    }
+    components
+}
+
+/// The smallest set of edges whose removal leaves `component` without a
+/// cycle. Among sets of the same size, the one making the fewest crates
+/// build twice wins, then the one with the most build-only edges.
+fn feedback_edges(
+    component: &BTreeMap<Node, BTreeSet<Node>>,
+    build_only: &BTreeSet<Edge>,
+) -> Vec<Edge> {
+    let edges: Vec<Edge> = component
+        .iter()
+        .flat_map(|(from, deps)| deps.iter().map(move |to| (from.clone(), to.clone())))
+        .collect();
+    if edges.len() <= MAX_EXACT_EDGES {
+        for size in 1..=MAX_EXACT_CUT.min(edges.len()) {
+            let best = combinations(edges.len(), size)
+                .into_iter()
+                .filter(|cut| {
+                    let mut reduced = component.clone();
+                    for &i in cut {
+                        let (from, to) = &edges[i];
+                        reduced.entry(from.clone()).or_default().remove(to);
+                    }
+                    sort(&reduced).is_ok()
+                })
+                .min_by_key(|cut| {
+                    let rebuilt: BTreeSet<&Node> = cut.iter().map(|&i| &edges[i].0).collect();
+                    let hard = cut
+                        .iter()
+                        .filter(|&&i| !build_only.contains(&edges[i]))
+                        .count();
+                    (rebuilt.len(), hard)
+                });
+            if let Some(cut) = best {
+                return cut.into_iter().map(|i| edges[i].clone()).collect();
+            }
+        }
+    }
+    back_edges(component)
+}
+
+/// All `k`-element subsets of `0..n`, in lexicographic order.
+fn combinations(n: usize, k: usize) -> Vec<Vec<usize>> {
+    let mut all = Vec::new();
+    let mut combo: Vec<usize> = (0..k).collect();
+    loop {
+        all.push(combo.clone());
+        let Some(i) = (0..k).rev().find(|&i| combo[i] < n - k + i) else {
+            return all;
+        };
+        let start = combo[i] + 1;
+        for (offset, slot) in combo[i..].iter_mut().enumerate() {
+            *slot = start + offset;
+        }
+    }
+}
+
+/// Edges pointing back into the depth-first search stack. Not minimal, but
+/// removing them always leaves `component` without a cycle.
+fn back_edges(component: &BTreeMap<Node, BTreeSet<Node>>) -> Vec<Edge> {
+    let mut back = Vec::new();
+    let mut done = BTreeSet::new();
+    for start in component.keys() {
+        if done.contains(start) {
+            continue;
+        }
+        // A work stack, as in find_cycles
+        let mut on_path = BTreeSet::from([start]);
+        let mut path = vec![(start, deps_of(component, start))];
+        while let Some((node, deps)) = path.last_mut() {
+            let node = *node;
+            match deps.next() {
+                Some(dep) if on_path.contains(dep) => back.push((node.clone(), dep.clone())),
+                Some(dep) if !done.contains(dep) => {
+                    on_path.insert(dep);
+                    path.push((dep, deps_of(component, dep)));
+                }
+                Some(_) => {}
+                None => {
+                    on_path.remove(node);
+                    done.insert(node);
+                    path.pop();
+                }
+            }
+        }
+    }
+    back
+}
+
+/// Order `remaining` with the `dropped` edges left out. A crate that comes
+/// before one of its dropped dependencies is bootstrapped without it, and
+/// rebuilt as soon as all of them are packaged.
+fn bootstrap_plan(
+    remaining: &BTreeMap<Node, BTreeSet<Node>>,
+    dropped: &[Edge],
+) -> Vec<BootstrapStep> {
+    let mut reduced = remaining.clone();
+    for (from, to) in dropped {
+        if let Some(deps) = reduced.get_mut(from) {
+            deps.remove(to);
+        }
+    }
+    // the dropped edges break every cycle, so this always sorts
+    let order = sort(&reduced).unwrap_or_default();
+
+    let mut plan = Vec::new();
+    let mut built = BTreeSet::new();
+    let mut waiting: Vec<(Node, BTreeSet<Node>)> = Vec::new();
+    for node in order {
+        let without: BTreeSet<Node> = dropped
+            .iter()
+            .filter(|(from, to)| *from == node && !built.contains(to))
+            .map(|(_, to)| to.clone())
+            .collect();
+        if without.is_empty() {
+            plan.push(BootstrapStep::Build(node.clone()));
+        } else {
+            let step = BootstrapStep::Bootstrap(node.clone(), without.iter().cloned().collect());
+            plan.push(step);
+            waiting.push((node.clone(), without));
+        }
+        for (_, missing) in &mut waiting {
+            missing.remove(&node);
+        }
+        built.insert(node);
+
+        let (ready, rest): (Vec<_>, Vec<_>) = waiting
+            .into_iter()
+            .partition(|(_, missing)| missing.is_empty());
+        plan.extend(
+            ready
+                .into_iter()
+                .map(|(node, _)| BootstrapStep::Rebuild(node)),
+        );
+        waiting = rest;
+    }
+    plan
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        back_edges, build_order, find_cycles, graph_from_index, graph_from_lockfile,
+        graph_from_packaged, BootstrapStep, DepGraph, Node,
+    };
+    use crate::crates::SparseIndex;
+    use crate::lockfile_parser::parse_lockfile_str;
+    use semver::Version;
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::fs;
+
+    fn node(name: &str, version: &str) -> Node {
+        (name.to_string(), Version::parse(version).unwrap())
+    }
+
+    fn graph(edges: &[(&str, &[&str])]) -> DepGraph {
+        let deps: BTreeMap<Node, BTreeSet<Node>> = edges
+            .iter()
+            .map(|(name, deps)| {
+                (
+                    node(name, "1.0.0"),
+                    deps.iter().map(|d| node(d, "1.0.0")).collect(),
+                )
+            })
+            .collect();
+        DepGraph {
+            deps,
+            ..DepGraph::default()
+        }
+    }
+
+    #[test]
+    fn dependencies_are_built_first() {
+        let order = build_order(graph(&[
+            ("app", &["serde", "log"]),
+            ("serde", &["serde_derive"]),
+            ("serde_derive", &[]),
+            ("log", &[]),
+        ]));
+        let names: Vec<&str> = order.order.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names, vec!["log", "serde_derive", "serde", "app"]);
+        assert!(!order.has_cycles());
+    }
+
+    #[test]
+    fn cycles_are_reported_with_blocked_crates() {
+        let order = build_order(graph(&[
+            ("app", &["b"]),
+            ("a", &["b", "leaf"]),
+            ("b", &["c"]),
+            ("c", &["a"]),
+            ("leaf", &[]),
+        ]));
+        assert_eq!(order.order, vec![node("leaf", "1.0.0")]);
+        assert_eq!(
+            order.cycles,
+            vec![vec![
+                node("a", "1.0.0"),
+                node("b", "1.0.0"),
+                node("c", "1.0.0")
+            ]]
+        );
+        assert_eq!(order.blocked, vec![node("app", "1.0.0")]);
+        let text = order.to_string();
+        assert!(text.contains("  - a 1.0.0 -> b 1.0.0 -> c 1.0.0 -> a 1.0.0\n"));
+        assert!(text.contains("Waiting for a cycle to be broken (1):\n  - app 1.0.0\n"));
+
+        assert_eq!(
+            order.dropped,
+            vec![(node("a", "1.0.0"), node("b", "1.0.0"))]
+        );
+        assert_eq!(
+            order.bootstrap,
+            vec![
+                BootstrapStep::Bootstrap(node("a", "1.0.0"), vec![node("b", "1.0.0")]),
+                BootstrapStep::Build(node("c", "1.0.0")),
+                BootstrapStep::Build(node("b", "1.0.0")),
+                BootstrapStep::Rebuild(node("a", "1.0.0")),
+                BootstrapStep::Build(node("app", "1.0.0")),
+            ]
+        );
+        assert!(text.contains("  1. a 1.0.0 (bootstrap, without b 1.0.0)\n"));
+        assert!(text.contains("  4. a 1.0.0 (rebuild)\n"));
+    }
+
+    #[test]
+    fn long_chains_do_not_exhaust_the_stack() {
+        let nodes: Vec<Node> = (0..100_000)
+            .map(|i| node(&format!("c{}", i), "1.0.0"))
+            .collect();
+        let chain: BTreeMap<Node, BTreeSet<Node>> = nodes
+            .iter()
+            .enumerate()
+            .map(|(i, n)| {
+                let next = nodes[(i + 1) % nodes.len()].clone();
+                (n.clone(), BTreeSet::from([next]))
+            })
+            .collect();
+        let cycles = find_cycles(&chain);
+        assert_eq!(cycles.len(), 1);
+        assert_eq!(cycles[0].len(), nodes.len());
+        assert_eq!(back_edges(&chain).len(), 1);
+    }
+
+    #[test]
+    fn cycles_are_broken_with_few_rebuilds_and_build_edges_first() {
+        // dropping both edges out of `a` rebuilds one crate instead of two
+        let order = build_order(graph(&[("a", &["b", "c"]), ("b", &["a"]), ("c", &["a"])]));
+        assert_eq!(
+            order.dropped,
+            vec![
+                (node("a", "1.0.0"), node("b", "1.0.0")),
+                (node("a", "1.0.0"), node("c", "1.0.0")),
+            ]
+        );
+        assert_eq!(order.bootstrap.len(), 4);
+        assert_eq!(
+            order.bootstrap.last(),
+            Some(&BootstrapStep::Rebuild(node("a", "1.0.0")))
+        );
+
+        let mut cycle = graph(&[("x", &["y"]), ("y", &["x"])]);
+        cycle
+            .build_only
+            .insert((node("y", "1.0.0"), node("x", "1.0.0")));
+        let order = build_order(cycle);
+        assert_eq!(
+            order.dropped,
+            vec![(node("y", "1.0.0"), node("x", "1.0.0"))]
+        );
+        assert_eq!(
+            order.bootstrap,
+            vec![
+                BootstrapStep::Bootstrap(node("y", "1.0.0"), vec![node("x", "1.0.0")]),
+                BootstrapStep::Build(node("x", "1.0.0")),
+                BootstrapStep::Rebuild(node("y", "1.0.0")),
+            ]
+        );
+    }
+
+    #[test]
+    fn dev_only_cycles_are_listed_apart_from_hard_ones() {
+        let mut deps = graph(&[
+            ("serde", &["serde_derive"]),
+            ("serde_derive", &["syn"]),
+            ("syn", &[]),
+            ("x", &["y"]),
+            ("y", &["x"]),
+        ]);
+        deps.dev_only
+            .insert((node("syn", "1.0.0"), node("serde", "1.0.0")));
+        deps.dev_only
+            .insert((node("syn", "1.0.0"), node("x", "1.0.0")));
+        let order = build_order(deps);
+        assert_eq!(
+            order.cycles,
+            vec![vec![node("x", "1.0.0"), node("y", "1.0.0")]]
+        );
+        assert_eq!(
+            order.dev_cycles,
+            vec![vec![
+                node("serde", "1.0.0"),
+                node("serde_derive", "1.0.0"),
+                node("syn", "1.0.0")
+            ]]
+        );
+        let text = order.to_string();
+        assert!(text.contains(
+            "Cycles through dev-dependencies (1):\n  - serde 1.0.0 -> serde_derive 1.0.0 -> syn 1.0.0 -> serde 1.0.0\n"
+        ));
+        assert!(text.contains("Hard dependency cycles (1):\n  - x 1.0.0 -> y 1.0.0 -> x 1.0.0\n"));
+    }
+
+    #[test]
+    fn packaged_crates_are_ordered_for_batch() {
+        let dep = |name: &str, req: Option<&str>| (name.to_string(), req.map(str::to_string));
+        let packaged = vec![
+            (
+                node("app", "1.0.0"),
+                vec![
+                    dep("syn", Some("^1")),
+                    dep("serde", None),
+                    dep("libc", None),
+                ],
+            ),
+            (node("syn", "2.0.60"), vec![]),
+            (
+                node("syn", "1.0.109"),
+                vec![dep("proc-macro2", Some("^1.0.50"))],
+            ),
+            (node("proc_macro2", "1.0.80"), vec![]),
+            (
+                node("serde", "1.0.200"),
+                vec![dep("serde_derive", Some("^1"))],
+            ),
+            (
+                node("serde_derive", "1.0.200"),
+                vec![dep("serde", Some("^1"))],
+            ),
+        ];
+        let graph = graph_from_packaged(&packaged);
+        assert_eq!(
+            graph.deps[&node("app", "1.0.0")],
+            [node("serde", "1.0.200"), node("syn", "1.0.109")]
+                .into_iter()
+                .collect()
+        );
+        assert_eq!(
+            graph.deps[&node("syn", "1.0.109")],
+            [node("proc_macro2", "1.0.80")].into_iter().collect()
+        );
+
+        let list = build_order(graph).batch_list();
+        let lines: Vec<&str> = list.lines().filter(|l| !l.starts_with('#')).collect();
+        assert_eq!(
+            lines,
+            vec!["proc_macro2 1.0.80", "syn 2.0.60", "syn 1.0.109"]
+        );
+        assert!(list.ends_with(
+            "# 1. serde 1.0.200 (bootstrap, without serde_derive 1.0.200)\n\
+             # 2. app 1.0.0\n\
+             # 3. serde_derive 1.0.200\n\
+             # 4. serde 1.0.200 (rebuild)\n"
+        ));
+    }
+
+    #[test]
+    fn lockfile_graph_is_limited_to_the_named_crate() {
+        let lock = parse_lockfile_str(
+            r#"
+version = 3
+
+[[package]]
+name = "workspace-member"
+version = "0.1.0"
+dependencies = ["app", "tool"]
+
+[[package]]
+name = "app"
+version = "0.1.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+dependencies = ["log"]
+
+[[package]]
+name = "log"
+version = "0.4.22"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "tool"
+version = "0.1.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+dependencies = ["regex"]
+
+[[package]]
+name = "regex"
+version = "1.10.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+"#,
+        )
+        .unwrap();
+
+        let all = graph_from_lockfile(&lock, None, None).unwrap();
+        assert_eq!(all.deps.len(), 4);
+        let app = graph_from_lockfile(&lock, Some("app"), None).unwrap();
+        assert_eq!(
+            app.deps.keys().cloned().collect::<Vec<_>>(),
+            vec![node("app", "0.1.0"), node("log", "0.4.22")]
+        );
+        assert!(graph_from_lockfile(&lock, Some("missing"), None).is_err());
+    }
+
+    #[test]
+    fn index_graph_follows_normal_and_build_dependencies() {
+        let cache = tempfile::tempdir().unwrap();
+        let write = |path: &str, lines: &[&str]| {
+            let file = cache.path().join(path);
+            fs::create_dir_all(file.parent().unwrap()).unwrap();
+            fs::write(file, lines.join("\n")).unwrap();
+        };
+        write(
+            "3/a/app",
+            &[r#"{"name":"app","vers":"1.0.0","deps":[
+                {"name":"log","req":"^0.4","kind":"normal"},
+                {"name":"cc","req":"^1","kind":"build"},
+                {"name":"json","package":"serde_json","req":"^1","optional":true},
+                {"name":"proptest","req":"^1","kind":"dev"}]}"#
+                .replace('\n', "")
+                .as_str()],
+        );
+        write(
+            "3/l/log",
+            &[
+                r#"{"name":"log","vers":"0.4.21","deps":[]}"#,
+                r#"{"name":"log","vers":"0.4.22","deps":[{"name":"app","req":"^1","kind":"dev"}]}"#,
+                r#"{"name":"log","vers":"0.4.23","deps":[],"yanked":true}"#,
+            ],
+        );
+        write("2/cc", &[r#"{"name":"cc","vers":"1.1.0","deps":[]}"#]);
+        write(
+            "se/rd/serde_json",
+            &[r#"{"name":"serde_json","vers":"1.0.120","deps":[]}"#],
+        );
+        let index = SparseIndex::new("http://127.0.0.1:9", Some(cache.path().to_path_buf()));
+
+        let deps = graph_from_index(&index, "app", None).unwrap();
+        assert_eq!(
+            deps.deps[&node("app", "1.0.0")],
+            [node("cc", "1.1.0"), node("log", "0.4.22")]
+                .into_iter()
+                .collect()
+        );
+        assert!(deps.deps.contains_key(&node("serde_json", "1.0.120")));
+        assert!(!deps.deps.keys().any(|(name, _)| name == "proptest"));
+        assert_eq!(
+            deps.build_only.iter().collect::<Vec<_>>(),
+            vec![&(node("app", "1.0.0"), node("cc", "1.1.0"))]
+        );
+
+        assert_eq!(
+            deps.dev_only.iter().collect::<Vec<_>>(),
+            vec![&(node("log", "0.4.22"), node("app", "1.0.0"))]
+        );
+
+        let order = build_order(deps);
+        assert_eq!(order.order.last(), Some(&node("app", "1.0.0")));
+        assert!(!order.has_cycles());
+        assert_eq!(
+            order.dev_cycles,
+            vec![vec![node("app", "1.0.0"), node("log", "0.4.22")]]
+        );
+    }
+}
@@ -0,0 +1,91 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::errors::Result;
+
+/// Cooperative cancellation flag shared between a long-running operation and
+/// whoever wants to stop it (a signal handler, an embedding application, ...).
+///
+/// Cloning a token yields a handle to the same flag. Operations poll it
+/// between crates and between pipeline phases, so a cancelled run stops at
+/// the next checkpoint instead of in the middle of writing output.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request cancellation. Safe to call from any thread, any number of times.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Return a [`Cancelled`] error if cancellation has been requested.
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            return Err(Cancelled.into());
+        }
+        Ok(())
+    }
+}
+
+/// Error returned when an operation stops because its token was cancelled.
+///
+/// Use [`is_cancelled`] to tell it apart from ordinary packaging failures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("operation cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Whether `err` (or anything in its context chain) is a [`Cancelled`] error.
+pub fn is_cancelled(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| cause.is::<Cancelled>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_cancelled, CancellationToken};
+    use anyhow::Context;
+
+    #[test]
+    fn clones_share_the_cancellation_flag() {
+        let token = CancellationToken::new();
+        let handle = token.clone();
+        assert!(token.check().is_ok());
+
+        handle.cancel();
+
+        assert!(token.is_cancelled());
+        let err = token.check().unwrap_err();
+        assert!(is_cancelled(&err));
+    }
+
+    #[test]
+    fn cancellation_is_detected_through_context() {
+        let token = CancellationToken::new();
+        token.cancel();
+
+        let err = token
+            .check()
+            .context("Failed to extract package for serde")
+            .unwrap_err();
+
+        assert!(is_cancelled(&err));
+        assert!(!is_cancelled(&anyhow::anyhow!("network error")));
+    }
+}
@@ -1,9 +1,19 @@
-use clap::{builder::styling::AnsiColor, builder::Styles, Parser, Subcommand};
+use std::io::Write;
+
+use clap::{
+    builder::styling::AnsiColor, builder::Styles, ArgAction, CommandFactory, Parser, Subcommand,
+};
+use clap_complete::Shell;
 
 use crate::{
-    package::{PackageExecuteArgs, PackageExtractArgs, PackageInitArgs},
+    backup::BackupArgs,
+    deps::DepsStyle,
+    events::OutputFormat,
+    package::{DownloadArgs, PackageExecuteArgs, PackageExtractArgs, PackageInitArgs},
     range_audit::RangeCapabilityPolicy,
     recursive_package::RecursivePackageArgs,
+    rpm_build::BuildTool,
+    stream_db::ExportFormat,
 };
 
 const CLI_STYLE: Styles = Styles::styled()
@@ -17,6 +27,28 @@ const CLI_STYLE: Styles = Styles::styled()
 #[command(version)]
 #[command(styles = CLI_STYLE)]
 pub struct Cli {
+    /// Run as takopack's integration tests expect: packages are named
+    /// `ruzt-*`, suspicious files in crates only warn and crate tests are
+    /// not run. See `takopack::test_mode`
+    #[arg(long, global = true, hide = true)]
+    pub test_mode: bool,
+    /// Run even if this takopack does not satisfy
+    /// `required_takopack_version` in takopack.toml
+    #[arg(long, global = true)]
+    pub ignore_version_check: bool,
+    /// How progress and results are reported: human-readable lines, JSON
+    /// lines on stdout for tools, or only failures
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Human)]
+    pub output_format: OutputFormat,
+    /// Only print warnings, failures and errors
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+    /// Print more: the info log, lines hidden behind progress bars and the
+    /// full output of failed builds; repeat for the debug and trace logs
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    pub verbose: u8,
+    #[command(flatten)]
+    pub backup: BackupArgs,
     #[command(subcommand)]
     pub command: Opt,
 }
@@ -25,17 +57,57 @@ pub struct Cli {
 pub enum Opt {
     /// Rust/Cargo package operations
     #[command(subcommand)]
-    Cargo(CargoOpt),
+    Cargo(Box<CargoOpt>),
     /// Python package operations
     #[command(subcommand)]
     Py(PyOpt),
+    /// Information about takopack itself
+    #[command(name = "self", subcommand)]
+    SelfCmd(SelfOpt),
+    /// Print the shell completion script of takopack, e.g.
+    /// `takopack completions bash > /usr/share/bash-completion/completions/takopack`
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+}
+
+/// Write the completion script of takopack for `shell` to `out`.
+pub fn write_completions(shell: Shell, out: &mut dyn Write) {
+    clap_complete::generate(shell, &mut Cli::command(), "takopack", out);
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum SelfOpt {
+    /// Print the version, enabled features, configuration and profile, for
+    /// bug reports
+    Check,
 }
 
 #[derive(Debug, Clone, Subcommand)]
 pub enum CargoOpt {
     /// Update the crates.io index cache
     #[command(alias = "u")]
-    Update,
+    Update {
+        /// Only refresh the index entries of these crates.
+        #[arg(value_name = "CRATE")]
+        crates: Vec<String>,
+        /// Only refresh cached entries last validated longer ago than AGE
+        /// (e.g. 30m, 12h, 7d).
+        #[arg(
+            long,
+            value_name = "AGE",
+            value_parser = crate::index_cache::parse_age,
+            conflicts_with = "crates"
+        )]
+        auto_refresh: Option<std::time::Duration>,
+        /// Show the location, size and age of the cache without changing it.
+        #[arg(long, conflicts_with_all = ["crates", "auto_refresh"])]
+        status: bool,
+        /// Empty the shared cache of downloaded crates instead.
+        #[arg(long, conflicts_with_all = ["crates", "auto_refresh", "status"])]
+        crate_cache: bool,
+    },
     /// Package a single Rust crate and generate RPM spec file
     #[command(alias = "pkg")]
     Package {
@@ -48,6 +120,20 @@ pub enum CargoOpt {
         /// Policy for range-capability warnings (warn|error|allow)
         #[arg(long, value_enum, default_value_t = RangeCapabilityPolicy::Warn)]
         range_capability_policy: RangeCapabilityPolicy,
+        /// Previously packaged version; writes crate-diff.txt summarizing
+        /// the source changes next to the spec.
+        #[arg(long, value_name = "OLD_VERSION")]
+        diff_from: Option<String>,
+        /// Build the generated spec: a source RPM with rpmbuild, or binary
+        /// RPMs with mock. The results are written next to the spec
+        #[arg(
+            long,
+            value_enum,
+            value_name = "TOOL",
+            num_args = 0..=1,
+            default_missing_value = "srpm"
+        )]
+        build: Option<BuildTool>,
     },
     /// Recursively package a crate and all its dependencies (vendor mode)
     #[command(alias = "v")]
@@ -76,15 +162,36 @@ pub enum CargoOpt {
         /// Output root directory. Each package is generated under this root.
         #[arg(short, long, value_name = "OUT_ROOT")]
         output: Option<std::path::PathBuf>,
+
+        /// Build every generated spec: a source RPM with rpmbuild, or binary
+        /// RPMs with mock. The results are written next to the spec
+        #[arg(
+            long,
+            value_enum,
+            value_name = "TOOL",
+            num_args = 0..=1,
+            default_missing_value = "srpm"
+        )]
+        build: Option<BuildTool>,
+
+        /// Upload the built source RPMs to this COPR project (OWNER/PROJECT,
+        /// or PROJECT of the user), with the credentials in ~/.config/copr
+        #[arg(long, value_name = "PROJECT", requires = "build")]
+        copr: Option<String>,
+
+        #[command(flatten)]
+        download: DownloadArgs,
     },
     /// Package from a local crate directory (with Cargo.toml)
     #[command(name = "localpkg", alias = "local")]
     LocalPackage {
-        /// Path to directory containing Cargo.toml (or path to Cargo.toml itself)
+        /// Path to directory containing Cargo.toml (or path to Cargo.toml itself).
+        /// A workspace root produces one spec per member.
         #[arg(value_name = "PATH")]
         path: std::path::PathBuf,
 
         /// Final output package directory. Files are written directly into this directory.
+        /// For a workspace, this is the root under which each member gets its own directory.
         #[arg(
             short = 'o',
             long = "directory",
@@ -100,6 +207,190 @@ pub enum CargoOpt {
         #[arg(long, value_enum, default_value_t = RangeCapabilityPolicy::Warn)]
         range_capability_policy: RangeCapabilityPolicy,
     },
+    /// Package a crate from a git repository
+    #[command(name = "gitpkg", alias = "git")]
+    GitPackage {
+        /// Repository URL (anything `git clone` accepts).
+        #[arg(value_name = "URL")]
+        url: String,
+
+        /// Commit (or any revision) to package.
+        #[arg(long, group = "git_ref")]
+        rev: Option<String>,
+
+        /// Tag to package.
+        #[arg(long, group = "git_ref")]
+        tag: Option<String>,
+
+        /// Branch to package (default: the remote's default branch).
+        #[arg(long, group = "git_ref")]
+        branch: Option<String>,
+
+        /// Crate directory or Cargo.toml inside the repository, e.g. a
+        /// workspace member. Defaults to the repository root.
+        #[arg(long, value_name = "PATH")]
+        path: Option<std::path::PathBuf>,
+
+        /// Final output package directory, as for localpkg.
+        #[arg(
+            short = 'o',
+            long = "directory",
+            alias = "output",
+            value_name = "OUT_DIR"
+        )]
+        output: Option<std::path::PathBuf>,
+
+        #[command(flatten)]
+        finish: PackageExecuteArgs,
+
+        /// Policy for range-capability warnings (warn|error|allow)
+        #[arg(long, value_enum, default_value_t = RangeCapabilityPolicy::Warn)]
+        range_capability_policy: RangeCapabilityPolicy,
+    },
+    /// Generate the spec of a crate and summarize how it differs from an
+    /// existing spec: version, subpackages and requirements
+    #[command(name = "diff-spec")]
+    DiffSpec {
+        #[command(flatten)]
+        init: PackageInitArgs,
+        #[command(flatten)]
+        finish: PackageExecuteArgs,
+
+        /// The spec to compare with, e.g. the one packaged now
+        #[arg(long, value_name = "SPEC")]
+        against: std::path::PathBuf,
+
+        /// Write the summary to this file instead of stdout
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<std::path::PathBuf>,
+    },
+    /// Regenerate the spec in a package directory for a new version of its
+    /// crate, keeping the regions between `# takopack:keep` and
+    /// `# takopack:end-keep`
+    #[command(name = "update-spec")]
+    UpdateSpec {
+        /// Package directory with the spec to update
+        #[arg(value_name = "DIR")]
+        dir: std::path::PathBuf,
+
+        /// Version to update to; the latest without it
+        #[arg(value_name = "VERSION")]
+        version: Option<String>,
+
+        #[command(flatten)]
+        download: DownloadArgs,
+        #[command(flatten)]
+        finish: PackageExecuteArgs,
+    },
+    /// Check generated specs for FIXMEs, unparsable requirements, features
+    /// without Provides, empty %files, invalid licenses and long summaries
+    Lint {
+        /// Spec file, or directory searched for spec files
+        #[arg(value_name = "SPEC_OR_DIR")]
+        path: std::path::PathBuf,
+    },
+    /// Check that the crate() requirements of the specs in a directory, such
+    /// as the output of vendor, are provided by specs in it
+    Verify {
+        /// Directory searched for spec files
+        #[arg(value_name = "OUTPUT_DIR")]
+        dir: std::path::PathBuf,
+    },
+    /// Print the dependencies of a crate with the given features
+    Deps {
+        /// Path to a directory containing Cargo.toml, or a Cargo.toml file
+        #[arg(value_name = "PATH")]
+        path: std::path::PathBuf,
+
+        /// Features to enable, separated by commas or spaces
+        #[arg(short = 'F', long, value_name = "FEATURES")]
+        features: Vec<String>,
+
+        /// Enable all features
+        #[arg(long)]
+        all_features: bool,
+
+        /// Do not enable the default feature
+        #[arg(long)]
+        no_default_features: bool,
+
+        /// crate() requirements as in a spec, or Debian dependencies
+        #[arg(long, value_enum, default_value_t = DepsStyle::Crate)]
+        style: DepsStyle,
+    },
+    /// Summarize source changes between two versions of a crate
+    #[command(name = "crate-diff")]
+    CrateDiff {
+        /// Name of the crate
+        #[arg(value_name = "CRATE")]
+        crate_name: String,
+
+        /// Version currently packaged
+        #[arg(value_name = "OLD_VERSION")]
+        old_version: String,
+
+        /// Version to update to
+        #[arg(value_name = "NEW_VERSION")]
+        new_version: String,
+
+        /// Write the summary to this file instead of stdout
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<std::path::PathBuf>,
+    },
+    /// Print the order in which a crate's dependencies have to be packaged
+    #[command(name = "build-order")]
+    BuildOrder {
+        /// Name of the crate. With --from-lockfile, limits the order to this
+        /// crate and its dependencies
+        #[arg(value_name = "CRATE", required_unless_present = "from_lockfile")]
+        crate_name: Option<String>,
+
+        /// Version of the crate (latest if omitted)
+        #[arg(value_name = "VERSION")]
+        version: Option<String>,
+
+        /// Take the dependency graph from the registry packages of this
+        /// Cargo.lock instead of the crates.io index
+        #[arg(long, value_name = "CARGO_LOCK")]
+        from_lockfile: Option<std::path::PathBuf>,
+
+        /// Also write the order to this file, in the input format of
+        /// `batch` (build it with `batch FILE --build`)
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<std::path::PathBuf>,
+    },
+    /// Build generated specs with rpmbuild or mock
+    #[command(name = "build-spec")]
+    BuildSpec {
+        /// Package directory, or an output root whose subdirectories are
+        /// package directories
+        #[arg(value_name = "DIR")]
+        dir: std::path::PathBuf,
+
+        /// srpm builds a source RPM; mock also rebuilds it into binary RPMs
+        #[arg(long, value_enum, default_value_t = BuildTool::Srpm)]
+        tool: BuildTool,
+
+        /// mock configuration (mock -r). Overrides mock_root in takopack.toml
+        #[arg(long, value_name = "CONFIG")]
+        mock_root: Option<String>,
+    },
+    /// Upload built source RPMs to a COPR project
+    #[command(name = "copr-upload")]
+    CoprUpload {
+        /// Package directory, or an output root whose subdirectories are
+        /// package directories
+        #[arg(value_name = "DIR")]
+        dir: std::path::PathBuf,
+
+        /// COPR project: OWNER/PROJECT, or PROJECT of the user
+        #[arg(long, value_name = "PROJECT")]
+        project: String,
+
+        /// copr-cli configuration with the API token (default: ~/.config/copr)
+        #[arg(long, value_name = "FILE")]
+        credentials: Option<std::path::PathBuf>,
+    },
     /// Sync Rust crate providers from ruyispec to local Cargo directory registry
     #[command(name = "registry-sync")]
     RegistrySync {
@@ -111,6 +402,9 @@ pub enum CargoOpt {
         #[arg(short = 'j', long, default_value_t = 8, value_name = "N")]
         jobs: usize,
     },
+    /// Maintain the packaged crate streams of the ruyispec tree
+    #[command(subcommand)]
+    Db(DbOpt),
     /// Check whether a single crate can resolve against the local TakoPack registry
     #[command(name = "resolve-check")]
     ResolveCheck {
@@ -133,6 +427,133 @@ pub enum CargoOpt {
         #[arg(long, value_name = "DIR")]
         registry: Option<std::path::PathBuf>,
     },
+    /// Regenerate the specs of the problem-crate corpus and compare them
+    /// with the expected ones
+    Repro {
+        /// Cases to run (all cases when omitted)
+        #[arg(value_name = "CASE")]
+        cases: Vec<String>,
+
+        /// Corpus directory (defaults to corpus/ in the current directory)
+        #[arg(long, value_name = "DIR")]
+        corpus: Option<std::path::PathBuf>,
+
+        /// Store the generated specs as the expected output
+        #[arg(long)]
+        bless: bool,
+
+        /// List the cases without running them
+        #[arg(long, conflicts_with = "bless")]
+        list: bool,
+    },
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum DbOpt {
+    /// Remove streams that no stored dependency graph needs anymore
+    Gc {
+        /// Directory searched for lockfiles (Cargo.lock, or the
+        /// <crate>-<version>.lock backups), whose packages are the roots
+        #[arg(long, value_name = "DIR")]
+        graphs: std::path::PathBuf,
+
+        /// ruyispec repository. Overrides [ruyispec].local_path in takopack.toml
+        #[arg(long, value_name = "DIR")]
+        ruyispec: Option<std::path::PathBuf>,
+
+        /// Only list the unreferenced streams without removing them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// List the packaged streams with their crate and version
+    List {
+        /// ruyispec repository. Overrides [ruyispec].local_path in takopack.toml
+        #[arg(long, value_name = "DIR")]
+        ruyispec: Option<std::path::PathBuf>,
+    },
+    /// List the streams whose crate or package name contains PATTERN
+    Search {
+        /// Part of a crate or package name, case ignored
+        #[arg(value_name = "PATTERN")]
+        pattern: String,
+
+        /// ruyispec repository. Overrides [ruyispec].local_path in takopack.toml
+        #[arg(long, value_name = "DIR")]
+        ruyispec: Option<std::path::PathBuf>,
+    },
+    /// Show the streams of a crate
+    Show {
+        /// Crate name, or <crate>@<compat> for one stream
+        #[arg(value_name = "CRATE")]
+        crate_name: String,
+
+        /// ruyispec repository. Overrides [ruyispec].local_path in takopack.toml
+        #[arg(long, value_name = "DIR")]
+        ruyispec: Option<std::path::PathBuf>,
+    },
+    /// Remove the stream of a crate from the ruyispec tree
+    Remove {
+        /// Crate name, or <crate>@<compat> when it has several streams
+        #[arg(value_name = "CRATE")]
+        crate_name: String,
+
+        /// ruyispec repository. Overrides [ruyispec].local_path in takopack.toml
+        #[arg(long, value_name = "DIR")]
+        ruyispec: Option<std::path::PathBuf>,
+
+        /// Only list the stream without removing it
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Write the streams as JSON or CSV
+    Export {
+        /// Output format
+        #[arg(long, value_enum, default_value_t = ExportFormat::Json)]
+        format: ExportFormat,
+
+        /// File to write; stdout without one
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<std::path::PathBuf>,
+
+        /// ruyispec repository. Overrides [ruyispec].local_path in takopack.toml
+        #[arg(long, value_name = "DIR")]
+        ruyispec: Option<std::path::PathBuf>,
+    },
+    /// Read an export and list its streams missing or older here
+    Import {
+        /// File written by `db export`
+        #[arg(value_name = "FILE")]
+        file: std::path::PathBuf,
+
+        /// Format of FILE; CSV for *.csv and JSON otherwise without it
+        #[arg(long, value_enum)]
+        format: Option<ExportFormat>,
+
+        /// Also write those streams to FILE, in the input format of `batch`
+        #[arg(long, value_name = "FILE")]
+        batch: Option<std::path::PathBuf>,
+
+        /// ruyispec repository. Overrides [ruyispec].local_path in takopack.toml
+        #[arg(long, value_name = "DIR")]
+        ruyispec: Option<std::path::PathBuf>,
+    },
+    /// List the streams a newer release of their compat version could bump
+    Outdated {
+        /// Also write the upgrade candidates to FILE, in the input format
+        /// of `batch`
+        #[arg(long, value_name = "FILE")]
+        batch: Option<std::path::PathBuf>,
+
+        /// ruyispec repository. Overrides [ruyispec].local_path in takopack.toml
+        #[arg(long, value_name = "DIR")]
+        ruyispec: Option<std::path::PathBuf>,
+    },
+    /// Count the streams and list the crates with several of them
+    Stats {
+        /// ruyispec repository. Overrides [ruyispec].local_path in takopack.toml
+        #[arg(long, value_name = "DIR")]
+        ruyispec: Option<std::path::PathBuf>,
+    },
 }
 
 #[derive(Debug, Clone, Subcommand)]
@@ -153,3 +574,19 @@ pub enum PyOpt {
         output: Option<std::path::PathBuf>,
     },
 }
+
+#[cfg(test)]
+mod tests {
+    use super::write_completions;
+    use clap_complete::Shell;
+
+    #[test]
+    fn completions_cover_the_cargo_subcommands() {
+        let mut out = Vec::new();
+        write_completions(Shell::Bash, &mut out);
+        let script = String::from_utf8(out).unwrap();
+        for subcommand in ["package", "vendor", "batch", "localpkg", "gitpkg"] {
+            assert!(script.contains(subcommand), "{}", subcommand);
+        }
+    }
+}
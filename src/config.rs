@@ -1,13 +1,23 @@
 use anyhow::Context;
+use semver::Version;
 use serde::de::IgnoredAny;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use toml;
 
+use crate::backup::BackupPolicy;
+use crate::crates::DownloadOptions;
+use crate::dep_filter::FilterRule;
 use crate::errors::*;
+use crate::file_modes::FileModes;
+use crate::name_collision::NameCollisions;
+use crate::takopack::targets::TargetPackages;
+use crate::tarball::ExtractionLimits;
+use crate::test_mode::TestMode;
+use crate::util::{rust_crate_output_names, RustCrateOutputNames};
+use crate::yanked::YankedArchive;
 
 use std::borrow::Cow;
 use std::collections::HashMap;
-use std::ffi::OsStr;
 use std::fs::{self, File};
 use std::io::Read;
 use std::path::{Path, PathBuf};
@@ -23,6 +33,9 @@ pub struct Config {
     pub overlay: Option<PathBuf>,
     pub excludes: Option<Vec<String>>,
     pub whitelist: Option<Vec<String>>,
+    /// Keep the pre-release version of dependencies like `^0.26.0-beta.1`
+    /// in their requirements. With `false` they require the release
+    /// instead, except the crates `allow_prerelease` of a package lists.
     pub allow_prerelease_deps: bool,
     pub crate_src_path: Option<PathBuf>,
     pub summary: Option<String>,
@@ -30,7 +43,109 @@ pub struct Config {
     pub maintainer: String,
     pub uploaders: Option<Vec<String>>,
     pub collapse_features: bool,
+    /// Generate no feature subpackages; the main package carries every
+    /// `crate(name/feature)` Provides and the union of all dependencies.
+    /// `true` applies to every crate, a list only to the named crates.
+    pub provides_only: CrateSelection,
+    /// Groups of mutually exclusive features, by crate. Packages providing
+    /// features of the same group get `Conflicts:` on each other.
+    pub exclusive_features: HashMap<String, Vec<Vec<String>>>,
+    /// Namespace of generated capabilities, `crate` by default. With
+    /// `mycorp-crate` packages provide and require `mycorp-crate(foo)`.
+    pub crate_namespace: Option<String>,
+    /// How dependencies are written in `Requires:`, see [`RequiresStyle`].
+    pub requires_style: RequiresStyle,
+    /// Where the `>=` floor of versioned `Requires:` comes from, see
+    /// [`RequiresFloor`].
+    pub requires_floor: RequiresFloor,
+    /// What target-specific dependencies get in `Requires:`, see
+    /// [`TargetDeps`]; `[packages.<key>]` can override it.
+    pub target_deps: TargetDeps,
+    /// rpm architectures the packages are built for, which `target_deps`
+    /// matches the cfg of dependencies against. All the architectures
+    /// takopack knows when empty.
+    pub build_arches: Vec<String>,
+    /// What is required instead of the dependencies a local project
+    /// replaces with `[patch]` or `[replace]`, by crate name; see
+    /// [`PatchedCrate`].
+    pub patched_crates: HashMap<String, PatchedCrate>,
+    /// What dependencies declared as `foo = "*"` are required as, see
+    /// [`WildcardDeps`].
+    pub wildcard_deps: WildcardDeps,
+    /// Have the main package recommend the default feature subpackages and
+    /// suggest the other ones, for distros that use RPM weak dependencies.
+    pub weak_deps: bool,
+    /// Where `%prep`, `%build`, `%install` and `%check` come from.
+    pub macro_flavor: MacroFlavor,
+    /// Package providing the Rust RPM macros, added to `BuildRequires:`.
+    pub macros_package: Option<String>,
+    /// Value of the `Release:` tag, `%autorelease` by default.
+    pub release: Option<String>,
+    /// Write `%autochangelog` when there are no changelog entries.
+    pub autochangelog: bool,
+    /// How `%check` runs the crate's tests with the `cargo` macro flavor.
+    pub tests: TestStyle,
+    /// `BuildRequires:` for the dev-dependencies the tests need, see
+    /// [`TestBuildRequires`].
+    pub test_build_requires: TestBuildRequires,
+    /// Keep the `[build-dependencies]` in the `Requires:` of the packages,
+    /// for crates whose build-dependencies leak into runtime. They are
+    /// otherwise only `BuildRequires:` of the spec. `true` applies to every
+    /// crate, a list only to the named crates.
+    pub build_deps_in_requires: CrateSelection,
+    /// What crates that build without the standard library get in addition,
+    /// see [`NoStdStyle`].
+    pub no_std: NoStdStyle,
+    /// Limits on unpacking crate archives, see [`ExtractionLimits`].
+    pub extraction: ExtractionLimits,
+    /// Retries and caching of crate downloads, see [`DownloadOptions`].
+    pub download: DownloadOptions,
+    /// Where the Cargo.toml and Cargo.lock of packaged crates are backed
+    /// up, see [`BackupPolicy`].
+    pub backup: BackupPolicy,
+    /// Modes of the files in generated package directories, see
+    /// [`FileModes`].
+    pub permissions: FileModes,
+    /// Directory of templates replacing sections of the generated spec, see
+    /// [`crate::takopack::template`].
+    pub template_dir: Option<PathBuf>,
+    /// Appended to the name of the generated package, its directory and
+    /// spec file, e.g. to tell apart crates that map to the same name.
+    pub rpm_name_suffix: Option<String>,
+    /// Handling of crates that map to the same package name, see
+    /// [`NameCollisions`].
+    pub name_collisions: NameCollisions,
+    /// Where `--allow-yanked` downloads yanked crates from, see
+    /// [`YankedArchive`].
+    pub yanked: YankedArchive,
+    /// Subpackages for the C API library and the examples, see
+    /// [`TargetPackages`].
+    pub target_packages: TargetPackages,
+    /// Distro profile supplying defaults for the settings above, see
+    /// [`crate::profile`].
+    pub profile: Option<String>,
+    /// Semver requirement on the takopack version, checked at startup; see
+    /// [`crate::self_check`].
+    pub required_takopack_version: Option<String>,
+    /// User-defined profiles and adjustments of the built-in ones.
+    pub profiles: toml::Table,
+    /// mock configuration (`mock -r`) used to build specs with mock.
+    pub mock_root: Option<String>,
+    /// Command listing what the distro repositories provide, for
+    /// `vendor --check-repo`; see [`crate::repo_query`].
+    pub repo_query: Option<String>,
+    /// Rules deciding which dependencies `vendor` descends into, see
+    /// [`crate::dep_filter`].
+    pub dependency_filters: Vec<FilterRule>,
+    /// Crates provided by the Rust toolchain packages in addition to the
+    /// built-in ones, as `name` or `name@version`; see
+    /// [`crate::toolchain_crates`].
+    pub toolchain_crates: Vec<String>,
     pub requires_root: Option<String>,
+    /// Set by the caller, never read from takopack.toml; see
+    /// [`crate::test_mode`].
+    #[serde(skip)]
+    pub test_mode: TestMode,
 
     pub source: Option<SourceOverride>,
     pub packages: HashMap<String, PackageOverride>,
@@ -39,11 +154,196 @@ pub struct Config {
     _ruyispec: Option<toml::Value>,
     #[serde(rename = "registry")]
     _registry: Option<toml::Value>,
+    #[serde(rename = "mirror")]
+    _mirror: Option<toml::Value>,
 
     #[serde(flatten)]
     pub unknown_fields: HashMap<String, IgnoredAny>,
 }
 
+/// A config switch that is either on for every crate or only for some.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum CrateSelection {
+    All(bool),
+    Crates(Vec<String>),
+}
+
+impl Default for CrateSelection {
+    fn default() -> Self {
+        CrateSelection::All(false)
+    }
+}
+
+impl CrateSelection {
+    pub fn contains(&self, crate_name: &str) -> bool {
+        match self {
+            CrateSelection::All(all) => *all,
+            CrateSelection::Crates(crates) => crates
+                .iter()
+                .any(|c| c.replace('_', "-") == crate_name.replace('_', "-")),
+        }
+    }
+}
+
+/// Form of generated `Requires:`. Build systems that cannot resolve virtual
+/// provides need the name of the package that carries the capability.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RequiresStyle {
+    /// `crate(foo-1/std) >= 1.2.3`
+    #[default]
+    Crate,
+    /// `rust-foo-1+std >= 1.2.3`
+    Package,
+    /// Both of the above.
+    Both,
+}
+
+/// Lower bound written for versioned dependencies.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RequiresFloor {
+    /// The lower bound of the Cargo requirement, raised to the locked
+    /// version when the spec is generated from a Cargo.lock.
+    #[default]
+    Lockfile,
+    /// The oldest non-yanked version on crates.io in the requirement's
+    /// compat stream that satisfies it, so the package also installs
+    /// against older distro snapshots.
+    Oldest,
+}
+
+/// What happens to dependencies declared as `foo = "*"`, which accept any
+/// version and have no bound to require.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum WildcardDeps {
+    /// Refuse to package the crate.
+    Error,
+    /// Required without a version, with a warning.
+    Warn,
+    /// Required at least at the version a Cargo.lock locks, in its compat
+    /// stream; without one locking it, the crate is not packaged.
+    PinToLockfile,
+    /// Required without a version.
+    #[default]
+    Unversioned,
+}
+
+/// What happens to dependencies in `[target.'cfg(..)'.dependencies]`.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TargetDeps {
+    /// Required like any other dependency.
+    #[default]
+    Keep,
+    /// Left out when their cfg matches none of the build architectures,
+    /// like `cfg(windows)`.
+    Omit,
+    /// Left out like with `omit`, and required in `%ifarch` when their cfg
+    /// matches only some of the build architectures. Only for specs that
+    /// are not `noarch`.
+    Arch,
+}
+
+/// The requirement replacing those on a crate that the manifest patches,
+/// set in `[patched_crates.<name>]`. The crates.io release Cargo would
+/// otherwise resolve to is not what the project builds with, and a git fork
+/// is never in the distro. Exactly one of `package`, `crate` and `path` is
+/// set.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PatchedCrate {
+    /// A distro package, required by name.
+    pub package: Option<String>,
+    /// A crate packaged on its own, e.g. the fork under another name; its
+    /// `crate()` is required.
+    #[serde(rename = "crate")]
+    pub crate_name: Option<String>,
+    /// Lowest version of `crate` required.
+    pub version: Option<String>,
+    /// Directory of the patched crate, packaged with `localpkg` as well.
+    /// Relative to the manifest with the patch; `localpkg` fills in `crate`
+    /// and `version` from its Cargo.toml.
+    pub path: Option<PathBuf>,
+}
+
+/// How the build sections of the spec are written.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MacroFlavor {
+    /// `BuildSystem: rustcrates`; the build system provides every section.
+    #[default]
+    Rustcrates,
+    /// Explicit sections calling `%cargo_prep`, `%cargo_build`,
+    /// `%cargo_install` and `%cargo_test`.
+    Cargo,
+}
+
+/// Form of the `%check` section.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TestStyle {
+    /// Always run the tests.
+    #[default]
+    Run,
+    /// Run them unless built `--without check`.
+    Bcond,
+    /// No `%check` section.
+    Skip,
+}
+
+/// `BuildRequires:` for dev-dependencies, set in the `[test_build_requires]`
+/// table. Without them the tests in `%check` cannot build, but a
+/// dev-dependency that itself depends on the crate makes a build cycle.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TestBuildRequires {
+    /// Write them; `--with-tests` turns this on for a single run.
+    pub enabled: bool,
+    /// Dev-dependencies left out, by crate name.
+    pub exclude: Vec<String>,
+    /// At most this many dev-dependencies are required, in manifest order.
+    pub max: Option<usize>,
+}
+
+/// Extra output for crates detected as supporting `no_std`.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum NoStdStyle {
+    /// Nothing; they are packaged like any other crate.
+    #[default]
+    Ignore,
+    /// `%check` also runs the tests without default features, which is how
+    /// such crates drop `std`. Only the `cargo` macro flavor writes `%check`.
+    Check,
+    /// A `+no-std` subpackage providing `crate(name/no-std)`.
+    Subpackage,
+    /// Both of the above.
+    Both,
+}
+
+impl NoStdStyle {
+    pub fn check(self) -> bool {
+        matches!(self, NoStdStyle::Check | NoStdStyle::Both)
+    }
+
+    pub fn subpackage(self) -> bool {
+        matches!(self, NoStdStyle::Subpackage | NoStdStyle::Both)
+    }
+}
+
+impl RequiresStyle {
+    pub fn crate_requires(self) -> bool {
+        matches!(self, RequiresStyle::Crate | RequiresStyle::Both)
+    }
+
+    pub fn package_requires(self) -> bool {
+        matches!(self, RequiresStyle::Package | RequiresStyle::Both)
+    }
+}
+
 #[derive(Deserialize, Debug, Clone, Default)]
 pub struct SourceOverride {
     section: Option<String>,
@@ -109,6 +409,15 @@ pub struct PackageOverride {
     test_is_broken: Option<bool>,
     test_architecture: Option<Vec<String>>,
     test_depends: Option<Vec<String>>,
+    target_deps: Option<TargetDeps>,
+    /// Dependencies that keep their pre-release requirement when
+    /// `allow_prerelease_deps` is off; on `lib` for all packages of the crate.
+    allow_prerelease: Option<CrateSelection>,
+    /// Install the man pages of the executables (`bin` only), see
+    /// [`crate::takopack::bin_assets`].
+    install_manpages: Option<bool>,
+    /// Install the shell completions of the executables (`bin` only).
+    install_completions: Option<bool>,
 
     #[serde(flatten)]
     pub unknown_fields: HashMap<String, IgnoredAny>,
@@ -123,18 +432,54 @@ impl Default for Config {
             overlay: None,
             excludes: None,
             whitelist: None,
-            allow_prerelease_deps: false,
+            allow_prerelease_deps: true,
             crate_src_path: None,
             summary: None,
             description: None,
             maintainer: RUST_MAINT.to_string(),
             uploaders: None,
             collapse_features: false,
+            provides_only: CrateSelection::default(),
+            exclusive_features: HashMap::new(),
+            crate_namespace: None,
+            requires_style: RequiresStyle::default(),
+            requires_floor: RequiresFloor::default(),
+            target_deps: TargetDeps::default(),
+            build_arches: Vec::new(),
+            patched_crates: HashMap::new(),
+            wildcard_deps: WildcardDeps::default(),
+            weak_deps: false,
+            macro_flavor: MacroFlavor::default(),
+            macros_package: None,
+            release: None,
+            autochangelog: true,
+            tests: TestStyle::default(),
+            test_build_requires: TestBuildRequires::default(),
+            build_deps_in_requires: CrateSelection::default(),
+            no_std: NoStdStyle::default(),
+            extraction: ExtractionLimits::default(),
+            download: DownloadOptions::default(),
+            backup: BackupPolicy::default(),
+            permissions: FileModes::default(),
+            template_dir: None,
+            rpm_name_suffix: None,
+            name_collisions: NameCollisions::default(),
+            yanked: YankedArchive::default(),
+            target_packages: TargetPackages::default(),
+            profile: None,
+            required_takopack_version: None,
+            profiles: toml::Table::new(),
+            mock_root: None,
+            repo_query: None,
+            dependency_filters: Vec::new(),
+            toolchain_crates: Vec::new(),
             source: None,
             packages: HashMap::new(),
             requires_root: None,
+            test_mode: TestMode::default(),
             _ruyispec: None,
             _registry: None,
+            _mirror: None,
             unknown_fields: HashMap::new(),
         }
     }
@@ -142,23 +487,61 @@ impl Default for Config {
 
 impl Config {
     pub fn load() -> Result<(Option<PathBuf>, Config)> {
+        Config::load_with_profile(None)
+    }
+
+    /// Like [`Config::load`], but with `profile` (if given) in place of the
+    /// one selected in takopack.toml.
+    pub fn load_with_profile(profile: Option<&str>) -> Result<(Option<PathBuf>, Config)> {
         let path = find_takopack_toml();
         match path {
             Some(path) => {
-                let config = Config::parse(&path)
+                let config = Config::parse_with_profile(&path, profile)
                     .with_context(|| format!("failed to parse {}", path.display()))?;
                 Ok((Some(path), config))
             }
-            None => Ok((None, Config::default())),
+            None => match profile {
+                Some(name) => {
+                    let mut table = crate::profile::resolve_profile(name, None)?;
+                    table.insert("profile".to_string(), toml::Value::String(name.to_string()));
+                    Ok((None, toml::Value::Table(table).try_into()?))
+                }
+                None => Ok((None, Config::default())),
+            },
         }
     }
 
     pub fn parse(src: &Path) -> Result<Config> {
+        Config::parse_with_profile(src, None)
+    }
+
+    /// Parse `src` with the settings of `profile`, or of the profile it
+    /// selects itself, underneath.
+    pub fn parse_with_profile(src: &Path, profile: Option<&str>) -> Result<Config> {
         let mut config_file = File::open(src)?;
         let mut content = String::new();
         config_file.read_to_string(&mut content)?;
 
-        let config: Config = toml::from_str(&content)?;
+        let mut table: toml::Table = toml::from_str(&content)?;
+        let selected = match profile {
+            Some(name) => Some(name.to_string()),
+            None => match table.get("profile") {
+                Some(toml::Value::String(name)) => Some(name.clone()),
+                Some(_) => takopack_bail!("profile in {} must be a string", src.display()),
+                None => None,
+            },
+        };
+        if let Some(name) = selected {
+            let user = match table.get("profiles") {
+                Some(toml::Value::Table(profiles)) => Some(profiles),
+                Some(_) => takopack_bail!("[profiles] in {} must be a table", src.display()),
+                None => None,
+            };
+            let base = crate::profile::resolve_profile(&name, user)?;
+            table = crate::profile::layer(base, table);
+            table.insert("profile".to_string(), toml::Value::String(name));
+        }
+        let config: Config = toml::Value::Table(table).try_into()?;
 
         let mut unknown_fields = Vec::new();
 
@@ -184,6 +567,41 @@ impl Config {
             }
         }
 
+        if let Some(namespace) = &config.crate_namespace {
+            if !is_valid_crate_namespace(namespace) {
+                takopack_bail!(
+                    "crate_namespace {:?} in {} must be a non-empty name without spaces or any of / ( ) , %",
+                    namespace,
+                    src.display()
+                );
+            }
+        }
+
+        for (name, patched) in &config.patched_crates {
+            let targets = [
+                patched.package.is_some(),
+                patched.crate_name.is_some(),
+                patched.path.is_some(),
+            ];
+            if targets.iter().filter(|set| **set).count() != 1 {
+                takopack_bail!(
+                    "[patched_crates.{}] in {} must set exactly one of package, crate and path",
+                    name,
+                    src.display()
+                );
+            }
+        }
+
+        for arch in &config.build_arches {
+            if !crate::crates::is_known_linux_arch(arch) {
+                takopack_warn!(
+                    "build_arches in {}: unknown architecture {}; target-specific dependencies are kept for it",
+                    src.display(),
+                    arch
+                );
+            }
+        }
+
         if !unknown_fields.is_empty() {
             takopack_warn!(
                 "Warning: Unknown fields in {}: {:?}",
@@ -196,6 +614,37 @@ impl Config {
         Ok(config)
     }
 
+    /// Whether `crate_name` gets all its features as Provides on the main
+    /// package instead of feature subpackages.
+    pub fn provides_only_features(&self, crate_name: &str) -> bool {
+        self.provides_only.contains(crate_name)
+    }
+
+    /// Exclusive feature groups configured for `crate_name`.
+    pub fn exclusive_features(&self, crate_name: &str) -> &[Vec<String>] {
+        self.exclusive_features
+            .iter()
+            .find(|(name, _)| name.replace('_', "-") == crate_name.replace('_', "-"))
+            .map(|(_, groups)| groups.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Namespace of `crate(...)` capabilities in Provides, Requires and
+    /// BuildRequires.
+    pub fn crate_namespace(&self) -> &str {
+        self.crate_namespace
+            .as_deref()
+            .unwrap_or(crate::takopack::spec::DEFAULT_CRATE_NAMESPACE)
+    }
+
+    pub fn macros_package(&self) -> &str {
+        self.macros_package.as_deref().unwrap_or("rust-rpm-macros")
+    }
+
+    pub fn release(&self) -> &str {
+        self.release.as_deref().unwrap_or("%autorelease")
+    }
+
     pub fn build_bin_package(&self) -> bool {
         self.bin.unwrap_or(!self.semver_suffix)
     }
@@ -208,6 +657,24 @@ impl Config {
         Some(config_path?.parent()?.join(self.crate_src_path.as_ref()?))
     }
 
+    /// Names of the package generated for `crate_name` `version`.
+    pub fn output_names(&self, crate_name: &str, version: &Version) -> RustCrateOutputNames {
+        let names = rust_crate_output_names(crate_name, version);
+        match &self.rpm_name_suffix {
+            Some(suffix) => names.with_suffix(suffix),
+            None => names,
+        }
+    }
+
+    /// `template_dir`, relative to the directory of takopack.toml.
+    pub fn template_dir(&self, config_path: Option<&Path>) -> Option<PathBuf> {
+        let dir = self.template_dir.as_ref()?;
+        Some(match config_path.and_then(Path::parent) {
+            Some(base) => base.join(dir),
+            None => dir.clone(),
+        })
+    }
+
     pub fn orig_tar_excludes(&self) -> Option<&Vec<String>> {
         self.excludes.as_ref()
     }
@@ -296,6 +763,22 @@ impl Config {
         self.with_package(key, |pkg| pkg.architecture.as_ref())
     }
 
+    /// [`Config::target_deps`], or its override for the package `key`.
+    pub fn package_target_deps(&self, key: PackageKey) -> TargetDeps {
+        self.with_package(key, |pkg| pkg.target_deps)
+            .unwrap_or(self.target_deps)
+    }
+
+    /// Whether the requirement on `dep_name` of the package `key` keeps its
+    /// pre-release version, see [`Config::allow_prerelease_deps`].
+    pub fn allow_prerelease_dep(&self, key: PackageKey, dep_name: &str) -> bool {
+        self.allow_prerelease_deps
+            || [key, PackageKey::BareLib].into_iter().any(|key| {
+                self.with_package(key, |pkg| pkg.allow_prerelease.as_ref())
+                    .is_some_and(|crates| crates.contains(dep_name))
+            })
+    }
+
     pub fn package_multi_arch(&self, key: PackageKey) -> Option<&str> {
         self.with_package(key, |pkg| pkg.multi_arch.as_deref())
     }
@@ -343,6 +826,14 @@ impl Config {
     pub fn package_test_depends(&self, key: PackageKey) -> Option<&Vec<String>> {
         self.with_package(key, |pkg| pkg.test_depends.as_ref())
     }
+
+    pub fn package_install_manpages(&self, key: PackageKey) -> Option<bool> {
+        self.with_package(key, |pkg| pkg.install_manpages)
+    }
+
+    pub fn package_install_completions(&self, key: PackageKey) -> Option<bool> {
+        self.with_package(key, |pkg| pkg.install_completions)
+    }
 }
 
 pub fn package_field_for_feature<'a, 'b, F: Fn(PackageKey) -> Option<&'a Vec<String>>>(
@@ -407,6 +898,7 @@ impl<'a> PackageKey<'a> {
 pub(crate) struct TakopackToml {
     pub ruyispec: Option<RuyispecConfig>,
     pub registry: Option<RegistryConfig>,
+    pub mirror: Option<MirrorConfig>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -419,6 +911,11 @@ pub(crate) struct RegistryConfig {
     pub local_path: Option<PathBuf>,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct MirrorConfig {
+    pub local_path: Option<PathBuf>,
+}
+
 pub fn resolve_ruyispec_dir(explicit: Option<&Path>, use_config: bool) -> Result<PathBuf> {
     if let Some(path) = explicit {
         return require_directory(path, "explicit ruyispec path");
@@ -464,14 +961,34 @@ pub fn resolve_registry_dir(explicit: Option<&Path>) -> Result<PathBuf> {
         }
     }
 
-    default_registry_dir()
+    crate::paths::registry_dir()
 }
 
-pub fn default_registry_dir() -> Result<PathBuf> {
-    let data_dir = dirs::data_dir().ok_or_else(|| {
-        anyhow::anyhow!("cannot determine XDG_DATA_HOME / home directory for default registry path")
+/// Directory of the local crates mirror used by `--offline`.
+pub fn resolve_mirror_dir(explicit: Option<&Path>) -> Result<PathBuf> {
+    if let Some(path) = explicit {
+        return require_directory(path, "explicit mirror path");
+    }
+
+    let (config_path, config) = load_takopack_toml()?.ok_or_else(|| {
+        anyhow::anyhow!(
+            "offline mode needs a crates mirror; create takopack.toml with [mirror].local_path or pass --mirror DIR"
+        )
     })?;
-    Ok(data_dir.join("takopack").join("cargo-registry"))
+    let local_path = config
+        .mirror
+        .and_then(|mirror| mirror.local_path)
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "{} does not define [mirror].local_path; pass --mirror DIR for offline mode",
+                config_path.display()
+            )
+        })?;
+
+    require_directory(
+        &resolve_config_relative_path(&config_path, local_path),
+        "mirror.local_path",
+    )
 }
 
 pub fn ruyispec_package_root(ruyispec_dir: &Path) -> PathBuf {
@@ -494,14 +1011,25 @@ pub(crate) fn load_takopack_toml() -> Result<Option<(PathBuf, TakopackToml)>> {
     Ok(Some((path, config)))
 }
 
+fn is_valid_crate_namespace(namespace: &str) -> bool {
+    !namespace.is_empty()
+        && !namespace
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, '/' | '(' | ')' | ',' | '%'))
+}
+
 fn find_takopack_toml() -> Option<PathBuf> {
+    if let Some(isolation) = crate::paths::isolation() {
+        return isolation.config.filter(|path| path.is_file());
+    }
     let current = PathBuf::from("takopack.toml");
     if current.is_file() {
         return Some(current);
     }
 
-    dirs::config_dir()
-        .map(|dir| dir.join("takopack").join("takopack.toml"))
+    crate::paths::config_dir()
+        .ok()
+        .map(|dir| dir.join("takopack.toml"))
         .filter(|path| path.is_file())
 }
 
@@ -522,12 +1050,3 @@ fn require_directory(path: &Path, label: &str) -> Result<PathBuf> {
     }
     Ok(path.to_path_buf())
 }
-
-pub fn testing_ignore_debpolv() -> bool {
-    std::env::var_os("takopack_TESTING_IGNORE_takopack_POLICY_VIOLATION").as_deref()
-        == Some(OsStr::new("1"))
-}
-
-pub fn testing_ruzt() -> bool {
-    std::env::var_os("takopack_TESTING_RUZT").as_deref() == Some(OsStr::new("1"))
-}
@@ -0,0 +1,446 @@
+//! Uploading source RPMs to a COPR project, for `takopack cargo copr-upload`
+//! and `batch --copr`.
+//!
+//! Credentials come from the copr-cli configuration (`~/.config/copr`, the
+//! file the COPR web UI hands out under "API"). Every SRPM is sent to the
+//! API v3 `build/create/upload` endpoint, which starts a build of it in the
+//! project. Connection problems, timeouts and server errors are retried.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::Context;
+use serde_derive::Deserialize;
+use walkdir::WalkDir;
+
+use crate::errors::*;
+
+const DEFAULT_COPR_URL: &str = "https://copr.fedorainfracloud.org";
+
+/// Tries per SRPM; the wait between them doubles from two seconds.
+const ATTEMPTS: u32 = 3;
+
+/// Account and API token from the copr-cli configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoprCredentials {
+    pub login: String,
+    pub username: String,
+    pub token: String,
+    pub copr_url: String,
+}
+
+impl CoprCredentials {
+    /// Read `path`, or `~/.config/copr` when `None`.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => dirs::config_dir()
+                .ok_or_else(|| format_err!("cannot determine the config directory"))?
+                .join("copr"),
+        };
+        let text = fs::read_to_string(&path).with_context(|| {
+            format!(
+                "Failed to read COPR credentials from {}; download them from the COPR web UI (API page)",
+                path.display()
+            )
+        })?;
+        Self::parse(&text)
+            .with_context(|| format!("Invalid COPR credentials in {}", path.display()))
+    }
+
+    /// The `[copr-cli]` section of an INI file.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut section = "";
+        let mut values = HashMap::new();
+        for line in text.lines() {
+            let line = line.trim();
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = name.trim();
+            } else if let Some((key, value)) = line.split_once('=') {
+                if section == "copr-cli" {
+                    values.insert(key.trim(), value.trim());
+                }
+            }
+        }
+        let value = |key: &str| {
+            values
+                .get(key)
+                .filter(|v| !v.is_empty())
+                .map(|v| v.to_string())
+        };
+        let required =
+            |key: &str| value(key).ok_or_else(|| format_err!("missing `{}` in [copr-cli]", key));
+        Ok(CoprCredentials {
+            login: required("login")?,
+            username: required("username")?,
+            token: required("token")?,
+            copr_url: value("copr_url").unwrap_or_else(|| DEFAULT_COPR_URL.to_string()),
+        })
+    }
+}
+
+/// `owner/project`; a bare project name belongs to the user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoprProject {
+    pub owner: String,
+    pub name: String,
+}
+
+impl CoprProject {
+    pub fn parse(project: &str, username: &str) -> Result<Self> {
+        let (owner, name) = project.split_once('/').unwrap_or((username, project));
+        if owner.is_empty() || name.is_empty() || name.contains('/') {
+            takopack_bail!("invalid COPR project {:?}, expected OWNER/PROJECT", project);
+        }
+        Ok(CoprProject {
+            owner: owner.to_string(),
+            name: name.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for CoprProject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.owner, self.name)
+    }
+}
+
+/// Outcome of uploading one SRPM: the COPR build id, or the error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Upload {
+    pub srpm: PathBuf,
+    pub result: std::result::Result<u64, String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UploadReport {
+    pub project: String,
+    pub uploads: Vec<Upload>,
+}
+
+impl UploadReport {
+    pub fn failed(&self) -> usize {
+        self.uploads.iter().filter(|u| u.result.is_err()).count()
+    }
+}
+
+impl fmt::Display for UploadReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "COPR uploads to {}: {} succeeded, {} failed",
+            self.project,
+            self.uploads.len() - self.failed(),
+            self.failed()
+        )?;
+        for upload in &self.uploads {
+            let name = upload
+                .srpm
+                .file_name()
+                .map(|n| n.to_string_lossy())
+                .unwrap_or_default();
+            match &upload.result {
+                Ok(id) => writeln!(f, "  ✓ {} (build {})", name, id)?,
+                Err(e) => writeln!(f, "  ✗ {}: {}", name, e)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Upload every SRPM under `dir` to `project`. Returns 1 when an upload
+/// failed.
+pub fn run_copr_upload(dir: &Path, project: &str, credentials: Option<&Path>) -> Result<i32> {
+    let srpms = find_srpms(dir)?;
+    if srpms.is_empty() {
+        takopack_bail!(
+            "no source RPMs under {}; build them first with `takopack cargo build-spec`",
+            dir.display()
+        );
+    }
+    let credentials = CoprCredentials::load(credentials)?;
+    let project = CoprProject::parse(project, &credentials.username)?;
+    let report = upload_srpms(&credentials, &project, &srpms);
+    print!("{}", report);
+    Ok(if report.failed() > 0 { 1 } else { 0 })
+}
+
+/// `*.src.rpm` files in `dir` and its package directories.
+pub fn find_srpms(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut srpms = Vec::new();
+    for entry in WalkDir::new(dir).max_depth(2).sort_by_file_name() {
+        let entry = entry?;
+        if entry.file_type().is_file() && entry.file_name().to_string_lossy().ends_with(".src.rpm")
+        {
+            srpms.push(entry.into_path());
+        }
+    }
+    Ok(srpms)
+}
+
+/// Upload `srpms` one by one, reporting each as it is done.
+pub fn upload_srpms(
+    credentials: &CoprCredentials,
+    project: &CoprProject,
+    srpms: &[PathBuf],
+) -> UploadReport {
+    let agent = crate::http::agent_builder()
+        .timeout_connect(Duration::from_secs(20))
+        .timeout_write(Duration::from_secs(600))
+        .timeout_read(Duration::from_secs(120))
+        .build();
+
+    let mut report = UploadReport {
+        project: project.to_string(),
+        uploads: Vec::new(),
+    };
+    for (i, srpm) in srpms.iter().enumerate() {
+        println!(
+            "[{}/{}] Uploading {} to {}",
+            i + 1,
+            srpms.len(),
+            srpm.display(),
+            project
+        );
+        let result = upload_with_retry(&agent, credentials, project, srpm);
+        match &result {
+            Ok(id) => println!("✓ COPR build {} started", id),
+            Err(e) => takopack_warn!("upload of {} failed: {}", srpm.display(), e),
+        }
+        report.uploads.push(Upload {
+            srpm: srpm.clone(),
+            result,
+        });
+    }
+    report
+}
+
+fn upload_with_retry(
+    agent: &ureq::Agent,
+    credentials: &CoprCredentials,
+    project: &CoprProject,
+    srpm: &Path,
+) -> std::result::Result<u64, String> {
+    let contents = fs::read(srpm).map_err(|e| format!("cannot read {}: {}", srpm.display(), e))?;
+    let file_name = srpm
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut attempt = 1;
+    loop {
+        match upload_once(agent, credentials, project, &file_name, &contents) {
+            Ok(id) => return Ok(id),
+            Err((message, transient)) if transient && attempt < ATTEMPTS => {
+                log::warn!(
+                    "upload attempt {}/{} of {} failed: {}",
+                    attempt,
+                    ATTEMPTS,
+                    file_name,
+                    message
+                );
+                std::thread::sleep(Duration::from_secs(2u64.pow(attempt)));
+                attempt += 1;
+            }
+            Err((message, _)) => return Err(message),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct BuildResponse {
+    id: u64,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+/// One upload; errors come with whether trying again may help.
+fn upload_once(
+    agent: &ureq::Agent,
+    credentials: &CoprCredentials,
+    project: &CoprProject,
+    file_name: &str,
+    contents: &[u8],
+) -> std::result::Result<u64, (String, bool)> {
+    let url = format!(
+        "{}/api_3/build/create/upload",
+        credentials.copr_url.trim_end_matches('/')
+    );
+    let json = serde_json::json!({
+        "ownername": project.owner,
+        "projectname": project.name,
+    })
+    .to_string();
+    let boundary = format!("takopack-{}", std::process::id());
+    let body = multipart_body(&boundary, &json, file_name, contents);
+    let auth = base64(format!("{}:{}", credentials.login, credentials.token).as_bytes());
+
+    let response = agent
+        .post(&url)
+        .set(
+            "User-Agent",
+            concat!("takopack/", env!("CARGO_PKG_VERSION")),
+        )
+        .set("Authorization", &format!("Basic {}", auth))
+        .set(
+            "Content-Type",
+            &format!("multipart/form-data; boundary={}", boundary),
+        )
+        .send_bytes(&body);
+    match response {
+        Ok(response) => {
+            let text = response
+                .into_string()
+                .map_err(|e| (format!("cannot read the response: {}", e), true))?;
+            serde_json::from_str::<BuildResponse>(&text)
+                .map(|build| build.id)
+                .map_err(|e| (format!("unexpected response {:?}: {}", text, e), false))
+        }
+        Err(ureq::Error::Status(code, response)) => {
+            let text = response.into_string().unwrap_or_default();
+            let message = serde_json::from_str::<ErrorResponse>(&text)
+                .map(|e| e.error)
+                .unwrap_or(text);
+            Err((
+                format!("HTTP {}: {}", code, message.trim()),
+                is_transient(code),
+            ))
+        }
+        Err(e) => Err((e.to_string(), true)),
+    }
+}
+
+/// Statuses worth retrying: timeouts, rate limiting and server errors.
+fn is_transient(status: u16) -> bool {
+    status == 408 || status == 429 || status >= 500
+}
+
+/// `multipart/form-data` with the build options as the `json` field and the
+/// SRPM as `pkgs`, the fields copr-cli sends.
+fn multipart_body(boundary: &str, json: &str, file_name: &str, contents: &[u8]) -> Vec<u8> {
+    let mut body = Vec::new();
+    body.extend_from_slice(
+        format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"json\"\r\nContent-Type: application/json\r\n\r\n{}\r\n",
+            boundary, json
+        )
+        .as_bytes(),
+    );
+    body.extend_from_slice(
+        format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"pkgs\"; filename=\"{}\"\r\nContent-Type: application/x-rpm\r\n\r\n",
+            boundary,
+            file_name.replace('"', "")
+        )
+        .as_bytes(),
+    );
+    body.extend_from_slice(contents);
+    body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+    body
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | ((b as u32) << (16 - 8 * i)));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[((n >> (18 - 6 * i)) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        base64, find_srpms, is_transient, multipart_body, CoprCredentials, CoprProject, Upload,
+        UploadReport,
+    };
+    use std::fs;
+    use std::path::PathBuf;
+
+    #[test]
+    fn credentials_come_from_the_copr_cli_section() {
+        let creds = CoprCredentials::parse(
+            "[other]\ntoken = nope\n\n[copr-cli]\nlogin = abcdef\nusername = alice\ntoken = s3cr3t\n",
+        )
+        .unwrap();
+        assert_eq!(creds.login, "abcdef");
+        assert_eq!(creds.username, "alice");
+        assert_eq!(creds.token, "s3cr3t");
+        assert_eq!(creds.copr_url, "https://copr.fedorainfracloud.org");
+
+        assert!(CoprCredentials::parse("[copr-cli]\nlogin = a\nusername = b\n").is_err());
+        assert!(CoprCredentials::parse("[other]\nlogin = a\nusername = b\ntoken = c\n").is_err());
+    }
+
+    #[test]
+    fn projects_default_to_the_user() {
+        let project = CoprProject::parse("rust-crates", "alice").unwrap();
+        assert_eq!(project.to_string(), "alice/rust-crates");
+        let group = CoprProject::parse("@openruyi/rust", "alice").unwrap();
+        assert_eq!(group.owner, "@openruyi");
+        assert!(CoprProject::parse("a/b/c", "alice").is_err());
+        assert!(CoprProject::parse("alice/", "alice").is_err());
+    }
+
+    #[test]
+    fn uploads_are_multipart_forms() {
+        let body = multipart_body("XX", "{}", "a.src.rpm", b"RPM");
+        let body = String::from_utf8(body).unwrap();
+        assert!(body.starts_with("--XX\r\nContent-Disposition: form-data; name=\"json\""));
+        assert!(body.contains("name=\"pkgs\"; filename=\"a.src.rpm\"\r\nContent-Type: application/x-rpm\r\n\r\nRPM\r\n--XX--\r\n"));
+
+        assert_eq!(base64(b"login:token"), "bG9naW46dG9rZW4=");
+        assert_eq!(base64(b"ab"), "YWI=");
+        assert_eq!(base64(b"abc"), "YWJj");
+        assert!(is_transient(503) && is_transient(429) && !is_transient(403));
+    }
+
+    #[test]
+    fn srpms_are_found_in_package_directories() {
+        let root = tempfile::tempdir().unwrap();
+        let pkg = root.path().join("rust-a-1");
+        fs::create_dir_all(&pkg).unwrap();
+        fs::write(pkg.join("rust-a-1-1.0.0-1.src.rpm"), "").unwrap();
+        fs::write(pkg.join("rust-a-1-devel-1.0.0-1.noarch.rpm"), "").unwrap();
+        fs::write(pkg.join("rust-a-1.spec"), "").unwrap();
+        assert_eq!(
+            find_srpms(root.path()).unwrap(),
+            vec![pkg.join("rust-a-1-1.0.0-1.src.rpm")]
+        );
+
+        let report = UploadReport {
+            project: "alice/rust".to_string(),
+            uploads: vec![
+                Upload {
+                    srpm: PathBuf::from("/out/a.src.rpm"),
+                    result: Ok(42),
+                },
+                Upload {
+                    srpm: PathBuf::from("/out/b.src.rpm"),
+                    result: Err("HTTP 403: forbidden".to_string()),
+                },
+            ],
+        };
+        assert_eq!(report.failed(), 1);
+        assert_eq!(
+            report.to_string(),
+            "COPR uploads to alice/rust: 1 succeeded, 1 failed\n  ✓ a.src.rpm (build 42)\n  ✗ b.src.rpm: HTTP 403: forbidden\n"
+        );
+    }
+}
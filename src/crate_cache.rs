@@ -0,0 +1,201 @@
+//! Crate archives shared between runs, under `~/.cache/takopack/crates/`.
+//!
+//! Every `vendor` or `batch` run downloads into a cargo registry cache that
+//! may be fresh (a CI container, another `CARGO_HOME`), so the same crates
+//! are fetched again and again. Downloaded archives are therefore also kept
+//! here, keyed by name, version and the sha256 the registry index lists
+//! for them, and copied into cargo's cache before cargo would download
+//! them. Since the checksum is part of the key and is checked again on the
+//! way out, an entry can never stand in for a different archive; a
+//! corrupted one is removed and downloaded again.
+//!
+//! `takopack cargo update --crate-cache` empties the cache, and
+//! `--no-cache` (or `cache = false` in the `[download]` table) bypasses
+//! it.
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use sha2::{Digest, Sha256};
+
+use crate::errors::*;
+use crate::index_cache::write_atomically;
+
+/// The shared cache of crate archives in one directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrateCache {
+    dir: PathBuf,
+}
+
+/// Summary of the crate cache for `update --status`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrateCacheStatus {
+    pub dir: PathBuf,
+    pub entries: usize,
+    pub bytes: u64,
+}
+
+impl fmt::Display for CrateCacheStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Crate cache: {}", self.dir.display())?;
+        write!(
+            f,
+            "Entries:     {} ({:.1} MiB)",
+            self.entries,
+            self.bytes as f64 / (1024.0 * 1024.0)
+        )
+    }
+}
+
+impl CrateCache {
+    pub fn new(dir: PathBuf) -> Self {
+        CrateCache { dir }
+    }
+
+    /// The cache at [`crate::paths::crate_cache_dir`].
+    pub fn open_default() -> Result<Self> {
+        Ok(Self::new(crate::paths::crate_cache_dir()?))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn entry_path(&self, crate_name: &str, version: &str, checksum: &str) -> PathBuf {
+        self.dir
+            .join(crate_name)
+            .join(format!("{}-{}-{}.crate", crate_name, version, checksum))
+    }
+
+    /// Copy the cached archive of `crate_name` `version` to `dest`, unless
+    /// `dest` already exists. Returns whether it was copied.
+    pub fn seed(
+        &self,
+        crate_name: &str,
+        version: &str,
+        checksum: &str,
+        dest: &Path,
+    ) -> Result<bool> {
+        if dest.exists() {
+            return Ok(false);
+        }
+        let path = self.entry_path(crate_name, version, checksum);
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        if sha256_hex(&data) != checksum {
+            takopack_warn!("removing corrupted crate cache entry {}", path.display());
+            fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove {}", path.display()))?;
+            return Ok(false);
+        }
+        write_atomically(dest, &data)
+            .with_context(|| format!("Failed to write {}", dest.display()))?;
+        Ok(true)
+    }
+
+    /// Keep the archive at `src` as the one of `crate_name` `version`, if it
+    /// has the checksum the index lists.
+    pub fn store(&self, crate_name: &str, version: &str, checksum: &str, src: &Path) -> Result<()> {
+        let path = self.entry_path(crate_name, version, checksum);
+        if path.exists() {
+            return Ok(());
+        }
+        let data = fs::read(src).with_context(|| format!("Failed to read {}", src.display()))?;
+        if sha256_hex(&data) != checksum {
+            takopack_bail!(
+                "{} does not have the checksum {} of {} {}",
+                src.display(),
+                checksum,
+                crate_name,
+                version
+            );
+        }
+        write_atomically(&path, &data)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Every archive in the cache; a missing directory is empty.
+    fn entries(&self) -> Result<Vec<(PathBuf, u64)>> {
+        if !self.dir.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut entries = Vec::new();
+        for file in walkdir::WalkDir::new(&self.dir).sort_by_file_name() {
+            let file = file.context("walkdir error")?;
+            if file.file_type().is_file() && file.path().extension() == Some("crate".as_ref()) {
+                let len = file.metadata().context("walkdir error")?.len();
+                entries.push((file.into_path(), len));
+            }
+        }
+        Ok(entries)
+    }
+
+    pub fn status(&self) -> Result<CrateCacheStatus> {
+        let entries = self.entries()?;
+        Ok(CrateCacheStatus {
+            dir: self.dir.clone(),
+            entries: entries.len(),
+            bytes: entries.iter().map(|(_, len)| len).sum(),
+        })
+    }
+
+    /// Remove every archive, returning how many there were.
+    pub fn clear(&self) -> Result<usize> {
+        let entries = self.entries()?;
+        for (path, _) in &entries {
+            fs::remove_file(path)
+                .with_context(|| format!("Failed to remove {}", path.display()))?;
+        }
+        Ok(entries.len())
+    }
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sha256_hex, CrateCache};
+    use std::fs;
+
+    #[test]
+    fn archives_are_stored_and_seeded_by_checksum() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = CrateCache::new(dir.path().join("crates"));
+        let download = dir.path().join("downloaded.crate");
+        fs::write(&download, b"archive").unwrap();
+        let checksum = sha256_hex(b"archive");
+
+        assert!(cache
+            .store("log", "0.4.22", &"0".repeat(64), &download)
+            .is_err());
+        cache.store("log", "0.4.22", &checksum, &download).unwrap();
+        assert_eq!(cache.status().unwrap().entries, 1);
+
+        let registry = dir.path().join("registry/log-0.4.22.crate");
+        assert!(!cache.seed("log", "0.4.21", &checksum, &registry).unwrap());
+        assert!(cache.seed("log", "0.4.22", &checksum, &registry).unwrap());
+        assert_eq!(fs::read(&registry).unwrap(), b"archive");
+        assert!(!cache.seed("log", "0.4.22", &checksum, &registry).unwrap());
+
+        // A corrupted entry is dropped instead of being handed to cargo
+        let entry = cache.entry_path("log", "0.4.22", &checksum);
+        fs::write(&entry, b"truncated").unwrap();
+        fs::remove_file(&registry).unwrap();
+        assert!(!cache.seed("log", "0.4.22", &checksum, &registry).unwrap());
+        assert!(!entry.exists() && !registry.exists());
+
+        cache.store("log", "0.4.22", &checksum, &download).unwrap();
+        assert_eq!(cache.clear().unwrap(), 1);
+        assert_eq!(cache.status().unwrap().entries, 0);
+    }
+}
@@ -0,0 +1,348 @@
+//! Source-level summary of what changed between two versions of a crate.
+//!
+//! Meant as a quick risk assessment before accepting a version bump: which
+//! files came and went, how many lines changed, which dependencies are new,
+//! and whether the build script appeared or started doing something notable.
+
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::crates::CrateInfo;
+use crate::tarball::ExtractionLimits;
+
+/// Files that change on every release and say nothing about the code.
+const IGNORED_FILES: &[&str] = &[".cargo_vcs_info.json", ".cargo-checksum.json", "Cargo.lock"];
+
+/// Things a build script can do that deserve a reviewer's attention.
+const BUILD_SCRIPT_PATTERNS: &[(&str, &str)] = &[
+    ("Command::new", "runs external commands"),
+    ("TcpStream", "opens network connections"),
+    ("reqwest", "opens network connections"),
+    ("ureq", "opens network connections"),
+    ("curl", "opens network connections"),
+    ("cc::Build", "compiles C/C++ code"),
+    ("pkg_config", "probes system libraries"),
+    ("rustc-link-lib", "links native libraries"),
+    ("include_bytes!", "embeds binary data"),
+    ("fs::remove", "deletes files"),
+];
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CrateDiff {
+    pub crate_name: String,
+    pub old_version: String,
+    pub new_version: String,
+    pub added_files: Vec<String>,
+    pub removed_files: Vec<String>,
+    pub changed_files: Vec<String>,
+    pub lines_added: usize,
+    pub lines_removed: usize,
+    pub new_dependencies: Vec<String>,
+    pub removed_dependencies: Vec<String>,
+    pub build_script: BuildScriptChange,
+    /// Notable behaviour found in the new build script that the old one
+    /// did not have.
+    pub new_build_behavior: Vec<String>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BuildScriptChange {
+    #[default]
+    None,
+    Added,
+    Removed,
+    Changed,
+    Unchanged,
+}
+
+/// Download `crate_name` at `old_version` and `new_version` and compare them.
+pub fn diff_crate_versions(
+    crate_name: &str,
+    old_version: &str,
+    new_version: &str,
+) -> Result<CrateDiff> {
+    let workdir = tempfile::Builder::new()
+        .prefix(&format!("takopack-diff-{}-", crate_name))
+        .tempdir()
+        .context("Failed to create temporary directory")?;
+    let old_dir = extract_version(crate_name, old_version, &workdir.path().join("old"))?;
+    let new_dir = extract_version(crate_name, new_version, &workdir.path().join("new"))?;
+    let mut diff = diff_crate_dirs(&old_dir, &new_dir)?;
+    diff.crate_name = crate_name.to_string();
+    diff.old_version = old_version.to_string();
+    diff.new_version = new_version.to_string();
+    Ok(diff)
+}
+
+fn extract_version(crate_name: &str, version: &str, dest: &Path) -> Result<PathBuf> {
+    let mut crate_info = CrateInfo::new(crate_name, Some(version))
+        .with_context(|| format!("Failed to fetch {} {}", crate_name, version))?;
+    crate_info
+        .extract_crate(dest, &ExtractionLimits::default())
+        .with_context(|| format!("Failed to extract {} {}", crate_name, version))?;
+    Ok(dest.to_path_buf())
+}
+
+/// Compare two unpacked crate directories.
+pub fn diff_crate_dirs(old_dir: &Path, new_dir: &Path) -> Result<CrateDiff> {
+    let old_files = collect_files(old_dir)?;
+    let new_files = collect_files(new_dir)?;
+    let mut diff = CrateDiff::default();
+
+    for (path, new_content) in &new_files {
+        match old_files.get(path) {
+            None => {
+                diff.added_files.push(path.clone());
+                diff.lines_added += text_lines(new_content).len();
+            }
+            Some(old_content) if old_content != new_content => {
+                diff.changed_files.push(path.clone());
+                let (added, removed) = line_changes(old_content, new_content);
+                diff.lines_added += added;
+                diff.lines_removed += removed;
+            }
+            Some(_) => {}
+        }
+    }
+    for (path, old_content) in &old_files {
+        if !new_files.contains_key(path) {
+            diff.removed_files.push(path.clone());
+            diff.lines_removed += text_lines(old_content).len();
+        }
+    }
+
+    let old_deps = manifest_dependencies(old_files.get("Cargo.toml"));
+    let new_deps = manifest_dependencies(new_files.get("Cargo.toml"));
+    diff.new_dependencies = new_deps.difference(&old_deps).cloned().collect();
+    diff.removed_dependencies = old_deps.difference(&new_deps).cloned().collect();
+
+    let old_build = build_script(&old_files);
+    let new_build = build_script(&new_files);
+    diff.build_script = match (old_build, new_build) {
+        (None, None) => BuildScriptChange::None,
+        (None, Some(_)) => BuildScriptChange::Added,
+        (Some(_), None) => BuildScriptChange::Removed,
+        (Some(old), Some(new)) if old == new => BuildScriptChange::Unchanged,
+        (Some(_), Some(_)) => BuildScriptChange::Changed,
+    };
+    if let Some(new) = new_build {
+        let old_behavior = build_behavior(old_build.unwrap_or(&[]));
+        diff.new_build_behavior = build_behavior(new)
+            .difference(&old_behavior)
+            .map(|s| s.to_string())
+            .collect();
+    }
+    Ok(diff)
+}
+
+fn collect_files(root: &Path) -> Result<BTreeMap<String, Vec<u8>>> {
+    let mut files = BTreeMap::new();
+    for entry in walkdir::WalkDir::new(root) {
+        let entry = entry.context("walkdir error")?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let rel = entry.path().strip_prefix(root).unwrap_or(entry.path());
+        let rel = rel.to_string_lossy().replace('\\', "/");
+        if IGNORED_FILES.contains(&rel.as_str()) {
+            continue;
+        }
+        let content = fs::read(entry.path())
+            .with_context(|| format!("Failed to read {}", entry.path().display()))?;
+        files.insert(rel, content);
+    }
+    Ok(files)
+}
+
+fn text_lines(content: &[u8]) -> Vec<&[u8]> {
+    if content.contains(&0) {
+        // binary file: nothing meaningful to count
+        return vec![];
+    }
+    content
+        .split(|b| *b == b'\n')
+        .filter(|l| !l.is_empty())
+        .collect()
+}
+
+/// Lines added and removed, counted as a multiset difference. Cheaper than
+/// a real diff and close enough for a summary.
+fn line_changes(old: &[u8], new: &[u8]) -> (usize, usize) {
+    let mut counts: HashMap<&[u8], isize> = HashMap::new();
+    for line in text_lines(old) {
+        *counts.entry(line).or_default() -= 1;
+    }
+    for line in text_lines(new) {
+        *counts.entry(line).or_default() += 1;
+    }
+    counts.values().fold((0, 0), |(added, removed), n| {
+        if *n > 0 {
+            (added + *n as usize, removed)
+        } else {
+            (added, removed + n.unsigned_abs())
+        }
+    })
+}
+
+/// Names of every normal, build and target-specific dependency.
+fn manifest_dependencies(manifest: Option<&Vec<u8>>) -> BTreeSet<String> {
+    let mut deps = BTreeSet::new();
+    let Some(value) = manifest
+        .and_then(|m| std::str::from_utf8(m).ok())
+        .and_then(|m| m.parse::<toml::Value>().ok())
+    else {
+        return deps;
+    };
+    let mut tables = vec![&value];
+    if let Some(targets) = value.get("target").and_then(toml::Value::as_table) {
+        tables.extend(targets.values());
+    }
+    for table in tables {
+        for key in ["dependencies", "build-dependencies"] {
+            if let Some(section) = table.get(key).and_then(toml::Value::as_table) {
+                for (name, spec) in section {
+                    let package = spec
+                        .get("package")
+                        .and_then(toml::Value::as_str)
+                        .unwrap_or(name);
+                    deps.insert(package.to_string());
+                }
+            }
+        }
+    }
+    deps
+}
+
+fn build_script(files: &BTreeMap<String, Vec<u8>>) -> Option<&[u8]> {
+    let manifest_build = files
+        .get("Cargo.toml")
+        .and_then(|m| std::str::from_utf8(m).ok())
+        .and_then(|m| m.parse::<toml::Value>().ok())
+        .and_then(|v| v.get("package")?.get("build").cloned());
+    let path = match manifest_build {
+        Some(toml::Value::Boolean(false)) => return None,
+        Some(toml::Value::String(path)) => path,
+        _ => "build.rs".to_string(),
+    };
+    files.get(path.trim_start_matches("./")).map(Vec::as_slice)
+}
+
+fn build_behavior(script: &[u8]) -> BTreeSet<&'static str> {
+    let script = String::from_utf8_lossy(script);
+    BUILD_SCRIPT_PATTERNS
+        .iter()
+        .filter(|(pattern, _)| script.contains(pattern))
+        .map(|(_, behavior)| *behavior)
+        .collect()
+}
+
+impl fmt::Display for CrateDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "# {} {} -> {}",
+            self.crate_name, self.old_version, self.new_version
+        )?;
+        writeln!(f)?;
+        writeln!(
+            f,
+            "Files: {} added, {} removed, {} changed",
+            self.added_files.len(),
+            self.removed_files.len(),
+            self.changed_files.len()
+        )?;
+        writeln!(f, "Lines: +{} -{}", self.lines_added, self.lines_removed)?;
+        let build = match self.build_script {
+            BuildScriptChange::None => "none",
+            BuildScriptChange::Added => "ADDED",
+            BuildScriptChange::Removed => "removed",
+            BuildScriptChange::Changed => "changed",
+            BuildScriptChange::Unchanged => "unchanged",
+        };
+        writeln!(f, "Build script: {}", build)?;
+        for behavior in &self.new_build_behavior {
+            writeln!(f, "  ! now {}", behavior)?;
+        }
+
+        let lists = [
+            ("New dependencies", &self.new_dependencies),
+            ("Removed dependencies", &self.removed_dependencies),
+            ("Added files", &self.added_files),
+            ("Removed files", &self.removed_files),
+            ("Changed files", &self.changed_files),
+        ];
+        for (title, items) in lists {
+            if items.is_empty() {
+                continue;
+            }
+            writeln!(f)?;
+            writeln!(f, "{}:", title)?;
+            for item in items {
+                writeln!(f, "  {}", item)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff_crate_dirs, line_changes, BuildScriptChange};
+    use std::fs;
+    use std::path::Path;
+
+    fn write(root: &Path, rel: &str, content: &str) {
+        let path = root.join(rel);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn diff_reports_files_dependencies_and_build_script() {
+        let old = tempfile::tempdir().unwrap();
+        let new = tempfile::tempdir().unwrap();
+        write(
+            old.path(),
+            "Cargo.toml",
+            "[package]\nname = \"demo\"\nversion = \"1.0.0\"\n\n[dependencies]\nlog = \"0.4\"\n",
+        );
+        write(old.path(), "src/lib.rs", "fn a() {}\nfn b() {}\n");
+        write(old.path(), "src/old.rs", "fn gone() {}\n");
+        write(old.path(), ".cargo_vcs_info.json", "{\"sha1\":\"a\"}");
+
+        write(
+            new.path(),
+            "Cargo.toml",
+            "[package]\nname = \"demo\"\nversion = \"1.1.0\"\n\n[dependencies]\nlog = \"0.4\"\n\n[target.'cfg(unix)'.dependencies]\nlibc = \"0.2\"\n\n[build-dependencies]\ncc = \"1\"\n",
+        );
+        write(new.path(), "src/lib.rs", "fn a() {}\nfn c() {}\n");
+        write(
+            new.path(),
+            "build.rs",
+            "fn main() { cc::Build::new().file(\"x.c\").compile(\"x\"); }\n",
+        );
+        write(new.path(), ".cargo_vcs_info.json", "{\"sha1\":\"b\"}");
+
+        let diff = diff_crate_dirs(old.path(), new.path()).unwrap();
+        assert_eq!(diff.added_files, vec!["build.rs"]);
+        assert_eq!(diff.removed_files, vec!["src/old.rs"]);
+        assert_eq!(diff.changed_files, vec!["Cargo.toml", "src/lib.rs"]);
+        assert_eq!(diff.new_dependencies, vec!["cc", "libc"]);
+        assert!(diff.removed_dependencies.is_empty());
+        assert_eq!(diff.build_script, BuildScriptChange::Added);
+        assert_eq!(diff.new_build_behavior, vec!["compiles C/C++ code"]);
+
+        let report = diff.to_string();
+        assert!(report.contains("Build script: ADDED"));
+        assert!(report.contains("New dependencies:\n  cc\n  libc"));
+    }
+
+    #[test]
+    fn line_changes_count_both_directions() {
+        assert_eq!(line_changes(b"a\nb\nc\n", b"a\nc\nd\ne\n"), (2, 1));
+        assert_eq!(line_changes(b"bin\0ary", b"other"), (1, 0));
+    }
+}
@@ -2,8 +2,8 @@ use anyhow::{format_err, Context, Error};
 use cargo::{
     core::{
         manifest::ManifestMetadata, registry::PackageRegistry, resolver::features::CliFeatures,
-        Dependency, EitherManifest, FeatureValue, Manifest, Package, PackageId, Registry, SourceId,
-        Summary, Target, TargetKind, Workspace,
+        Dependency, EitherManifest, FeatureValue, Manifest, Package, PackageId, Registry, Shell,
+        SourceId, Summary, Target, TargetKind, Workspace,
     },
     ops::{self, PackageMessageFormat, PackageOpts, Packages},
     sources::{
@@ -11,8 +11,8 @@ use cargo::{
         IndexSummary, RegistrySource, SourceConfigMap,
     },
     util::{
-        cache_lock::CacheLockMode, interning::InternedString, toml::read_manifest, FileLock,
-        Filesystem, GlobalContext,
+        cache_lock::CacheLockMode, errors::HttpNotSuccessful, interning::InternedString,
+        toml::read_manifest, FileLock, Filesystem, GlobalContext, IntoUrl,
     },
 };
 use filetime::{set_file_times, FileTime};
@@ -25,14 +25,22 @@ use sha2::{Digest, Sha256};
 use tar::Archive;
 use tempfile;
 
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::fs;
 use std::io::{self, Read};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::{self, ffi::OsStr};
 
-use crate::config::testing_ignore_debpolv;
+use serde_derive::Deserialize;
+
+use crate::crate_cache::CrateCache;
 use crate::errors::*;
+use crate::http::CRATES_IO_API;
+use crate::index_cache::write_atomically;
+use crate::lockfile_parser::{member_dependencies, parse_lockfile_str, DependencyGraph};
+use crate::tarball::{self, EntryCheck, ExtractionLimits};
+use crate::test_mode::TestMode;
+use crate::toolchain_crates::ToolchainCrates;
 #[derive(Debug)]
 pub struct CrateInfo {
     // only used for to_registry_toml in extract_crate. DO NOT USE ELSEWHERE
@@ -44,6 +52,275 @@ pub struct CrateInfo {
     source_id: SourceId,
     excludes: Vec<String>,
     includes: Vec<String>,
+    test_mode: TestMode,
+    // spec Source: URL for crates that don't come from crates.io
+    download_url: Option<String>,
+    // which crates.io index the crate was resolved from; None elsewhere
+    metadata_source: Option<MetadataSource>,
+    // a yanked version packaged with --allow-yanked
+    yanked: bool,
+    // where a crate of an offline mirror was unpacked or packed; removed
+    // when the CrateInfo is dropped, after the crate_file lock
+    _mirror_work_dir: Option<tempfile::TempDir>,
+}
+
+/// Registry that crates are looked up and downloaded from.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum CrateRegistry {
+    #[default]
+    CratesIo,
+    /// A registry configured as `[registries.<name>]` in cargo's config.
+    Named(String),
+    /// A registry index URL, either a git index or `sparse+https://...`.
+    IndexUrl(String),
+}
+
+impl CrateRegistry {
+    pub fn from_args(name: Option<&str>, index_url: Option<&str>) -> Result<Self> {
+        match (name, index_url) {
+            (Some(_), Some(_)) => {
+                takopack_bail!("--registry and --index-url are mutually exclusive")
+            }
+            (Some(name), None) => Ok(CrateRegistry::Named(name.to_string())),
+            (None, Some(url)) => Ok(CrateRegistry::IndexUrl(url.to_string())),
+            (None, None) => Ok(CrateRegistry::CratesIo),
+        }
+    }
+
+    fn source_id(&self, context: &GlobalContext) -> Result<SourceId> {
+        match self {
+            CrateRegistry::CratesIo => SourceId::crates_io_maybe_sparse_http(context),
+            CrateRegistry::Named(name) => SourceId::alt_registry(context, name)
+                .with_context(|| format!("Unknown registry `{}` in cargo config", name)),
+            CrateRegistry::IndexUrl(url) => SourceId::for_registry(&url.as_str().into_url()?),
+        }
+    }
+}
+
+/// Where the metadata of a crates.io crate was resolved from. Normally the
+/// index protocol cargo is configured for; the other protocol, or for
+/// version lists the crates.io API, is used when that one is unreachable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetadataSource {
+    SparseIndex,
+    GitIndex,
+    Api,
+}
+
+impl std::fmt::Display for MetadataSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MetadataSource::SparseIndex => write!(f, "sparse index"),
+            MetadataSource::GitIndex => write!(f, "git index"),
+            MetadataSource::Api => write!(f, "crates.io API"),
+        }
+    }
+}
+
+/// The crates.io index over the protocol cargo is configured for, then over
+/// the other one.
+fn crates_io_sources(context: &GlobalContext) -> Result<[(SourceId, MetadataSource); 2]> {
+    let configured = SourceId::crates_io_maybe_sparse_http(context)?;
+    Ok(if configured.is_sparse() {
+        [
+            (configured, MetadataSource::SparseIndex),
+            (SourceId::crates_io(context)?, MetadataSource::GitIndex),
+        ]
+    } else {
+        let sparse = format!("sparse+{}/", SparseIndex::CRATES_IO);
+        [
+            (configured, MetadataSource::GitIndex),
+            (
+                SourceId::for_registry(&sparse.into_url()?)?,
+                MetadataSource::SparseIndex,
+            ),
+        ]
+    })
+}
+
+/// Directory name cargo caches the index and downloads of `source_id` under.
+fn registry_cache_name(source_id: SourceId) -> String {
+    format!(
+        "{}-{}",
+        source_id.url().host_str().unwrap_or(""),
+        cargo::util::hex::short_hash(&source_id)
+    )
+}
+
+/// Whether the index of `source_id` answers at all, to tell an unreachable
+/// index from a failed lookup.
+fn index_reachable(source_id: SourceId) -> bool {
+    let url = source_id.url().as_str().trim_end_matches('/');
+    let probe = if source_id.is_sparse() {
+        format!("{}/config.json", url.trim_start_matches("sparse+"))
+    } else {
+        format!("{}/info/refs?service=git-upload-pack", url)
+    };
+    crate::http::agent()
+        .get(&probe)
+        .timeout(std::time::Duration::from_secs(10))
+        .call()
+        .is_ok()
+}
+
+/// How crates are downloaded, set in the `[download]` table of
+/// takopack.toml. Failed downloads are retried over flaky networks; cargo's
+/// own retries are turned off so that these are the only ones.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct DownloadOptions {
+    /// Attempts after the first one.
+    pub retries: u32,
+    /// Wait before the first retry, in seconds; doubled for each further one.
+    pub backoff: u64,
+    /// HTTP timeout of every attempt, in seconds.
+    pub timeout: u64,
+    /// Reuse crate archives from the shared
+    /// [`CrateCache`](crate::crate_cache::CrateCache).
+    pub cache: bool,
+    /// Index URL of a crates.io mirror to use instead of crates.io
+    /// (`sparse+https://...` for sparse indexes).
+    pub mirror_url: Option<String>,
+    /// HTTP(S) proxy for every download.
+    pub proxy: Option<String>,
+    /// PEM file of additional CA certificates to trust.
+    pub cacert: Option<PathBuf>,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        DownloadOptions {
+            retries: 3,
+            backoff: 2,
+            timeout: 30,
+            cache: true,
+            mirror_url: None,
+            proxy: None,
+            cacert: None,
+        }
+    }
+}
+
+impl DownloadOptions {
+    /// Wait before retry number `retry`, counting from 1.
+    fn delay(&self, retry: u32) -> std::time::Duration {
+        std::time::Duration::from_secs(self.backoff.saturating_mul(1 << (retry - 1).min(16)))
+    }
+
+    /// `--config` values handing the timeout, proxy and certificates to
+    /// cargo.
+    fn cargo_config(&self) -> Vec<String> {
+        let string = |value: &str| toml::Value::String(value.to_string()).to_string();
+        let mut config = vec![
+            format!("http.timeout={}", self.timeout),
+            "net.retry=0".to_string(),
+        ];
+        if let Some(proxy) = &self.proxy {
+            config.push(format!("http.proxy={}", string(proxy)));
+        }
+        if let Some(cacert) = &self.cacert {
+            config.push(format!("http.cainfo={}", string(&cacert.to_string_lossy())));
+        }
+        config
+    }
+
+    /// A cargo context using the network with this timeout.
+    fn online_context(&self) -> Result<GlobalContext> {
+        let mut context = cargo_context()?;
+        self.configure(&mut context, false)?;
+        Ok(context)
+    }
+
+    fn configure(&self, context: &mut GlobalContext, offline: bool) -> Result<()> {
+        let target_dir = context.target_dir()?.map(|x| x.into_path_unlocked());
+        // unfriendly API from cargo; we'll have to make do with it for
+        // now as there is no other alternative
+        context.configure(
+            0,
+            false,
+            None,
+            false,
+            false,
+            offline,
+            &target_dir,
+            &[],
+            &self.cargo_config(),
+        )
+    }
+
+    /// Run `attempt` until it succeeds, retrying failures `retry_if`
+    /// considers transient.
+    fn run<T>(
+        &self,
+        what: &str,
+        mut attempt: impl FnMut() -> Result<T>,
+        retry_if: impl Fn(&Error) -> bool,
+    ) -> Result<T> {
+        let mut retry = 0;
+        loop {
+            match attempt() {
+                Err(e) if retry < self.retries && retry_if(&e) => {
+                    retry += 1;
+                    let delay = self.delay(retry);
+                    takopack_warn!(
+                        "downloading {} failed ({:#}), retry {}/{} in {}s",
+                        what,
+                        e,
+                        retry,
+                        self.retries,
+                        delay.as_secs()
+                    );
+                    std::thread::sleep(delay);
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Whether `error` is a network failure that may go away on its own:
+/// a timeout, a dropped connection, or an HTTP 5xx or 429 answer.
+fn is_transient(error: &Error) -> bool {
+    error.chain().any(|cause| {
+        if let Some(e) = cause.downcast_ref::<HttpNotSuccessful>() {
+            return is_transient_status(e.code);
+        }
+        if let Some(e) = cause.downcast_ref::<ureq::Error>() {
+            return match e {
+                ureq::Error::Status(code, _) => is_transient_status(u32::from(*code)),
+                ureq::Error::Transport(transport) => matches!(
+                    transport.kind(),
+                    ureq::ErrorKind::Dns | ureq::ErrorKind::ConnectionFailed | ureq::ErrorKind::Io
+                ),
+            };
+        }
+        if let Some(e) = cause.downcast_ref::<curl::Error>() {
+            return e.is_operation_timedout()
+                || e.is_couldnt_connect()
+                || e.is_couldnt_resolve_host()
+                || e.is_recv_error()
+                || e.is_send_error()
+                || e.is_partial_file();
+        }
+        if let Some(e) = cause.downcast_ref::<git2::Error>() {
+            return matches!(e.class(), git2::ErrorClass::Net | git2::ErrorClass::Http)
+                && e.code() != git2::ErrorCode::Certificate;
+        }
+        if let Some(e) = cause.downcast_ref::<io::Error>() {
+            return matches!(
+                e.kind(),
+                io::ErrorKind::TimedOut
+                    | io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::UnexpectedEof
+            );
+        }
+        false
+    })
+}
+
+fn is_transient_status(code: u32) -> bool {
+    code == 429 || (500..600).contains(&code)
 }
 
 pub type CrateDepInfo = BTreeMap<
@@ -67,8 +344,63 @@ fn fetch_candidates(registry: &mut PackageRegistry, dep: &Dependency) -> Result<
     Ok(summaries)
 }
 
+/// Hex sha256 of everything `reader` yields.
+fn sha256_hex(mut reader: impl Read) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 8192];
+    loop {
+        let bytes_read = reader.read(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..bytes_read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Check the crate archive at `path` against the sha256 the registry index
+/// lists for it.
+pub fn verify_crate_checksum(path: &Path, expected: &str) -> Result<()> {
+    let file =
+        fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let actual = sha256_hex(file).with_context(|| format!("Failed to read {}", path.display()))?;
+    if !actual.eq_ignore_ascii_case(expected) {
+        takopack_bail!(
+            "integrity error: {} has sha256 {}, but the registry index lists {}\n\
+            The download is corrupt or was tampered with; remove the file and try again.",
+            path.display(),
+            actual,
+            expected
+        );
+    }
+    Ok(())
+}
+
+/// The shared crate cache, or `None` (with a warning) when there is no
+/// place for it.
+fn shared_crate_cache() -> Option<CrateCache> {
+    match CrateCache::open_default() {
+        Ok(cache) => Some(cache),
+        Err(e) => {
+            takopack_warn!("not using the crate cache: {:#}", e);
+            None
+        }
+    }
+}
+
+/// A cargo context; in an [`crate::paths::isolated`] call, one that keeps
+/// its `CARGO_HOME` in the state directory and prints nothing.
+fn cargo_context() -> Result<GlobalContext> {
+    let Some(state_dir) = crate::paths::isolated_state_dir() else {
+        return GlobalContext::default();
+    };
+    let shell = Shell::from_write(Box::new(io::sink()));
+    let cwd = std::env::current_dir().context("Failed to resolve the current directory")?;
+    Ok(GlobalContext::new(shell, cwd, state_dir.join("cargo")))
+}
+
 pub fn invalidate_crates_io_cache() -> Result<()> {
-    let context = GlobalContext::default()?;
+    let context = cargo_context()?;
     let _lock = context.acquire_package_cache_lock(CacheLockMode::DownloadExclusive)?;
     let source_id = SourceId::crates_io_maybe_sparse_http(&context)?;
     let yanked_whitelist = HashSet::new();
@@ -78,9 +410,18 @@ pub fn invalidate_crates_io_cache() -> Result<()> {
 }
 
 pub fn crate_name_ver_to_dep(crate_name: &str, version: Option<&str>) -> Result<Dependency> {
+    registry_crate_dep(crate_name, version, &CrateRegistry::CratesIo)
+}
+
+/// Like [`crate_name_ver_to_dep`], for a crate hosted on `registry`.
+pub fn registry_crate_dep(
+    crate_name: &str,
+    version: Option<&str>,
+    registry: &CrateRegistry,
+) -> Result<Dependency> {
     // note: this forces a network call
-    let context = GlobalContext::default()?;
-    let source_id = SourceId::crates_io_maybe_sparse_http(&context)?;
+    let context = cargo_context()?;
+    let source_id = registry.source_id(&context)?;
     let version = version.and_then(|v| {
         if v.is_empty() {
             None
@@ -98,8 +439,22 @@ pub fn crate_name_ver_to_dep(crate_name: &str, version: Option<&str>) -> Result<
 ///
 /// Unlike `crate_name_ver_to_dep`, this treats `version_req` as dependency
 /// syntax (`"0.29"` means caret-compatible `0.29`, not exact `=0.29`).
+///
+/// The crates.io sparse index is tried first (or the crates.io API when the
+/// index is unreachable); cargo's registry machinery is the fallback when
+/// neither can be reached.
 pub fn resolve_crates_io_version_req(crate_name: &str, version_req: &str) -> Result<Version> {
-    let context = GlobalContext::default()?;
+    match SparseIndex::crates_io().and_then(|index| index.resolve(crate_name, version_req)) {
+        Ok(found) => return Ok(found.version),
+        Err(e) => log::debug!(
+            "sparse index lookup of {} {} failed, asking cargo: {:#}",
+            crate_name,
+            version_req,
+            e
+        ),
+    }
+
+    let context = cargo_context()?;
     let source_id = SourceId::crates_io_maybe_sparse_http(&context)?;
     let version_req = if version_req.trim().is_empty() {
         None
@@ -132,6 +487,291 @@ pub fn resolve_crates_io_version_req(crate_name: &str, version_req: &str) -> Res
     Ok(pkgid.version().clone())
 }
 
+/// One published version of a crate, as listed in a sparse index file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct IndexVersion {
+    pub name: String,
+    #[serde(rename = "vers")]
+    pub version: Version,
+    #[serde(default)]
+    pub cksum: String,
+    #[serde(default)]
+    pub yanked: bool,
+    #[serde(default)]
+    pub deps: Vec<IndexDependency>,
+}
+
+/// A dependency of an [`IndexVersion`].
+#[derive(Debug, Clone, Deserialize)]
+pub struct IndexDependency {
+    /// Name the dependency is known by in the depending crate.
+    pub name: String,
+    pub req: String,
+    #[serde(default)]
+    pub optional: bool,
+    /// `normal`, `build` or `dev`; missing means `normal`.
+    #[serde(default)]
+    pub kind: Option<String>,
+    /// Real crate name when the dependency is renamed.
+    #[serde(default)]
+    pub package: Option<String>,
+}
+
+impl IndexDependency {
+    pub fn crate_name(&self) -> &str {
+        self.package.as_deref().unwrap_or(&self.name)
+    }
+
+    pub fn is_dev(&self) -> bool {
+        self.kind.as_deref() == Some("dev")
+    }
+}
+
+/// Lightweight client for a cargo sparse HTTP index (crates.io by default).
+///
+/// Index files are cached on disk and revalidated with their ETag, so an
+/// unchanged crate costs one `304 Not Modified`, and a cached copy is used
+/// when the index is unreachable.
+pub struct SparseIndex {
+    base_url: String,
+    cache_dir: Option<std::path::PathBuf>,
+}
+
+impl SparseIndex {
+    pub const CRATES_IO: &'static str = "https://index.crates.io";
+
+    /// The crates.io index, cached under `~/.cache/takopack/index-cache/`.
+    /// A configured sparse mirror stands in for it.
+    pub fn crates_io() -> Result<Self> {
+        let cache_dir = crate::paths::index_cache_dir()?;
+        let url = crate::http::mirror_url()
+            .filter(|url| url.starts_with("sparse+"))
+            .unwrap_or_else(|| Self::CRATES_IO.to_string());
+        Ok(Self::new(&url, Some(cache_dir)))
+    }
+
+    pub fn new(base_url: &str, cache_dir: Option<std::path::PathBuf>) -> Self {
+        SparseIndex {
+            base_url: base_url
+                .trim_start_matches("sparse+")
+                .trim_end_matches('/')
+                .to_string(),
+            cache_dir,
+        }
+    }
+
+    /// All published versions of `crate_name`, yanked ones included.
+    pub fn versions(&self, crate_name: &str) -> Result<Vec<IndexVersion>> {
+        let path = sparse_index_path(crate_name);
+        let body = self.fetch(&path)?;
+        let mut versions = Vec::new();
+        for line in body.lines().filter(|l| !l.trim().is_empty()) {
+            match serde_json::from_str::<IndexVersion>(line) {
+                Ok(version) => versions.push(version),
+                // newer index schema versions may carry entries we can't read
+                Err(e) => log::debug!("skipping index entry of {}: {}", crate_name, e),
+            }
+        }
+        Ok(versions)
+    }
+
+    /// [`Self::versions`] of a crates.io crate, listed by the crates.io API
+    /// instead when the index cannot be reached at all. Versions from the
+    /// API carry no dependencies, so this only serves version lookups.
+    fn listed_versions(&self, crate_name: &str) -> Result<Vec<IndexVersion>> {
+        match self.versions(crate_name) {
+            // a lookup the index answered, even with "not found", is final
+            Err(e)
+                if self.base_url == Self::CRATES_IO
+                    && e.downcast_ref::<ureq::Error>().is_some() =>
+            {
+                takopack_warn!(
+                    "crates.io index unreachable ({:#}), listing {} through the {}",
+                    e,
+                    crate_name,
+                    MetadataSource::Api
+                );
+                api_versions(crate_name)
+            }
+            listed => listed,
+        }
+    }
+
+    /// Highest non-yanked version matching `version_req` (dependency syntax;
+    /// empty means any version).
+    pub fn resolve(&self, crate_name: &str, version_req: &str) -> Result<IndexVersion> {
+        select_index_version(crate_name, self.listed_versions(crate_name)?, version_req)
+    }
+
+    /// Fetch the index entry of `crate_name` again, failing instead of
+    /// falling back to the cached copy when the index is unreachable.
+    pub fn refresh(&self, crate_name: &str) -> Result<()> {
+        self.fetch_with(&sparse_index_path(crate_name), false)
+            .map(|_| ())
+    }
+
+    /// Whether `crate_name` `version` is yanked; `None` if it was never published.
+    pub fn is_yanked(&self, crate_name: &str, version: &Version) -> Result<Option<bool>> {
+        Ok(self
+            .listed_versions(crate_name)?
+            .into_iter()
+            .find(|v| &v.version == version)
+            .map(|v| v.yanked))
+    }
+
+    fn fetch(&self, path: &str) -> Result<String> {
+        self.fetch_with(path, true)
+    }
+
+    /// Fetch `path`, revalidating the cached copy. With `use_stale`, the
+    /// cached copy is returned when the index cannot be reached.
+    fn fetch_with(&self, path: &str, use_stale: bool) -> Result<String> {
+        let cache_file = self.cache_dir.as_ref().map(|dir| dir.join(path));
+        let etag_file = cache_file.as_ref().map(|f| f.with_extension("etag"));
+        let cached = cache_file.as_ref().and_then(|f| fs::read_to_string(f).ok());
+        let etag = etag_file.as_ref().and_then(|f| fs::read_to_string(f).ok());
+
+        let url = format!("{}/{}", self.base_url, path);
+        let mut request = crate::http::agent()
+            .get(&url)
+            .timeout(std::time::Duration::from_secs(30));
+        if let (Some(_), Some(etag)) = (&cached, &etag) {
+            request = request.set("If-None-Match", etag.trim());
+        }
+        let response = match request.call() {
+            Ok(response) => response,
+            Err(ureq::Error::Status(404, _)) | Err(ureq::Error::Status(410, _)) => {
+                takopack_bail!("crate not found in index {}: {}", self.base_url, path)
+            }
+            Err(e) => {
+                if let Some(cached) = cached.filter(|_| use_stale) {
+                    takopack_warn!("{} unreachable ({}), using cached index entry", url, e);
+                    return Ok(cached);
+                }
+                return Err(e).with_context(|| format!("Failed to fetch {}", url));
+            }
+        };
+        if response.status() == 304 {
+            if let (Some(cached), Some(cache_file)) = (cached, &cache_file) {
+                // the age of an entry is the time since it was last validated
+                let _ = filetime::set_file_mtime(cache_file, FileTime::now());
+                return Ok(cached);
+            }
+        }
+
+        let new_etag = response.header("ETag").map(str::to_string);
+        let body = response
+            .into_string()
+            .with_context(|| format!("Failed to read {}", url))?;
+        if let (Some(cache_file), Some(etag_file)) = (cache_file, etag_file) {
+            // the cache is an optimisation; failing to write it is not an error
+            let written =
+                write_atomically(&cache_file, body.as_bytes()).and_then(|_| match &new_etag {
+                    Some(etag) => write_atomically(&etag_file, etag.as_bytes()),
+                    None => fs::remove_file(&etag_file).or(Ok(())),
+                });
+            if let Err(e) = written {
+                log::debug!("could not cache {}: {}", cache_file.display(), e);
+            }
+        }
+        Ok(body)
+    }
+}
+
+/// A version in a crates.io API `versions` response.
+#[derive(Deserialize)]
+struct ApiVersion {
+    num: Version,
+    #[serde(default)]
+    checksum: String,
+    #[serde(default)]
+    yanked: bool,
+}
+
+#[derive(Deserialize)]
+struct ApiVersions {
+    versions: Vec<ApiVersion>,
+}
+
+/// Published versions of `crate_name` as listed by the crates.io API.
+fn api_versions(crate_name: &str) -> Result<Vec<IndexVersion>> {
+    let url = format!("{}/crates/{}/versions", CRATES_IO_API, crate_name);
+    let body = crate::http::agent()
+        .get(&url)
+        .set(
+            "User-Agent",
+            concat!("takopack/", env!("CARGO_PKG_VERSION")),
+        )
+        .timeout(std::time::Duration::from_secs(30))
+        .call()
+        .with_context(|| format!("Failed to fetch {}", url))?
+        .into_string()
+        .with_context(|| format!("Failed to read {}", url))?;
+    parse_api_versions(crate_name, &body)
+        .with_context(|| format!("Failed to parse versions of {}", crate_name))
+}
+
+fn parse_api_versions(crate_name: &str, body: &str) -> Result<Vec<IndexVersion>> {
+    let response: ApiVersions = serde_json::from_str(body)?;
+    Ok(response
+        .versions
+        .into_iter()
+        .map(|v| IndexVersion {
+            name: crate_name.to_string(),
+            version: v.num,
+            cksum: v.checksum,
+            yanked: v.yanked,
+            deps: Vec::new(),
+        })
+        .collect())
+}
+
+/// Highest non-yanked entry of `versions` matching `version_req`
+/// (dependency syntax; empty means any version).
+pub fn select_index_version(
+    crate_name: &str,
+    versions: Vec<IndexVersion>,
+    version_req: &str,
+) -> Result<IndexVersion> {
+    let req = match version_req.trim() {
+        "" => semver::VersionReq::STAR,
+        req => semver::VersionReq::parse(req)
+            .with_context(|| format!("Invalid version requirement: {}", req))?,
+    };
+    let matching: Vec<IndexVersion> = versions
+        .into_iter()
+        .filter(|v| req.matches(&v.version))
+        .collect();
+    if let Some(found) = matching
+        .iter()
+        .filter(|v| !v.yanked)
+        .max_by(|a, b| a.version.cmp(&b.version))
+    {
+        return Ok(found.clone());
+    }
+    if matching.is_empty() {
+        takopack_bail!("no version of {} matches {}", crate_name, req);
+    }
+    takopack_bail!(
+        "every version of {} matching {} is yanked ({})",
+        crate_name,
+        req,
+        matching.iter().map(|v| v.version.to_string()).join(", ")
+    )
+}
+
+/// Path of a crate's file in a sparse index: `1/a`, `2/ab`, `3/a/abc`,
+/// `se/rd/serde`. Names are lowercased, as the index stores them.
+pub fn sparse_index_path(crate_name: &str) -> String {
+    let name = crate_name.to_lowercase();
+    match name.len() {
+        1 => format!("1/{}", name),
+        2 => format!("2/{}", name),
+        3 => format!("3/{}/{}", &name[..1], name),
+        _ => format!("{}/{}/{}", &name[..2], &name[2..4], name),
+    }
+}
+
 // attempt to map back a version requirement to a version that can be used as last resort
 // fallback in case all versions satisfying the requirement are yanked
 fn ver_req_to_ver(dep: &Dependency) -> Option<Version> {
@@ -176,13 +816,155 @@ fn ver_req_to_ver(dep: &Dependency) -> Option<Version> {
     }
 }
 
+#[derive(Deserialize)]
+struct IndexConfig {
+    dl: String,
+}
+
+/// Read the `dl` download template from a registry index's config.json,
+/// preferring the copy cargo already fetched into its index cache.
+fn registry_dl_template(
+    context: &GlobalContext,
+    source_id: SourceId,
+    registry_name: &str,
+) -> Result<String> {
+    let index_dir = context
+        .registry_index_path()
+        .join(registry_name)
+        .into_path_unlocked();
+    let config_json = if let Ok(json) = fs::read_to_string(index_dir.join("config.json")) {
+        json
+    } else if source_id.is_sparse() {
+        let index = source_id.url().as_str().trim_start_matches("sparse+");
+        crate::http::agent()
+            .get(&format!("{}/config.json", index.trim_end_matches('/')))
+            .call()
+            .with_context(|| format!("Failed to fetch config.json from {}", index))?
+            .into_string()?
+    } else {
+        let repo = git2::Repository::open(&index_dir)
+            .with_context(|| format!("Failed to open registry index {}", index_dir.display()))?;
+        let head = repo.refname_to_id("refs/remotes/origin/HEAD")?;
+        let entry = repo
+            .find_commit(head)?
+            .tree()?
+            .get_path(Path::new("config.json"))?;
+        let blob = repo.find_blob(entry.id())?;
+        String::from_utf8(blob.content().to_vec())?
+    };
+    let config: IndexConfig = serde_json::from_str(&config_json)
+        .with_context(|| format!("Invalid config.json for registry {}", source_id.url()))?;
+    Ok(config.dl)
+}
+
+/// Directory prefix of a crate in a registry index (`se/rd` for `serde`).
+fn index_prefix(crate_name: &str) -> String {
+    match crate_name.len() {
+        1 => "1".to_string(),
+        2 => "2".to_string(),
+        3 => format!("3/{}", &crate_name[..1]),
+        _ => format!("{}/{}", &crate_name[..2], &crate_name[2..4]),
+    }
+}
+
+/// Turn a registry `dl` template into a spec Source: URL. Crate name and
+/// version stay as spec macros; markers that depend on the crate are filled
+/// in. A template without markers gets cargo's default `/{crate}/{version}/download`.
+pub(crate) fn spec_download_url(dl: &str, crate_name: &str, checksum: Option<&str>) -> String {
+    let url = fill_dl_template(
+        dl,
+        "%{crate_name}",
+        "%{full_version}",
+        crate_name,
+        checksum.unwrap_or("FIXME"),
+    );
+    format!("{}#/%{{name}}-%{{version}}.tar.gz", url)
+}
+
+/// Download URL of the `.crate` file of `crate_name` `version` from
+/// crates.io, or from the configured mirror standing in for it.
+pub(crate) fn crates_io_download_url(crate_name: &str, version: &str) -> Result<String> {
+    let Some(mirror) = crate::http::mirror_url() else {
+        return Ok(format!(
+            "https://static.crates.io/crates/{}/{}/download",
+            crate_name, version
+        ));
+    };
+    let context = cargo_context()?;
+    let source_id = SourceId::for_registry(&mirror.as_str().into_url()?)?;
+    let dl = registry_dl_template(&context, source_id, &registry_cache_name(source_id))?;
+    if dl.contains("{sha256-checksum}") {
+        takopack_bail!(
+            "the download URL of mirror {} needs the checksum of the crate: {}",
+            mirror,
+            dl
+        );
+    }
+    Ok(fill_dl_template(&dl, crate_name, version, crate_name, ""))
+}
+
+/// `dl` with `crate_field` and `version_field` for its crate and version
+/// markers, see [`spec_download_url`].
+fn fill_dl_template(
+    dl: &str,
+    crate_field: &str,
+    version_field: &str,
+    crate_name: &str,
+    checksum: &str,
+) -> String {
+    const MARKERS: [&str; 5] = [
+        "{crate}",
+        "{version}",
+        "{prefix}",
+        "{lowerprefix}",
+        "{sha256-checksum}",
+    ];
+    let template = if MARKERS.iter().any(|m| dl.contains(m)) {
+        dl.to_string()
+    } else {
+        format!(
+            "{}/{{crate}}/{{version}}/download",
+            dl.trim_end_matches('/')
+        )
+    };
+    let prefix = index_prefix(crate_name);
+    template
+        .replace("{crate}", crate_field)
+        .replace("{version}", version_field)
+        .replace("{lowerprefix}", &prefix.to_lowercase())
+        .replace("{prefix}", &prefix)
+        .replace("{sha256-checksum}", checksum)
+}
+
 pub fn show_dep(dep: &Dependency) -> String {
     format!("{} {}", dep.package_name(), dep.version_req())
 }
 
 impl CrateInfo {
     pub fn new(crate_name: &str, version: Option<&str>) -> Result<CrateInfo> {
-        CrateInfo::new_with_update(crate_name, version, true)
+        CrateInfo::new_with_download(crate_name, version, &DownloadOptions::default())
+    }
+
+    /// Like [`Self::new`], downloading the crate as `download` says.
+    pub fn new_with_download(
+        crate_name: &str,
+        version: Option<&str>,
+        download: &DownloadOptions,
+    ) -> Result<CrateInfo> {
+        let dep = crate_name_ver_to_dep(crate_name, version)?;
+        Self::new_from_dependency(&dep, true, download)
+    }
+
+    /// Create CrateInfo for a crate hosted on `registry`. Authentication and
+    /// named registries come from cargo's own configuration.
+    pub fn new_with_registry(
+        crate_name: &str,
+        version: Option<&str>,
+        registry: &CrateRegistry,
+        download: &DownloadOptions,
+    ) -> Result<CrateInfo> {
+        let dep = registry_crate_dep(crate_name, version, registry)?;
+        Self::new_from_dependency(&dep, true, download)
     }
 
     pub fn new_with_local_crate(
@@ -190,7 +972,7 @@ impl CrateInfo {
         version: Option<&str>,
         crate_path: &Path,
     ) -> Result<CrateInfo> {
-        let context = GlobalContext::default()?;
+        let context = cargo_context()?;
         let crate_path = crate_path.canonicalize()?;
         let source_id = SourceId::for_path(&crate_path)?;
 
@@ -275,12 +1057,17 @@ impl CrateInfo {
             source_id,
             excludes: vec![],
             includes: vec![],
+            test_mode: TestMode::default(),
+            download_url: None,
+            metadata_source: None,
+            yanked: false,
+            _mirror_work_dir: None,
         })
     }
 
     /// Create CrateInfo directly from a Cargo.toml path (no source code required)
     pub fn new_with_local_crate_from_path(cargo_toml: &Path) -> Result<CrateInfo> {
-        let context = GlobalContext::default()?;
+        let context = cargo_context()?;
         let crate_dir = cargo_toml.parent().unwrap();
 
         log::info!("Creating CrateInfo directly from local Cargo.toml");
@@ -313,6 +1100,82 @@ impl CrateInfo {
             source_id,
             excludes: vec![],
             includes: vec![],
+            test_mode: TestMode::default(),
+            download_url: None,
+            metadata_source: None,
+            yanked: false,
+            _mirror_work_dir: None,
+        })
+    }
+
+    /// Create CrateInfo from a local crates mirror without touching the network.
+    ///
+    /// See [`crate::mirror`] for the supported mirror layouts. A missing crate
+    /// or version is an error rather than a fallback to crates.io.
+    pub fn new_from_mirror(
+        crate_name: &str,
+        version: Option<&str>,
+        mirror: &Path,
+    ) -> Result<CrateInfo> {
+        let found = crate::mirror::find_mirror_crate(mirror, crate_name, version)?;
+        log::info!("Using {} from offline mirror", found.file_stem());
+
+        // Each CrateInfo gets its own directory, so that concurrent runs
+        // do not remove each other's
+        let temp_root = match crate::paths::isolated_state_dir() {
+            Some(state_dir) => state_dir,
+            None => std::env::temp_dir(),
+        };
+        let mirror_work_dir = tempfile::Builder::new()
+            .prefix("takopack-mirror-")
+            .tempdir_in(&temp_root)
+            .with_context(|| format!("Failed to create a directory in {}", temp_root.display()))?;
+        let work_dir = mirror_work_dir.path();
+
+        let (crate_dir, crate_file) = match &found.location {
+            crate::mirror::MirrorLocation::CrateFile(path) => (
+                crate::mirror::unpack_crate_file(path, &found, work_dir)?,
+                path.clone(),
+            ),
+            crate::mirror::MirrorLocation::Directory(dir) => (
+                dir.clone(),
+                crate::mirror::pack_crate_dir(dir, &found, work_dir)?,
+            ),
+        };
+
+        let context = cargo_context()?;
+        let cargo_toml = crate_dir.join("Cargo.toml");
+        let source_id = SourceId::for_path(&crate_dir)?;
+        let manifest = match read_manifest(&cargo_toml, source_id, &context)? {
+            EitherManifest::Real(m) => m,
+            _ => takopack_bail!("{} is a virtual manifest", cargo_toml.display()),
+        };
+        let package = Package::new(manifest.clone(), &cargo_toml);
+
+        let crate_file_dir = crate_file.parent().unwrap_or_else(|| Path::new("."));
+        let filename = crate_file
+            .file_name()
+            .and_then(OsStr::to_str)
+            .ok_or_else(|| format_err!("invalid crate file name: {}", crate_file.display()))?;
+        let crate_file = Filesystem::new(crate_file_dir.to_path_buf()).open_ro_shared(
+            filename,
+            &context,
+            "offline mirror crate file",
+        )?;
+
+        Ok(CrateInfo {
+            package,
+            manifest,
+            crate_file,
+            context,
+            source_id,
+            excludes: vec![],
+            includes: vec![],
+            test_mode: TestMode::default(),
+            download_url: None,
+            metadata_source: None,
+            yanked: false,
+            _mirror_work_dir: Some(mirror_work_dir),
         })
     }
 
@@ -322,33 +1185,110 @@ impl CrateInfo {
         update: bool,
     ) -> Result<CrateInfo> {
         let dep = crate_name_ver_to_dep(crate_name, version)?;
-        Self::new_from_dependency(&dep, update)
-    }
-
-    pub fn new_from_dependency(dependency: &Dependency, update: bool) -> Result<CrateInfo> {
-        let mut context = GlobalContext::default()?;
-        if !update {
-            // unfriendly API from cargo; we'll have to make do with it for
-            // now as there is no other alternative
-            context.configure(
-                0,
-                false,
-                None,
-                false,
-                false,
-                true, // offline
-                &context.target_dir()?.map(|x| x.into_path_unlocked()),
-                &[],
-                &[],
-            )?;
+        Self::new_from_dependency(&dep, update, &DownloadOptions::default())
+    }
+
+    pub fn new_from_dependency(
+        dependency: &Dependency,
+        update: bool,
+        download: &DownloadOptions,
+    ) -> Result<CrateInfo> {
+        let mut context = cargo_context()?;
+        download.configure(&mut context, !update)?;
+        let what = show_dep(dependency);
+
+        let (source_id, metadata_source, (package, manifest, crate_file)) =
+            if dependency.source_id().is_crates_io() {
+                let [(configured, configured_source), (other, other_source)] =
+                    crates_io_sources(&context)?;
+                // An unreachable index is not retried; the other one is
+                // tried instead
+                let fetched = download.run(
+                    &what,
+                    || Self::fetch_package(&context, dependency, configured, download),
+                    |e| is_transient(e) && index_reachable(configured),
+                );
+                match fetched {
+                    Ok(found) => (configured, Some(configured_source), found),
+                    Err(e) if !index_reachable(configured) => {
+                        takopack_warn!(
+                            "crates.io {} unreachable ({:#}), resolving {} through the {}",
+                            configured_source,
+                            e,
+                            show_dep(dependency),
+                            other_source
+                        );
+                        let mut dependency = dependency.clone();
+                        dependency.set_source_id(other);
+                        let found = download.run(
+                            &what,
+                            || Self::fetch_package(&context, &dependency, other, download),
+                            is_transient,
+                        )?;
+                        (other, Some(other_source), found)
+                    }
+                    Err(e) => return Err(e),
+                }
+            } else {
+                let source_id = dependency.source_id();
+                let found = download.run(
+                    &what,
+                    || Self::fetch_package(&context, dependency, source_id, download),
+                    is_transient,
+                )?;
+                (source_id, None, found)
+            };
+        if let Some(metadata_source) = metadata_source {
+            log::info!(
+                "Resolved {} {} through the {}",
+                package.name(),
+                package.version(),
+                metadata_source
+            );
         }
 
-        let source_id = SourceId::crates_io_maybe_sparse_http(&context)?;
-        let registry_name = format!(
-            "{}-{}",
-            source_id.url().host_str().unwrap_or(""),
-            cargo::util::hex::short_hash(&source_id)
-        );
+        let download_url = if source_id.is_crates_io() {
+            None
+        } else {
+            let checksum = manifest.summary().checksum();
+            match registry_dl_template(&context, source_id, &registry_cache_name(source_id)) {
+                Ok(dl) => Some(spec_download_url(&dl, &package.name(), checksum)),
+                Err(e) => {
+                    takopack_warn!(
+                        "Could not determine the download URL of {}: {:#}",
+                        source_id.url(),
+                        e
+                    );
+                    Some("FIXME".to_string())
+                }
+            }
+        };
+
+        Ok(CrateInfo {
+            package,
+            manifest,
+            crate_file,
+            context,
+            source_id,
+            excludes: vec![],
+            includes: vec![],
+            test_mode: TestMode::default(),
+            download_url,
+            metadata_source,
+            yanked: false,
+            _mirror_work_dir: None,
+        })
+    }
+
+    /// Download the crate matching `dependency` from the registry
+    /// `source_id`, whose index is cached as [`registry_cache_name`].
+    fn fetch_package(
+        context: &GlobalContext,
+        dependency: &Dependency,
+        source_id: SourceId,
+        download: &DownloadOptions,
+    ) -> Result<(Package, Manifest, FileLock)> {
+        let registry_name = registry_cache_name(source_id);
         let get_package_info = |context: &GlobalContext,
                                 possibly_yanked_ver: Option<&Version>|
          -> Result<_> {
@@ -373,8 +1313,7 @@ impl CrateInfo {
             }
             registry.lock_patches();
             let summaries = fetch_candidates(&mut registry, dependency)?;
-            drop(lock);
-            let pkgids = summaries.into_iter().map(|s| s.package_id()).collect_vec();
+            let pkgids = summaries.iter().map(|s| s.package_id()).collect_vec();
             let pkgid = pkgids.iter().max().ok_or_else(|| {
                 format_err!(
                     concat!(
@@ -384,6 +1323,32 @@ impl CrateInfo {
                     show_dep(dependency)
                 )
             })?;
+            let filename = format!("{}-{}.crate", pkgid.name(), pkgid.version());
+            let cached_file = context
+                .registry_cache_path()
+                .join(&registry_name)
+                .into_path_unlocked()
+                .join(&filename);
+            // checksum the index lists for the archive, the key of the
+            // shared crate cache
+            let checksum = summaries
+                .iter()
+                .find(|s| s.package_id() == *pkgid)
+                .and_then(|s| s.as_summary().checksum())
+                .map(str::to_string);
+            let crate_cache = match &checksum {
+                Some(_) if download.cache => shared_crate_cache(),
+                _ => None,
+            };
+            if let (Some(cache), Some(checksum)) = (&crate_cache, &checksum) {
+                let version = pkgid.version().to_string();
+                match cache.seed(&pkgid.name(), &version, checksum, &cached_file) {
+                    Ok(true) => log::info!("Using {} from the crate cache", filename),
+                    Ok(false) => {}
+                    Err(e) => takopack_warn!("could not use the crate cache: {:#}", e),
+                }
+            }
+            drop(lock);
             let pkgset = registry.get(pkgids.as_slice())?;
             let package = pkgset.get_one(*pkgid)?;
 
@@ -405,35 +1370,36 @@ impl CrateInfo {
                     )
                 }
             }
-            let filename = format!("{}-{}.crate", pkgid.name(), pkgid.version());
             let crate_file = context
                 .registry_cache_path()
                 .join(&registry_name)
                 .open_ro_shared(&filename, context, &filename)?;
+            // cargo only checks archives it downloads itself, not ones that
+            // were already in its cache
+            match &checksum {
+                Some(checksum) => verify_crate_checksum(crate_file.path(), checksum)?,
+                None => log::warn!("{} has no checksum in the index; not verified", pkgid),
+            }
+            if let (Some(cache), Some(checksum)) = (&crate_cache, &checksum) {
+                let version = pkgid.version().to_string();
+                if let Err(e) = cache.store(&pkgid.name(), &version, checksum, crate_file.path()) {
+                    takopack_warn!("could not add {} to the crate cache: {:#}", filename, e);
+                }
+            }
             Ok((package.clone(), manifest.clone(), crate_file))
         };
         // if update is false but the user never downloaded the crate then the
         // first call will error; re-try with online in that case
-        let (package, manifest, crate_file) = get_package_info(&context, None)
-            .or_else(|_| get_package_info(&GlobalContext::default()?, None))
+        get_package_info(context, None)
+            .or_else(|_| get_package_info(&download.online_context()?, None))
             .or_else(|err| {
                 let ver = ver_req_to_ver(dependency);
                 if ver.is_some() {
-                    get_package_info(&GlobalContext::default()?, ver.as_ref())
+                    get_package_info(&download.online_context()?, ver.as_ref())
                 } else {
                     Err(err)
                 }
-            })?;
-
-        Ok(CrateInfo {
-            package,
-            manifest,
-            crate_file,
-            context,
-            source_id,
-            excludes: vec![],
-            includes: vec![],
-        })
+            })
     }
 
     pub fn crate_name(&self) -> &'static str {
@@ -445,13 +1411,9 @@ impl CrateInfo {
         self.package_id().version()
     }
 
+    /// The compat version, see [`crate::version::compat_version`].
     pub fn semver(&self) -> String {
-        match *self.package_id().version() {
-            Version {
-                major: 0, minor, ..
-            } => format!("0.{}", minor),
-            Version { major, .. } => format!("{}", major),
-        }
+        crate::version::compat_version(self.package_id().version())
     }
 
     pub fn manifest(&self) -> &Manifest {
@@ -469,6 +1431,31 @@ impl CrateInfo {
         self.manifest.metadata()
     }
 
+    /// Spec Source: URL for crates that are not downloaded from crates.io.
+    pub fn download_url(&self) -> Option<&str> {
+        self.download_url.as_deref()
+    }
+
+    /// Use `url` as the spec Source: line, e.g. for crates built from git.
+    pub fn set_download_url(&mut self, url: &str) {
+        self.download_url = Some(url.to_string());
+    }
+
+    /// Which crates.io index, or the crates.io API, the crate was resolved
+    /// from; `None` for crates from other registries, mirrors or paths.
+    pub fn metadata_source(&self) -> Option<MetadataSource> {
+        self.metadata_source
+    }
+
+    /// Whether crates.io lists this version as yanked, see [`crate::yanked`].
+    pub fn is_yanked(&self) -> bool {
+        self.yanked
+    }
+
+    pub fn mark_yanked(&mut self) {
+        self.yanked = true;
+    }
+
     pub fn manifest_path(&self) -> &Path {
         self.package.manifest_path()
     }
@@ -477,6 +1464,10 @@ impl CrateInfo {
         self.manifest.rust_version().map(|v| v.to_string())
     }
 
+    pub fn edition(&self) -> String {
+        self.manifest.edition().to_string()
+    }
+
     pub fn targets(&self) -> &[Target] {
         self.manifest.targets()
     }
@@ -495,6 +1486,14 @@ impl CrateInfo {
         lib
     }
 
+    /// Whether the library target is a proc-macro (`proc-macro = true`).
+    pub fn is_proc_macro(&self) -> bool {
+        self.manifest
+            .targets()
+            .iter()
+            .any(|target| target.proc_macro())
+    }
+
     pub fn get_binary_targets(&self) -> Vec<&str> {
         let mut bins = Vec::new();
         for target in self.manifest.targets() {
@@ -533,23 +1532,12 @@ impl CrateInfo {
         // Seek to the beginning of the file
         file.seek(std::io::SeekFrom::Start(0))?;
 
-        let mut hasher = Sha256::new();
-        let mut buffer = [0u8; 8192];
-
-        loop {
-            let bytes_read = file.read(&mut buffer)?;
-            if bytes_read == 0 {
-                break;
-            }
-            hasher.update(&buffer[..bytes_read]);
-        }
-
-        let hash = hasher.finalize();
+        let hash = sha256_hex(file)?;
 
         // Reset file pointer to beginning for subsequent operations
         file.seek(std::io::SeekFrom::Start(0))?;
 
-        Ok(format!("{:x}", hash))
+        Ok(hash)
     }
 
     pub fn dependencies(&self) -> &[Dependency] {
@@ -650,6 +1638,11 @@ impl CrateInfo {
         self.includes = includes.cloned().unwrap_or_default();
     }
 
+    /// To be called before extract_crate.
+    pub fn set_test_mode(&mut self, test_mode: TestMode) {
+        self.test_mode = test_mode;
+    }
+
     pub fn filter_path(&self, path: &Path) -> std::result::Result<bool, String> {
         let top_level = path
             .ancestors()
@@ -684,7 +1677,7 @@ impl CrateInfo {
             if self.includes.iter().any(matches) {
                 takopack_info!("Suspicious file, on whitelist so ignored: {:?}", path);
                 Ok(false)
-            } else if testing_ignore_debpolv() {
+            } else if self.test_mode.ignore_policy_violations {
                 takopack_warn!("Suspicious file, ignoring as per override: {:?}", path);
                 Ok(false)
             } else {
@@ -698,8 +1691,15 @@ impl CrateInfo {
         }
     }
 
-    pub fn extract_crate(&mut self, path: &Path) -> Result<bool> {
-        let mut archive = Archive::new(GzDecoder::new(self.crate_file.file()));
+    /// Unpack the crate to `path`, within `limits`. Returns whether files
+    /// were left out.
+    pub fn extract_crate(&mut self, path: &Path, limits: &ExtractionLimits) -> Result<bool> {
+        use std::io::{Seek, SeekFrom};
+
+        // The archive may have been read before, e.g. by Self::lockfile
+        let mut file = self.crate_file.file();
+        file.seek(SeekFrom::Start(0))?;
+        let mut archive = Archive::new(GzDecoder::new(file));
         let parent = path
             .parent()
             .filter(|parent| !parent.as_os_str().is_empty())
@@ -710,16 +1710,24 @@ impl CrateInfo {
                 path.display()
             )
         })?;
-        let tempdir = tempfile::Builder::new()
-            .prefix("takopack")
-            .tempdir_in(parent)?;
+        let tempdir = tempfile::Builder::new().prefix("tp").tempdir_in(parent)?;
         let mut source_modified = false;
         let mut last_mtime = 0;
+        let mut budget = limits.budget();
         let mut err = vec![];
 
         for entry in archive.entries()? {
             let mut entry = entry?;
-            match self.filter_path(&(entry.path()?)) {
+            let path = match tarball::check_entry(&entry)? {
+                EntryCheck::Keep(path) => path,
+                EntryCheck::Ignore => continue,
+                EntryCheck::Skip(reason) => {
+                    tarball::warn_skipped(&entry, self.crate_file.path(), &reason);
+                    source_modified = true;
+                    continue;
+                }
+            };
+            match self.filter_path(&path) {
                 Err(e) => err.push(e),
                 Ok(r) => {
                     if r {
@@ -729,6 +1737,12 @@ impl CrateInfo {
                 }
             }
 
+            budget.admit(&entry).with_context(|| {
+                format!(
+                    "{} exceeds the extraction limits",
+                    self.crate_file.path().display()
+                )
+            })?;
             if !entry.unpack_in(tempdir.path())? {
                 takopack_bail!("Crate contained path traversals via '..'");
             }
@@ -855,23 +1869,74 @@ impl CrateInfo {
                     break;
                 }
             }
-            fs::write(path.join("Cargo.toml.orig"), orig_toml.as_bytes())?;
-            fs::remove_dir_all(path.join("target"))?;
-            source_modified = true;
-            // avoid lintian errors about package-contains-ancient-file
-            // TODO: do we want to do this for unmodified tarballs? it would
-            // force us to modify them, but otherwise we get that ugly warning
-            let last_mtime = FileTime::from_unix_time(last_mtime as i64, 0);
-            set_file_times(toml_path, last_mtime, last_mtime)?;
-            takopack_info!("Cargo.toml manually canonicalized!");
+            fs::write(path.join("Cargo.toml.orig"), orig_toml.as_bytes())?;
+            fs::remove_dir_all(path.join("target"))?;
+            source_modified = true;
+            // avoid lintian errors about package-contains-ancient-file
+            // TODO: do we want to do this for unmodified tarballs? it would
+            // force us to modify them, but otherwise we get that ugly warning
+            let last_mtime = FileTime::from_unix_time(last_mtime as i64, 0);
+            set_file_times(toml_path, last_mtime, last_mtime)?;
+            takopack_info!("Cargo.toml manually canonicalized!");
+        }
+        Ok(source_modified)
+    }
+
+    /// The Cargo.lock shipped in the .crate file, read straight from the
+    /// archive without extracting it. `None` when the crate ships none, or
+    /// when it does not lock this crate's dependencies.
+    pub fn embedded_lockfile(&self) -> Result<Option<DependencyGraph>> {
+        use std::io::{Seek, SeekFrom};
+
+        let pkgid = self.package_id();
+        let lock_path =
+            Path::new(&format!("{}-{}", pkgid.name(), pkgid.version())).join("Cargo.lock");
+        let mut file = self.crate_file.file();
+        file.seek(SeekFrom::Start(0))?;
+        let mut content = None;
+        for entry in Archive::new(GzDecoder::new(file)).entries()? {
+            let mut entry = entry?;
+            if entry.path()? == lock_path {
+                let mut text = String::new();
+                entry.read_to_string(&mut text)?;
+                content = Some(text);
+                break;
+            }
+        }
+
+        match content {
+            Some(content) if self.lockfile_is_usable(&content) => {
+                Ok(Some(parse_lockfile_str(&content)?))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn lockfile_is_usable(&self, content: &str) -> bool {
+        match lockfile_locks_crate(
+            content,
+            self.crate_name(),
+            self.version(),
+            self.dependencies(),
+        ) {
+            Ok(usable) => usable,
+            Err(e) => {
+                log::info!(
+                    "Ignoring the Cargo.lock shipped with {}: {:#}",
+                    self.package_id(),
+                    e
+                );
+                false
+            }
         }
-        Ok(source_modified)
     }
 
-    /// Generate Cargo.lock file in the extracted crate directory using cargo API
-    /// This is equivalent to running `cargo generate-lockfile`
-    /// Returns true if lockfile was successfully generated, false on failure (with warning logged)
-    pub fn generate_cargo_lock(&self, extract_path: &Path) -> Result<bool> {
+    /// Make sure the extracted crate at `extract_path` has a Cargo.lock. The
+    /// one shipped with the crate is kept when it locks the crate's
+    /// dependencies; otherwise, or with `regenerate`, one is generated like
+    /// `cargo generate-lockfile` would.
+    /// Returns true if a lockfile is in place, false on failure (with warning logged)
+    pub fn generate_cargo_lock(&self, extract_path: &Path, regenerate: bool) -> Result<bool> {
         let toml_path = extract_path.join("Cargo.toml");
 
         if !toml_path.exists() {
@@ -882,6 +1947,16 @@ impl CrateInfo {
             return Ok(false);
         }
 
+        let lock_path = extract_path.join("Cargo.lock");
+        if !regenerate {
+            if let Ok(content) = fs::read_to_string(&lock_path) {
+                if self.lockfile_is_usable(&content) {
+                    log::info!("Reusing the Cargo.lock shipped at {:?}", lock_path);
+                    return Ok(true);
+                }
+            }
+        }
+
         // Try to generate lockfile using cargo API
         match self._generate_lockfile_internal(&toml_path) {
             Ok(()) => {
@@ -898,6 +1973,24 @@ impl CrateInfo {
         }
     }
 
+    /// The Cargo.lock the crate is built with, as returned by
+    /// [`Self::generate_cargo_lock`] on a copy unpacked to a temporary
+    /// directory within `limits`.
+    pub fn lockfile(&mut self, limits: &ExtractionLimits) -> Result<String> {
+        let pkgid = self.package_id();
+        let tempdir = tempfile::Builder::new().prefix("takopack-lock").tempdir()?;
+        let path = tempdir
+            .path()
+            .join(format!("{}-{}", pkgid.name(), pkgid.version()));
+        self.extract_crate(&path, limits)?;
+        if !self.generate_cargo_lock(&path, false)? {
+            takopack_bail!("could not generate a Cargo.lock for {}", pkgid);
+        }
+        let lock_path = path.join("Cargo.lock");
+        fs::read_to_string(&lock_path)
+            .with_context(|| format!("Failed to read {}", lock_path.display()))
+    }
+
     /// Internal helper to generate lockfile using cargo API
     fn _generate_lockfile_internal(&self, toml_path: &Path) -> Result<()> {
         // Create a workspace from the Cargo.toml
@@ -911,12 +2004,79 @@ impl CrateInfo {
     }
 }
 
+/// Whether the Cargo.lock `content` can stand in for a freshly generated
+/// lockfile of the crate `name` `version`: the crate is its root, and it
+/// pins every required registry dependency in `deps` to a matching version.
+/// Dev-dependencies are not checked.
+pub fn lockfile_locks_crate(
+    content: &str,
+    name: &str,
+    version: &Version,
+    deps: &[Dependency],
+) -> Result<bool> {
+    use cargo::core::dependency::DepKind;
+
+    let Some(locked) = member_dependencies(content, name, version)? else {
+        log::info!("Cargo.lock is not the lockfile of {} {}", name, version);
+        return Ok(false);
+    };
+    Ok(deps
+        .iter()
+        .filter(|dep| {
+            dep.kind() != DepKind::Development
+                && !dep.is_optional()
+                && dep.source_id().is_registry()
+        })
+        .all(|dep| {
+            let is_locked = locked.iter().any(|(locked_name, locked_version)| {
+                locked_name == dep.package_name().as_str()
+                    && dep.version_req().matches(locked_version)
+            });
+            if !is_locked {
+                log::info!("Cargo.lock does not lock {} for {}", show_dep(dep), name);
+            }
+            is_locked
+        }))
+}
+
 /// Collect information about the dependency structure of features and
 /// their external crate dependencies, in a simple output format.
 pub fn all_dependencies_and_features(manifest: &Manifest) -> Result<CrateDepInfo> {
     all_dependencies_and_features_filtered(manifest, false)
 }
 
+/// The features of optional dependencies that each feature enables weakly,
+/// with `bar?/baz`: only when something else enables `bar`. They are left
+/// out of [`all_dependencies_and_features`]; each dependency here has just
+/// that feature, without default features.
+pub fn weak_dependency_features(manifest: &Manifest) -> BTreeMap<&'static str, Vec<Dependency>> {
+    let mut weak_features: BTreeMap<&'static str, Vec<Dependency>> = BTreeMap::new();
+    for (feature, values) in manifest.summary().features() {
+        for value in values {
+            let FeatureValue::DepFeature {
+                dep_name,
+                dep_feature,
+                weak: true,
+            } = value
+            else {
+                continue;
+            };
+            let deps = manifest.dependencies().iter().filter(|dep| {
+                dep.is_optional()
+                    && dep.name_in_toml() == *dep_name
+                    && dependency_is_runtime_candidate(dep, false)
+            });
+            for dep in deps {
+                let mut dep = dep.clone();
+                dep.set_features([*dep_feature]);
+                dep.set_default_features(false);
+                weak_features.entry(feature.as_str()).or_default().push(dep);
+            }
+        }
+    }
+    weak_features
+}
+
 /// Collect information about the dependency structure of features and
 /// their external crate dependencies, in a simple output format.
 /// If include_dev_dependencies is true, dev-dependencies will be included.
@@ -982,16 +2142,26 @@ pub fn all_dependencies_and_features_filtered(
                         feature
                     ),
                 },
-                // another package is a dependency
+                // a feature of another package, `bar/baz` or weak `bar?/baz`
                 DepFeature {
                     dep_name,
                     dep_feature,
-                    ..
+                    weak,
                 } => {
+                    // Like cargo, `bar/baz` also enables the implicit
+                    // feature of an optional `bar` unless `dep:bar` hides it
+                    if !*weak && manifest.summary().features().contains_key(dep_name) {
+                        feature_deps.push(dep_name.as_str());
+                    }
                     match deps_by_name.get(dep_name.as_str()) {
                         // unwrap is ok, valid Cargo.toml files must have this
                         Some(dd) => {
                             for &dep in dd {
+                                // A weak feature does not enable an optional
+                                // dependency, see weak_dependency_features
+                                if *weak && dep.is_optional() {
+                                    continue;
+                                }
                                 let mut dep = dep.clone();
                                 let mut features: Vec<InternedString> =
                                     vec![InternedString::new(dep_feature)];
@@ -1074,9 +2244,9 @@ pub fn dependency_is_runtime_candidate(dep: &Dependency, include_dev_dependencie
         return false;
     }
 
-    if is_special_rustc_workspace_crate(dep.package_name().as_str()) {
+    if ToolchainCrates::builtin().provides_dependency(dep) {
         takopack_warn!(
-            "Skipping special/internal Rust workspace dependency from runtime Requires: {}",
+            "Skipping dependency provided by the Rust toolchain from runtime Requires: {}",
             dep.package_name()
         );
         return false;
@@ -1096,37 +2266,146 @@ pub fn dependency_is_runtime_candidate(dep: &Dependency, include_dev_dependencie
     true
 }
 
+/// The Linux targets of the architectures packages are built for, by rpm
+/// architecture: the target triple and the cfgs only set for it.
+const LINUX_TARGETS: &[(&str, &str, &[&str])] = &[
+    (
+        "x86_64",
+        "x86_64-unknown-linux-gnu",
+        &[
+            "target_arch = \"x86_64\"",
+            "target_endian = \"little\"",
+            "target_feature = \"fxsr\"",
+            "target_feature = \"sse\"",
+            "target_feature = \"sse2\"",
+            "target_pointer_width = \"64\"",
+        ],
+    ),
+    (
+        "i686",
+        "i686-unknown-linux-gnu",
+        &[
+            "target_arch = \"x86\"",
+            "target_endian = \"little\"",
+            "target_feature = \"fxsr\"",
+            "target_feature = \"sse\"",
+            "target_feature = \"sse2\"",
+            "target_pointer_width = \"32\"",
+        ],
+    ),
+    (
+        "aarch64",
+        "aarch64-unknown-linux-gnu",
+        &[
+            "target_arch = \"aarch64\"",
+            "target_endian = \"little\"",
+            "target_feature = \"neon\"",
+            "target_pointer_width = \"64\"",
+        ],
+    ),
+    (
+        "riscv64",
+        "riscv64gc-unknown-linux-gnu",
+        &[
+            "target_arch = \"riscv64\"",
+            "target_endian = \"little\"",
+            "target_feature = \"a\"",
+            "target_feature = \"c\"",
+            "target_feature = \"d\"",
+            "target_feature = \"f\"",
+            "target_feature = \"m\"",
+            "target_pointer_width = \"64\"",
+        ],
+    ),
+    (
+        "ppc64le",
+        "powerpc64le-unknown-linux-gnu",
+        &[
+            "target_arch = \"powerpc64\"",
+            "target_endian = \"little\"",
+            "target_pointer_width = \"64\"",
+        ],
+    ),
+    (
+        "s390x",
+        "s390x-unknown-linux-gnu",
+        &[
+            "target_arch = \"s390x\"",
+            "target_endian = \"big\"",
+            "target_pointer_width = \"64\"",
+        ],
+    ),
+    (
+        "loongarch64",
+        "loongarch64-unknown-linux-gnu",
+        &[
+            "target_arch = \"loongarch64\"",
+            "target_endian = \"little\"",
+            "target_pointer_width = \"64\"",
+        ],
+    ),
+];
+
+/// The cfgs set for every target of [`LINUX_TARGETS`].
+const LINUX_CFGS: &[&str] = &[
+    "debug_assertions",
+    "panic = \"unwind\"",
+    "target_abi = \"\"",
+    "target_env = \"gnu\"",
+    "target_family = \"unix\"",
+    "target_has_atomic = \"16\"",
+    "target_has_atomic = \"32\"",
+    "target_has_atomic = \"64\"",
+    "target_has_atomic = \"8\"",
+    "target_has_atomic = \"ptr\"",
+    "target_os = \"linux\"",
+    "target_vendor = \"unknown\"",
+    "unix",
+];
+
 pub fn dependency_matches_openruyi_linux_target(dep: &Dependency) -> bool {
+    dependency_matches_linux_target(dep, "x86_64").unwrap_or(true)
+}
+
+/// Whether `dep` is built on Linux for the rpm architecture `arch`; `None`
+/// for an architecture not in [`LINUX_TARGETS`].
+pub fn dependency_matches_linux_target(dep: &Dependency, arch: &str) -> Option<bool> {
+    let (_, triple, arch_cfgs) = LINUX_TARGETS.iter().find(|(name, _, _)| *name == arch)?;
     let Some(platform) = dep.platform() else {
-        return true;
+        return Some(true);
     };
+    let target_cfgs = LINUX_CFGS
+        .iter()
+        .chain(arch_cfgs.iter())
+        .map(|cfg| cfg.parse().expect("built-in Linux cfg should parse"))
+        .collect::<Vec<_>>();
+    Some(platform.matches(triple, &target_cfgs))
+}
+
+/// Whether takopack knows the Linux target of the rpm architecture `arch`.
+pub fn is_known_linux_arch(arch: &str) -> bool {
+    LINUX_TARGETS.iter().any(|(name, _, _)| *name == arch)
+}
+
+/// The rpm architectures whose Linux target takopack knows.
+pub fn known_linux_arches() -> Vec<String> {
+    LINUX_TARGETS
+        .iter()
+        .map(|(arch, _, _)| arch.to_string())
+        .collect()
+}
 
-    let target_cfgs = [
-        "debug_assertions",
-        "panic = \"unwind\"",
-        "target_abi = \"\"",
-        "target_arch = \"x86_64\"",
-        "target_endian = \"little\"",
-        "target_env = \"gnu\"",
-        "target_family = \"unix\"",
-        "target_feature = \"fxsr\"",
-        "target_feature = \"sse\"",
-        "target_feature = \"sse2\"",
-        "target_has_atomic = \"16\"",
-        "target_has_atomic = \"32\"",
-        "target_has_atomic = \"64\"",
-        "target_has_atomic = \"8\"",
-        "target_has_atomic = \"ptr\"",
-        "target_os = \"linux\"",
-        "target_pointer_width = \"64\"",
-        "target_vendor = \"unknown\"",
-        "unix",
-    ]
-    .into_iter()
-    .map(|cfg| cfg.parse().expect("built-in Linux cfg should parse"))
-    .collect::<Vec<_>>();
-
-    platform.matches("x86_64-unknown-linux-gnu", &target_cfgs)
+/// For a dependency of `[target.'cfg(..)'.dependencies]`, the known
+/// architectures it is built for, possibly none; `None` for the others.
+pub fn dependency_target_arches(dep: &Dependency) -> Option<Vec<String>> {
+    dep.platform()?;
+    Some(
+        LINUX_TARGETS
+            .iter()
+            .filter(|(arch, _, _)| dependency_matches_linux_target(dep, arch) == Some(true))
+            .map(|(arch, _, _)| arch.to_string())
+            .collect(),
+    )
 }
 
 pub fn dependency_is_windows_only(dep: &Dependency) -> bool {
@@ -1140,13 +2419,6 @@ pub fn dependency_is_windows_only(dep: &Dependency) -> bool {
         || platform.contains("windows-gnu")
 }
 
-pub fn is_special_rustc_workspace_crate(crate_name: &str) -> bool {
-    matches!(
-        crate_name.replace('_', "-").as_str(),
-        "rustc-std-workspace-core" | "rustc-std-workspace-alloc" | "rustc-std-workspace-std"
-    )
-}
-
 fn dependency_is_filtered_runtime_dependency(
     manifest: &Manifest,
     dep_name: &str,
@@ -1167,6 +2439,95 @@ fn dependency_is_dev_dependency(manifest: &Manifest, dep_name: &str) -> bool {
         .any(|dep| dep.kind() == DepKind::Development && dep.name_in_toml().as_str() == dep_name)
 }
 
+/// Features of a crate turned on by a set of requested ones, and what they
+/// turn on in its dependencies.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FeatureActivation {
+    /// Enabled features, the requested ones included.
+    pub features: BTreeSet<String>,
+    /// Dependencies turned on by a feature, by name in Cargo.toml.
+    pub enabled_deps: BTreeSet<String>,
+    /// Features enabled on dependencies, by name in Cargo.toml.
+    pub dep_features: BTreeMap<String, BTreeSet<String>>,
+}
+
+/// Resolve the features `requested`, and `default` with `default`, against
+/// the feature table of `summary` the way cargo does for a single crate.
+/// `dep?/feature` enables the feature without turning the dependency on.
+/// Unknown features are ignored.
+pub fn activate_features<'a>(
+    summary: &Summary,
+    requested: impl IntoIterator<Item = &'a str>,
+    default: bool,
+) -> FeatureActivation {
+    let table = summary.features();
+    let mut activation = FeatureActivation::default();
+    let mut pending: Vec<String> = requested.into_iter().map(str::to_string).collect();
+    if default {
+        pending.push("default".to_string());
+    }
+    while let Some(feature) = pending.pop() {
+        let Some(values) = table.get(feature.as_str()) else {
+            continue;
+        };
+        if !activation.features.insert(feature) {
+            continue;
+        }
+        for value in values {
+            match value {
+                FeatureValue::Feature(name) => pending.push(name.to_string()),
+                FeatureValue::Dep { dep_name } => {
+                    activation.enabled_deps.insert(dep_name.to_string());
+                }
+                FeatureValue::DepFeature {
+                    dep_name,
+                    dep_feature,
+                    weak,
+                } => {
+                    if !weak {
+                        activation.enabled_deps.insert(dep_name.to_string());
+                        // the implicit feature of an optional dependency
+                        if table.contains_key(dep_name) {
+                            pending.push(dep_name.to_string());
+                        }
+                    }
+                    activation
+                        .dep_features
+                        .entry(dep_name.to_string())
+                        .or_default()
+                        .insert(dep_feature.to_string());
+                }
+            }
+        }
+    }
+    activation
+}
+
+/// Take the `[build-dependencies]` out of `features_with_deps`, leaving the
+/// features they came from in place, and return the ones the default
+/// features build with. Only the build script of the crate itself uses them,
+/// so they are `BuildRequires:` of the spec rather than `Requires:` of the
+/// feature packages.
+pub fn split_build_dependencies(features_with_deps: &mut CrateDepInfo) -> Result<Vec<Dependency>> {
+    use cargo::core::dependency::DepKind;
+
+    let mut build_deps: Vec<Dependency> = Vec::new();
+    for root in ["", "default"] {
+        if !features_with_deps.contains_key(root) {
+            continue;
+        }
+        for dep in transitive_deps(features_with_deps, root)?.1 {
+            if dep.kind() == DepKind::Build && !build_deps.contains(&dep) {
+                build_deps.push(dep);
+            }
+        }
+    }
+    for (_, deps) in features_with_deps.values_mut() {
+        deps.retain(|dep| dep.kind() != DepKind::Build);
+    }
+    Ok(build_deps)
+}
+
 /// Calculate all feature-dependencies and external-dependencies of a given
 /// feature, using the information previously generated by
 /// `all_dependencies_and_features`.
@@ -1215,13 +2576,19 @@ fn transitive_deps_impl<'a>(
 #[cfg(test)]
 mod tests {
     use super::{
-        all_dependencies_and_features, dependency_is_runtime_candidate,
-        dependency_matches_openruyi_linux_target,
+        activate_features, all_dependencies_and_features, dependency_is_runtime_candidate,
+        dependency_matches_linux_target, dependency_matches_openruyi_linux_target,
+        dependency_target_arches, fill_dl_template, is_transient, lockfile_locks_crate,
+        parse_api_versions, select_index_version, sparse_index_path, spec_download_url,
+        split_build_dependencies, verify_crate_checksum, weak_dependency_features, CrateInfo,
+        CrateRegistry, DownloadOptions, MetadataSource, SparseIndex, LINUX_TARGETS,
     };
     use cargo::core::{dependency::DepKind, Dependency, EitherManifest, SourceId};
+    use cargo::util::errors::HttpNotSuccessful;
     use cargo::util::toml::read_manifest;
     use cargo::GlobalContext;
     use std::fs;
+    use std::io;
 
     fn manifest_from_toml(toml: &str) -> cargo::core::Manifest {
         let temp = tempfile::tempdir().unwrap();
@@ -1307,6 +2674,168 @@ use-build = ["dep:optional-build"]
         assert_eq!(vec!["optional-build"], optional_build_names);
     }
 
+    #[test]
+    fn build_dependencies_split_from_the_feature_graph() {
+        let manifest = manifest_from_toml(
+            r#"
+[package]
+name = "build-split"
+version = "1.0.0"
+
+[dependencies]
+normal = "1"
+
+[build-dependencies]
+cc = "1"
+optional-build = { version = "1", optional = true }
+pkg-config = { version = "0.3", optional = true }
+
+[features]
+default = ["dep:pkg-config"]
+use-build = ["dep:optional-build"]
+"#,
+        );
+
+        let mut features = all_dependencies_and_features(&manifest).unwrap();
+        let build_deps = split_build_dependencies(&mut features).unwrap();
+        assert_eq!(vec!["cc", "pkg-config"], dep_names(&build_deps));
+        assert_eq!(vec!["normal"], dep_names(&features.get("").unwrap().1));
+        assert!(features.get("use-build").unwrap().1.is_empty());
+    }
+
+    #[test]
+    fn selected_features_enable_optional_dependencies() {
+        let manifest = manifest_from_toml(
+            r#"
+[package]
+name = "feature-fixture"
+version = "1.0.0"
+edition = "2021"
+
+[dependencies]
+serde = { version = "1", optional = true }
+log = { version = "0.4", optional = true }
+regex = { version = "1", optional = true }
+memchr = "2"
+
+[features]
+default = ["std"]
+std = ["serde/std", "memchr/std", "regex?/unicode"]
+logging = ["dep:log"]
+"#,
+        );
+        let summary = manifest.summary();
+
+        let default = activate_features(summary, [], true);
+        assert!(default.features.contains("std"));
+        assert_eq!(
+            default.enabled_deps.iter().collect::<Vec<_>>(),
+            vec!["memchr", "serde"]
+        );
+        assert!(default.dep_features["serde"].contains("std"));
+        assert!(default.dep_features["regex"].contains("unicode"));
+
+        let logging = activate_features(summary, ["logging", "missing"], false);
+        assert_eq!(logging.enabled_deps.iter().collect::<Vec<_>>(), vec!["log"]);
+        assert!(activate_features(summary, [], false)
+            .enabled_deps
+            .is_empty());
+    }
+
+    #[test]
+    fn namespaced_and_weak_features_follow_cargo() {
+        // The feature tables of clap 4.5 and serde_with 3.x, trimmed
+        let clap = manifest_from_toml(
+            r#"
+[package]
+name = "clap"
+version = "4.5.4"
+edition = "2021"
+
+[dependencies]
+clap_builder = { version = "=4.5.2", default-features = false }
+clap_derive = { version = "=4.5.4", optional = true }
+
+[features]
+default = ["std", "color"]
+std = ["clap_builder/std"]
+color = ["clap_builder/color"]
+debug = ["clap_builder/debug", "clap_derive?/debug"]
+derive = ["dep:clap_derive"]
+unstable-doc = ["clap_builder/unstable-doc", "derive"]
+"#,
+        );
+        let features = all_dependencies_and_features(&clap).unwrap();
+        // `dep:` hides the implicit feature
+        assert!(!features.contains_key("clap_derive"));
+        assert_eq!(dep_names(&features["derive"].1), vec!["clap_derive"]);
+        assert_eq!(features["unstable-doc"].0, vec!["derive"]);
+        let (debug_features, debug_deps) = &features["debug"];
+        assert_eq!(debug_features, &vec![""]);
+        assert_eq!(dep_names(debug_deps), vec!["clap_builder"]);
+        assert!(!debug_deps[0].uses_default_features());
+        let weak = weak_dependency_features(&clap);
+        assert_eq!(weak.keys().copied().collect::<Vec<_>>(), vec!["debug"]);
+        assert_eq!(dep_names(&weak["debug"]), vec!["clap_derive"]);
+        assert_eq!(weak["debug"][0].features()[0].as_str(), "debug");
+
+        let serde_with = manifest_from_toml(
+            r#"
+[package]
+name = "serde_with"
+version = "3.8.1"
+edition = "2021"
+
+[dependencies]
+base64 = { version = "0.22.1", optional = true, default-features = false }
+chrono_0_4 = { package = "chrono", version = "0.4.20", optional = true, default-features = false, features = ["serde"] }
+hex = { version = "0.4.3", optional = true, default-features = false }
+serde = { version = "1.0.152", default-features = false, features = ["derive"] }
+serde_json = { version = "1.0.45", optional = true, default-features = false }
+
+[features]
+default = ["std"]
+alloc = ["serde/alloc", "base64?/alloc", "chrono_0_4?/alloc", "hex?/alloc", "serde_json?/alloc"]
+std = ["alloc", "serde/std", "chrono_0_4?/clock", "chrono_0_4?/std"]
+base64 = ["dep:base64", "alloc"]
+chrono_0_4 = ["dep:chrono_0_4"]
+hex = ["dep:hex", "alloc"]
+json = ["dep:serde_json", "alloc"]
+"#,
+        );
+        let features = all_dependencies_and_features(&serde_with).unwrap();
+        // Weak features turn no optional dependency on
+        assert_eq!(dep_names(&features["alloc"].1), vec!["serde"]);
+        assert_eq!(dep_names(&features["std"].1), vec!["serde"]);
+        assert_eq!(dep_names(&features["hex"].1), vec!["hex"]);
+        assert_eq!(features["hex"].0, vec!["alloc"]);
+        let weak = weak_dependency_features(&serde_with);
+        assert_eq!(
+            dep_names(&weak["alloc"]),
+            vec!["base64", "chrono", "hex", "serde_json"]
+        );
+        assert_eq!(dep_names(&weak["std"]), vec!["chrono", "chrono"]);
+
+        // Without `dep:`, `serde/std` also enables the implicit feature `serde`
+        let implicit = manifest_from_toml(
+            r#"
+[package]
+name = "implicit"
+version = "1.0.0"
+edition = "2021"
+
+[dependencies]
+serde = { version = "1", optional = true }
+
+[features]
+std = ["serde/std"]
+"#,
+        );
+        let features = all_dependencies_and_features(&implicit).unwrap();
+        assert_eq!(features["std"].0, vec!["serde"]);
+        assert_eq!(dep_names(&features["serde"].1), vec!["serde"]);
+    }
+
     #[test]
     fn build_dependencies_are_provider_metadata_candidates_by_default() {
         let manifest = manifest_from_toml(
@@ -1346,6 +2875,28 @@ cc = "1"
         assert!(dependency_is_runtime_candidate(&unix_dep, false));
         assert!(dependency_is_runtime_candidate(&macos_dep, false));
         assert!(dependency_is_runtime_candidate(&windows_dep, false));
+
+        let mut x86_dep = test_dep("x86-only", "1");
+        x86_dep.set_platform(Some(
+            r#"cfg(any(target_arch = "x86", target_arch = "x86_64"))"#
+                .parse()
+                .unwrap(),
+        ));
+        assert_eq!(
+            dependency_target_arches(&x86_dep).unwrap(),
+            ["x86_64", "i686"]
+        );
+        assert_eq!(dependency_target_arches(&windows_dep), Some(vec![]));
+        assert_eq!(
+            dependency_target_arches(&unix_dep).unwrap().len(),
+            LINUX_TARGETS.len()
+        );
+        assert_eq!(dependency_target_arches(&test_dep("plain", "1")), None);
+        assert_eq!(
+            dependency_matches_linux_target(&x86_dep, "aarch64"),
+            Some(false)
+        );
+        assert_eq!(dependency_matches_linux_target(&x86_dep, "m68k"), None);
     }
 
     #[test]
@@ -1370,4 +2921,301 @@ proptest = "1"
         assert!(!dependency_is_runtime_candidate(dev_dep, false));
         assert!(dependency_is_runtime_candidate(dev_dep, true));
     }
+
+    #[test]
+    fn downloads_are_retried_with_doubling_backoff() {
+        let retry = DownloadOptions::default();
+        let delays: Vec<u64> = (1..=4).map(|n| retry.delay(n).as_secs()).collect();
+        assert_eq!(delays, vec![2, 4, 8, 16]);
+
+        let retry = DownloadOptions {
+            retries: 2,
+            backoff: 0,
+            ..DownloadOptions::default()
+        };
+        let mut attempts = 0;
+        let result = retry.run(
+            "flaky 1",
+            || {
+                attempts += 1;
+                if attempts < 3 {
+                    Err(anyhow::format_err!("HTTP 503"))
+                } else {
+                    Ok(attempts)
+                }
+            },
+            |_| true,
+        );
+        assert_eq!(result.unwrap(), 3);
+
+        attempts = 0;
+        let result: anyhow::Result<()> = retry.run(
+            "missing 1",
+            || {
+                attempts += 1;
+                Err(anyhow::format_err!("not found"))
+            },
+            |_| false,
+        );
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn transient_errors_are_found_in_the_error_chain() {
+        let status = |code| HttpNotSuccessful {
+            code,
+            url: "https://index.crates.io/se/rd/serde".to_string(),
+            ip: None,
+            body: Vec::new(),
+            headers: Vec::new(),
+        };
+        assert!(is_transient(&anyhow::Error::new(status(503))));
+        assert!(is_transient(&anyhow::Error::new(status(429))));
+        assert!(!is_transient(&anyhow::Error::new(status(404))));
+
+        let reset = io::Error::new(io::ErrorKind::ConnectionReset, "reset by peer");
+        assert!(is_transient(
+            &anyhow::Error::new(reset).context("failed to download serde 1.0.0")
+        ));
+        let timeout = io::Error::new(io::ErrorKind::TimedOut, "timed out");
+        assert!(is_transient(&anyhow::Error::new(timeout)));
+        let missing = io::Error::new(io::ErrorKind::NotFound, "no such file");
+        assert!(!is_transient(&anyhow::Error::new(missing)));
+
+        assert!(!is_transient(&anyhow::format_err!("checksum mismatch")));
+    }
+
+    #[test]
+    fn spec_download_url_fills_registry_template() {
+        assert_eq!(
+            spec_download_url("https://dl.example.com/api/v1/crates", "serde", None),
+            "https://dl.example.com/api/v1/crates/%{crate_name}/%{full_version}/download#/%{name}-%{version}.tar.gz"
+        );
+        assert_eq!(
+            spec_download_url(
+                "https://files.example.com/{prefix}/{lowerprefix}/{crate}/{crate}-{version}.crate",
+                "MyCrate",
+                Some("abc"),
+            ),
+            "https://files.example.com/My/Cr/my/cr/%{crate_name}/%{crate_name}-%{full_version}.crate#/%{name}-%{version}.tar.gz"
+        );
+        assert_eq!(
+            spec_download_url(
+                "https://x.invalid/{prefix}/{sha256-checksum}",
+                "abc",
+                Some("f00")
+            ),
+            "https://x.invalid/3/a/f00#/%{name}-%{version}.tar.gz"
+        );
+        assert_eq!(
+            fill_dl_template(
+                "https://mirror.example.com/crates",
+                "log",
+                "0.4.27",
+                "log",
+                ""
+            ),
+            "https://mirror.example.com/crates/log/0.4.27/download"
+        );
+    }
+
+    #[test]
+    fn registry_args_are_mutually_exclusive() {
+        assert_eq!(
+            CrateRegistry::from_args(None, None).unwrap(),
+            CrateRegistry::CratesIo
+        );
+        assert_eq!(
+            CrateRegistry::from_args(Some("kellnr"), None).unwrap(),
+            CrateRegistry::Named("kellnr".to_string())
+        );
+        assert!(CrateRegistry::from_args(Some("kellnr"), Some("sparse+https://x/")).is_err());
+    }
+
+    #[test]
+    fn sparse_index_paths_follow_cargo_layout() {
+        assert_eq!(sparse_index_path("a"), "1/a");
+        assert_eq!(sparse_index_path("cc"), "2/cc");
+        assert_eq!(sparse_index_path("syn"), "3/s/syn");
+        assert_eq!(sparse_index_path("Serde_JSON"), "se/rd/serde_json");
+    }
+
+    #[test]
+    fn sparse_index_resolves_from_cache_and_skips_yanked() {
+        let cache = tempfile::tempdir().unwrap();
+        let entry = cache.path().join("de/mo/demo");
+        std::fs::create_dir_all(entry.parent().unwrap()).unwrap();
+        std::fs::write(
+            &entry,
+            concat!(
+                r#"{"name":"demo","vers":"1.0.0","deps":[],"cksum":"a","features":{},"yanked":false}"#,
+                "\n",
+                r#"{"name":"demo","vers":"1.2.0","deps":[],"cksum":"b","features":{},"yanked":false}"#,
+                "\n",
+                r#"{"name":"demo","vers":"1.3.0","deps":[],"cksum":"c","features":{},"yanked":true}"#,
+                "\n",
+                r#"{"name":"demo","vers":"2.0.0-rc.1","deps":[],"cksum":"d","features":{},"yanked":false}"#,
+                "\n",
+            ),
+        )
+        .unwrap();
+        // an unreachable index falls back to the cached entry
+        let index = SparseIndex::new("http://127.0.0.1:9", Some(cache.path().to_path_buf()));
+
+        assert_eq!(
+            index.resolve("demo", "").unwrap().version.to_string(),
+            "1.2.0"
+        );
+        assert_eq!(
+            index.resolve("demo", "1.0").unwrap().version.to_string(),
+            "1.2.0"
+        );
+        assert_eq!(
+            index.resolve("demo", "=1.0.0").unwrap().version.to_string(),
+            "1.0.0"
+        );
+        let err = index.resolve("demo", "=1.3.0").unwrap_err();
+        assert!(err.to_string().contains("yanked"));
+        assert_eq!(
+            index.is_yanked("demo", &"1.3.0".parse().unwrap()).unwrap(),
+            Some(true)
+        );
+        assert!(index.resolve("demo", "3").is_err());
+    }
+
+    #[test]
+    fn api_versions_stand_in_for_index_entries() {
+        let versions = parse_api_versions(
+            "demo",
+            r#"{"versions":[
+                {"num":"1.3.0","checksum":"c","yanked":true,"created_at":"2024-01-01T00:00:00Z"},
+                {"num":"1.2.0","checksum":"b","yanked":false},
+                {"num":"1.0.0"}
+            ],"meta":{"total":3}}"#,
+        )
+        .unwrap();
+        assert_eq!(versions.len(), 3);
+        assert!(versions
+            .iter()
+            .all(|v| v.name == "demo" && v.deps.is_empty()));
+        assert_eq!(versions[1].cksum, "b");
+        let found = select_index_version("demo", versions, "1").unwrap();
+        assert_eq!(found.version.to_string(), "1.2.0");
+        assert_eq!(MetadataSource::Api.to_string(), "crates.io API");
+    }
+
+    #[test]
+    fn shipped_lockfile_is_reused_only_when_it_locks_the_crate() {
+        let manifest = manifest_from_toml(
+            r#"
+[package]
+name = "app"
+version = "0.3.1"
+edition = "2021"
+
+[dependencies]
+serde = "1.0.100"
+log = { version = "0.4", optional = true }
+
+[dev-dependencies]
+proptest = "1"
+"#,
+        );
+        let lockfile = |root: &str, serde: &str| {
+            format!(
+                r#"
+version = 3
+
+[[package]]
+name = "app"
+version = "{root}"
+dependencies = ["serde"]
+
+[[package]]
+name = "serde"
+version = "{serde}"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+"#
+            )
+        };
+        let locks = |root: &str, serde: &str| {
+            lockfile_locks_crate(
+                &lockfile(root, serde),
+                "app",
+                &"0.3.1".parse().unwrap(),
+                manifest.dependencies(),
+            )
+            .unwrap()
+        };
+
+        // optional and dev dependencies need not be locked
+        assert!(locks("0.3.1", "1.0.200"));
+        assert!(!locks("0.3.0", "1.0.200"));
+        assert!(!locks("0.3.1", "1.0.99"));
+    }
+
+    #[test]
+    fn corrupt_archives_fail_the_checksum() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("log-0.4.22.crate");
+        fs::write(&path, b"hello").unwrap();
+        let hello = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+        verify_crate_checksum(&path, hello).unwrap();
+        verify_crate_checksum(&path, &hello.to_uppercase()).unwrap();
+
+        fs::write(&path, b"hellp").unwrap();
+        let err = verify_crate_checksum(&path, hello).unwrap_err().to_string();
+        assert!(err.starts_with("integrity error: "), "{}", err);
+        assert!(err.contains(hello), "{}", err);
+    }
+
+    #[test]
+    fn mirror_crates_are_unpacked_per_crate_info() {
+        let mirror = tempfile::tempdir().unwrap();
+        let dir = mirror.path().join("demo-1.0.0");
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("src/lib.rs"), "").unwrap();
+        fs::write(
+            dir.join("Cargo.toml"),
+            "[package]\nname = \"demo\"\nversion = \"1.0.0\"\n",
+        )
+        .unwrap();
+
+        // Dropping one does not remove what the other was packed into
+        let first = CrateInfo::new_from_mirror("demo", None, mirror.path()).unwrap();
+        let second = CrateInfo::new_from_mirror("demo", None, mirror.path()).unwrap();
+        assert_ne!(first.crate_file().path(), second.crate_file().path());
+        let first_file = first.crate_file().path().to_path_buf();
+        drop(first);
+        assert!(!first_file.exists());
+        assert!(second.crate_file().path().is_file());
+    }
+
+    #[test]
+    fn proc_macro_crates_are_detected_from_the_manifest() {
+        let mirror = tempfile::tempdir().unwrap();
+        for (name, lib) in [
+            ("serde_derive", "[lib]\nproc-macro = true\n"),
+            ("demo-macros", ""),
+        ] {
+            let dir = mirror.path().join(format!("{}-1.0.0", name));
+            fs::create_dir_all(dir.join("src")).unwrap();
+            fs::write(dir.join("src/lib.rs"), "").unwrap();
+            fs::write(
+                dir.join("Cargo.toml"),
+                format!(
+                    "[package]\nname = \"{}\"\nversion = \"1.0.0\"\n{}",
+                    name, lib
+                ),
+            )
+            .unwrap();
+        }
+
+        let derive = CrateInfo::new_from_mirror("serde_derive", None, mirror.path()).unwrap();
+        assert!(derive.is_proc_macro());
+        // The name alone does not make a crate a proc-macro
+        let macros = CrateInfo::new_from_mirror("demo-macros", None, mirror.path()).unwrap();
+        assert!(!macros.is_proc_macro());
+    }
 }
@@ -0,0 +1,358 @@
+//! Which dependencies `takopack cargo vendor` descends into.
+//!
+//! Every dependency edge met during recursive packaging is put to the
+//! packager's filters in order, and the first one with an opinion decides.
+//! When none has one, [`DefaultFilter`] applies the built-in heuristics.
+//! Filters are either written against [`DependencyFilter`] (closures work
+//! too) or configured as `[[dependency_filters]]` rules in takopack.toml:
+//!
+//! ```toml
+//! [[dependency_filters]]
+//! crate = "windows-*"
+//! action = "skip"
+//!
+//! # follow dev-dependencies, but only near the root
+//! [[dependency_filters]]
+//! kind = "dev"
+//! max_depth = 3
+//! action = "follow"
+//! ```
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::errors::*;
+use crate::toolchain_crates::ToolchainCrates;
+
+/// Section of Cargo.toml a dependency comes from.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EdgeKind {
+    Normal,
+    Build,
+    Dev,
+}
+
+impl fmt::Display for EdgeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            EdgeKind::Normal => "normal",
+            EdgeKind::Build => "build",
+            EdgeKind::Dev => "dev",
+        })
+    }
+}
+
+/// A dependency of a crate being packaged.
+#[derive(Debug, Clone, Copy)]
+pub struct Edge<'a> {
+    /// Crate declaring the dependency.
+    pub parent: &'a str,
+    /// Real name of the dependency, after renames.
+    pub crate_name: &'a str,
+    /// Version requirement; `None` for `*`.
+    pub req: Option<&'a str>,
+    pub kind: EdgeKind,
+    /// Optional, and not enabled by the features selected for the parent
+    /// (its default features unless its dependents ask for others).
+    pub optional: bool,
+    /// Distance of the dependency from the root crate, which is at 0.
+    pub depth: usize,
+}
+
+/// What to do with a dependency.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    /// Package it and descend into its dependencies.
+    Follow,
+    /// Leave it out; the reason is shown to the user and recorded in the
+    /// run's skip report.
+    Skip(SkipReason),
+}
+
+/// Why a recursive run left a crate out.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    DevDependency,
+    /// Optional, and not enabled by the selected features
+    OptionalDependency,
+    /// Provided by the Rust toolchain, see [`crate::toolchain_crates`]
+    Toolchain,
+    /// Matched the `[[dependency_filters]]` rule with this number,
+    /// counting from 1
+    FilterRule(usize),
+    /// Listed with `--skip` or in a `--skip-file`
+    SkipList,
+    /// The distro repositories have this version of it
+    InRepository(String),
+    /// Not pinned by the root crate's Cargo.lock in a `--locked` run
+    NotInLockfile,
+    /// Deeper than `--max-depth`
+    MaxDepth,
+    /// Reached after `--max-crates` crates were attempted
+    MaxCrates,
+    /// The version is yanked from crates.io and `--allow-yanked` was not
+    /// given
+    Yanked,
+    /// Given by a custom [`DependencyFilter`]
+    Other(String),
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkipReason::DevDependency => f.write_str("dev dependency"),
+            SkipReason::OptionalDependency => f.write_str("optional dependency"),
+            SkipReason::Toolchain => f.write_str("crate provided by the Rust toolchain"),
+            SkipReason::FilterRule(i) => write!(f, "dependency (filter rule {})", i),
+            SkipReason::SkipList => f.write_str("skip list"),
+            SkipReason::InRepository(version) => {
+                write!(f, "{} is in the distro repositories", version)
+            }
+            SkipReason::NotInLockfile => f.write_str("not in Cargo.lock"),
+            SkipReason::MaxDepth => f.write_str("--max-depth reached"),
+            SkipReason::MaxCrates => f.write_str("--max-crates reached"),
+            SkipReason::Yanked => f.write_str("yanked from crates.io"),
+            SkipReason::Other(reason) => f.write_str(reason),
+        }
+    }
+}
+
+impl From<&str> for SkipReason {
+    fn from(reason: &str) -> Self {
+        SkipReason::Other(reason.to_string())
+    }
+}
+
+/// A hook deciding which dependencies recursive packaging follows.
+pub trait DependencyFilter {
+    /// The decision for `edge`, or `None` to leave it to the next filter.
+    fn decide(&self, edge: &Edge<'_>) -> Option<Decision>;
+}
+
+impl<F> DependencyFilter for F
+where
+    F: Fn(&Edge<'_>) -> Option<Decision>,
+{
+    fn decide(&self, edge: &Edge<'_>) -> Option<Decision> {
+        self(edge)
+    }
+}
+
+/// Decide `edge` with the first of `filters` that has an opinion, falling
+/// back to [`DefaultFilter`].
+pub fn decide(filters: &[Box<dyn DependencyFilter>], edge: &Edge<'_>) -> Decision {
+    filters
+        .iter()
+        .find_map(|filter| filter.decide(edge))
+        .or_else(|| DefaultFilter.decide(edge))
+        .unwrap_or(Decision::Follow)
+}
+
+/// The built-in heuristics: only required normal and build dependencies are
+/// followed, minus the built-in [`ToolchainCrates`]. Proc-macro crates are
+/// followed like any other dependency.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultFilter;
+
+impl DependencyFilter for DefaultFilter {
+    fn decide(&self, edge: &Edge<'_>) -> Option<Decision> {
+        if edge.kind == EdgeKind::Dev {
+            return Some(Decision::Skip(SkipReason::DevDependency));
+        }
+        if let Some(decision) = ToolchainCrates::builtin().decide(edge) {
+            return Some(decision);
+        }
+        if edge.optional {
+            return Some(Decision::Skip(SkipReason::OptionalDependency));
+        }
+        Some(Decision::Follow)
+    }
+}
+
+/// What a matching [`FilterRule`] does.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterAction {
+    Follow,
+    Skip,
+}
+
+/// A `[[dependency_filters]]` entry. It matches an edge when every
+/// condition given holds; a rule without conditions matches everything.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct FilterRule {
+    /// Glob over the crate name; `-` and `_` are treated alike.
+    #[serde(rename = "crate")]
+    pub crate_pattern: Option<String>,
+    pub kind: Option<EdgeKind>,
+    pub optional: Option<bool>,
+    pub min_depth: Option<usize>,
+    pub max_depth: Option<usize>,
+    pub action: FilterAction,
+}
+
+/// Configured rules, tried in order.
+#[derive(Debug, Clone)]
+pub struct RuleFilter {
+    rules: Vec<(FilterRule, Option<glob::Pattern>)>,
+}
+
+impl RuleFilter {
+    pub fn new(rules: Vec<FilterRule>) -> Result<Self> {
+        let rules = rules
+            .into_iter()
+            .map(|rule| {
+                let pattern = rule
+                    .crate_pattern
+                    .as_deref()
+                    .map(|p| {
+                        glob::Pattern::new(&p.replace('_', "-"))
+                            .map_err(|e| format_err!("invalid crate pattern {:?}: {}", p, e))
+                    })
+                    .transpose()?;
+                Ok((rule, pattern))
+            })
+            .collect::<Result<_>>()?;
+        Ok(RuleFilter { rules })
+    }
+}
+
+impl DependencyFilter for RuleFilter {
+    fn decide(&self, edge: &Edge<'_>) -> Option<Decision> {
+        let name = edge.crate_name.replace('_', "-");
+        let (i, (rule, _)) = self.rules.iter().enumerate().find(|(_, (rule, pattern))| {
+            pattern.iter().all(|p| p.matches(&name))
+                && rule.kind.iter().all(|&kind| kind == edge.kind)
+                && rule
+                    .optional
+                    .iter()
+                    .all(|&optional| optional == edge.optional)
+                && rule.min_depth.iter().all(|&min| edge.depth >= min)
+                && rule.max_depth.iter().all(|&max| edge.depth <= max)
+        })?;
+        Some(match rule.action {
+            FilterAction::Follow => Decision::Follow,
+            FilterAction::Skip => Decision::Skip(SkipReason::FilterRule(i + 1)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decide, Decision, DependencyFilter, Edge, EdgeKind, RuleFilter, SkipReason};
+    use crate::config::Config;
+
+    fn edge(crate_name: &str, kind: EdgeKind, depth: usize) -> Edge<'_> {
+        Edge {
+            parent: "app",
+            crate_name,
+            req: Some("^1"),
+            kind,
+            optional: false,
+            depth,
+        }
+    }
+
+    fn skipped(decision: Decision) -> bool {
+        matches!(decision, Decision::Skip(_))
+    }
+
+    #[test]
+    fn default_heuristics_apply_without_filters() {
+        let filters: Vec<Box<dyn DependencyFilter>> = Vec::new();
+        assert_eq!(
+            decide(&filters, &edge("serde", EdgeKind::Normal, 1)),
+            Decision::Follow
+        );
+        assert_eq!(
+            decide(&filters, &edge("proptest", EdgeKind::Dev, 1)),
+            Decision::Skip(SkipReason::DevDependency)
+        );
+        assert_eq!(
+            decide(&filters, &edge("serde_derive", EdgeKind::Normal, 1)),
+            Decision::Follow
+        );
+        assert!(skipped(decide(
+            &filters,
+            &edge("compiler_builtins", EdgeKind::Normal, 1)
+        )));
+        let optional = Edge {
+            optional: true,
+            ..edge("log", EdgeKind::Normal, 1)
+        };
+        assert!(skipped(decide(&filters, &optional)));
+    }
+
+    #[test]
+    fn configured_rules_come_before_the_defaults() {
+        let config: Config = toml::from_str(
+            r#"
+[[dependency_filters]]
+crate = "windows-*"
+action = "skip"
+
+[[dependency_filters]]
+kind = "dev"
+max_depth = 2
+action = "follow"
+
+[[dependency_filters]]
+crate = "my_derive"
+action = "follow"
+"#,
+        )
+        .unwrap();
+        let filters: Vec<Box<dyn DependencyFilter>> = vec![Box::new(
+            RuleFilter::new(config.dependency_filters).unwrap(),
+        )];
+
+        assert_eq!(
+            decide(&filters, &edge("windows_sys", EdgeKind::Normal, 1)),
+            Decision::Skip(SkipReason::FilterRule(1))
+        );
+        assert_eq!(
+            decide(&filters, &edge("proptest", EdgeKind::Dev, 2)),
+            Decision::Follow
+        );
+        assert!(skipped(decide(
+            &filters,
+            &edge("proptest", EdgeKind::Dev, 3)
+        )));
+        assert_eq!(
+            decide(&filters, &edge("my-derive", EdgeKind::Normal, 1)),
+            Decision::Follow
+        );
+        assert_eq!(
+            decide(&filters, &edge("libc", EdgeKind::Normal, 5)),
+            Decision::Follow
+        );
+    }
+
+    #[test]
+    fn closures_are_filters() {
+        let shallow =
+            |edge: &Edge<'_>| (edge.depth > 1).then(|| Decision::Skip("deep dependency".into()));
+        let filters: Vec<Box<dyn DependencyFilter>> = vec![Box::new(shallow)];
+        assert_eq!(
+            decide(&filters, &edge("libc", EdgeKind::Normal, 1)),
+            Decision::Follow
+        );
+        assert_eq!(
+            decide(&filters, &edge("libc", EdgeKind::Normal, 2)),
+            Decision::Skip(SkipReason::Other("deep dependency".to_string()))
+        );
+        assert!(RuleFilter::new(vec![super::FilterRule {
+            crate_pattern: Some("[".to_string()),
+            kind: None,
+            optional: None,
+            min_depth: None,
+            max_depth: None,
+            action: super::FilterAction::Skip,
+        }])
+        .is_err());
+    }
+}
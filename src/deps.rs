@@ -0,0 +1,247 @@
+//! The dependencies of a crate with a given set of features.
+//!
+//! When an update breaks a build, the first question is usually what a
+//! feature pulls in. `cargo deps` reads a Cargo.toml, follows the feature
+//! graph from the features cargo would enable and prints the dependencies
+//! it reaches, either as the `crate()` requirements of a spec or in the
+//! Debian style of [`crate::takopack::deb_deps`]. Nothing is downloaded and
+//! the crate directory is left alone.
+
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use cargo::core::{Dependency, EitherManifest, Manifest, SourceId};
+use cargo::util::toml::read_manifest;
+use cargo::GlobalContext;
+
+use crate::config::{Config, PackageKey};
+use crate::crates::{all_dependencies_and_features, transitive_deps, weak_dependency_features};
+use crate::errors::*;
+use crate::takopack::{coerce_prerelease, control, deb_deps, spec};
+
+/// How `cargo deps` prints the dependencies.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DepsStyle {
+    /// `crate(foo/default) >= 1.2.0`, as in `Requires:`
+    #[default]
+    Crate,
+    /// `librust-foo-1+default-dev (>= 1.2-~~)`
+    Deb,
+}
+
+/// The features `cargo deps` enables.
+#[derive(Debug, Clone, Default)]
+pub struct FeatureSelection {
+    pub features: Vec<String>,
+    pub all_features: bool,
+    pub no_default_features: bool,
+}
+
+pub fn run_deps(path: &Path, selection: &FeatureSelection, style: DepsStyle) -> Result<i32> {
+    let cargo_toml = if path.is_dir() {
+        path.join("Cargo.toml")
+    } else {
+        path.to_path_buf()
+    };
+    let crate_dir = cargo_toml.parent().unwrap_or_else(|| Path::new("."));
+    let manifest = match read_manifest(
+        &cargo_toml,
+        SourceId::for_path(crate_dir)?,
+        &GlobalContext::default()?,
+    )? {
+        EitherManifest::Real(m) => m,
+        _ => takopack_bail!("{} is a virtual manifest", cargo_toml.display()),
+    };
+
+    let (_, config) = Config::load()?;
+    let deps: Vec<Dependency> = selected_dependencies(&manifest, selection)?
+        .into_iter()
+        .map(|dep| {
+            if config.allow_prerelease_dep(PackageKey::BareLib, dep.package_name().as_str()) {
+                dep
+            } else {
+                coerce_prerelease(&dep).unwrap_or(dep)
+            }
+        })
+        .collect();
+    let lines: Vec<String> = match style {
+        DepsStyle::Crate => {
+            control::crate_requirements_from_cargo_deps(&deps, manifest.name().as_str(), false)
+                .iter()
+                .map(|req| spec::render_crate_requirement(config.crate_namespace(), req))
+                .collect()
+        }
+        DepsStyle::Deb => deb_deps(config.allow_prerelease_deps, &deps, config.test_mode)?,
+    };
+    for line in lines {
+        println!("{}", line);
+    }
+    Ok(0)
+}
+
+/// The dependencies of `manifest` reached from the features of `selection`.
+pub fn selected_dependencies(
+    manifest: &Manifest,
+    selection: &FeatureSelection,
+) -> Result<Vec<Dependency>> {
+    let features_with_deps = all_dependencies_and_features(manifest)?;
+    // "" is the library without features
+    let mut roots: BTreeSet<&str> = BTreeSet::from([""]);
+    if selection.all_features {
+        roots.extend(features_with_deps.keys().copied());
+    } else if !selection.no_default_features {
+        roots.insert("default");
+    }
+    for requested in selection.features.iter().flat_map(|f| f.split([',', ' '])) {
+        if requested.is_empty() {
+            continue;
+        }
+        match features_with_deps.get_key_value(requested) {
+            Some((feature, _)) => roots.insert(*feature),
+            None => takopack_bail!(
+                "{} has no feature `{}`",
+                manifest.name().as_str(),
+                requested
+            ),
+        };
+    }
+
+    let mut deps = Vec::new();
+    let mut enabled = BTreeSet::new();
+    for root in roots {
+        let (root_features, root_deps) = transitive_deps(&features_with_deps, root)?;
+        enabled.insert(root);
+        enabled.extend(root_features);
+        deps.extend(root_deps);
+    }
+    // `bar?/baz` applies when something else enables bar
+    let weak_features = weak_dependency_features(manifest);
+    let weak_deps: Vec<Dependency> = enabled
+        .iter()
+        .filter_map(|feature| weak_features.get(feature))
+        .flatten()
+        .filter(|weak| {
+            deps.iter()
+                .any(|dep| dep.name_in_toml() == weak.name_in_toml())
+        })
+        .cloned()
+        .collect();
+    deps.extend(weak_deps);
+    deps.sort_by(|a, b| {
+        (a.package_name(), a.version_req().to_string())
+            .cmp(&(b.package_name(), b.version_req().to_string()))
+    });
+    deps.dedup();
+    Ok(deps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{selected_dependencies, FeatureSelection};
+    use cargo::core::{EitherManifest, SourceId};
+    use cargo::util::toml::read_manifest;
+    use cargo::GlobalContext;
+    use std::fs;
+
+    #[test]
+    fn features_select_dependencies() {
+        let temp = tempfile::tempdir().unwrap();
+        fs::create_dir_all(temp.path().join("src")).unwrap();
+        fs::write(temp.path().join("src/lib.rs"), "").unwrap();
+        let cargo_toml = temp.path().join("Cargo.toml");
+        fs::write(
+            &cargo_toml,
+            r#"
+[package]
+name = "demo"
+version = "0.1.0"
+
+[dependencies]
+log = "0.4"
+serde = { version = "1", optional = true }
+regex = { version = "1", optional = true }
+
+[features]
+default = ["serde"]
+extra = ["dep:regex"]
+fast = ["regex?/perf"]
+"#,
+        )
+        .unwrap();
+        let source_id = SourceId::for_path(temp.path()).unwrap();
+        let manifest = match read_manifest(
+            &cargo_toml,
+            source_id,
+            &GlobalContext::default().unwrap(),
+        )
+        .unwrap()
+        {
+            EitherManifest::Real(manifest) => manifest,
+            _ => panic!("expected real manifest"),
+        };
+        let names = |selection: FeatureSelection| -> Vec<String> {
+            let mut names: Vec<String> = selected_dependencies(&manifest, &selection)
+                .unwrap()
+                .iter()
+                .map(|dep| dep.package_name().to_string())
+                .collect();
+            names.dedup();
+            names
+        };
+
+        assert_eq!(names(FeatureSelection::default()), ["log", "serde"]);
+        assert_eq!(
+            names(FeatureSelection {
+                no_default_features: true,
+                ..FeatureSelection::default()
+            }),
+            ["log"]
+        );
+        assert_eq!(
+            names(FeatureSelection {
+                features: vec!["extra".to_string()],
+                no_default_features: true,
+                ..FeatureSelection::default()
+            }),
+            ["log", "regex"]
+        );
+        assert_eq!(
+            names(FeatureSelection {
+                all_features: true,
+                ..FeatureSelection::default()
+            }),
+            ["log", "regex", "serde"]
+        );
+        let weak = |features: &[&str]| {
+            selected_dependencies(
+                &manifest,
+                &FeatureSelection {
+                    features: features.iter().map(|f| f.to_string()).collect(),
+                    no_default_features: true,
+                    ..FeatureSelection::default()
+                },
+            )
+            .unwrap()
+            .iter()
+            .any(|dep| dep.features().iter().any(|f| f.as_str() == "perf"))
+        };
+        assert_eq!(
+            names(FeatureSelection {
+                features: vec!["fast".to_string()],
+                no_default_features: true,
+                ..FeatureSelection::default()
+            }),
+            ["log"]
+        );
+        assert!(!weak(&["fast"]));
+        assert!(weak(&["fast", "extra"]));
+        assert!(selected_dependencies(
+            &manifest,
+            &FeatureSelection {
+                features: vec!["missing".to_string()],
+                ..FeatureSelection::default()
+            }
+        )
+        .is_err());
+    }
+}
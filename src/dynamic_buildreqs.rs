@@ -5,19 +5,21 @@ use std::path::Path;
 
 use semver::Version;
 
+use crate::config::Config;
 use crate::errors::Result;
 use crate::resolve_check::{self, LockPackage};
-use crate::util::calculate_compat_version;
+use crate::version::compat_version;
 
 pub fn run_buildreqs(path: &Path, registry: Option<&Path>) -> Result<i32> {
+    let (_, config) = Config::load()?;
     let report = resolve_check::resolve_single_crate(path, registry)?;
-    for line in buildrequires_from_lock_packages(&report.lock_packages) {
+    for line in buildrequires_from_lock_packages(&report.lock_packages, config.crate_namespace()) {
         println!("{line}");
     }
     Ok(0)
 }
 
-pub fn buildrequires_from_lock_packages(packages: &[LockPackage]) -> Vec<String> {
+pub fn buildrequires_from_lock_packages(packages: &[LockPackage], namespace: &str) -> Vec<String> {
     let mut lines = BTreeSet::new();
 
     for package in packages {
@@ -30,10 +32,10 @@ pub fn buildrequires_from_lock_packages(packages: &[LockPackage]) -> Vec<String>
         }
 
         let capability_name = package.name.replace('_', "-");
-        let compat = calculate_compat_version(&package.version);
+        let compat = compat_version(&package.version);
         let version = clean_semver_without_build(&package.version);
         lines.insert(format!(
-            "BuildRequires:  crate({capability_name}-{compat}) >= {version}"
+            "BuildRequires:  {namespace}({capability_name}-{compat}) >= {version}"
         ));
     }
 
@@ -74,8 +76,12 @@ mod tests {
         ];
 
         assert_eq!(
-            buildrequires_from_lock_packages(&packages),
+            buildrequires_from_lock_packages(&packages, "crate"),
             vec!["BuildRequires:  crate(foo-bar-1) >= 1.2.3"]
         );
+        assert_eq!(
+            buildrequires_from_lock_packages(&packages, "mycorp-crate"),
+            vec!["BuildRequires:  mycorp-crate(foo-bar-1) >= 1.2.3"]
+        );
     }
 }
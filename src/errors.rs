@@ -6,17 +6,17 @@ pub use anyhow::format_err;
 #[macro_export]
 macro_rules! takopack_info {
     ($e:expr) => {
-        {
+        if !$crate::verbosity::Verbosity::current().is_quiet() {
             use nu_ansi_term::Color::Green;
-            eprintln!("{}", Green.paint($e));
+            $crate::progress::eprint_line(&Green.paint($e).to_string());
         }
     };
 
     ($fmt:expr, $( $arg:tt)+) => {
-        {
+        if !$crate::verbosity::Verbosity::current().is_quiet() {
             use nu_ansi_term::Color::Green;
             let print_string = format!($fmt, $($arg)+);
-            eprintln!("{}", Green.paint(print_string));
+            $crate::progress::eprint_line(&Green.paint(print_string).to_string());
         }
     };
 }
@@ -26,7 +26,7 @@ macro_rules! takopack_warn {
     ($e:expr) => {
         {
             use nu_ansi_term::Color::Rgb;
-            eprintln!("{}", Rgb(255,165,0).bold().paint($e));
+            $crate::progress::eprint_line(&Rgb(255,165,0).bold().paint($e).to_string());
         }
     };
 
@@ -34,7 +34,7 @@ macro_rules! takopack_warn {
         {
             use nu_ansi_term::Color::Rgb;
             let print_string = Rgb(255,165,0).bold().paint(format!($fmt, $($arg)+));
-            eprintln!("{}", print_string);
+            $crate::progress::eprint_line(&print_string.to_string());
         }
     };
 
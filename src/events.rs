@@ -0,0 +1,277 @@
+//! What takopack runs report, for people and for tools.
+//!
+//! `pkg`, `batch` and `vendor` used to report progress and results with a
+//! mix of `println!`, `eprintln!` and emoji, which CI jobs could only
+//! scrape. They now report [`Event`]s to the sink chosen with the global
+//! `--output-format` flag: `human` prints them as before, `json` writes one
+//! JSON object per line to stdout, and `quiet` only prints failures. The
+//! sink is installed once with [`OutputFormat::install`], like the
+//! [`crate::verbosity`]. Warnings from `takopack_warn!` stay on stderr in
+//! every format, so stdout only ever holds events.
+
+use std::fmt;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+use crate::lint::Severity;
+
+/// How events are reported.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Lines for people
+    #[default]
+    Human,
+    /// One JSON object per line on stdout
+    Json,
+    /// Only failures
+    Quiet,
+}
+
+static CURRENT: Mutex<OutputFormat> = Mutex::new(OutputFormat::Human);
+
+impl OutputFormat {
+    /// Report events in this format from now on.
+    pub fn install(self) {
+        *CURRENT.lock().unwrap_or_else(|e| e.into_inner()) = self;
+    }
+
+    /// The installed format; `human` unless `--output-format` was given.
+    pub fn current() -> Self {
+        *CURRENT.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn sink(self) -> Box<dyn EventSink> {
+        match self {
+            OutputFormat::Human => Box::new(HumanSink),
+            OutputFormat::Json => Box::new(JsonLinesSink(io::stdout())),
+            OutputFormat::Quiet => Box::new(QuietSink),
+        }
+    }
+}
+
+/// Something a run reports.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// A crate is about to be packaged
+    CrateStarted { crate_name: String, version: String },
+    /// A crate was packaged into `package_dir`
+    CratePackaged {
+        crate_name: String,
+        version: String,
+        package_dir: Option<PathBuf>,
+    },
+    /// A crate failed to package; `version` is empty for workspace members,
+    /// which are named by their path
+    CrateFailed {
+        crate_name: String,
+        version: String,
+        error: String,
+    },
+    /// A crate was left out on purpose
+    CrateSkipped {
+        crate_name: String,
+        version: String,
+        reason: String,
+    },
+    /// The spec of a `pkg` or `localpkg` run was written
+    SpecWritten { spec: PathBuf },
+    /// Anything else worth telling
+    Note { text: String },
+    /// A problem `cargo lint` found in a spec; `line` is 1-based
+    LintIssue {
+        spec: PathBuf,
+        line: Option<usize>,
+        severity: Severity,
+        rule: String,
+        message: String,
+    },
+    /// The totals of a `batch` or `vendor` run
+    Summary {
+        attempted: usize,
+        packaged: usize,
+        failed: usize,
+        skipped: usize,
+        output_dir: PathBuf,
+    },
+}
+
+impl Event {
+    pub fn note(text: impl Into<String>) -> Self {
+        Event::Note { text: text.into() }
+    }
+
+    pub fn is_failure(&self) -> bool {
+        matches!(
+            self,
+            Event::CrateFailed { .. }
+                | Event::LintIssue {
+                    severity: Severity::Error,
+                    ..
+                }
+        )
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::CrateStarted {
+                crate_name,
+                version,
+            } => write!(f, "\nProcessing {} {}...", crate_name, version),
+            Event::CratePackaged {
+                crate_name,
+                version,
+                ..
+            } => write!(f, "✓ Successfully packaged {} {}", crate_name, version),
+            Event::CrateFailed {
+                crate_name,
+                version,
+                error,
+            } if version.is_empty() => write!(f, "✗ Failed to package {}: {}", crate_name, error),
+            Event::CrateFailed {
+                crate_name,
+                version,
+                error,
+            } => write!(
+                f,
+                "✗ Failed to package {} {}: {}",
+                crate_name, version, error
+            ),
+            Event::CrateSkipped {
+                crate_name,
+                version,
+                reason,
+            } => write!(f, "⏭️  Skipping {} {} ({})", crate_name, version, reason),
+            Event::SpecWritten { spec } => write!(f, "Spec file: {}", spec.display()),
+            Event::Note { text } => write!(f, "{}", text),
+            Event::LintIssue {
+                spec,
+                line,
+                severity,
+                rule,
+                message,
+            } => {
+                write!(f, "{}", spec.display())?;
+                if let Some(line) = line {
+                    write!(f, ":{}", line)?;
+                }
+                write!(f, ": {}[{}]: {}", severity, rule, message)
+            }
+            Event::Summary {
+                attempted,
+                packaged,
+                failed,
+                skipped,
+                output_dir,
+            } => write!(
+                f,
+                "{} attempted, {} packaged, {} failed, {} skipped; output in {}",
+                attempted,
+                packaged,
+                failed,
+                skipped,
+                output_dir.display()
+            ),
+        }
+    }
+}
+
+/// Where events go.
+pub trait EventSink {
+    fn emit(&mut self, event: &Event);
+}
+
+/// Lines for people; failures go to stderr.
+pub struct HumanSink;
+
+impl EventSink for HumanSink {
+    fn emit(&mut self, event: &Event) {
+        if event.is_failure() {
+            eprintln!("{}", event);
+        } else {
+            println!("{}", event);
+        }
+    }
+}
+
+/// One JSON object per event and line.
+pub struct JsonLinesSink<W>(pub W);
+
+impl<W: Write> EventSink for JsonLinesSink<W> {
+    fn emit(&mut self, event: &Event) {
+        match serde_json::to_string(event) {
+            Ok(line) => {
+                if let Err(e) = writeln!(self.0, "{}", line) {
+                    log::warn!("could not write event: {}", e);
+                }
+            }
+            Err(e) => log::warn!("could not serialize event {:?}: {}", event, e),
+        }
+    }
+}
+
+/// Only failures, on stderr.
+pub struct QuietSink;
+
+impl EventSink for QuietSink {
+    fn emit(&mut self, event: &Event) {
+        if event.is_failure() {
+            eprintln!("{}", event);
+        }
+    }
+}
+
+/// Report `event` in the installed format, or keep it for a
+/// [`crate::progress::captured`] call.
+pub fn emit(event: Event) {
+    if crate::progress::capture(&event.to_string()) {
+        return;
+    }
+    OutputFormat::current().sink().emit(&event);
+}
+
+/// Whether reports meant for people, such as the summary tables of a run,
+/// are printed.
+pub fn is_human() -> bool {
+    OutputFormat::current() == OutputFormat::Human
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Event, EventSink, JsonLinesSink};
+    use std::path::PathBuf;
+
+    #[test]
+    fn events_are_written_as_json_lines_and_text() {
+        let mut sink = JsonLinesSink(Vec::new());
+        sink.emit(&Event::CratePackaged {
+            crate_name: "serde".to_string(),
+            version: "1.0.219".to_string(),
+            package_dir: Some(PathBuf::from("out/rust-serde-1")),
+        });
+        sink.emit(&Event::note("Keeping out/rust-log-0.4"));
+        let text = String::from_utf8(sink.0).unwrap();
+        assert_eq!(
+            text,
+            "{\"event\":\"crate_packaged\",\"crate_name\":\"serde\",\"version\":\"1.0.219\",\
+             \"package_dir\":\"out/rust-serde-1\"}\n\
+             {\"event\":\"note\",\"text\":\"Keeping out/rust-log-0.4\"}\n"
+        );
+
+        let failed = Event::CrateFailed {
+            crate_name: "openssl-sys".to_string(),
+            version: "0.9.100".to_string(),
+            error: "no spec".to_string(),
+        };
+        assert!(failed.is_failure());
+        assert_eq!(
+            failed.to_string(),
+            "✗ Failed to package openssl-sys 0.9.100: no spec"
+        );
+    }
+}
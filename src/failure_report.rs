@@ -0,0 +1,247 @@
+//! Failure reports of `takopack cargo batch` and `takopack cargo vendor`.
+//!
+//! At the end of a run two files are written to the output directory:
+//! `failures.json`, with the crate, version, pipeline phase and error chain
+//! of every failure for CI to parse, and `failures.txt`, listing the failed
+//! crates in the input format of `batch` so that they can be retried with
+//! `takopack cargo batch failures.txt`. Runs without failures remove the
+//! reports of earlier runs in the same directory.
+//!
+//! Crates a `vendor` run leaves out on purpose are not failures; they are
+//! listed with their [`SkipReason`] in `skipped.json` instead.
+//!
+//! The phase is taken from a [`Phase`] context attached where the pipeline
+//! runs, so errors keep their message and only gain a line naming it.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::dep_filter::SkipReason;
+use crate::errors::*;
+
+pub const FAILURES_JSON: &str = "failures.json";
+pub const FAILURES_TXT: &str = "failures.txt";
+pub const SKIPPED_JSON: &str = "skipped.json";
+
+/// Step of the packaging pipeline a crate failed in.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Phase {
+    /// Resolving and downloading the crate
+    Download,
+    /// Unpacking the crate
+    Extract,
+    /// Generating the spec
+    Spec,
+    /// Building the generated spec
+    Build,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Phase::Download => "Failed to download the crate",
+            Phase::Extract => "Failed to extract the crate",
+            Phase::Spec => "Failed to generate the spec",
+            Phase::Build => "Failed to build the package",
+        })
+    }
+}
+
+impl Phase {
+    /// The phase `error` was raised in, if one was attached as context.
+    pub fn of(error: &anyhow::Error) -> Option<Phase> {
+        error.downcast_ref::<Phase>().copied()
+    }
+}
+
+/// One failed crate.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+    #[serde(rename = "crate")]
+    pub crate_name: String,
+    /// Version or version requirement the crate was asked for
+    pub version: String,
+    /// `None` when the failure happened outside the pipeline phases
+    pub phase: Option<Phase>,
+    /// Messages of the error chain, outermost first
+    pub errors: Vec<String>,
+}
+
+impl Failure {
+    pub fn new(crate_name: &str, version: &str, error: &anyhow::Error) -> Self {
+        Failure {
+            crate_name: crate_name.to_string(),
+            version: version.to_string(),
+            phase: Phase::of(error),
+            errors: error.chain().map(|cause| cause.to_string()).collect(),
+        }
+    }
+}
+
+/// A crate left out of a recursive run.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Skip {
+    #[serde(rename = "crate")]
+    pub crate_name: String,
+    /// Version or version requirement the crate was asked for
+    pub version: String,
+    /// Crate whose dependency was left out, where there is one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    pub reason: SkipReason,
+}
+
+impl fmt::Display for Skip {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} ({}", self.crate_name, self.version, self.reason)?;
+        if let Some(parent) = &self.parent {
+            write!(f, ", required by {}", parent)?;
+        }
+        f.write_str(")")
+    }
+}
+
+/// Write [`FAILURES_JSON`] and [`FAILURES_TXT`] for `failures` to `dir`.
+pub fn write_failure_report(dir: &Path, failures: &[Failure]) -> Result<()> {
+    let json = dir.join(FAILURES_JSON);
+    let txt = dir.join(FAILURES_TXT);
+    if failures.is_empty() {
+        remove_report(&json)?;
+        return remove_report(&txt);
+    }
+    fs::write(&json, serde_json::to_string_pretty(failures)? + "\n")
+        .with_context(|| format!("Failed to write {}", json.display()))?;
+    fs::write(&txt, retry_list(failures))
+        .with_context(|| format!("Failed to write {}", txt.display()))
+}
+
+/// Write [`SKIPPED_JSON`] for `skips` to `dir`.
+pub fn write_skip_report(dir: &Path, skips: &[Skip]) -> Result<()> {
+    let json = dir.join(SKIPPED_JSON);
+    if skips.is_empty() {
+        return remove_report(&json);
+    }
+    fs::write(&json, serde_json::to_string_pretty(skips)? + "\n")
+        .with_context(|| format!("Failed to write {}", json.display()))
+}
+
+/// Remove the report of an earlier run at `path`, if there is one.
+fn remove_report(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(e).with_context(|| format!("Failed to remove {}", path.display()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// `failures` as a `batch` input file. Crates asked for without a version
+/// are retried at the latest one.
+fn retry_list(failures: &[Failure]) -> String {
+    let mut list = String::from("# Failed crates; retry with `takopack cargo batch <this file>`\n");
+    let mut seen = Vec::new();
+    for failure in failures {
+        let version = match failure.version.as_str() {
+            "" | "latest" => "*",
+            version => version,
+        };
+        if seen.contains(&(&failure.crate_name, version)) {
+            continue;
+        }
+        seen.push((&failure.crate_name, version));
+        list.push_str(&format!("{} {}\n", failure.crate_name, version));
+    }
+    list
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        write_failure_report, write_skip_report, Failure, Phase, Skip, FAILURES_JSON, FAILURES_TXT,
+        SKIPPED_JSON,
+    };
+    use crate::dep_filter::SkipReason;
+    use anyhow::{format_err, Context};
+    use std::fs;
+
+    #[test]
+    fn reports_carry_the_phase_and_error_chain() {
+        let error = Err::<(), _>(format_err!("HTTP 404"))
+            .context(Phase::Download)
+            .context("Failed to init package process for nope")
+            .unwrap_err();
+        let failure = Failure::new("nope", "latest", &error);
+        assert_eq!(failure.phase, Some(Phase::Download));
+        assert_eq!(
+            failure.errors,
+            vec![
+                "Failed to init package process for nope",
+                "Failed to download the crate",
+                "HTTP 404"
+            ]
+        );
+        let unclassified = Failure::new("serde", "1.0.200", &format_err!("disk full"));
+        assert_eq!(unclassified.phase, None);
+
+        let dir = tempfile::tempdir().unwrap();
+        write_failure_report(dir.path(), &[failure.clone(), unclassified, failure]).unwrap();
+        let json: Vec<Failure> =
+            serde_json::from_str(&fs::read_to_string(dir.path().join(FAILURES_JSON)).unwrap())
+                .unwrap();
+        assert_eq!(json.len(), 3);
+        assert!(fs::read_to_string(dir.path().join(FAILURES_JSON))
+            .unwrap()
+            .contains("\"phase\": \"download\""));
+        let txt = fs::read_to_string(dir.path().join(FAILURES_TXT)).unwrap();
+        let entries: Vec<&str> = txt.lines().filter(|l| !l.starts_with('#')).collect();
+        assert_eq!(entries, vec!["nope *", "serde 1.0.200"]);
+
+        write_failure_report(dir.path(), &[]).unwrap();
+        assert!(!dir.path().join(FAILURES_JSON).exists());
+        assert!(!dir.path().join(FAILURES_TXT).exists());
+    }
+
+    #[test]
+    fn skips_are_reported_with_their_reason() {
+        let skips = vec![
+            Skip {
+                crate_name: "proptest".to_string(),
+                version: "^1".to_string(),
+                parent: Some("app".to_string()),
+                reason: SkipReason::DevDependency,
+            },
+            Skip {
+                crate_name: "libc".to_string(),
+                version: "^0.2".to_string(),
+                parent: None,
+                reason: SkipReason::InRepository("0.2.150".to_string()),
+            },
+        ];
+        assert_eq!(
+            skips[0].to_string(),
+            "proptest ^1 (dev dependency, required by app)"
+        );
+        assert_eq!(
+            skips[1].to_string(),
+            "libc ^0.2 (0.2.150 is in the distro repositories)"
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        write_skip_report(dir.path(), &skips).unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(dir.path().join(SKIPPED_JSON)).unwrap())
+                .unwrap();
+        assert_eq!(json[0]["reason"], "dev_dependency");
+        assert_eq!(json[0]["parent"], "app");
+        assert_eq!(json[1]["reason"]["in_repository"], "0.2.150");
+        assert!(json[1].get("parent").is_none());
+
+        write_skip_report(dir.path(), &[]).unwrap();
+        assert!(!dir.path().join(SKIPPED_JSON).exists());
+    }
+}
@@ -0,0 +1,119 @@
+//! Modes of the files takopack leaves in package directories.
+//!
+//! Left alone, a generated spec gets whatever the umask allows and a copied
+//! Cargo.toml keeps the mode it had in the crate archive, which permission
+//! lints of rpmlint and dist-git hooks then flag. Every package directory is
+//! therefore brought to one policy at the end of a run: scripts (files
+//! starting with `#!`) get the executable mode, everything else the data
+//! mode. Both can be changed in the `[permissions]` table of takopack.toml.
+
+use std::fs;
+use std::io::Read;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use crate::errors::*;
+
+/// Modes of generated files, set in the `[permissions]` table of
+/// takopack.toml.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct FileModes {
+    /// Mode of scripts, `0o755` by default.
+    pub executable: u32,
+    /// Mode of every other file, `0o644` by default.
+    pub data: u32,
+}
+
+impl Default for FileModes {
+    fn default() -> Self {
+        FileModes {
+            executable: 0o755,
+            data: 0o644,
+        }
+    }
+}
+
+impl FileModes {
+    /// Mode for an executable or a data file.
+    pub fn mode(&self, executable: bool) -> Result<u32> {
+        let mode = if executable {
+            self.executable
+        } else {
+            self.data
+        };
+        if mode > 0o7777 {
+            takopack_bail!("invalid file mode {:o} in [permissions]", mode);
+        }
+        Ok(mode)
+    }
+
+    /// Give `path` the mode of an executable or a data file.
+    pub fn set(&self, path: &Path, executable: bool) -> Result<()> {
+        fs::set_permissions(path, fs::Permissions::from_mode(self.mode(executable)?))
+            .with_context(|| format!("Failed to set the mode of {}", path.display()))
+    }
+
+    /// Give every file under `dir` the mode of its kind. Symlinks are left
+    /// alone.
+    pub fn apply_to_dir(&self, dir: &Path) -> Result<()> {
+        for entry in walkdir::WalkDir::new(dir) {
+            let entry = entry.context("walkdir error")?;
+            if entry.file_type().is_file() {
+                self.set(entry.path(), is_script(entry.path())?)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_script(path: &Path) -> Result<bool> {
+    let mut start = [0; 2];
+    let mut file =
+        fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let read = file
+        .read(&mut start)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(start[..read] == *b"#!")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FileModes;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn scripts_and_data_files_get_their_modes() {
+        let dir = tempfile::tempdir().unwrap();
+        let spec = dir.path().join("rust-log-0.4.spec");
+        let script = dir.path().join("sub/rules");
+        fs::create_dir_all(script.parent().unwrap()).unwrap();
+        fs::write(&spec, "Name: rust-log-0.4\n").unwrap();
+        fs::write(&script, "#!/usr/bin/make -f\n").unwrap();
+        fs::set_permissions(&spec, fs::Permissions::from_mode(0o777)).unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o600)).unwrap();
+
+        let mode = |path| fs::metadata(path).unwrap().permissions().mode() & 0o7777;
+        FileModes::default().apply_to_dir(dir.path()).unwrap();
+        assert_eq!(mode(&spec), 0o644);
+        assert_eq!(mode(&script), 0o755);
+
+        let custom = FileModes {
+            executable: 0o750,
+            data: 0o640,
+        };
+        custom.apply_to_dir(dir.path()).unwrap();
+        assert_eq!(mode(&spec), 0o640);
+        assert_eq!(mode(&script), 0o750);
+
+        let invalid = FileModes {
+            data: 0o100644,
+            ..FileModes::default()
+        };
+        assert!(invalid.apply_to_dir(dir.path()).is_err());
+    }
+}
@@ -0,0 +1,199 @@
+use anyhow::{Context, Result};
+use std::path::{Component, Path, PathBuf};
+
+use crate::events::{self, Event};
+use crate::package::PackageExecuteArgs;
+use crate::range_audit::RangeCapabilityPolicy;
+
+/// Which revision of a repository to package.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GitReference {
+    /// The remote's default branch.
+    DefaultBranch,
+    Branch(String),
+    Tag(String),
+    /// Any revision `git rev-parse` understands, usually a commit hash.
+    Rev(String),
+}
+
+impl GitReference {
+    pub fn from_args(rev: Option<String>, tag: Option<String>, branch: Option<String>) -> Self {
+        match (rev, tag, branch) {
+            (Some(rev), _, _) => GitReference::Rev(rev),
+            (None, Some(tag), _) => GitReference::Tag(tag),
+            (None, None, Some(branch)) => GitReference::Branch(branch),
+            (None, None, None) => GitReference::DefaultBranch,
+        }
+    }
+}
+
+/// Clone `url`, check out `reference` and package the crate at `path`
+/// (relative to the repository root) through the `localpkg` pipeline.
+/// The spec's Source: points at a tarball of the checked-out commit.
+pub fn process_git_package(
+    url: &str,
+    reference: &GitReference,
+    path: Option<&Path>,
+    output_dir: Option<PathBuf>,
+    finish_args: PackageExecuteArgs,
+    range_capability_policy: RangeCapabilityPolicy,
+) -> Result<()> {
+    let checkout = tempfile::tempdir().context("Failed to create temporary clone directory")?;
+    let commit = clone_repository(url, reference, checkout.path())?;
+    events::emit(Event::note(format!("Cloned {} at {}", url, commit)));
+
+    let crate_path = crate_path_in_checkout(checkout.path(), path)?;
+    let source_url = git_source_url(url, &commit);
+    crate::local_package::process_local_package_with_source(
+        &crate_path,
+        output_dir,
+        finish_args,
+        range_capability_policy,
+        Some(&source_url),
+    )
+}
+
+/// Clone `url` into `dest` and check out `reference`. Returns the full hash
+/// of the checked-out commit.
+fn clone_repository(url: &str, reference: &GitReference, dest: &Path) -> Result<String> {
+    log::info!("Cloning {} into {:?}", url, dest);
+    let mut builder = git2::build::RepoBuilder::new();
+    if let GitReference::Branch(branch) = reference {
+        builder.branch(branch);
+    }
+    let repo = builder
+        .clone(url, dest)
+        .with_context(|| format!("Failed to clone {}", url))?;
+
+    let spec = match reference {
+        GitReference::DefaultBranch | GitReference::Branch(_) => "HEAD".to_string(),
+        GitReference::Tag(tag) => format!("refs/tags/{}", tag),
+        GitReference::Rev(rev) => rev.clone(),
+    };
+    let commit = repo
+        .revparse_single(&spec)
+        .and_then(|object| object.peel_to_commit())
+        .with_context(|| format!("Revision {} not found in {}", spec, url))?;
+
+    repo.checkout_tree(
+        commit.as_object(),
+        Some(git2::build::CheckoutBuilder::new().force()),
+    )
+    .with_context(|| format!("Failed to check out {}", commit.id()))?;
+    repo.set_head_detached(commit.id())?;
+
+    Ok(commit.id().to_string())
+}
+
+/// Resolve `--path` inside the checkout, refusing paths that leave it.
+fn crate_path_in_checkout(checkout: &Path, path: Option<&Path>) -> Result<PathBuf> {
+    let Some(path) = path else {
+        return Ok(checkout.to_path_buf());
+    };
+    if path.is_absolute()
+        || path
+            .components()
+            .any(|c| matches!(c, Component::ParentDir | Component::Prefix(_)))
+    {
+        anyhow::bail!("--path must be relative to the repository root: {:?}", path);
+    }
+    let crate_path = checkout.join(path);
+    if !crate_path.exists() {
+        anyhow::bail!("{:?} does not exist in the repository", path);
+    }
+    Ok(crate_path)
+}
+
+/// Source: URL of a tarball of `commit`, in the archive layout of the
+/// forge hosting `url`. GitLab uses `/-/archive/`, GitHub, Gitea and
+/// Forgejo all accept `/archive/{commit}.tar.gz`.
+fn git_source_url(url: &str, commit: &str) -> String {
+    let base = url.trim_end_matches('/').trim_end_matches(".git");
+    let base = match base.strip_prefix("git@") {
+        // scp-like syntax: git@host:owner/repo
+        Some(rest) => format!("https://{}", rest.replacen(':', "/", 1)),
+        None => base.to_string(),
+    };
+    let repo = base.rsplit('/').next().unwrap_or("source");
+    let archive = if base.contains("gitlab") {
+        format!("{}/-/archive/{}/{}-{}.tar.gz", base, commit, repo, commit)
+    } else {
+        format!("{}/archive/{}.tar.gz", base, commit)
+    };
+    format!("{}#/%{{name}}-%{{version}}.tar.gz", archive)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{clone_repository, crate_path_in_checkout, git_source_url, GitReference};
+    use std::fs;
+    use std::path::Path;
+
+    fn commit_all(repo: &git2::Repository, message: &str) -> git2::Oid {
+        let mut index = repo.index().unwrap();
+        index
+            .add_all(["*"], git2::IndexAddOption::DEFAULT, None)
+            .unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let sig = git2::Signature::now("test", "test@example.com").unwrap();
+        let parents: Vec<_> = repo
+            .head()
+            .ok()
+            .and_then(|h| h.peel_to_commit().ok())
+            .into_iter()
+            .collect();
+        let parents: Vec<_> = parents.iter().collect();
+        repo.commit(Some("HEAD"), &sig, &sig, message, &tree, &parents)
+            .unwrap()
+    }
+
+    #[test]
+    fn clone_checks_out_requested_tag() {
+        let upstream = tempfile::tempdir().unwrap();
+        let repo = git2::Repository::init(upstream.path()).unwrap();
+        fs::create_dir_all(upstream.path().join("crates/demo")).unwrap();
+        fs::write(
+            upstream.path().join("crates/demo/Cargo.toml"),
+            "[package]\nname = \"demo\"\nversion = \"0.1.0\"\n",
+        )
+        .unwrap();
+        let first = commit_all(&repo, "v0.1.0");
+        repo.tag_lightweight("v0.1.0", &repo.find_object(first, None).unwrap(), false)
+            .unwrap();
+        fs::write(
+            upstream.path().join("crates/demo/Cargo.toml"),
+            "[package]\nname = \"demo\"\nversion = \"0.2.0\"\n",
+        )
+        .unwrap();
+        let second = commit_all(&repo, "v0.2.0");
+
+        let url = upstream.path().to_str().unwrap();
+        let head = tempfile::tempdir().unwrap();
+        let commit = clone_repository(url, &GitReference::DefaultBranch, head.path()).unwrap();
+        assert_eq!(commit, second.to_string());
+
+        let tagged = tempfile::tempdir().unwrap();
+        let reference = GitReference::Tag("v0.1.0".to_string());
+        let commit = clone_repository(url, &reference, tagged.path()).unwrap();
+        assert_eq!(commit, first.to_string());
+
+        let crate_dir =
+            crate_path_in_checkout(tagged.path(), Some(Path::new("crates/demo"))).unwrap();
+        let manifest = fs::read_to_string(crate_dir.join("Cargo.toml")).unwrap();
+        assert!(manifest.contains("0.1.0"));
+        assert!(crate_path_in_checkout(tagged.path(), Some(Path::new("../x"))).is_err());
+    }
+
+    #[test]
+    fn source_url_follows_forge_archive_layout() {
+        assert_eq!(
+            git_source_url("https://github.com/o/r.git", "abc"),
+            "https://github.com/o/r/archive/abc.tar.gz#/%{name}-%{version}.tar.gz"
+        );
+        assert_eq!(
+            git_source_url("git@gitlab.com:o/r.git", "abc"),
+            "https://gitlab.com/o/r/-/archive/abc/r-abc.tar.gz#/%{name}-%{version}.tar.gz"
+        );
+    }
+}
@@ -0,0 +1,176 @@
+//! Proxy, CA certificates and crates.io mirror shared by every download.
+//!
+//! The settings come from the `[download]` table of takopack.toml and the
+//! `--proxy`, `--cacert` and `--mirror-url` flags. Cargo is handed the
+//! proxy and CA bundle through its own `http.proxy` and `http.cainfo`
+//! settings (see [`DownloadOptions`]); everything takopack fetches itself
+//! (sparse index files, the crates.io API, PyPI, COPR) goes through
+//! [`agent`] or [`agent_builder`]. Without a configured proxy, the usual
+//! `HTTPS_PROXY` / `HTTP_PROXY` variables are honored.
+//!
+//! The settings are shared by the whole process, except inside [`scoped`]:
+//! there they start out empty and are configured for the calling thread
+//! alone, so that concurrent library calls do not overwrite each other's.
+
+use std::cell::RefCell;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Context;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::CertificateDer;
+
+use crate::crates::DownloadOptions;
+use crate::errors::*;
+
+#[derive(Default, Clone)]
+struct Settings {
+    proxy: Option<ureq::Proxy>,
+    tls: Option<Arc<rustls::ClientConfig>>,
+    mirror_url: Option<String>,
+    agent: Option<ureq::Agent>,
+}
+
+/// Base URL of the crates.io web API. A mirror only stands in for the
+/// index and the downloads, so the API is always crates.io's.
+pub const CRATES_IO_API: &str = "https://crates.io/api/v1";
+
+static SETTINGS: Mutex<Option<Settings>> = Mutex::new(None);
+
+thread_local! {
+    static SCOPED: RefCell<Option<Option<Settings>>> = const { RefCell::new(None) };
+}
+
+/// Run `f` on the settings of this thread: its own inside [`scoped`], the
+/// process-wide ones otherwise.
+fn with_settings<T>(f: impl FnOnce(&mut Option<Settings>) -> T) -> T {
+    SCOPED.with(|scoped| match &mut *scoped.borrow_mut() {
+        Some(settings) => f(settings),
+        None => f(&mut SETTINGS.lock().unwrap_or_else(|e| e.into_inner())),
+    })
+}
+
+/// Run `f` with settings of its own on this thread, which [`configure`]
+/// changes without affecting other threads.
+pub fn scoped<T>(f: impl FnOnce() -> T) -> T {
+    let previous = SCOPED.with(|scoped| scoped.replace(Some(None)));
+    let result = f();
+    SCOPED.with(|scoped| scoped.replace(previous));
+    result
+}
+
+/// Use the proxy, CA certificates and mirror of `options` from now on.
+pub fn configure(options: &DownloadOptions) -> Result<()> {
+    let proxy = options
+        .proxy
+        .as_deref()
+        .map(|proxy| ureq::Proxy::new(proxy).with_context(|| format!("Invalid proxy {}", proxy)))
+        .transpose()?;
+    let tls = options.cacert.as_deref().map(tls_config).transpose()?;
+    let settings = Settings {
+        proxy,
+        tls,
+        mirror_url: options.mirror_url.clone(),
+        agent: None,
+    };
+    with_settings(|current| *current = Some(settings));
+    Ok(())
+}
+
+/// A builder for HTTP agents using the configured proxy and certificates.
+pub fn agent_builder() -> ureq::AgentBuilder {
+    let settings = with_settings(|settings| settings.clone());
+    let settings = settings.as_ref();
+    let mut builder = ureq::AgentBuilder::new().try_proxy_from_env(true);
+    if let Some(proxy) = settings.and_then(|s| s.proxy.clone()) {
+        builder = builder.proxy(proxy);
+    }
+    if let Some(tls) = settings.and_then(|s| s.tls.clone()) {
+        builder = builder.tls_config(tls);
+    }
+    builder
+}
+
+/// The HTTP agent for plain requests, built once per configuration.
+pub fn agent() -> ureq::Agent {
+    if let Some(agent) = with_settings(|settings| settings.as_ref().and_then(|s| s.agent.clone())) {
+        return agent;
+    }
+    let agent = agent_builder().build();
+    with_settings(|settings| {
+        settings.get_or_insert_with(Settings::default).agent = Some(agent.clone())
+    });
+    agent
+}
+
+/// Index URL of the configured crates.io mirror.
+pub fn mirror_url() -> Option<String> {
+    with_settings(|settings| settings.as_ref().and_then(|s| s.mirror_url.clone()))
+}
+
+/// TLS configuration trusting the public roots and the PEM certificates in
+/// `cacert`.
+fn tls_config(cacert: &Path) -> Result<Arc<rustls::ClientConfig>> {
+    let pem = fs::read(cacert).with_context(|| format!("Failed to read {}", cacert.display()))?;
+    let mut roots = rustls::RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    let mut added = 0;
+    for cert in CertificateDer::pem_slice_iter(&pem) {
+        let cert = cert.with_context(|| format!("Invalid certificate in {}", cacert.display()))?;
+        roots
+            .add(cert)
+            .with_context(|| format!("Invalid certificate in {}", cacert.display()))?;
+        added += 1;
+    }
+    if added == 0 {
+        takopack_bail!("{} holds no PEM certificates", cacert.display());
+    }
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let config = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(Arc::new(config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{configure, mirror_url, scoped, tls_config};
+    use crate::crates::DownloadOptions;
+    use std::fs;
+
+    #[test]
+    fn ca_bundles_must_hold_certificates() {
+        let dir = tempfile::tempdir().unwrap();
+        let bundle = dir.path().join("ca.pem");
+        fs::write(&bundle, "not a certificate\n").unwrap();
+        let err = tls_config(&bundle).unwrap_err().to_string();
+        assert!(err.contains("holds no PEM certificates"), "{}", err);
+        assert!(tls_config(&dir.path().join("missing.pem")).is_err());
+    }
+
+    #[test]
+    fn scoped_settings_stay_on_their_thread() {
+        let mirrored = |url: &str| DownloadOptions {
+            mirror_url: Some(url.to_string()),
+            ..DownloadOptions::default()
+        };
+        scoped(|| {
+            configure(&mirrored("sparse+https://one.example.com/")).unwrap();
+            std::thread::scope(|threads| {
+                threads.spawn(|| {
+                    scoped(|| {
+                        assert_eq!(mirror_url(), None);
+                        configure(&mirrored("sparse+https://two.example.com/")).unwrap();
+                    })
+                });
+            });
+            assert_eq!(
+                mirror_url().as_deref(),
+                Some("sparse+https://one.example.com/")
+            );
+        });
+    }
+}
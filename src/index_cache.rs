@@ -0,0 +1,285 @@
+//! Management of the sparse index cache kept by [`SparseIndex`] under
+//! `~/.cache/takopack/index-cache/`, for `takopack cargo update`.
+//!
+//! Index files are replaced by renaming a complete temporary file over
+//! them, so other takopack processes reading the cache never see a
+//! half-written entry. `update` itself holds an exclusive lock on the cache
+//! directory so that two updates do not interleave.
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::Context;
+use cargo::util::{FileLock, Filesystem, GlobalContext};
+
+use crate::crate_cache::CrateCache;
+use crate::crates::{invalidate_crates_io_cache, SparseIndex};
+use crate::errors::*;
+
+/// Lock file in the cache directory.
+const LOCK_FILE: &str = ".lock";
+
+/// What `takopack cargo update` does.
+#[derive(Debug, Clone, Default)]
+pub struct UpdateOptions {
+    /// Only refresh the index entries of these crates.
+    pub crates: Vec<String>,
+    /// Only refresh entries last validated longer ago than this.
+    pub auto_refresh: Option<Duration>,
+    /// Report the state of the cache without changing it.
+    pub status: bool,
+    /// Empty the [`CrateCache`] instead of touching the index.
+    pub crate_cache: bool,
+}
+
+/// A crate's file in the index cache.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheEntry {
+    pub crate_name: String,
+    pub path: PathBuf,
+    /// Time since the entry was last fetched or revalidated.
+    pub age: Duration,
+}
+
+/// Summary of the index cache for `update --status`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheStatus {
+    pub dir: PathBuf,
+    pub entries: usize,
+    pub oldest: Option<Duration>,
+    pub newest: Option<Duration>,
+}
+
+impl fmt::Display for CacheStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Index cache: {}", self.dir.display())?;
+        write!(f, "Entries:     {}", self.entries)?;
+        if let (Some(oldest), Some(newest)) = (self.oldest, self.newest) {
+            write!(
+                f,
+                "\nOldest:      {} ago\nNewest:      {} ago",
+                format_age(oldest),
+                format_age(newest)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+pub fn run_update(options: &UpdateOptions) -> Result<()> {
+    let dir = crate::paths::index_cache_dir()?;
+    if options.status {
+        println!("{}", cache_status(&dir)?);
+        println!("{}", CrateCache::open_default()?.status()?);
+        return Ok(());
+    }
+    if options.crate_cache {
+        let cache = CrateCache::open_default()?;
+        let removed = cache.clear()?;
+        println!(
+            "Removed {} crate(s) from {}",
+            removed,
+            cache.dir().display()
+        );
+        return Ok(());
+    }
+
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let _lock = lock_cache(&dir)?;
+    let index = SparseIndex::new(SparseIndex::CRATES_IO, Some(dir.clone()));
+
+    if !options.crates.is_empty() {
+        for crate_name in &options.crates {
+            index.refresh(crate_name)?;
+            println!("Refreshed index entry of {}", crate_name);
+        }
+        return Ok(());
+    }
+
+    if let Some(max_age) = options.auto_refresh {
+        let stale = stale_entries(cache_entries(&dir)?, max_age);
+        let mut failed = 0;
+        for entry in &stale {
+            if let Err(e) = index.refresh(&entry.crate_name) {
+                takopack_warn!("could not refresh {}: {:#}", entry.crate_name, e);
+                failed += 1;
+            }
+        }
+        println!(
+            "Refreshed {} of {} index entries older than {}",
+            stale.len() - failed,
+            stale.len(),
+            format_age(max_age)
+        );
+        return Ok(());
+    }
+
+    invalidate_crates_io_cache()?;
+    let entries = cache_entries(&dir)?;
+    for entry in &entries {
+        remove_entry(&entry.path)?;
+    }
+    println!(
+        "Invalidated the cargo registry cache and {} cached index entries",
+        entries.len()
+    );
+    Ok(())
+}
+
+/// Hold an exclusive lock on the cache in `dir`, waiting for other holders.
+fn lock_cache(dir: &Path) -> Result<FileLock> {
+    let context = GlobalContext::default()?;
+    Filesystem::new(dir.to_path_buf()).open_rw_exclusive_create(LOCK_FILE, &context, "index cache")
+}
+
+pub fn cache_status(dir: &Path) -> Result<CacheStatus> {
+    let entries = cache_entries(dir)?;
+    Ok(CacheStatus {
+        dir: dir.to_path_buf(),
+        entries: entries.len(),
+        oldest: entries.iter().map(|e| e.age).max(),
+        newest: entries.iter().map(|e| e.age).min(),
+    })
+}
+
+/// Every crate file cached under `dir`; a missing directory is empty.
+pub fn cache_entries(dir: &Path) -> Result<Vec<CacheEntry>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let now = SystemTime::now();
+    let mut entries = Vec::new();
+    for file in walkdir::WalkDir::new(dir).sort_by_file_name() {
+        let file = file.context("walkdir error")?;
+        let name = file.file_name().to_string_lossy();
+        if !file.file_type().is_file() || name.starts_with('.') || file.path().extension().is_some()
+        {
+            continue;
+        }
+        let modified = file
+            .metadata()
+            .context("walkdir error")?
+            .modified()
+            .with_context(|| format!("Failed to read the age of {}", file.path().display()))?;
+        entries.push(CacheEntry {
+            crate_name: name.into_owned(),
+            path: file.path().to_path_buf(),
+            age: now.duration_since(modified).unwrap_or_default(),
+        });
+    }
+    Ok(entries)
+}
+
+fn stale_entries(entries: Vec<CacheEntry>, max_age: Duration) -> Vec<CacheEntry> {
+    entries.into_iter().filter(|e| e.age >= max_age).collect()
+}
+
+fn remove_entry(path: &Path) -> Result<()> {
+    for file in [path.to_path_buf(), path.with_extension("etag")] {
+        match fs::remove_file(&file) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(e).with_context(|| format!("Failed to remove {}", file.display()))
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Replace `path` with `contents` in one step, through a temporary file
+/// next to it.
+pub(crate) fn write_atomically(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(format!(".{}.tmp", std::process::id()));
+    let tmp = PathBuf::from(tmp);
+    fs::write(&tmp, contents)?;
+    fs::rename(&tmp, path).map_err(|e| {
+        let _ = fs::remove_file(&tmp);
+        e
+    })
+}
+
+/// Parse an age such as `90s`, `30m`, `12h` or `7d`.
+pub fn parse_age(age: &str) -> std::result::Result<Duration, String> {
+    let age = age.trim();
+    let split = age.find(|c: char| !c.is_ascii_digit()).unwrap_or(age.len());
+    let (number, unit) = age.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid age {:?}: expected a number and a unit", age))?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => {
+            return Err(format!(
+                "invalid age {:?}: the unit must be s, m, h or d",
+                age
+            ))
+        }
+    };
+    Ok(Duration::from_secs(number * seconds))
+}
+
+/// `age` in its largest whole unit, e.g. `3d` or `45m`.
+pub fn format_age(age: Duration) -> String {
+    let seconds = age.as_secs();
+    [("d", 24 * 60 * 60), ("h", 60 * 60), ("m", 60)]
+        .iter()
+        .find(|(_, unit)| seconds >= *unit)
+        .map(|(suffix, unit)| format!("{}{}", seconds / unit, suffix))
+        .unwrap_or_else(|| format!("{}s", seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cache_entries, format_age, parse_age, stale_entries, write_atomically};
+    use filetime::{set_file_mtime, FileTime};
+    use std::fs;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn ages_parse_and_format() {
+        assert_eq!(parse_age("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_age("30m"), Ok(Duration::from_secs(30 * 60)));
+        assert_eq!(parse_age("7d"), Ok(Duration::from_secs(7 * 24 * 3600)));
+        assert!(parse_age("12").is_err());
+        assert!(parse_age("h").is_err());
+        assert!(parse_age("3w").is_err());
+
+        assert_eq!(format_age(Duration::from_secs(59)), "59s");
+        assert_eq!(format_age(Duration::from_secs(45 * 60 + 10)), "45m");
+        assert_eq!(format_age(Duration::from_secs(3 * 24 * 3600 + 5)), "3d");
+    }
+
+    #[test]
+    fn entries_are_listed_with_their_age() {
+        let dir = tempfile::tempdir().unwrap();
+        let serde = dir.path().join("se/rd/serde");
+        write_atomically(&serde, b"{}\n").unwrap();
+        write_atomically(&serde.with_extension("etag"), b"\"abc\"").unwrap();
+        write_atomically(&dir.path().join("3/l/log"), b"{}\n").unwrap();
+        fs::write(dir.path().join(".lock"), "").unwrap();
+
+        let day_ago = SystemTime::now() - Duration::from_secs(24 * 3600);
+        set_file_mtime(&serde, FileTime::from_system_time(day_ago)).unwrap();
+
+        let entries = cache_entries(dir.path()).unwrap();
+        let names: Vec<&str> = entries.iter().map(|e| e.crate_name.as_str()).collect();
+        assert_eq!(names, vec!["log", "serde"]);
+        assert_eq!(fs::read_to_string(&serde).unwrap(), "{}\n");
+
+        let stale = stale_entries(entries, Duration::from_secs(3600));
+        assert_eq!(stale.len(), 1);
+        assert_eq!(stale[0].crate_name, "serde");
+        assert!(cache_entries(&dir.path().join("missing"))
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -1,19 +1,62 @@
 #[macro_use]
 pub mod errors;
+pub mod api;
+pub mod audit;
+pub mod cancel;
 pub mod cli;
 pub mod config;
+pub mod copr;
 pub mod crates;
 pub mod dynamic_buildreqs;
+pub mod events;
 pub mod takopack;
 pub mod util;
+pub mod version;
 
+pub mod backup;
 pub mod batch_package;
+pub mod build_order;
+pub mod crate_cache;
+pub mod crate_diff;
+pub mod dep_filter;
+pub mod deps;
+pub mod failure_report;
+pub mod file_modes;
+pub mod git_package;
+pub mod http;
+pub mod index_cache;
+pub mod lint;
 pub mod local_package;
 pub mod lockfile_parser;
+pub mod mirror;
+pub mod name_collision;
+pub mod output_stamp;
+pub mod owners;
 pub mod package;
+pub mod paths;
+pub mod profile;
+pub mod progress;
 pub mod python_package;
 pub mod range_audit;
 pub mod recursive_package;
 pub mod registry_sync;
+pub mod repo_query;
+pub mod repro;
 pub mod resolve_check;
+pub mod rpm_build;
+pub mod self_check;
+pub mod skip_list;
+pub mod spec_diff;
 pub mod spec_from_toml;
+pub mod stream_db;
+pub mod stream_gc;
+pub mod tarball;
+pub mod test_mode;
+pub mod toolchain_crates;
+pub mod update_spec;
+pub mod verbosity;
+pub mod verify;
+pub mod workspace;
+pub mod yanked;
+
+pub use api::{generate_spec, SpecOutput, SpecRequest};
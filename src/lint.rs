@@ -0,0 +1,422 @@
+//! Checks of generated specs before they are shipped.
+//!
+//! Problems in a generated spec used to surface only when rpmbuild or the
+//! distro's review tooling choked on it: a `FIXME` left where metadata was
+//! missing, a Cargo version requirement that did not translate into an rpm
+//! one, a `%files` section without files. `cargo lint` checks a spec, or
+//! every spec under a directory such as the output of `vendor`, and reports
+//! [`Event::LintIssue`]s; with `--output-format json` CI jobs can gate on
+//! them. Errors make the command fail, warnings do not.
+
+use anyhow::Context;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::errors::*;
+use crate::events::{self, Event};
+use crate::takopack::control::SUMMARY_MAX_LEN;
+use crate::takopack::spec::normalize_feature_name;
+use crate::update_spec::is_section;
+
+/// How bad an issue is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// Something wrong in a spec.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    /// 1-based line of the spec; `None` for the spec as a whole
+    pub line: Option<usize>,
+    pub severity: Severity,
+    pub rule: &'static str,
+    pub message: String,
+}
+
+impl Issue {
+    fn new(line: usize, severity: Severity, rule: &'static str, message: String) -> Self {
+        Issue {
+            line: Some(line + 1),
+            severity,
+            rule,
+            message,
+        }
+    }
+}
+
+/// Operators of rpm version comparisons.
+const OPERATORS: &[&str] = &["=", "==", ">=", "<=", ">", "<"];
+
+/// Words joining the terms of rich dependencies.
+const RICH_OPERATORS: &[&str] = &["with", "without", "and", "or", "if", "else", "unless"];
+
+/// Lint `path`, a spec or a directory searched for specs, and report the
+/// issues. Returns the number of errors.
+pub fn run_lint(path: &Path) -> Result<usize> {
+    let specs = find_specs(path)?;
+    if specs.is_empty() {
+        takopack_bail!("no spec files in {}", path.display());
+    }
+    let mut errors = 0;
+    for spec in specs {
+        let text = fs::read_to_string(&spec)
+            .with_context(|| format!("Failed to read {}", spec.display()))?;
+        let features = spec
+            .parent()
+            .map(|dir| declared_features(&dir.join("Cargo.toml")))
+            .unwrap_or_default();
+        for issue in lint_spec(&text, &features) {
+            if issue.severity == Severity::Error {
+                errors += 1;
+            }
+            events::emit(Event::LintIssue {
+                spec: spec.clone(),
+                line: issue.line,
+                severity: issue.severity,
+                rule: issue.rule.to_string(),
+                message: issue.message,
+            });
+        }
+    }
+    Ok(errors)
+}
+
+/// `path` if it is a file, or the spec files under it.
+pub fn find_specs(path: &Path) -> Result<Vec<PathBuf>> {
+    if path.is_file() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    if !path.is_dir() {
+        takopack_bail!("{} does not exist", path.display());
+    }
+    let mut specs = Vec::new();
+    for entry in WalkDir::new(path).sort_by_file_name() {
+        let entry = entry.with_context(|| format!("Failed to read {}", path.display()))?;
+        if entry.file_type().is_file() && entry.path().extension().is_some_and(|e| e == "spec") {
+            specs.push(entry.into_path());
+        }
+    }
+    Ok(specs)
+}
+
+/// The features of the normalized Cargo.toml next to a spec, as they are
+/// named in capabilities.
+fn declared_features(cargo_toml: &Path) -> BTreeSet<String> {
+    let Ok(text) = fs::read_to_string(cargo_toml) else {
+        return BTreeSet::new();
+    };
+    let Ok(manifest) = text.parse::<toml::Table>() else {
+        return BTreeSet::new();
+    };
+    manifest
+        .get("features")
+        .and_then(toml::Value::as_table)
+        .into_iter()
+        .flat_map(|features| features.keys())
+        .map(|feature| normalize_feature_name(feature))
+        .collect()
+}
+
+/// A `%package` of a feature, and whether it provides the feature.
+struct FeaturePackage {
+    line: usize,
+    feature: String,
+    provided: bool,
+}
+
+/// Check what the section ending here left open.
+fn end_section(
+    issues: &mut Vec<Issue>,
+    feature_package: &mut Option<FeaturePackage>,
+    files: &mut Option<(usize, bool)>,
+) {
+    if let Some(package) = feature_package.take().filter(|p| !p.provided) {
+        issues.push(Issue::new(
+            package.line,
+            Severity::Error,
+            "feature-provides",
+            format!(
+                "package of feature `{}` does not provide it",
+                package.feature
+            ),
+        ));
+    }
+    if let Some((line, false)) = files.take() {
+        issues.push(Issue::new(
+            line,
+            Severity::Error,
+            "empty-files",
+            "%files section lists no files".to_string(),
+        ));
+    }
+}
+
+/// The issues of the spec `text`; `features` are the features the crate
+/// declares.
+pub fn lint_spec(text: &str, features: &BTreeSet<String>) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let mut provides = Vec::new();
+    // Tags are only read in the preamble and in `%package` sections
+    let mut in_preamble = true;
+    let mut feature_package: Option<FeaturePackage> = None;
+    // The `%files` line of the section being read, and whether it lists any
+    let mut files: Option<(usize, bool)> = None;
+
+    for (number, line) in text.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.contains("FIXME") {
+            issues.push(Issue::new(
+                number,
+                Severity::Error,
+                "fixme",
+                format!("unresolved FIXME: {}", trimmed),
+            ));
+        }
+        if is_section(trimmed) {
+            end_section(&mut issues, &mut feature_package, &mut files);
+            in_preamble = trimmed.starts_with("%package");
+            if in_preamble {
+                feature_package = trimmed.rsplit_once('+').map(|(_, feature)| FeaturePackage {
+                    line: number,
+                    feature: feature.to_string(),
+                    provided: false,
+                });
+            } else if trimmed.starts_with("%files") {
+                files = Some((number, false));
+            }
+            continue;
+        }
+        if let Some((_, listed)) = &mut files {
+            *listed |= !trimmed.is_empty() && !trimmed.starts_with('#');
+            continue;
+        }
+        if !in_preamble {
+            continue;
+        }
+        let Some((tag, value)) = trimmed.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match tag {
+            // `%%` is one character of the summary rpm shows.
+            "Summary" if summary_len(value) > SUMMARY_MAX_LEN => issues.push(Issue::new(
+                number,
+                Severity::Warning,
+                "summary-length",
+                format!(
+                    "Summary is {} characters long, more than {}",
+                    summary_len(value),
+                    SUMMARY_MAX_LEN
+                ),
+            )),
+            "License" => {
+                if let Err(problem) = check_license(value) {
+                    issues.push(Issue::new(
+                        number,
+                        Severity::Error,
+                        "license",
+                        format!("invalid license `{}`: {}", value, problem),
+                    ));
+                }
+            }
+            "Provides" => {
+                if let Some(package) = &mut feature_package {
+                    package.provided |= value.contains(&format!("/{})", package.feature));
+                }
+                provides.push(value.to_string());
+            }
+            tag if tag == "BuildRequires" || tag == "Requires" || tag.starts_with("Requires(") => {
+                if let Err(problem) = check_requirement(value) {
+                    issues.push(Issue::new(
+                        number,
+                        Severity::Error,
+                        "requires-version",
+                        format!("unparsable requirement `{}`: {}", value, problem),
+                    ));
+                }
+            }
+            _ => {}
+        }
+    }
+    end_section(&mut issues, &mut feature_package, &mut files);
+
+    for feature in features {
+        let capability = format!("/{})", feature);
+        if !provides.iter().any(|p| p.contains(&capability)) {
+            issues.push(Issue {
+                line: None,
+                severity: Severity::Warning,
+                rule: "feature-provides",
+                message: format!("feature `{}` of Cargo.toml is not provided", feature),
+            });
+        }
+    }
+    issues
+}
+
+/// Length of a `Summary:` value as rpm shows it, with `%%` read back as `%`.
+fn summary_len(value: &str) -> usize {
+    value.replace("%%", "%").chars().count()
+}
+
+/// Whether `value` of a `Requires:` is something rpm parses: a name, or a
+/// name, an operator and a version, or a rich dependency of those.
+fn check_requirement(value: &str) -> std::result::Result<(), String> {
+    let rich = value.starts_with('(');
+    if rich && !value.ends_with(')') {
+        return Err("unbalanced parentheses".to_string());
+    }
+    let inner = if rich {
+        &value[1..value.len() - 1]
+    } else {
+        value
+    };
+    let words: Vec<&str> = inner.split_whitespace().collect();
+    let terms: Vec<&[&str]> = if rich {
+        words.split(|word| RICH_OPERATORS.contains(word)).collect()
+    } else {
+        vec![&words]
+    };
+    for term in terms {
+        match term {
+            [_name] => {}
+            [_name, operator, version] => {
+                if !OPERATORS.contains(operator) {
+                    return Err(format!("unknown operator `{}`", operator));
+                }
+                let valid_start = version
+                    .chars()
+                    .next()
+                    .is_some_and(|c| c.is_ascii_alphanumeric() || c == '%');
+                let valid_chars = version
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "._+~^:%{}?-".contains(c));
+                if !valid_start || !valid_chars {
+                    return Err(format!("invalid version `{}`", version));
+                }
+            }
+            [] => return Err("empty requirement".to_string()),
+            _ => return Err(format!("cannot parse `{}`", term.join(" "))),
+        }
+    }
+    Ok(())
+}
+
+/// Whether `value` of `License:` is an SPDX expression: license ids joined
+/// with `AND`, `OR` and `WITH`, and grouped in parentheses.
+fn check_license(value: &str) -> std::result::Result<(), String> {
+    let spaced = value.replace('(', " ( ").replace(')', " ) ");
+    let mut depth = 0usize;
+    // Whether the next token has to be a license id (or an opening paren)
+    let mut want_id = true;
+    for token in spaced.split_whitespace() {
+        match token {
+            "(" if want_id => depth += 1,
+            ")" if !want_id && depth > 0 => depth -= 1,
+            "AND" | "OR" | "WITH" if !want_id => want_id = true,
+            "(" | ")" => return Err("misplaced parenthesis".to_string()),
+            "AND" | "OR" | "WITH" => return Err(format!("misplaced `{}`", token)),
+            id if want_id => {
+                let valid = id
+                    .trim_end_matches('+')
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-');
+                if !valid || id == "+" {
+                    return Err(format!("`{}` is no license id", id));
+                }
+                want_id = false;
+            }
+            id => return Err(format!("missing operator before `{}`", id)),
+        }
+    }
+    if want_id {
+        return Err("expression is empty or incomplete".to_string());
+    }
+    if depth > 0 {
+        return Err("unbalanced parentheses".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_license, check_requirement, lint_spec, summary_len, Severity};
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn lint_finds_broken_specs() {
+        let spec = "\
+Name:           rust-demo
+Summary:        Rust crate \"demo\" with a summary that goes on and on until it is really far too long
+License:        MIT/Apache-2.0
+BuildRequires:  crate(serde/default) >= 1.0.0
+Requires:       crate(log/default) ^0.4
+
+%description
+Requires: nothing, this is text. FIXME
+
+%package     -n %{name}+std
+Summary:        std feature
+Provides:       crate(%{pkgname}/std) = %{version}
+
+%package     -n %{name}+alloc
+Summary:        alloc feature
+
+%files
+%license LICENSE
+
+%files -n %{name}+alloc
+
+%changelog
+";
+        let features: BTreeSet<String> = ["std", "alloc", "serde"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let issues: Vec<_> = lint_spec(spec, &features)
+            .into_iter()
+            .map(|issue| (issue.line, issue.severity, issue.rule))
+            .collect();
+        assert_eq!(
+            issues,
+            [
+                (Some(2), Severity::Warning, "summary-length"),
+                (Some(3), Severity::Error, "license"),
+                (Some(5), Severity::Error, "requires-version"),
+                (Some(8), Severity::Error, "fixme"),
+                (Some(14), Severity::Error, "feature-provides"),
+                (Some(20), Severity::Error, "empty-files"),
+                (None, Severity::Warning, "feature-provides"),
+                (None, Severity::Warning, "feature-provides"),
+            ]
+        );
+
+        assert_eq!(summary_len("100%% safe Rust"), 14);
+        assert!(check_license("(MIT OR Apache-2.0) AND Unicode-DFS-2016").is_ok());
+        assert!(check_license("Apache-2.0 WITH LLVM-exception").is_ok());
+        assert!(check_license("GPL-2.0+").is_ok());
+        assert!(check_license("").is_err());
+        assert!(check_license("MIT OR").is_err());
+        assert!(check_license("(MIT").is_err());
+        assert!(check_requirement("(crate(a) >= 1.0 with crate(a) < 2.0~)").is_ok());
+        assert!(check_requirement("rust >= 1.70").is_ok());
+        assert!(check_requirement("crate(a) >= *").is_err());
+        assert!(check_requirement("crate(a) >=").is_err());
+    }
+}
@@ -6,10 +6,14 @@ use toml::Value;
 
 use crate::config::Config;
 use crate::crates::CrateInfo;
+use crate::events::{self, Event};
 use crate::package::PackageExecuteArgs;
 use crate::range_audit::{self, RangeCapabilityPolicy};
 use crate::takopack::{self, DebInfo};
 use crate::util::write_file_ensuring_dir;
+use crate::workspace::{
+    manifest_inherits_from_workspace, patched_dependencies, PatchedDependency, WorkspaceRoot,
+};
 
 /// Process a local crate directory and generate spec file
 pub fn process_local_package(
@@ -17,6 +21,18 @@ pub fn process_local_package(
     output_dir: Option<PathBuf>,
     finish_args: PackageExecuteArgs,
     range_capability_policy: RangeCapabilityPolicy,
+) -> Result<()> {
+    process_local_package_with_source(path, output_dir, finish_args, range_capability_policy, None)
+}
+
+/// Like [`process_local_package`], writing `source_url` as the spec Source:
+/// line instead of the crates.io download URL.
+pub(crate) fn process_local_package_with_source(
+    path: &Path,
+    output_dir: Option<PathBuf>,
+    finish_args: PackageExecuteArgs,
+    range_capability_policy: RangeCapabilityPolicy,
+    source_url: Option<&str>,
 ) -> Result<()> {
     // Canonicalize the path first to get absolute path
     let path_abs =
@@ -43,12 +59,130 @@ pub fn process_local_package(
         );
     };
 
+    if let Some(workspace) = WorkspaceRoot::load(&cargo_toml)? {
+        return process_local_workspace(
+            &workspace,
+            output_dir,
+            finish_args,
+            range_capability_policy,
+            source_url,
+        );
+    }
+
+    process_local_manifest(
+        &cargo_toml,
+        None,
+        LocalOutput::Package(output_dir),
+        finish_args,
+        range_capability_policy,
+        source_url,
+    )
+}
+
+/// Where the generated spec of a local crate is written.
+enum LocalOutput {
+    /// Files go directly into this directory (default: `rust-{name}-{compat}/`).
+    Package(Option<PathBuf>),
+    /// Files go into `rust-{name}-{compat}/` under this root (default: current directory).
+    Root(Option<PathBuf>),
+}
+
+impl LocalOutput {
+    fn final_dir(&self, output_names: &crate::util::RustCrateOutputNames) -> Result<PathBuf> {
+        match self {
+            LocalOutput::Package(dir) => {
+                crate::util::package_final_output_dir(dir.as_deref(), output_names)
+            }
+            LocalOutput::Root(root) => {
+                let dir = root.as_ref().map(|root| root.join(&output_names.directory));
+                crate::util::package_final_output_dir(dir.as_deref(), output_names)
+            }
+        }
+    }
+}
+
+/// Generate one spec per member of a workspace, resolving `workspace = true`
+/// fields against the root manifest.
+///
+/// A package whose `[workspace]` only contains itself keeps the single-crate
+/// output layout; otherwise `output_root` receives one directory per member.
+fn process_local_workspace(
+    workspace: &WorkspaceRoot,
+    output_root: Option<PathBuf>,
+    finish_args: PackageExecuteArgs,
+    range_capability_policy: RangeCapabilityPolicy,
+    source_url: Option<&str>,
+) -> Result<()> {
+    let members = workspace.member_manifests()?;
+    if members.is_empty() {
+        anyhow::bail!(
+            "Workspace at {:?} has no members with a Cargo.toml",
+            workspace.manifest_path
+        );
+    }
+
+    if !workspace.is_virtual() && members.len() == 1 {
+        return process_local_manifest(
+            &members[0],
+            Some(workspace),
+            LocalOutput::Package(output_root),
+            finish_args,
+            range_capability_policy,
+            source_url,
+        );
+    }
+
+    events::emit(Event::note(format!(
+        "Workspace {:?}: {} member(s)",
+        workspace.dir,
+        members.len()
+    )));
+
+    let mut failed = Vec::new();
+    for member in &members {
+        if let Err(e) = process_local_manifest(
+            member,
+            Some(workspace),
+            LocalOutput::Root(output_root.clone()),
+            finish_args.clone(),
+            range_capability_policy,
+            source_url,
+        ) {
+            events::emit(Event::CrateFailed {
+                crate_name: member.display().to_string(),
+                version: String::new(),
+                error: format!("{:#}", e),
+            });
+            failed.push(member);
+        }
+    }
+
+    if !failed.is_empty() {
+        anyhow::bail!(
+            "{} of {} workspace member(s) failed: {:?}",
+            failed.len(),
+            members.len(),
+            failed
+        );
+    }
+
+    Ok(())
+}
+
+fn process_local_manifest(
+    cargo_toml: &Path,
+    workspace: Option<&WorkspaceRoot>,
+    output: LocalOutput,
+    finish_args: PackageExecuteArgs,
+    range_capability_policy: RangeCapabilityPolicy,
+    source_url: Option<&str>,
+) -> Result<()> {
     log::info!("Processing local crate from: {:?}", cargo_toml);
 
+    let patches = RootPatches::load(cargo_toml, workspace)?;
     let temp_crate_dir =
         tempfile::tempdir().context("Failed to create temporary crate directory")?;
-    let temp_cargo_toml =
-        materialize_manifest_backed_temp_crate(&cargo_toml, temp_crate_dir.path())?;
+    let temp_cargo_toml = materialize_temp_crate(cargo_toml, workspace, temp_crate_dir.path())?;
 
     log::info!(
         "Temporary crate structure created at: {:?}",
@@ -59,21 +193,158 @@ pub fn process_local_package(
     process_complete_crate(
         temp_crate_dir.path(),
         &temp_cargo_toml,
-        output_dir,
+        &patches,
+        output,
         finish_args,
         range_capability_policy,
+        source_url,
     )
 }
 
+/// The dependencies that the root manifest of a local crate replaces with
+/// `[patch]` or `[replace]`.
+struct RootPatches {
+    /// Directory of the root manifest, which paths in the patches are
+    /// relative to
+    dir: PathBuf,
+    dependencies: Vec<PatchedDependency>,
+}
+
+impl RootPatches {
+    /// Cargo reads the patches of `cargo_toml` from the root of its
+    /// workspace, or from itself outside any workspace.
+    fn load(cargo_toml: &Path, workspace: Option<&WorkspaceRoot>) -> Result<RootPatches> {
+        let discovered;
+        let workspace = match workspace {
+            Some(workspace) => Some(workspace),
+            None => {
+                // A broken `package.workspace` is only fatal when fields are
+                // inherited, see materialize_temp_crate
+                discovered = WorkspaceRoot::discover(cargo_toml).ok().flatten();
+                discovered.as_ref()
+            }
+        };
+        if let Some(workspace) = workspace {
+            return Ok(RootPatches {
+                dir: workspace.dir.clone(),
+                dependencies: workspace.patched_dependencies(),
+            });
+        }
+        let manifest = read_toml(cargo_toml)?;
+        Ok(RootPatches {
+            dir: cargo_toml
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_else(|| PathBuf::from(".")),
+            dependencies: patched_dependencies(&manifest),
+        })
+    }
+
+    /// Warn about the patched dependencies of `crate_info` that
+    /// `[patched_crates]` does not map, and read the crates its `path`
+    /// entries point to.
+    fn apply(&self, config: &mut Config, crate_info: &CrateInfo) -> Result<()> {
+        for patched in &self.dependencies {
+            let depended_on = crate_info
+                .dependencies()
+                .iter()
+                .any(|dep| dep.package_name().as_str() == patched.name);
+            if depended_on && !config.patched_crates.contains_key(&patched.name) {
+                takopack_warn!(
+                    "{} replaces {} with {}; the spec requires its crates.io release instead. Map it to a distro package or a local crate in [patched_crates.{}] of takopack.toml.",
+                    patched.table,
+                    patched.name,
+                    patched.source,
+                    patched.name
+                );
+            }
+        }
+        for target in config.patched_crates.values_mut() {
+            let Some(path) = target.path.take() else {
+                continue;
+            };
+            let cargo_toml = self.dir.join(&path).join("Cargo.toml");
+            let manifest = read_toml(&cargo_toml)?;
+            let field = |key: &str| {
+                manifest
+                    .get("package")
+                    .and_then(|package| package.get(key))
+                    .and_then(Value::as_str)
+                    .map(str::to_string)
+                    .with_context(|| format!("{:?} has no package.{}", cargo_toml, key))
+            };
+            target.crate_name = Some(field("name")?);
+            target.version = Some(field("version")?);
+        }
+        Ok(())
+    }
+}
+
+fn read_toml(path: &Path) -> Result<Value> {
+    let content = fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))?;
+    toml::from_str(&content).with_context(|| format!("Failed to parse {:?}", path))
+}
+
 pub(crate) fn materialize_manifest_backed_temp_crate(
     cargo_toml: &Path,
     temp_dir: &Path,
 ) -> Result<PathBuf> {
-    let cargo_toml_content = fs::read_to_string(cargo_toml)
+    materialize_temp_crate(cargo_toml, None, temp_dir)
+}
+
+/// Like [`materialize_manifest_backed_temp_crate`], but for a workspace member:
+/// inherited fields are resolved against `workspace` so the copied manifest
+/// stands on its own. Without an explicit `workspace`, a manifest that uses
+/// `workspace = true` is resolved against the root found by walking up from it.
+pub(crate) fn materialize_temp_crate(
+    cargo_toml: &Path,
+    workspace: Option<&WorkspaceRoot>,
+    temp_dir: &Path,
+) -> Result<PathBuf> {
+    let mut cargo_toml_content = fs::read_to_string(cargo_toml)
         .with_context(|| format!("Failed to read Cargo.toml: {:?}", cargo_toml))?;
-    let manifest: Value = toml::from_str(&cargo_toml_content)
+    let mut manifest: Value = toml::from_str(&cargo_toml_content)
         .with_context(|| format!("Failed to parse Cargo.toml: {:?}", cargo_toml))?;
 
+    let discovered;
+    let workspace = match workspace {
+        Some(workspace) => Some(workspace),
+        None if manifest_inherits_from_workspace(&manifest) => {
+            discovered = WorkspaceRoot::discover(cargo_toml)?.with_context(|| {
+                format!(
+                    "{:?} inherits fields with `workspace = true` but no workspace root was found above it",
+                    cargo_toml
+                )
+            })?;
+            log::info!(
+                "Resolving workspace inheritance from {:?}",
+                discovered.manifest_path
+            );
+            Some(&discovered)
+        }
+        None => None,
+    };
+
+    if let Some(workspace) = workspace {
+        let member_dir = cargo_toml.parent().unwrap_or_else(|| Path::new("."));
+        workspace
+            .inherit_into(&mut manifest, member_dir)
+            .with_context(|| {
+                format!(
+                    "Failed to resolve workspace inheritance for {:?}",
+                    cargo_toml
+                )
+            })?;
+        // A member's own [workspace] table would make cargo look for members
+        // inside the temporary directory.
+        if let Some(table) = manifest.as_table_mut() {
+            table.remove("workspace");
+        }
+        cargo_toml_content = toml::to_string(&manifest).with_context(|| {
+            format!("Failed to serialize resolved Cargo.toml: {:?}", cargo_toml)
+        })?;
+    }
+
     let temp_cargo_toml = temp_dir.join("Cargo.toml");
     fs::write(&temp_cargo_toml, cargo_toml_content).with_context(|| {
         format!(
@@ -82,12 +353,15 @@ pub(crate) fn materialize_manifest_backed_temp_crate(
         )
     })?;
 
-    if let Some(parent) = cargo_toml.parent() {
-        let config = parent.join("takopack.toml");
-        if config.exists() {
-            fs::copy(&config, temp_dir.join("takopack.toml"))
-                .with_context(|| format!("Failed to copy takopack.toml from {:?}", config))?;
-        }
+    let config_dirs = [cargo_toml.parent(), workspace.map(|w| w.dir.as_path())];
+    if let Some(config) = config_dirs
+        .into_iter()
+        .flatten()
+        .map(|dir| dir.join("takopack.toml"))
+        .find(|config| config.exists())
+    {
+        fs::copy(&config, temp_dir.join("takopack.toml"))
+            .with_context(|| format!("Failed to copy takopack.toml from {:?}", config))?;
     }
 
     materialize_manifest_paths(&manifest, temp_dir)?;
@@ -254,22 +528,38 @@ fn safe_manifest_relative_path(path: &str) -> Result<PathBuf> {
 fn process_complete_crate(
     temp_crate_dir: &Path,
     cargo_toml: &Path,
-    output_dir: Option<PathBuf>,
+    patches: &RootPatches,
+    output: LocalOutput,
     finish_args: PackageExecuteArgs,
     range_capability_policy: RangeCapabilityPolicy,
+    source_url: Option<&str>,
 ) -> Result<()> {
     // Load config if available
     let config_path = temp_crate_dir.join("takopack.toml");
-    let (config_path, config) = if config_path.exists() {
-        let config = Config::parse(&config_path).context("failed to parse takopack.toml")?;
+    let profile = finish_args.profile.as_deref();
+    let (config_path, mut config) = if config_path.exists() {
+        let config = Config::parse_with_profile(&config_path, profile)
+            .context("failed to parse takopack.toml")?;
         (Some(config_path), config)
     } else {
-        Config::load()?
+        Config::load_with_profile(profile)?
     };
+    if finish_args.with_tests {
+        config.test_build_requires.enabled = true;
+    }
+    config.test_mode = finish_args.test_mode;
+    if let Some(dir) = &finish_args.template_dir {
+        config.template_dir = Some(std::env::current_dir()?.join(dir));
+    }
 
     // Create CrateInfo from local crate (now it has src/ so Cargo APIs will work)
     let mut crate_info = CrateInfo::new_with_local_crate_from_path(cargo_toml)
         .with_context(|| format!("Failed to load crate from: {:?}", cargo_toml))?;
+    if let Some(url) = source_url {
+        crate_info.set_download_url(url);
+    }
+    crate_info.set_test_mode(config.test_mode);
+    patches.apply(&mut config, &crate_info)?;
 
     let crate_name = crate_info.crate_name();
     // It's a full version,like "0.9.11+spec-1.1.0"
@@ -278,14 +568,20 @@ fn process_complete_crate(
     log::info!("Crate: {} {}", crate_name, version);
 
     // Create DebInfo
-    let deb_info = DebInfo::new(&crate_info, env!("CARGO_PKG_VERSION"), config.semver_suffix);
+    let deb_info = DebInfo::new(
+        &crate_info,
+        env!("CARGO_PKG_VERSION"),
+        config.semver_suffix,
+        config.test_mode,
+    );
 
-    let output_names = crate::util::rust_crate_output_names(crate_name, version);
+    let output_names = config.output_names(crate_name, version);
 
     if range_capability_policy != RangeCapabilityPolicy::Allow {
         let warnings = range_audit::audit_cargo_dependencies(
             crate_info.dependencies(),
             Some(&output_names.directory),
+            config.crate_namespace(),
         );
         if range_audit::emit_warnings(&warnings, range_capability_policy) {
             anyhow::bail!("range capability audit failed (policy: error)");
@@ -293,7 +589,7 @@ fn process_complete_crate(
     }
 
     // Determine final output package directory.
-    let final_output = crate::util::package_final_output_dir(output_dir.as_deref(), &output_names)?;
+    let final_output = output.final_dir(&output_names)?;
 
     fs::create_dir_all(&final_output)
         .with_context(|| format!("Failed to create output directory: {:?}", final_output))?;
@@ -314,6 +610,7 @@ fn process_complete_crate(
         temp_crate_dir,
         &tempdir,
         finish_args.changelog_ready,
+        finish_args.changelog_from,
         finish_args.copyright_guess_harder,
         !finish_args.no_overlay_write_back,
         None, // TODO: sha256: local packages don't have downloaded crate files, maybe consider record the sha256 when use pkg.
@@ -352,9 +649,13 @@ fn process_complete_crate(
         fs::copy(&source_spec, &final_spec)
             .with_context(|| format!("Failed to copy spec file to: {:?}", final_spec))?;
         crate::util::copy_normalized_cargo_toml_to_dir(temp_crate_dir, &final_output)?;
+        crate::util::copy_copyright_report_to_dir(temp_crate_dir, &final_output)?;
+        config.permissions.apply_to_dir(&final_output)?;
 
         log::info!("Spec file saved to: {}", final_spec.display());
-        println!("Spec file: {}", final_spec.display());
+        events::emit(Event::SpecWritten {
+            spec: final_spec.clone(),
+        });
     } else {
         anyhow::bail!("Spec file not found at: {:?}", source_spec);
     }
@@ -364,10 +665,15 @@ fn process_complete_crate(
 
 #[cfg(test)]
 mod tests {
-    use super::{materialize_manifest_backed_temp_crate, process_local_package};
+    use super::{
+        materialize_manifest_backed_temp_crate, materialize_temp_crate, process_local_package,
+    };
     use crate::package::PackageExecuteArgs;
     use crate::range_audit::RangeCapabilityPolicy;
+    use crate::takopack::changelog::ChangelogSource;
+    use crate::test_mode::TestMode;
     use crate::util::rust_crate_output_names;
+    use crate::workspace::WorkspaceRoot;
     use semver::Version;
     use std::fs;
 
@@ -547,10 +853,15 @@ edition = "2021"
 
         let finish = PackageExecuteArgs {
             changelog_ready: false,
+            changelog_from: ChangelogSource::None,
             copyright_guess_harder: false,
             no_overlay_write_back: false,
             with_spdx: false,
+            with_tests: false,
+            template_dir: None,
+            profile: None,
             lockfile_deps: None,
+            test_mode: TestMode::default(),
         };
 
         let output_names =
@@ -569,4 +880,170 @@ edition = "2021"
         assert!(package_dir.join("Cargo.toml").exists());
         assert!(!output.path().join(&output_names.directory).exists());
     }
+
+    #[test]
+    fn localpkg_resolves_workspace_inheritance_for_members() {
+        let source = tempfile::tempdir().unwrap();
+        let temp = tempfile::tempdir().unwrap();
+        fs::write(
+            source.path().join("Cargo.toml"),
+            r#"
+[workspace]
+members = ["member"]
+
+[workspace.package]
+version = "2.0.1"
+edition = "2021"
+
+[workspace.dependencies]
+serde = "1.0.100"
+"#,
+        )
+        .unwrap();
+        fs::write(source.path().join("takopack.toml"), "[source]\n").unwrap();
+        fs::create_dir_all(source.path().join("member")).unwrap();
+        fs::write(
+            source.path().join("member/Cargo.toml"),
+            r#"
+[package]
+name = "member"
+version.workspace = true
+edition.workspace = true
+
+[dependencies]
+serde = { workspace = true, features = ["derive"] }
+"#,
+        )
+        .unwrap();
+
+        let workspace = WorkspaceRoot::load(&source.path().join("Cargo.toml"))
+            .unwrap()
+            .unwrap();
+        let manifest = materialize_temp_crate(
+            &source.path().join("member/Cargo.toml"),
+            Some(&workspace),
+            temp.path(),
+        )
+        .unwrap();
+
+        let manifest: toml::Value = toml::from_str(&fs::read_to_string(manifest).unwrap()).unwrap();
+        assert_eq!(manifest["package"]["version"].as_str(), Some("2.0.1"));
+        assert_eq!(
+            manifest["dependencies"]["serde"]["version"].as_str(),
+            Some("1.0.100")
+        );
+        assert!(temp.path().join("takopack.toml").exists());
+        assert!(temp.path().join("src/lib.rs").exists());
+    }
+
+    #[test]
+    fn localpkg_generates_one_spec_per_workspace_member() {
+        let source = tempfile::tempdir().unwrap();
+        let output = tempfile::tempdir().unwrap();
+        fs::write(
+            source.path().join("Cargo.toml"),
+            r#"
+[workspace]
+members = ["crates/*"]
+
+[workspace.package]
+version = "0.3.0"
+edition = "2021"
+"#,
+        )
+        .unwrap();
+        for name in ["ws_alpha", "ws_beta"] {
+            let dir = source.path().join("crates").join(name);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(
+                dir.join("Cargo.toml"),
+                format!(
+                    "[package]\nname = \"{name}\"\nversion.workspace = true\nedition.workspace = true\n"
+                ),
+            )
+            .unwrap();
+        }
+
+        let finish = PackageExecuteArgs {
+            changelog_ready: false,
+            changelog_from: ChangelogSource::None,
+            copyright_guess_harder: false,
+            no_overlay_write_back: false,
+            with_spdx: false,
+            with_tests: false,
+            template_dir: None,
+            profile: None,
+            lockfile_deps: None,
+            test_mode: TestMode::default(),
+        };
+
+        process_local_package(
+            source.path(),
+            Some(output.path().to_path_buf()),
+            finish,
+            RangeCapabilityPolicy::Allow,
+        )
+        .unwrap();
+
+        for name in ["ws_alpha", "ws_beta"] {
+            let names = rust_crate_output_names(name, &Version::parse("0.3.0").unwrap());
+            let package_dir = output.path().join(&names.directory);
+            assert!(
+                package_dir.join(&names.spec_file).exists(),
+                "missing {name}"
+            );
+            assert!(package_dir.join("Cargo.toml").exists());
+        }
+    }
+
+    #[test]
+    fn localpkg_discovers_workspace_root_for_member_path() {
+        let source = tempfile::tempdir().unwrap();
+        let temp = tempfile::tempdir().unwrap();
+        fs::write(
+            source.path().join("Cargo.toml"),
+            r#"
+[workspace]
+members = ["member"]
+
+[workspace.package]
+version = "0.7.2"
+
+[workspace.dependencies]
+log = { version = "0.4.20", default-features = false }
+"#,
+        )
+        .unwrap();
+        fs::create_dir_all(source.path().join("member")).unwrap();
+        fs::write(
+            source.path().join("member/Cargo.toml"),
+            r#"
+[package]
+name = "member"
+version.workspace = true
+edition = "2021"
+
+[dependencies]
+log.workspace = true
+"#,
+        )
+        .unwrap();
+
+        let manifest = materialize_manifest_backed_temp_crate(
+            &source.path().join("member/Cargo.toml"),
+            temp.path(),
+        )
+        .unwrap();
+
+        let manifest: toml::Value = toml::from_str(&fs::read_to_string(manifest).unwrap()).unwrap();
+        assert_eq!(manifest["package"]["version"].as_str(), Some("0.7.2"));
+        assert_eq!(
+            manifest["dependencies"]["log"]["version"].as_str(),
+            Some("0.4.20")
+        );
+        assert_eq!(
+            manifest["dependencies"]["log"]["default-features"].as_bool(),
+            Some(false)
+        );
+    }
 }
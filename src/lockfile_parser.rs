@@ -1,16 +1,20 @@
 use anyhow::{Context, Result};
 use cargo::core::Resolve;
 use semver::Version;
-use std::collections::BTreeMap;
+use serde_derive::Deserialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::Path;
+use std::sync::Arc;
+
+use crate::crates::SparseIndex;
 
 /// Information about a package in the dependency graph
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct PackageInfo {
-    /// Package name
-    pub name: String,
-    /// Package version
-    pub version: Version,
+    /// Package name (interned, shared with every reference to this crate)
+    pub name: Arc<str>,
+    /// Package version (shared with every dependency edge pointing here)
+    pub version: Arc<Version>,
     /// Dependencies of this package (name and version)
     pub dependencies: Vec<DependencyInfo>,
 }
@@ -19,17 +23,18 @@ pub struct PackageInfo {
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct DependencyInfo {
     /// Dependency name
-    pub name: String,
+    pub name: Arc<str>,
     /// Dependency version
-    pub version: Version,
+    pub version: Arc<Version>,
 }
 
 /// Complete dependency graph parsed from Cargo.lock
 #[derive(Debug, Clone)]
 pub struct DependencyGraph {
-    /// Map from (package_name, version) to PackageInfo
-    /// We use BTreeMap to handle multiple versions of the same crate
-    packages: BTreeMap<(String, Version), PackageInfo>,
+    /// Map from package name to its versions
+    /// We use nested maps so lookups by name need neither allocation nor a full scan
+    packages: BTreeMap<Arc<str>, BTreeMap<Arc<Version>, PackageInfo>>,
+    len: usize,
 }
 
 impl DependencyGraph {
@@ -37,42 +42,46 @@ impl DependencyGraph {
     pub fn new() -> Self {
         Self {
             packages: BTreeMap::new(),
+            len: 0,
         }
     }
 
     /// Add a package to the dependency graph
     pub fn add_package(&mut self, package: PackageInfo) {
-        let key = (package.name.clone(), package.version.clone());
-        self.packages.insert(key, package);
+        let versions = self.packages.entry(package.name.clone()).or_default();
+        if versions.insert(package.version.clone(), package).is_none() {
+            self.len += 1;
+        }
     }
 
     /// Get all packages in the dependency graph
     pub fn packages(&self) -> impl Iterator<Item = &PackageInfo> {
-        self.packages.values()
+        self.packages.values().flat_map(BTreeMap::values)
     }
 
     /// Get a specific package by name and version
     pub fn get_package(&self, name: &str, version: &Version) -> Option<&PackageInfo> {
-        self.packages.get(&(name.to_string(), version.clone()))
+        self.packages.get(name)?.get(version)
     }
 
     /// Get all versions of a crate
     pub fn get_versions(&self, name: &str) -> Vec<&Version> {
         self.packages
-            .keys()
-            .filter(|(pkg_name, _)| pkg_name == name)
-            .map(|(_, version)| version)
+            .get(name)
+            .into_iter()
+            .flat_map(BTreeMap::keys)
+            .map(|version| &**version)
             .collect()
     }
 
     /// Get total number of packages (including different versions)
     pub fn len(&self) -> usize {
-        self.packages.len()
+        self.len
     }
 
     /// Check if the dependency graph is empty
     pub fn is_empty(&self) -> bool {
-        self.packages.is_empty()
+        self.len == 0
     }
 
     /// Get dependencies for a specific package as a HashMap
@@ -81,22 +90,73 @@ impl DependencyGraph {
         &self,
         name: &str,
         version: &Version,
-    ) -> Option<std::collections::HashMap<String, Version>> {
+    ) -> Option<HashMap<String, Version>> {
         self.get_package(name, version).map(|pkg| {
             pkg.dependencies
                 .iter()
-                .map(|dep| (dep.name.clone(), dep.version.clone()))
+                .map(|dep| (dep.name.to_string(), (*dep.version).clone()))
                 .collect()
         })
     }
 }
 
+impl DependencyGraph {
+    /// Look every package up in `index` and return the ones whose pinned
+    /// version has been yanked. Crates the index cannot answer for are
+    /// reported as a warning and otherwise ignored.
+    pub fn yanked_packages(&self, index: &SparseIndex) -> Vec<(String, Version)> {
+        let mut yanked = Vec::new();
+        for package in self.packages() {
+            match index.is_yanked(&package.name, &package.version) {
+                Ok(Some(true)) => {
+                    yanked.push((package.name.to_string(), (*package.version).clone()))
+                }
+                Ok(_) => {}
+                Err(e) => takopack_warn!(
+                    "could not check whether {} {} is yanked: {:#}",
+                    package.name,
+                    package.version,
+                    e
+                ),
+            }
+        }
+        yanked
+    }
+}
+
 impl Default for DependencyGraph {
     fn default() -> Self {
         Self::new()
     }
 }
 
+/// Hands out one shared allocation per distinct string / version.
+#[derive(Default)]
+struct Interner {
+    names: HashSet<Arc<str>>,
+    versions: HashSet<Arc<Version>>,
+}
+
+impl Interner {
+    fn name(&mut self, name: &str) -> Arc<str> {
+        if let Some(interned) = self.names.get(name) {
+            return interned.clone();
+        }
+        let interned: Arc<str> = Arc::from(name);
+        self.names.insert(interned.clone());
+        interned
+    }
+
+    fn version(&mut self, version: Version) -> Arc<Version> {
+        if let Some(interned) = self.versions.get(&version) {
+            return interned.clone();
+        }
+        let interned = Arc::new(version);
+        self.versions.insert(interned.clone());
+        interned
+    }
+}
+
 /// Parse a Cargo.lock file and extract the complete dependency graph
 ///
 /// # Arguments
@@ -118,23 +178,34 @@ pub fn parse_lockfile(lockfile_path: &Path) -> Result<DependencyGraph> {
     let content = fs::read_to_string(lockfile_path)
         .with_context(|| format!("Failed to read Cargo.lock: {:?}", lockfile_path))?;
 
-    // Parse using cargo's internal TOML parser
-    let lockfile: toml::Value = toml::de::from_str(&content)
-        .with_context(|| format!("Failed to parse Cargo.lock as TOML: {:?}", lockfile_path))?;
+    parse_lockfile_str(&content)
+        .with_context(|| format!("Failed to parse Cargo.lock: {:?}", lockfile_path))
+}
 
-    // Build dependency graph from parsed TOML
-    build_dependency_graph_from_toml(&lockfile)
+/// Parse the contents of a Cargo.lock file. See [`parse_lockfile`].
+pub fn parse_lockfile_str(content: &str) -> Result<DependencyGraph> {
+    let lockfile = parse_raw_lockfile(content)?;
+    let built = build_dependency_graph_from_lockfile(lockfile, None)?;
+    report_skipped(&built.skipped);
+    Ok(built.graph)
+}
+
+/// Deserialize straight into the few fields we need instead of building a
+/// generic TOML tree of the whole lockfile.
+fn parse_raw_lockfile(content: &str) -> Result<RawLockfile> {
+    toml::de::from_str(content).context("Failed to parse Cargo.lock as TOML")
 }
 
 /// Build a DependencyGraph from a Resolve
 #[allow(unused)]
 fn build_dependency_graph(resolve: &Resolve) -> Result<DependencyGraph> {
     let mut graph = DependencyGraph::new();
+    let mut interner = Interner::default();
 
     // Iterate through all packages in the resolve graph
     for package_id in resolve.iter() {
-        let name = package_id.name().to_string();
-        let version = package_id.version().clone();
+        let name = interner.name(package_id.name().as_str());
+        let version = interner.version(package_id.version().clone());
 
         // Get dependencies for this package
         let mut dependencies = Vec::new();
@@ -143,8 +214,8 @@ fn build_dependency_graph(resolve: &Resolve) -> Result<DependencyGraph> {
         // The PackageId is the actual resolved dependency with its version
         for (dep_pkg_id, _deps_set) in resolve.deps(package_id) {
             dependencies.push(DependencyInfo {
-                name: dep_pkg_id.name().to_string(),
-                version: dep_pkg_id.version().clone(),
+                name: interner.name(dep_pkg_id.name().as_str()),
+                version: interner.version(dep_pkg_id.version().clone()),
             });
         }
 
@@ -164,143 +235,213 @@ fn build_dependency_graph(resolve: &Resolve) -> Result<DependencyGraph> {
     Ok(graph)
 }
 
-/// Build a DependencyGraph from parsed TOML (Cargo.lock format)
-fn build_dependency_graph_from_toml(lockfile: &toml::Value) -> Result<DependencyGraph> {
-    use std::collections::HashMap;
+/// Dependencies of the workspace member `name` `version` of a Cargo.lock,
+/// with the versions the lockfile pins them to. `None` when there is no such
+/// member; [`parse_lockfile_str`] leaves members out of its graph.
+pub fn member_dependencies(
+    content: &str,
+    name: &str,
+    version: &Version,
+) -> Result<Option<Vec<(String, Version)>>> {
+    let lockfile = parse_raw_lockfile(content)?;
+    let built = build_dependency_graph_from_lockfile(lockfile, Some((name, version)))?;
+    Ok(built.member.map(|member| {
+        member
+            .dependencies
+            .into_iter()
+            .map(|dep| (dep.name.to_string(), (*dep.version).clone()))
+            .collect()
+    }))
+}
+
+/// [`parse_lockfile_str`], with the workspace member `name` `version` added
+/// to the graph so that its dependencies can be looked up too. Fails when
+/// the lockfile has no such member.
+pub fn parse_lockfile_with_member(
+    content: &str,
+    name: &str,
+    version: &Version,
+) -> Result<DependencyGraph> {
+    let lockfile = parse_raw_lockfile(content)?;
+    let mut built = build_dependency_graph_from_lockfile(lockfile, Some((name, version)))?;
+    report_skipped(&built.skipped);
+    let member = built
+        .member
+        .with_context(|| format!("Cargo.lock is not the lockfile of {} {}", name, version))?;
+    built.graph.add_package(member);
+    Ok(built.graph)
+}
+
+#[derive(Deserialize)]
+struct RawLockfile {
+    package: Option<Vec<RawPackage>>,
+}
 
+#[derive(Deserialize)]
+struct RawPackage {
+    name: String,
+    version: String,
+    source: Option<String>,
+    #[serde(default)]
+    dependencies: Vec<String>,
+}
+
+/// What [`build_dependency_graph_from_lockfile`] got out of a Cargo.lock.
+struct BuiltGraph {
+    /// The registry packages
+    graph: DependencyGraph,
+    /// The workspace member asked for, if the lockfile has it
+    member: Option<PackageInfo>,
+    /// Non-registry packages left out of the graph, as `name version (source: ...)`
+    skipped: Vec<String>,
+}
+
+/// Build a DependencyGraph from a deserialized Cargo.lock, along with the
+/// workspace member `member` (name and version) if one is given.
+///
+/// Packages are visited once to intern names/versions and index the
+/// available versions per name; dependency edges, the member's included,
+/// are then resolved against that index without re-reading the lockfile.
+fn build_dependency_graph_from_lockfile(
+    lockfile: RawLockfile,
+    member: Option<(&str, &Version)>,
+) -> Result<BuiltGraph> {
     // Get the [[package]] array
     let packages = lockfile
-        .get("package")
-        .and_then(|v| v.as_array())
+        .package
         .ok_or_else(|| anyhow::anyhow!("Cargo.lock missing 'package' array"))?;
 
-    // First pass: Build a map of package name -> versions
+    let mut interner = Interner::default();
     // Only include packages from crates.io registry
-    let mut name_to_versions: HashMap<String, Vec<Version>> = HashMap::new();
-    let mut skipped_packages = Vec::new();
+    let mut name_to_versions: HashMap<Arc<str>, Vec<Arc<Version>>> = HashMap::new();
+    let mut registry_packages = Vec::with_capacity(packages.len());
+    let mut member_package = None;
+    let mut skipped = Vec::new();
 
     for package in packages {
-        let name = package
-            .get("name")
-            .and_then(|v| v.as_str())
-            .ok_or_else(|| anyhow::anyhow!("Package missing 'name' field"))?;
-
-        let version_str = package
-            .get("version")
-            .and_then(|v| v.as_str())
-            .ok_or_else(|| anyhow::anyhow!("Package missing 'version' field"))?;
-
         // Check source - skip non-registry packages
-        if let Some(source) = package.get("source").and_then(|v| v.as_str()) {
-            if !source.starts_with("registry+") {
+        match package.source.as_deref() {
+            Some(source) if source.starts_with("registry+") => {}
+            Some(source) => {
                 // Skip git, path, and other non-registry sources
-                skipped_packages.push(format!("{} {} (source: {})", name, version_str, source));
+                skipped.push(format!(
+                    "{} {} (source: {})",
+                    package.name, package.version, source
+                ));
+                continue;
+            }
+            // No source field means it's a workspace member - skip unless
+            // it is the one asked for
+            None => {
+                if let Some((name, version)) = member {
+                    if package.name == name
+                        && Version::parse(&package.version).is_ok_and(|v| v == *version)
+                    {
+                        member_package = Some(package);
+                    }
+                }
                 continue;
             }
-        } else {
-            // No source field means it's a workspace member - skip
-            continue;
         }
 
-        let version = Version::parse(version_str)
-            .with_context(|| format!("Failed to parse version for package '{}'", name))?;
+        let version = Version::parse(&package.version)
+            .with_context(|| format!("Failed to parse version for package '{}'", package.name))?;
+        let name = interner.name(&package.name);
+        let version = interner.version(version);
 
         name_to_versions
-            .entry(name.to_string())
+            .entry(name.clone())
             .or_default()
-            .push(version);
+            .push(version.clone());
+        registry_packages.push((name, version, package.dependencies));
     }
 
-    // Second pass: Build the dependency graph with resolved versions
-    // Only include packages from crates.io registry
     let mut graph = DependencyGraph::new();
+    for (name, version, raw_dependencies) in registry_packages {
+        graph.add_package(PackageInfo {
+            name,
+            version,
+            dependencies: resolve_dependencies(&raw_dependencies, &name_to_versions, &mut interner),
+        });
+    }
+    let member = member_package
+        .zip(member)
+        .map(|(package, (name, version))| PackageInfo {
+            name: interner.name(name),
+            version: interner.version(version.clone()),
+            dependencies: resolve_dependencies(
+                &package.dependencies,
+                &name_to_versions,
+                &mut interner,
+            ),
+        });
+
+    Ok(BuiltGraph {
+        graph,
+        member,
+        skipped,
+    })
+}
 
-    for package in packages {
-        let name = package.get("name").and_then(|v| v.as_str()).unwrap();
-        let version_str = package.get("version").and_then(|v| v.as_str()).unwrap();
-
-        // Skip non-registry packages (same check as first pass)
-        if let Some(source) = package.get("source").and_then(|v| v.as_str()) {
-            if !source.starts_with("registry+") {
-                continue;
-            }
-        } else {
-            // No source = workspace member, skip
+/// The locked versions of the `dependencies` entries of a package, sorted.
+/// Entries that are neither registry packages nor name a version are left
+/// out.
+fn resolve_dependencies(
+    raw_dependencies: &[String],
+    name_to_versions: &HashMap<Arc<str>, Vec<Arc<Version>>>,
+    interner: &mut Interner,
+) -> Vec<DependencyInfo> {
+    let mut dependencies = Vec::with_capacity(raw_dependencies.len());
+    for dep_str in raw_dependencies {
+        // Cargo.lock dependencies format:
+        // - "package_name" (no version = unique package)
+        // - "package_name version" (with version for multiple versions of same package)
+        // Examples: "bitflags 2.10.0", "objc2-foundation 0.2.2"
+        let mut parts = dep_str.split_whitespace();
+        let Some(dep_name) = parts.next() else {
             continue;
-        }
-
-        let version = Version::parse(version_str).unwrap();
-
-        // Parse dependencies
-        let mut dependencies = Vec::new();
-        if let Some(deps_array) = package.get("dependencies").and_then(|v| v.as_array()) {
-            for dep in deps_array {
-                if let Some(dep_str) = dep.as_str() {
-                    // Cargo.lock dependencies format:
-                    // - "package_name" (no version = unique package)
-                    // - "package_name version" (with version for multiple versions of same package)
-                    // Examples: "bitflags 2.10.0", "objc2-foundation 0.2.2"
-
-                    let parts: Vec<&str> = dep_str.split_whitespace().collect();
-                    let dep_name = parts[0];
-
-                    // Try to extract version from dependency string
-                    let dep_version = if parts.len() > 1 {
-                        // Version specified in dep string
-                        Version::parse(parts[1]).ok()
-                    } else {
-                        // No version in string, lookup in map
-                        None
-                    };
-
-                    // If we got version from string, use it; otherwise lookup in map
-                    let dep_version = dep_version.or_else(|| {
-                        name_to_versions.get(dep_name).and_then(|versions| {
-                            if versions.len() == 1 {
-                                Some(versions[0].clone())
-                            } else {
-                                // Multiple versions exist but none specified in dep string
-                                // This shouldn't happen in a valid Cargo.lock
-                                // Use max as fallback
-                                versions.iter().max().cloned()
-                            }
-                        })
-                    });
-
-                    if let Some(version) = dep_version {
-                        dependencies.push(DependencyInfo {
-                            name: dep_name.to_string(),
-                            version,
-                        });
-                    }
-                }
-            }
-        }
-
-        dependencies.sort();
-        dependencies.dedup();
+        };
 
-        let package_info = PackageInfo {
-            name: name.to_string(),
-            version,
-            dependencies,
+        // Prefer the version named in the dependency string; otherwise use
+        // the single locked version. Multiple versions without one being
+        // named shouldn't happen in a valid Cargo.lock, use max as fallback.
+        let explicit = parts.next().and_then(|v| Version::parse(v).ok());
+        let dep_version = match (explicit, name_to_versions.get(dep_name)) {
+            (Some(version), Some(versions)) => versions
+                .iter()
+                .find(|locked| ***locked == version)
+                .cloned()
+                .unwrap_or_else(|| interner.version(version)),
+            (Some(version), None) => interner.version(version),
+            (None, Some(versions)) => match versions.iter().max() {
+                Some(version) => version.clone(),
+                None => continue,
+            },
+            // Not a registry package (path/git dependency)
+            (None, None) => continue,
         };
 
-        graph.add_package(package_info);
+        dependencies.push(DependencyInfo {
+            name: interner.name(dep_name),
+            version: dep_version,
+        });
     }
 
-    // Report skipped packages
-    if !skipped_packages.is_empty() {
-        eprintln!(
-            "\n⚠ Skipped {} non-registry package(s):",
-            skipped_packages.len()
-        );
-        for pkg in &skipped_packages {
-            eprintln!("  - {}", pkg);
-        }
-        eprintln!();
+    dependencies.sort();
+    dependencies.dedup();
+    dependencies
+}
+
+/// Report the packages left out of the graph.
+fn report_skipped(skipped: &[String]) {
+    if skipped.is_empty() {
+        return;
     }
-    Ok(graph)
+    eprintln!("\n⚠ Skipped {} non-registry package(s):", skipped.len());
+    for pkg in skipped {
+        eprintln!("  - {}", pkg);
+    }
+    eprintln!();
 }
 
 #[cfg(test)]
@@ -314,8 +455,8 @@ mod tests {
         assert_eq!(graph.len(), 0);
 
         let package = PackageInfo {
-            name: "test-crate".to_string(),
-            version: Version::parse("1.0.0").unwrap(),
+            name: "test-crate".into(),
+            version: Version::parse("1.0.0").unwrap().into(),
             dependencies: vec![],
         };
 
@@ -325,7 +466,7 @@ mod tests {
 
         let retrieved = graph.get_package("test-crate", &Version::parse("1.0.0").unwrap());
         assert!(retrieved.is_some());
-        assert_eq!(retrieved.unwrap().name, "test-crate");
+        assert_eq!(&*retrieved.unwrap().name, "test-crate");
     }
 
     #[test]
@@ -333,14 +474,14 @@ mod tests {
         let mut graph = DependencyGraph::new();
 
         let package_v1 = PackageInfo {
-            name: "test-crate".to_string(),
-            version: Version::parse("1.0.0").unwrap(),
+            name: "test-crate".into(),
+            version: Version::parse("1.0.0").unwrap().into(),
             dependencies: vec![],
         };
 
         let package_v2 = PackageInfo {
-            name: "test-crate".to_string(),
-            version: Version::parse("2.0.0").unwrap(),
+            name: "test-crate".into(),
+            version: Version::parse("2.0.0").unwrap().into(),
             dependencies: vec![],
         };
 
@@ -352,4 +493,144 @@ mod tests {
         let versions = graph.get_versions("test-crate");
         assert_eq!(versions.len(), 2);
     }
+
+    #[test]
+    fn parse_lockfile_str_shares_names_and_versions() {
+        let graph = parse_lockfile_str(
+            r#"
+version = 3
+
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = ["bitflags 1.3.2", "bitflags 2.4.0", "libc", "local-helper"]
+
+[[package]]
+name = "local-helper"
+version = "0.1.0"
+
+[[package]]
+name = "bitflags"
+version = "1.3.2"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "bitflags"
+version = "2.4.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "libc"
+version = "0.2.150"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "user"
+version = "1.0.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+dependencies = ["bitflags 2.4.0", "libc", "git-only"]
+
+[[package]]
+name = "git-only"
+version = "0.3.0"
+source = "git+https://example.com/git-only#abc"
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(graph.len(), 4);
+        assert_eq!(graph.get_versions("bitflags").len(), 2);
+        assert!(graph.get_versions("git-only").is_empty());
+
+        let user = graph
+            .get_package("user", &Version::parse("1.0.0").unwrap())
+            .unwrap();
+        let deps: Vec<_> = user
+            .dependencies
+            .iter()
+            .map(|dep| format!("{} {}", dep.name, dep.version))
+            .collect();
+        assert_eq!(deps, vec!["bitflags 2.4.0", "libc 0.2.150"]);
+
+        let libc = graph
+            .get_package("libc", &Version::parse("0.2.150").unwrap())
+            .unwrap();
+        let libc_edge = &user.dependencies[1];
+        assert!(Arc::ptr_eq(&libc.name, &libc_edge.name));
+        assert!(Arc::ptr_eq(&libc.version, &libc_edge.version));
+
+        let map = graph
+            .get_dependencies_map("user", &Version::parse("1.0.0").unwrap())
+            .unwrap();
+        assert_eq!(map["bitflags"], Version::parse("2.4.0").unwrap());
+    }
+
+    #[test]
+    fn members_can_be_added_to_the_graph() {
+        let lock = r#"
+version = 3
+
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = ["libc"]
+
+[[package]]
+name = "libc"
+version = "0.2.150"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+"#;
+        let app = Version::parse("0.1.0").unwrap();
+        let graph = parse_lockfile_with_member(lock, "app", &app).unwrap();
+        assert_eq!(graph.len(), 2);
+        let map = graph.get_dependencies_map("app", &app).unwrap();
+        assert_eq!(map["libc"], Version::parse("0.2.150").unwrap());
+        // The member's edges share the graph's allocations
+        let libc = graph
+            .get_package("libc", &Version::parse("0.2.150").unwrap())
+            .unwrap();
+        let app_edge = &graph.get_package("app", &app).unwrap().dependencies[0];
+        assert!(Arc::ptr_eq(&libc.version, &app_edge.version));
+        assert_eq!(
+            member_dependencies(lock, "app", &app).unwrap(),
+            Some(vec![(
+                "libc".to_string(),
+                Version::parse("0.2.150").unwrap()
+            )])
+        );
+        assert!(parse_lockfile_with_member(lock, "other", &app).is_err());
+    }
+
+    #[test]
+    fn yanked_packages_are_reported() {
+        let cache = tempfile::tempdir().unwrap();
+        let entry = cache.path().join("3/l/log");
+        std::fs::create_dir_all(entry.parent().unwrap()).unwrap();
+        std::fs::write(
+            &entry,
+            concat!(
+                r#"{"name":"log","vers":"0.4.19","cksum":"a","yanked":true}"#,
+                "\n",
+                r#"{"name":"log","vers":"0.4.20","cksum":"b","yanked":false}"#,
+                "\n",
+            ),
+        )
+        .unwrap();
+        let index = SparseIndex::new("http://127.0.0.1:9", Some(cache.path().to_path_buf()));
+
+        let mut graph = DependencyGraph::new();
+        for version in ["0.4.19", "0.4.20"] {
+            graph.add_package(PackageInfo {
+                name: "log".into(),
+                version: Version::parse(version).unwrap().into(),
+                dependencies: vec![],
+            });
+        }
+
+        let yanked = graph.yanked_packages(&index);
+        assert_eq!(
+            yanked,
+            vec![("log".to_string(), Version::parse("0.4.19").unwrap())]
+        );
+    }
 }
@@ -0,0 +1,263 @@
+//! Local crates mirror used by offline packaging.
+//!
+//! A mirror is a plain directory holding either `.crate` files
+//! (`{name}-{version}.crate`, flat or grouped under `{name}/`) or unpacked
+//! crate directories (`{name}-{version}/Cargo.toml`, as written by
+//! `cargo vendor --versioned-dirs` or `takopack cargo registry-sync`).
+
+use anyhow::{Context, Result};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use semver::{Version, VersionReq};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::tarball::{self, ExtractionLimits};
+
+/// Where a crate was found inside the mirror.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MirrorLocation {
+    /// A packed `{name}-{version}.crate` tarball.
+    CrateFile(PathBuf),
+    /// An unpacked `{name}-{version}/` directory.
+    Directory(PathBuf),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MirrorCrate {
+    pub name: String,
+    pub version: Version,
+    pub location: MirrorLocation,
+}
+
+impl MirrorCrate {
+    pub fn file_stem(&self) -> String {
+        format!("{}-{}", self.name, self.version)
+    }
+}
+
+/// Turn a user-supplied version (exact `1.2.3`, a requirement like `^1.2`,
+/// or nothing for "latest") into a requirement.
+fn version_req(version: Option<&str>) -> Result<VersionReq> {
+    let version = version.map(str::trim).filter(|v| !v.is_empty());
+    match version {
+        None => Ok(VersionReq::STAR),
+        Some(v) if v.starts_with(|c: char| c.is_ascii_digit()) => {
+            VersionReq::parse(&format!("={}", v)).with_context(|| format!("Invalid version: {}", v))
+        }
+        Some(v) => {
+            VersionReq::parse(v).with_context(|| format!("Invalid version requirement: {}", v))
+        }
+    }
+}
+
+/// Find the highest version of `crate_name` in `mirror` that satisfies
+/// `version`. Fails with a list of what the mirror does have otherwise.
+pub fn find_mirror_crate(
+    mirror: &Path,
+    crate_name: &str,
+    version: Option<&str>,
+) -> Result<MirrorCrate> {
+    if !mirror.is_dir() {
+        anyhow::bail!("crates mirror is not a directory: {}", mirror.display());
+    }
+    let req = version_req(version)?;
+
+    let mut candidates = Vec::new();
+    let mut scan = |dir: &Path| -> Result<()> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return Ok(()),
+        };
+        for entry in entries {
+            let entry = entry.with_context(|| format!("failed to read {}", dir.display()))?;
+            let path = entry.path();
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            if let Some(found) = parse_mirror_entry(&path, &file_name, crate_name) {
+                candidates.push(found);
+            }
+        }
+        Ok(())
+    };
+    scan(mirror)?;
+    scan(&mirror.join(crate_name))?;
+
+    let available: Vec<String> = candidates.iter().map(|c| c.version.to_string()).collect();
+    candidates
+        .into_iter()
+        .filter(|c| req.matches(&c.version))
+        // Prefer a packed .crate over a directory of the same version: its
+        // checksum matches what crates.io serves.
+        .max_by(|a, b| {
+            a.version.cmp(&b.version).then_with(|| {
+                matches!(a.location, MirrorLocation::CrateFile(_))
+                    .cmp(&matches!(b.location, MirrorLocation::CrateFile(_)))
+            })
+        })
+        .with_context(|| {
+            if available.is_empty() {
+                format!(
+                    "crate {} is not available in the offline mirror {}",
+                    crate_name,
+                    mirror.display()
+                )
+            } else {
+                format!(
+                    "no version of {} matching {} in the offline mirror {} (available: {})",
+                    crate_name,
+                    req,
+                    mirror.display(),
+                    available.join(", ")
+                )
+            }
+        })
+}
+
+fn parse_mirror_entry(path: &Path, file_name: &str, crate_name: &str) -> Option<MirrorCrate> {
+    let (stem, is_crate_file) = match file_name.strip_suffix(".crate") {
+        Some(stem) if path.is_file() => (stem, true),
+        Some(_) => return None,
+        None if path.join("Cargo.toml").is_file() => (file_name, false),
+        None => return None,
+    };
+
+    let version = stem.strip_prefix(crate_name)?.strip_prefix('-')?;
+    let version = Version::parse(version).ok()?;
+    let location = if is_crate_file {
+        MirrorLocation::CrateFile(path.to_path_buf())
+    } else {
+        MirrorLocation::Directory(path.to_path_buf())
+    };
+    Some(MirrorCrate {
+        name: crate_name.to_string(),
+        version,
+        location,
+    })
+}
+
+/// Unpack a `.crate` tarball under `dest` and return the crate directory.
+pub(crate) fn unpack_crate_file(
+    crate_file: &Path,
+    found: &MirrorCrate,
+    dest: &Path,
+) -> Result<PathBuf> {
+    let file = fs::File::open(crate_file)
+        .with_context(|| format!("failed to open {}", crate_file.display()))?;
+    let mut archive = tar::Archive::new(GzDecoder::new(file));
+    let mut budget = ExtractionLimits::default().budget();
+    for entry in archive.entries().context("failed to read crate archive")? {
+        let mut entry = entry.context("corrupt crate archive entry")?;
+        tarball::unpack_checked(&mut entry, dest, crate_file, &mut budget)?;
+    }
+
+    let crate_dir = dest.join(found.file_stem());
+    if !crate_dir.join("Cargo.toml").is_file() {
+        anyhow::bail!(
+            "{} did not unpack to {}/Cargo.toml",
+            crate_file.display(),
+            found.file_stem()
+        );
+    }
+    Ok(crate_dir)
+}
+
+/// Pack an unpacked crate directory into `{name}-{version}.crate` under `dest`.
+pub(crate) fn pack_crate_dir(
+    crate_dir: &Path,
+    found: &MirrorCrate,
+    dest: &Path,
+) -> Result<PathBuf> {
+    let crate_file = dest.join(format!("{}.crate", found.file_stem()));
+    let file = fs::File::create(&crate_file)
+        .with_context(|| format!("failed to create {}", crate_file.display()))?;
+    let mut builder = tar::Builder::new(GzEncoder::new(file, flate2::Compression::default()));
+
+    for entry in walkdir::WalkDir::new(crate_dir).sort_by_file_name() {
+        let entry = entry.context("walkdir error")?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let rel = entry.path().strip_prefix(crate_dir).unwrap_or(entry.path());
+        // Registry checksums describe the directory, not the tarball.
+        if rel == Path::new(".cargo-checksum.json") {
+            continue;
+        }
+        builder
+            .append_path_with_name(entry.path(), Path::new(&found.file_stem()).join(rel))
+            .with_context(|| format!("failed to add {} to crate", entry.path().display()))?;
+    }
+    builder
+        .into_inner()
+        .and_then(|encoder| encoder.finish())
+        .with_context(|| format!("failed to write {}", crate_file.display()))?;
+    Ok(crate_file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_mirror_crate, pack_crate_dir, unpack_crate_file, MirrorLocation};
+    use std::fs;
+
+    fn write_crate_dir(root: &std::path::Path, name: &str, version: &str) {
+        let dir = root.join(format!("{name}-{version}"));
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(
+            dir.join("Cargo.toml"),
+            format!("[package]\nname = \"{name}\"\nversion = \"{version}\"\n"),
+        )
+        .unwrap();
+        fs::write(dir.join("src/lib.rs"), "").unwrap();
+    }
+
+    #[test]
+    fn find_mirror_crate_picks_highest_matching_version() {
+        let mirror = tempfile::tempdir().unwrap();
+        write_crate_dir(mirror.path(), "serde", "1.0.100");
+        write_crate_dir(mirror.path(), "serde", "1.0.200");
+        write_crate_dir(mirror.path(), "serde_json", "1.0.300");
+        fs::create_dir_all(mirror.path().join("serde")).unwrap();
+        fs::write(mirror.path().join("serde/serde-0.9.0.crate"), b"").unwrap();
+
+        let latest = find_mirror_crate(mirror.path(), "serde", None).unwrap();
+        assert_eq!(latest.version.to_string(), "1.0.200");
+
+        let exact = find_mirror_crate(mirror.path(), "serde", Some("1.0.100")).unwrap();
+        assert_eq!(exact.version.to_string(), "1.0.100");
+
+        let old = find_mirror_crate(mirror.path(), "serde", Some("^0.9")).unwrap();
+        assert!(matches!(old.location, MirrorLocation::CrateFile(_)));
+    }
+
+    #[test]
+    fn find_mirror_crate_reports_missing_versions() {
+        let mirror = tempfile::tempdir().unwrap();
+        write_crate_dir(mirror.path(), "log", "0.4.20");
+
+        let err = find_mirror_crate(mirror.path(), "log", Some("0.3.9")).unwrap_err();
+        assert!(err.to_string().contains("available: 0.4.20"));
+
+        let err = find_mirror_crate(mirror.path(), "rand", None).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("crate rand is not available in the offline mirror"));
+    }
+
+    #[test]
+    fn packed_directory_roundtrips_through_unpack() {
+        let mirror = tempfile::tempdir().unwrap();
+        let out = tempfile::tempdir().unwrap();
+        write_crate_dir(mirror.path(), "tiny", "0.1.0");
+        fs::write(mirror.path().join("tiny-0.1.0/.cargo-checksum.json"), "{}").unwrap();
+        let found = find_mirror_crate(mirror.path(), "tiny", None).unwrap();
+        let MirrorLocation::Directory(dir) = &found.location else {
+            panic!("expected a directory entry");
+        };
+
+        let crate_file = pack_crate_dir(dir, &found, out.path()).unwrap();
+        let unpacked = tempfile::tempdir().unwrap();
+        let crate_dir = unpack_crate_file(&crate_file, &found, unpacked.path()).unwrap();
+
+        assert!(crate_dir.join("src/lib.rs").is_file());
+        assert!(!crate_dir.join(".cargo-checksum.json").exists());
+    }
+}
@@ -0,0 +1,275 @@
+//! Distinct crates that map to the same package name.
+//!
+//! Package names replace `_` with `-`, so crates such as `foo-bar` and
+//! `foo_bar` (both may exist on alternative registries, or as a registry
+//! crate and a git or local one) both become `rust-foo-bar-1`: the second
+//! one silently replaced the first one's package directory, spec and RPM.
+//! [`NameRegistry`] remembers which crate every package name of a run
+//! belongs to, including the package directories already in the output
+//! root and the ruyispec tree. A crate claiming a name that belongs to
+//! another one is an error, or with `policy = "suffix"` in the
+//! `[name_collisions]` table gets the name with the configured suffix.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use anyhow::Context;
+use semver::Version;
+use serde::Deserialize;
+
+use crate::config::Config;
+use crate::errors::*;
+use crate::util::RustCrateOutputNames;
+
+/// What happens when a crate's package name belongs to another crate.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CollisionPolicy {
+    /// The crate fails to package
+    #[default]
+    Fail,
+    /// The crate's package name gets the suffix
+    Suffix,
+}
+
+/// The `[name_collisions]` table of takopack.toml.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct NameCollisions {
+    pub policy: CollisionPolicy,
+    /// Appended to the package name with `policy = "suffix"`, followed by
+    /// a number when that name is taken as well. `-alt` by default.
+    pub suffix: String,
+}
+
+impl Default for NameCollisions {
+    fn default() -> Self {
+        NameCollisions {
+            policy: CollisionPolicy::default(),
+            suffix: "-alt".to_string(),
+        }
+    }
+}
+
+/// A crate whose package name belonged to another crate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Collision {
+    pub crate_name: String,
+    pub version: String,
+    /// The package name the crate maps to
+    pub package: String,
+    /// The crate that package name belongs to
+    pub owner: String,
+    /// The package name the crate got instead
+    pub renamed: String,
+}
+
+impl fmt::Display for Collision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}: {} belongs to {}, packaged as {}",
+            self.crate_name, self.version, self.package, self.owner, self.renamed
+        )
+    }
+}
+
+/// Which crate every package name of a run belongs to.
+#[derive(Debug, Default)]
+pub struct NameRegistry {
+    owners: BTreeMap<String, String>,
+    /// Crates packaged under another name than their own
+    pub collisions: Vec<Collision>,
+}
+
+impl NameRegistry {
+    /// A registry knowing the package directories in `output_root` and,
+    /// when `[ruyispec].local_path` is configured, in its `SPECS`.
+    pub fn for_run(output_root: &Path) -> Result<Self> {
+        let mut names = Self::default();
+        names.scan(output_root)?;
+        match crate::config::resolve_ruyispec_dir(None, true) {
+            Ok(ruyispec) => names.scan(&ruyispec.join("SPECS"))?,
+            Err(e) => log::debug!("not checking package names against ruyispec: {:#}", e),
+        }
+        Ok(names)
+    }
+
+    /// Record the crates of the package directories in `root`.
+    pub fn scan(&mut self, root: &Path) -> Result<()> {
+        if !root.is_dir() {
+            return Ok(());
+        }
+        for entry in
+            fs::read_dir(root).with_context(|| format!("Failed to read {}", root.display()))?
+        {
+            let entry = entry.with_context(|| format!("Failed to read {}", root.display()))?;
+            let package = entry.file_name().to_string_lossy().into_owned();
+            if !package.starts_with("rust-") || !entry.path().is_dir() {
+                continue;
+            }
+            if let Some(crate_name) = packaged_crate(&entry.path(), &package) {
+                self.owners.entry(package).or_insert(crate_name);
+            }
+        }
+        Ok(())
+    }
+
+    /// The output names of `crate_name` `version` as `config` gives them,
+    /// unless they belong to another crate. With the `suffix` policy, the
+    /// crate is renamed instead by extending `config.rpm_name_suffix`.
+    pub fn claim(
+        &mut self,
+        crate_name: &str,
+        version: &Version,
+        config: &mut Config,
+    ) -> Result<RustCrateOutputNames> {
+        let names = config.output_names(crate_name, version);
+        let owner = match self.owners.get(&names.directory) {
+            Some(owner) if owner != crate_name => owner.clone(),
+            _ => {
+                self.owners
+                    .insert(names.directory.clone(), crate_name.to_string());
+                return Ok(names);
+            }
+        };
+        let settings = &config.name_collisions;
+        if settings.policy == CollisionPolicy::Fail {
+            takopack_bail!(
+                "{} {} and {} both map to package {}; rename one of them with \
+                 rpm_name_suffix, or set policy = \"suffix\" in [name_collisions]",
+                crate_name,
+                version,
+                owner,
+                names.directory
+            );
+        }
+        let mut attempt = 1;
+        let (suffix, renamed) = loop {
+            let mut suffix = settings.suffix.clone();
+            if attempt > 1 {
+                suffix.push_str(&attempt.to_string());
+            }
+            let candidate = names.clone().with_suffix(&suffix);
+            match self.owners.get(&candidate.directory) {
+                Some(other) if other != crate_name => attempt += 1,
+                _ => break (suffix, candidate),
+            }
+        };
+        let base_suffix = config.rpm_name_suffix.take().unwrap_or_default();
+        config.rpm_name_suffix = Some(base_suffix + &suffix);
+        takopack_warn!(
+            "{} {} maps to package {} of {}; packaging it as {}",
+            crate_name,
+            version,
+            names.directory,
+            owner,
+            renamed.directory
+        );
+        self.owners
+            .insert(renamed.directory.clone(), crate_name.to_string());
+        self.collisions.push(Collision {
+            crate_name: crate_name.to_string(),
+            version: version.to_string(),
+            package: names.directory,
+            owner,
+            renamed: renamed.directory.clone(),
+        });
+        Ok(renamed)
+    }
+
+    /// Print the collisions of the run, if there were any.
+    pub fn print_collisions(&self) {
+        if self.collisions.is_empty() {
+            return;
+        }
+        println!("\n🔀 Package name collisions ({}):", self.collisions.len());
+        println!("{}", "-".repeat(62));
+        for collision in &self.collisions {
+            println!("   {}", collision);
+        }
+        println!();
+    }
+}
+
+/// Crate of the package directory `dir`: the package name of its
+/// Cargo.toml, or else the `crate_name` of its spec.
+fn packaged_crate(dir: &Path, package: &str) -> Option<String> {
+    let from_manifest = fs::read_to_string(dir.join("Cargo.toml"))
+        .ok()
+        .and_then(|text| toml::from_str::<toml::Value>(&text).ok())
+        .and_then(|manifest| Some(manifest.get("package")?.get("name")?.as_str()?.to_string()));
+    from_manifest.or_else(|| {
+        let spec = fs::read_to_string(dir.join(format!("{}.spec", package))).ok()?;
+        spec.lines().find_map(|line| {
+            let mut words = line.split_whitespace();
+            match (words.next(), words.next(), words.next()) {
+                (Some("%global"), Some("crate_name"), Some(name)) => Some(name.to_string()),
+                _ => None,
+            }
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CollisionPolicy, NameRegistry};
+    use crate::config::Config;
+    use semver::Version;
+    use std::fs;
+
+    #[test]
+    fn crates_mapping_to_the_same_package_fail_or_get_a_suffix() {
+        let dir = tempfile::tempdir().unwrap();
+        let existing = dir.path().join("rust-foo-bar-baz-1");
+        fs::create_dir(&existing).unwrap();
+        fs::write(
+            existing.join("Cargo.toml"),
+            "[package]\nname = \"foo-bar-baz\"\nversion = \"1.2.0\"\n",
+        )
+        .unwrap();
+        let version = Version::parse("1.0.3").unwrap();
+
+        let mut names = NameRegistry::default();
+        names.scan(dir.path()).unwrap();
+        let mut config = Config::default();
+        assert_eq!(
+            names
+                .claim("foo-bar-baz", &version, &mut config)
+                .unwrap()
+                .directory,
+            "rust-foo-bar-baz-1"
+        );
+        let err = names
+            .claim("foo_bar_baz", &version, &mut config)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("foo-bar-baz") && err.contains("rust-foo-bar-baz-1"),
+            "{}",
+            err
+        );
+        assert!(names.collisions.is_empty());
+
+        config.name_collisions.policy = CollisionPolicy::Suffix;
+        let renamed = names.claim("foo_bar_baz", &version, &mut config).unwrap();
+        assert_eq!(renamed.directory, "rust-foo-bar-baz-1-alt");
+        assert_eq!(renamed.spec_file, "rust-foo-bar-baz-1-alt.spec");
+        assert_eq!(config.rpm_name_suffix.as_deref(), Some("-alt"));
+        assert_eq!(names.collisions.len(), 1);
+
+        // A third crate with the same name skips the taken suffix
+        let mut config = Config::default();
+        config.name_collisions.policy = CollisionPolicy::Suffix;
+        let third = names.claim("foo-bar_baz", &version, &mut config).unwrap();
+        assert_eq!(third.directory, "rust-foo-bar-baz-1-alt2");
+        assert_eq!(names.collisions.len(), 2);
+        // Claiming again keeps the name the crate already got
+        let mut config = Config::default();
+        config.name_collisions.policy = CollisionPolicy::Suffix;
+        let again = names.claim("foo_bar_baz", &version, &mut config).unwrap();
+        assert_eq!(again.directory, "rust-foo-bar-baz-1-alt");
+    }
+}
@@ -0,0 +1,117 @@
+//! Recognizing package directories an earlier `vendor` run already
+//! generated, so that they are not generated again.
+//!
+//! Every generated directory gets a [`STAMP_FILE`] holding a fingerprint
+//! of what went into it: the crate and its checksum, the takopack version,
+//! the configuration and the locked dependency versions. A later run into
+//! the same output root keeps the directory when the fingerprint is still
+//! the same. With `--skip-existing` it is enough for the directory to hold
+//! the same crate version, which also covers dist-git checkouts that were
+//! never stamped.
+
+use std::fs;
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+use crate::errors::*;
+
+/// Fingerprint file in a generated package directory.
+pub const STAMP_FILE: &str = ".takopack-inputs";
+
+/// Fingerprint of `inputs`, as (name, value) pairs in a fixed order.
+pub fn fingerprint(inputs: &[(&str, String)]) -> String {
+    let mut hasher = Sha256::new();
+    for (name, value) in inputs {
+        hasher.update(format!("{}={}\n", name, value.escape_debug()));
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// Whether `dir` was generated from the inputs with `fingerprint`.
+pub fn is_current(dir: &Path, fingerprint: &str) -> bool {
+    fs::read_to_string(dir.join(STAMP_FILE)).is_ok_and(|stamp| stamp.trim() == fingerprint)
+}
+
+/// Record in `dir` that it was generated from the inputs with
+/// `fingerprint`.
+pub fn write_stamp(dir: &Path, fingerprint: &str) -> Result<()> {
+    crate::util::write_file_ensuring_dir(&dir.join(STAMP_FILE), format!("{}\n", fingerprint))
+}
+
+/// Crate version the package directory `dir` holds: the `full_version` of
+/// its spec `spec_file`, or else the version in its Cargo.toml.
+pub fn existing_version(dir: &Path, spec_file: &str) -> Option<String> {
+    let from_spec = fs::read_to_string(dir.join(spec_file))
+        .ok()
+        .and_then(|spec| {
+            spec.lines().find_map(|line| {
+                let mut words = line.split_whitespace();
+                match (words.next(), words.next(), words.next()) {
+                    (Some("%global"), Some("full_version"), Some(version)) => {
+                        Some(version.to_string())
+                    }
+                    _ => None,
+                }
+            })
+        });
+    from_spec.or_else(|| {
+        let manifest: toml::Value =
+            toml::from_str(&fs::read_to_string(dir.join("Cargo.toml")).ok()?).ok()?;
+        Some(
+            manifest
+                .get("package")?
+                .get("version")?
+                .as_str()?
+                .to_string(),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{existing_version, fingerprint, is_current, write_stamp};
+    use std::fs;
+
+    #[test]
+    fn directories_are_recognized_by_fingerprint_or_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let inputs = [
+            ("crate", "log".to_string()),
+            ("version", "0.4.22".to_string()),
+        ];
+        let current = fingerprint(&inputs);
+        assert_ne!(
+            current,
+            fingerprint(&[
+                ("crate", "log".to_string()),
+                ("version", "0.4.21".to_string())
+            ])
+        );
+
+        assert!(!is_current(dir.path(), &current));
+        write_stamp(dir.path(), &current).unwrap();
+        assert!(is_current(dir.path(), &current));
+        assert!(!is_current(dir.path(), &fingerprint(&inputs[..1])));
+
+        assert_eq!(existing_version(dir.path(), "rust-log-0.4.spec"), None);
+        fs::write(
+            dir.path().join("Cargo.toml"),
+            "[package]\nname = \"log\"\nversion = \"0.4.21\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            existing_version(dir.path(), "rust-log-0.4.spec").as_deref(),
+            Some("0.4.21")
+        );
+        fs::write(
+            dir.path().join("rust-log-0.4.spec"),
+            "%global crate_name log\n%global full_version 0.4.22\nName: rust-log-0.4\n",
+        )
+        .unwrap();
+        assert_eq!(
+            existing_version(dir.path(), "rust-log-0.4.spec").as_deref(),
+            Some("0.4.22")
+        );
+    }
+}
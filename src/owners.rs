@@ -0,0 +1,188 @@
+//! Crate ownership tracking between packaged versions.
+//!
+//! The crates.io owner list of every checked crate is remembered in a small
+//! JSON file together with the version it was seen at. When a later run
+//! packages another version and the owners differ, the change is reported so
+//! it can be reviewed before the update is shipped.
+
+use anyhow::{Context, Result};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::http::CRATES_IO_API;
+
+/// Owners of one crate as last seen.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OwnerRecord {
+    pub version: String,
+    pub owners: BTreeSet<String>,
+}
+
+/// Owners that differ between two packaged versions of a crate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnershipChange {
+    pub crate_name: String,
+    pub previous_version: String,
+    pub version: String,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl std::fmt::Display for OwnershipChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} -> {}",
+            self.crate_name, self.previous_version, self.version
+        )?;
+        if !self.added.is_empty() {
+            write!(f, "; added: {}", self.added.join(", "))?;
+        }
+        if !self.removed.is_empty() {
+            write!(f, "; removed: {}", self.removed.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Owner lists keyed by crate name, persisted as JSON.
+#[derive(Debug, Default)]
+pub struct OwnerStore {
+    path: PathBuf,
+    records: BTreeMap<String, OwnerRecord>,
+}
+
+impl OwnerStore {
+    /// Load the store at `path`; a missing file is an empty store.
+    pub fn load(path: &Path) -> Result<Self> {
+        let records = match fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text)
+                .with_context(|| format!("Failed to parse owners cache {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to read owners cache {}", path.display()))
+            }
+        };
+        Ok(OwnerStore {
+            path: path.to_path_buf(),
+            records,
+        })
+    }
+
+    pub fn get(&self, crate_name: &str) -> Option<&OwnerRecord> {
+        self.records.get(crate_name)
+    }
+
+    /// Remember `owners` for `crate_name` `version`. Returns the change
+    /// against the previously recorded owners, if there were any and they
+    /// differ.
+    pub fn record(
+        &mut self,
+        crate_name: &str,
+        version: &str,
+        owners: BTreeSet<String>,
+    ) -> Option<OwnershipChange> {
+        let change = self.records.get(crate_name).and_then(|previous| {
+            let added: Vec<String> = owners.difference(&previous.owners).cloned().collect();
+            let removed: Vec<String> = previous.owners.difference(&owners).cloned().collect();
+            (!added.is_empty() || !removed.is_empty()).then(|| OwnershipChange {
+                crate_name: crate_name.to_string(),
+                previous_version: previous.version.clone(),
+                version: version.to_string(),
+                added,
+                removed,
+            })
+        });
+        self.records.insert(
+            crate_name.to_string(),
+            OwnerRecord {
+                version: version.to_string(),
+                owners,
+            },
+        );
+        change
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let text = serde_json::to_string_pretty(&self.records)?;
+        fs::write(&self.path, text)
+            .with_context(|| format!("Failed to write owners cache {}", self.path.display()))
+    }
+}
+
+#[derive(Deserialize)]
+struct OwnersResponse {
+    users: Vec<ApiOwner>,
+}
+
+#[derive(Deserialize)]
+struct ApiOwner {
+    login: String,
+}
+
+/// Current owners (user and team logins) of `crate_name` on crates.io.
+pub fn fetch_owners(crate_name: &str) -> Result<BTreeSet<String>> {
+    let url = format!("{}/crates/{}/owners", CRATES_IO_API, crate_name);
+    let body = crate::http::agent()
+        .get(&url)
+        .set(
+            "User-Agent",
+            concat!("takopack/", env!("CARGO_PKG_VERSION")),
+        )
+        .timeout(std::time::Duration::from_secs(30))
+        .call()
+        .with_context(|| format!("Failed to fetch owners of {}", crate_name))?
+        .into_string()
+        .with_context(|| format!("Failed to read owners of {}", crate_name))?;
+    let response: OwnersResponse = serde_json::from_str(&body)
+        .with_context(|| format!("Failed to parse owners of {}", crate_name))?;
+    Ok(response.users.into_iter().map(|o| o.login).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OwnerStore;
+    use std::collections::BTreeSet;
+
+    fn owners(names: &[&str]) -> BTreeSet<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn owner_changes_are_detected_across_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("owners.json");
+
+        let mut store = OwnerStore::load(&path).unwrap();
+        assert_eq!(
+            store.record("demo", "1.0.0", owners(&["alice", "bob"])),
+            None
+        );
+        store.save().unwrap();
+
+        let mut store = OwnerStore::load(&path).unwrap();
+        assert_eq!(store.get("demo").unwrap().version, "1.0.0");
+        assert_eq!(
+            store.record("demo", "1.1.0", owners(&["alice", "bob"])),
+            None
+        );
+
+        let change = store
+            .record("demo", "1.2.0", owners(&["alice", "mallory"]))
+            .unwrap();
+        assert_eq!(change.previous_version, "1.1.0");
+        assert_eq!(change.added, vec!["mallory"]);
+        assert_eq!(change.removed, vec!["bob"]);
+        assert_eq!(
+            change.to_string(),
+            "demo 1.1.0 -> 1.2.0; added: mallory; removed: bob"
+        );
+    }
+}
@@ -3,9 +3,11 @@ use std::path::PathBuf;
 use clap::{crate_version, Parser};
 
 use crate::config::{Config, PackageKey};
-use crate::crates::CrateInfo;
+use crate::crates::{CrateInfo, CrateRegistry, DownloadOptions};
 use crate::errors::Result;
+use crate::takopack::changelog::ChangelogSource;
 use crate::takopack::{self, DebInfo};
+use crate::test_mode::TestMode;
 use crate::util;
 pub struct PackageProcess {
     // below state is filled in during init
@@ -30,6 +32,75 @@ pub struct PackageInitArgs {
     /// Version of the crate to package; may contain dependency operators.
     /// If empty string or omitted, resolves to the latest version.
     pub version: Option<String>,
+    /// Resolve the crate from the local mirror ([mirror].local_path in
+    /// takopack.toml) instead of crates.io.
+    #[arg(long)]
+    pub offline: bool,
+    /// Local crates mirror directory; implies --offline.
+    #[arg(long, value_name = "DIR")]
+    pub mirror: Option<PathBuf>,
+    /// Fetch the crate from this registry, as named in cargo's
+    /// `[registries]` config, instead of crates.io.
+    #[arg(long, value_name = "NAME", conflicts_with = "index_url")]
+    pub registry: Option<String>,
+    /// Fetch the crate from the registry with this index URL
+    /// (`sparse+https://...` for sparse registries).
+    #[arg(long, value_name = "URL")]
+    pub index_url: Option<String>,
+    /// Package a version crates.io lists as yanked, downloading it from
+    /// [yanked].archive_url in takopack.toml. Needs the exact version
+    #[arg(long, conflicts_with_all = ["offline", "mirror", "registry", "index_url"])]
+    pub allow_yanked: bool,
+    /// `.crate` archive of the yanked version, used instead of downloading it
+    #[arg(long, value_name = "FILE", requires = "allow_yanked")]
+    pub crate_file: Option<PathBuf>,
+    #[command(flatten)]
+    pub download: DownloadArgs,
+}
+
+/// Download settings given on the command line, overriding the `[download]`
+/// table of takopack.toml.
+#[derive(Debug, Clone, Default, Parser)]
+pub struct DownloadArgs {
+    /// Retry failed downloads this many times. Overrides [download].retries
+    /// in takopack.toml
+    #[arg(long, value_name = "N")]
+    pub retries: Option<u32>,
+    /// Download crates again instead of reusing the shared crate cache
+    #[arg(long)]
+    pub no_cache: bool,
+    /// Index URL of a crates.io mirror to download from (`sparse+https://...`
+    /// for sparse indexes). Overrides [download].mirror_url
+    #[arg(long, value_name = "URL")]
+    pub mirror_url: Option<String>,
+    /// HTTP(S) proxy for downloads. Overrides [download].proxy
+    #[arg(long, value_name = "URL")]
+    pub proxy: Option<String>,
+    /// PEM file of additional CA certificates to trust. Overrides
+    /// [download].cacert
+    #[arg(long, value_name = "FILE")]
+    pub cacert: Option<PathBuf>,
+}
+
+impl DownloadArgs {
+    /// Apply the settings given on the command line to `options`.
+    pub fn apply(&self, options: &mut DownloadOptions) {
+        if let Some(retries) = self.retries {
+            options.retries = retries;
+        }
+        if self.no_cache {
+            options.cache = false;
+        }
+        if let Some(mirror_url) = &self.mirror_url {
+            options.mirror_url = Some(mirror_url.clone());
+        }
+        if let Some(proxy) = &self.proxy {
+            options.proxy = Some(proxy.clone());
+        }
+        if let Some(cacert) = &self.cacert {
+            options.cacert = Some(cacert.clone());
+        }
+    }
 }
 
 #[derive(Debug, Clone, Parser)]
@@ -45,7 +116,13 @@ pub struct PackageExecuteArgs {
     /// Assume the changelog is already bumped, and leave it alone.
     #[arg(long)]
     pub changelog_ready: bool,
-    /// Guess extra values for d/copyright. Might be slow.
+    /// Write %changelog entries from the crate's crates.io releases or the
+    /// version tags of its repository instead of only %autochangelog.
+    #[arg(long, value_enum, default_value_t = ChangelogSource::None)]
+    pub changelog_from: ChangelogSource,
+    /// Scan the sources for per-file licenses and copyright holders and
+    /// write a debian/copyright-style `copyright` file next to the spec.
+    /// Might be slow.
     #[arg(long)]
     pub copyright_guess_harder: bool,
     /// Don't write back hint files or d/changelog to the source overlay directory.
@@ -54,10 +131,27 @@ pub struct PackageExecuteArgs {
     /// Include TakoPack's built-in SPDX header in generated spec files.
     #[arg(long)]
     pub with_spdx: bool,
+    /// Add `BuildRequires:` for the dev-dependencies the tests need, as
+    /// [test_build_requires].enabled in takopack.toml does
+    #[arg(long)]
+    pub with_tests: bool,
+    /// Directory of templates for sections of the spec (source.spec,
+    /// package.spec, check.spec, files.spec). Overrides `template_dir` in
+    /// takopack.toml.
+    #[arg(long, value_name = "DIR")]
+    pub template_dir: Option<PathBuf>,
+    /// Target distro profile (fedora, opensuse, openeuler, openruyi or one
+    /// defined under [profiles] in takopack.toml). Overrides `profile` in
+    /// takopack.toml.
+    #[arg(long, value_name = "NAME")]
+    pub profile: Option<String>,
     /// Optional: Dependencies from Cargo.lock for accurate spec generation
     /// (used by track command, None for pkg/batch commands)
     #[arg(skip)]
     pub lockfile_deps: Option<std::collections::HashMap<String, semver::Version>>,
+    /// Set from the global `--test-mode` flag, see [`crate::test_mode`]
+    #[arg(skip)]
+    pub test_mode: TestMode,
 }
 
 impl PackageProcess {
@@ -68,7 +162,13 @@ impl PackageProcess {
         config: Config,
     ) -> Result<Self> {
         crate_info.set_includes_excludes(config.orig_tar_excludes(), config.orig_tar_whitelist());
-        let deb_info = DebInfo::new(&crate_info, crate_version!(), config.semver_suffix);
+        crate_info.set_test_mode(config.test_mode);
+        let deb_info = DebInfo::new(
+            &crate_info,
+            crate_version!(),
+            config.semver_suffix,
+            config.test_mode,
+        );
 
         // Calculate SHA256 hash for downloaded crates
         let sha256 = match crate_info.calculate_sha256() {
@@ -95,15 +195,54 @@ impl PackageProcess {
         })
     }
 
-    pub fn init(init_args: PackageInitArgs) -> Result<Self> {
+    /// `profile` selects the target distro profile, see
+    /// [`PackageExecuteArgs::profile`], and `test_mode` is that of
+    /// [`PackageExecuteArgs::test_mode`].
+    pub fn init(
+        init_args: PackageInitArgs,
+        profile: Option<&str>,
+        test_mode: TestMode,
+    ) -> Result<Self> {
         let crate_name = &init_args.crate_name;
         let version = init_args.version.as_deref();
-        let (config_path, config) = Config::load()?;
+        let (config_path, mut config) = Config::load_with_profile(profile)?;
+        config.test_mode = test_mode;
 
         let crate_path = config.crate_src_path(config_path.as_deref());
         let crate_info = match crate_path {
             Some(p) => CrateInfo::new_with_local_crate(crate_name, version, &p)?,
-            None => CrateInfo::new(crate_name, version)?,
+            None if init_args.allow_yanked => {
+                let mut download = config.download.clone();
+                init_args.download.apply(&mut download);
+                crate::http::configure(&download)?;
+                crate::yanked::crate_info(
+                    crate_name,
+                    version,
+                    &config.yanked,
+                    init_args.crate_file.as_deref(),
+                )?
+            }
+            None if init_args.offline || init_args.mirror.is_some() => {
+                let mirror = crate::config::resolve_mirror_dir(init_args.mirror.as_deref())?;
+                CrateInfo::new_from_mirror(crate_name, version, &mirror)?
+            }
+            None => {
+                let mut download = config.download.clone();
+                init_args.download.apply(&mut download);
+                crate::http::configure(&download)?;
+                // a mirror stands in for crates.io, so that the spec's
+                // Source: points at it too
+                let index_url = init_args.index_url.as_deref().or(download
+                    .mirror_url
+                    .as_deref()
+                    .filter(|_| init_args.registry.is_none()));
+                let registry = CrateRegistry::from_args(init_args.registry.as_deref(), index_url)?;
+                if registry == CrateRegistry::CratesIo {
+                    CrateInfo::new_with_download(crate_name, version, &download)?
+                } else {
+                    CrateInfo::new_with_registry(crate_name, version, &registry, &download)?
+                }
+            }
         };
 
         Self::new(crate_info, config_path, config)
@@ -123,6 +262,8 @@ impl PackageProcess {
         let Self {
             crate_info,
             deb_info,
+            config,
+            config_path,
             ..
         } = self;
         // vars read; begin stage
@@ -131,17 +272,21 @@ impl PackageProcess {
             .directory
             .unwrap_or_else(|| deb_info.package_source_dir().to_path_buf());
 
-        let source_modified = crate_info.extract_crate(&output_dir)?;
+        let source_modified = crate_info.extract_crate(&output_dir, &config.extraction)?;
 
         // Get crate info before clean (for backup)
         let crate_name = crate_info.crate_name().to_string();
         let version = crate_info.version().to_string();
 
-        // Backup original Cargo.toml under the takopack cargo_back origin path (no cleaning)
+        // Backup original Cargo.toml under the origin path of the backups (no cleaning)
         let cargo_toml = output_dir.join("Cargo.toml");
-        if let Err(e) =
-            crate::util::backup_cargo_toml(&cargo_toml, &crate_name, &version, Some("origin"))
-        {
+        if let Err(e) = config.backup.backup_cargo_toml(
+            config_path.as_deref(),
+            &cargo_toml,
+            &crate_name,
+            &version,
+            Some("origin"),
+        ) {
             log::warn!("Failed to backup original Cargo.toml: {:?}", e);
         }
 
@@ -212,6 +357,12 @@ impl PackageProcess {
         } = self;
         let output_dir = output_dir.as_ref().unwrap();
         let temp_output_dir = temp_output_dir.as_ref().unwrap();
+        if args.with_tests {
+            config.test_build_requires.enabled = true;
+        }
+        if let Some(dir) = &args.template_dir {
+            config.template_dir = Some(std::env::current_dir()?.join(dir));
+        }
         takopack::prepare_takopack_folder(
             crate_info,
             deb_info,
@@ -220,6 +371,7 @@ impl PackageProcess {
             output_dir,
             temp_output_dir,
             args.changelog_ready,
+            args.changelog_from,
             args.copyright_guess_harder,
             !args.no_overlay_write_back,
             sha256.clone(),
@@ -0,0 +1,161 @@
+//! Where takopack keeps its files.
+//!
+//! Every module used to place its own files: the sparse index cache went to
+//! the config directory, next to takopack.toml, and the backups were found
+//! from `XDG_DATA_HOME` by hand. All locations now come from here. They
+//! follow the XDG base directories, and their macOS and Windows
+//! equivalents through [`dirs`], each with a `takopack` subdirectory:
+//!
+//! - config ([`config_dir`]): `$XDG_CONFIG_HOME/takopack`, holding
+//!   takopack.toml; `TAKOPACK_CONFIG_DIR` overrides it
+//! - cache ([`cache_dir`]): `$XDG_CACHE_HOME/takopack`, holding what can be
+//!   downloaded again; `TAKOPACK_CACHE_DIR` overrides it
+//! - data ([`data_dir`]): `$XDG_DATA_HOME/takopack`, holding records of
+//!   earlier runs and backups; `TAKOPACK_DATA_DIR` overrides it
+//!
+//! Inside an [`isolated`] library call, all three are `takopack` in the
+//! state directory of the call.
+
+use std::cell::RefCell;
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+use crate::errors::*;
+
+pub const CONFIG_DIR_ENV: &str = "TAKOPACK_CONFIG_DIR";
+pub const CACHE_DIR_ENV: &str = "TAKOPACK_CACHE_DIR";
+pub const DATA_DIR_ENV: &str = "TAKOPACK_DATA_DIR";
+
+/// Where a call through the library keeps what takopack otherwise keeps in
+/// the directories of the user, see [`isolated`].
+#[derive(Debug, Clone, Default)]
+pub struct Isolation {
+    /// Stands in for the config, cache and data directories, and for
+    /// `CARGO_HOME`
+    pub state_dir: PathBuf,
+    /// The takopack.toml to use; none is looked up in the current or the
+    /// config directory
+    pub config: Option<PathBuf>,
+}
+
+thread_local! {
+    static ISOLATION: RefCell<Option<Isolation>> = const { RefCell::new(None) };
+}
+
+/// Run `f` with `isolation` in place of the directories of the user, on
+/// this thread.
+pub fn isolated<T>(isolation: Isolation, f: impl FnOnce() -> T) -> T {
+    let previous = ISOLATION.with(|current| current.replace(Some(isolation)));
+    let result = f();
+    ISOLATION.with(|current| current.replace(previous));
+    result
+}
+
+/// The [`isolated`] call running on this thread.
+pub fn isolation() -> Option<Isolation> {
+    ISOLATION.with(|current| current.borrow().clone())
+}
+
+/// The state directory of the [`isolated`] call running on this thread.
+pub fn isolated_state_dir() -> Option<PathBuf> {
+    isolation().map(|isolation| isolation.state_dir)
+}
+
+/// The takopack directory in `base`, or `overridden` when it is set and
+/// not empty.
+fn takopack_dir(overridden: Option<OsString>, base: Option<PathBuf>) -> Option<PathBuf> {
+    match overridden.filter(|dir| !dir.is_empty()) {
+        Some(dir) => Some(PathBuf::from(dir)),
+        None => base.map(|base| base.join("takopack")),
+    }
+}
+
+fn user_dir(env: &str, base: fn() -> Option<PathBuf>, what: &str) -> Result<PathBuf> {
+    if let Some(state_dir) = isolated_state_dir() {
+        return Ok(state_dir.join("takopack"));
+    }
+    takopack_dir(std::env::var_os(env), base())
+        .ok_or_else(|| format_err!("cannot determine the {} directory; set {}", what, env))
+}
+
+/// Directory of the user's takopack.toml.
+pub fn config_dir() -> Result<PathBuf> {
+    user_dir(CONFIG_DIR_ENV, dirs::config_dir, "config")
+}
+
+/// Directory of files that can be fetched again.
+pub fn cache_dir() -> Result<PathBuf> {
+    user_dir(CACHE_DIR_ENV, dirs::cache_dir, "cache")
+}
+
+/// Directory of records of earlier runs and backups.
+pub fn data_dir() -> Result<PathBuf> {
+    user_dir(DATA_DIR_ENV, dirs::data_dir, "data")
+}
+
+/// Default local Cargo registry of `registry-sync`.
+pub fn registry_dir() -> Result<PathBuf> {
+    Ok(data_dir()?.join("cargo-registry"))
+}
+
+/// Crate owner lists remembered from earlier runs, used to spot
+/// ownership changes between packaged versions.
+pub fn owners_file() -> Result<PathBuf> {
+    Ok(data_dir()?.join("owners.json"))
+}
+
+/// Yanked versions packaged with `--allow-yanked`, see [`crate::yanked`].
+pub fn yanked_exceptions_file() -> Result<PathBuf> {
+    Ok(data_dir()?.join("yanked-exceptions.json"))
+}
+
+/// Cargo.toml and Cargo.lock files backed up by default, see
+/// [`crate::backup`].
+pub fn backup_dir() -> Result<PathBuf> {
+    Ok(data_dir()?.join("cargo_back"))
+}
+
+/// On-disk cache of crates.io sparse index files.
+pub fn index_cache_dir() -> Result<PathBuf> {
+    Ok(cache_dir()?.join("index-cache"))
+}
+
+/// Crate archives shared between runs, see [`crate::crate_cache`].
+pub fn crate_cache_dir() -> Result<PathBuf> {
+    Ok(cache_dir()?.join("crates"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{crate_cache_dir, isolated, takopack_dir, Isolation};
+    use std::ffi::OsString;
+    use std::path::PathBuf;
+
+    #[test]
+    fn directories_follow_xdg_with_overrides() {
+        let base = || Some(PathBuf::from("/home/me/.cache"));
+        assert_eq!(
+            takopack_dir(None, base()),
+            Some(PathBuf::from("/home/me/.cache/takopack"))
+        );
+        assert_eq!(
+            takopack_dir(Some(OsString::from("/srv/takopack")), base()),
+            Some(PathBuf::from("/srv/takopack"))
+        );
+        assert_eq!(
+            takopack_dir(Some(OsString::new()), base()),
+            Some(PathBuf::from("/home/me/.cache/takopack"))
+        );
+        assert_eq!(takopack_dir(None, None), None);
+
+        let state = tempfile::tempdir().unwrap();
+        let isolation = Isolation {
+            state_dir: state.path().to_path_buf(),
+            config: None,
+        };
+        assert_eq!(
+            isolated(isolation, || crate_cache_dir().unwrap()),
+            state.path().join("takopack/crates")
+        );
+    }
+}
@@ -0,0 +1,143 @@
+//! Target distro profiles.
+//!
+//! A profile is a set of takopack.toml settings describing one distro's
+//! spec conventions: the macro flavor, the package carrying the macros,
+//! how `Release:`, `%check` and `%changelog` are written, and how
+//! dependencies are expressed. The profiles in `profiles/` are built in;
+//! `[profiles.<name>]` tables in takopack.toml adjust them or add new ones.
+//!
+//! Settings are layered as: built-in profile, then the user's
+//! `[profiles.<name>]` table, then the top-level keys of takopack.toml.
+
+use anyhow::Context;
+
+use crate::errors::*;
+
+/// Built-in profiles, as shipped in `profiles/`.
+pub const BUILTIN_PROFILES: &[(&str, &str)] = &[
+    ("fedora", include_str!("../profiles/fedora.toml")),
+    ("openeuler", include_str!("../profiles/openeuler.toml")),
+    ("openruyi", include_str!("../profiles/openruyi.toml")),
+    ("opensuse", include_str!("../profiles/opensuse.toml")),
+];
+
+/// How deep `inherits` chains may go before they are treated as a loop.
+const MAX_INHERITS: usize = 8;
+
+/// Names of all profiles: the built-in ones and those defined in `user`.
+pub fn profile_names(user: Option<&toml::Table>) -> Vec<String> {
+    let mut names: Vec<String> = BUILTIN_PROFILES
+        .iter()
+        .map(|(name, _)| name.to_string())
+        .chain(user.into_iter().flat_map(|u| u.keys().cloned()))
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// The settings of profile `name`. `user` is the `[profiles]` table of
+/// takopack.toml; a user profile is layered over the built-in profile of
+/// the same name, or over the one it names in `inherits`.
+pub fn resolve_profile(name: &str, user: Option<&toml::Table>) -> Result<toml::Table> {
+    resolve_profile_at(name, user, 0)
+}
+
+fn resolve_profile_at(name: &str, user: Option<&toml::Table>, depth: usize) -> Result<toml::Table> {
+    if depth > MAX_INHERITS {
+        takopack_bail!("profile {:?}: `inherits` chain is too long or loops", name);
+    }
+    let builtin = match BUILTIN_PROFILES.iter().find(|(n, _)| *n == name) {
+        Some((_, text)) => Some(
+            toml::from_str::<toml::Table>(text)
+                .with_context(|| format!("built-in profile {} is malformed", name))?,
+        ),
+        None => None,
+    };
+    let user_profile = match user.and_then(|u| u.get(name)) {
+        Some(toml::Value::Table(table)) => Some(table.clone()),
+        Some(_) => takopack_bail!("[profiles.{}] in takopack.toml must be a table", name),
+        None => None,
+    };
+
+    let Some(mut user_profile) = user_profile else {
+        return builtin.ok_or_else(|| {
+            format_err!(
+                "unknown profile {:?}; available profiles: {}",
+                name,
+                profile_names(user).join(", ")
+            )
+        });
+    };
+    let base = match user_profile.remove("inherits") {
+        Some(toml::Value::String(parent)) => {
+            if builtin.is_some() {
+                takopack_bail!(
+                    "[profiles.{}] adjusts the built-in profile and cannot inherit from {:?}",
+                    name,
+                    parent
+                );
+            }
+            resolve_profile_at(&parent, user, depth + 1)?
+        }
+        Some(_) => takopack_bail!("[profiles.{}].inherits must be a profile name", name),
+        None => builtin.unwrap_or_default(),
+    };
+    Ok(layer(base, user_profile))
+}
+
+/// `base` with every key of `top` set over it.
+pub fn layer(mut base: toml::Table, top: toml::Table) -> toml::Table {
+    base.extend(top);
+    base
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{profile_names, resolve_profile, BUILTIN_PROFILES};
+    use crate::config::Config;
+
+    #[test]
+    fn builtin_profiles_are_valid_configs() {
+        for (name, _) in BUILTIN_PROFILES {
+            let table = resolve_profile(name, None).unwrap();
+            let config: Config = toml::Value::Table(table).try_into().unwrap();
+            assert!(config.unknown_fields.is_empty(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn user_profiles_adjust_or_inherit() {
+        let user: toml::Table = toml::from_str(
+            r#"
+[fedora]
+release = "1%{?dist}"
+
+[mydistro]
+inherits = "opensuse"
+macros_package = "mydistro-rust-macros"
+
+[loop]
+inherits = "loop"
+"#,
+        )
+        .unwrap();
+
+        let fedora = resolve_profile("fedora", Some(&user)).unwrap();
+        assert_eq!(fedora["release"].as_str(), Some("1%{?dist}"));
+        assert_eq!(fedora["tests"].as_str(), Some("bcond"));
+
+        let mine = resolve_profile("mydistro", Some(&user)).unwrap();
+        assert_eq!(
+            mine["macros_package"].as_str(),
+            Some("mydistro-rust-macros")
+        );
+        assert_eq!(mine["release"].as_str(), Some("0"));
+        assert!(!mine.contains_key("inherits"));
+
+        assert!(resolve_profile("loop", Some(&user)).is_err());
+        let unknown = resolve_profile("debian", Some(&user)).unwrap_err();
+        assert!(unknown.to_string().contains("mydistro"));
+        assert!(profile_names(Some(&user)).contains(&"openruyi".to_string()));
+    }
+}
@@ -0,0 +1,219 @@
+//! Progress display of recursive runs.
+//!
+//! A `vendor` run over a large dependency graph prints several lines for
+//! every crate, which buries the warnings and failures. With `--progress`
+//! it shows one bar for the whole run with live counts of packaged and
+//! failed crates, and a spinner naming the crate being packaged; the lines
+//! for every crate only go to the info log, unless `--verbose` prints them
+//! above the bars. Warnings and failures are still printed, above the bars,
+//! which is why `takopack_info!` and `takopack_warn!` print through
+//! [`eprint_line`].
+//! Without a terminal on stderr, e.g. in CI logs, or with another
+//! `--output-format` than `human`, the run reports its events as before.
+
+use std::cell::RefCell;
+use std::io::IsTerminal;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+
+use crate::events::{self, Event};
+use crate::verbosity::Verbosity;
+
+/// The bars being shown, for [`eprint_line`].
+static ACTIVE: Mutex<Option<MultiProgress>> = Mutex::new(None);
+
+thread_local! {
+    static CAPTURED: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+}
+
+/// Run `f`, keeping the lines and events it would print on this thread
+/// instead of printing them.
+pub fn captured<T>(f: impl FnOnce() -> T) -> (T, Vec<String>) {
+    let previous = CAPTURED.with(|lines| lines.replace(Some(Vec::new())));
+    let result = f();
+    let lines = CAPTURED
+        .with(|lines| lines.replace(previous))
+        .unwrap_or_default();
+    (result, lines)
+}
+
+/// Keep `line` if a [`captured`] call runs on this thread; whether it was
+/// kept.
+pub(crate) fn capture(line: &str) -> bool {
+    CAPTURED.with(|lines| match &mut *lines.borrow_mut() {
+        Some(lines) => {
+            lines.push(line.to_string());
+            true
+        }
+        None => false,
+    })
+}
+
+/// Print `line` to stderr, above the progress bars if they are shown.
+pub fn eprint_line(line: &str) {
+    if capture(line) {
+        return;
+    }
+    match &*ACTIVE.lock().unwrap_or_else(|e| e.into_inner()) {
+        Some(bars) => {
+            if bars.println(line).is_err() {
+                eprintln!("{}", line);
+            }
+        }
+        None => eprintln!("{}", line),
+    }
+}
+
+struct Bars {
+    run: ProgressBar,
+    current: ProgressBar,
+}
+
+/// Progress of one recursive run; plain events unless `--progress` was
+/// given, stderr is a terminal and the output format is `human`.
+#[derive(Default)]
+pub struct RunProgress {
+    bars: Option<Bars>,
+    visited: u64,
+    packaged: usize,
+    failed: usize,
+}
+
+impl RunProgress {
+    pub fn new(enabled: bool) -> Self {
+        if !enabled || !std::io::stderr().is_terminal() || !events::is_human() {
+            return Self::default();
+        }
+        let multi = MultiProgress::new();
+        let run = multi.add(ProgressBar::new(0));
+        run.set_style(
+            ProgressStyle::with_template("{bar:40.cyan/blue} {pos}/{len} crates  {msg}")
+                .expect("valid progress template")
+                .progress_chars("=> "),
+        );
+        let current = multi.add(ProgressBar::new_spinner());
+        current.set_style(
+            ProgressStyle::with_template("{spinner:.green} {msg} ({elapsed})")
+                .expect("valid progress template"),
+        );
+        current.enable_steady_tick(Duration::from_millis(120));
+        *ACTIVE.lock().unwrap_or_else(|e| e.into_inner()) = Some(multi);
+        let progress = RunProgress {
+            bars: Some(Bars { run, current }),
+            ..Self::default()
+        };
+        progress.update_counts();
+        progress
+    }
+
+    pub fn is_shown(&self) -> bool {
+        self.bars.is_some()
+    }
+
+    /// A line about the crate at hand: reported without bars, only logged
+    /// with them.
+    pub fn note(&self, line: &str) {
+        self.event(Event::note(line));
+    }
+
+    /// Report `event`; with bars, only failures are printed, above them,
+    /// unless `--verbose` was given.
+    pub fn event(&self, event: Event) {
+        if !self.is_shown() {
+            events::emit(event);
+        } else if event.is_failure() || Verbosity::current().is_verbose() {
+            eprint_line(&event.to_string());
+        } else {
+            log::info!("{}", event);
+        }
+    }
+
+    /// The next crate of the run is visited, with `queued` more waiting.
+    pub fn visit(&mut self, queued: usize) {
+        if let Some(bars) = &self.bars {
+            bars.run.set_length(self.visited + 1 + queued as u64);
+            bars.run.set_position(self.visited);
+        }
+        self.visited += 1;
+    }
+
+    /// `crate_name` `version` is being packaged.
+    pub fn start_crate(&self, crate_name: &str, version: &str) {
+        if let Some(bars) = &self.bars {
+            bars.current.reset_elapsed();
+            bars.current
+                .set_message(format!("packaging {} {}", crate_name, version));
+        }
+        self.event(Event::CrateStarted {
+            crate_name: crate_name.to_string(),
+            version: version.to_string(),
+        });
+    }
+
+    /// The crate being packaged is done: `outcome` is
+    /// [`Event::CratePackaged`] or [`Event::CrateFailed`].
+    pub fn finish_crate(&mut self, outcome: Event) {
+        if outcome.is_failure() {
+            self.failed += 1;
+        } else {
+            self.packaged += 1;
+        }
+        self.update_counts();
+        self.event(outcome);
+    }
+
+    fn update_counts(&self) {
+        if let Some(bars) = &self.bars {
+            bars.run.set_message(format!(
+                "✔ {} packaged  ✘ {} failed",
+                self.packaged, self.failed
+            ));
+        }
+    }
+
+    /// Remove the bars, e.g. before the summary of the run is printed.
+    pub fn finish(&mut self) {
+        if let Some(bars) = self.bars.take() {
+            bars.current.finish_and_clear();
+            bars.run.finish_and_clear();
+            *ACTIVE.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        }
+    }
+}
+
+impl Drop for RunProgress {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RunProgress;
+    use crate::events::Event;
+
+    #[test]
+    fn progress_stays_plain_without_a_terminal_or_the_flag() {
+        let mut progress = RunProgress::new(false);
+        assert!(!progress.is_shown());
+        progress.visit(3);
+        progress.start_crate("serde", "1.0.0");
+        progress.finish_crate(Event::CratePackaged {
+            crate_name: "serde".to_string(),
+            version: "1.0.0".to_string(),
+            package_dir: None,
+        });
+        progress.finish_crate(Event::CrateFailed {
+            crate_name: "serde_json".to_string(),
+            version: "1.0.0".to_string(),
+            error: "no spec".to_string(),
+        });
+        assert_eq!(
+            (progress.visited, progress.packaged, progress.failed),
+            (1, 1, 1)
+        );
+        progress.finish();
+    }
+}
@@ -11,6 +11,7 @@ use serde_json::Value;
 use sha2::{Digest, Sha256};
 use tar::Archive;
 
+use crate::tarball::{unpack_checked, ExtractionLimits};
 use crate::util::write_file_ensuring_dir;
 
 const FALLBACK_LICENSE: &str = "LicenseRef-Unknown-Please-Check-Manual";
@@ -179,7 +180,8 @@ struct ReleaseFile {
 
 fn fetch_pypi_json(package_name: &str) -> Result<Value> {
     let url = format!("https://pypi.org/pypi/{}/json", package_name);
-    let response = ureq::get(&url)
+    let response = crate::http::agent()
+        .get(&url)
         .call()
         .with_context(|| "failed to query PyPI metadata")?;
     let mut reader = response.into_reader();
@@ -283,7 +285,8 @@ fn pick_release_file(pypi_json: &Value, version: &str) -> Result<ReleaseFile> {
 }
 
 fn download_file(url: &str, destination: &Path) -> Result<()> {
-    let response = ureq::get(url)
+    let response = crate::http::agent()
+        .get(url)
         .call()
         .with_context(|| format!("failed to download source archive: {}", url))?;
     let mut reader = response.into_reader();
@@ -352,9 +355,15 @@ fn extract_tar_gz(archive_path: &Path, extract_dir: &Path) -> Result<()> {
         .with_context(|| format!("failed to open archive: {}", archive_path.display()))?;
     let decoder = GzDecoder::new(f);
     let mut archive = Archive::new(decoder);
-    archive
-        .unpack(extract_dir)
-        .with_context(|| format!("failed to extract archive: {}", archive_path.display()))?;
+    let entries = archive
+        .entries()
+        .with_context(|| format!("failed to read archive: {}", archive_path.display()))?;
+    let mut budget = ExtractionLimits::default().budget();
+    for entry in entries {
+        let mut entry = entry
+            .with_context(|| format!("failed to extract archive: {}", archive_path.display()))?;
+        unpack_checked(&mut entry, extract_dir, archive_path, &mut budget)?;
+    }
     Ok(())
 }
 
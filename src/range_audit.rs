@@ -16,16 +16,17 @@ use semver::{Comparator, Op, Version, VersionReq};
 
 use crate::crates::dependency_is_runtime_candidate;
 use crate::takopack::spec::normalize_crate_name;
-use crate::util::calculate_compat_version;
+use crate::version::compat_version;
 
 // ---------------------------------------------------------------------------
 // Public types
 // ---------------------------------------------------------------------------
 
 /// Policy for handling range capability warnings during spec generation.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
 pub enum RangeCapabilityPolicy {
     /// Print warnings to stderr but continue generating the spec.
+    #[default]
     Warn,
     /// Print errors and exit non-zero; abort spec generation.
     Error,
@@ -33,12 +34,6 @@ pub enum RangeCapabilityPolicy {
     Allow,
 }
 
-impl Default for RangeCapabilityPolicy {
-    fn default() -> Self {
-        RangeCapabilityPolicy::Warn
-    }
-}
-
 impl fmt::Display for RangeCapabilityPolicy {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -104,10 +99,10 @@ impl fmt::Display for RangeWarning {
 
 /// Compute the compat key for a semver `Version`.
 ///
-/// This delegates to [`crate::util::calculate_compat_version`] so the audit
+/// This delegates to [`crate::version::compat_version`] so the audit
 /// logic always stays in sync with the actual spec-generation policy.
 fn compat_key(version: &Version) -> String {
-    calculate_compat_version(version)
+    compat_version(version)
 }
 
 /// Given a version requirement string, determine whether it spans more than
@@ -466,6 +461,11 @@ fn collect_deps_from_table(
     out: &mut Vec<(String, String)>,
 ) {
     for (name, value) in table {
+        // `foo = { package = "bar", ... }` depends on bar
+        let name = value
+            .get("package")
+            .and_then(toml::Value::as_str)
+            .unwrap_or(name);
         let version = match value {
             toml::Value::String(v) => Some(v.clone()),
             toml::Value::Table(t) => t
@@ -475,7 +475,7 @@ fn collect_deps_from_table(
             _ => None,
         };
         if let Some(v) = version {
-            out.push((name.clone(), v));
+            out.push((name.to_string(), v));
         }
     }
 }
@@ -498,9 +498,7 @@ fn collect_dependency_paths(manifest: &toml::Value) -> Vec<String> {
     paths
 }
 
-fn dependency_tables<'a>(
-    manifest: &'a toml::Value,
-) -> Vec<&'a toml::map::Map<String, toml::Value>> {
+fn dependency_tables(manifest: &toml::Value) -> Vec<&toml::map::Map<String, toml::Value>> {
     let mut tables = Vec::new();
 
     for section in &["dependencies", "build-dependencies", "dev-dependencies"] {
@@ -614,9 +612,11 @@ fn provider_name_from_input(path: &Path) -> Option<String> {
 /// Audit a set of Cargo [`Dependency`] objects and return range warnings.
 ///
 /// This is the entry point used by `package` / `localpkg` spec generation.
+/// `namespace` is the capability namespace used in the warnings' context.
 pub fn audit_cargo_dependencies(
     deps: &[cargo::core::Dependency],
     provider_name: Option<&str>,
+    namespace: &str,
 ) -> Vec<RangeWarning> {
     let mut warnings = Vec::new();
 
@@ -644,11 +644,11 @@ pub fn audit_cargo_dependencies(
         let generated_cap = lower_bound.as_deref().map(|lb| {
             let crate_base = normalize_crate_name(dep.package_name().as_str());
             if lb.contains('-') {
-                format!("crate({}-{})", crate_base, lb)
+                format!("{}({}-{})", namespace, crate_base, lb)
             } else if let Ok(ver) = Version::parse(lb) {
-                format!("crate({}-{})", crate_base, calculate_compat_version(&ver))
+                format!("{}({}-{})", namespace, crate_base, compat_version(&ver))
             } else {
-                format!("crate({})", crate_base)
+                format!("{}({})", namespace, crate_base)
             }
         });
 
@@ -927,6 +927,30 @@ version = ">=0.61,<0.63"
         assert!(warnings.iter().any(|w| w.dependency == "windows"));
     }
 
+    #[test]
+    fn scan_cargo_toml_names_renamed_dependency_by_package() {
+        let temp = tempfile::tempdir().unwrap();
+        let cargo_toml = temp.path().join("Cargo.toml");
+        fs::write(
+            &cargo_toml,
+            r#"
+[package]
+name = "fixture"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+goblin-old = { package = "goblin", version = ">=0.9,<0.11" }
+"#,
+        )
+        .unwrap();
+
+        let warnings = scan_cargo_toml(&cargo_toml, Some("rust-fixture-0.1")).unwrap();
+
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].dependency, "goblin");
+    }
+
     #[test]
     fn scan_directory_sees_path_member_dependency() {
         let temp = tempfile::tempdir().unwrap();
@@ -982,7 +1006,8 @@ goblin = ">=0.9,<0.11"
 "#,
         );
 
-        let warnings = audit_cargo_dependencies(manifest.dependencies(), Some("rust-fixture-0.1"));
+        let warnings =
+            audit_cargo_dependencies(manifest.dependencies(), Some("rust-fixture-0.1"), "crate");
 
         assert!(warnings.is_empty());
     }
@@ -1001,7 +1026,8 @@ version = ">=0.61,<0.63"
 "#,
         );
 
-        let warnings = audit_cargo_dependencies(manifest.dependencies(), Some("rust-fixture-0.1"));
+        let warnings =
+            audit_cargo_dependencies(manifest.dependencies(), Some("rust-fixture-0.1"), "crate");
 
         assert!(warnings.iter().any(|w| w.dependency == "windows"));
     }
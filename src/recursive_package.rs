@@ -1,11 +1,34 @@
 use anyhow::{Context, Result};
 use chrono::Local;
 use clap::Parser;
-use std::collections::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use crate::package::{PackageExecuteArgs, PackageExtractArgs, PackageInitArgs, PackageProcess};
+use crate::audit::{AuditStatus, VetStore};
+use crate::build_order::{self, BUILD_ORDER_TXT};
+use crate::cancel::{self, CancellationToken};
+use crate::config::Config;
+use crate::crates::{activate_features, CrateInfo, SparseIndex};
+use crate::dep_filter::{self, Decision, DependencyFilter, Edge, EdgeKind, RuleFilter, SkipReason};
+use crate::events::{self, Event};
+use crate::failure_report::{self, Failure, Phase, Skip};
+use crate::lockfile_parser::{parse_lockfile_with_member, DependencyGraph};
+use crate::name_collision::NameRegistry;
+use crate::output_stamp;
+use crate::owners::{OwnerStore, OwnershipChange};
+use crate::package::{
+    DownloadArgs, PackageExecuteArgs, PackageExtractArgs, PackageInitArgs, PackageProcess,
+};
+use crate::progress::RunProgress;
+use crate::repo_query::{RepoQuery, DEFAULT_REPO_QUERY};
+use crate::skip_list::SkipList;
+use crate::takopack::changelog::ChangelogSource;
+use crate::test_mode::TestMode;
+use crate::toolchain_crates::ToolchainCrates;
 
 /// Arguments for recursive packaging command
 #[derive(Debug, Clone, Parser)]
@@ -18,10 +41,98 @@ pub struct RecursivePackageArgs {
     /// Output root directory. Each package is generated under this root.
     #[arg(short = 'o', long, value_name = "OUT_ROOT")]
     pub output: Option<PathBuf>,
+    /// Continue an interrupted run in its output root, skipping the crates
+    /// it already packaged.
+    #[arg(long, value_name = "DIR", conflicts_with = "output")]
+    pub resume: Option<PathBuf>,
+    /// Resolve every crate from the local mirror ([mirror].local_path in
+    /// takopack.toml) instead of crates.io.
+    #[arg(long)]
+    pub offline: bool,
+    /// Local crates mirror directory; implies --offline.
+    #[arg(long, value_name = "DIR")]
+    pub mirror: Option<PathBuf>,
+    /// Package yanked crate versions in the dependency graph and only warn
+    /// about them, instead of leaving them out and failing once the run is
+    /// done.
+    #[arg(long)]
+    pub allow_yanked: bool,
+    /// Compare each crate's crates.io owners with those recorded on the
+    /// last run and report any change.
+    #[arg(long)]
+    pub check_owners: bool,
+    /// cargo-vet store (the `supply-chain/` directory) to look up the audit
+    /// status of every packaged crate in.
+    #[arg(long, value_name = "DIR")]
+    pub vet_store: Option<PathBuf>,
+    /// Fail if any packaged crate is neither audited nor exempted in the
+    /// cargo-vet store.
+    #[arg(long, requires = "vet_store")]
+    pub require_audit: bool,
+    /// Target distro profile for every generated spec.
+    #[arg(long, value_name = "NAME")]
+    pub profile: Option<String>,
+    /// Do not follow dependencies more than N edges away from the root
+    /// crate.
+    #[arg(long, value_name = "N")]
+    pub max_depth: Option<usize>,
+    /// Stop after attempting N crates.
+    #[arg(long, value_name = "N")]
+    pub max_crates: Option<usize>,
+    /// Resolve the dependency graph and list the crates that would be
+    /// packaged, without writing anything.
+    #[arg(long, conflicts_with = "resume")]
+    pub dry_run: bool,
+    /// Do not package CRATE (any version) or CRATE@VERSION (where VERSION
+    /// satisfies the requirement); may be repeated.
+    #[arg(long, value_name = "CRATE[@VERSION]")]
+    pub skip: Vec<String>,
+    /// Read crates not to package from FILE, one per line.
+    #[arg(long, value_name = "FILE")]
+    pub skip_file: Option<PathBuf>,
+    /// Do not follow the dependencies of skipped crates either.
+    #[arg(long)]
+    pub skip_subtrees: bool,
+    /// Leave out crates the distro repositories already provide at a
+    /// satisfying version (queried with `repo_query` from takopack.toml,
+    /// `dnf repoquery` by default).
+    #[arg(long)]
+    pub check_repo: bool,
+    /// Package exactly the versions pinned by the root crate's Cargo.lock
+    /// (the one it ships, or one generated once) instead of resolving each
+    /// dependency on its own.
+    #[arg(long)]
+    pub locked: bool,
+    /// Keep package directories of the output root that already hold the
+    /// same crate version, even when takopack.toml or takopack changed
+    /// since they were generated. Without it, they are only kept when
+    /// nothing changed.
+    #[arg(long)]
+    pub skip_existing: bool,
+    /// Show a progress bar with live counts instead of the lines for every
+    /// crate. Ignored unless stderr is a terminal.
+    #[arg(long)]
+    pub progress: bool,
+    #[command(flatten)]
+    pub download: DownloadArgs,
+}
+
+impl RecursivePackageArgs {
+    /// The `--skip` and `--skip-file` entries.
+    pub fn skip_list(&self) -> Result<SkipList> {
+        let mut skip = SkipList::default();
+        if let Some(file) = &self.skip_file {
+            skip.add_file(file)?;
+        }
+        for entry in &self.skip {
+            skip.add(entry)?;
+        }
+        Ok(skip)
+    }
 }
 
 /// Information about a failed package
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FailedPackage {
     pub crate_name: String,
     pub version: String,
@@ -29,7 +140,65 @@ pub struct FailedPackage {
 }
 
 type DependencySpec = (String, Option<String>);
-type PackagedCrate = (PathBuf, String, Vec<DependencySpec>);
+
+/// Outcome of packaging (or only resolving) one crate.
+struct PackagedCrate {
+    spec_path: PathBuf,
+    /// Real crate name and version
+    crate_name: String,
+    version: String,
+    dependencies: Vec<DependencySpec>,
+    /// Dependencies that were not followed
+    skips: Vec<Skip>,
+    proc_macro: bool,
+    /// The package directory of an earlier run was kept as it is
+    kept: bool,
+    /// crates.io has yanked this version, packaged with `--allow-yanked`
+    yanked: bool,
+}
+
+/// Error of [`RecursivePackager::package_single_crate`] for a yanked
+/// version, before anything of it is written.
+#[derive(Debug)]
+struct YankedVersion;
+
+impl fmt::Display for YankedVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("version yanked from crates.io")
+    }
+}
+
+impl std::error::Error for YankedVersion {}
+
+/// File in the output root recording the progress of a run, for `--resume`.
+pub const STATE_FILE: &str = ".takopack-vendor-state.json";
+
+/// Copy of the root crate's Cargo.lock kept in the output root by
+/// `--locked` runs.
+pub const LOCK_FILE: &str = ".takopack-vendor.lock";
+
+/// A crate packaged by a run, with the dependencies it was followed into.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PackagedState {
+    crate_name: String,
+    version: String,
+    dependencies: Vec<DependencySpec>,
+}
+
+/// Contents of [`STATE_FILE`].
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct RunState {
+    /// Root crate of the run
+    crate_name: String,
+    version: Option<String>,
+    processed: Vec<PackagedState>,
+    /// Real name and version of every packaged crate, for the build order
+    #[serde(default)]
+    packaged: Vec<PackagedState>,
+    in_progress: Vec<(String, String)>,
+    failed: Vec<FailedPackage>,
+    crate_name_map: HashMap<String, String>,
+}
 
 /// State for recursive package processing
 pub struct RecursivePackager {
@@ -41,37 +210,321 @@ pub struct RecursivePackager {
     pub in_progress: HashSet<(String, String)>,
     /// List of failed packages
     pub failed: Vec<FailedPackage>,
+    /// Failures of this run with their phase and error chain, for
+    /// [`Self::write_failure_report`]
+    pub failures: Vec<Failure>,
     /// Statistics
     pub total_attempted: usize,
     /// Mapping from normalized name (with dashes) to real crate name
     /// Example: "parking-lot-core" -> "parking_lot_core"
     ///          "proc-macro2" -> "proc-macro2"
     pub crate_name_map: HashMap<String, String>,
+    /// Checked between crates and between pipeline phases
+    pub cancel: CancellationToken,
+    /// Offline mirror to resolve crates from; None uses crates.io
+    pub mirror: Option<PathBuf>,
+    /// Download settings overriding the ones from takopack.toml
+    pub download: DownloadArgs,
+    /// Downgrade the yanked-version check from an error to a warning
+    pub allow_yanked: bool,
+    /// Packaged (crate_name, version) pairs that crates.io lists as yanked
+    pub yanked: Vec<(String, String)>,
+    /// Owner lists from earlier runs; None skips the ownership check
+    pub owners: Option<OwnerStore>,
+    /// Crates whose owners changed since the version recorded last time
+    pub ownership_changes: Vec<OwnershipChange>,
+    /// cargo-vet audits to check packaged crates against
+    pub vet_store: Option<VetStore>,
+    /// Fail in [`Self::check_audits`] when a crate is unaudited
+    pub require_audit: bool,
+    /// Audit status of each packaged (crate_name, version)
+    pub audit_status: Vec<(String, String, AuditStatus)>,
+    /// Target distro profile; None uses the one from takopack.toml
+    pub profile: Option<String>,
+    /// Test mode every spec is generated in
+    pub test_mode: TestMode,
+    /// Consulted for every dependency before the built-in heuristics
+    pub filters: Vec<Box<dyn DependencyFilter>>,
+    /// Dependencies followed from each processed crate, kept in the state file
+    dependencies: HashMap<(String, String), Vec<DependencySpec>>,
+    /// Crates packaged before the run was resumed whose dependencies have
+    /// not been revisited yet
+    resumed: HashSet<(String, String)>,
+    /// Root crate and version requirement of the run
+    root: Option<(String, Option<String>)>,
+    /// Dependencies deeper than this are not followed
+    pub max_depth: Option<usize>,
+    /// No more crates are attempted once this many have been
+    pub max_crates: Option<usize>,
+    /// Dependencies left out because of `max_depth` or `max_crates`
+    pub limited: Vec<DependencySpec>,
+    /// Only resolve crates; nothing is written to the output directory
+    pub dry_run: bool,
+    /// Real (crate_name, version) of every packaged crate, in order
+    pub packaged: Vec<(String, String)>,
+    /// Dependencies of every packaged crate by its real name and version,
+    /// kept in the state file for the build order
+    packaged_dependencies: BTreeMap<(String, String), Vec<DependencySpec>>,
+    /// Packaged proc-macro crates. They are only listed in the summary;
+    /// the specs of their dependents still require them in `Requires:`
+    pub proc_macros: Vec<(String, String)>,
+    /// Crates not to package
+    pub skip_list: SkipList,
+    /// Leave out the dependencies of skipped crates as well
+    pub skip_subtrees: bool,
+    /// Every crate or dependency left out on purpose, and why
+    pub skips: Vec<Skip>,
+    /// Distro repositories to look crates up in before packaging them
+    pub repo: Option<RepoQuery>,
+    /// Follow the versions pinned by the root crate's Cargo.lock
+    pub locked: bool,
+    /// Keep existing package directories of the same crate version, see
+    /// [`output_stamp`]
+    pub skip_existing: bool,
+    /// Crates whose existing package directory was kept
+    pub kept: Vec<(String, String)>,
+    /// The root crate's lockfile, once loaded
+    lock: Option<DependencyGraph>,
+    /// Features requested of each crate (by name with `-`) by the crates
+    /// depending on it, and whether any of them wants the default features.
+    /// Crates not in here, such as the root, get their default features.
+    requested_features: RefCell<HashMap<String, (BTreeSet<String>, bool)>>,
+    /// The features of `requested_features` each crate's dependencies were
+    /// last followed with; a crate is visited again when a later edge
+    /// requests more.
+    followed_features: RefCell<HashMap<String, (BTreeSet<String>, bool)>>,
+    /// Crate of every package name in the output root and of this run
+    names: RefCell<NameRegistry>,
+    /// Progress bars or plain lines, see [`crate::progress`]
+    progress: RunProgress,
 }
 
 impl RecursivePackager {
     /// Create a new recursive packager with timestamp-based directory
     pub fn new(base_path: Option<PathBuf>) -> Result<Self> {
-        let base_dir = if let Some(path) = base_path {
-            path
-        } else {
-            let timestamp = Local::now().format("%Y%m%d_%H%M%S").to_string();
-            PathBuf::from(&timestamp)
-        };
+        let base_dir = base_path.unwrap_or_else(timestamped_dir);
 
         fs::create_dir_all(&base_dir)
             .with_context(|| format!("Failed to create base directory: {:?}", base_dir))?;
 
-        println!("Created output directory: {}", base_dir.display());
+        events::emit(Event::note(format!(
+            "Created output directory: {}",
+            base_dir.display()
+        )));
+
+        let packager = Self::with_base_dir(base_dir);
+        *packager.names.borrow_mut() = NameRegistry::for_run(&packager.base_dir)?;
+        Ok(packager)
+    }
+
+    /// A packager that only resolves the dependency graph, for `--dry-run`.
+    /// The output directory is not created.
+    pub fn new_dry_run(base_path: Option<PathBuf>) -> Self {
+        let mut packager = Self::with_base_dir(base_path.unwrap_or_else(timestamped_dir));
+        packager.dry_run = true;
+        packager
+    }
 
-        Ok(RecursivePackager {
+    fn with_base_dir(base_dir: PathBuf) -> Self {
+        RecursivePackager {
             base_dir,
             processed: HashSet::new(),
             in_progress: HashSet::new(),
             failed: Vec::new(),
+            failures: Vec::new(),
             total_attempted: 0,
             crate_name_map: HashMap::new(),
-        })
+            cancel: CancellationToken::new(),
+            mirror: None,
+            download: DownloadArgs::default(),
+            allow_yanked: false,
+            yanked: Vec::new(),
+            owners: None,
+            ownership_changes: Vec::new(),
+            vet_store: None,
+            require_audit: false,
+            audit_status: Vec::new(),
+            profile: None,
+            test_mode: TestMode::default(),
+            filters: Vec::new(),
+            dependencies: HashMap::new(),
+            resumed: HashSet::new(),
+            root: None,
+            max_depth: None,
+            max_crates: None,
+            limited: Vec::new(),
+            dry_run: false,
+            packaged: Vec::new(),
+            packaged_dependencies: BTreeMap::new(),
+            proc_macros: Vec::new(),
+            skip_list: SkipList::default(),
+            skip_subtrees: false,
+            skips: Vec::new(),
+            repo: None,
+            locked: false,
+            skip_existing: false,
+            kept: Vec::new(),
+            lock: None,
+            requested_features: RefCell::new(HashMap::new()),
+            followed_features: RefCell::new(HashMap::new()),
+            names: RefCell::new(NameRegistry::default()),
+            progress: RunProgress::default(),
+        }
+    }
+
+    /// Continue the run whose output root is `dir`. Crates it packaged are
+    /// not packaged again; the ones that failed or were interrupted are
+    /// retried.
+    pub fn resume(dir: &Path) -> Result<Self> {
+        let path = dir.join(STATE_FILE);
+        let text = fs::read_to_string(&path).with_context(|| {
+            format!(
+                "Failed to read {}; was {} created by `takopack cargo vendor`?",
+                path.display(),
+                dir.display()
+            )
+        })?;
+        let state: RunState = serde_json::from_str(&text)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+
+        let mut packager = Self::new(Some(dir.to_path_buf()))?;
+        events::emit(Event::note(format!(
+            "Resuming {} {}: {} crate(s) already packaged, {} to retry",
+            state.crate_name,
+            state.version.as_deref().unwrap_or("latest"),
+            state.processed.len(),
+            state.failed.len() + state.in_progress.len()
+        )));
+        for packaged in state.processed {
+            let key = (packaged.crate_name, packaged.version);
+            packager.processed.insert(key.clone());
+            packager.resumed.insert(key.clone());
+            packager.dependencies.insert(key, packaged.dependencies);
+        }
+        for packaged in state.packaged {
+            packager.packaged_dependencies.insert(
+                (packaged.crate_name, packaged.version),
+                packaged.dependencies,
+            );
+        }
+        packager.crate_name_map = state.crate_name_map;
+        packager.root = Some((state.crate_name, state.version));
+        Ok(packager)
+    }
+
+    /// Resolve crates from a local mirror instead of crates.io.
+    pub fn with_mirror(mut self, mirror: Option<PathBuf>) -> Self {
+        self.mirror = mirror;
+        self
+    }
+
+    /// Download crates as `download` says instead of as configured.
+    pub fn with_download(mut self, download: DownloadArgs) -> Self {
+        self.download = download;
+        self
+    }
+
+    /// Warn about yanked versions instead of failing in [`Self::check_yanked`].
+    pub fn with_allow_yanked(mut self, allow_yanked: bool) -> Self {
+        self.allow_yanked = allow_yanked;
+        self
+    }
+
+    /// Check crate owners against `store` as crates are packaged.
+    pub fn with_owner_store(mut self, store: Option<OwnerStore>) -> Self {
+        self.owners = store;
+        self
+    }
+
+    /// Look up every packaged crate in a cargo-vet store; with
+    /// `require_audit`, unaudited crates fail [`Self::check_audits`].
+    pub fn with_vet_store(mut self, store: Option<VetStore>, require_audit: bool) -> Self {
+        self.vet_store = store;
+        self.require_audit = require_audit;
+        self
+    }
+
+    /// Generate specs for the distro profile `profile`.
+    pub fn with_profile(mut self, profile: Option<String>) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    /// Generate specs in `test_mode`, see [`crate::test_mode`].
+    pub fn with_test_mode(mut self, test_mode: TestMode) -> Self {
+        self.test_mode = test_mode;
+        self
+    }
+
+    /// Let `filter` decide which dependencies to descend into. Filters are
+    /// consulted in the order they were added, all of them before
+    /// [`dep_filter::DefaultFilter`].
+    pub fn with_filter(mut self, filter: impl DependencyFilter + 'static) -> Self {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    /// Add the `[[dependency_filters]]` rules and the `toolchain_crates`
+    /// of `config`.
+    pub fn with_configured_filters(mut self, config: &Config) -> Result<Self> {
+        if !config.dependency_filters.is_empty() {
+            let rules = RuleFilter::new(config.dependency_filters.clone())?;
+            self = self.with_filter(rules);
+        }
+        if !config.toolchain_crates.is_empty() {
+            self = self.with_filter(ToolchainCrates::from_config(config)?);
+        }
+        Ok(self)
+    }
+
+    /// Bound the run to dependencies at most `max_depth` edges from the root
+    /// and to `max_crates` attempted crates.
+    pub fn with_limits(mut self, max_depth: Option<usize>, max_crates: Option<usize>) -> Self {
+        self.max_depth = max_depth;
+        self.max_crates = max_crates;
+        self
+    }
+
+    /// Leave the crates of `skip_list` unpackaged, and with `subtrees` the
+    /// dependencies only they lead to.
+    pub fn with_skip_list(mut self, skip_list: SkipList, subtrees: bool) -> Self {
+        self.skip_list = skip_list;
+        self.skip_subtrees = subtrees;
+        self
+    }
+
+    /// Look crates up in the distro repositories with the `repo_query` of
+    /// `config`, and leave out those already available.
+    pub fn with_repo_check(mut self, config: &Config) -> Result<Self> {
+        let command = config.repo_query.as_deref().unwrap_or(DEFAULT_REPO_QUERY);
+        self.repo = Some(RepoQuery::new(command, config.crate_namespace())?);
+        Ok(self)
+    }
+
+    /// Keep existing package directories of the same crate version even
+    /// when their inputs changed.
+    pub fn with_skip_existing(mut self, skip_existing: bool) -> Self {
+        self.skip_existing = skip_existing;
+        self
+    }
+
+    /// Package the versions pinned by the root crate's Cargo.lock.
+    pub fn with_locked(mut self, locked: bool) -> Self {
+        self.locked = locked;
+        self
+    }
+
+    /// Show progress bars instead of lines for every crate, if stderr is a
+    /// terminal.
+    pub fn with_progress(mut self, enabled: bool) -> Self {
+        self.progress = RunProgress::new(enabled);
+        self
+    }
+
+    /// Stop the run at the next checkpoint once `token` is cancelled.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancel = token;
+        self
     }
 
     /// Process a crate and its dependencies recursively
@@ -81,46 +534,191 @@ impl RecursivePackager {
         crate_name: &str,
         version: Option<&str>,
     ) -> Result<()> {
-        println!("crate_name is {}", crate_name);
+        if let Some((root_name, root_version)) = &self.root {
+            if root_name != crate_name || root_version.as_deref() != version {
+                anyhow::bail!(
+                    "{} holds a run for {} {}, not {} {}",
+                    self.base_dir.display(),
+                    root_name,
+                    root_version.as_deref().unwrap_or("latest"),
+                    crate_name,
+                    version.unwrap_or("latest")
+                );
+            }
+        }
+        self.root = Some((crate_name.to_string(), version.map(str::to_string)));
+        if self.locked && self.lock.is_none() {
+            self.lock = Some(self.lock_root(crate_name, version)?);
+        }
+        // A work stack rather than recursion, so that deep dependency
+        // chains cannot exhaust the call stack. Dependencies are pushed in
+        // reverse, so crates are still visited depth-first in the order
+        // they are listed.
+        let mut stack = vec![(crate_name.to_string(), version.map(str::to_string), 0)];
+        while let Some((name, version, depth)) = stack.pop() {
+            self.progress.visit(stack.len());
+            let dependencies = self.process_crate_at_depth(&name, version.as_deref(), depth)?;
+            stack.extend(
+                dependencies
+                    .into_iter()
+                    .rev()
+                    .map(|(dep_name, dep_version)| (dep_name, dep_version, depth + 1)),
+            );
+        }
+        self.progress.finish();
+        Ok(())
+    }
+
+    /// The dependency graph pinned by the root crate's Cargo.lock. The
+    /// lockfile is kept as [`LOCK_FILE`] in the output root, so a resumed
+    /// run packages the same versions.
+    fn lock_root(&self, crate_name: &str, version: Option<&str>) -> Result<DependencyGraph> {
+        let init_args = PackageInitArgs {
+            crate_name: crate_name.to_string(),
+            version: version.map(|s| s.to_string()),
+            offline: self.mirror.is_some(),
+            mirror: self.mirror.clone(),
+            registry: None,
+            index_url: None,
+            allow_yanked: false,
+            crate_file: None,
+            download: self.download.clone(),
+        };
+        let mut process = PackageProcess::init(init_args, self.profile.as_deref(), self.test_mode)
+            .context(Phase::Download)
+            .with_context(|| format!("Failed to init package process for {}", crate_name))?;
+
+        let saved = self.base_dir.join(LOCK_FILE);
+        let content = if saved.is_file() {
+            fs::read_to_string(&saved)
+                .with_context(|| format!("Failed to read {}", saved.display()))?
+        } else {
+            let content = process
+                .crate_info
+                .lockfile(&process.config.extraction)
+                .with_context(|| format!("Failed to lock the dependencies of {}", crate_name))?;
+            if !self.dry_run {
+                fs::write(&saved, &content)
+                    .with_context(|| format!("Failed to write {}", saved.display()))?;
+            }
+            content
+        };
+
+        let crate_info = &process.crate_info;
+        let lock =
+            parse_lockfile_with_member(&content, crate_info.crate_name(), crate_info.version())?;
+        self.progress.note(&format!(
+            "Packaging the {} crate version(s) locked for {} {}",
+            lock.len() - 1,
+            crate_info.crate_name(),
+            crate_info.version()
+        ));
+        Ok(lock)
+    }
+
+    /// With `--locked`, the versions the root crate's Cargo.lock pins the
+    /// dependencies of `crate_info` to.
+    fn locked_dependencies(
+        &self,
+        crate_info: &CrateInfo,
+    ) -> Option<HashMap<String, semver::Version>> {
+        self.lock
+            .as_ref()?
+            .get_dependencies_map(crate_info.crate_name(), crate_info.version())
+    }
+
+    /// Package one crate `depth` edges away from the root, returning the
+    /// dependencies to follow from it.
+    fn process_crate_at_depth(
+        &mut self,
+        crate_name: &str,
+        version: Option<&str>,
+        depth: usize,
+    ) -> Result<Vec<DependencySpec>> {
+        self.cancel.check()?;
+        self.progress.note(&format!("crate_name is {}", crate_name));
         let version_str = version.unwrap_or("latest");
         let key = (crate_name.to_string(), version_str.to_string());
 
+        // Crates packaged before a resume are not packaged again, but the
+        // run may have been interrupted before it got to their dependencies
+        if self.resumed.remove(&key) {
+            self.progress.note(&format!(
+                "Skipping {} {} (packaged before the run was resumed)",
+                crate_name, version_str
+            ));
+            return Ok(self.dependencies.get(&key).cloned().unwrap_or_default());
+        }
+
         // Check if already processed or failed
         if self.processed.contains(&key) {
-            println!(
+            if self.wants_more_features(crate_name) {
+                return self.reopen_crate(crate_name, version, &key, depth);
+            }
+            self.progress.note(&format!(
                 "Skipping {} {} (already processed)",
                 crate_name, version_str
-            );
-            return Ok(());
+            ));
+            return Ok(Vec::new());
         }
 
         // Check if currently in progress (circular dependency detection)
         if self.in_progress.contains(&key) {
-            println!(
+            self.progress.note(&format!(
                 "Circular dependency detected for {} {}, skipping",
                 crate_name, version_str
-            );
-            return Ok(());
+            ));
+            return Ok(Vec::new());
         }
 
-        // Check if any version of this crate has already been processed OR is currently being processed
-        // This prevents re-packaging and overwriting when a dependency requests a different version
-        let crate_already_packaged = self.processed.iter().any(|(name, _)| name == crate_name);
-        let crate_in_progress = self.in_progress.iter().any(|(name, _)| name == crate_name);
-
-        if crate_already_packaged {
-            println!(
+        // Semver-incompatible versions (syn 1 and syn 2) are packaged side
+        // by side as rust-<name>-<compat>. Within one compat branch, the
+        // version packaged first serves every requirement, so its output
+        // directory is not overwritten. Requirements only name a branch, so
+        // they are compared with the versions they were resolved to.
+        let normalized_name = crate_name.replace('_', "-");
+        let serving = self
+            .packaged_dependencies
+            .keys()
+            .find(|(name, packaged)| {
+                name.replace('_', "-") == normalized_name
+                    && serves_requirement(version_str, packaged)
+            })
+            .cloned();
+        if let Some((name, packaged)) = serving {
+            if self.wants_more_features(crate_name) {
+                let exact = format!("={}", packaged);
+                return self.reopen_crate(&name, Some(&exact), &(name.clone(), packaged), depth);
+            }
+            self.progress.note(&format!(
                 "Skipping {} {} (another version already packaged)",
                 crate_name, version_str
-            );
-            return Ok(());
+            ));
+            return Ok(Vec::new());
         }
-        if crate_in_progress {
-            println!(
+        if self
+            .in_progress
+            .iter()
+            .any(|(name, other)| name == crate_name && same_compat_branch(version_str, other))
+        {
+            self.progress.note(&format!(
                 "Skipping {} {} (another version currently being processed)",
                 crate_name, version_str
-            );
-            return Ok(());
+            ));
+            return Ok(Vec::new());
+        }
+
+        // Refused yanked versions are not processed, so that a resumed run
+        // with --allow-yanked packages them
+        if !self.allow_yanked
+            && self
+                .yanked
+                .iter()
+                .any(|(name, yanked)| name == crate_name && yanked == version_str)
+        {
+            self.progress
+                .note(&format!("Skipping {} {} (yanked)", crate_name, version_str));
+            return Ok(Vec::new());
         }
 
         // Check if already failed
@@ -129,128 +727,322 @@ impl RecursivePackager {
             .iter()
             .any(|f| f.crate_name == crate_name && f.version == version_str)
         {
-            println!(
+            self.progress.note(&format!(
                 "Skipping {} {} (previously failed)",
                 crate_name, version_str
-            );
-            return Ok(());
+            ));
+            return Ok(Vec::new());
+        }
+
+        if self.skip_list.matches(crate_name, version) {
+            return self.skip_crate(crate_name, version, depth);
+        }
+        if let Some(repo) = &self.repo {
+            if let Some(available) = repo.find(crate_name, version)? {
+                // Whatever it depends on is in the repositories too
+                let reason = SkipReason::InRepository(available.to_string());
+                self.progress.event(Event::CrateSkipped {
+                    crate_name: crate_name.to_string(),
+                    version: version_str.to_string(),
+                    reason: reason.to_string(),
+                });
+                self.record_skip(crate_name, version_str, None, reason);
+                return Ok(Vec::new());
+            }
+        }
+
+        if self
+            .max_crates
+            .is_some_and(|max| self.total_attempted >= max)
+        {
+            self.progress.note(&format!(
+                "Not packaging {} {} (--max-crates reached)",
+                crate_name, version_str
+            ));
+            self.limited
+                .push((crate_name.to_string(), version.map(str::to_string)));
+            self.record_skip(crate_name, version_str, None, SkipReason::MaxCrates);
+            return Ok(Vec::new());
         }
 
         // Mark as in progress
         self.in_progress.insert(key.clone());
         self.total_attempted += 1;
-        println!("\nProcessing {} {}...", crate_name, version_str);
+        self.save_state()?;
+        self.progress.start_crate(crate_name, version_str);
 
         // Try to package this crate
         // If crate_name contains '-', try both '-' and '_' versions
-        let (_spec_path, _real_crate_name, dependencies) =
-            match self.package_single_crate(crate_name, version) {
-                Ok((path, real_name, deps)) => {
-                    println!(
-                        "Successfully packaged {} {} (real name: {})",
-                        crate_name, version_str, real_name
-                    );
-
-                    // Store the mapping: normalized name (with dashes) -> real crate name
-                    let normalized_name = crate_name.replace('_', "-");
-                    self.crate_name_map
-                        .insert(normalized_name, real_name.clone());
+        let dependencies = match self.package_single_crate(crate_name, version, depth, false) {
+            Ok(packaged) => self.record_success(crate_name, &key, packaged),
+            Err(e) if cancel::is_cancelled(&e) => {
+                self.in_progress.remove(&key);
+                return Err(e);
+            }
+            Err(e) if e.is::<YankedVersion>() => {
+                self.in_progress.remove(&key);
+                self.record_yanked_skip(crate_name, version_str);
+                self.save_state()?;
+                return Ok(Vec::new());
+            }
+            Err(e) => {
+                let error_msg = format!("{:#}", e);
 
-                    self.in_progress.remove(&key);
-                    self.processed.insert(key.clone());
-                    (path, real_name, deps)
-                }
-                Err(e) => {
-                    let error_msg = format!("{:#}", e);
-
-                    // If the crate name contains dashes and packaging failed,
-                    // try with underscores (e.g., parking-lot-core -> parking_lot_core)
-                    if crate_name.contains('-') {
-                        let alt_name = crate_name.replace('-', "_");
-                        println!(
-                            "Failed with '{}', trying alternate name '{}'...",
-                            crate_name, alt_name
-                        );
-
-                        match self.package_single_crate(&alt_name, version) {
-                            Ok((path, real_name, deps)) => {
-                                println!(
-                                    "Successfully packaged {} {} (as {}, real name: {})",
-                                    crate_name, version_str, alt_name, real_name
-                                );
-
-                                // Store the mapping: normalized name (with dashes) -> real crate name
-                                let normalized_name = crate_name.replace('_', "-");
-                                self.crate_name_map
-                                    .insert(normalized_name, real_name.clone());
-
-                                self.in_progress.remove(&key);
-                                self.processed.insert(key.clone());
-                                (path, real_name, deps)
-                            }
-                            Err(e2) => {
-                                let error_msg2 = format!("{:#}", e2);
-                                println!(
-                                    "Failed to package {} {}: {} (also tried {})",
-                                    crate_name, version_str, error_msg, alt_name
-                                );
-                                self.in_progress.remove(&key);
-                                self.failed.push(FailedPackage {
-                                    crate_name: crate_name.to_string(),
-                                    version: version_str.to_string(),
-                                    error: format!(
-                                        "Both failed - '{}': {}, '{}': {}",
-                                        crate_name, error_msg, alt_name, error_msg2
-                                    ),
-                                });
-                                return Ok(());
-                            }
+                // If the crate name contains dashes and packaging failed,
+                // try with underscores (e.g., parking-lot-core -> parking_lot_core)
+                if crate_name.contains('-') {
+                    let alt_name = crate_name.replace('-', "_");
+                    self.progress.note(&format!(
+                        "Failed with '{}', trying alternate name '{}'...",
+                        crate_name, alt_name
+                    ));
+
+                    match self.package_single_crate(&alt_name, version, depth, false) {
+                        Ok(packaged) => self.record_success(crate_name, &key, packaged),
+                        Err(e2) if cancel::is_cancelled(&e2) => {
+                            self.in_progress.remove(&key);
+                            return Err(e2);
+                        }
+                        Err(e2) => {
+                            let error_msg2 = format!("{:#}", e2);
+                            self.progress.finish_crate(Event::CrateFailed {
+                                crate_name: crate_name.to_string(),
+                                version: version_str.to_string(),
+                                error: format!("{} (also tried {})", error_msg, alt_name),
+                            });
+                            self.in_progress.remove(&key);
+                            self.failures
+                                .push(Failure::new(crate_name, version_str, &e));
+                            self.failed.push(FailedPackage {
+                                crate_name: crate_name.to_string(),
+                                version: version_str.to_string(),
+                                error: format!(
+                                    "Both failed - '{}': {}, '{}': {}",
+                                    crate_name, error_msg, alt_name, error_msg2
+                                ),
+                            });
+                            self.save_state()?;
+                            return Ok(Vec::new());
                         }
-                    } else {
-                        println!(
-                            "Failed to package {} {}: {}",
-                            crate_name, version_str, error_msg
-                        );
-                        self.in_progress.remove(&key);
-                        self.failed.push(FailedPackage {
-                            crate_name: crate_name.to_string(),
-                            version: version_str.to_string(),
-                            error: error_msg,
-                        });
-                        return Ok(());
                     }
+                } else {
+                    self.progress.finish_crate(Event::CrateFailed {
+                        crate_name: crate_name.to_string(),
+                        version: version_str.to_string(),
+                        error: error_msg.clone(),
+                    });
+                    self.in_progress.remove(&key);
+                    self.failures
+                        .push(Failure::new(crate_name, version_str, &e));
+                    self.failed.push(FailedPackage {
+                        crate_name: crate_name.to_string(),
+                        version: version_str.to_string(),
+                        error: error_msg,
+                    });
+                    self.save_state()?;
+                    return Ok(Vec::new());
                 }
-            };
+            }
+        };
 
-        println!(
-            "Found {} runtime dependencies for {}",
+        self.dependencies.insert(key, dependencies.clone());
+        self.save_state()?;
+
+        self.progress.note(&format!(
+            "Found {} dependencies to follow for {}",
             dependencies.len(),
             crate_name
-        );
+        ));
+
+        if self.max_depth.is_some_and(|max| depth >= max) {
+            if !dependencies.is_empty() {
+                self.progress.note(&format!(
+                    "Not following the dependencies of {} (--max-depth reached)",
+                    crate_name
+                ));
+            }
+            for (dep_name, dep_version) in &dependencies {
+                self.record_skip(
+                    dep_name,
+                    dep_version.as_deref().unwrap_or("latest"),
+                    Some(crate_name),
+                    SkipReason::MaxDepth,
+                );
+            }
+            self.limited.extend(dependencies);
+            return Ok(Vec::new());
+        }
 
-        // Map dependencies to their real names before processing
         // (dependencies already contain the real crate names from Cargo.toml)
-        let deps_with_real_names: Vec<DependencySpec> = dependencies.into_iter().collect();
+        Ok(dependencies)
+    }
 
-        // Recursively process each dependency
-        for (real_dep_name, dep_version) in deps_with_real_names {
-            self.process_crate_recursive(&real_dep_name, dep_version.as_deref())?;
+    /// Whether the crates depending on `crate_name` asked for features its
+    /// dependencies were not followed with yet.
+    fn wants_more_features(&self, crate_name: &str) -> bool {
+        let name = crate_name.replace('_', "-");
+        let requested = self.requested_features.borrow();
+        let followed = self.followed_features.borrow();
+        match (requested.get(&name), followed.get(&name)) {
+            (Some((features, default)), Some((followed, followed_default))) => {
+                !features.is_subset(followed) || (*default && !*followed_default)
+            }
+            _ => false,
         }
+    }
 
-        Ok(())
+    /// Follow the dependencies of the already packaged `crate_name` again,
+    /// now that more of its features are requested. The crate is not
+    /// packaged again, since its spec covers every feature; `key` is the
+    /// requirement it was packaged for.
+    fn reopen_crate(
+        &mut self,
+        crate_name: &str,
+        version: Option<&str>,
+        key: &(String, String),
+        depth: usize,
+    ) -> Result<Vec<DependencySpec>> {
+        self.progress.note(&format!(
+            "Following {} {} again for newly requested features",
+            crate_name, key.1
+        ));
+        let dependencies = match self.package_single_crate(crate_name, version, depth, true) {
+            Ok(resolved) => {
+                self.record_skips(resolved.skips);
+                resolved.dependencies
+            }
+            Err(e) if cancel::is_cancelled(&e) => return Err(e),
+            Err(e) => {
+                takopack_warn!(
+                    "could not follow the features newly requested of {} {}: {:#}",
+                    crate_name,
+                    key.1,
+                    e
+                );
+                return Ok(Vec::new());
+            }
+        };
+        for known in [
+            self.dependencies.get_mut(key),
+            self.packaged_dependencies.get_mut(key),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            for dependency in &dependencies {
+                if !known.contains(dependency) {
+                    known.push(dependency.clone());
+                }
+            }
+        }
+        self.save_state()?;
+        Ok(dependencies)
     }
 
-    /// Package a single crate and return (spec_path, real_crate_name, dependencies)
+    /// Write the progress of the run to [`STATE_FILE`] in the output root.
+    fn save_state(&self) -> Result<()> {
+        if self.dry_run {
+            return Ok(());
+        }
+        let Some((crate_name, version)) = self.root.clone() else {
+            return Ok(());
+        };
+        let sorted = |set: &HashSet<(String, String)>| {
+            let mut keys: Vec<(String, String)> = set.iter().cloned().collect();
+            keys.sort();
+            keys
+        };
+        let processed = sorted(&self.processed)
+            .into_iter()
+            .map(|key| PackagedState {
+                dependencies: self.dependencies.get(&key).cloned().unwrap_or_default(),
+                crate_name: key.0,
+                version: key.1,
+            })
+            .collect();
+        let packaged = self
+            .packaged_dependencies
+            .iter()
+            .map(|((crate_name, version), dependencies)| PackagedState {
+                crate_name: crate_name.clone(),
+                version: version.clone(),
+                dependencies: dependencies.clone(),
+            })
+            .collect();
+        let state = RunState {
+            crate_name,
+            version,
+            processed,
+            packaged,
+            in_progress: sorted(&self.in_progress),
+            failed: self.failed.clone(),
+            crate_name_map: self.crate_name_map.clone(),
+        };
+
+        // Written aside and renamed so that a kill mid-write leaves the
+        // previous state intact
+        let path = self.base_dir.join(STATE_FILE);
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(&state)?)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        fs::rename(&tmp, &path).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Leave out a crate of the skip list, returning its dependencies to
+    /// follow unless whole subtrees are skipped.
+    fn skip_crate(
+        &mut self,
+        crate_name: &str,
+        version: Option<&str>,
+        depth: usize,
+    ) -> Result<Vec<DependencySpec>> {
+        let version_str = version.unwrap_or("latest");
+        if !self.record_skip(crate_name, version_str, None, SkipReason::SkipList) {
+            return Ok(Vec::new());
+        }
+        if self.skip_subtrees {
+            self.progress.event(Event::CrateSkipped {
+                crate_name: crate_name.to_string(),
+                version: version_str.to_string(),
+                reason: "skip list".to_string(),
+            });
+            return Ok(Vec::new());
+        }
+
+        self.progress.note(&format!(
+            "⏭️  Not packaging {} {} (skip list), following its dependencies",
+            crate_name, version_str
+        ));
+        match self.package_single_crate(crate_name, version, depth, true) {
+            Ok(packaged) => {
+                self.record_skips(packaged.skips);
+                Ok(packaged.dependencies)
+            }
+            Err(e) if cancel::is_cancelled(&e) => Err(e),
+            Err(e) => {
+                takopack_warn!(
+                    "could not resolve the dependencies of skipped crate {} {}: {:#}",
+                    crate_name,
+                    version_str,
+                    e
+                );
+                Ok(Vec::new())
+            }
+        }
+    }
+
+    /// Package a single crate. With `resolve_only`, or in a dry run,
+    /// nothing is written and only the dependencies are worked out.
     fn package_single_crate(
         &self,
         crate_name: &str,
         version: Option<&str>,
+        depth: usize,
+        resolve_only: bool,
     ) -> Result<PackagedCrate> {
-        let pkg_base = format!("rust-{}", crate_name.replace('_', "-"));
-
         // Use a temporary directory for extraction and processing
         let temp_dir = tempfile::Builder::new()
-            .prefix(&format!("takopack-{}-", pkg_base))
+            .prefix("tp-")
             .tempdir()
             .context("Failed to create temporary directory")?;
 
@@ -260,30 +1052,115 @@ impl RecursivePackager {
         let init_args = PackageInitArgs {
             crate_name: crate_name.to_string(),
             version: version.map(|s| s.to_string()),
+            offline: self.mirror.is_some(),
+            mirror: self.mirror.clone(),
+            registry: None,
+            index_url: None,
+            allow_yanked: false,
+            crate_file: None,
+            download: self.download.clone(),
         };
 
         let extract_args = PackageExtractArgs {
             directory: Some(temp_pkg_dir.clone()),
         };
 
+        // Execute packaging
+        self.cancel.check()?;
+        let mut process = PackageProcess::init(init_args, self.profile.as_deref(), self.test_mode)
+            .context(Phase::Download)
+            .with_context(|| format!("Failed to init package process for {}", crate_name))?;
+
         let execute_args = PackageExecuteArgs {
             changelog_ready: false,
+            changelog_from: ChangelogSource::None,
             copyright_guess_harder: false,
             no_overlay_write_back: true,
             with_spdx: false,
-            lockfile_deps: None, // recursive command uses Cargo.toml dependencies
+            with_tests: false,
+            template_dir: None,
+            profile: self.profile.clone(),
+            // Cargo.toml dependencies, unless the run is --locked
+            lockfile_deps: self.locked_dependencies(&process.crate_info),
+            test_mode: self.test_mode,
+        };
+        let real_name = process.crate_info.crate_name().to_string();
+        let real_version = process.crate_info.version().clone();
+        // Checked before anything is written, so that a yanked version
+        // leaves no package directory behind
+        let yanked = !resolve_only && self.is_yanked(&real_name, &real_version);
+        if yanked && !self.allow_yanked {
+            return Err(YankedVersion.into());
+        }
+        let output_names = if resolve_only {
+            process.config.output_names(&real_name, &real_version)
+        } else {
+            self.names
+                .borrow_mut()
+                .claim(&real_name, &real_version, &mut process.config)
+                .context(Phase::Spec)?
         };
 
-        // Execute packaging
-        let mut process = PackageProcess::init(init_args)
-            .with_context(|| format!("Failed to init package process for {}", crate_name))?;
-        let output_names = crate::util::rust_crate_output_names(
-            process.crate_info.crate_name(),
-            process.crate_info.version(),
-        );
+        let mut skips = Vec::new();
+        if self.dry_run || resolve_only {
+            let dependencies = self.extract_dependencies_from_crate_info(
+                &process.crate_info,
+                crate_name,
+                depth,
+                &mut skips,
+            )?;
+            return Ok(PackagedCrate {
+                spec_path: self
+                    .base_dir
+                    .join(&output_names.directory)
+                    .join(&output_names.spec_file),
+                crate_name: process.crate_info.crate_name().to_string(),
+                version: process.crate_info.version().to_string(),
+                dependencies,
+                skips,
+                proc_macro: process.crate_info.is_proc_macro(),
+                kept: false,
+                yanked,
+            });
+        }
 
-        // Create final output directory for this crate.
+        // Keep the output of an earlier run when it is still up to date
         let final_pkg_dir = self.base_dir.join(&output_names.directory);
+        let fingerprint = self.output_fingerprint(&process, execute_args.lockfile_deps.as_ref());
+        let full_version = process.crate_info.version().to_string();
+        let up_to_date = if self.skip_existing {
+            output_stamp::existing_version(&final_pkg_dir, &output_names.spec_file)
+                .is_some_and(|version| version == full_version)
+        } else {
+            output_stamp::is_current(&final_pkg_dir, &fingerprint)
+        };
+        let spec_path = final_pkg_dir.join(&output_names.spec_file);
+        if up_to_date && spec_path.is_file() {
+            self.progress.note(&format!(
+                "Keeping {} ({} {} is already generated there)",
+                final_pkg_dir.display(),
+                process.crate_info.crate_name(),
+                full_version
+            ));
+            let dependencies = self.extract_dependencies_from_crate_info(
+                &process.crate_info,
+                crate_name,
+                depth,
+                &mut skips,
+            )?;
+            return Ok(PackagedCrate {
+                spec_path,
+                crate_name: process.crate_info.crate_name().to_string(),
+                version: full_version,
+                dependencies,
+                skips,
+                proc_macro: process.crate_info.is_proc_macro(),
+                kept: true,
+                yanked,
+            });
+        }
+
+        // Create final output directory for this crate.
         if final_pkg_dir.exists() {
             if final_pkg_dir.is_dir() {
                 fs::remove_dir_all(&final_pkg_dir).with_context(|| {
@@ -298,28 +1175,42 @@ impl RecursivePackager {
         fs::create_dir_all(&final_pkg_dir)
             .with_context(|| format!("Failed to create package directory: {:?}", final_pkg_dir))?;
 
+        self.cancel.check()?;
         process
             .extract(extract_args)
+            .context(Phase::Extract)
             .with_context(|| format!("Failed to extract package for {}", crate_name))?;
+        self.cancel.check()?;
         process
             .apply_overrides()
+            .context(Phase::Spec)
             .with_context(|| format!("Failed to apply overrides for {}", crate_name))?;
+        self.cancel.check()?;
         process
             .prepare_orig_tarball()
+            .context(Phase::Spec)
             .with_context(|| format!("Failed to prepare tarball for {}", crate_name))?;
+        self.cancel.check()?;
         process
             .prepare_takopack_folder(execute_args)
+            .context(Phase::Spec)
             .with_context(|| format!("Failed to prepare takopack folder for {}", crate_name))?;
 
         // Extract the real crate name from the package metadata
         let real_crate_name = process.crate_info.crate_name().to_string();
+        let real_version = process.crate_info.version().to_string();
 
         // Extract runtime dependencies from the crate's Cargo.toml metadata
         // This is more reliable than parsing the generated spec file
-        let dependencies =
-            self.extract_dependencies_from_crate_info(&process.crate_info, crate_name)?;
+        let dependencies = self.extract_dependencies_from_crate_info(
+            &process.crate_info,
+            crate_name,
+            depth,
+            &mut skips,
+        )?;
 
         // Find and copy the generated spec file to final location
+        self.cancel.check()?;
         let temp_spec_path = temp_pkg_dir.join("takopack").join(&output_names.spec_file);
         let final_spec_path = final_pkg_dir.join(&output_names.spec_file);
 
@@ -334,76 +1225,327 @@ impl RecursivePackager {
         } else {
             anyhow::bail!("Spec file not found: {:?}", temp_spec_path);
         }
+        output_stamp::write_stamp(&final_pkg_dir, &fingerprint)?;
+        process.config.permissions.apply_to_dir(&final_pkg_dir)?;
 
         // temp_dir will be automatically cleaned up when dropped
 
-        Ok((final_spec_path, real_crate_name, dependencies))
+        Ok(PackagedCrate {
+            spec_path: final_spec_path,
+            crate_name: real_crate_name,
+            version: real_version,
+            dependencies,
+            skips,
+            proc_macro: process.crate_info.is_proc_macro(),
+            kept: false,
+            yanked,
+        })
+    }
+
+    /// Fingerprint of everything the package directory of `process` is
+    /// generated from, see [`output_stamp`].
+    fn output_fingerprint(
+        &self,
+        process: &PackageProcess,
+        lockfile_deps: Option<&HashMap<String, semver::Version>>,
+    ) -> String {
+        let config = process
+            .config_path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .unwrap_or_default();
+        let locked: BTreeMap<&String, String> = lockfile_deps
+            .into_iter()
+            .flatten()
+            .map(|(name, version)| (name, version.to_string()))
+            .collect();
+        output_stamp::fingerprint(&[
+            ("takopack", env!("CARGO_PKG_VERSION").to_string()),
+            ("crate", process.crate_info.crate_name().to_string()),
+            ("version", process.crate_info.version().to_string()),
+            ("sha256", process.sha256.clone().unwrap_or_default()),
+            ("profile", self.profile.clone().unwrap_or_default()),
+            ("config", config),
+            ("locked", format!("{:?}", locked)),
+        ])
+    }
+
+    /// Record `packaged`, packaged for the requirement `key` of
+    /// `requested_name`, returning the dependencies to follow from it.
+    fn record_success(
+        &mut self,
+        requested_name: &str,
+        key: &(String, String),
+        packaged: PackagedCrate,
+    ) -> Vec<DependencySpec> {
+        let PackagedCrate {
+            spec_path,
+            crate_name,
+            version,
+            dependencies,
+            skips,
+            proc_macro,
+            kept,
+            yanked,
+        } = packaged;
+        self.record_skips(skips);
+        self.progress.finish_crate(Event::CratePackaged {
+            crate_name: crate_name.clone(),
+            version: version.clone(),
+            package_dir: spec_path.parent().map(Path::to_path_buf),
+        });
+
+        // Store the mapping: normalized name (with dashes) -> real crate name
+        self.crate_name_map
+            .insert(requested_name.replace('_', "-"), crate_name.clone());
+
+        self.in_progress.remove(key);
+        self.processed.insert(key.clone());
+        if yanked {
+            takopack_warn!("{} {} has been yanked from crates.io", crate_name, version);
+            self.yanked.push((crate_name.clone(), version.clone()));
+        }
+        self.record_owners(&crate_name, &version);
+        self.record_audit_status(&crate_name, &version);
+        if proc_macro {
+            self.proc_macros.push((crate_name.clone(), version.clone()));
+        }
+        if kept {
+            self.kept.push((crate_name.clone(), version.clone()));
+        }
+        self.packaged_dependencies
+            .insert((crate_name.clone(), version.clone()), dependencies.clone());
+        self.packaged.push((crate_name, version));
+        dependencies
+    }
+
+    /// Whether crates.io has yanked `crate_name` `version`. Crates
+    /// resolved from an offline mirror are not checked.
+    fn is_yanked(&self, crate_name: &str, version: &semver::Version) -> bool {
+        if self.mirror.is_some() {
+            return false;
+        }
+        let yanked =
+            SparseIndex::crates_io().and_then(|index| index.is_yanked(crate_name, version));
+        match yanked {
+            Ok(yanked) => yanked == Some(true),
+            Err(e) => {
+                takopack_warn!(
+                    "could not check whether {} {} is yanked: {:#}",
+                    crate_name,
+                    version,
+                    e
+                );
+                false
+            }
+        }
+    }
+
+    /// Leave out a yanked version that `--allow-yanked` did not let in;
+    /// [`Self::check_yanked`] reports it at the end of the run.
+    fn record_yanked_skip(&mut self, crate_name: &str, version: &str) {
+        self.progress.event(Event::CrateSkipped {
+            crate_name: crate_name.to_string(),
+            version: version.to_string(),
+            reason: SkipReason::Yanked.to_string(),
+        });
+        self.yanked
+            .push((crate_name.to_string(), version.to_string()));
+        self.record_skip(crate_name, version, None, SkipReason::Yanked);
+    }
+
+    /// Fetch the crates.io owners of `crate_name` and compare them with the
+    /// ones recorded for the previously packaged version.
+    fn record_owners(&mut self, crate_name: &str, version: &str) {
+        if self.mirror.is_some() {
+            return;
+        }
+        let Some(store) = self.owners.as_mut() else {
+            return;
+        };
+        match crate::owners::fetch_owners(crate_name) {
+            Ok(owners) => {
+                if let Some(change) = store.record(crate_name, version, owners) {
+                    takopack_warn!("ownership changed: {}", change);
+                    self.ownership_changes.push(change);
+                }
+            }
+            Err(e) => takopack_warn!("could not check owners of {}: {:#}", crate_name, e),
+        }
+    }
+
+    fn record_audit_status(&mut self, crate_name: &str, version: &str) {
+        let Some(store) = &self.vet_store else {
+            return;
+        };
+        let status = match semver::Version::parse(version) {
+            Ok(parsed) => store.status(crate_name, &parsed),
+            Err(_) => AuditStatus::Unaudited,
+        };
+        self.audit_status
+            .push((crate_name.to_string(), version.to_string(), status));
+    }
+
+    /// With `require_audit`, fail listing every crate that is neither
+    /// audited nor exempted.
+    pub fn check_audits(&self) -> Result<()> {
+        if !self.require_audit {
+            return Ok(());
+        }
+        let unaudited: Vec<String> = self
+            .audit_status
+            .iter()
+            .filter(|(_, _, status)| *status == AuditStatus::Unaudited)
+            .map(|(name, version, _)| format!("{} {}", name, version))
+            .collect();
+        if unaudited.is_empty() {
+            return Ok(());
+        }
+        anyhow::bail!(
+            "{} crate(s) have no cargo-vet audit or exemption:\n  - {}",
+            unaudited.len(),
+            unaudited.join("\n  - ")
+        )
+    }
+
+    /// Write the failures and skips of this run to the output root, see
+    /// [`failure_report`].
+    pub fn write_failure_report(&self) -> Result<()> {
+        failure_report::write_failure_report(&self.base_dir, &self.failures)?;
+        failure_report::write_skip_report(&self.base_dir, &self.skips)
+    }
+
+    /// Write the order in which to build the crates packaged so far to
+    /// [`BUILD_ORDER_TXT`] in the output root, see [`build_order`].
+    pub fn write_build_order(&self) -> Result<()> {
+        let packaged: Vec<_> = self
+            .packaged_dependencies
+            .iter()
+            .filter_map(|((crate_name, version), dependencies)| {
+                let version = semver::Version::parse(version).ok()?;
+                Some(((crate_name.clone(), version), dependencies.clone()))
+            })
+            .collect();
+        if packaged.is_empty() {
+            return Ok(());
+        }
+        let order = build_order::build_order(build_order::graph_from_packaged(&packaged));
+        let path = self.base_dir.join(BUILD_ORDER_TXT);
+        fs::write(&path, order.batch_list())
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        self.progress
+            .note(&format!("Build order written to {}", path.display()));
+        if order.has_cycles() {
+            self.progress.note(&format!(
+                "⚠️  {} dependency cycle(s) among the packaged crates; some are built twice",
+                order.cycles.len()
+            ));
+        }
+        Ok(())
     }
 
-    /// Extract runtime dependencies from CrateInfo (from Cargo.toml metadata)
+    /// Record that `crate_name` `version` was left out, unless the same
+    /// skip is already recorded. Returns whether it was new.
+    fn record_skip(
+        &mut self,
+        crate_name: &str,
+        version: &str,
+        parent: Option<&str>,
+        reason: SkipReason,
+    ) -> bool {
+        self.push_skip(Skip {
+            crate_name: crate_name.to_string(),
+            version: version.to_string(),
+            parent: parent.map(str::to_string),
+            reason,
+        })
+    }
+
+    fn record_skips(&mut self, skips: Vec<Skip>) {
+        for skip in skips {
+            self.push_skip(skip);
+        }
+    }
+
+    fn push_skip(&mut self, skip: Skip) -> bool {
+        if self.skips.contains(&skip) {
+            return false;
+        }
+        self.skips.push(skip);
+        true
+    }
+
+    /// Persist the owner lists seen during this run.
+    pub fn save_owners(&self) -> Result<()> {
+        match &self.owners {
+            Some(store) => store.save(),
+            None => Ok(()),
+        }
+    }
+
+    /// Fail if the dependency graph has yanked crate versions, listing all
+    /// of them, unless yanked versions were allowed. Without
+    /// `--allow-yanked` they were left out of the run.
+    pub fn check_yanked(&self) -> Result<()> {
+        if self.yanked.is_empty() {
+            return Ok(());
+        }
+        let report = yanked_report(&self.yanked);
+        if self.allow_yanked {
+            takopack_warn!("{}", report);
+            return Ok(());
+        }
+        anyhow::bail!(
+            "{}\nthey were not packaged; re-run with --allow-yanked to package them anyway",
+            report
+        )
+    }
+
+    /// Extract the dependencies to descend into from CrateInfo (from
+    /// Cargo.toml metadata), as decided by the dependency filters.
     /// This is more reliable than parsing the generated spec file
+    ///
+    /// Optional dependencies only count as optional when none of the
+    /// features requested of this crate enables them. The features the
+    /// followed dependencies are asked for are remembered for when they are
+    /// processed in turn, or followed again if they already were.
     fn extract_dependencies_from_crate_info(
         &self,
-        crate_info: &crate::crates::CrateInfo,
+        crate_info: &CrateInfo,
         current_crate: &str,
+        depth: usize,
+        skips: &mut Vec<Skip>,
     ) -> Result<Vec<DependencySpec>> {
         use cargo::core::dependency::DepKind;
 
         let mut dependencies = Vec::new();
         let mut seen = HashSet::new();
         let current_crate_normalized = current_crate.replace('-', "_");
-
-        // List of crates to skip (internal Rust workspace crates, etc.)
-        let skip_crates = [
-            "rustc_std_workspace_core",
-            "rustc_std_workspace_alloc",
-            "rustc_std_workspace_std",
-            "compiler_builtins",
-        ];
-
-        // Common proc-macro crate suffixes to skip
-        let proc_macro_suffixes = ["-derive", "-macro", "-macros"];
+        let locked = self.locked_dependencies(crate_info);
+        let (requested, default) = self
+            .requested_features
+            .borrow()
+            .get(&crate_info.crate_name().replace('_', "-"))
+            .cloned()
+            .unwrap_or_else(|| (BTreeSet::new(), true));
+        let activation = activate_features(
+            crate_info.manifest().summary(),
+            requested.iter().map(String::as_str),
+            default,
+        );
+        self.followed_features.borrow_mut().insert(
+            crate_info.crate_name().replace('_', "-"),
+            (requested, default),
+        );
 
         // Iterate through all dependencies from Cargo.toml
         for dep in crate_info.dependencies() {
-            // Skip dev dependencies and build dependencies
-            // We only want runtime dependencies
-            if dep.kind() == DepKind::Development {
-                println!("⏭️  Skipping dev dependency: {}", dep.package_name());
-                continue;
-            }
-
             // Get the real crate name from the dependency
             // This is the actual package name on crates.io
             let dep_crate_name = dep.package_name().to_string();
 
-            // For comparison with current crate, normalize both
-            let dep_crate_name_normalized = dep_crate_name.replace('-', "_");
-            let current_crate_normalized_cmp = current_crate_normalized.replace('-', "_");
-
             // Skip if it's the current crate itself
-            if dep_crate_name_normalized == current_crate_normalized_cmp {
-                continue;
-            }
-
-            // Skip internal Rust workspace crates
-            if skip_crates.contains(&dep_crate_name_normalized.as_str()) {
-                println!("⏭️  Skipping internal crate: {}", dep_crate_name);
-                continue;
-            }
-
-            // Skip proc-macro crates (they are build-time dependencies)
-            if proc_macro_suffixes
-                .iter()
-                .any(|suffix| dep_crate_name.ends_with(suffix))
-            {
-                println!("⏭️  Skipping proc-macro crate: {}", dep_crate_name);
-                continue;
-            }
-
-            // Skip optional dependencies
-            if dep.is_optional() {
-                println!("⏭️  Skipping optional dependency: {}", dep_crate_name);
+            if dep_crate_name.replace('-', "_") == current_crate_normalized {
                 continue;
             }
 
@@ -417,6 +1559,66 @@ impl RecursivePackager {
                 // For now, we'll just use the version requirement as-is
                 Some(version_req.to_string())
             };
+            // Locked runs follow the exact version in Cargo.lock, and leave
+            // out what it does not pin (such as dependencies of disabled
+            // features, or dev-dependencies of dependencies)
+            let version_str = match &locked {
+                None => version_str,
+                Some(locked) => match locked.get(&dep_crate_name) {
+                    Some(version) => Some(format!("={}", version)),
+                    None => {
+                        self.progress.note(&format!(
+                            "⏭️  Skipping {} (not in Cargo.lock)",
+                            dep_crate_name
+                        ));
+                        skips.push(Skip {
+                            crate_name: dep_crate_name,
+                            version: version_req.to_string(),
+                            parent: Some(crate_info.crate_name().to_string()),
+                            reason: SkipReason::NotInLockfile,
+                        });
+                        continue;
+                    }
+                },
+            };
+
+            let edge = Edge {
+                parent: crate_info.crate_name(),
+                crate_name: &dep_crate_name,
+                req: version_str.as_deref(),
+                kind: match dep.kind() {
+                    DepKind::Normal => EdgeKind::Normal,
+                    DepKind::Build => EdgeKind::Build,
+                    DepKind::Development => EdgeKind::Dev,
+                },
+                optional: dep.is_optional()
+                    && !activation
+                        .enabled_deps
+                        .contains(dep.name_in_toml().as_str()),
+                depth: depth + 1,
+            };
+            if let Decision::Skip(reason) = dep_filter::decide(&self.filters, &edge) {
+                self.progress
+                    .note(&format!("⏭️  Skipping {}: {}", reason, dep_crate_name));
+                skips.push(Skip {
+                    version: version_str.unwrap_or_else(|| "latest".to_string()),
+                    crate_name: dep_crate_name,
+                    parent: Some(crate_info.crate_name().to_string()),
+                    reason,
+                });
+                continue;
+            }
+
+            // Remember what this crate asks of the dependency
+            let mut requests = self.requested_features.borrow_mut();
+            let (features, wants_default) = requests
+                .entry(dep_crate_name.replace('_', "-"))
+                .or_default();
+            features.extend(dep.features().iter().map(|f| f.to_string()));
+            if let Some(dep_features) = activation.dep_features.get(dep.name_in_toml().as_str()) {
+                features.extend(dep_features.iter().cloned());
+            }
+            *wants_default |= dep.uses_default_features();
 
             // Deduplicate dependencies
             if !seen.contains(&dep_crate_name) {
@@ -428,16 +1630,55 @@ impl RecursivePackager {
         Ok(dependencies)
     }
 
-    /// Print summary of the packaging process
+    /// Print summary of the packaging process; other output formats than
+    /// `human` get an [`Event::Summary`]
     pub fn print_summary(&self) {
+        if !events::is_human() {
+            events::emit(Event::Summary {
+                attempted: self.total_attempted,
+                packaged: self.processed.len(),
+                failed: self.failed.len(),
+                skipped: self.skips.len(),
+                output_dir: self.base_dir.clone(),
+            });
+            return;
+        }
+        if self.dry_run {
+            self.print_plan();
+            return;
+        }
         println!("\n{}", "=".repeat(62));
         println!("📊 Packaging Summary");
         println!("{}", "=".repeat(62));
         println!("Total attempted:    {}", self.total_attempted);
         println!("Successfully built: {}", self.processed.len());
         println!("Failed:             {}", self.failed.len());
+        if !self.kept.is_empty() {
+            println!("Kept from earlier:  {}", self.kept.len());
+        }
+        if !self.skips.is_empty() {
+            println!("Skipped:            {}", self.skips.len());
+        }
         println!("{}", "=".repeat(62));
 
+        if !self.audit_status.is_empty() {
+            println!("\n🔍 Audit Status (cargo-vet):");
+            println!("{}", "-".repeat(62));
+            for (name, version, status) in &self.audit_status {
+                println!("   {:<10} {} {}", status.to_string(), name, version);
+            }
+            println!();
+        }
+
+        if !self.ownership_changes.is_empty() {
+            println!("\n⚠️  Ownership Changes (review before shipping):");
+            println!("{}", "-".repeat(62));
+            for change in &self.ownership_changes {
+                println!("   {}", change);
+            }
+            println!();
+        }
+
         if !self.failed.is_empty() {
             println!("\n❌ Failed Packages:");
             println!("{}", "-".repeat(62));
@@ -446,9 +1687,372 @@ impl RecursivePackager {
                 println!("   Error: {}", failed.error);
                 println!();
             }
+            println!(
+                "Failure report: {} (retry with: takopack cargo batch {})",
+                self.base_dir.join(failure_report::FAILURES_JSON).display(),
+                self.base_dir.join(failure_report::FAILURES_TXT).display()
+            );
         }
 
+        self.print_skips();
+        self.names.borrow().print_collisions();
+        if !self.skips.is_empty() {
+            println!(
+                "Skip report: {}\n",
+                self.base_dir.join(failure_report::SKIPPED_JSON).display()
+            );
+        }
+        self.print_proc_macros();
         println!("📁 Output directory: {}", self.base_dir.display());
         println!("{}\n", "=".repeat(62));
     }
+
+    /// Summary of a dry run: the crates that would be packaged.
+    fn print_plan(&self) {
+        println!("\n{}", "=".repeat(62));
+        println!(
+            "📋 Dry run: {} crate(s) would be packaged",
+            self.packaged.len()
+        );
+        println!("{}", "=".repeat(62));
+        for (name, version) in &self.packaged {
+            println!("   {} {}", name, version);
+        }
+        self.print_skips();
+        self.names.borrow().print_collisions();
+        if !self.failed.is_empty() {
+            println!("\n❌ Could not be resolved:");
+            println!("{}", "-".repeat(62));
+            for failed in &self.failed {
+                println!(
+                    "   {} {}: {}",
+                    failed.crate_name, failed.version, failed.error
+                );
+            }
+        }
+        self.print_proc_macros();
+        println!("{}\n", "=".repeat(62));
+    }
+
+    /// Proc-macro crates run on the build host, so dependents could move
+    /// them to `BuildRequires:` by hand; the generated specs do not.
+    fn print_proc_macros(&self) {
+        if self.proc_macros.is_empty() {
+            return;
+        }
+        println!(
+            "\n🔧 Proc-macro crates, build-time only ({}):",
+            self.proc_macros.len()
+        );
+        println!("{}", "-".repeat(62));
+        for (name, version) in &self.proc_macros {
+            println!("   {} {}", name, version);
+        }
+        println!();
+    }
+
+    /// Everything left out on purpose, as opposed to the failures.
+    fn print_skips(&self) {
+        if self.skips.is_empty() {
+            return;
+        }
+        let mut skips: Vec<String> = self.skips.iter().map(Skip::to_string).collect();
+        skips.sort();
+        println!("\n⏭️  Skipped ({}):", skips.len());
+        println!("{}", "-".repeat(62));
+        for skip in skips {
+            println!("   {}", skip);
+        }
+        println!();
+    }
+}
+
+/// Whether crates packaged for the requirements `a` and `b` would share an
+/// output directory. Which branch a requirement spanning several (such as
+/// `latest`) resolves to is not known before it is resolved.
+fn same_compat_branch(a: &str, b: &str) -> bool {
+    match (
+        crate::version::requirement_compat_version(a),
+        crate::version::requirement_compat_version(b),
+    ) {
+        (Some(a), Some(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Whether the packaged `version` serves the requirement `req`: it is in
+/// the compat branch `req` names, or matches `req` when that spans several.
+fn serves_requirement(req: &str, version: &str) -> bool {
+    let Ok(version) = semver::Version::parse(version) else {
+        return false;
+    };
+    match crate::version::requirement_compat_version(req) {
+        Some(compat) => crate::version::compat_version(&version) == compat,
+        None if req == "latest" => true,
+        None => semver::VersionReq::parse(req).is_ok_and(|req| req.matches(&version)),
+    }
+}
+
+/// Output directory named after the current time.
+fn timestamped_dir() -> PathBuf {
+    PathBuf::from(Local::now().format("%Y%m%d_%H%M%S").to_string())
+}
+
+/// Human-readable list of yanked `(crate_name, version)` pairs.
+pub fn yanked_report(yanked: &[(String, String)]) -> String {
+    let mut report = format!(
+        "{} yanked crate version(s) in the dependency graph:",
+        yanked.len()
+    );
+    for (name, version) in yanked {
+        report.push_str(&format!("\n  - {} {}", name, version));
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        same_compat_branch, serves_requirement, yanked_report, FailedPackage, RecursivePackager,
+        STATE_FILE,
+    };
+    use crate::audit::AuditStatus;
+    use crate::build_order::BUILD_ORDER_TXT;
+    use crate::dep_filter::SkipReason;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn yanked_versions_fail_unless_allowed() {
+        let out = tempfile::tempdir().unwrap();
+        let mut packager = RecursivePackager::new(Some(out.path().to_path_buf())).unwrap();
+        assert!(packager.check_yanked().is_ok());
+
+        packager.record_yanked_skip("foo", "1.0.1");
+        packager.record_yanked_skip("bar", "0.3.0");
+        assert_eq!(packager.skips.len(), 2);
+        assert_eq!(packager.skips[0].reason, SkipReason::Yanked);
+        let err = packager.check_yanked().unwrap_err().to_string();
+        assert!(err.contains("foo 1.0.1"));
+        assert!(err.contains("bar 0.3.0"));
+        assert!(err.contains("not packaged"));
+        assert!(err.contains("--allow-yanked"));
+
+        // Refused versions are not tried again within the run, and are not
+        // processed: not counted as packaged, nor saved as done for a resume
+        packager.root = Some(("foo".to_string(), Some("1.0.1".to_string())));
+        packager
+            .process_crate_recursive("foo", Some("1.0.1"))
+            .unwrap();
+        assert_eq!(packager.total_attempted, 0);
+        assert!(packager.processed.is_empty());
+
+        let packager = packager.with_allow_yanked(true);
+        assert!(packager.check_yanked().is_ok());
+    }
+
+    #[test]
+    fn unaudited_crates_fail_only_when_required() {
+        let out = tempfile::tempdir().unwrap();
+        let mut packager = RecursivePackager::new(Some(out.path().to_path_buf())).unwrap();
+        packager.audit_status = vec![
+            ("foo".to_string(), "1.0.0".to_string(), AuditStatus::Audited),
+            (
+                "bar".to_string(),
+                "0.1.0".to_string(),
+                AuditStatus::Unaudited,
+            ),
+        ];
+        assert!(packager.check_audits().is_ok());
+
+        packager.require_audit = true;
+        let err = packager.check_audits().unwrap_err().to_string();
+        assert!(err.contains("bar 0.1.0"));
+        assert!(!err.contains("foo"));
+    }
+
+    #[test]
+    fn yanked_report_lists_every_crate() {
+        let report = yanked_report(&[("foo".to_string(), "1.0.1".to_string())]);
+        assert_eq!(
+            report,
+            "1 yanked crate version(s) in the dependency graph:\n  - foo 1.0.1"
+        );
+    }
+
+    #[test]
+    fn incompatible_versions_are_packaged_side_by_side() {
+        assert!(!same_compat_branch("^1.0.109", "^2.0.40"));
+        assert!(!same_compat_branch("=0.3.5", "^0.4"));
+        assert!(same_compat_branch("^2.0.40", "=2.0.87"));
+        assert!(!same_compat_branch(">=1, <3", "^2"));
+
+        // syn packaged as "latest" resolved to 2.x and does not serve ^1
+        assert!(serves_requirement("latest", "2.0.87"));
+        assert!(!serves_requirement("^1", "2.0.87"));
+        assert!(serves_requirement("^1.0.100", "1.0.109"));
+        assert!(serves_requirement(">=1, <3", "2.0.87"));
+        assert!(!serves_requirement(">=3", "2.0.87"));
+    }
+
+    #[test]
+    fn resumed_runs_skip_packaged_crates_and_retry_the_rest() {
+        let out = tempfile::tempdir().unwrap();
+        let mut packager = RecursivePackager::new(Some(out.path().to_path_buf())).unwrap();
+        packager.root = Some(("app".to_string(), None));
+        let packaged = ("app".to_string(), "latest".to_string());
+        packager.processed.insert(packaged.clone());
+        packager
+            .dependencies
+            .insert(packaged.clone(), vec![("serde".to_string(), None)]);
+        packager.packaged_dependencies.insert(
+            ("app".to_string(), "1.2.0".to_string()),
+            vec![("serde".to_string(), None)],
+        );
+        packager
+            .in_progress
+            .insert(("serde".to_string(), "latest".to_string()));
+        packager.failed.push(FailedPackage {
+            crate_name: "libc".to_string(),
+            version: "^0.2".to_string(),
+            error: "download failed".to_string(),
+        });
+        packager.save_state().unwrap();
+        assert!(out.path().join(STATE_FILE).is_file());
+
+        let resumed = RecursivePackager::resume(out.path()).unwrap();
+        assert_eq!(resumed.processed, [packaged.clone()].into());
+        assert!(resumed.resumed.contains(&packaged));
+        assert_eq!(
+            resumed.dependencies[&packaged],
+            vec![("serde".to_string(), None)]
+        );
+        assert!(resumed.in_progress.is_empty());
+        assert!(resumed.failed.is_empty());
+        resumed.write_build_order().unwrap();
+        let order = fs::read_to_string(out.path().join(BUILD_ORDER_TXT)).unwrap();
+        assert!(order.ends_with("\napp 1.2.0\n"), "{}", order);
+
+        let mut resumed = resumed;
+        let err = resumed
+            .process_crate_recursive("other", None)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("app latest"));
+
+        let empty = tempfile::tempdir().unwrap();
+        assert!(RecursivePackager::resume(empty.path()).is_err());
+    }
+
+    #[test]
+    fn limits_leave_crates_out_and_dry_runs_write_nothing() {
+        let out = tempfile::tempdir().unwrap();
+        let planned = out.path().join("plan");
+        let mut packager =
+            RecursivePackager::new_dry_run(Some(planned.clone())).with_limits(Some(1), Some(0));
+        packager
+            .process_crate_recursive("serde", Some("^1"))
+            .unwrap();
+        assert_eq!(
+            packager.limited,
+            vec![("serde".to_string(), Some("^1".to_string()))]
+        );
+        assert_eq!(packager.total_attempted, 0);
+        assert_eq!(packager.skips.len(), 1);
+        assert_eq!(packager.skips[0].reason, SkipReason::MaxCrates);
+        assert!(!planned.exists());
+    }
+
+    #[test]
+    fn deep_dependency_graphs_do_not_exhaust_the_stack() {
+        // Crate i depends on crates i + 1 and i + 2, so the graph is 1000
+        // crates deep and every crate is reached twice. All of them count as
+        // packaged before a resume, which walks the graph without packaging.
+        let out = tempfile::tempdir().unwrap();
+        let base = out.path().to_path_buf();
+        // On a thread with a small stack, where recursion would overflow
+        let (unvisited, processed, attempted) = std::thread::Builder::new()
+            .stack_size(256 * 1024)
+            .spawn(move || {
+                let mut packager = RecursivePackager::new_dry_run(Some(base));
+                let key = |i: usize| (format!("crate{}", i), "latest".to_string());
+                for i in 0..1000 {
+                    let dependencies = (i + 1..1000.min(i + 3))
+                        .map(|dep| (format!("crate{}", dep), None))
+                        .collect();
+                    packager.dependencies.insert(key(i), dependencies);
+                    packager.processed.insert(key(i));
+                    packager.resumed.insert(key(i));
+                }
+                packager.process_crate_recursive("crate0", None).unwrap();
+                (
+                    packager.resumed.len(),
+                    packager.processed.len(),
+                    packager.total_attempted,
+                )
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!((unvisited, processed, attempted), (0, 1000, 0));
+    }
+
+    /// An unpacked `name-1.0.0` crate in `mirror`.
+    fn mirror_crate(mirror: &Path, name: &str, rest: &str) {
+        let dir = mirror.join(format!("{}-1.0.0", name));
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("src/lib.rs"), "").unwrap();
+        fs::write(
+            dir.join("Cargo.toml"),
+            format!(
+                "[package]\nname = \"{}\"\nversion = \"1.0.0\"\nedition = \"2021\"\n{}",
+                name, rest
+            ),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn features_requested_after_the_first_visit_are_followed() {
+        // app depends on b and then c, and c asks b for the feature that
+        // enables its optional dependency d. b is visited first, before
+        // anything asked for the feature.
+        let mirror = tempfile::tempdir().unwrap();
+        mirror_crate(
+            mirror.path(),
+            "app",
+            "[dependencies]\nb = \"1\"\nc = \"1\"\n",
+        );
+        mirror_crate(
+            mirror.path(),
+            "c",
+            "[dependencies]\nb = { version = \"1\", features = [\"x\"] }\n",
+        );
+        mirror_crate(
+            mirror.path(),
+            "b",
+            "[dependencies]\nd = { version = \"1\", optional = true }\n\n[features]\nx = [\"dep:d\"]\n",
+        );
+        mirror_crate(mirror.path(), "d", "");
+
+        let out = tempfile::tempdir().unwrap();
+        let mut packager = RecursivePackager::new_dry_run(Some(out.path().join("plan")))
+            .with_mirror(Some(mirror.path().to_path_buf()));
+        packager
+            .process_crate_recursive("app", Some("1.0.0"))
+            .unwrap();
+
+        let key = |name: &str| (name.to_string(), "^1".to_string());
+        assert!(packager.processed.contains(&key("b")));
+        assert!(
+            packager.processed.contains(&key("d")),
+            "{:?}",
+            packager.processed
+        );
+        assert_eq!(
+            packager.dependencies[&key("b")],
+            vec![("d".to_string(), Some("^1".to_string()))]
+        );
+        assert_eq!(packager.total_attempted, 4);
+    }
 }
@@ -28,8 +28,9 @@ use serde_derive::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use walkdir::WalkDir;
 
-use crate::config::{default_registry_dir, load_takopack_toml};
+use crate::config::load_takopack_toml;
 use crate::errors::Result;
+use crate::tarball::{self, EntryCheck, ExtractionLimits};
 
 const TAKOPACK_METADATA_DIR: &str = ".takopack";
 const REGISTRY_MARKER: &str = "managed-by-takopack";
@@ -235,7 +236,7 @@ fn resolve_paths() -> Result<(PathBuf, PathBuf)> {
                 .join(registry_local)
         }
     } else {
-        default_registry_dir()?
+        crate::paths::registry_dir()?
     };
 
     Ok((ruyispec_dir, registry_dir))
@@ -319,16 +320,16 @@ fn registry_is_empty_or_metadata_only(registry_dir: &Path) -> Result<bool> {
 
 /// Information extracted from a single `.spec` file.
 #[derive(Debug, Clone)]
-struct ProviderEntry {
+pub(crate) struct ProviderEntry {
     /// Relative path to the spec file from the ruyispec root, e.g.
     /// `SPECS/rust-tokio-1/rust-tokio-1.spec`.
-    spec_key: String,
+    pub(crate) spec_key: String,
     /// Crate name as declared via `%global crate_name`.
-    crate_name: String,
+    pub(crate) crate_name: String,
     /// Crate version as declared via `%global full_version`.
-    version: String,
+    pub(crate) version: String,
     /// RPM name derived from the directory name (e.g. `rust-tokio-1`).
-    rpm_name: String,
+    pub(crate) rpm_name: String,
     /// Registry sub-directory, `{crate_name}-{version}`.
     registry_path: String,
     /// SHA-256 hex digest of the spec file contents.
@@ -339,9 +340,9 @@ struct ProviderEntry {
 
 /// Result of scanning the ruyispec tree.
 #[derive(Debug, Default)]
-struct ScanResult {
-    providers: BTreeMap<String, ProviderEntry>,
-    warnings: Vec<ScanWarning>,
+pub(crate) struct ScanResult {
+    pub(crate) providers: BTreeMap<String, ProviderEntry>,
+    pub(crate) warnings: Vec<ScanWarning>,
 }
 
 impl ScanResult {
@@ -360,9 +361,9 @@ enum ScanWarningKind {
 }
 
 #[derive(Debug, Clone)]
-struct ScanWarning {
+pub(crate) struct ScanWarning {
     kind: ScanWarningKind,
-    message: String,
+    pub(crate) message: String,
 }
 
 impl ScanWarning {
@@ -372,7 +373,7 @@ impl ScanWarning {
 }
 
 /// Scan `{ruyispec}/SPECS/rust-*/*.spec` and return a map keyed by `spec_key`.
-fn scan_providers(ruyispec_dir: &Path) -> Result<ScanResult> {
+pub(crate) fn scan_providers(ruyispec_dir: &Path) -> Result<ScanResult> {
     let pattern = format!("{}/SPECS/rust-*/*.spec", ruyispec_dir.display());
     let re_crate = Regex::new(r"^%global\s+crate_name\s+(\S+)").expect("regex");
     let re_version = Regex::new(r"^%global\s+full_version\s+(\S+)").expect("regex");
@@ -815,7 +816,7 @@ fn sync_entries_parallel(
 // Crate download / extract / patch
 // ---------------------------------------------------------------------------
 
-/// Download a crate tarball from crates.io, extract it, optionally overlay
+/// Download a crate tarball from crates.io or its mirror, extract it, optionally overlay
 /// the provider `Cargo.toml`, and regenerate `.cargo-checksum.json`.
 fn sync_crate(entry: &ProviderEntry, ruyispec_dir: &Path, registry_dir: &Path) -> Result<()> {
     let dest = registry_dir.join(&entry.registry_path);
@@ -828,11 +829,8 @@ fn sync_crate(entry: &ProviderEntry, ruyispec_dir: &Path, registry_dir: &Path) -
         .with_context(|| format!("failed to create temp dir in {}", registry_dir.display()))?;
     let work_dir = temp_dir.path();
 
-    // Download
-    let url = format!(
-        "https://static.crates.io/crates/{}/{}/download",
-        entry.crate_name, entry.version
-    );
+    // Download, from the configured mirror if there is one
+    let url = crate::crates::crates_io_download_url(&entry.crate_name, &entry.version)?;
     log::info!("downloading {}", url);
     let body = download_crate_tarball(&url)?;
 
@@ -883,7 +881,7 @@ fn sync_crate(entry: &ProviderEntry, ruyispec_dir: &Path, registry_dir: &Path) -
 fn download_crate_tarball(url: &str) -> Result<Vec<u8>> {
     const ATTEMPTS: usize = 3;
 
-    let agent = ureq::AgentBuilder::new()
+    let agent = crate::http::agent_builder()
         .timeout_connect(Duration::from_secs(20))
         .timeout_read(Duration::from_secs(120))
         .timeout_write(Duration::from_secs(30))
@@ -935,10 +933,21 @@ fn extract_tarball(tarball: &[u8], dest: &Path, expected_prefix: &str) -> Result
     let gz = GzDecoder::new(tarball);
     let mut archive = tar::Archive::new(gz);
     let mut extracted_files = 0usize;
+    let mut budget = ExtractionLimits::default().budget();
 
     for file in archive.entries().context("failed to read tar entries")? {
         let mut file = file.context("corrupt tar entry")?;
-        let raw_path = file.path().context("bad tar entry path")?.into_owned();
+        let raw_path = match tarball::check_entry(&file)? {
+            EntryCheck::Keep(path) => path,
+            EntryCheck::Ignore => continue,
+            EntryCheck::Skip(reason) => {
+                tarball::warn_skipped(&file, Path::new(expected_prefix), &reason);
+                continue;
+            }
+        };
+        budget
+            .admit(&file)
+            .with_context(|| format!("{} exceeds the extraction limits", expected_prefix))?;
 
         // Strip the top-level directory.  Most crates.io tarballs contain
         // `{name}-{version}/…` as the prefix.  We strip exactly one leading
@@ -952,9 +961,14 @@ fn extract_tarball(tarball: &[u8], dest: &Path, expected_prefix: &str) -> Result
 
         let out_path = dest.join(&stripped);
 
-        if file.header().entry_type().is_dir() {
+        let kind = file.header().entry_type();
+        if kind.is_dir() {
             fs::create_dir_all(&out_path)
                 .with_context(|| format!("mkdir {}", out_path.display()))?;
+        } else if !kind.is_file() {
+            // Registry sources hold plain files only; copying a link's
+            // (empty) data would leave a truncated file behind.
+            tarball::warn_skipped(&file, Path::new(expected_prefix), "not a regular file");
         } else {
             // Ensure parent exists
             if let Some(parent) = out_path.parent() {
@@ -0,0 +1,194 @@
+//! Looking up crates the target distribution already ships, for
+//! `takopack cargo vendor --check-repo`.
+//!
+//! A crate counts as available when some package in the configured
+//! repositories provides `crate(NAME-COMPAT/default)` at a version
+//! satisfying the requirement, the capability takopack's own specs provide
+//! (`crate(serde-1/default)` for `^1.0.100`). Requirements spanning several
+//! compat versions look for the bare `crate(NAME/default)`. The query runs `repo_query` from takopack.toml, by default
+//! `dnf repoquery`, with `{capability}` and `{crate}` substituted. Its
+//! output is read line by line: `CAPABILITY = VERSION` lines (as printed by
+//! `--provides`) and bare version lines are both understood.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::process::Command;
+
+use anyhow::Context;
+use semver::{Version, VersionReq};
+
+use crate::errors::*;
+use crate::takopack::spec::normalize_crate_name;
+use crate::version::requirement_compat_version;
+
+/// Command used when `repo_query` is not configured.
+pub const DEFAULT_REPO_QUERY: &str = "dnf repoquery --quiet --whatprovides {capability} --provides";
+
+/// Runs the repository query, remembering the answer for each crate.
+#[derive(Debug)]
+pub struct RepoQuery {
+    command: Vec<String>,
+    namespace: String,
+    available: RefCell<HashMap<String, Vec<Version>>>,
+}
+
+impl RepoQuery {
+    /// Query with `command` (see the module docs), looking for capabilities
+    /// in `namespace` (`crate` unless configured otherwise).
+    pub fn new(command: &str, namespace: &str) -> Result<Self> {
+        let command: Vec<String> = command.split_whitespace().map(str::to_string).collect();
+        if command.is_empty() {
+            takopack_bail!("repo_query is empty");
+        }
+        Ok(RepoQuery {
+            command,
+            namespace: namespace.to_string(),
+            available: RefCell::new(HashMap::new()),
+        })
+    }
+
+    /// A version of `crate_name` in the repositories that satisfies `req`
+    /// (`None` for any version), preferring the highest.
+    pub fn find(&self, crate_name: &str, req: Option<&str>) -> Result<Option<Version>> {
+        let capability_name = match req.and_then(requirement_compat_version) {
+            Some(compat) => format!("{}-{}", normalize_crate_name(crate_name), compat),
+            None => normalize_crate_name(crate_name),
+        };
+        let req = match req {
+            Some(req) => VersionReq::parse(req)
+                .with_context(|| format!("Invalid version requirement: {}", req))?,
+            None => VersionReq::STAR,
+        };
+        if !self.available.borrow().contains_key(&capability_name) {
+            let versions = self.query(&capability_name, crate_name)?;
+            self.available
+                .borrow_mut()
+                .insert(capability_name.clone(), versions);
+        }
+        Ok(self.available.borrow()[&capability_name]
+            .iter()
+            .filter(|version| req.matches(version))
+            .max()
+            .cloned())
+    }
+
+    /// Versions providing `capability_name` (`serde-1` or `serde`) of
+    /// `crate_name`.
+    fn query(&self, capability_name: &str, crate_name: &str) -> Result<Vec<Version>> {
+        let capability = format!("{}({}/default)", self.namespace, capability_name);
+        let args: Vec<String> = self.command[1..]
+            .iter()
+            .map(|arg| {
+                arg.replace("{capability}", &capability)
+                    .replace("{crate}", crate_name)
+            })
+            .collect();
+        let output = Command::new(&self.command[0])
+            .args(&args)
+            .output()
+            .with_context(|| format!("Failed to run {}", self.command[0]))?;
+        if !output.status.success() {
+            takopack_bail!(
+                "{} {} failed ({}): {}",
+                self.command[0],
+                args.join(" "),
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(provided_versions(
+            &String::from_utf8_lossy(&output.stdout),
+            &capability,
+        ))
+    }
+}
+
+/// Versions of `capability` in the output of a repository query.
+fn provided_versions(output: &str, capability: &str) -> Vec<Version> {
+    let mut versions: Vec<Version> = output
+        .lines()
+        .filter_map(|line| match line.split_once('=') {
+            Some((name, version)) if name.trim() == capability => Some(version),
+            Some(_) => None,
+            None => Some(line),
+        })
+        // RPM spells pre-releases with `~`
+        .filter_map(|version| Version::parse(&version.trim().replace('~', "-")).ok())
+        .collect();
+    versions.sort();
+    versions.dedup();
+    versions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{provided_versions, RepoQuery};
+    use semver::Version;
+
+    #[test]
+    fn versions_are_read_from_provides_and_bare_lines() {
+        let output = "\
+crate(serde) = 1.0.200
+crate(serde/default) = 1.0.200
+crate(serde/std) = 1.0.200
+crate(serde/default) = 1.0.188
+crate(serde_derive/default) = 1.0.200
+";
+        assert_eq!(
+            provided_versions(output, "crate(serde/default)"),
+            vec![
+                Version::parse("1.0.188").unwrap(),
+                Version::parse("1.0.200").unwrap()
+            ]
+        );
+        assert_eq!(
+            provided_versions("0.3.0~rc.1\nnot a version\n", "crate(x/default)"),
+            vec![Version::parse("0.3.0-rc.1").unwrap()]
+        );
+    }
+
+    #[test]
+    fn requirements_pick_a_satisfying_version() {
+        let query = RepoQuery::new(
+            "printf {capability}\\x20=\\x201.0.188\\n{capability}\\x20=\\x201.2.0\\n",
+            "crate",
+        )
+        .unwrap();
+        assert_eq!(
+            query.find("serde", Some("^1.0.100")).unwrap(),
+            Some(Version::parse("1.2.0").unwrap())
+        );
+        assert_eq!(
+            query.find("serde", Some("~1.0")).unwrap(),
+            Some(Version::parse("1.0.188").unwrap())
+        );
+        assert_eq!(query.find("serde", Some("^2")).unwrap(), None);
+        assert!(RepoQuery::new("  ", "crate").is_err());
+    }
+
+    #[test]
+    fn compat_capabilities_are_queried() {
+        // Echoes the capability it was asked about
+        let query = RepoQuery::new(
+            "printf {capability}\\x20=\\x201.0.188\\n{capability}\\x20=\\x200.9.0\\n",
+            "crate",
+        )
+        .unwrap();
+        assert_eq!(
+            query.find("serde", Some("^1.0.100")).unwrap(),
+            Some(Version::parse("1.0.188").unwrap())
+        );
+        assert_eq!(
+            query.available.borrow()["serde-1"],
+            vec![
+                Version::parse("0.9.0").unwrap(),
+                Version::parse("1.0.188").unwrap()
+            ]
+        );
+        assert_eq!(
+            query.find("serde", Some(">=0.9")).unwrap(),
+            Some(Version::parse("1.0.188").unwrap())
+        );
+        assert!(query.available.borrow().contains_key("serde"));
+    }
+}
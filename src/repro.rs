@@ -0,0 +1,275 @@
+//! The corpus of crates that needed special handling at some point, for
+//! `takopack cargo repro`.
+//!
+//! Every case is a directory of the corpus (`corpus/` in the source tree)
+//! holding a `case.toml` naming the crate and version and what it covers,
+//! and the spec expected for it in `expected.spec`:
+//!
+//! ```toml
+//! crate = "toml_datetime"
+//! version = "0.7.5+spec-1.1.0"
+//! note = "build metadata in the version"
+//! ```
+//!
+//! `repro` packages the crate again and compares the spec with the expected
+//! one; `--bless` stores the spec as the new expectation instead. Crates are
+//! packaged with the default configuration, or with the `takopack.toml` of
+//! the case if it has one, so the user's own configuration does not leak
+//! into the comparison.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use crate::errors::*;
+use crate::util::{crate_work_dir, process_single_crate, rust_crate_output_names};
+
+/// Corpus used without `--corpus`, relative to the working directory, so
+/// that `repro` run from a takopack checkout finds the checkout's corpus.
+pub const DEFAULT_CORPUS_DIR: &str = "corpus";
+
+const CASE_FILE: &str = "case.toml";
+const EXPECTED_FILE: &str = "expected.spec";
+const CONFIG_FILE: &str = "takopack.toml";
+
+/// Differing lines shown for a case before the rest is cut off.
+const MAX_DIFF_LINES: usize = 40;
+
+/// Contents of a case's `case.toml`.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct ReproCase {
+    #[serde(rename = "crate")]
+    pub crate_name: String,
+    pub version: String,
+    /// What about the crate needed handling
+    pub note: String,
+}
+
+/// What `takopack cargo repro` does.
+#[derive(Debug, Clone, Default)]
+pub struct ReproOptions {
+    /// Cases to run; every case of the corpus when empty.
+    pub cases: Vec<String>,
+    /// Corpus directory; [`DEFAULT_CORPUS_DIR`] of the working directory
+    /// when not given.
+    pub corpus: Option<PathBuf>,
+    /// Store the generated specs as the expected output.
+    pub bless: bool,
+    /// Only list the cases.
+    pub list: bool,
+}
+
+/// Run the cases of `options`, returning 1 when any of them did not
+/// reproduce.
+pub fn run_repro(options: &ReproOptions) -> Result<i32> {
+    let corpus = match &options.corpus {
+        Some(corpus) => corpus.clone(),
+        None => {
+            let corpus = PathBuf::from(DEFAULT_CORPUS_DIR);
+            if !corpus.is_dir() {
+                takopack_bail!(
+                    "no {} directory here; run from the takopack sources or pass --corpus",
+                    DEFAULT_CORPUS_DIR
+                );
+            }
+            corpus
+        }
+    };
+    let names = if options.cases.is_empty() {
+        case_names(&corpus)?
+    } else {
+        options.cases.clone()
+    };
+
+    if options.list {
+        for name in &names {
+            let case = load_case(&corpus.join(name))?;
+            println!(
+                "{:<24} {} {}: {}",
+                name, case.crate_name, case.version, case.note
+            );
+        }
+        return Ok(0);
+    }
+
+    let mut failed = Vec::new();
+    for name in &names {
+        let dir = corpus.join(name);
+        let case = load_case(&dir)?;
+        println!("{}: {} {}", name, case.crate_name, case.version);
+        let spec = match generate_spec(&dir, &case) {
+            Ok(spec) => spec,
+            Err(e) => {
+                println!("  ✗ could not package: {:#}", e);
+                failed.push(name.as_str());
+                continue;
+            }
+        };
+        let expected_path = dir.join(EXPECTED_FILE);
+        if options.bless {
+            fs::write(&expected_path, &spec)
+                .with_context(|| format!("Failed to write {}", expected_path.display()))?;
+            println!("  ✓ stored {}", expected_path.display());
+            continue;
+        }
+        match fs::read_to_string(&expected_path) {
+            Ok(expected) if expected == spec => println!("  ✓ reproduced"),
+            Ok(expected) => {
+                println!("  ✗ spec differs from {}", expected_path.display());
+                print!("{}", line_diff(&expected, &spec, MAX_DIFF_LINES));
+                failed.push(name.as_str());
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                println!(
+                    "  ✗ no {} yet; run with --bless to store one",
+                    EXPECTED_FILE
+                );
+                failed.push(name.as_str());
+            }
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to read {}", expected_path.display()))
+            }
+        }
+    }
+
+    if options.bless {
+        return Ok(0);
+    }
+    println!(
+        "\n{} of {} case(s) reproduced",
+        names.len() - failed.len(),
+        names.len()
+    );
+    if failed.is_empty() {
+        Ok(0)
+    } else {
+        println!("Failed: {}", failed.join(", "));
+        Ok(1)
+    }
+}
+
+/// Names of the cases in `corpus`, sorted.
+fn case_names(corpus: &Path) -> Result<Vec<String>> {
+    let entries = fs::read_dir(corpus)
+        .with_context(|| format!("Failed to read corpus {}", corpus.display()))?;
+    let mut names = Vec::new();
+    for entry in entries {
+        let entry = entry.with_context(|| format!("Failed to read corpus {}", corpus.display()))?;
+        if entry.path().join(CASE_FILE).is_file() {
+            names.push(entry.file_name().to_string_lossy().into_owned());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+fn load_case(dir: &Path) -> Result<ReproCase> {
+    let path = dir.join(CASE_FILE);
+    let text = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read {}; no such case?", path.display()))?;
+    toml::from_str(&text).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// Package the crate of `case` in a scratch directory and return its spec.
+fn generate_spec(dir: &Path, case: &ReproCase) -> Result<String> {
+    let scratch = tempfile::Builder::new()
+        .prefix("takopack-repro-")
+        .tempdir()
+        .context("Failed to create temporary directory")?;
+    let base_dir = fs::canonicalize(scratch.path())?;
+
+    // A takopack.toml in the work directory takes precedence over the
+    // user's; an empty one means the defaults
+    let work_dir = crate_work_dir(&base_dir, &case.crate_name);
+    fs::create_dir_all(&work_dir)?;
+    let config = match fs::read_to_string(dir.join(CONFIG_FILE)) {
+        Ok(config) => config,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e).context("Failed to read the takopack.toml of the case"),
+    };
+    fs::write(work_dir.join(CONFIG_FILE), config)?;
+
+    let package_dir = process_single_crate(&case.crate_name, &case.version, &base_dir, None)?;
+    let version = semver::Version::parse(&case.version)
+        .with_context(|| format!("case versions must be exact, not {}", case.version))?;
+    let spec_path = package_dir.join(rust_crate_output_names(&case.crate_name, &version).spec_file);
+    fs::read_to_string(&spec_path)
+        .with_context(|| format!("Failed to read {}", spec_path.display()))
+}
+
+/// The lines between the common beginning and end of `expected` and
+/// `actual`, as `-expected` / `+actual` lines, at most `max` of them.
+fn line_diff(expected: &str, actual: &str, max: usize) -> String {
+    let old: Vec<&str> = expected.lines().collect();
+    let new: Vec<&str> = actual.lines().collect();
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let lines: Vec<String> = old[prefix..old.len() - suffix]
+        .iter()
+        .map(|line| format!("    -{}", line))
+        .chain(
+            new[prefix..new.len() - suffix]
+                .iter()
+                .map(|line| format!("    +{}", line)),
+        )
+        .collect();
+    let mut diff = format!("    @@ line {} @@\n", prefix + 1);
+    for line in lines.iter().take(max) {
+        diff.push_str(line);
+        diff.push('\n');
+    }
+    if lines.len() > max {
+        diff.push_str(&format!("    ... {} more line(s)\n", lines.len() - max));
+    }
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{case_names, line_diff, load_case, DEFAULT_CORPUS_DIR, EXPECTED_FILE};
+    use std::path::Path;
+
+    #[test]
+    fn corpus_cases_parse() {
+        let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join(DEFAULT_CORPUS_DIR);
+        let names = case_names(&corpus).unwrap();
+        assert!(names.contains(&"winapi-0.3.9".to_string()), "{:?}", names);
+        for name in names {
+            let case = load_case(&corpus.join(&name)).unwrap();
+            assert!(
+                semver::Version::parse(&case.version).is_ok(),
+                "{}: {}",
+                name,
+                case.version
+            );
+            assert!(!case.note.is_empty(), "{}", name);
+            assert!(
+                corpus.join(&name).join(EXPECTED_FILE).is_file(),
+                "{} has no {}",
+                name,
+                EXPECTED_FILE
+            );
+        }
+    }
+
+    #[test]
+    fn diffs_show_the_changed_lines() {
+        let expected = "Name: a\nVersion: 1\nRequires: x\nRequires: y\n%files\n";
+        let actual = "Name: a\nVersion: 1\nRequires: z\n%files\n";
+        assert_eq!(
+            line_diff(expected, actual, 10),
+            "    @@ line 3 @@\n    -Requires: x\n    -Requires: y\n    +Requires: z\n"
+        );
+        assert!(line_diff(expected, actual, 1).ends_with("    ... 2 more line(s)\n"));
+    }
+}
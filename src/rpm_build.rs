@@ -0,0 +1,306 @@
+//! Building generated specs with rpmbuild or mock, for `--build` and
+//! `takopack cargo build-spec`.
+//!
+//! `rpmbuild -bs` turns a package directory into a source RPM, downloading
+//! the remote `Source:` into the directory unless it is already there. With
+//! mock, the source RPM is then rebuilt in a chroot. Everything produced is
+//! left in the package directory, next to the spec.
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::Context;
+
+use crate::errors::*;
+use crate::events::{self, Event};
+use crate::verbosity::Verbosity;
+
+/// Lines of rpmbuild output kept in the error of a failed build; all of
+/// them with `--verbose`.
+const ERROR_TAIL_LINES: usize = 20;
+
+/// What a build produces.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum BuildTool {
+    /// A source RPM, with `rpmbuild -bs`.
+    #[default]
+    Srpm,
+    /// Binary RPMs, rebuilding the source RPM with mock.
+    Mock,
+}
+
+impl fmt::Display for BuildTool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildTool::Srpm => write!(f, "srpm"),
+            BuildTool::Mock => write!(f, "mock"),
+        }
+    }
+}
+
+/// Packages built from one directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildResult {
+    pub spec: PathBuf,
+    pub srpm: PathBuf,
+    /// Binary RPMs; empty unless built with mock.
+    pub rpms: Vec<PathBuf>,
+}
+
+/// Build every package under `dir` (a package directory, or an output root
+/// whose subdirectories are package directories) and print a summary.
+/// Returns 1 when a build failed.
+pub fn run_build_spec(dir: &Path, tool: BuildTool, mock_root: Option<&str>) -> Result<i32> {
+    let package_dirs = package_dirs(dir)?;
+    if package_dirs.is_empty() {
+        takopack_bail!(
+            "no spec file found in {} or its subdirectories",
+            dir.display()
+        );
+    }
+
+    let mut failed = Vec::new();
+    for package_dir in &package_dirs {
+        match build_package_dir(package_dir, tool, mock_root) {
+            Ok(result) => print_result(&result),
+            Err(e) => {
+                takopack_warn!("build of {} failed: {:#}", package_dir.display(), e);
+                failed.push((package_dir, e));
+            }
+        }
+    }
+
+    println!(
+        "\nBuilt {} of {} package(s)",
+        package_dirs.len() - failed.len(),
+        package_dirs.len()
+    );
+    for (package_dir, e) in &failed {
+        println!("  - {}: {:#}", package_dir.display(), e);
+    }
+    Ok(if failed.is_empty() { 0 } else { 1 })
+}
+
+pub fn print_result(result: &BuildResult) {
+    events::emit(Event::note(format!("SRPM: {}", result.srpm.display())));
+    for rpm in &result.rpms {
+        events::emit(Event::note(format!("RPM:  {}", rpm.display())));
+    }
+}
+
+/// Build the spec in `dir` with `tool`. `mock_root` is passed to mock's
+/// `-r`; without it mock uses its default configuration.
+pub fn build_package_dir(
+    dir: &Path,
+    tool: BuildTool,
+    mock_root: Option<&str>,
+) -> Result<BuildResult> {
+    let dir =
+        fs::canonicalize(dir).with_context(|| format!("Failed to resolve {}", dir.display()))?;
+    let spec = find_spec(&dir)?;
+
+    let output = rpmbuild_command(&spec, &dir)
+        .output()
+        .context("Failed to run rpmbuild; is rpm-build installed?")?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        takopack_bail!(
+            "rpmbuild -bs failed ({}):\n{}",
+            output.status,
+            if Verbosity::current().is_verbose() {
+                stderr.trim_end().to_string()
+            } else {
+                tail(&stderr, ERROR_TAIL_LINES)
+            }
+        );
+    }
+    let Some(srpm) = wrote_paths(&stdout)
+        .into_iter()
+        .find(|p| p.to_string_lossy().ends_with(".src.rpm"))
+    else {
+        takopack_bail!("rpmbuild did not report a source RPM");
+    };
+
+    let mut rpms = Vec::new();
+    if tool == BuildTool::Mock {
+        let status = mock_command(&srpm, &dir, mock_root)
+            .status()
+            .context("Failed to run mock; is it installed?")?;
+        if !status.success() {
+            takopack_bail!(
+                "mock failed ({}); see the logs in {}",
+                status,
+                dir.display()
+            );
+        }
+        rpms = binary_rpms(&dir)?;
+    }
+    Ok(BuildResult { spec, srpm, rpms })
+}
+
+/// `dir` itself when it holds a spec, otherwise its subdirectories that do.
+fn package_dirs(dir: &Path) -> Result<Vec<PathBuf>> {
+    if find_spec(dir).is_ok() {
+        return Ok(vec![dir.to_path_buf()]);
+    }
+    let mut dirs = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let path = entry?.path();
+        if path.is_dir() && find_spec(&path).is_ok() {
+            dirs.push(path);
+        }
+    }
+    dirs.sort();
+    Ok(dirs)
+}
+
+/// The one `*.spec` file in `dir`.
+/// The one spec file in `dir`.
+pub fn find_spec(dir: &Path) -> Result<PathBuf> {
+    let mut specs = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|ext| ext == "spec") {
+            specs.push(path);
+        }
+    }
+    match specs.len() {
+        1 => Ok(specs.remove(0)),
+        0 => takopack_bail!("no spec file in {}", dir.display()),
+        _ => takopack_bail!("more than one spec file in {}", dir.display()),
+    }
+}
+
+/// `rpmbuild -bs` reading sources from, and writing the source RPM to, `dir`.
+fn rpmbuild_command(spec: &Path, dir: &Path) -> Command {
+    let mut cmd = Command::new("rpmbuild");
+    cmd.arg("-bs")
+        .arg("--define")
+        .arg(format!("_sourcedir {}", dir.display()))
+        .arg("--define")
+        .arg(format!("_srcrpmdir {}", dir.display()))
+        .arg("--define")
+        .arg("_disable_source_fetch 0")
+        .arg(spec);
+    cmd
+}
+
+fn mock_command(srpm: &Path, dir: &Path, mock_root: Option<&str>) -> Command {
+    let mut cmd = Command::new("mock");
+    if let Some(root) = mock_root {
+        cmd.arg("-r").arg(root);
+    }
+    cmd.arg("--resultdir").arg(dir).arg("--rebuild").arg(srpm);
+    cmd
+}
+
+/// Files rpmbuild reports as `Wrote: <path>`.
+fn wrote_paths(output: &str) -> Vec<PathBuf> {
+    output
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("Wrote:"))
+        .map(|path| PathBuf::from(path.trim()))
+        .collect()
+}
+
+fn binary_rpms(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut rpms = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_binary = {
+            let name = path.to_string_lossy();
+            name.ends_with(".rpm") && !name.ends_with(".src.rpm")
+        };
+        if is_binary {
+            rpms.push(path);
+        }
+    }
+    rpms.sort();
+    Ok(rpms)
+}
+
+fn tail(text: &str, lines: usize) -> String {
+    let all: Vec<&str> = text.lines().collect();
+    all[all.len().saturating_sub(lines)..].join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_spec, mock_command, package_dirs, rpmbuild_command, tail, wrote_paths};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn package_directories_hold_exactly_one_spec() {
+        let root = tempfile::tempdir().unwrap();
+        let a = root.path().join("rust-a-1");
+        let b = root.path().join("rust-b-0.2");
+        let empty = root.path().join("notes");
+        for dir in [&a, &b, &empty] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        fs::write(a.join("rust-a-1.spec"), "").unwrap();
+        fs::write(b.join("rust-b-0.2.spec"), "").unwrap();
+
+        assert_eq!(
+            package_dirs(root.path()).unwrap(),
+            vec![a.clone(), b.clone()]
+        );
+        assert_eq!(package_dirs(&a).unwrap(), vec![a.clone()]);
+        assert_eq!(find_spec(&a).unwrap(), a.join("rust-a-1.spec"));
+        assert!(find_spec(&empty).is_err());
+
+        fs::write(a.join("other.spec"), "").unwrap();
+        assert!(find_spec(&a).is_err());
+    }
+
+    #[test]
+    fn commands_keep_everything_in_the_package_directory() {
+        let dir = Path::new("/out/rust-a-1");
+        let args = |cmd: std::process::Command| -> Vec<String> {
+            cmd.get_args()
+                .map(|a| a.to_string_lossy().into_owned())
+                .collect()
+        };
+        assert_eq!(
+            args(rpmbuild_command(&dir.join("rust-a-1.spec"), dir)),
+            vec![
+                "-bs",
+                "--define",
+                "_sourcedir /out/rust-a-1",
+                "--define",
+                "_srcrpmdir /out/rust-a-1",
+                "--define",
+                "_disable_source_fetch 0",
+                "/out/rust-a-1/rust-a-1.spec",
+            ]
+        );
+        let srpm = dir.join("rust-a-1-1.0.0-1.src.rpm");
+        assert_eq!(
+            args(mock_command(&srpm, dir, Some("fedora-rawhide-x86_64"))),
+            vec![
+                "-r",
+                "fedora-rawhide-x86_64",
+                "--resultdir",
+                "/out/rust-a-1",
+                "--rebuild",
+                "/out/rust-a-1/rust-a-1-1.0.0-1.src.rpm",
+            ]
+        );
+    }
+
+    #[test]
+    fn written_files_are_read_from_rpmbuild_output() {
+        let output =
+            "setting SOURCE_DATE_EPOCH=1700000000\nWrote: /out/rust-a-1/rust-a-1-1.0.0-1.src.rpm\n";
+        assert_eq!(
+            wrote_paths(output),
+            vec![PathBuf::from("/out/rust-a-1/rust-a-1-1.0.0-1.src.rpm")]
+        );
+        assert_eq!(tail("a\nb\nc", 2), "b\nc");
+        assert_eq!(tail("a", 5), "a");
+    }
+}
@@ -0,0 +1,144 @@
+//! The takopack version a team agreed on, and what goes into bug reports.
+//!
+//! Specs generated by different takopack versions differ in small ways, so
+//! a team sharing one conf repository can pin the version in its
+//! takopack.toml with `required_takopack_version`, a semver requirement
+//! such as `=0.4.2` or `~0.4`. Every command checks it at startup and
+//! refuses to run under another version unless given
+//! `--ignore-version-check`. `takopack self check` prints the version, the
+//! compiled-in features and the configuration and profile in effect.
+
+use std::fmt::Write;
+use std::path::Path;
+
+use anyhow::Context;
+use semver::{Version, VersionReq};
+
+use crate::config::Config;
+use crate::errors::*;
+
+/// Version of this takopack.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Cargo features takopack was built with.
+pub fn enabled_features() -> Vec<&'static str> {
+    let mut features = Vec::new();
+    if cfg!(feature = "back_db") {
+        features.push("back_db");
+    }
+    features
+}
+
+fn version_matches(required: &str, version: &str) -> Result<bool> {
+    let requirement = VersionReq::parse(required)
+        .with_context(|| format!("invalid required_takopack_version {:?}", required))?;
+    Ok(requirement.matches(&Version::parse(version)?))
+}
+
+/// Fail unless this takopack satisfies `required_takopack_version`.
+pub fn check_required_version(config: &Config, config_path: Option<&Path>) -> Result<()> {
+    let Some(required) = &config.required_takopack_version else {
+        return Ok(());
+    };
+    if version_matches(required, VERSION)? {
+        return Ok(());
+    }
+    takopack_bail!(
+        "{} requires takopack {}, but this is takopack {}\n\
+         Install the agreed version, or pass --ignore-version-check to run anyway.",
+        config_path.map_or("takopack.toml".into(), |p| p.display().to_string()),
+        required,
+        VERSION
+    );
+}
+
+/// The version, features, configuration and profile, for bug reports.
+pub fn report(config_path: Option<&Path>, config: &Config) -> Result<String> {
+    let mut out = String::new();
+    writeln!(out, "takopack {}", VERSION)?;
+    let features = enabled_features();
+    writeln!(
+        out,
+        "features: {}",
+        if features.is_empty() {
+            "(none)".to_string()
+        } else {
+            features.join(", ")
+        }
+    )?;
+    writeln!(
+        out,
+        "platform: {}-{}",
+        std::env::consts::ARCH,
+        std::env::consts::OS
+    )?;
+    match config_path {
+        Some(path) => writeln!(out, "config: {}", path.display())?,
+        None => writeln!(out, "config: (none, built-in defaults)")?,
+    }
+    if let Some(required) = &config.required_takopack_version {
+        let status = match version_matches(required, VERSION) {
+            Ok(true) => "satisfied".to_string(),
+            Ok(false) => "NOT satisfied".to_string(),
+            Err(e) => format!("{:#}", e),
+        };
+        writeln!(out, "required version: {} ({})", required, status)?;
+    }
+    match &config.profile {
+        Some(name) => {
+            writeln!(out, "profile: {}", name)?;
+            let settings = crate::profile::resolve_profile(name, Some(&config.profiles))?;
+            for line in toml::to_string_pretty(&settings)?.lines() {
+                writeln!(out, "    {}", line)?;
+            }
+        }
+        None => writeln!(out, "profile: (none)")?,
+    }
+    Ok(out)
+}
+
+/// `takopack self check`
+pub fn run_self_check() -> Result<i32> {
+    let (config_path, config) = Config::load()?;
+    print!("{}", report(config_path.as_deref(), &config)?);
+    Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_required_version, report, version_matches, VERSION};
+    use crate::config::Config;
+    use std::path::Path;
+
+    #[test]
+    fn required_version_is_checked_and_reported() {
+        assert!(version_matches("=0.4.2", "0.4.2").unwrap());
+        assert!(version_matches("~0.4", "0.4.7").unwrap());
+        assert!(!version_matches("~0.4", "0.5.0").unwrap());
+        assert!(version_matches("not a version", "0.4.2").is_err());
+
+        let mut config = Config::default();
+        assert!(check_required_version(&config, None).is_ok());
+        config.required_takopack_version = Some(format!("={}", VERSION));
+        assert!(check_required_version(&config, None).is_ok());
+        config.required_takopack_version = Some(">=999".to_string());
+        let path = Path::new("/conf/takopack.toml");
+        let err = check_required_version(&config, Some(path))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("/conf/takopack.toml requires takopack >=999"),
+            "{}",
+            err
+        );
+
+        let text = report(Some(path), &config).unwrap();
+        assert!(
+            text.starts_with(&format!("takopack {}\n", VERSION)),
+            "{}",
+            text
+        );
+        assert!(text.contains("required version: >=999 (NOT satisfied)"));
+        assert!(text.contains("profile: (none)"));
+    }
+}
@@ -0,0 +1,122 @@
+//! Crates `takopack cargo vendor` does not package, usually because the
+//! distro already ships them.
+//!
+//! Entries are `name` or `name@version`, given with `--skip` or listed one
+//! per line in a `--skip-file` (`#` starts a comment). A bare name skips
+//! every version of the crate. With a version, the crate is only skipped
+//! where that version satisfies the dependency's requirement, so a
+//! dependent needing a newer release still gets one packaged. Skipped
+//! crates are still required by the specs that depend on them.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::Context;
+use semver::{Version, VersionReq};
+
+use crate::errors::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SkipEntry {
+    /// Crate name with `_` replaced by `-`
+    name: String,
+    version: Option<Version>,
+}
+
+/// Crates to leave out of a recursive packaging run.
+#[derive(Debug, Clone, Default)]
+pub struct SkipList {
+    entries: Vec<SkipEntry>,
+}
+
+impl SkipList {
+    /// Add a `name` or `name@version` entry.
+    pub fn add(&mut self, entry: &str) -> Result<()> {
+        let entry = entry.trim();
+        let (name, version) = match entry.split_once(['@', ' ', '\t']) {
+            Some((name, version)) => (name, Some(version.trim())),
+            None => (entry, None),
+        };
+        if name.is_empty() {
+            takopack_bail!("invalid skip list entry {:?}: missing crate name", entry);
+        }
+        let version = version
+            .map(|v| {
+                Version::parse(v).map_err(|e| {
+                    format_err!("invalid version in skip list entry {:?}: {}", entry, e)
+                })
+            })
+            .transpose()?;
+        self.entries.push(SkipEntry {
+            name: name.replace('_', "-"),
+            version,
+        });
+        Ok(())
+    }
+
+    /// Add every entry listed in the file at `path`.
+    pub fn add_file(&mut self, path: &Path) -> Result<()> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read skip list {}", path.display()))?;
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            self.add(line)
+                .with_context(|| format!("{}:{}", path.display(), i + 1))?;
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Whether a dependency on `crate_name` with requirement `req` (`None`
+    /// for any version) is to be skipped.
+    pub fn matches(&self, crate_name: &str, req: Option<&str>) -> bool {
+        let name = crate_name.replace('_', "-");
+        let req = req.and_then(|req| VersionReq::parse(req).ok());
+        self.entries
+            .iter()
+            .filter(|entry| entry.name == name)
+            .any(|entry| match (&entry.version, &req) {
+                (Some(version), Some(req)) => req.matches(version),
+                _ => true,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SkipList;
+
+    #[test]
+    fn versioned_entries_only_skip_requirements_they_satisfy() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("skip.txt");
+        std::fs::write(
+            &file,
+            "# shipped by the distro\nlibc\nserde@1.0.150\n\nproc_macro2 1.0.60  # old\n",
+        )
+        .unwrap();
+        let mut skip = SkipList::default();
+        skip.add_file(&file).unwrap();
+        skip.add("log@0.4.20").unwrap();
+
+        assert!(skip.matches("libc", Some("^0.2.100")));
+        assert!(skip.matches("serde", Some("^1.0.100")));
+        assert!(!skip.matches("serde", Some("^1.0.190")));
+        assert!(skip.matches("serde", None));
+        assert!(skip.matches("proc-macro2", Some("1")));
+        assert!(skip.matches("log", Some("0.4")));
+        assert!(!skip.matches("serde_json", Some("1")));
+
+        assert!(skip.add("serde@latest").is_err());
+        assert!(skip.add("@1.0.0").is_err());
+        std::fs::write(&file, "ok\nbad@1.x\n").unwrap();
+        let err = format!("{:#}", SkipList::default().add_file(&file).unwrap_err());
+        assert!(err.contains("skip.txt:2"), "{}", err);
+    }
+}
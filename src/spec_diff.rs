@@ -0,0 +1,356 @@
+//! What changes in a spec when a crate is packaged again.
+//!
+//! A text diff between the spec in the distro and a regenerated one is
+//! mostly noise: changelog entries, reflowed descriptions, dates. When an
+//! updated crate is packaged again, the reviewer wants to know whether the
+//! version moved, which subpackages (mostly features) came and went, and
+//! which `Requires:` and `BuildRequires:` were added, dropped or changed
+//! their version. `cargo diff-spec` generates the new spec in a temporary
+//! directory and reports just that.
+
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::package::{PackageExecuteArgs, PackageExtractArgs, PackageInitArgs, PackageProcess};
+
+/// The key of the package whose preamble is the spec header.
+const MAIN_PACKAGE: &str = "%{name}";
+
+/// The parts of a spec that [`SpecDiff`] compares.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SpecSummary {
+    /// `full_version`, or `Version:` in specs written by hand
+    pub version: Option<String>,
+    pub build_requires: Vec<String>,
+    /// The `Requires:` of every package, by package name
+    pub requires: BTreeMap<String, Vec<String>>,
+}
+
+/// How the requirements of a package changed.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RequirementChanges {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    /// The same capability with other versions: old and new requirements
+    pub changed: Vec<(String, String)>,
+}
+
+impl RequirementChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SpecDiff {
+    pub crate_name: String,
+    pub old_version: Option<String>,
+    pub new_version: Option<String>,
+    pub added_packages: Vec<String>,
+    pub removed_packages: Vec<String>,
+    pub build_requires: RequirementChanges,
+    /// Changed `Requires:` of packages in both specs
+    pub requires: BTreeMap<String, RequirementChanges>,
+}
+
+/// Generate the spec of the crate of `init` and compare `existing` with it.
+pub fn diff_against(
+    init: PackageInitArgs,
+    mut finish: PackageExecuteArgs,
+    existing: &Path,
+) -> Result<SpecDiff> {
+    let old = fs::read_to_string(existing)
+        .with_context(|| format!("Failed to read {}", existing.display()))?;
+    // Only a preview: leave the overlay directory alone
+    finish.no_overlay_write_back = true;
+    let workdir = tempfile::Builder::new()
+        .prefix("takopack-diff-spec-")
+        .tempdir()
+        .context("Failed to create temporary directory")?;
+    let (crate_name, new) = generate_spec(init, finish, workdir.path())?;
+    let mut diff = diff_specs(&parse_spec(&old), &parse_spec(&new));
+    diff.crate_name = crate_name;
+    Ok(diff)
+}
+
+/// The name of the crate of `init` and its spec, generated in `workdir`.
+pub fn generate_spec(
+    init: PackageInitArgs,
+    finish: PackageExecuteArgs,
+    workdir: &Path,
+) -> Result<(String, String)> {
+    let mut process = PackageProcess::init(init, finish.profile.as_deref(), finish.test_mode)?;
+    let crate_name = process.crate_info().crate_name().to_string();
+    let output_names = process
+        .config
+        .output_names(&crate_name, process.crate_info().version());
+    process.extract(PackageExtractArgs {
+        directory: Some(workdir.to_path_buf()),
+    })?;
+    process.apply_overrides()?;
+    process.prepare_orig_tarball()?;
+    process.prepare_takopack_folder(finish)?;
+    let spec = workdir.join("takopack").join(&output_names.spec_file);
+    let text =
+        fs::read_to_string(&spec).with_context(|| format!("Failed to read {}", spec.display()))?;
+    Ok((crate_name, text))
+}
+
+/// The version, packages and requirements of the spec `text`.
+pub fn parse_spec(text: &str) -> SpecSummary {
+    let mut summary = SpecSummary::default();
+    let mut package = MAIN_PACKAGE.to_string();
+    summary.requires.insert(package.clone(), Vec::new());
+    let mut rpm_version = None;
+    let mut in_description = false;
+    for line in text.lines() {
+        let line = line.trim();
+        // Packages are declared before the build sections
+        if line.starts_with("%prep") {
+            break;
+        }
+        if line.starts_with("%description") {
+            in_description = true;
+        } else if let Some(rest) = line.strip_prefix("%package") {
+            in_description = false;
+            let mut words = rest.split_whitespace();
+            package = match words.next() {
+                Some("-n") => words.next().unwrap_or_default().to_string(),
+                Some(suffix) => format!("{}-{}", MAIN_PACKAGE, suffix),
+                None => continue,
+            };
+            summary.requires.entry(package.clone()).or_default();
+        } else if let Some(version) = line.strip_prefix("%global full_version") {
+            summary.version = Some(version.trim().to_string());
+        } else if in_description {
+            continue;
+        } else if let Some((tag, value)) = line.split_once(':') {
+            let value = value.trim().to_string();
+            match tag.trim() {
+                "Version" => rpm_version = Some(value),
+                "BuildRequires" => summary.build_requires.push(value),
+                tag if tag == "Requires" || tag.starts_with("Requires(") => summary
+                    .requires
+                    .get_mut(&package)
+                    .expect("package was inserted")
+                    .push(value),
+                _ => {}
+            }
+        }
+    }
+    if summary.version.is_none() {
+        summary.version = rpm_version;
+    }
+    summary
+}
+
+/// What changed from `old` to `new`.
+pub fn diff_specs(old: &SpecSummary, new: &SpecSummary) -> SpecDiff {
+    let mut diff = SpecDiff {
+        old_version: old.version.clone(),
+        new_version: new.version.clone(),
+        build_requires: diff_requirements(&old.build_requires, &new.build_requires),
+        ..SpecDiff::default()
+    };
+    for (package, requires) in &new.requires {
+        match old.requires.get(package) {
+            None => diff.added_packages.push(package.clone()),
+            Some(old_requires) => {
+                let changes = diff_requirements(old_requires, requires);
+                if !changes.is_empty() {
+                    diff.requires.insert(package.clone(), changes);
+                }
+            }
+        }
+    }
+    diff.removed_packages = old
+        .requires
+        .keys()
+        .filter(|package| !new.requires.contains_key(*package))
+        .cloned()
+        .collect();
+    diff
+}
+
+fn diff_requirements(old: &[String], new: &[String]) -> RequirementChanges {
+    let old = by_capability(old);
+    let new = by_capability(new);
+    let mut changes = RequirementChanges::default();
+    for (capability, requirements) in &new {
+        match old.get(capability) {
+            None => changes
+                .added
+                .extend(requirements.iter().map(|r| r.to_string())),
+            Some(old_requirements) if old_requirements != requirements => changes
+                .changed
+                .push((old_requirements.join(", "), requirements.join(", "))),
+            Some(_) => {}
+        }
+    }
+    for (capability, requirements) in &old {
+        if !new.contains_key(capability) {
+            changes
+                .removed
+                .extend(requirements.iter().map(|r| r.to_string()));
+        }
+    }
+    changes
+}
+
+/// `requirements` by what they require, e.g. `crate(serde/std)` for
+/// `crate(serde/std) >= 1.0.0` and for a rich dependency on it.
+fn by_capability(requirements: &[String]) -> BTreeMap<&str, Vec<&str>> {
+    let mut map: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for requirement in requirements {
+        let capability = requirement
+            .trim_start_matches('(')
+            .split_whitespace()
+            .next()
+            .unwrap_or_default();
+        let entry = map.entry(capability).or_default();
+        entry.push(requirement);
+        entry.sort_unstable();
+    }
+    map
+}
+
+impl fmt::Display for SpecDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# {}", self.crate_name)?;
+        writeln!(f)?;
+        let version = |v: &Option<String>| v.clone().unwrap_or_else(|| "?".to_string());
+        if self.old_version == self.new_version {
+            writeln!(f, "Version: {} (unchanged)", version(&self.new_version))?;
+        } else {
+            writeln!(
+                f,
+                "Version: {} -> {}",
+                version(&self.old_version),
+                version(&self.new_version)
+            )?;
+        }
+
+        for (title, packages, sign) in [
+            ("New packages", &self.added_packages, '+'),
+            ("Removed packages", &self.removed_packages, '-'),
+        ] {
+            if packages.is_empty() {
+                continue;
+            }
+            writeln!(f)?;
+            writeln!(f, "{}:", title)?;
+            for package in packages {
+                writeln!(f, "  {} {}", sign, package)?;
+            }
+        }
+
+        let requires = self
+            .requires
+            .iter()
+            .map(|(package, changes)| (format!("Requires of {}", package), changes));
+        for (title, changes) in
+            std::iter::once(("BuildRequires".to_string(), &self.build_requires)).chain(requires)
+        {
+            if changes.is_empty() {
+                continue;
+            }
+            writeln!(f)?;
+            writeln!(f, "{}:", title)?;
+            for requirement in &changes.added {
+                writeln!(f, "  + {}", requirement)?;
+            }
+            for requirement in &changes.removed {
+                writeln!(f, "  - {}", requirement)?;
+            }
+            for (old, new) in &changes.changed {
+                writeln!(f, "  ~ {} -> {}", old, new)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff_specs, parse_spec};
+
+    const OLD: &str = "\
+%global crate_name demo
+%global full_version 1.0.0
+Name:           rust-demo
+Version:        1.0.0
+BuildRequires:  cargo-rpm-macros
+BuildRequires:  crate(serde/default) >= 1.0.100
+
+%description
+Requires: nothing, this is text.
+
+%package     -n %{name}+default
+Requires:       crate(%{pkgname}) = %{version}
+Requires:       crate(serde/default) >= 1.0.100
+
+%package     -n %{name}+old
+Requires:       crate(%{pkgname}) = %{version}
+
+%prep
+Requires: not a tag either
+";
+
+    const NEW: &str = "\
+%global crate_name demo
+%global full_version 1.1.0
+Name:           rust-demo
+Version:        1.1.0
+BuildRequires:  cargo-rpm-macros
+BuildRequires:  crate(serde/default) >= 1.0.200
+BuildRequires:  crate(log/default) >= 0.4.0
+
+%package     -n %{name}+default
+Requires:       crate(%{pkgname}) = %{version}
+Requires:       crate(serde/default) >= 1.0.200
+Requires:       crate(log/default) >= 0.4.0
+
+%package     -n %{name}+fancy
+Requires:       crate(%{pkgname}) = %{version}
+
+%prep
+";
+
+    #[test]
+    fn diff_reports_version_packages_and_requirements() {
+        let old = parse_spec(OLD);
+        assert_eq!(old.version.as_deref(), Some("1.0.0"));
+        assert_eq!(
+            old.requires.keys().collect::<Vec<_>>(),
+            ["%{name}", "%{name}+default", "%{name}+old"]
+        );
+        assert!(old.requires["%{name}"].is_empty());
+
+        let diff = diff_specs(&old, &parse_spec(NEW));
+        assert_eq!(diff.new_version.as_deref(), Some("1.1.0"));
+        assert_eq!(diff.added_packages, ["%{name}+fancy"]);
+        assert_eq!(diff.removed_packages, ["%{name}+old"]);
+        assert_eq!(diff.build_requires.added, ["crate(log/default) >= 0.4.0"]);
+        assert_eq!(
+            diff.build_requires.changed,
+            [(
+                "crate(serde/default) >= 1.0.100".to_string(),
+                "crate(serde/default) >= 1.0.200".to_string()
+            )]
+        );
+        let default = &diff.requires["%{name}+default"];
+        assert_eq!(default.added, ["crate(log/default) >= 0.4.0"]);
+        assert_eq!(default.changed.len(), 1);
+        assert_eq!(diff.requires.len(), 1);
+
+        let text = diff.to_string();
+        assert!(text.contains("Version: 1.0.0 -> 1.1.0"));
+        assert!(text.contains("New packages:\n  + %{name}+fancy"));
+        assert!(text.contains(
+            "Requires of %{name}+default:\n  + crate(log/default) >= 0.4.0\n  ~ crate(serde/default) >= 1.0.100 -> crate(serde/default) >= 1.0.200"
+        ));
+    }
+}
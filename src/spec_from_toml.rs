@@ -3,7 +3,9 @@ use std::fs;
 use std::path::{Path, PathBuf};
 use toml::Value;
 
+use crate::config::Config;
 use crate::recursive_package::RecursivePackager;
+use crate::workspace::{manifest_inherits_from_workspace, WorkspaceRoot};
 
 /// Parse dependencies from Cargo.toml and recursively generate spec files for all dependencies
 pub fn parse_dependencies_from_toml(toml_path: &Path, output_dir: Option<PathBuf>) -> Result<()> {
@@ -11,9 +13,22 @@ pub fn parse_dependencies_from_toml(toml_path: &Path, output_dir: Option<PathBuf
     let cargo_toml_content = fs::read_to_string(toml_path)
         .with_context(|| format!("Failed to read Cargo.toml: {:?}", toml_path))?;
 
-    let manifest: Value =
+    let mut manifest: Value =
         toml::from_str(&cargo_toml_content).with_context(|| "Failed to parse Cargo.toml")?;
 
+    // Substitute `workspace = true` dependencies with the versions declared
+    // by the workspace root, otherwise they would resolve to "latest".
+    if manifest_inherits_from_workspace(&manifest) {
+        let workspace = WorkspaceRoot::discover(toml_path)?.with_context(|| {
+            format!(
+                "{:?} uses `workspace = true` but no workspace root was found above it",
+                toml_path
+            )
+        })?;
+        let member_dir = toml_path.parent().unwrap_or_else(|| Path::new("."));
+        workspace.inherit_into(&mut manifest, member_dir)?;
+    }
+
     // Parse dependencies
     let dependencies = manifest
         .get("dependencies")
@@ -31,7 +46,9 @@ pub fn parse_dependencies_from_toml(toml_path: &Path, output_dir: Option<PathBuf
     println!("Output directory: {:?}", output_dir);
 
     // Create a recursive packager to handle dependency resolution
-    let mut packager = RecursivePackager::new(Some(output_dir))?;
+    let (_, config) = Config::load()?;
+    let mut packager =
+        RecursivePackager::new(Some(output_dir))?.with_configured_filters(&config)?;
 
     println!("Found {} dependencies in Cargo.toml", dependencies.len());
 
@@ -62,24 +79,26 @@ pub fn parse_dependencies_from_toml(toml_path: &Path, output_dir: Option<PathBuf
             None
         };
 
-        // Use the dependency name as-is (keep dashes, don't convert to underscores)
-        // Cargo.toml uses the actual crate name which matches crates.io
+        // The crates.io name: `foo = { package = "bar", ... }` depends on
+        // bar. Keep dashes, don't convert to underscores.
+        let crate_name = dep_value
+            .get("package")
+            .and_then(|v| v.as_str())
+            .unwrap_or(dep_name);
 
         println!(
             "\nProcessing dependency: {} (version: {:?})",
-            dep_name, version
+            crate_name, version
         );
 
         // Process this crate and all its dependencies recursively
-        if let Err(e) = packager.process_crate_recursive(
-            dep_name, // Use the original name with dashes
-            version.as_deref(),
-        ) {
-            eprintln!("Failed to process {}: {:#}", dep_name, e);
+        if let Err(e) = packager.process_crate_recursive(crate_name, version.as_deref()) {
+            eprintln!("Failed to process {}: {:#}", crate_name, e);
         }
     }
 
     // Print summary
+    packager.write_failure_report()?;
     packager.print_summary();
 
     Ok(())
@@ -0,0 +1,608 @@
+//! Querying and pruning the packaged streams by hand.
+//!
+//! takopack keeps no database of what it packaged: the ruyispec tree is the
+//! record, one `SPECS/rust-<crate>-<compat>/` stream per packaged compat
+//! version (see [`crate::stream_gc`]). `db list`, `db search` and `db show`
+//! read the streams back from their specs, `db stats` summarizes them, and
+//! `db remove` deletes the streams of a crate that should not have been
+//! packaged, which `db gc` keeps as long as a lockfile still pins them.
+//! `db export` writes the streams as JSON or CSV for other tools, and
+//! `db import` reads such an export back, from another team say, to find
+//! the streams the local tree lacks. `db outdated` finds the streams a
+//! newer compatible release could bump.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::Context;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+
+use crate::config::resolve_ruyispec_dir;
+use crate::crates::{select_index_version, IndexVersion, SparseIndex};
+use crate::errors::*;
+use crate::registry_sync::scan_providers;
+use crate::stream_gc::Stream;
+
+/// The streams of the ruyispec tree at `ruyispec_dir`, ordered by spec path.
+pub fn scan_streams(ruyispec_dir: &Path) -> Result<Vec<Stream>> {
+    let scan = scan_providers(ruyispec_dir)?;
+    for warning in &scan.warnings {
+        log::info!("{}", warning.message);
+    }
+    Ok(scan
+        .providers
+        .into_values()
+        .filter_map(|entry| {
+            let dir = ruyispec_dir.join(&entry.spec_key).parent()?.to_path_buf();
+            Some(Stream {
+                rpm_name: entry.rpm_name,
+                crate_name: entry.crate_name,
+                version: entry.version,
+                dir,
+            })
+        })
+        .collect())
+}
+
+/// The streams `selector` names: every stream of a crate for `<crate>`, or
+/// the one of a compat version for `<crate>@<compat>`. `-` and `_` in crate
+/// names are the same, as in package names.
+pub fn select<'a>(streams: &'a [Stream], selector: &str) -> Vec<&'a Stream> {
+    let (crate_name, compat) = match selector.split_once('@') {
+        Some((crate_name, compat)) => (crate_name, Some(compat)),
+        None => (selector, None),
+    };
+    let crate_name = crate_name.replace('_', "-");
+    streams
+        .iter()
+        .filter(|stream| stream.crate_name.replace('_', "-") == crate_name)
+        .filter(|stream| match compat {
+            Some(compat) => stream.rpm_name == format!("rust-{}-{}", crate_name, compat),
+            None => true,
+        })
+        .collect()
+}
+
+/// How `db export` writes the streams and `db import` reads them.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// An array of objects
+    #[default]
+    Json,
+    /// One line per stream after a header line
+    Csv,
+}
+
+const CSV_COLUMNS: [&str; 4] = ["rpm_name", "crate_name", "version", "directory"];
+
+/// A stream as `db export` writes it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ExportedStream {
+    pub rpm_name: String,
+    pub crate_name: String,
+    pub version: String,
+    /// Directory of the stream in the exporting tree
+    pub directory: String,
+}
+
+impl From<&Stream> for ExportedStream {
+    fn from(stream: &Stream) -> Self {
+        ExportedStream {
+            rpm_name: stream.rpm_name.clone(),
+            crate_name: stream.crate_name.clone(),
+            version: stream.version.clone(),
+            directory: stream.dir.display().to_string(),
+        }
+    }
+}
+
+/// `streams` in `format`.
+pub fn export(streams: &[Stream], format: ExportFormat) -> Result<String> {
+    let exported: Vec<ExportedStream> = streams.iter().map(ExportedStream::from).collect();
+    match format {
+        ExportFormat::Json => {
+            let mut json = serde_json::to_string_pretty(&exported)?;
+            json.push('\n');
+            Ok(json)
+        }
+        ExportFormat::Csv => {
+            let mut csv = format!("{}\n", CSV_COLUMNS.join(","));
+            for stream in &exported {
+                let fields = [
+                    &stream.rpm_name,
+                    &stream.crate_name,
+                    &stream.version,
+                    &stream.directory,
+                ];
+                let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+                csv.push_str(&fields.join(","));
+                csv.push('\n');
+            }
+            Ok(csv)
+        }
+    }
+}
+
+/// `field` quoted as CSV needs it.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// The streams of an export in `format`, see [`export`]. CSV columns are
+/// found by their header, so they may come in any order.
+pub fn import(text: &str, format: ExportFormat) -> Result<Vec<ExportedStream>> {
+    match format {
+        ExportFormat::Json => Ok(serde_json::from_str(text)?),
+        ExportFormat::Csv => {
+            let mut records = csv_records(text)?.into_iter();
+            let Some(header) = records.next() else {
+                return Ok(Vec::new());
+            };
+            let mut columns = [0; 4];
+            for (column, name) in columns.iter_mut().zip(CSV_COLUMNS) {
+                *column = header
+                    .iter()
+                    .position(|field| field == name)
+                    .ok_or_else(|| format_err!("no {} column in the CSV header", name))?;
+            }
+            records
+                .enumerate()
+                .map(|(i, record)| {
+                    if record.len() != header.len() {
+                        takopack_bail!(
+                            "CSV record {} has {} fields, the header {}",
+                            i + 1,
+                            record.len(),
+                            header.len()
+                        );
+                    }
+                    let [rpm_name, crate_name, version, directory] =
+                        columns.map(|column| record[column].clone());
+                    Ok(ExportedStream {
+                        rpm_name,
+                        crate_name,
+                        version,
+                        directory,
+                    })
+                })
+                .collect()
+        }
+    }
+}
+
+/// The records of `text`, with quoted fields unquoted.
+fn csv_records(text: &str) -> Result<Vec<Vec<String>>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') => quoted = true,
+            (false, ',') => record.push(std::mem::take(&mut field)),
+            (false, '\r') => {}
+            (false, '\n') => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            (false, c) => field.push(c),
+        }
+    }
+    if quoted {
+        takopack_bail!("unterminated quoted field in CSV");
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    Ok(records)
+}
+
+fn load(ruyispec: Option<&Path>) -> Result<Vec<Stream>> {
+    let ruyispec_dir = resolve_ruyispec_dir(ruyispec, true)?;
+    scan_streams(&ruyispec_dir)
+}
+
+fn print_streams<'a>(streams: impl IntoIterator<Item = &'a Stream>) {
+    for stream in streams {
+        println!(
+            "{}\t{}\t{}",
+            stream.rpm_name, stream.crate_name, stream.version
+        );
+    }
+}
+
+/// Run `db list`: every stream, with its crate and version.
+pub fn run_list(ruyispec: Option<&Path>) -> Result<i32> {
+    print_streams(&load(ruyispec)?);
+    Ok(0)
+}
+
+/// Run `db search`: the streams whose crate or package name contains
+/// `pattern`, ignoring case.
+pub fn run_search(pattern: &str, ruyispec: Option<&Path>) -> Result<i32> {
+    let pattern = pattern.to_lowercase();
+    let streams = load(ruyispec)?;
+    let found: Vec<&Stream> = streams
+        .iter()
+        .filter(|stream| {
+            stream.crate_name.to_lowercase().contains(&pattern)
+                || stream.rpm_name.to_lowercase().contains(&pattern)
+        })
+        .collect();
+    if found.is_empty() {
+        takopack_info!("No stream matches {:?}", pattern);
+        return Ok(1);
+    }
+    print_streams(found);
+    Ok(0)
+}
+
+/// Run `db show`: the details of the streams of a crate.
+pub fn run_show(selector: &str, ruyispec: Option<&Path>) -> Result<i32> {
+    let streams = load(ruyispec)?;
+    let found = select(&streams, selector);
+    if found.is_empty() {
+        takopack_bail!("no stream of {} in the ruyispec tree", selector);
+    }
+    for stream in found {
+        println!("{}", stream.rpm_name);
+        println!("  crate:      {}", stream.crate_name);
+        println!("  version:    {}", stream.version);
+        println!("  directory:  {}", stream.dir.display());
+        let cargo_toml = stream.dir.join("Cargo.toml");
+        println!(
+            "  Cargo.toml: {}",
+            if cargo_toml.is_file() {
+                "present"
+            } else {
+                "missing (db gc cannot follow its dependencies)"
+            }
+        );
+    }
+    Ok(0)
+}
+
+/// Run `db remove`: delete the streams `selector` names, see [`select`].
+/// A crate with several streams needs `<crate>@<compat>`.
+pub fn run_remove(selector: &str, ruyispec: Option<&Path>, dry_run: bool) -> Result<i32> {
+    let streams = load(ruyispec)?;
+    let found = select(&streams, selector);
+    match found.as_slice() {
+        [] => takopack_bail!("no stream of {} in the ruyispec tree", selector),
+        [_] => {}
+        several => takopack_bail!(
+            "{} has {} streams ({}); name one as <crate>@<compat>",
+            selector,
+            several.len(),
+            several
+                .iter()
+                .map(|stream| stream.rpm_name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+    for stream in found {
+        if dry_run {
+            println!("Would remove {}", stream.dir.display());
+            continue;
+        }
+        fs::remove_dir_all(&stream.dir)
+            .with_context(|| format!("Failed to remove {}", stream.dir.display()))?;
+        println!("Removed {}", stream.dir.display());
+    }
+    if dry_run {
+        println!("(dry-run mode — no files were removed)");
+    }
+    Ok(0)
+}
+
+/// Run `db export`: write the streams in `format` to `output`, or to
+/// stdout without one.
+pub fn run_export(
+    format: ExportFormat,
+    output: Option<&Path>,
+    ruyispec: Option<&Path>,
+) -> Result<i32> {
+    let text = export(&load(ruyispec)?, format)?;
+    match output {
+        Some(path) => {
+            fs::write(path, text).with_context(|| format!("Failed to write {}", path.display()))?;
+            takopack_info!("Exported the streams to {}", path.display());
+        }
+        None => print!("{}", text),
+    }
+    Ok(0)
+}
+
+/// The newest release of `versions` in the compat version of the stream at
+/// `version`, when it is newer. Pre-release streams, whose compat version
+/// is the whole version, have none.
+pub fn compatible_upgrade(
+    crate_name: &str,
+    version: &Version,
+    versions: Vec<IndexVersion>,
+) -> Result<Option<Version>> {
+    if !version.pre.is_empty() {
+        return Ok(None);
+    }
+    // `^` stays in the compat version: below 2 for 1.x, 0.3 for 0.3.x, and
+    // exactly 0.0.x
+    let newest = select_index_version(crate_name, versions, &format!("^{}", version))?;
+    Ok(Some(newest.version).filter(|newest| newest > version))
+}
+
+/// Run `db outdated`: list the streams a newer release of their compat
+/// version could bump, and write them to `batch` as a `batch` input file.
+pub fn run_outdated(batch: Option<&Path>, ruyispec: Option<&Path>) -> Result<i32> {
+    let streams = load(ruyispec)?;
+    let index = SparseIndex::crates_io()?;
+    let mut upgrades = Vec::new();
+    let mut failed = 0;
+    for stream in &streams {
+        let upgrade = Version::parse(&stream.version)
+            .with_context(|| format!("{} has an invalid version", stream.rpm_name))
+            .and_then(|version| {
+                let versions = index.versions(&stream.crate_name)?;
+                compatible_upgrade(&stream.crate_name, &version, versions)
+            });
+        match upgrade {
+            Ok(Some(newest)) => {
+                println!(
+                    "{}\t{} {} -> {}",
+                    stream.rpm_name, stream.crate_name, stream.version, newest
+                );
+                upgrades.push((stream.crate_name.as_str(), newest));
+            }
+            Ok(None) => {}
+            Err(e) => {
+                takopack_warn!("{}: {:#}", stream.rpm_name, e);
+                failed += 1;
+            }
+        }
+    }
+    println!(
+        "{} of {} stream(s) can be bumped",
+        upgrades.len(),
+        streams.len()
+    );
+    if let Some(path) = batch {
+        let mut list =
+            String::from("# Upgrade candidates; package with `takopack cargo batch <this file>`\n");
+        for (crate_name, version) in &upgrades {
+            list.push_str(&format!("{} {}\n", crate_name, version));
+        }
+        fs::write(path, list).with_context(|| format!("Failed to write {}", path.display()))?;
+        takopack_info!("Wrote the upgrade candidates to {}", path.display());
+    }
+    Ok(if failed > 0 { 1 } else { 0 })
+}
+
+/// Run `db import`: read an export from `file` and list its streams that
+/// the local tree lacks or has at an older version, writing them to
+/// `batch` as a `batch` input file if given. Without `format`, files
+/// ending in `.csv` are read as CSV and others as JSON.
+pub fn run_import(
+    file: &Path,
+    format: Option<ExportFormat>,
+    batch: Option<&Path>,
+    ruyispec: Option<&Path>,
+) -> Result<i32> {
+    let format = format.unwrap_or(match file.extension() {
+        Some(ext) if ext.eq_ignore_ascii_case("csv") => ExportFormat::Csv,
+        _ => ExportFormat::Json,
+    });
+    let text =
+        fs::read_to_string(file).with_context(|| format!("Failed to read {}", file.display()))?;
+    let imported =
+        import(&text, format).with_context(|| format!("Failed to import {}", file.display()))?;
+    let local = load(ruyispec)?;
+
+    let mut wanted = Vec::new();
+    for stream in &imported {
+        let newer = |version: &str| match (Version::parse(&stream.version), Version::parse(version))
+        {
+            (Ok(imported), Ok(local)) => imported > local,
+            _ => false,
+        };
+        match local.iter().find(|local| local.rpm_name == stream.rpm_name) {
+            None => println!(
+                "{}\t{} {} (missing)",
+                stream.rpm_name, stream.crate_name, stream.version
+            ),
+            Some(local) if newer(&local.version) => println!(
+                "{}\t{} {} -> {}",
+                stream.rpm_name, stream.crate_name, local.version, stream.version
+            ),
+            Some(_) => continue,
+        }
+        wanted.push(stream);
+    }
+    println!(
+        "{} of {} imported stream(s) missing or older here",
+        wanted.len(),
+        imported.len()
+    );
+    if let Some(path) = batch {
+        let mut list =
+            String::from("# Imported streams; package with `takopack cargo batch <this file>`\n");
+        for stream in &wanted {
+            list.push_str(&format!("{} {}\n", stream.crate_name, stream.version));
+        }
+        fs::write(path, list).with_context(|| format!("Failed to write {}", path.display()))?;
+        takopack_info!("Wrote the imported streams to {}", path.display());
+    }
+    Ok(0)
+}
+
+/// Run `db stats`: how many streams and crates there are, and the crates
+/// packaged in more than one compat version.
+pub fn run_stats(ruyispec: Option<&Path>) -> Result<i32> {
+    let streams = load(ruyispec)?;
+    let mut by_crate: BTreeMap<String, Vec<&Stream>> = BTreeMap::new();
+    for stream in &streams {
+        by_crate
+            .entry(stream.crate_name.replace('_', "-"))
+            .or_default()
+            .push(stream);
+    }
+    let without_cargo_toml = streams
+        .iter()
+        .filter(|stream| !stream.dir.join("Cargo.toml").is_file())
+        .count();
+
+    println!("{} stream(s) of {} crate(s)", streams.len(), by_crate.len());
+    println!("{} stream(s) without a Cargo.toml", without_cargo_toml);
+    let several: Vec<_> = by_crate
+        .iter()
+        .filter(|(_, streams)| streams.len() > 1)
+        .collect();
+    println!("Crates with several streams ({}):", several.len());
+    for (crate_name, streams) in several {
+        println!(
+            "  {}: {}",
+            crate_name,
+            streams
+                .iter()
+                .map(|stream| stream.rpm_name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compatible_upgrade, export, import, select, ExportFormat, ExportedStream};
+    use crate::crates::IndexVersion;
+    use crate::stream_gc::Stream;
+    use semver::Version;
+    use std::path::PathBuf;
+
+    #[test]
+    fn selectors_name_a_crate_or_one_stream() {
+        let stream = |rpm_name: &str, crate_name: &str, version: &str| Stream {
+            rpm_name: rpm_name.to_string(),
+            crate_name: crate_name.to_string(),
+            version: version.to_string(),
+            dir: PathBuf::from("SPECS").join(rpm_name),
+        };
+        let streams = vec![
+            stream("rust-serde-json-1", "serde_json", "1.0.140"),
+            stream("rust-base64-0.21", "base64", "0.21.7"),
+            stream("rust-base64-0.22", "base64", "0.22.1"),
+        ];
+        let names = |selector: &str| {
+            select(&streams, selector)
+                .iter()
+                .map(|stream| stream.rpm_name.clone())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(names("base64"), ["rust-base64-0.21", "rust-base64-0.22"]);
+        assert_eq!(names("base64@0.22"), ["rust-base64-0.22"]);
+        assert_eq!(names("serde-json"), ["rust-serde-json-1"]);
+        assert_eq!(names("serde_json@1"), ["rust-serde-json-1"]);
+        assert!(names("base64@1").is_empty());
+        assert!(names("base").is_empty());
+
+        let odd = [stream("rust-a-1", "a", "1.0.0")].map(|s| Stream {
+            dir: PathBuf::from("SPECS/a,\"b\""),
+            ..s
+        });
+        assert_eq!(
+            export(&odd, ExportFormat::Csv).unwrap(),
+            "rpm_name,crate_name,version,directory\nrust-a-1,a,1.0.0,\"SPECS/a,\"\"b\"\"\"\n"
+        );
+        let json: serde_json::Value =
+            serde_json::from_str(&export(&streams, ExportFormat::Json).unwrap()).unwrap();
+        assert_eq!(json[0]["crate_name"], "serde_json");
+        assert_eq!(json.as_array().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn exports_import_back() {
+        let stream = |rpm_name: &str, crate_name: &str, version: &str, dir: &str| Stream {
+            rpm_name: rpm_name.to_string(),
+            crate_name: crate_name.to_string(),
+            version: version.to_string(),
+            dir: PathBuf::from(dir),
+        };
+        let streams = vec![
+            stream(
+                "rust-serde-json-1",
+                "serde_json",
+                "1.0.140",
+                "SPECS/rust-serde-json-1",
+            ),
+            stream("rust-a-1", "a", "1.0.0", "SPECS/a,\"b\"\nc"),
+        ];
+        let expected: Vec<ExportedStream> = streams.iter().map(ExportedStream::from).collect();
+        for format in [ExportFormat::Json, ExportFormat::Csv] {
+            let text = export(&streams, format).unwrap();
+            assert_eq!(import(&text, format).unwrap(), expected, "{:?}", format);
+        }
+
+        let reordered =
+            "version,rpm_name,directory,crate_name\r\n1.0.0,rust-a-1,SPECS/rust-a-1,a\r\n";
+        assert_eq!(
+            import(reordered, ExportFormat::Csv).unwrap()[0].rpm_name,
+            "rust-a-1"
+        );
+        assert!(import("rpm_name,version\n", ExportFormat::Csv).is_err());
+        assert!(import(
+            &format!("{}\n\"open", super::CSV_COLUMNS.join(",")),
+            ExportFormat::Csv
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn upgrades_stay_in_the_compat_version() {
+        let published = |versions: &[(&str, bool)]| {
+            versions
+                .iter()
+                .map(|(version, yanked)| IndexVersion {
+                    name: "demo".to_string(),
+                    version: Version::parse(version).unwrap(),
+                    cksum: String::new(),
+                    yanked: *yanked,
+                    deps: Vec::new(),
+                })
+                .collect::<Vec<_>>()
+        };
+        let versions = published(&[
+            ("0.3.1", false),
+            ("0.3.4", false),
+            ("0.3.5", true),
+            ("0.4.0", false),
+            ("1.0.0", false),
+            ("1.2.0", false),
+            ("2.0.0", false),
+        ]);
+        let upgrade = |version: &str| {
+            compatible_upgrade("demo", &Version::parse(version).unwrap(), versions.clone())
+                .unwrap()
+                .map(|v| v.to_string())
+        };
+
+        assert_eq!(upgrade("0.3.1").as_deref(), Some("0.3.4"));
+        assert_eq!(upgrade("1.0.0").as_deref(), Some("1.2.0"));
+        assert_eq!(upgrade("1.2.0"), None);
+        assert_eq!(upgrade("2.0.0-rc.1"), None);
+    }
+}
@@ -0,0 +1,334 @@
+//! Garbage collection of packaged compat streams.
+//!
+//! Every `SPECS/rust-<crate>-<compat>/` directory of the ruyispec tree is a
+//! stream. Over time streams pile up that nothing needs anymore. A stream is
+//! kept when a stored dependency graph (a Cargo.lock, such as the ones
+//! backed up under `cargo_back/`) pins a version that falls into it, or when
+//! a kept stream depends on it through its provider Cargo.toml. Optional
+//! dependencies count, since feature subpackages require them. Everything
+//! else is reported and, unless this is a dry run, removed.
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use semver::{Version, VersionReq};
+use walkdir::WalkDir;
+
+use crate::config::resolve_ruyispec_dir;
+use crate::errors::*;
+use crate::lockfile_parser::parse_lockfile;
+use crate::stream_db::scan_streams;
+use crate::util::rust_crate_output_names;
+
+/// A packaged stream in the ruyispec tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stream {
+    /// Directory name, e.g. `rust-tokio-1`.
+    pub rpm_name: String,
+    pub crate_name: String,
+    pub version: String,
+    /// The stream's directory.
+    pub dir: PathBuf,
+}
+
+/// Outcome of a garbage collection run.
+#[derive(Debug, Default)]
+pub struct GcReport {
+    /// Number of lockfiles read.
+    pub graphs: usize,
+    pub kept: Vec<Stream>,
+    pub unreferenced: Vec<Stream>,
+    /// Kept streams whose dependencies could not be followed.
+    pub unknown_deps: Vec<String>,
+}
+
+impl fmt::Display for GcReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} stream(s) referenced from {} dependency graph(s)",
+            self.kept.len(),
+            self.graphs
+        )?;
+        if !self.unknown_deps.is_empty() {
+            writeln!(
+                f,
+                "Dependencies not followed (no provider Cargo.toml): {}",
+                self.unknown_deps.join(", ")
+            )?;
+        }
+        writeln!(f, "Unreferenced streams ({}):", self.unreferenced.len())?;
+        for stream in &self.unreferenced {
+            writeln!(
+                f,
+                "  {} ({} {})",
+                stream.rpm_name, stream.crate_name, stream.version
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Run `db gc`: collect the streams of the ruyispec tree not reachable from
+/// any lockfile under `graphs_dir`, and remove them unless `dry_run`.
+pub fn run_gc(graphs_dir: &Path, ruyispec: Option<&Path>, dry_run: bool) -> Result<i32> {
+    let ruyispec_dir = resolve_ruyispec_dir(ruyispec, true)?;
+    let streams = scan_streams(&ruyispec_dir)?;
+
+    let report = collect_garbage(streams, graphs_dir)?;
+    print!("{}", report);
+
+    if dry_run {
+        println!("(dry-run mode — no files were removed)");
+        return Ok(0);
+    }
+    let mut failed = 0;
+    for stream in &report.unreferenced {
+        log::info!("removing {}", stream.dir.display());
+        if let Err(e) = fs::remove_dir_all(&stream.dir) {
+            takopack_warn!("failed to remove {}: {}", stream.dir.display(), e);
+            failed += 1;
+        }
+    }
+    println!("Removed {} stream(s)", report.unreferenced.len() - failed);
+    Ok(if failed > 0 { 1 } else { 0 })
+}
+
+/// Split `streams` into the ones reachable from the lockfiles under
+/// `graphs_dir` and the rest.
+pub fn collect_garbage(streams: Vec<Stream>, graphs_dir: &Path) -> Result<GcReport> {
+    let lockfiles = find_lockfiles(graphs_dir)?;
+    if lockfiles.is_empty() {
+        // Without any root everything would be collected.
+        takopack_bail!("no lockfiles found under {}", graphs_dir.display());
+    }
+
+    let by_name: BTreeMap<&str, usize> = streams
+        .iter()
+        .enumerate()
+        .map(|(i, s)| (s.rpm_name.as_str(), i))
+        .collect();
+
+    let mut kept = BTreeSet::new();
+    let mut queue = VecDeque::new();
+    for lockfile in &lockfiles {
+        let graph = parse_lockfile(lockfile)?;
+        for package in graph.packages() {
+            let names = rust_crate_output_names(&package.name, &package.version);
+            if let Some(&i) = by_name.get(names.directory.as_str()) {
+                if kept.insert(i) {
+                    queue.push_back(i);
+                }
+            }
+        }
+    }
+
+    let mut unknown_deps = Vec::new();
+    while let Some(i) = queue.pop_front() {
+        let stream = &streams[i];
+        let Some(requirements) = provider_requirements(&stream.dir)? else {
+            unknown_deps.push(stream.rpm_name.clone());
+            continue;
+        };
+        for (crate_name, req) in requirements {
+            let crate_name = crate_name.replace('_', "-");
+            for (j, candidate) in streams.iter().enumerate() {
+                if candidate.crate_name.replace('_', "-") != crate_name || kept.contains(&j) {
+                    continue;
+                }
+                let matches = Version::parse(&candidate.version)
+                    .map(|v| req.matches(&v))
+                    .unwrap_or(false);
+                if matches {
+                    kept.insert(j);
+                    queue.push_back(j);
+                }
+            }
+        }
+    }
+
+    let (kept, unreferenced): (Vec<_>, Vec<_>) = streams
+        .into_iter()
+        .enumerate()
+        .partition(|(i, _)| kept.contains(i));
+    Ok(GcReport {
+        graphs: lockfiles.len(),
+        kept: kept.into_iter().map(|(_, s)| s).collect(),
+        unreferenced: unreferenced.into_iter().map(|(_, s)| s).collect(),
+        unknown_deps,
+    })
+}
+
+/// `Cargo.lock` files and backed-up `<crate>-<version>.lock` files.
+fn find_lockfiles(dir: &Path) -> Result<Vec<PathBuf>> {
+    if !dir.is_dir() {
+        takopack_bail!("graphs directory does not exist: {}", dir.display());
+    }
+    let mut lockfiles = Vec::new();
+    for entry in WalkDir::new(dir).sort_by_file_name() {
+        let entry = entry?;
+        if entry.file_type().is_file() && entry.path().extension().is_some_and(|ext| ext == "lock")
+        {
+            lockfiles.push(entry.into_path());
+        }
+    }
+    Ok(lockfiles)
+}
+
+/// Registry dependencies of the provider Cargo.toml in `stream_dir`, dev
+/// dependencies excepted. `None` when the stream has no Cargo.toml.
+fn provider_requirements(stream_dir: &Path) -> Result<Option<Vec<(String, VersionReq)>>> {
+    let path = stream_dir.join("Cargo.toml");
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let manifest: toml::Table = toml::from_str(
+        &fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?,
+    )
+    .with_context(|| format!("Failed to parse {}", path.display()))?;
+
+    let mut tables = vec![
+        manifest.get("dependencies"),
+        manifest.get("build-dependencies"),
+    ];
+    if let Some(targets) = manifest.get("target").and_then(toml::Value::as_table) {
+        for target in targets.values() {
+            tables.push(target.get("dependencies"));
+            tables.push(target.get("build-dependencies"));
+        }
+    }
+
+    let mut requirements = Vec::new();
+    for table in tables
+        .into_iter()
+        .flatten()
+        .filter_map(toml::Value::as_table)
+    {
+        for (name, dep) in table {
+            let (crate_name, req) = match dep {
+                toml::Value::String(req) => (name.as_str(), req.as_str()),
+                toml::Value::Table(dep) => {
+                    // path and git dependencies have no version to match
+                    let Some(req) = dep.get("version").and_then(toml::Value::as_str) else {
+                        continue;
+                    };
+                    let crate_name = dep
+                        .get("package")
+                        .and_then(toml::Value::as_str)
+                        .unwrap_or(name);
+                    (crate_name, req)
+                }
+                _ => continue,
+            };
+            match VersionReq::parse(req) {
+                Ok(req) => requirements.push((crate_name.to_string(), req)),
+                Err(e) => log::warn!(
+                    "{}: bad requirement {:?} for {}: {}",
+                    path.display(),
+                    req,
+                    crate_name,
+                    e
+                ),
+            }
+        }
+    }
+    Ok(Some(requirements))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{collect_garbage, Stream};
+    use std::fs;
+    use std::path::Path;
+
+    fn stream(root: &Path, rpm_name: &str, crate_name: &str, version: &str) -> Stream {
+        let dir = root.join("SPECS").join(rpm_name);
+        fs::create_dir_all(&dir).unwrap();
+        Stream {
+            rpm_name: rpm_name.to_string(),
+            crate_name: crate_name.to_string(),
+            version: version.to_string(),
+            dir,
+        }
+    }
+
+    #[test]
+    fn streams_outside_every_graph_are_collected() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let app = stream(root, "rust-app-1", "app", "1.2.0");
+        fs::write(
+            app.dir.join("Cargo.toml"),
+            r#"
+[package]
+name = "app"
+version = "1.2.0"
+
+[dependencies.log_facade]
+version = "0.2.1"
+package = "log-facade"
+optional = true
+
+[dev-dependencies]
+tester = "1"
+"#,
+        )
+        .unwrap();
+        let log_facade = stream(root, "rust-log-facade-0.2", "log-facade", "0.2.4");
+        fs::write(
+            log_facade.dir.join("Cargo.toml"),
+            "[package]\nname = \"log-facade\"\nversion = \"0.2.4\"\n",
+        )
+        .unwrap();
+        let streams = vec![
+            app,
+            stream(root, "rust-app-0.9", "app", "0.9.3"),
+            log_facade,
+            stream(root, "rust-tester-1", "tester", "1.0.0"),
+            stream(root, "rust-unused-1", "unused", "1.0.0"),
+        ];
+
+        let graphs = root.join("graphs");
+        fs::create_dir_all(graphs.join("nested")).unwrap();
+        fs::write(
+            graphs.join("nested/tool-0.1.0.lock"),
+            r#"
+version = 3
+
+[[package]]
+name = "app"
+version = "1.2.5"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+"#,
+        )
+        .unwrap();
+
+        let report = collect_garbage(streams, &graphs).unwrap();
+        assert_eq!(report.graphs, 1);
+        let names = |streams: &[Stream]| {
+            streams
+                .iter()
+                .map(|s| s.rpm_name.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(&report.kept),
+            vec!["rust-app-1", "rust-log-facade-0.2"]
+        );
+        assert_eq!(
+            names(&report.unreferenced),
+            vec!["rust-app-0.9", "rust-tester-1", "rust-unused-1"]
+        );
+        assert!(report.unknown_deps.is_empty());
+    }
+
+    #[test]
+    fn no_graphs_means_no_collection() {
+        let temp = tempfile::tempdir().unwrap();
+        let streams = vec![stream(temp.path(), "rust-a-1", "a", "1.0.0")];
+        assert!(collect_garbage(streams, temp.path()).is_err());
+    }
+}
@@ -0,0 +1,239 @@
+//! Man pages and shell completions of a crate's executables.
+//!
+//! Many CLI crates ship man pages (`man/foo.1`) and completion scripts
+//! (`completions/foo.bash`, `_foo`, `foo.fish`), or generate them in a build
+//! script with clap_mangen and clap_complete. With `install_manpages` and
+//! `install_completions` in `[packages.bin]`, the spec installs them next to
+//! the executables: shipped files from the crate, generated ones from the
+//! `OUT_DIR` of the build script under the names clap gives them.
+
+use std::path::Path;
+
+use cargo::core::dependency::DepKind;
+use walkdir::WalkDir;
+
+use crate::config::{Config, PackageKey};
+use crate::crates::CrateInfo;
+use crate::takopack::spec::SpecAsset;
+
+const BASH_COMPLETIONS: &str = "%{_datadir}/bash-completion/completions";
+const ZSH_COMPLETIONS: &str = "%{_datadir}/zsh/site-functions";
+const FISH_COMPLETIONS: &str = "%{_datadir}/fish/vendor_completions.d";
+
+/// Directories that hold no man pages or completions, or too many files to
+/// look through.
+const SKIPPED_DIRS: &[&str] = &["src", "tests", "benches", "examples", "target", "vendor"];
+
+/// The man pages and completions of `bins` to install, as configured for
+/// the `bin` package.
+pub fn detect(crate_info: &CrateInfo, config: &Config, bins: &[&str]) -> Vec<SpecAsset> {
+    if bins.is_empty() {
+        return Vec::new();
+    }
+    let root = crate_info.manifest_path().parent().unwrap_or(Path::new(""));
+    let shipped = shipped_assets(root);
+    let build_deps: Vec<&str> = crate_info
+        .dependencies()
+        .iter()
+        .filter(|dep| dep.kind() == DepKind::Build)
+        .map(|dep| dep.package_name().as_str())
+        .collect();
+
+    let mut manpages: Vec<SpecAsset> = shipped.iter().filter(|a| a.man_page).cloned().collect();
+    if manpages.is_empty() && build_deps.contains(&"clap_mangen") {
+        manpages = bins.iter().map(|bin| generated_manpage(bin)).collect();
+    }
+    let mut completions: Vec<SpecAsset> = shipped.iter().filter(|a| !a.man_page).cloned().collect();
+    if completions.is_empty() && build_deps.contains(&"clap_complete") {
+        completions = bins
+            .iter()
+            .flat_map(|bin| generated_completions(bin))
+            .collect();
+    }
+
+    let mut assets = Vec::new();
+    for (found, enabled, what, setting) in [
+        (
+            manpages,
+            config.package_install_manpages(PackageKey::Bin),
+            "man pages",
+            "install_manpages",
+        ),
+        (
+            completions,
+            config.package_install_completions(PackageKey::Bin),
+            "shell completions",
+            "install_completions",
+        ),
+    ] {
+        match enabled {
+            _ if found.is_empty() => {}
+            Some(true) => assets.extend(found),
+            Some(false) => {}
+            None => takopack_info!(
+                "{} ships {}; set {} = true in [packages.bin] to install them.",
+                crate_info.crate_name(),
+                what,
+                setting
+            ),
+        }
+    }
+    assets
+}
+
+/// Man pages and completion scripts in the crate's own files.
+fn shipped_assets(root: &Path) -> Vec<SpecAsset> {
+    let mut assets = Vec::new();
+    let walker = WalkDir::new(root)
+        .max_depth(4)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| {
+            entry.depth() != 1
+                || !entry.file_type().is_dir()
+                || !entry
+                    .file_name()
+                    .to_str()
+                    .is_some_and(|name| name.starts_with('.') || SKIPPED_DIRS.contains(&name))
+        });
+    for entry in walker.filter_map(|entry| entry.ok()) {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let Ok(relative) = entry.path().strip_prefix(root) else {
+            continue;
+        };
+        if let Some(asset) = shipped_asset(relative) {
+            assets.push(asset);
+        }
+    }
+    assets
+}
+
+/// What `relative` is installed as, if it is a man page or a completion
+/// script.
+fn shipped_asset(relative: &Path) -> Option<SpecAsset> {
+    let source = relative.to_str()?;
+    let file_name = relative.file_name()?.to_str()?;
+    let dirs: Vec<&str> = relative
+        .parent()?
+        .iter()
+        .filter_map(|dir| dir.to_str())
+        .collect();
+    let (stem, extension) = file_name.rsplit_once('.').unwrap_or((file_name, ""));
+
+    let in_doc_dir = dirs
+        .iter()
+        .any(|dir| dir.starts_with("man") || dir.starts_with("doc"));
+    let section = extension.parse::<u8>().ok().filter(|s| (1..=9).contains(s));
+    if let Some(section) = section.filter(|_| dirs.is_empty() || in_doc_dir) {
+        return Some(SpecAsset {
+            source: source.to_string(),
+            destination: format!("%{{_mandir}}/man{}/{}", section, file_name),
+            man_page: true,
+        });
+    }
+
+    if !dirs.iter().any(|dir| dir.contains("complet")) {
+        return None;
+    }
+    let destination = match extension {
+        "bash" | "bash-completion" => format!("{}/{}", BASH_COMPLETIONS, stem),
+        "zsh" => format!("{}/_{}", ZSH_COMPLETIONS, stem),
+        "fish" => format!("{}/{}", FISH_COMPLETIONS, file_name),
+        "" if file_name.starts_with('_') => format!("{}/{}", ZSH_COMPLETIONS, file_name),
+        _ => return None,
+    };
+    Some(SpecAsset {
+        source: source.to_string(),
+        destination,
+        man_page: false,
+    })
+}
+
+/// `file` as written by the crate's build script.
+fn out_dir_file(file: &str) -> String {
+    format!(
+        "\"$(find target/release/build -path '*/out/{}' | head -n 1)\"",
+        file
+    )
+}
+
+fn generated_manpage(bin: &str) -> SpecAsset {
+    SpecAsset {
+        source: out_dir_file(&format!("{}.1", bin)),
+        destination: format!("%{{_mandir}}/man1/{}.1", bin),
+        man_page: true,
+    }
+}
+
+fn generated_completions(bin: &str) -> Vec<SpecAsset> {
+    [
+        (
+            format!("{}.bash", bin),
+            format!("{}/{}", BASH_COMPLETIONS, bin),
+        ),
+        (format!("_{}", bin), format!("{}/_{}", ZSH_COMPLETIONS, bin)),
+        (
+            format!("{}.fish", bin),
+            format!("{}/{}.fish", FISH_COMPLETIONS, bin),
+        ),
+    ]
+    .into_iter()
+    .map(|(file, destination)| SpecAsset {
+        source: out_dir_file(&file),
+        destination,
+        man_page: false,
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generated_completions, shipped_assets};
+    use std::fs;
+
+    #[test]
+    fn man_pages_and_completions_are_found_in_the_crate() {
+        let dir = tempfile::tempdir().unwrap();
+        for file in [
+            "man/foo.1",
+            "doc/foo.conf.5",
+            "completions/foo.bash",
+            "completions/_foo",
+            "completions/foo.fish",
+            "src/bar.1",
+            "assets/logo.1",
+            "README.md",
+        ] {
+            let path = dir.path().join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+
+        let found: Vec<_> = shipped_assets(dir.path())
+            .into_iter()
+            .map(|asset| format!("{} -> {}", asset.source, asset.destination))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                "completions/_foo -> %{_datadir}/zsh/site-functions/_foo",
+                "completions/foo.bash -> %{_datadir}/bash-completion/completions/foo",
+                "completions/foo.fish -> %{_datadir}/fish/vendor_completions.d/foo.fish",
+                "doc/foo.conf.5 -> %{_mandir}/man5/foo.conf.5",
+                "man/foo.1 -> %{_mandir}/man1/foo.1",
+            ]
+        );
+
+        let generated = generated_completions("foo");
+        assert_eq!(
+            generated[0].source,
+            "\"$(find target/release/build -path '*/out/foo.bash' | head -n 1)\""
+        );
+        assert_eq!(
+            generated[1].destination,
+            "%{_datadir}/zsh/site-functions/_foo"
+        );
+    }
+}
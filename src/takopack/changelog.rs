@@ -0,0 +1,235 @@
+//! `%changelog` entries built from a crate's release history, for
+//! `--changelog-from`.
+//!
+//! Without a source (or when the history cannot be fetched) the spec keeps
+//! `%autochangelog`.
+
+use std::fmt;
+
+use anyhow::Context;
+use chrono::{DateTime, NaiveDate};
+use semver::Version;
+use serde_derive::Deserialize;
+
+use crate::crates::CrateInfo;
+use crate::errors::*;
+use crate::http::CRATES_IO_API;
+use crate::takopack::spec::ChangelogEntry;
+
+/// Where `%changelog` entries come from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ChangelogSource {
+    /// Publish dates of the crate's releases on crates.io.
+    Releases,
+    /// Version tags of the crate's repository.
+    Git,
+    /// Only `%autochangelog`.
+    #[default]
+    None,
+}
+
+impl fmt::Display for ChangelogSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChangelogSource::Releases => write!(f, "releases"),
+            ChangelogSource::Git => write!(f, "git"),
+            ChangelogSource::None => write!(f, "none"),
+        }
+    }
+}
+
+/// A released version and the day it was published.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Release {
+    pub(crate) version: Version,
+    pub(crate) date: NaiveDate,
+}
+
+/// Changelog entries for `crate_info` up to its own version, newest first.
+/// Failures are reported and leave the changelog empty.
+pub(crate) fn changelog_entries(
+    source: ChangelogSource,
+    crate_info: &CrateInfo,
+    author: &str,
+) -> Vec<ChangelogEntry> {
+    let crate_name = crate_info.crate_name();
+    let releases = match source {
+        ChangelogSource::None => return vec![],
+        ChangelogSource::Releases => fetch_releases(crate_name),
+        ChangelogSource::Git => match crate_info.metadata().repository.as_deref() {
+            Some(url) => git_tag_releases(crate_name, url),
+            None => Err(anyhow::anyhow!("the crate has no repository URL")),
+        },
+    };
+    match releases {
+        Ok(releases) => entries_up_to(releases, crate_info.version(), author),
+        Err(e) => {
+            takopack_warn!(
+                "Could not build %changelog from {} for {}: {:#}; using %autochangelog",
+                source,
+                crate_name,
+                e
+            );
+            vec![]
+        }
+    }
+}
+
+/// Entries for the releases not newer than `current`, newest first.
+pub(crate) fn entries_up_to(
+    mut releases: Vec<Release>,
+    current: &Version,
+    author: &str,
+) -> Vec<ChangelogEntry> {
+    releases.retain(|r| r.version.cmp_precedence(current).is_le());
+    releases.sort_by(|a, b| b.version.cmp_precedence(&a.version));
+    releases.dedup_by(|a, b| a.version.cmp_precedence(&b.version).is_eq());
+    releases
+        .into_iter()
+        .map(|r| ChangelogEntry {
+            date: r.date,
+            author: author.to_string(),
+            version: rpm_version(&r.version),
+            text: format!("Update to version {}", r.version),
+        })
+        .collect()
+}
+
+/// `1.0.0-rc.1` sorts before `1.0.0` in rpm only as `1.0.0~rc.1`.
+fn rpm_version(version: &Version) -> String {
+    let mut rpm = format!("{}.{}.{}", version.major, version.minor, version.patch);
+    if !version.pre.is_empty() {
+        rpm.push('~');
+        rpm.push_str(&version.pre.as_str().replace('-', "_"));
+    }
+    rpm
+}
+
+#[derive(Deserialize)]
+struct VersionsResponse {
+    versions: Vec<ApiVersion>,
+}
+
+#[derive(Deserialize)]
+struct ApiVersion {
+    num: String,
+    created_at: String,
+    #[serde(default)]
+    yanked: bool,
+}
+
+fn fetch_releases(crate_name: &str) -> Result<Vec<Release>> {
+    let url = format!("{}/crates/{}/versions", CRATES_IO_API, crate_name);
+    let body = crate::http::agent()
+        .get(&url)
+        .set(
+            "User-Agent",
+            concat!("takopack/", env!("CARGO_PKG_VERSION")),
+        )
+        .timeout(std::time::Duration::from_secs(30))
+        .call()
+        .with_context(|| format!("Failed to fetch versions of {}", crate_name))?
+        .into_string()
+        .with_context(|| format!("Failed to read versions of {}", crate_name))?;
+    parse_releases(&body).with_context(|| format!("Failed to parse versions of {}", crate_name))
+}
+
+/// Releases in a crates.io `versions` response; yanked ones are left out.
+pub(crate) fn parse_releases(body: &str) -> Result<Vec<Release>> {
+    let response: VersionsResponse = serde_json::from_str(body)?;
+    Ok(response
+        .versions
+        .into_iter()
+        .filter(|v| !v.yanked)
+        .filter_map(|v| {
+            Some(Release {
+                version: Version::parse(&v.num).ok()?,
+                date: DateTime::parse_from_rfc3339(&v.created_at)
+                    .ok()?
+                    .date_naive(),
+            })
+        })
+        .collect())
+}
+
+/// Releases from the version tags of the repository at `url`. The tag date
+/// is the tagger date of annotated tags and the commit date otherwise.
+fn git_tag_releases(crate_name: &str, url: &str) -> Result<Vec<Release>> {
+    let dir = tempfile::tempdir().context("Failed to create temporary clone directory")?;
+    let repo = git2::build::RepoBuilder::new()
+        .bare(true)
+        .clone(url, dir.path())
+        .with_context(|| format!("Failed to clone {}", url))?;
+
+    let mut releases = Vec::new();
+    for tag in repo.tag_names(None)?.iter().flatten() {
+        let Some(version) = version_from_tag(crate_name, tag) else {
+            continue;
+        };
+        let object = repo.revparse_single(&format!("refs/tags/{}", tag))?;
+        let seconds = match object.as_tag().and_then(|t| t.tagger()) {
+            Some(tagger) => tagger.when().seconds(),
+            None => object.peel_to_commit()?.time().seconds(),
+        };
+        if let Some(date) = DateTime::from_timestamp(seconds, 0) {
+            releases.push(Release {
+                version,
+                date: date.date_naive(),
+            });
+        }
+    }
+    Ok(releases)
+}
+
+/// The version named by `tag`: `1.2.3`, `v1.2.3`, `<crate>-1.2.3` or
+/// `<crate>-v1.2.3`. Tags of other crates in the same repository don't parse.
+pub(crate) fn version_from_tag(crate_name: &str, tag: &str) -> Option<Version> {
+    let tag = tag
+        .strip_prefix(crate_name)
+        .and_then(|t| t.strip_prefix(['-', '@', '_']))
+        .unwrap_or(tag);
+    let tag = tag.strip_prefix('v').unwrap_or(tag);
+    Version::parse(tag).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{entries_up_to, parse_releases, version_from_tag};
+    use semver::Version;
+
+    #[test]
+    fn releases_are_read_from_crates_io_versions() {
+        let body = r#"{"versions": [
+            {"num": "1.1.0", "created_at": "2024-03-02T10:00:00.123456+00:00", "yanked": false},
+            {"num": "1.0.1", "created_at": "2024-01-15T08:00:00+00:00", "yanked": true},
+            {"num": "1.0.0", "created_at": "2023-12-31T23:30:00+00:00", "yanked": false},
+            {"num": "1.0.0-rc.1", "created_at": "2023-12-01T00:00:00+00:00"}
+        ]}"#;
+        let releases = parse_releases(body).unwrap();
+        assert_eq!(releases.len(), 3);
+
+        let entries = entries_up_to(
+            releases,
+            &Version::parse("1.0.0").unwrap(),
+            "Packager <p@example.com>",
+        );
+        let versions: Vec<&str> = entries.iter().map(|e| e.version.as_str()).collect();
+        assert_eq!(versions, vec!["1.0.0", "1.0.0~rc.1"]);
+        assert_eq!(entries[0].date.to_string(), "2023-12-31");
+        assert_eq!(entries[1].text, "Update to version 1.0.0-rc.1");
+    }
+
+    #[test]
+    fn versions_are_recognized_in_common_tag_styles() {
+        let v = |s: &str| Some(Version::parse(s).unwrap());
+        assert_eq!(version_from_tag("demo", "1.2.3"), v("1.2.3"));
+        assert_eq!(version_from_tag("demo", "v1.2.3"), v("1.2.3"));
+        assert_eq!(
+            version_from_tag("demo", "demo-v0.4.0-beta.2"),
+            v("0.4.0-beta.2")
+        );
+        assert_eq!(version_from_tag("demo", "demo@1.0.0"), v("1.0.0"));
+        assert_eq!(version_from_tag("demo", "demo-macros-v1.0.0"), None);
+        assert_eq!(version_from_tag("demo", "nightly"), None);
+    }
+}
@@ -1,16 +1,30 @@
 use std::collections::HashMap;
-use std::fmt::{self, Write};
+use std::fmt;
 
-use cargo::{core::Dependency, util::OptVersionReq};
+use anyhow::Context;
+use cargo::core::Dependency;
 use semver::Version;
 use textwrap::fill;
 
-use crate::config::{self, Config, PackageKey};
-use crate::crates::dependency_is_runtime_candidate;
+use crate::config::{
+    self, Config, MacroFlavor, PackageKey, RequiresStyle, TestBuildRequires, TestStyle,
+};
+use crate::crates::{
+    dependency_is_runtime_candidate, dependency_target_arches, is_known_linux_arch,
+    known_linux_arches, IndexVersion,
+};
 use crate::errors::*;
+use crate::takopack::dependency::{
+    self, cargo_dep_crate_name, lower_bound_from_opt_version_req, VersionBounds,
+};
 use crate::takopack::spec::{
-    self, CrateCapability, CrateRequirement, RequirementVersion, SpecPackage, SpecSource,
+    self, CapabilityVersion, CheckRun, CrateCapability, CrateRequirement, RequirementVersion,
+    SpecAsset, SpecCLibrary, SpecPackage, SpecSource,
 };
+use crate::test_mode::TestMode;
+
+/// Longest `Summary:` distros accept without complaint.
+pub const SUMMARY_MAX_LEN: usize = 80;
 
 #[derive(Default, Debug)]
 pub struct BuildDeps {
@@ -31,8 +45,22 @@ pub struct Source {
     homepage: String,
     crate_name: String,
     license: String,
-    sha256: Option<String>, // SHA256 hash of the downloaded crate file
+    sha256: Option<String>,     // SHA256 hash of the downloaded crate file
+    source_url: Option<String>, // Source: URL when not downloaded from crates.io
     with_spdx: bool,
+    release: String,
+    macros_package: String,
+    macro_flavor: MacroFlavor,
+    tests: TestStyle,
+    test_build_requires: Vec<String>,
+    build_dependency_requires: Vec<String>,
+    edition: Option<String>,
+    rust_version: Option<String>,
+    yanked: bool,
+    bins: Vec<String>,
+    c_library: Option<SpecCLibrary>,
+    bin_assets: Vec<SpecAsset>,
+    rpm_name_suffix: Option<String>,
 }
 
 pub struct Package {
@@ -45,17 +73,25 @@ pub struct Package {
     crate_requires: Vec<CrateRequirement>, // Structured external crate requirements from Cargo.toml
     recommends: Vec<String>,
     suggests: Vec<String>,
+    // Feature subpackages the base package recommends or suggests in the spec
+    recommended_features: Vec<String>,
+    suggested_features: Vec<String>,
+    weak_deps: bool,
     provides: Vec<String>,
     feature_provides: Vec<String>, // Structured Cargo feature aliases provided by this package
     breaks: Vec<String>,
     replaces: Vec<String>,
     conflicts: Vec<String>,
+    feature_conflicts: Vec<String>, // Features of the same crate this package cannot be installed with
     summary: Description,
     description: Description,
     extra_lines: Vec<String>,
     feature: Option<String>, // Original feature name, None for base package
     crate_name: Option<String>, // Original crate name for proper feature extraction
     all_features: Vec<String>, // All features available in Cargo.toml (only for base package)
+    crate_namespace: Option<String>, // Namespace of crate() capabilities, None for the default
+    requires_style: RequiresStyle,
+    test_mode: TestMode,
 }
 
 pub struct Description {
@@ -91,102 +127,33 @@ impl CrateDep {
         }
     }
 
-    pub fn to_crate_format(&self) -> String {
-        spec::render_crate_requirement(&self.to_crate_requirement())
+    pub fn to_crate_format(&self, namespace: &str) -> Result<String> {
+        Ok(spec::render_crate_requirement(
+            namespace,
+            &self.to_crate_requirement()?,
+        ))
     }
 
-    fn to_crate_requirement(&self) -> CrateRequirement {
-        let crate_name = self.crate_name_with_compat();
-        let requirement = if crate_name == "%{pkgname}" {
-            RequirementVersion::Exact("%{version}".to_string())
-        } else if let Some(version) = self.cleaned_version_requirement() {
-            RequirementVersion::Range(version)
-        } else {
-            RequirementVersion::None
-        };
-
-        CrateRequirement {
-            crate_name,
-            feature: self.feature.clone(),
-            requirement,
+    fn to_crate_requirement(&self) -> Result<CrateRequirement> {
+        if self.crate_name == "%{pkgname}" && self.version.is_none() {
+            return Ok(CrateRequirement {
+                crate_name: self.crate_name.clone(),
+                feature: self.feature.clone(),
+                requirement: RequirementVersion::Exact("%{version}".to_string()),
+                target_arches: None,
+                arches: Vec::new(),
+                weak: false,
+            });
         }
-    }
-
-    fn crate_name_with_compat(&self) -> String {
-        let crate_base = self.crate_name.replace('_', "-");
-        // Extract compatibility version from version constraint
-        // E.g., ">= 0.6.2" -> "0.6", ">= 2.2.1" -> "2", ">= 1.13" -> "1"
-        // For prerelease: ">= 0.26.0-beta.1" -> "0.26.0-beta.1" (full version with - separator)
-        // log::debug!("before version_num: {} {:?}", crate_base, &self.version);
-
-        if let Some(version_str) = &self.version {
-            // the option deps won't appear in here.
-            // println!("Version crate_name string: {} {}", self.crate_name, version_str);
-            // Clean version string first: remove wildcards and other invalid RPM chars
-            // "0.4.*" -> "0.4.0", ">= 0.4.*" -> ">= 0.4.0"
-            let cleaned_version_str = version_str.replace(".*", ".0").replace('*', "0");
-
-            // Extract version number from constraint (e.g., ">= 0.6.2" -> "0.6.2", ">= 1.13" -> "1.13")
-            let version_num = cleaned_version_str
-                .trim()
-                .trim_start_matches(">=")
-                .trim_start_matches("=")
-                .trim_start_matches(">")
-                .trim_start_matches("<")
-                .trim();
-            // log::debug!("after version_num: {} {}", crate_base, version_num);
-            // TODO: there the version_num maybe the full version like "0.7.5+spec-1.1.0" and "0.26.0-beta.1"
-            // But it depends on how the author writes the dependencies in Cargo.toml
-            // Remove build metadata (+xxx) for version string
-            // "0.7.5+spec-1.1.0" -> "0.7.5", "1.0.1+wasi-0.2.4" -> "1.0.1"
-            let version_without_build = version_num.split('+').next().unwrap_or(version_num);
-            // Check if version has prerelease (AFTER removing build metadata)
-            // Build metadata should not affect the crate name - only prerelease should
-            if version_without_build.contains('-') {
-                // For prerelease versions, use full version
-                format!("{}-{}", crate_base, version_without_build)
-            } else {
-                // For regular versions (including those with build metadata), use compatibility version
-                // Normalize version_num: if only major.minor (like "1.4"), add .0 for patch
-                let version_num = if version_without_build.split('.').count() == 2 {
-                    format!("{}.0", version_without_build)
-                } else {
-                    version_without_build.to_string()
-                };
-                if let Ok(ver) = Version::parse(&version_num) {
-                    let compat_version = crate::util::calculate_compat_version(&ver);
-                    format!("{}-{}", crate_base, compat_version)
-                } else {
-                    // Legacy fallback only: structured Cargo requirements use
-                    // cargo_dep_crate_name instead. If this old path sees a
-                    // shape it cannot parse, keep an unversioned capability
-                    // rather than aborting spec rendering.
-                    log::warn!(
-                        "failed to parse legacy crate dependency version '{}' for crate '{}'",
-                        version_num,
-                        crate_base
-                    );
-                    crate_base
-                }
-            }
-        } else {
-            crate_base
-        }
-    }
-
-    fn cleaned_version_requirement(&self) -> Option<String> {
-        self.version.as_ref().map(|version| {
-            // Clean version string for output: remove wildcards, build metadata, and other invalid RPM chars
-            // "0.4.*" -> "0.4.0", ">= 0.4.*" -> ">= 0.4.0"
-            // "0.7.5+spec-1.1.0" -> "0.7.5"
-            version
-                .replace(".*", ".0")
-                .replace('*', "0")
-                .split('+')
-                .next()
-                .unwrap_or(version)
-                .to_string()
-        })
+        let bounds = match self.version.as_deref() {
+            Some(version) => VersionBounds::parse(&self.crate_name, version)?,
+            None => VersionBounds::default(),
+        };
+        Ok(dependency::crate_requirement(
+            &self.crate_name,
+            self.feature.clone(),
+            &bounds,
+        ))
     }
 }
 
@@ -207,8 +174,9 @@ pub struct PkgTest {
     architecture: Vec<String>,
 }
 
-impl fmt::Display for Source {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+impl Source {
+    /// The data model of the spec header and `BuildRequires:`.
+    pub fn spec_source(&self) -> SpecSource {
         // Package name uses hyphens instead of underscores
         let pkg_name = self.crate_name.replace('_', "-");
 
@@ -227,26 +195,23 @@ impl fmt::Display for Source {
                 format!("rust-{}-{}", pkg_name, compat_version),
             )
         };
-
-        // For RPM Version field, strip prerelease suffix (RPM doesn't allow '-' in Version)
-        // e.g., "0.26.0-beta.1" -> "0.26.0"
-        let rpm_version = if let Ok(ver) = Version::parse(&self.version) {
-            if !ver.pre.is_empty() {
-                // Strip prerelease part
-                format!("{}.{}.{}", ver.major, ver.minor, ver.patch)
-            } else {
-                self.version.clone()
-            }
-        } else {
-            self.version.clone()
+        // The capabilities keep the crate's own name
+        let rpm_name = match &self.rpm_name_suffix {
+            Some(suffix) => format!("{}{}", rpm_name, suffix),
+            None => rpm_name,
         };
 
-        let source = SpecSource {
+        let rpm_version = Version::parse(&self.version)
+            .map(|ver| crate::version::rpm_version(&ver))
+            .unwrap_or_else(|_| self.version.clone());
+
+        SpecSource {
             crate_name: self.crate_name.clone(),
             full_version: self.full_version.clone(),
             pkgname,
             rpm_name,
             rpm_version,
+            release: self.release.clone(),
             summary: format!("Rust crate \"{}\"", self.crate_name),
             license: if !self.license.is_empty() {
                 self.license.clone()
@@ -259,12 +224,30 @@ impl fmt::Display for Source {
                 "FIXME".to_string()
             },
             // Use full version (including build metadata) in Source URL.
-            source_url: "https://static.crates.io/crates/%{crate_name}/%{full_version}/download#/%{name}-%{version}.tar.gz".to_string(),
+            source_url: self.source_url.clone().unwrap_or_else(|| {
+                "https://static.crates.io/crates/%{crate_name}/%{full_version}/download#/%{name}-%{version}.tar.gz".to_string()
+            }),
             sha256: self.sha256.clone(),
-            build_requires: vec!["rust-rpm-macros".to_string()],
+            build_requires: std::iter::once(self.macros_package.clone())
+                .chain(self.build_dependency_requires.iter().cloned())
+                .collect(),
+            test_build_requires: self.test_build_requires.clone(),
+            edition: self.edition.clone(),
+            rust_version: self.rust_version.clone(),
+            yanked: self.yanked,
+            bins: self.bins.clone(),
+            c_library: self.c_library.clone(),
+            bin_assets: self.bin_assets.clone(),
             with_spdx: self.with_spdx,
-        };
+            macro_flavor: self.macro_flavor,
+            tests: self.tests,
+        }
+    }
+}
 
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let source = self.spec_source();
         spec::render_header_section(f, &source)?;
         spec::render_source_requirements_section(f, &source)?;
         Ok(())
@@ -318,17 +301,21 @@ fn clean_package_name(pkg_name: &str) -> String {
     cleaned_parts.join("-")
 }
 
-fn crate_requirements_from_cargo_deps(
+/// The `crate()` requirements on the runtime dependencies in `deps` of
+/// `current_crate_name`, one per enabled feature.
+pub fn crate_requirements_from_cargo_deps(
     deps: &[Dependency],
     current_crate_name: &str,
+    include_dev_dependencies: bool,
 ) -> Vec<CrateRequirement> {
     use cargo::core::dependency::DepKind;
 
-    let mut requirements = std::collections::BTreeMap::new();
+    let mut requirements: std::collections::BTreeMap<String, (CrateRequirement, VersionBounds)> =
+        std::collections::BTreeMap::new();
     let current_crate_base = spec::normalize_crate_name(current_crate_name);
 
     for dep in deps {
-        if !dependency_is_runtime_candidate(dep, false) {
+        if !dependency_is_runtime_candidate(dep, include_dev_dependencies) {
             continue;
         }
 
@@ -340,13 +327,10 @@ fn crate_requirements_from_cargo_deps(
         // Optional dependencies are already selected by the feature graph before
         // they reach this helper, so the optional flag is intentionally not a filter.
         let _is_optional = dep.is_optional();
-        let lower_bound = lower_bound_from_opt_version_req(dep.version_req());
-        let crate_name = cargo_dep_crate_name(dep.package_name().as_str(), lower_bound.as_deref());
-        let requirement = lower_bound
-            .map(|version| RequirementVersion::Range(format!(">= {}", version)))
-            // A wildcard dependency such as "*" has no meaningful lower bound.
-            // Keep the crate requirement unversioned rather than inventing one.
-            .unwrap_or(RequirementVersion::None);
+        // A wildcard dependency such as "*" has no meaningful lower bound;
+        // its crate requirement stays unversioned rather than inventing one.
+        let bounds = VersionBounds::of(dep.package_name().as_str(), dep.version_req());
+        let target_arches = dependency_target_arches(dep);
 
         let mut features = std::collections::BTreeSet::new();
         if dep.kind() == DepKind::Build && !dep.is_optional() {
@@ -367,114 +351,143 @@ fn crate_requirements_from_cargo_deps(
         }
 
         for feature in features {
-            let requirement = CrateRequirement {
-                crate_name: crate_name.clone(),
-                feature,
-                requirement: requirement.clone(),
-            };
-            requirements.insert(crate_requirement_key(&requirement), requirement);
+            let mut requirement =
+                dependency::crate_requirement(dep.package_name().as_str(), feature, &bounds);
+            requirement.target_arches = target_arches.clone();
+            let key = crate_requirement_key(&requirement);
+            let mut merged_bounds = bounds.clone();
+            // The same dependency for several targets is needed on all of
+            // them, with the tighter of their bounds
+            if let Some((existing, existing_bounds)) = requirements.get(&key) {
+                merged_bounds = tighter_bounds(existing_bounds, &bounds);
+                let target_arches = match (&existing.target_arches, requirement.target_arches) {
+                    (Some(existing), Some(mut arches)) => {
+                        arches.retain(|arch| !existing.contains(arch));
+                        Some(existing.iter().cloned().chain(arches).collect())
+                    }
+                    _ => None,
+                };
+                requirement = dependency::crate_requirement(
+                    dep.package_name().as_str(),
+                    requirement.feature,
+                    &merged_bounds,
+                );
+                requirement.target_arches = target_arches;
+            }
+            requirements.insert(key, (requirement, merged_bounds));
         }
     }
 
-    requirements.into_values().collect()
+    requirements
+        .into_values()
+        .map(|(requirement, _)| requirement)
+        .collect()
 }
 
-fn cargo_dep_crate_name(crate_name: &str, lower_bound: Option<&str>) -> String {
-    let crate_base = spec::normalize_crate_name(crate_name);
-
-    if let Some(version) = lower_bound {
-        if version.contains('-') {
-            format!("{}-{}", crate_base, version)
-        } else if let Ok(version) = Version::parse(version) {
-            format!(
-                "{}-{}",
-                crate_base,
-                crate::util::calculate_compat_version(&version)
-            )
-        } else {
-            crate_base
-        }
-    } else {
-        crate_base
+/// The bounds satisfying both `a` and `b`: the higher lower bound and the
+/// lower upper bound. Bounds that are not versions keep those of `a`.
+fn tighter_bounds(a: &VersionBounds, b: &VersionBounds) -> VersionBounds {
+    let pick = |a: &Option<String>, b: &Option<String>, higher: bool| match (a, b) {
+        (Some(x), Some(y)) => match (Version::parse(x), Version::parse(y)) {
+            (Ok(vx), Ok(vy)) if (vy > vx) == higher && vy != vx => Some(y.clone()),
+            _ => Some(x.clone()),
+        },
+        (Some(x), None) | (None, Some(x)) => Some(x.clone()),
+        (None, None) => None,
+    };
+    VersionBounds {
+        lower: pick(&a.lower, &b.lower, true),
+        upper: pick(&a.upper, &b.upper, false),
     }
 }
 
-fn lower_bound_from_opt_version_req(version_req: &OptVersionReq) -> Option<String> {
-    match version_req {
-        OptVersionReq::Any => None,
-        OptVersionReq::Req(req) if req.to_string() == "*" => None,
-        OptVersionReq::Req(req) => req
-            .comparators
+/// Requirements on the dev-dependencies `dev_deps` of `current_crate_name`
+/// that `settings` lets through, for `BuildRequires:` of test builds.
+pub fn test_build_requirements(
+    dev_deps: &[Dependency],
+    current_crate_name: &str,
+    settings: &TestBuildRequires,
+) -> Vec<CrateRequirement> {
+    let excluded = |dep: &&Dependency| {
+        let name = spec::normalize_crate_name(dep.package_name().as_str());
+        settings
+            .exclude
             .iter()
-            .filter_map(lower_bound_from_comparator)
-            .max_by(compare_version_strings),
-        OptVersionReq::Locked(version, _) | OptVersionReq::Precise(version, _) => {
-            Some(version_without_build_metadata(version))
-        }
+            .any(|exclude| spec::normalize_crate_name(exclude) == name)
+    };
+    let mut included: Vec<Dependency> = dev_deps
+        .iter()
+        .filter(|dep| !excluded(dep))
+        .cloned()
+        .collect();
+    if let Some(max) = settings.max.filter(|max| included.len() > *max) {
+        let left_out: Vec<String> = included
+            .drain(max..)
+            .map(|dep| dep.package_name().to_string())
+            .collect();
+        takopack_warn!(
+            "{}: leaving dev-dependencies out of BuildRequires (test_build_requires.max = {}): {}",
+            current_crate_name,
+            max,
+            left_out.join(", ")
+        );
     }
+    crate_requirements_from_cargo_deps(&included, current_crate_name, true)
 }
 
-fn lower_bound_from_comparator(comparator: &semver::Comparator) -> Option<String> {
-    use semver::Op;
-
-    match comparator.op {
-        Op::Exact | Op::GreaterEq | Op::Tilde | Op::Caret => {
-            Some(comparator_lower_bound(comparator))
+/// For [`config::RequiresFloor::Oldest`]: the oldest non-yanked release of
+/// each runtime dependency in `deps` that satisfies its requirement and
+/// stays in the compat stream of the requirement's lower bound, keyed by
+/// the crate name of its requirement (`foo-1`). `versions` lists the
+/// published versions of a crate; dependencies it cannot answer for, and
+/// pre-release requirements, keep the requirement's own floor.
+pub fn oldest_requires_floors(
+    deps: &[Dependency],
+    versions: impl Fn(&str) -> Result<Vec<IndexVersion>>,
+) -> HashMap<String, Version> {
+    let mut floors: HashMap<String, Version> = HashMap::new();
+    for dep in deps {
+        if !dependency_is_runtime_candidate(dep, false) || !dep.source_id().is_crates_io() {
+            continue;
         }
-        Op::Greater => Some(comparator_strict_lower_bound(comparator)),
-        Op::Wildcard if comparator.minor.is_some() || comparator.patch.is_some() => {
-            Some(comparator_lower_bound(comparator))
+        let Some(lower_bound) = lower_bound_from_opt_version_req(dep.version_req()) else {
+            continue;
+        };
+        let Ok(lower) = Version::parse(&lower_bound) else {
+            continue;
+        };
+        if !lower.pre.is_empty() {
+            continue;
+        }
+        let name = dep.package_name();
+        let published = match versions(name.as_str()) {
+            Ok(published) => published,
+            Err(e) => {
+                takopack_warn!(
+                    "could not list the versions of {}, keeping its requirement's floor: {:#}",
+                    name,
+                    e
+                );
+                continue;
+            }
+        };
+        let compat = crate::version::compat_version(&lower);
+        let oldest = published
+            .into_iter()
+            .filter(|v| !v.yanked && v.version.pre.is_empty())
+            .map(|v| v.version)
+            .filter(|v| dep.version_req().matches(v) && crate::version::compat_version(v) == compat)
+            .min();
+        if let Some(oldest) = oldest {
+            // Several requirements on one stream (e.g. per target) need the highest floor
+            let key = cargo_dep_crate_name(name.as_str(), Some(&lower_bound));
+            let floor = floors.entry(key).or_insert_with(|| oldest.clone());
+            if oldest > *floor {
+                *floor = oldest;
+            }
         }
-        Op::Wildcard | Op::Less | Op::LessEq => None,
-        _ => None,
-    }
-}
-
-fn comparator_lower_bound(comparator: &semver::Comparator) -> String {
-    let mut version = format!(
-        "{}.{}.{}",
-        comparator.major,
-        comparator.minor.unwrap_or(0),
-        comparator.patch.unwrap_or(0)
-    );
-    if !comparator.pre.is_empty() {
-        version.push('-');
-        version.push_str(comparator.pre.as_str());
-    }
-    version
-}
-
-fn comparator_strict_lower_bound(comparator: &semver::Comparator) -> String {
-    if !comparator.pre.is_empty() {
-        // TODO: model strict prerelease bounds more precisely when RPM crate
-        // requirements grow beyond simple lower bounds.
-        return comparator_lower_bound(comparator);
-    }
-
-    match (comparator.minor, comparator.patch) {
-        (Some(minor), Some(patch)) => format!("{}.{}.{}", comparator.major, minor, patch + 1),
-        (Some(minor), None) => format!("{}.{}.0", comparator.major, minor + 1),
-        (None, None) => format!("{}.0.0", comparator.major + 1),
-        (None, Some(patch)) => format!("{}.0.{}", comparator.major, patch + 1),
-    }
-}
-
-fn version_without_build_metadata(version: &Version) -> String {
-    if !version.pre.is_empty() {
-        format!(
-            "{}.{}.{}-{}",
-            version.major, version.minor, version.patch, version.pre
-        )
-    } else {
-        format!("{}.{}.{}", version.major, version.minor, version.patch)
-    }
-}
-
-fn compare_version_strings(a: &String, b: &String) -> std::cmp::Ordering {
-    match (Version::parse(a), Version::parse(b)) {
-        (Ok(a), Ok(b)) => a.cmp(&b),
-        _ => a.cmp(b),
     }
+    floors
 }
 
 #[cfg(test)]
@@ -550,34 +563,81 @@ fn parse_package_name_simple(pkg_name: &str) -> Option<CrateDep> {
     Some(CrateDep::new(crate_name, feature))
 }
 
-impl fmt::Display for Package {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let spec_package = SpecPackage {
+impl Package {
+    /// The data model of the `%package` section.
+    pub fn spec_package(&self) -> Result<SpecPackage> {
+        let requires = self
+            .spec_requires()
+            .with_context(|| format!("Failed to write the requirements of {}", self.name))?;
+        Ok(SpecPackage {
             feature: self.feature.clone(),
+            binary: None,
             summary: format!("{}", self.summary),
             description: format!("{}", self.description),
-            requires: self.spec_requires(),
+            requires,
             provides: self.spec_provides(),
             obsoletes: self.spec_obsoletes(),
             conflicts: self.spec_conflicts(),
+            recommends: self.spec_weak_deps(&self.recommended_features),
+            suggests: self.spec_weak_deps(&self.suggested_features),
             extra_lines: self.extra_lines.clone(),
-        };
+            crate_namespace: self.crate_namespace.clone(),
+            requires_style: self.requires_style,
+            test_mode: self.test_mode,
+        })
+    }
 
-        if self.feature.is_some() {
-            spec::render_feature_package_section(f, &spec_package)
-        } else {
-            spec::render_main_package_section(f, &spec_package)
+    /// The data model of the `%package` section of the crate's executables,
+    /// see [`Self::new_bin`]. Being built, they need no crates at runtime;
+    /// the configured `depends` and `provides` are plain packages.
+    pub fn spec_binary_package(&self) -> SpecPackage {
+        let extra_lines = self
+            .depends
+            .iter()
+            .map(|depend| format!("Requires:       {}", depend))
+            .chain(
+                self.provides
+                    .iter()
+                    .map(|provide| format!("Provides:       {}", provide)),
+            )
+            .chain(self.extra_lines.iter().cloned())
+            .collect();
+        SpecPackage {
+            binary: Some(self.name.clone()),
+            summary: format!("{}", self.summary),
+            description: format!("{}", self.description),
+            obsoletes: self.spec_obsoletes(),
+            conflicts: self.spec_conflicts(),
+            recommends: self.recommends.clone(),
+            suggests: self.suggests.clone(),
+            extra_lines,
+            crate_namespace: self.crate_namespace.clone(),
+            requires_style: self.requires_style,
+            test_mode: self.test_mode,
+            ..SpecPackage::default()
         }
     }
 }
 
+/// Whether `requirement` is on `crate_name`, in any compat stream:
+/// `crate(foo-1)` and `crate(foo)` are, `crate(foo-bar-1)` is not.
+fn requires_crate(requirement: &CrateRequirement, crate_name: &str) -> bool {
+    let crate_name = spec::normalize_crate_name(crate_name);
+    match requirement.crate_name.strip_prefix(&crate_name) {
+        Some("") => true,
+        Some(rest) => rest.strip_prefix('-').is_some_and(|compat| {
+            Version::parse(compat).is_ok() || compat.split('.').all(|n| n.parse::<u64>().is_ok())
+        }),
+        None => false,
+    }
+}
+
 fn crate_requirement_key(requirement: &CrateRequirement) -> String {
-    let rendered = spec::render_crate_requirement(requirement);
-    rendered
-        .split(' ')
-        .next()
-        .unwrap_or(rendered.as_str())
-        .to_string()
+    let capability = CrateRequirement {
+        requirement: RequirementVersion::None,
+        ..requirement.clone()
+    };
+    spec::render_crate_requirement(spec::DEFAULT_CRATE_NAMESPACE, &capability)
 }
 
 fn insert_crate_requirement(
@@ -592,8 +652,10 @@ fn insert_crate_requirement(
             dep_map.insert(key, requirement);
         }
         Some(existing) => {
-            let existing_len = spec::render_crate_requirement(existing).len();
-            let new_len = spec::render_crate_requirement(&requirement).len();
+            let existing_len =
+                spec::render_crate_requirement(spec::DEFAULT_CRATE_NAMESPACE, existing).len();
+            let new_len =
+                spec::render_crate_requirement(spec::DEFAULT_CRATE_NAMESPACE, &requirement).len();
             if new_len > existing_len {
                 dep_map.insert(key, requirement);
             }
@@ -652,15 +714,6 @@ impl fmt::Display for PkgTest {
 }
 
 impl Source {
-    pub fn pkg_prefix() -> &'static str {
-        if config::testing_ruzt() {
-            // avoid accidentally installing official packages during tests
-            "ruzt"
-        } else {
-            "rust"
-        }
-    }
-
     #[allow(clippy::too_many_arguments)]
     pub fn new(
         basename: &str,
@@ -674,6 +727,7 @@ impl Source {
         build_deps: BuildDeps,
         full_version: String,   // Full version including build metadata
         sha256: Option<String>, // SHA256 hash of downloaded crate file
+        test_mode: TestMode,
     ) -> Result<Source> {
         let pkgbase = match name_suffix {
             None => basename.to_string(),
@@ -699,7 +753,7 @@ impl Source {
             String::new()
         };
         Ok(Source {
-            name: dsc_name(&pkgbase),
+            name: dsc_name(&pkgbase, test_mode),
             version: version.to_string(),
             full_version,
             section: section.to_string(),
@@ -711,7 +765,21 @@ impl Source {
             crate_name: crate_name.to_string(),
             license: license.to_string(),
             sha256,
+            source_url: None,
             with_spdx: false,
+            release: "%autorelease".to_string(),
+            macros_package: "rust-rpm-macros".to_string(),
+            macro_flavor: MacroFlavor::default(),
+            tests: TestStyle::default(),
+            test_build_requires: vec![],
+            build_dependency_requires: vec![],
+            edition: None,
+            rust_version: None,
+            yanked: false,
+            bins: vec![],
+            c_library: None,
+            bin_assets: vec![],
+            rpm_name_suffix: None,
         })
     }
 
@@ -719,6 +787,56 @@ impl Source {
         &self.name
     }
 
+    /// Download from `url` instead of static.crates.io.
+    pub fn set_source_url(&mut self, url: &str) {
+        self.source_url = Some(url.to_string());
+    }
+
+    /// Capabilities the tests need at build time, e.g.
+    /// `crate(proptest-1/default) >= 1.4.0`.
+    pub fn set_test_build_requires(&mut self, requires: Vec<String>) {
+        self.test_build_requires = requires;
+    }
+
+    /// Capabilities the build script needs, e.g.
+    /// `crate(cc/default) >= 1.0.83`, when the build-dependencies are not
+    /// required of the packages.
+    pub fn set_build_dependency_requires(&mut self, requires: Vec<String>) {
+        self.build_dependency_requires = requires;
+    }
+
+    /// The crate's `edition` and `rust-version` (MSRV).
+    pub fn set_toolchain(&mut self, edition: String, rust_version: Option<String>) {
+        self.edition = Some(edition);
+        self.rust_version = rust_version;
+    }
+
+    /// Mark the version as yanked on crates.io, see [`crate::yanked`].
+    pub fn set_yanked(&mut self, yanked: bool) {
+        self.yanked = yanked;
+    }
+
+    /// Executables the spec builds and installs into `%{_bindir}`.
+    pub fn set_binaries(&mut self, bins: &[&str]) {
+        self.bins = bins.iter().map(|bin| bin.to_string()).collect();
+    }
+
+    /// The C API library the spec builds and installs into `%{_libdir}`,
+    /// see [`crate::takopack::targets`].
+    pub fn set_c_library(&mut self, c_library: Option<SpecCLibrary>) {
+        self.c_library = c_library;
+    }
+
+    pub fn c_library(&self) -> Option<&SpecCLibrary> {
+        self.c_library.as_ref()
+    }
+
+    /// Man pages and completions installed with the executables, see
+    /// [`crate::takopack::bin_assets`].
+    pub fn set_bin_assets(&mut self, bin_assets: Vec<SpecAsset>) {
+        self.bin_assets = bin_assets;
+    }
+
     pub fn apply_overrides(&mut self, config: &Config, with_spdx: bool) {
         if let Some(section) = config.section() {
             self.section = section.to_string();
@@ -778,20 +896,16 @@ impl Source {
         }
 
         self.with_spdx = with_spdx;
+        self.release = config.release().to_string();
+        self.macros_package = config.macros_package().to_string();
+        self.macro_flavor = config.macro_flavor;
+        self.tests = config.tests;
+        self.rpm_name_suffix = config.rpm_name_suffix.clone();
     }
 }
 
 impl Package {
-    pub fn pkg_prefix() -> &'static str {
-        if config::testing_ruzt() {
-            // avoid accidentally installing official packages during tests
-            "ruzt"
-        } else {
-            "rust"
-        }
-    }
-
-    fn spec_requires(&self) -> Vec<CrateRequirement> {
+    fn spec_requires(&self) -> Result<Vec<CrateRequirement>> {
         // Deduplicate by the crate(...) key, preferring versioned requirements.
         let mut dep_map: std::collections::BTreeMap<String, CrateRequirement> =
             std::collections::BTreeMap::new();
@@ -801,11 +915,11 @@ impl Package {
         }
 
         for dep in &self.crate_deps {
-            let requirement = dep.to_crate_requirement();
+            let requirement = dep.to_crate_requirement()?;
             insert_crate_requirement(&mut dep_map, requirement);
         }
 
-        dep_map.into_values().collect()
+        Ok(dep_map.into_values().collect())
     }
 
     fn spec_provides(&self) -> Vec<CrateCapability> {
@@ -842,6 +956,23 @@ impl Package {
         capabilities
     }
 
+    /// The feature subpackages for `features`, if weak dependencies are
+    /// enabled.
+    fn spec_weak_deps(&self, features: &[String]) -> Vec<String> {
+        if !self.weak_deps {
+            return vec![];
+        }
+        features
+            .iter()
+            .map(|feature| {
+                format!(
+                    "%{{name}}+{} = %{{version}}-%{{release}}",
+                    spec::normalize_feature_name(feature)
+                )
+            })
+            .collect()
+    }
+
     fn spec_obsoletes(&self) -> Vec<String> {
         self.replaces
             .iter()
@@ -853,15 +984,156 @@ impl Package {
     }
 
     fn spec_conflicts(&self) -> Vec<String> {
+        let namespace = self
+            .crate_namespace
+            .as_deref()
+            .unwrap_or(spec::DEFAULT_CRATE_NAMESPACE);
         self.breaks
             .iter()
             .map(|brk| {
                 let cleaned = brk.split('(').next().unwrap_or(brk).trim();
                 clean_package_name(cleaned)
             })
+            .chain(self.feature_conflicts.iter().map(|feature| {
+                spec::render_crate_capability(
+                    namespace,
+                    &CrateCapability {
+                        crate_name: "%{pkgname}".to_string(),
+                        feature: Some(feature.clone()),
+                        version: CapabilityVersion::None,
+                    },
+                )
+            }))
             .collect()
     }
 
+    /// Conflict with whatever provides a feature that shares one of the
+    /// exclusive `groups` with a feature of this package.
+    pub fn apply_exclusive_features(&mut self, groups: &[Vec<String>]) {
+        let provided: std::collections::BTreeSet<String> = self
+            .spec_provides()
+            .into_iter()
+            .filter_map(|capability| capability.feature)
+            .collect();
+        for group in groups {
+            let group: Vec<String> = group
+                .iter()
+                .map(|feature| spec::normalize_feature_name(feature))
+                .collect();
+            if group.iter().any(|feature| provided.contains(feature)) {
+                self.feature_conflicts.extend(
+                    group
+                        .into_iter()
+                        .filter(|feature| !provided.contains(feature)),
+                );
+            }
+        }
+        self.feature_conflicts.sort();
+        self.feature_conflicts.dedup();
+    }
+
+    /// Replace the floor of versioned crate requirements with the ones in
+    /// `floors`, see [`oldest_requires_floors`].
+    pub fn apply_requires_floors(&mut self, floors: &HashMap<String, Version>) {
+        for requirement in &mut self.crate_requires {
+            let Some(floor) = floors.get(&requirement.crate_name) else {
+                continue;
+            };
+            match &requirement.requirement {
+                RequirementVersion::Range(range) if range.starts_with(">= ") => {
+                    requirement.requirement = RequirementVersion::Range(format!(">= {}", floor));
+                }
+                // The floor matches the requirement, so it is below the upper bound
+                RequirementVersion::Between(_, upper) => {
+                    requirement.requirement =
+                        RequirementVersion::Between(floor.to_string(), upper.clone());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Leave out, or limit to some architectures with `%ifarch`, the
+    /// requirements on target-specific dependencies, see
+    /// [`config::TargetDeps`]. `build_arches` empty means every known one.
+    pub fn apply_target_deps(&mut self, target_deps: config::TargetDeps, build_arches: &[String]) {
+        if target_deps == config::TargetDeps::Keep {
+            return;
+        }
+        let build_arches = if build_arches.is_empty() {
+            known_linux_arches()
+        } else {
+            build_arches.to_vec()
+        };
+        self.crate_requires.retain_mut(|requirement| {
+            let Some(target_arches) = &requirement.target_arches else {
+                return true;
+            };
+            let arches: Vec<String> = build_arches
+                .iter()
+                .filter(|arch| !is_known_linux_arch(arch) || target_arches.contains(arch))
+                .cloned()
+                .collect();
+            if arches.is_empty() {
+                return false;
+            }
+            if target_deps == config::TargetDeps::Arch && arches.len() < build_arches.len() {
+                requirement.arches = arches;
+            }
+            true
+        });
+    }
+
+    /// Require the features that the features of this package enable
+    /// weakly (`bar?/baz`), see [`crate::crates::weak_dependency_features`].
+    /// They only apply when the dependency is installed anyway, unless the
+    /// package requires it itself.
+    pub fn apply_weak_dependency_features(&mut self, deps: &[Dependency]) {
+        let crate_name = self.crate_name.clone().unwrap_or_default();
+        for mut requirement in crate_requirements_from_cargo_deps(deps, &crate_name, false) {
+            requirement.weak = !self
+                .crate_requires
+                .iter()
+                .any(|required| required.crate_name == requirement.crate_name);
+            self.crate_requires.push(requirement);
+        }
+    }
+
+    /// Require what `[patched_crates]` maps the patched dependencies to, see
+    /// [`config::PatchedCrate`], instead of their crates.io releases.
+    pub fn apply_patched_crates(&mut self, patched: &HashMap<String, config::PatchedCrate>) {
+        let mut packages = Vec::new();
+        self.crate_requires.retain_mut(|requirement| {
+            let Some(target) = patched
+                .iter()
+                .find(|(name, _)| requires_crate(requirement, name))
+                .map(|(_, target)| target)
+            else {
+                return true;
+            };
+            if let Some(package) = &target.package {
+                packages.push(package.clone());
+                return false;
+            }
+            // Left alone while `path` has not been read by localpkg
+            if let Some(crate_name) = &target.crate_name {
+                requirement.crate_name =
+                    cargo_dep_crate_name(crate_name, target.version.as_deref());
+                requirement.requirement = match &target.version {
+                    Some(version) => RequirementVersion::Range(format!(">= {}", version)),
+                    None => RequirementVersion::None,
+                };
+            }
+            true
+        });
+        for package in packages {
+            let line = format!("Requires:       {}", package);
+            if !self.extra_lines.contains(&line) {
+                self.extra_lines.push(line);
+            }
+        }
+    }
+
     /// Apply lockfile dependencies
     pub fn apply_lockfile_deps(&mut self, lockfile_deps: &HashMap<String, semver::Version>) {
         for dep in &mut self.crate_deps {
@@ -871,22 +1143,7 @@ impl Package {
                 .or_else(|| lockfile_deps.get(&name_dash))
             {
                 // some optionnal deps won't appear into the lockfile,like bytemuck in bitflags of alacrittty
-                // println!("Applying lockfile version for {}: {}", dep.crate_name, ver);
-                // Version handling:
-                // - Regular versions (no prerelease, no build): use as-is for later compat calculation
-                // - Prerelease versions (with -): use full version
-                // - Build metadata versions (with +): use full version including build metadata
-                let version_str = if !ver.build.is_empty() {
-                    // Has build metadata: include it (e.g., "0.9.11+spec-1.1.0")
-                    format!("{}.{}.{}+{}", ver.major, ver.minor, ver.patch, ver.build)
-                } else if !ver.pre.is_empty() {
-                    // Has prerelease: include it (e.g., "0.26.0-beta.1")
-                    format!("{}.{}.{}-{}", ver.major, ver.minor, ver.patch, ver.pre)
-                } else {
-                    // Regular version (e.g., "1.0.228")
-                    format!("{}.{}.{}", ver.major, ver.minor, ver.patch)
-                };
-                dep.version = Some(format!(">= {}", version_str));
+                dep.version = Some(format!(">= {}", crate::version::upstream_version(ver)));
             }
         }
     }
@@ -906,6 +1163,7 @@ impl Package {
         f_recommends: Vec<&str>,
         f_suggests: Vec<&str>,
         all_features: Vec<String>, // All features from Cargo.toml (only used for base package)
+        test_mode: TestMode,
     ) -> Result<Package> {
         // for d in &o_deps {
         //     println!("dep: {}", d);
@@ -916,8 +1174,8 @@ impl Package {
             Some(suf) => format!("{}{}", basename, suf),
         };
         let deb_feature2 = &|p: &str, f: &str| match f {
-            "" => deb_name(p),
-            _ => deb_feature_name(p, f),
+            "" => deb_name(p, test_mode),
+            _ => deb_feature_name(p, f, test_mode),
         };
         let deb_feature = &|f: &str| deb_feature2(&pkgbase, f);
 
@@ -927,6 +1185,16 @@ impl Package {
                 .map(deb_feature)
                 .collect()
         };
+        let weak_features = &|x: &[&str]| -> Vec<String> {
+            x.iter()
+                .filter(|f| !f_provides.contains(f))
+                .map(|f| f.to_string())
+                .collect()
+        };
+        let (recommended_features, suggested_features) = match feature {
+            Some(_) => (vec![], vec![]),
+            None => (weak_features(&f_recommends), weak_features(&f_suggests)),
+        };
         let (recommends, suggests) = match feature {
             Some(_) => (vec![], vec![]),
             None => (filter_provides(f_recommends), filter_provides(f_suggests)),
@@ -980,7 +1248,7 @@ impl Package {
         for o_dep in o_deps.iter() {
             depends.push(o_dep.clone());
         }
-        let crate_requires = crate_requirements_from_cargo_deps(&ori_deps, basename);
+        let crate_requires = crate_requirements_from_cargo_deps(&ori_deps, basename, false);
         let mut breaks = vec![];
         let mut replaces = vec![];
         if name_suffix.is_some() && feature.is_none() {
@@ -989,15 +1257,23 @@ impl Package {
             // non-suffixed package at the same time
             let mut next_version = version.clone();
             next_version.patch += 1;
-            breaks.push(format!("{} (<< {}~)", deb_name(basename), next_version));
-            replaces.push(format!("{} (<< {}~)", deb_name(basename), next_version));
+            breaks.push(format!(
+                "{} (<< {}~)",
+                deb_name(basename, test_mode),
+                next_version
+            ));
+            replaces.push(format!(
+                "{} (<< {}~)",
+                deb_name(basename, test_mode),
+                next_version
+            ));
         }
         let conflicts = vec![];
 
         Ok(Package {
             name: match feature {
-                None => deb_name(&pkgbase),
-                Some(f) => deb_feature_name(&pkgbase, f),
+                None => deb_name(&pkgbase, test_mode),
+                Some(f) => deb_feature_name(&pkgbase, f, test_mode),
             },
             arch: "any".to_string(),
             // This is the best but not ideal option for us.
@@ -1027,6 +1303,9 @@ impl Package {
             crate_requires,
             recommends,
             suggests,
+            recommended_features,
+            suggested_features,
+            weak_deps: false,
             provides,
             feature_provides: f_provides
                 .iter()
@@ -1035,12 +1314,16 @@ impl Package {
             breaks,
             replaces,
             conflicts,
+            feature_conflicts: vec![],
             summary,
             description,
             extra_lines: vec![],
             feature: feature.map(|s| s.to_string()),
             crate_name: Some(basename.to_string()),
             all_features,
+            crate_namespace: None,
+            requires_style: RequiresStyle::default(),
+            test_mode,
         })
     }
 
@@ -1051,42 +1334,41 @@ impl Package {
         summary: Description,
         description: Description,
     ) -> Self {
-        let (name, mut provides) = match name_suffix {
+        let (name, provides) = match name_suffix {
             None => (basename.to_string(), vec![]),
             Some(suf) => (
                 format!("{}{}", basename, suf),
-                vec![format!("{} (= ${{binary:Version}})", basename)],
+                vec![format!("{} = %{{version}}-%{{release}}", basename)],
             ),
         };
-        provides.push("${cargo:Provides}".to_string());
         Package {
             name,
             arch: "any".to_string(),
             multi_arch: None,
             section: section.map(|s| s.to_string()),
-            depends: vec![
-                "${misc:Depends}".to_string(),
-                "${shlibs:Depends}".to_string(),
-                "${cargo:Depends}".to_string(),
-            ],
+            depends: vec![],
             crate_deps: vec![],
             crate_requires: vec![],
-            recommends: vec!["${cargo:Recommends}".to_string()],
-            suggests: vec!["${cargo:Suggests}".to_string()],
+            recommends: vec![],
+            suggests: vec![],
+            recommended_features: vec![],
+            suggested_features: vec![],
+            weak_deps: false,
             provides,
             feature_provides: vec![],
             breaks: vec![],
             replaces: vec![],
             conflicts: vec![],
+            feature_conflicts: vec![],
             summary,
             description,
-            extra_lines: vec![
-                "Built-Using: ${cargo:Built-Using}".to_string(),
-                "Static-Built-Using: ${cargo:Static-Built-Using}".to_string(),
-            ],
+            extra_lines: vec![],
             feature: None,
             crate_name: None,
             all_features: vec![],
+            crate_namespace: None,
+            requires_style: RequiresStyle::default(),
+            test_mode: TestMode::default(),
         }
     }
 
@@ -1101,17 +1383,24 @@ impl Package {
             crate_requires: Default::default(),
             recommends: Default::default(),
             suggests: Default::default(),
+            recommended_features: Default::default(),
+            suggested_features: Default::default(),
+            weak_deps: Default::default(),
             provides: Default::default(),
             feature_provides: Default::default(),
             breaks: Default::default(),
             replaces: Default::default(),
             conflicts: Default::default(),
+            feature_conflicts: Default::default(),
             summary: Description::new(Default::default(), Default::default()),
             description: Description::new(Default::default(), Default::default()),
             extra_lines: Default::default(),
             feature: None,
             crate_name: None,
             all_features: vec![],
+            crate_namespace: None,
+            requires_style: RequiresStyle::default(),
+            test_mode: TestMode::default(),
         }
     }
 
@@ -1138,7 +1427,7 @@ impl Package {
 
     #[allow(clippy::result_unit_err)]
     pub fn summary_check_len(&self) -> std::result::Result<(), ()> {
-        if self.summary.prefix.len() <= 80 {
+        if self.summary.prefix.chars().count() <= SUMMARY_MAX_LEN {
             Ok(())
         } else {
             Err(())
@@ -1202,6 +1491,12 @@ impl Package {
         if let Some(multi_arch) = config.package_multi_arch(key) {
             self.multi_arch = Some(multi_arch.to_owned());
         }
+        self.crate_namespace = config.crate_namespace.clone();
+        self.requires_style = config.requires_style;
+        self.test_mode = config.test_mode;
+        self.apply_target_deps(config.package_target_deps(key), &config.build_arches);
+        self.apply_patched_crates(&config.patched_crates);
+        self.weak_deps = config.weak_deps;
     }
 }
 
@@ -1244,36 +1539,52 @@ impl PkgTest {
             architecture: architecture.iter().map(|x| x.to_string()).collect(),
         })
     }
+
+    /// This test as a `%cargo_test` run of the spec's `%check`.
+    pub fn check_run(&self) -> CheckRun {
+        let mut args = Vec::new();
+        let mut test_args = self.extra_test_args.iter();
+        while let Some(arg) = test_args.next() {
+            match arg.as_str() {
+                "--no-default-features" => args.push("-n".to_string()),
+                "--all-features" => args.push("-a".to_string()),
+                "--features" => {
+                    args.push("-f".to_string());
+                    args.extend(test_args.next().cloned());
+                }
+                other => args.push(other.to_string()),
+            }
+        }
+        CheckRun {
+            args,
+            broken: self.extra_restricts.iter().any(|r| r == "flaky"),
+            architectures: self.architecture.clone(),
+        }
+    }
 }
 
-/// Translates a semver into a takopack-format upstream version.
-/// Omits the build metadata, and uses a ~ before the prerelease version so it
-/// compares earlier than the subsequent release.
+/// Translates a semver into a takopack-format upstream version, see
+/// [`crate::version::upstream_version`].
 pub fn deb_upstream_version(v: &Version) -> String {
-    let mut s = format!("{}.{}.{}", v.major, v.minor, v.patch);
-    if !v.pre.is_empty() {
-        // Use '-' instead of '~' for prerelease versions in RPM spec
-        write!(s, "-{}", v.pre.as_str()).unwrap();
-    }
-    s
+    crate::version::upstream_version(v)
 }
 
 pub fn base_deb_name(crate_name: &str) -> String {
     crate_name.replace('_', "-").to_lowercase()
 }
 
-pub fn dsc_name(name: &str) -> String {
-    format!("{}-{}", Source::pkg_prefix(), base_deb_name(name))
+pub fn dsc_name(name: &str, test_mode: TestMode) -> String {
+    format!("{}-{}", test_mode.pkg_prefix(), base_deb_name(name))
 }
 
-pub fn deb_name(name: &str) -> String {
-    format!("{}-{}", Package::pkg_prefix(), base_deb_name(name))
+pub fn deb_name(name: &str, test_mode: TestMode) -> String {
+    format!("{}-{}", test_mode.pkg_prefix(), base_deb_name(name))
 }
 
-pub fn deb_feature_name(name: &str, feature: &str) -> String {
+pub fn deb_feature_name(name: &str, feature: &str, test_mode: TestMode) -> String {
     format!(
         "{}-{}-{}",
-        Package::pkg_prefix(),
+        test_mode.pkg_prefix(),
         base_deb_name(name),
         base_deb_name(feature)
     )
@@ -1282,13 +1593,18 @@ pub fn deb_feature_name(name: &str, feature: &str) -> String {
 #[cfg(test)]
 mod tests {
     use super::{
-        crate_requirements_from_cargo_deps, parse_package_name_simple, BuildDeps, CrateDep, Source,
+        crate_requirements_from_cargo_deps, deb_upstream_version, oldest_requires_floors,
+        parse_package_name_simple, test_build_requirements, BuildDeps, CrateDep, Description,
+        Package, PkgTest, Source,
     };
-    use crate::crates::{all_dependencies_and_features, transitive_deps};
+    use crate::config::{PatchedCrate, TargetDeps, TestBuildRequires};
+    use crate::crates::{all_dependencies_and_features, transitive_deps, IndexVersion};
     use crate::takopack::spec;
+    use crate::test_mode::TestMode;
     use cargo::core::{dependency::DepKind, Dependency, EitherManifest, SourceId};
     use cargo::util::toml::read_manifest;
     use cargo::GlobalContext;
+    use std::collections::HashMap;
     use std::fs;
 
     fn test_dep(
@@ -1317,6 +1633,18 @@ mod tests {
         }
     }
 
+    /// The spec section of `package`.
+    fn render(package: &Package) -> String {
+        let spec_package = package.spec_package().unwrap();
+        let mut rendered = String::new();
+        if spec_package.feature.is_some() {
+            spec::render_feature_package_section(&mut rendered, &spec_package).unwrap();
+        } else {
+            spec::render_main_package_section(&mut rendered, &spec_package).unwrap();
+        }
+        rendered
+    }
+
     fn rendered_cargo_requirements(deps: &[Dependency]) -> Vec<String> {
         rendered_cargo_requirements_for_crate(deps, "current_crate")
     }
@@ -1325,9 +1653,11 @@ mod tests {
         deps: &[Dependency],
         current_crate_name: &str,
     ) -> Vec<String> {
-        crate_requirements_from_cargo_deps(deps, current_crate_name)
+        crate_requirements_from_cargo_deps(deps, current_crate_name, false)
             .into_iter()
-            .map(|requirement| spec::render_crate_requires(&requirement))
+            .map(|requirement| {
+                spec::render_crate_requires(spec::DEFAULT_CRATE_NAMESPACE, &requirement)
+            })
             .collect()
     }
 
@@ -1352,6 +1682,7 @@ mod tests {
             BuildDeps::default(),
             "4.6.1".to_string(),
             None,
+            TestMode::default(),
         )
         .unwrap();
         let rendered = source.to_string();
@@ -1360,6 +1691,72 @@ mod tests {
         assert!(rendered.contains("Name:           rust-clap-4"));
     }
 
+    #[test]
+    fn build_metadata_and_prereleases_render_consistently() {
+        let header = |version: &semver::Version| {
+            let source = Source::new(
+                "toml_datetime",
+                &deb_upstream_version(version),
+                None,
+                "toml_datetime",
+                "",
+                "",
+                "MIT",
+                true,
+                BuildDeps::default(),
+                version.to_string(),
+                None,
+                TestMode::default(),
+            )
+            .unwrap()
+            .spec_source();
+            (source.pkgname, source.rpm_version, source.full_version)
+        };
+        assert_eq!(
+            header(&"0.7.5+spec-1.1.0".parse().unwrap()),
+            (
+                "toml-datetime-0.7".to_string(),
+                "0.7.5".to_string(),
+                "0.7.5+spec-1.1.0".to_string()
+            )
+        );
+        assert_eq!(
+            header(&"0.26.0-beta.1".parse().unwrap()),
+            (
+                "toml-datetime-0.26.0-beta.1".to_string(),
+                "0.26.0".to_string(),
+                "0.26.0-beta.1".to_string()
+            )
+        );
+
+        let required = |version: &str| {
+            CrateDep::new_with_version("foo".to_string(), None, Some(version.to_string()))
+                .to_crate_format("crate")
+                .unwrap()
+        };
+        assert_eq!(required(">= 0.7.5+spec-1.1.0"), "crate(foo-0.7) >= 0.7.5");
+        assert_eq!(
+            required(">= 0.26.0-beta.1"),
+            "crate(foo-0.26.0-beta.1) >= 0.26.0-beta.1"
+        );
+        assert_eq!(
+            required(">= 1.0.0-rc.1+abc"),
+            "crate(foo-1.0.0-rc.1) >= 1.0.0-rc.1"
+        );
+    }
+
+    #[test]
+    fn malformed_requirements_are_errors_naming_the_crate() {
+        let error = CrateDep::new_with_version(
+            "foo".to_string(),
+            Some("std".to_string()),
+            Some(">= one.two".to_string()),
+        )
+        .to_crate_format("crate")
+        .unwrap_err();
+        assert!(format!("{:#}", error).contains("of crate 'foo'"));
+    }
+
     #[test]
     fn cargo_dependency_default_features_require_default_capability() {
         let dep = test_dep("base64", "0.22.1", true, &[]);
@@ -1436,6 +1833,65 @@ mod tests {
         );
     }
 
+    #[test]
+    fn oldest_floors_stay_in_the_requirement_stream() {
+        let crates_io = SourceId::crates_io(&GlobalContext::default().unwrap()).unwrap();
+        let dep = |name: &str, req: &str| Dependency::parse(name, Some(req), crates_io).unwrap();
+        let published = |versions: &[(&str, bool)]| {
+            versions
+                .iter()
+                .map(|(version, yanked)| IndexVersion {
+                    name: String::new(),
+                    version: version.parse().unwrap(),
+                    cksum: String::new(),
+                    yanked: *yanked,
+                    deps: vec![],
+                })
+                .collect()
+        };
+        let floors = oldest_requires_floors(
+            &[
+                dep("serde", "1.0.100"),
+                dep("log", ">=0.4.5"),
+                dep("rand", "0.8"),
+            ],
+            |name| match name {
+                "serde" => Ok(published(&[
+                    ("1.0.99", false),
+                    ("1.0.100", true),
+                    ("1.0.101", false),
+                    ("1.0.200", false),
+                    ("2.0.0", false),
+                ])),
+                "log" => Ok(published(&[("0.4.0", false), ("0.5.0", false)])),
+                _ => Err(crate::errors::format_err!("index unreachable")),
+            },
+        );
+        assert_eq!(floors.len(), 1);
+        assert_eq!(floors["serde-1"].to_string(), "1.0.101");
+
+        let mut package = Package::new(
+            "serde-user",
+            None,
+            &semver::Version::new(0, 1, 0),
+            Description::new(String::new(), String::new()),
+            Description::new(String::new(), String::new()),
+            None,
+            vec![],
+            vec![],
+            vec![dep("serde", "1.0.100")],
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+            TestMode::default(),
+        )
+        .unwrap();
+        assert!(render(&package).contains("crate(serde-1/default) >= 1.0.100\n"));
+        package.apply_requires_floors(&floors);
+        assert!(render(&package).contains("crate(serde-1/default) >= 1.0.101\n"));
+    }
+
     #[test]
     fn cargo_dev_dependency_does_not_enter_runtime_crate_requires() {
         let mut dep = test_dep("proptest", "1", true, &[]);
@@ -1444,6 +1900,46 @@ mod tests {
         assert!(rendered_cargo_requirements(&[dep]).is_empty());
     }
 
+    #[test]
+    fn test_builds_require_dev_dependencies_unless_excluded_or_capped() {
+        let dev_deps: Vec<Dependency> = [("proptest", "1.4"), ("criterion", "0.5"), ("demo", "1")]
+            .iter()
+            .map(|(name, version)| {
+                let mut dep = test_dep(name, version, true, &[]);
+                dep.set_kind(DepKind::Development);
+                dep
+            })
+            .collect();
+        let rendered = |settings: &TestBuildRequires| {
+            test_build_requirements(&dev_deps, "demo", settings)
+                .iter()
+                .map(|r| spec::render_crate_requirement(spec::DEFAULT_CRATE_NAMESPACE, r))
+                .collect::<Vec<_>>()
+        };
+
+        // The crate's own dev-dependency on itself is never required
+        assert_eq!(
+            rendered(&TestBuildRequires::default()),
+            vec![
+                "crate(criterion-0.5/default) >= 0.5.0",
+                "crate(proptest-1/default) >= 1.4.0"
+            ]
+        );
+        let settings = TestBuildRequires {
+            exclude: vec!["criterion".to_string()],
+            ..TestBuildRequires::default()
+        };
+        assert_eq!(
+            rendered(&settings),
+            vec!["crate(proptest-1/default) >= 1.4.0"]
+        );
+        let settings = TestBuildRequires {
+            max: Some(0),
+            ..TestBuildRequires::default()
+        };
+        assert!(rendered(&settings).is_empty());
+    }
+
     #[test]
     fn onig_sys_style_build_dependencies_are_rendered_without_optional_leakage() {
         let toml = r#"
@@ -1491,6 +1987,200 @@ generate = ["bindgen"]
         );
     }
 
+    #[test]
+    fn same_dependency_for_several_targets_keeps_the_tighter_bound() {
+        let mut unix = test_dep("libc", "0.2.100", true, &[]);
+        unix.set_platform(Some("cfg(unix)".parse().unwrap()));
+        let mut linux = test_dep("libc", "0.2.150", true, &[]);
+        linux.set_platform(Some(r#"cfg(target_os = "linux")"#.parse().unwrap()));
+
+        assert_eq!(
+            vec!["Requires:       crate(libc-0.2/default) >= 0.2.150"],
+            rendered_cargo_requirements(&[linux.clone(), unix.clone()])
+        );
+        assert_eq!(
+            vec!["Requires:       crate(libc-0.2/default) >= 0.2.150"],
+            rendered_cargo_requirements(&[unix, linux])
+        );
+    }
+
+    #[test]
+    fn target_deps_drop_or_limit_target_specific_requirements() {
+        let mut windows = test_dep("windows-win", "3", true, &[]);
+        windows.set_platform(Some("cfg(windows)".parse().unwrap()));
+        let mut x86 = test_dep("cpuid", "1", true, &[]);
+        x86.set_platform(Some(r#"cfg(target_arch = "x86_64")"#.parse().unwrap()));
+        let mut unix = test_dep("libc", "0.2", true, &[]);
+        unix.set_platform(Some("cfg(unix)".parse().unwrap()));
+        let package = |target_deps, build_arches: &[&str]| {
+            let mut package = Package::new(
+                "target-user",
+                None,
+                &semver::Version::new(0, 1, 0),
+                Description::new(String::new(), String::new()),
+                Description::new(String::new(), String::new()),
+                None,
+                vec![],
+                vec![],
+                vec![windows.clone(), x86.clone(), unix.clone()],
+                vec![],
+                vec![],
+                vec![],
+                vec![],
+                TestMode::default(),
+            )
+            .unwrap();
+            let build_arches: Vec<String> = build_arches.iter().map(|a| a.to_string()).collect();
+            package.apply_target_deps(target_deps, &build_arches);
+            render(&package)
+        };
+
+        let kept = package(TargetDeps::Keep, &[]);
+        assert!(kept.contains("Requires:       crate(windows-win-3/default) >= 3.0.0\n"));
+
+        let omitted = package(TargetDeps::Omit, &["x86_64", "aarch64"]);
+        assert!(!omitted.contains("windows-win"));
+        assert!(omitted.contains("Requires:       crate(cpuid-1/default) >= 1.0.0\n"));
+        assert!(!omitted.contains("%ifarch"));
+
+        let arch = package(TargetDeps::Arch, &["x86_64", "aarch64"]);
+        assert!(!arch.contains("windows-win"));
+        assert!(arch
+            .contains("%ifarch x86_64\nRequires:       crate(cpuid-1/default) >= 1.0.0\n%endif\n"));
+        assert!(arch.contains("\nRequires:       crate(libc-0.2/default) >= 0.2.0\n"));
+        assert!(!package(TargetDeps::Arch, &["aarch64"]).contains("cpuid"));
+    }
+
+    #[test]
+    fn weak_dependency_features_are_conditional() {
+        let mut regex = test_dep("regex", "1", false, &["unicode"]);
+        regex.set_optional(true);
+        let mut serde = test_dep("serde", "1", false, &["std"]);
+        serde.set_optional(true);
+        let mut package = Package::new(
+            "weak-user",
+            None,
+            &semver::Version::new(0, 1, 0),
+            Description::new(String::new(), String::new()),
+            Description::new(String::new(), String::new()),
+            Some("std"),
+            vec![""],
+            vec![],
+            vec![test_dep("serde", "1", true, &[])],
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+            TestMode::default(),
+        )
+        .unwrap();
+        package.apply_weak_dependency_features(&[regex, serde]);
+        let rendered = render(&package);
+
+        assert!(rendered
+            .contains("Requires:       (crate(regex-1/unicode) >= 1.0.0 if crate(regex-1))\n"));
+        // Required by the package anyway
+        assert!(rendered.contains("Requires:       crate(serde-1/std) >= 1.0.0\n"));
+        assert!(!rendered.contains("if crate(serde-1)"));
+
+        package.requires_style = crate::config::RequiresStyle::Package;
+        assert!(render(&package)
+            .contains("Requires:       (rust-regex-1+unicode >= 1.0.0 if rust-regex-1)\n"));
+    }
+
+    #[test]
+    fn patched_crates_replace_their_requirements() {
+        let mut package = Package::new(
+            "patch-user",
+            None,
+            &semver::Version::new(0, 1, 0),
+            Description::new(String::new(), String::new()),
+            Description::new(String::new(), String::new()),
+            None,
+            vec![],
+            vec![],
+            vec![
+                test_dep("serde", "1.0.100", true, &["derive"]),
+                test_dep("log", "0.4", true, &[]),
+                test_dep("log-derive", "0.4", true, &[]),
+            ],
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+            TestMode::default(),
+        )
+        .unwrap();
+        let patched = HashMap::from([
+            (
+                "serde".to_string(),
+                PatchedCrate {
+                    package: Some("rust-serde-fork".to_string()),
+                    ..PatchedCrate::default()
+                },
+            ),
+            (
+                "log".to_string(),
+                PatchedCrate {
+                    crate_name: Some("log-fork".to_string()),
+                    version: Some("0.5.0".to_string()),
+                    ..PatchedCrate::default()
+                },
+            ),
+        ]);
+        package.apply_patched_crates(&patched);
+        let rendered = render(&package);
+
+        assert!(!rendered.contains("crate(serde-1"));
+        assert_eq!(
+            rendered
+                .matches("Requires:       rust-serde-fork\n")
+                .count(),
+            1
+        );
+        assert!(!rendered.contains("crate(log-0.4"));
+        assert!(rendered.contains("Requires:       crate(log-fork-0.5/default) >= 0.5.0\n"));
+        assert!(rendered.contains("crate(log-derive-0.4/default) >= 0.4.0"));
+    }
+
+    #[test]
+    fn renamed_dependencies_require_the_real_package() {
+        let toml = r#"
+[package]
+name = "renamer"
+version = "1.0.0"
+edition = "2021"
+
+[dependencies]
+rand07 = { package = "rand", version = "0.7" }
+rand = "0.8"
+log2 = { package = "log", version = "0.4", optional = true }
+
+[features]
+logging = ["log2/std"]
+"#;
+        let base = rendered_feature_requirements(toml, "");
+        assert_eq!(
+            base,
+            vec![
+                "Requires:       crate(rand-0.7/default) >= 0.7.0",
+                "Requires:       crate(rand-0.8/default) >= 0.8.0",
+            ]
+        );
+        let logging = rendered_feature_requirements(toml, "logging");
+        assert!(logging.contains(&"Requires:       crate(log-0.4/std) >= 0.4.0".to_string()));
+        assert!(logging.iter().all(|line| !line.contains("log2")));
+
+        let manifest = manifest_from_toml(toml);
+        let debs =
+            crate::takopack::deb_deps(false, manifest.dependencies(), TestMode::default()).unwrap();
+        assert!(debs.iter().any(|dep| dep.contains("-rand-0.7+default-dev")));
+        assert!(debs.iter().any(|dep| dep.contains("-log-0.4+default-dev")));
+        assert!(debs
+            .iter()
+            .all(|dep| !dep.contains("rand07") && !dep.contains("log2")));
+    }
+
     #[test]
     fn target_specific_dev_dependency_does_not_enter_provider_crate_requires() {
         let mut dep = test_dep("windows-dev", "1", true, &[]);
@@ -1557,14 +2247,132 @@ windows = ["dep:windows-sys"]
     fn same_crate_feature_dependencies_remain_exact_version() {
         assert_eq!(
             "crate(%{pkgname}) = %{version}",
-            CrateDep::new("%{pkgname}".to_string(), None).to_crate_format()
+            CrateDep::new("%{pkgname}".to_string(), None)
+                .to_crate_format("crate")
+                .unwrap()
         );
         assert_eq!(
             "crate(%{pkgname}/std) = %{version}",
-            CrateDep::new("%{pkgname}".to_string(), Some("std".to_string())).to_crate_format()
+            CrateDep::new("%{pkgname}".to_string(), Some("std".to_string()))
+                .to_crate_format("crate")
+                .unwrap()
+        );
+        assert_eq!(
+            "mycorp-crate(%{pkgname}/std) = %{version}",
+            CrateDep::new("%{pkgname}".to_string(), Some("std".to_string()))
+                .to_crate_format("mycorp-crate")
+                .unwrap()
         );
     }
 
+    #[test]
+    fn exclusive_features_conflict_with_the_other_members_of_their_group() {
+        let package = |feature: &str, f_provides: Vec<&str>| {
+            let mut package = Package::new(
+                "reqwest",
+                None,
+                &semver::Version::new(0, 12, 0),
+                Description::new("Rust crate \"reqwest\"".to_string(), String::new()),
+                Description::new(String::new(), String::new()),
+                Some(feature),
+                vec![],
+                vec![],
+                vec![],
+                f_provides,
+                vec![],
+                vec![],
+                vec![],
+                TestMode::default(),
+            )
+            .unwrap();
+            package.apply_exclusive_features(&[
+                vec!["rustls-tls".to_string(), "native_tls".to_string()],
+                vec!["blocking".to_string(), "wasm".to_string()],
+            ]);
+            render(&package)
+        };
+
+        let rustls = package("rustls-tls", vec![]);
+        assert!(rustls.contains("Conflicts:      crate(%{pkgname}/native-tls)\n"));
+        assert!(!rustls.contains("Conflicts:      crate(%{pkgname}/rustls-tls)"));
+        assert!(!rustls.contains("/wasm)"));
+
+        // Features merged into one package cannot conflict with each other.
+        let both = package("native-tls", vec!["rustls-tls"]);
+        assert!(!both.contains("Conflicts:"));
+
+        assert!(!package("json", vec![]).contains("Conflicts:"));
+    }
+
+    #[test]
+    fn every_crate_provides_is_versioned() {
+        let package = |feature: Option<&str>, f_provides: Vec<&str>| {
+            let package = Package::new(
+                "serde",
+                None,
+                &semver::Version::new(1, 0, 200),
+                Description::new("Rust crate \"serde\"".to_string(), String::new()),
+                Description::new(String::new(), String::new()),
+                feature,
+                vec![],
+                vec![],
+                vec![],
+                f_provides,
+                vec![],
+                vec![],
+                vec!["derive".to_string(), "std".to_string()],
+                TestMode::default(),
+            )
+            .unwrap();
+            render(&package)
+        };
+
+        for rendered in [
+            package(None, vec!["default"]),
+            package(Some("derive"), vec!["serde_derive"]),
+        ] {
+            let provides: Vec<&str> = rendered
+                .lines()
+                .filter(|line| line.starts_with("Provides:"))
+                .collect();
+            assert!(!provides.is_empty());
+            for line in provides {
+                assert!(line.ends_with(" = %{version}"), "{}", line);
+            }
+        }
+        assert!(package(None, vec![])
+            .contains("Provides:       crate(%{pkgname}/derive) = %{version}\n"));
+    }
+
+    #[test]
+    fn weak_deps_point_at_feature_subpackages() {
+        let mut package = Package::new(
+            "serde",
+            None,
+            &semver::Version::new(1, 0, 200),
+            Description::new("Rust crate \"serde\"".to_string(), String::new()),
+            Description::new(String::new(), String::new()),
+            None,
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+            vec!["default", "std"],
+            vec!["rc", "unstable_api"],
+            vec![],
+            TestMode::default(),
+        )
+        .unwrap();
+        assert!(!render(&package).contains("Recommends:"));
+
+        package.weak_deps = true;
+        let rendered = render(&package);
+        assert!(rendered.contains("Recommends:     %{name}+default = %{version}-%{release}\n"));
+        assert!(rendered.contains("Recommends:     %{name}+std = %{version}-%{release}\n"));
+        assert!(rendered.contains("Suggests:       %{name}+rc = %{version}-%{release}\n"));
+        assert!(rendered.contains("Suggests:       %{name}+unstable-api = %{version}-%{release}\n"));
+    }
+
     #[test]
     fn legacy_package_parser_only_uses_explicit_plus_features() {
         let plain_rc = parse_package_name_simple("rust-example-rc-dev").unwrap();
@@ -1575,4 +2383,38 @@ windows = ["dep:windows-sys"]
         assert_eq!("example", feature_rc.crate_name);
         assert_eq!(Some("rc"), feature_rc.feature.as_deref());
     }
+
+    #[test]
+    fn feature_tests_become_cargo_test_runs() {
+        let test = PkgTest::new(
+            "rust-example-1+serde",
+            "example",
+            "serde",
+            "1.0.0",
+            vec!["--no-default-features", "--features", "serde"],
+            &[],
+            vec!["flaky"],
+            &["x86_64"],
+        )
+        .unwrap();
+        let check = test.check_run();
+        assert_eq!(check.args, vec!["-n", "-f", "serde"]);
+        assert!(check.broken);
+        assert_eq!(check.architectures, vec!["x86_64"]);
+
+        let all = PkgTest::new(
+            "rust-example-1",
+            "example",
+            "@",
+            "1.0.0",
+            vec!["--all-features"],
+            &[],
+            vec![],
+            &[],
+        )
+        .unwrap()
+        .check_run();
+        assert_eq!(all.args, vec!["-a"]);
+        assert!(!all.broken && all.architectures.is_empty());
+    }
 }
@@ -0,0 +1,273 @@
+//! debian/copyright-style license breakdown of an unpacked crate, written
+//! for `--copyright-guess-harder`.
+//!
+//! The crate-wide stanza comes from Cargo.toml. Every file is scanned for
+//! SPDX headers, copyright lines and well-known license texts, and files
+//! that deviate from the crate-wide license or carry their own copyright
+//! get a stanza of their own.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+use regex::Regex;
+
+use crate::crates::CrateInfo;
+use crate::errors::*;
+
+const FORMAT_URL: &str = "https://www.debian.org/doc/packaging-manuals/copyright-format/1.0/";
+
+/// Only the top of a file is searched for headers.
+const HEADER_BYTES: u64 = 8 * 1024;
+
+/// Directories that are not part of the crate's own sources.
+const SKIPPED_DIRS: &[&str] = &["takopack", "target", ".git", ".pc"];
+
+/// Phrases identifying common license texts, most specific first.
+const LICENSE_TEXTS: &[(&str, &str)] = &[
+    ("Apache License", "Apache-2.0"),
+    ("Permission is hereby granted, free of charge", "MIT"),
+    ("Neither the name of", "BSD-3-Clause"),
+    (
+        "Redistribution and use in source and binary forms",
+        "BSD-2-Clause",
+    ),
+    ("Mozilla Public License Version 2.0", "MPL-2.0"),
+    (
+        "This is free and unencumbered software released into the public domain",
+        "Unlicense",
+    ),
+    ("Boost Software License", "BSL-1.0"),
+    ("CC0 1.0 Universal", "CC0-1.0"),
+    (
+        "Permission to use, copy, modify, and/or distribute this software",
+        "ISC",
+    ),
+    ("This software is provided 'as-is'", "Zlib"),
+    ("GNU LESSER GENERAL PUBLIC LICENSE", "LGPL"),
+    ("GNU GENERAL PUBLIC LICENSE", "GPL"),
+];
+
+/// What was found in one file.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct FileLicense {
+    pub(crate) license: Option<String>,
+    pub(crate) copyright: BTreeSet<String>,
+}
+
+/// Render the copyright report for the crate unpacked at `source_dir`.
+pub(crate) fn copyright_report(crate_info: &CrateInfo, source_dir: &Path) -> Result<String> {
+    let meta = crate_info.metadata();
+    let license = meta
+        .license
+        .as_deref()
+        .map(|l| l.replace('/', " OR "))
+        .unwrap_or_else(|| "FIXME".to_string());
+    let authors: Vec<String> = meta.authors.iter().map(|a| strip_email(a)).collect();
+    let source = meta
+        .repository
+        .as_deref()
+        .or(meta.homepage.as_deref())
+        .unwrap_or("FIXME");
+
+    let files = scan_sources(source_dir)?;
+    Ok(render_report(
+        crate_info.crate_name(),
+        source,
+        &authors,
+        &license,
+        &files,
+    ))
+}
+
+fn strip_email(author: &str) -> String {
+    match author.find('<') {
+        Some(i) => author[..i].trim().to_string(),
+        None => author.trim().to_string(),
+    }
+}
+
+/// Scan every file under `root` and keep the ones that say something.
+fn scan_sources(root: &Path) -> Result<BTreeMap<String, FileLicense>> {
+    let mut found = BTreeMap::new();
+    let walker = walkdir::WalkDir::new(root)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|e| {
+            e.depth() != 1
+                || !e.file_type().is_dir()
+                || !SKIPPED_DIRS.contains(&e.file_name().to_string_lossy().as_ref())
+        });
+    for entry in walker {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let mut head = Vec::new();
+        fs::File::open(entry.path())?
+            .take(HEADER_BYTES)
+            .read_to_end(&mut head)?;
+        if head.contains(&0) {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().to_lowercase();
+        let is_license_file = ["license", "licence", "copying", "unlicense"]
+            .iter()
+            .any(|p| name.starts_with(p));
+        let info = scan_text(&String::from_utf8_lossy(&head), is_license_file);
+        if info.license.is_some() || !info.copyright.is_empty() {
+            let rel = entry.path().strip_prefix(root).unwrap_or(entry.path());
+            found.insert(rel.to_string_lossy().replace('\\', "/"), info);
+        }
+    }
+    Ok(found)
+}
+
+/// Look for an SPDX identifier and copyright lines; license files are also
+/// matched against well-known license texts.
+pub(crate) fn scan_text(text: &str, is_license_file: bool) -> FileLicense {
+    let spdx = Regex::new(r"SPDX-License-Identifier:\s*([^\s*/][^*\n]*?)\s*(?:\*/)?\s*$").unwrap();
+    let copyright = Regex::new(r"(?i)^[\s/*#!;-]*copyright\s*(\(c\)|©)?\s*(.*?)[\s*/]*$").unwrap();
+    let year = Regex::new(r"\b(19|20)\d{2}\b").unwrap();
+
+    let mut info = FileLicense::default();
+    for line in text.lines() {
+        if info.license.is_none() {
+            if let Some(caps) = spdx.captures(line) {
+                info.license = Some(caps[1].trim().to_string());
+                continue;
+            }
+        }
+        if let Some(caps) = copyright.captures(line) {
+            let holder = caps[2].trim().trim_end_matches('.').to_string();
+            // "copyright notice" in license texts has neither a year nor (c)
+            if !holder.is_empty() && (caps.get(1).is_some() || year.is_match(&holder)) {
+                info.copyright.insert(holder);
+            }
+        }
+    }
+    if info.license.is_none() && is_license_file {
+        info.license = LICENSE_TEXTS
+            .iter()
+            .find(|(phrase, _)| text.contains(phrase))
+            .map(|(_, id)| id.to_string());
+    }
+    info
+}
+
+fn render_report(
+    crate_name: &str,
+    source: &str,
+    authors: &[String],
+    license: &str,
+    files: &BTreeMap<String, FileLicense>,
+) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "Format: {}", FORMAT_URL);
+    let _ = writeln!(out, "Upstream-Name: {}", crate_name);
+    let _ = writeln!(out, "Source: {}", source);
+
+    let crate_copyright: Vec<String> = if authors.is_empty() {
+        vec!["FIXME".to_string()]
+    } else {
+        authors.to_vec()
+    };
+    write_stanza(&mut out, &["*".to_string()], &crate_copyright, license);
+
+    // Files sharing the same copyright holders and license share a stanza.
+    let mut groups: BTreeMap<(Vec<String>, String), Vec<String>> = BTreeMap::new();
+    for (path, info) in files {
+        let file_license = info.license.clone().unwrap_or_else(|| license.to_string());
+        if file_license == license && info.copyright.is_empty() {
+            continue;
+        }
+        let holders: Vec<String> = if info.copyright.is_empty() {
+            crate_copyright.clone()
+        } else {
+            info.copyright.iter().cloned().collect()
+        };
+        groups
+            .entry((holders, file_license))
+            .or_default()
+            .push(path.clone());
+    }
+    for ((holders, file_license), paths) in groups {
+        write_stanza(&mut out, &paths, &holders, &file_license);
+    }
+    out
+}
+
+fn write_stanza(out: &mut String, files: &[String], copyright: &[String], license: &str) {
+    let _ = writeln!(out);
+    let _ = writeln!(out, "Files: {}", files.join("\n       "));
+    let _ = writeln!(out, "Copyright: {}", copyright.join("\n           "));
+    let _ = writeln!(out, "License: {}", license);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render_report, scan_text, FileLicense};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn headers_and_license_texts_are_recognized() {
+        let header = scan_text(
+            "// SPDX-License-Identifier: BSD-3-Clause\n// Copyright (c) 2019-2021 Jane Doe\nfn main() {}\n",
+            false,
+        );
+        assert_eq!(header.license.as_deref(), Some("BSD-3-Clause"));
+        assert_eq!(
+            header.copyright.iter().collect::<Vec<_>>(),
+            vec!["2019-2021 Jane Doe"]
+        );
+
+        let c_style = scan_text("/* SPDX-License-Identifier: MIT */\n", false);
+        assert_eq!(c_style.license.as_deref(), Some("MIT"));
+
+        let text = "MIT License\n\nCopyright 2020 The Demo Authors\n\nPermission is hereby granted, free of charge, to any person\nThe above copyright notice and this permission notice shall be included\n";
+        let mit = scan_text(text, true);
+        assert_eq!(mit.license.as_deref(), Some("MIT"));
+        assert_eq!(
+            mit.copyright.iter().collect::<Vec<_>>(),
+            vec!["2020 The Demo Authors"]
+        );
+
+        // a README quoting license text is not a license file
+        assert_eq!(scan_text(text, false).license, None);
+        assert_eq!(scan_text("fn main() {}\n", false), FileLicense::default());
+    }
+
+    #[test]
+    fn report_groups_deviating_files() {
+        let mut files = BTreeMap::new();
+        let bsd = FileLicense {
+            license: Some("BSD-3-Clause".to_string()),
+            copyright: ["2019 Jane Doe".to_string()].into_iter().collect(),
+        };
+        files.insert("src/a.rs".to_string(), bsd.clone());
+        files.insert("src/b.rs".to_string(), bsd);
+        files.insert(
+            "src/c.rs".to_string(),
+            FileLicense {
+                license: Some("MIT OR Apache-2.0".to_string()),
+                copyright: Default::default(),
+            },
+        );
+
+        let report = render_report(
+            "demo",
+            "https://example.com/demo",
+            &["Demo Authors".to_string()],
+            "MIT OR Apache-2.0",
+            &files,
+        );
+        assert!(report.starts_with("Format: https://www.debian.org/"));
+        assert!(report.contains("Files: *\nCopyright: Demo Authors\nLicense: MIT OR Apache-2.0\n"));
+        assert!(report.contains(
+            "Files: src/a.rs\n       src/b.rs\nCopyright: 2019 Jane Doe\nLicense: BSD-3-Clause\n"
+        ));
+        assert!(!report.contains("src/c.rs"));
+    }
+}
@@ -1,11 +1,23 @@
+//! Translating Cargo dependencies into package dependencies.
+//!
+//! A Cargo requirement is read once into the versions it accepts, and both
+//! the Debian dependencies of [`deb_deps`] and the `crate()` requirements
+//! of specs ([`crate_requirement`]) are written from that.
+
+use anyhow::Context;
 use cargo::core::Dependency;
+use cargo::util::OptVersionReq;
 use itertools::Itertools;
+use semver::Version;
 
 use std::cmp;
 use std::fmt;
 
 use crate::errors::*;
-use crate::takopack::{self, control::base_deb_name, Package};
+use crate::takopack::spec::{self, CrateRequirement, RequirementVersion};
+use crate::takopack::{self, control::base_deb_name};
+use crate::test_mode::TestMode;
+use crate::version::{compat_version, upstream_version};
 
 #[derive(Eq, Clone)]
 #[allow(clippy::upper_case_acronyms)]
@@ -243,7 +255,7 @@ fn coerce_unacceptable_predicate<'a>(
 
 fn generate_version_constraints(
     vr: &mut VRange,
-    dep: &Dependency,
+    name: &str,
     p: &semver::Comparator,
     op: &semver::Op,
 ) -> Result<()> {
@@ -255,7 +267,7 @@ fn generate_version_constraints(
     match (*op, &mmp) {
         (Less, &M(0)) | (Less, &MM(0, 0)) | (Less, &MMP(0, 0, 0)) => takopack_bail!(
             "Unrepresentable dependency version predicate: {} {:?}",
-            dep.package_name(),
+            name,
             p
         ),
         (Less, _) => {
@@ -316,7 +328,7 @@ fn generate_version_constraints(
 
         (_, _) => takopack_bail!(
             "Unhandled dependency version predicate for {}: {:?}",
-            dep.package_name(),
+            name,
             p
         ),
     }
@@ -325,7 +337,11 @@ fn generate_version_constraints(
 }
 
 /// Translates a Cargo dependency into a takopack package dependency.
-pub fn deb_dep(allow_prerelease_deps: bool, dep: &Dependency) -> Result<Vec<String>> {
+pub fn deb_dep(
+    allow_prerelease_deps: bool,
+    dep: &Dependency,
+    test_mode: TestMode,
+) -> Result<Vec<String>> {
     // println!("{:?}",dep.package_name());
     let dep_dashed = base_deb_name(&dep.package_name());
     let mut suffixes = Vec::new();
@@ -341,25 +357,29 @@ pub fn deb_dep(allow_prerelease_deps: bool, dep: &Dependency) -> Result<Vec<Stri
     let req = semver::VersionReq::parse(&dep.version_req().to_string())?;
     let mut deps = Vec::new();
     for suffix in suffixes {
-        let base = format!("{}-{}", Package::pkg_prefix(), dep_dashed);
+        let base = format!("{}-{}", test_mode.pkg_prefix(), dep_dashed);
         let mut vr = VRange::new();
         for p in &req.comparators {
             let op = coerce_unacceptable_predicate(dep, p, allow_prerelease_deps)?;
-            generate_version_constraints(&mut vr, dep, p, op)?;
+            generate_version_constraints(&mut vr, &dep.package_name(), p, op)?;
         }
         deps.extend(vr.to_deb_clause(&base, &suffix)?);
     }
     Ok(deps)
 }
 
-pub fn deb_deps(allow_prerelease_deps: bool, cdeps: &[Dependency]) -> Result<Vec<String>> // result is an AND-clause
+pub fn deb_deps(
+    allow_prerelease_deps: bool,
+    cdeps: &[Dependency],
+    test_mode: TestMode,
+) -> Result<Vec<String>> // result is an AND-clause
 {
     let mut deps = Vec::new();
     // let mut i = 0;
     for dep in cdeps {
         // println!(" dep {:?}", dep);
         deps.extend(
-            deb_dep(allow_prerelease_deps, dep)?
+            deb_dep(allow_prerelease_deps, dep, test_mode)?
                 .iter()
                 .map(String::to_string),
         );
@@ -371,6 +391,32 @@ pub fn deb_deps(allow_prerelease_deps: bool, cdeps: &[Dependency]) -> Result<Vec
     Ok(deps)
 }
 
+/// `dep` requiring the release of the pre-release it names, e.g. `^0.26.0`
+/// for `^0.26.0-beta.1`; `None` when it names no pre-release. Packages of
+/// pre-releases provide capabilities named after the full version, which
+/// a distro that only packages releases does not have.
+pub fn coerce_prerelease(dep: &Dependency) -> Option<Dependency> {
+    let OptVersionReq::Req(req) = dep.version_req() else {
+        return None;
+    };
+    if req.comparators.iter().all(|p| p.pre.is_empty()) {
+        return None;
+    }
+    let mut release = req.clone();
+    for p in &mut release.comparators {
+        p.pre = semver::Prerelease::EMPTY;
+    }
+    takopack_warn!(
+        "Coercing pre-release requirement {} {} to {}; list the crate in allow_prerelease to keep it",
+        dep.package_name(),
+        req,
+        release
+    );
+    let mut dep = dep.clone();
+    dep.set_version_req(OptVersionReq::Req(release));
+    Some(dep)
+}
+
 pub fn deb_dep_add_nocheck(x: &str) -> String {
     x.split('|')
         .map(|x| x.trim_end().to_string() + " <!nocheck> ")
@@ -378,3 +424,243 @@ pub fn deb_dep_add_nocheck(x: &str) -> String {
         .trim_end()
         .to_string()
 }
+
+/// The versions a Cargo requirement accepts: the lowest one, and the first
+/// one above it that is no longer accepted. Both are written out in full
+/// (`1.2.0`, not `1.2`), the way rpm compares them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VersionBounds {
+    pub lower: Option<String>,
+    pub upper: Option<String>,
+}
+
+impl VersionBounds {
+    /// The bounds of `req`, a requirement on `crate_name`.
+    pub fn of(crate_name: &str, req: &OptVersionReq) -> Self {
+        let upper = match req {
+            OptVersionReq::Req(req) => upper_bound(crate_name, req),
+            _ => None,
+        };
+        VersionBounds {
+            lower: lower_bound_from_opt_version_req(req),
+            upper,
+        }
+    }
+
+    /// The bounds of a requirement written as text, like the `>= 0.9.11+spec-1.1.0`
+    /// taken from a lockfile.
+    pub fn parse(crate_name: &str, req: &str) -> Result<Self> {
+        // Wildcards and build metadata are not allowed after an operator
+        let cleaned = req
+            .replace(".*", ".0")
+            .replace('*', "0")
+            .split(',')
+            .map(|comparator| comparator.split('+').next().unwrap_or(comparator))
+            .join(",");
+        let parsed = semver::VersionReq::parse(&cleaned).with_context(|| {
+            format!(
+                "Failed to parse version requirement '{}' of crate '{}'",
+                req, crate_name
+            )
+        })?;
+        Ok(Self::of(crate_name, &OptVersionReq::Req(parsed)))
+    }
+}
+
+fn upper_bound(crate_name: &str, req: &semver::VersionReq) -> Option<String> {
+    let mut vr = VRange::new();
+    for p in &req.comparators {
+        generate_version_constraints(&mut vr, crate_name, p, &p.op).ok()?;
+    }
+    vr.lt.map(|lt| {
+        let (major, minor, patch) = lt.mmp();
+        match lt {
+            V::Prerelease(_, _, _, pre) => format!("{}.{}.{}-{}", major, minor, patch, pre),
+            _ => format!("{}.{}.{}", major, minor, patch),
+        }
+    })
+}
+
+/// The `crate()` requirement on `feature` of `crate_name` with `bounds`.
+/// The capability already names the compat stream of the lower bound
+/// (`foo-1`), so the upper bound is only written, as a rich dependency
+/// like `(crate(foo-1) >= 1.2.0 with crate(foo-1) < 1.5.0)`, when it is
+/// below the next incompatible version.
+pub fn crate_requirement(
+    crate_name: &str,
+    feature: Option<String>,
+    bounds: &VersionBounds,
+) -> CrateRequirement {
+    let requirement = match (&bounds.lower, &bounds.upper) {
+        (None, _) => RequirementVersion::None,
+        (Some(lower), Some(upper)) if narrows_compat_stream(lower, upper) => {
+            RequirementVersion::Between(lower.clone(), upper.clone())
+        }
+        (Some(lower), _) => RequirementVersion::Range(format!(">= {}", lower)),
+    };
+    CrateRequirement {
+        crate_name: cargo_dep_crate_name(crate_name, bounds.lower.as_deref()),
+        feature,
+        requirement,
+        target_arches: None,
+        arches: Vec::new(),
+        weak: false,
+    }
+}
+
+/// Whether `upper` leaves out versions of the compat stream of `lower`.
+/// Pre-release capabilities name a single version and need no bound.
+fn narrows_compat_stream(lower: &str, upper: &str) -> bool {
+    let (Ok(lower), Ok(upper)) = (Version::parse(lower), Version::parse(upper)) else {
+        return false;
+    };
+    lower.pre.is_empty() && lower < upper && upper < next_incompatible(&lower)
+}
+
+/// The first version that is not semver compatible with `version`.
+fn next_incompatible(version: &Version) -> Version {
+    if version.major > 0 {
+        Version::new(version.major + 1, 0, 0)
+    } else if version.minor > 0 {
+        Version::new(0, version.minor + 1, 0)
+    } else {
+        Version::new(0, 0, version.patch + 1)
+    }
+}
+
+/// The name of the `crate()` capability of `crate_name` for a requirement
+/// with `lower_bound`: its compat version (`foo-1`, the full version for
+/// pre-releases, see [`crate::version`]), and the bare name without a
+/// bound.
+pub(crate) fn cargo_dep_crate_name(crate_name: &str, lower_bound: Option<&str>) -> String {
+    let crate_base = spec::normalize_crate_name(crate_name);
+    match lower_bound.map(Version::parse) {
+        Some(Ok(version)) => format!("{}-{}", crate_base, compat_version(&version)),
+        _ => crate_base,
+    }
+}
+
+pub(crate) fn lower_bound_from_opt_version_req(version_req: &OptVersionReq) -> Option<String> {
+    match version_req {
+        OptVersionReq::Any => None,
+        OptVersionReq::Req(req) if req.to_string() == "*" => None,
+        OptVersionReq::Req(req) => req
+            .comparators
+            .iter()
+            .filter_map(lower_bound_from_comparator)
+            .max_by(compare_version_strings),
+        OptVersionReq::Locked(version, _) | OptVersionReq::Precise(version, _) => {
+            Some(upstream_version(version))
+        }
+    }
+}
+
+fn lower_bound_from_comparator(comparator: &semver::Comparator) -> Option<String> {
+    use semver::Op;
+
+    match comparator.op {
+        Op::Exact | Op::GreaterEq | Op::Tilde | Op::Caret => {
+            Some(comparator_lower_bound(comparator))
+        }
+        Op::Greater => Some(comparator_strict_lower_bound(comparator)),
+        Op::Wildcard if comparator.minor.is_some() || comparator.patch.is_some() => {
+            Some(comparator_lower_bound(comparator))
+        }
+        Op::Wildcard | Op::Less | Op::LessEq => None,
+        _ => None,
+    }
+}
+
+fn comparator_lower_bound(comparator: &semver::Comparator) -> String {
+    upstream_version(&Version {
+        major: comparator.major,
+        minor: comparator.minor.unwrap_or(0),
+        patch: comparator.patch.unwrap_or(0),
+        pre: comparator.pre.clone(),
+        build: semver::BuildMetadata::EMPTY,
+    })
+}
+
+fn comparator_strict_lower_bound(comparator: &semver::Comparator) -> String {
+    if !comparator.pre.is_empty() {
+        // TODO: model strict prerelease bounds more precisely when RPM crate
+        // requirements grow beyond simple lower bounds.
+        return comparator_lower_bound(comparator);
+    }
+
+    match (comparator.minor, comparator.patch) {
+        (Some(minor), Some(patch)) => format!("{}.{}.{}", comparator.major, minor, patch + 1),
+        (Some(minor), None) => format!("{}.{}.0", comparator.major, minor + 1),
+        (None, None) => format!("{}.0.0", comparator.major + 1),
+        (None, Some(patch)) => format!("{}.0.{}", comparator.major, patch + 1),
+    }
+}
+
+fn compare_version_strings(a: &String, b: &String) -> std::cmp::Ordering {
+    match (Version::parse(a), Version::parse(b)) {
+        (Ok(a), Ok(b)) => a.cmp(&b),
+        _ => a.cmp(b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{coerce_prerelease, crate_requirement, VersionBounds};
+    use crate::takopack::spec::{self, RequirementVersion};
+    use cargo::core::{Dependency, SourceId};
+    use cargo::util::OptVersionReq;
+    use cargo::GlobalContext;
+
+    fn rendered(req: &str) -> String {
+        let req = OptVersionReq::Req(semver::VersionReq::parse(req).unwrap());
+        let bounds = VersionBounds::of("foo", &req);
+        spec::render_crate_requirement(
+            spec::DEFAULT_CRATE_NAMESPACE,
+            &crate_requirement("foo", Some("default".to_string()), &bounds),
+        )
+    }
+
+    #[test]
+    fn upper_bounds_below_the_compat_stream_become_rich_dependencies() {
+        assert_eq!(rendered("^1.2"), "crate(foo-1/default) >= 1.2.0");
+        assert_eq!(rendered("0.4.*"), "crate(foo-0.4/default) >= 0.4.0");
+        assert_eq!(
+            rendered("~1.2.3"),
+            "(crate(foo-1/default) >= 1.2.3 with crate(foo-1/default) < 1.3.0)"
+        );
+        assert_eq!(
+            rendered("=0.3.5"),
+            "(crate(foo-0.3/default) >= 0.3.5 with crate(foo-0.3/default) < 0.3.6)"
+        );
+        assert_eq!(
+            rendered(">=1.2, <1.5"),
+            "(crate(foo-1/default) >= 1.2.0 with crate(foo-1/default) < 1.5.0)"
+        );
+        assert_eq!(rendered(">=1.2, <3"), "crate(foo-1/default) >= 1.2.0");
+        assert_eq!(
+            rendered("=0.26.0-beta.1"),
+            "crate(foo-0.26.0-beta.1/default) >= 0.26.0-beta.1"
+        );
+        assert_eq!(rendered("<2"), "crate(foo/default)");
+
+        let bounds = VersionBounds::parse("foo", ">= 0.9.11+spec-1.1.0").unwrap();
+        assert_eq!(bounds.lower.as_deref(), Some("0.9.11"));
+        assert_eq!(bounds.upper, None);
+        assert!(VersionBounds::parse("foo", ">= one.two").is_err());
+        assert_eq!(
+            crate_requirement("%{pkgname}", None, &VersionBounds::default()).requirement,
+            RequirementVersion::None
+        );
+    }
+
+    #[test]
+    fn prerelease_requirements_coerce_to_the_release() {
+        let crates_io = SourceId::crates_io(&GlobalContext::default().unwrap()).unwrap();
+        let dep = |req: &str| Dependency::parse("libbpf-rs", Some(req), crates_io).unwrap();
+
+        let coerced = coerce_prerelease(&dep("^0.26.0-beta.1")).unwrap();
+        assert_eq!(coerced.version_req().to_string(), "^0.26.0");
+        assert_eq!(coerced.package_name().as_str(), "libbpf-rs");
+        assert!(coerce_prerelease(&dep("^0.25.0")).is_none());
+    }
+}
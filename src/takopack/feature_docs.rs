@@ -0,0 +1,332 @@
+//! Per-feature documentation scraped from a crate's own sources.
+//!
+//! Used to give feature subpackages a real %description line instead of the
+//! generic "enables feature X" text. Sources, in order of preference:
+//!
+//! 1. `[package.metadata.docs.rs.features]` written as a table of strings,
+//! 2. `##` / `#` comments directly above an entry in `[features]` (the
+//!    `document-features` convention), read from `Cargo.toml.orig` when the
+//!    published manifest has been normalized,
+//! 3. a Markdown table in the README with a "feature" column.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use toml_edit::{DocumentMut, Item};
+
+use crate::crates::CrateInfo;
+
+/// Longest explanation we put on a single %description line.
+const MAX_DOC_LEN: usize = 200;
+
+/// Collect one-line feature explanations for `crate_info`, keyed by feature
+/// name. Missing or unreadable sources are ignored.
+pub(crate) fn collect_feature_docs(crate_info: &CrateInfo) -> BTreeMap<String, String> {
+    let Some(crate_dir) = crate_info.manifest_path().parent() else {
+        return BTreeMap::new();
+    };
+
+    let mut docs = BTreeMap::new();
+    if let Some(readme) = read_readme(crate_dir, crate_info.metadata().readme.as_deref()) {
+        docs.extend(readme_table_docs(&readme));
+    }
+    for name in ["Cargo.toml", "Cargo.toml.orig"] {
+        if let Ok(manifest) = fs::read_to_string(crate_dir.join(name)) {
+            docs.extend(manifest_comment_docs(&manifest));
+        }
+    }
+    if let Ok(manifest) = fs::read_to_string(crate_dir.join("Cargo.toml")) {
+        docs.extend(docs_rs_metadata_docs(&manifest));
+    }
+    docs
+}
+
+fn read_readme(crate_dir: &Path, readme: Option<&str>) -> Option<String> {
+    let candidates = readme
+        .into_iter()
+        .chain(["README.md", "README.markdown", "README"]);
+    for name in candidates {
+        if let Ok(text) = fs::read_to_string(crate_dir.join(name)) {
+            return Some(text);
+        }
+    }
+    None
+}
+
+/// `[package.metadata.docs.rs.features]` when given as `feature = "doc"`.
+/// The usual list form only tells docs.rs what to build and is skipped.
+fn docs_rs_metadata_docs(manifest: &str) -> BTreeMap<String, String> {
+    let mut docs = BTreeMap::new();
+    let Ok(doc) = manifest.parse::<DocumentMut>() else {
+        return docs;
+    };
+    let features = doc
+        .get("package")
+        .and_then(|p| p.get("metadata"))
+        .and_then(|m| m.get("docs"))
+        .and_then(|d| d.get("rs"))
+        .and_then(|r| r.get("features"))
+        .and_then(Item::as_table_like);
+    if let Some(features) = features {
+        for (name, value) in features.iter() {
+            if let Some(line) = value.as_str().and_then(one_line) {
+                docs.insert(name.to_string(), line);
+            }
+        }
+    }
+    docs
+}
+
+/// Comments immediately preceding a key in `[features]`.
+fn manifest_comment_docs(manifest: &str) -> BTreeMap<String, String> {
+    let mut docs = BTreeMap::new();
+    let Ok(doc) = manifest.parse::<DocumentMut>() else {
+        return docs;
+    };
+    let Some(features) = doc.get("features").and_then(Item::as_table) else {
+        return docs;
+    };
+    for (name, _) in features.iter() {
+        let prefix = features
+            .key(name)
+            .and_then(|key| key.leaf_decor().prefix())
+            .and_then(|raw| raw.as_str())
+            .unwrap_or("");
+        if let Some(line) = comment_block_doc(prefix) {
+            docs.insert(name.to_string(), line);
+        }
+    }
+    docs
+}
+
+/// Turn the comment block above a feature into a one-line explanation.
+/// Only the paragraph touching the key counts, `#!` section headers are
+/// skipped, and commented-out entries (`# foo = []`) are not documentation.
+fn comment_block_doc(prefix: &str) -> Option<String> {
+    let mut paragraph = Vec::new();
+    for line in prefix.lines().map(str::trim) {
+        if line.is_empty() {
+            paragraph.clear();
+            continue;
+        }
+        let Some(comment) = line.strip_prefix('#') else {
+            paragraph.clear();
+            continue;
+        };
+        if comment.starts_with('!') {
+            paragraph.clear();
+            continue;
+        }
+        let text = comment.trim_start_matches('#').trim();
+        if text.contains(" = ") || text.ends_with('=') {
+            paragraph.clear();
+            continue;
+        }
+        paragraph.push(text);
+    }
+    one_line(&paragraph.join(" "))
+}
+
+/// Rows of a Markdown table whose header has a "feature" column. The
+/// explanation comes from a "description"-like column, or the first other
+/// non-empty column.
+fn readme_table_docs(readme: &str) -> BTreeMap<String, String> {
+    let mut docs = BTreeMap::new();
+    let mut columns: Option<(usize, usize)> = None;
+    let mut in_code_block = false;
+
+    for line in readme.lines().map(str::trim) {
+        if line.starts_with("```") {
+            in_code_block = !in_code_block;
+            continue;
+        }
+        if in_code_block || !line.starts_with('|') {
+            columns = None;
+            continue;
+        }
+        let cells = table_cells(line);
+        if cells
+            .iter()
+            .all(|c| !c.is_empty() && c.chars().all(|ch| matches!(ch, '-' | ':' | ' ')))
+        {
+            continue;
+        }
+        match columns {
+            None => columns = feature_table_columns(&cells),
+            Some((feature_col, doc_col)) => {
+                let (Some(feature), Some(doc)) = (cells.get(feature_col), cells.get(doc_col))
+                else {
+                    continue;
+                };
+                let feature = strip_markdown(feature);
+                if feature.is_empty() || feature.contains(char::is_whitespace) {
+                    continue;
+                }
+                if let Some(doc) = one_line(doc) {
+                    docs.entry(feature).or_insert(doc);
+                }
+            }
+        }
+    }
+    docs
+}
+
+fn table_cells(line: &str) -> Vec<String> {
+    let line = line.trim_start_matches('|').trim_end_matches('|');
+    line.split('|').map(|c| c.trim().to_string()).collect()
+}
+
+fn feature_table_columns(header: &[String]) -> Option<(usize, usize)> {
+    let header: Vec<String> = header
+        .iter()
+        .map(|h| strip_markdown(h).to_lowercase())
+        .collect();
+    let feature_col = header.iter().position(|h| h.starts_with("feature"))?;
+    let doc_col = header
+        .iter()
+        .position(|h| {
+            ["description", "purpose", "effect", "what", "enables"]
+                .iter()
+                .any(|k| h.contains(k))
+        })
+        .or_else(|| {
+            header
+                .iter()
+                .enumerate()
+                .find(|(i, h)| *i != feature_col && !h.is_empty())
+                .map(|(i, _)| i)
+        })?;
+    (doc_col != feature_col).then_some((feature_col, doc_col))
+}
+
+/// Drop inline Markdown that would look odd in a spec file.
+fn strip_markdown(text: &str) -> String {
+    let link = regex::Regex::new(r"\[([^\]]*)\]\([^)]*\)").unwrap();
+    let text = link.replace_all(text, "$1");
+    text.chars()
+        .filter(|c| !matches!(c, '`' | '*'))
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// Reduce free text to its first sentence, capitalized and ending in a
+/// period. Returns `None` when nothing useful is left.
+fn one_line(text: &str) -> Option<String> {
+    let text = strip_markdown(text)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    let sentence = match text.find(". ") {
+        Some(end) => &text[..end],
+        None => text.as_str(),
+    };
+    let sentence = sentence.trim_end_matches('.').trim();
+    if sentence.is_empty() {
+        return None;
+    }
+    let mut sentence = if sentence.chars().count() > MAX_DOC_LEN {
+        let cut: String = sentence.chars().take(MAX_DOC_LEN).collect();
+        format!("{}...", cut.trim_end())
+    } else {
+        sentence.to_string()
+    };
+    if let Some(first) = sentence.chars().next() {
+        sentence.replace_range(..first.len_utf8(), &first.to_uppercase().to_string());
+    }
+    if !sentence.ends_with("...") {
+        sentence.push('.');
+    }
+    Some(sentence)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        comment_block_doc, docs_rs_metadata_docs, manifest_comment_docs, one_line,
+        readme_table_docs,
+    };
+
+    #[test]
+    fn document_features_comments_are_picked_up() {
+        let manifest = r#"
+[package]
+name = "demo"
+version = "0.1.0"
+
+[features]
+#! ### Optional backends
+
+## Use `rustls` for TLS. Pure Rust, no OpenSSL needed.
+rustls = ["dep:rustls"]
+# Old spelling, kept for compatibility
+"native-tls" = []
+# serde = ["dep:serde"]
+plain = []
+"#;
+        let docs = manifest_comment_docs(manifest);
+        assert_eq!(docs["rustls"], "Use rustls for TLS.");
+        assert_eq!(docs["native-tls"], "Old spelling, kept for compatibility.");
+        assert!(!docs.contains_key("plain"));
+    }
+
+    #[test]
+    fn comment_block_ignores_section_headers_and_detached_comments() {
+        assert_eq!(comment_block_doc("#! Section\n"), None);
+        assert_eq!(comment_block_doc("# unrelated\n\n"), None);
+        assert_eq!(
+            comment_block_doc("# unrelated\n\n## Enables logging\n"),
+            Some("Enables logging.".to_string())
+        );
+    }
+
+    #[test]
+    fn docs_rs_metadata_table_is_used_and_list_is_skipped() {
+        let table = r#"
+[package]
+name = "demo"
+version = "0.1.0"
+
+[package.metadata.docs.rs.features]
+json = "serialize to and from [JSON](https://json.org)"
+"#;
+        let docs = docs_rs_metadata_docs(table);
+        assert_eq!(docs["json"], "Serialize to and from JSON.");
+
+        let list = r#"
+[package.metadata.docs.rs]
+features = ["json"]
+"#;
+        assert!(docs_rs_metadata_docs(list).is_empty());
+    }
+
+    #[test]
+    fn readme_feature_table_is_parsed() {
+        let readme = r#"
+# demo
+
+| Feature | Default | Description |
+|---------|:-------:|-------------|
+| `std`   | yes     | Use the standard library. |
+| **alloc** | no    | Only needs `alloc` |
+
+```text
+| feature | not | a table |
+```
+"#;
+        let docs = readme_table_docs(readme);
+        assert_eq!(docs["std"], "Use the standard library.");
+        assert_eq!(docs["alloc"], "Only needs alloc.");
+        assert_eq!(docs.len(), 2);
+    }
+
+    #[test]
+    fn one_line_truncates_long_text() {
+        let long = "word ".repeat(100);
+        let line = one_line(&long).unwrap();
+        assert!(line.ends_with("..."));
+        assert!(line.chars().count() <= super::MAX_DOC_LEN + 3);
+        assert_eq!(one_line("  "), None);
+    }
+}
@@ -0,0 +1,177 @@
+//! License files shipped in a crate, for `%license` lines in `%files`.
+
+use std::fs;
+use std::path::Path;
+
+use crate::crates::CrateInfo;
+use crate::takopack::spec::escape_rpm_macros;
+
+const LICENSE_PREFIXES: &[&str] = &["license", "licence", "copying", "notice", "unlicense"];
+
+/// License files of `crate_info` relative to the crate root, sorted.
+/// Picks up `LICENSE*`, `LICENCE*`, `COPYING*`, `NOTICE*` and `UNLICENSE`
+/// in the root, everything under a REUSE-style `LICENSES/` directory, and
+/// the manifest's `license-file` when it points inside the crate.
+pub(crate) fn find_license_files(crate_info: &CrateInfo) -> Vec<String> {
+    let Some(crate_dir) = crate_info.manifest_path().parent() else {
+        return vec![];
+    };
+    let mut files = scan_license_files(crate_dir);
+    if let Some(license_file) = crate_info.metadata().license_file.as_deref() {
+        let license_file = license_file.trim_start_matches("./");
+        if !license_file.contains("..")
+            && crate_dir.join(license_file).is_file()
+            && !files.iter().any(|f| f == license_file)
+        {
+            files.push(license_file.to_string());
+            files.sort();
+        }
+    }
+    files
+}
+
+fn scan_license_files(crate_dir: &Path) -> Vec<String> {
+    let mut files = Vec::new();
+    let Ok(entries) = fs::read_dir(crate_dir) else {
+        return files;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let path = entry.path();
+        if path.is_file() && is_license_file_name(&name) {
+            files.push(name);
+        } else if path.is_dir() && name == "LICENSES" {
+            for license in fs::read_dir(&path).into_iter().flatten().flatten() {
+                if license.path().is_file() {
+                    files.push(format!(
+                        "LICENSES/{}",
+                        license.file_name().to_string_lossy()
+                    ));
+                }
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+fn is_license_file_name(name: &str) -> bool {
+    let lower = name.to_lowercase();
+    LICENSE_PREFIXES.iter().any(|p| lower.starts_with(p))
+}
+
+/// `%license` lines for `files`.
+pub(crate) fn license_entries(files: &[String]) -> Vec<String> {
+    files
+        .iter()
+        .map(|f| {
+            let f = escape_rpm_macros(f);
+            if f.contains(char::is_whitespace) {
+                format!("%license \"{}\"", f)
+            } else {
+                format!("%license {}", f)
+            }
+        })
+        .collect()
+}
+
+/// SPDX identifiers of `license` (an SPDX expression, `/` accepted as OR)
+/// that none of `files` appears to carry. A generically named file such as
+/// plain `LICENSE` covers the expression when it has a single identifier.
+pub(crate) fn licenses_without_files(license: &str, files: &[String]) -> Vec<String> {
+    let mut ids = Vec::new();
+    let mut after_with = false;
+    for token in license
+        .split(|c: char| c.is_whitespace() || matches!(c, '(' | ')' | '/'))
+        .filter(|t| !t.is_empty())
+    {
+        match token {
+            "OR" | "AND" => {}
+            "WITH" => after_with = true,
+            // exceptions such as LLVM-exception have no file of their own
+            _ if after_with => after_with = false,
+            id => ids.push(id),
+        }
+    }
+    let file_names: Vec<String> = files
+        .iter()
+        .map(|f| f.rsplit('/').next().unwrap_or(f).to_lowercase())
+        .collect();
+    let generic = file_names.iter().any(|f| {
+        let stem = f.split('.').next().unwrap_or(f);
+        LICENSE_PREFIXES.contains(&stem)
+    });
+    if generic && ids.len() == 1 {
+        return vec![];
+    }
+
+    ids.into_iter()
+        .filter(|id| {
+            let id = id.to_lowercase();
+            // "Apache-2.0" is shipped as LICENSE-APACHE, "MIT" as LICENSE-MIT
+            let family = id.split(['-', '.']).next().unwrap_or(&id);
+            !file_names
+                .iter()
+                .any(|f| f.contains(id.as_str()) || f.contains(family))
+        })
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{license_entries, licenses_without_files, scan_license_files};
+    use std::fs;
+
+    #[test]
+    fn license_files_are_found_in_root_and_licenses_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["LICENSE-MIT", "LICENSE-APACHE", "COPYING", "README.md"] {
+            fs::write(dir.path().join(name), "").unwrap();
+        }
+        fs::create_dir_all(dir.path().join("LICENSES")).unwrap();
+        fs::write(dir.path().join("LICENSES/Zlib.txt"), "").unwrap();
+        fs::create_dir_all(dir.path().join("license-dir-is-not-a-file")).unwrap();
+
+        assert_eq!(
+            scan_license_files(dir.path()),
+            vec![
+                "COPYING",
+                "LICENSE-APACHE",
+                "LICENSE-MIT",
+                "LICENSES/Zlib.txt"
+            ]
+        );
+    }
+
+    #[test]
+    fn declared_licenses_are_matched_against_files() {
+        let dual = vec!["LICENSE-APACHE".to_string(), "LICENSE-MIT".to_string()];
+        assert!(licenses_without_files("MIT OR Apache-2.0", &dual).is_empty());
+        assert!(licenses_without_files("MIT/Apache-2.0", &dual).is_empty());
+        assert!(licenses_without_files("Apache-2.0 WITH LLVM-exception", &dual).is_empty());
+        assert_eq!(
+            licenses_without_files("(MIT OR Apache-2.0) AND Unicode-3.0", &dual),
+            vec!["Unicode-3.0"]
+        );
+
+        let generic = vec!["LICENSE".to_string()];
+        assert!(licenses_without_files("MIT", &generic).is_empty());
+        assert_eq!(
+            licenses_without_files("MIT OR Apache-2.0", &generic),
+            vec!["MIT", "Apache-2.0"]
+        );
+        assert_eq!(
+            licenses_without_files("BSD-3-Clause", &[]),
+            vec!["BSD-3-Clause"]
+        );
+    }
+
+    #[test]
+    fn license_entries_quote_and_escape() {
+        assert_eq!(
+            license_entries(&["LICENSE-MIT".to_string(), "100% FREE".to_string()]),
+            vec!["%license LICENSE-MIT", "%license \"100%% FREE\""]
+        );
+    }
+}
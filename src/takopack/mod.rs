@@ -15,24 +15,38 @@ use itertools::Itertools;
 use tar::{Archive, Builder};
 use tempfile;
 
-use crate::config::{package_field_for_feature, testing_ignore_debpolv, Config, PackageKey};
+use crate::config::{package_field_for_feature, Config, PackageKey, RequiresFloor, WildcardDeps};
 use crate::crates::{
-    all_dependencies_and_features, show_dep, transitive_deps, CrateDepInfo, CrateInfo,
+    all_dependencies_and_features, show_dep, split_build_dependencies, transitive_deps,
+    weak_dependency_features, CrateDepInfo, CrateInfo, SparseIndex,
 };
 use crate::errors::*;
+use crate::tarball::{self, EntryCheck};
+use crate::test_mode::TestMode;
+use crate::toolchain_crates::ToolchainCrates;
 use crate::util::{self, copy_tree, expect_success, get_transitive_val, traverse_depth};
 
 use self::control::{base_deb_name, deb_upstream_version};
 use self::control::{Description, Package, PkgTest, Source};
-pub use self::dependency::{deb_dep_add_nocheck, deb_deps};
+pub use self::dependency::{coerce_prerelease, deb_dep_add_nocheck, deb_deps};
+use self::no_std::NoStdSupport;
 use self::spec::{
-    render_build_check_install_placeholder, render_changelog_section, render_files_section,
-    render_patch_prep_placeholder, SpecFiles,
+    render_changelog_section, render_prep_section, ChangelogEntry, CheckRun, CrateCapability,
+    CrateRequirement, SpecAsset, SpecFiles, SpecPackage, SpecSource,
 };
+use self::template::SpecTemplates;
 
+pub mod bin_assets;
+pub mod changelog;
 pub mod control;
+mod copyright;
 mod dependency;
+mod feature_docs;
+mod license_files;
+mod no_std;
 pub mod spec;
+pub mod targets;
+pub mod template;
 
 pub struct DebInfo {
     upstream_name: String,
@@ -51,7 +65,12 @@ pub struct DebInfo {
 }
 
 impl DebInfo {
-    pub fn new(crate_info: &CrateInfo, takopack_version: &str, semver_suffix: bool) -> Self {
+    pub fn new(
+        crate_info: &CrateInfo,
+        takopack_version: &str,
+        semver_suffix: bool,
+        test_mode: TestMode,
+    ) -> Self {
         let upstream_name = crate_info.package_id().name().to_string();
         let name_dashed = base_deb_name(&upstream_name);
         let base_package_name = name_dashed.to_lowercase();
@@ -72,13 +91,13 @@ impl DebInfo {
         };
         let package_source_dir = PathBuf::from(format!(
             "{}-{}-{}",
-            Source::pkg_prefix(),
+            test_mode.pkg_prefix(),
             package_name,
             deb_upstream_version
         ));
         let orig_tarball_path = PathBuf::from(format!(
             "{}-{}_{}.orig.tar.gz",
-            Source::pkg_prefix(),
+            test_mode.pkg_prefix(),
             package_name,
             deb_upstream_version
         ));
@@ -158,6 +177,16 @@ impl Clone for DebInfo {
     }
 }
 
+/// A temporary directory next to `path`, so that nothing is written to the
+/// current directory and files in it can be renamed to `path`.
+fn tempdir_next_to(path: &Path) -> Result<tempfile::TempDir> {
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    Ok(tempfile::Builder::new().prefix("tp").tempdir_in(dir)?)
+}
+
 pub fn prepare_orig_tarball(
     crate_info: &CrateInfo,
     tarball: &Path,
@@ -165,9 +194,7 @@ pub fn prepare_orig_tarball(
     output_dir: &Path,
 ) -> Result<()> {
     let crate_file = crate_info.crate_file();
-    let tempdir = tempfile::Builder::new()
-        .prefix("takopack")
-        .tempdir_in(".")?;
+    let tempdir = tempdir_next_to(tarball)?;
     let temp_archive_path = tempdir.path().join(tarball);
 
     // Remove existing tarball file if it exists to avoid "File exists" error
@@ -190,7 +217,11 @@ pub fn prepare_orig_tarball(
 
         for entry in archive.entries()? {
             let entry = entry?;
-            let path = entry.path()?.into_owned();
+            // Entries left out when the crate was extracted stay out of the
+            // orig tarball too.
+            let EntryCheck::Keep(path) = tarball::check_entry(&entry)? else {
+                continue;
+            };
             if path.ends_with("Cargo.toml") && path.iter().count() == 2 {
                 // Put the rewritten and original Cargo.toml back into the orig tarball
                 let mut new_archive_append = |name: &str| {
@@ -204,17 +235,25 @@ pub fn prepare_orig_tarball(
                 new_archive_append("Cargo.toml")?;
                 new_archive_append("Cargo.toml.orig")?;
             } else {
-                match crate_info.filter_path(&entry.path()?) {
+                match crate_info.filter_path(&path) {
                     Err(e) => takopack_bail!(e),
                     Ok(r) => {
                         if !r {
-                            new_archive.append_data(&mut entry.header().clone(), path, entry)?;
+                            let mut header = entry.header().clone();
+                            // The header alone would lose link targets kept
+                            // in pax or GNU long-link records.
+                            let target = entry.link_name()?.map(|t| t.into_owned());
+                            match target {
+                                Some(target) if header.entry_type().is_symlink() => {
+                                    new_archive.append_link(&mut header, path, target)?
+                                }
+                                _ => new_archive.append_data(&mut header, path, entry)?,
+                            }
                         } else {
-                            writeln!(
-                                io::stderr(),
+                            takopack_info!(
                                 "Filtered out files from .orig.tar.gz: {:?}",
                                 &entry.path()?
-                            )?;
+                            );
                         }
                     }
                 }
@@ -236,9 +275,7 @@ pub fn apply_overlay_and_patches(
     config: &Config,
     output_dir: &Path,
 ) -> Result<tempfile::TempDir> {
-    let tempdir = tempfile::Builder::new()
-        .prefix("takopack")
-        .tempdir_in(".")?;
+    let tempdir = tempdir_next_to(output_dir)?;
     let overlay = config.overlay_dir(config_path);
     if let Some(p) = overlay.as_ref() {
         for anc in tempdir.path().ancestors() {
@@ -317,7 +354,8 @@ pub fn prepare_takopack_folder(
     output_dir: &Path,
     tempdir: &tempfile::TempDir,
     changelog_ready: bool,
-    _copyright_guess_harder: bool,
+    changelog_from: changelog::ChangelogSource,
+    copyright_guess_harder: bool,
     overlay_write_back: bool,
     sha256: Option<String>, // SHA256 hash of downloaded crate
     lockfile_deps: Option<std::collections::HashMap<String, semver::Version>>, // Optional: dependencies from Cargo.lock
@@ -325,13 +363,15 @@ pub fn prepare_takopack_folder(
 ) -> Result<()> {
     let mut create = fs::OpenOptions::new();
     create.write(true).create_new(true);
+    let data_mode = config.permissions.mode(false)?;
+    let executable_mode = config.permissions.mode(true)?;
 
     let mut new_hints = vec![];
     let mut file = |name: &str| {
         let path = tempdir.path();
         let f = path.join(name);
         fs::create_dir_all(f.parent().unwrap())?;
-        create.open(&f).or_else(|e| match e.kind() {
+        let file = create.open(&f).or_else(|e| match e.kind() {
             ErrorKind::AlreadyExists => {
                 let hintname = name.to_owned() + util::HINT_SUFFIX;
                 let hint = path.join(&hintname);
@@ -342,7 +382,10 @@ pub fn prepare_takopack_folder(
                 create.open(&hint)
             }
             _ => Err(e),
-        })
+        })?;
+        // not left to the umask
+        file.set_permissions(fs::Permissions::from_mode(data_mode))?;
+        Ok::<_, io::Error>(file)
     };
 
     // takopack/cargo-checksum.json
@@ -358,6 +401,9 @@ pub fn prepare_takopack_folder(
         )?;
     }
 
+    let changelog = changelog::changelog_entries(changelog_from, crate_info, config.maintainer());
+    let templates = SpecTemplates::load_optional(config.template_dir(config_path).as_deref())?;
+
     // takopack/control & takopack/tests/control
     let (_source, has_dev_depends, default_test_broken) = prepare_takopack_control(
         deb_info,
@@ -365,16 +411,26 @@ pub fn prepare_takopack_folder(
         config,
         sha256,
         lockfile_deps.as_ref(),
+        &changelog,
+        &templates,
         &mut file,
         with_spdx,
     )?;
 
+    // takopack/copyright
+    if copyright_guess_harder {
+        let report = copyright::copyright_report(crate_info, output_dir)?;
+        let mut copyright = file("copyright")?;
+        write!(copyright, "{}", report)?;
+    }
+
     // for testing only, takopack/takopack_testing_bin/env
-    if testing_ignore_debpolv() {
+    let test_mode = config.test_mode;
+    if test_mode.ignore_policy_violations {
         fs::create_dir_all(tempdir.path().join("takopack_testing_bin"))?;
         let mut env_hack = file("takopack_testing_bin/env")?;
         #[cfg(unix)]
-        env_hack.set_permissions(fs::Permissions::from_mode(0o777))?;
+        env_hack.set_permissions(fs::Permissions::from_mode(executable_mode))?;
         // intercept calls to dh-cargo-built-using
         writeln!(
             env_hack,
@@ -390,8 +446,8 @@ echo "takopack testing: suppressing dh-cargo-built-using";;
     {
         let mut rules = file("rules")?;
         #[cfg(unix)]
-        rules.set_permissions(fs::Permissions::from_mode(0o777))?;
-        if has_dev_depends || testing_ignore_debpolv() {
+        rules.set_permissions(fs::Permissions::from_mode(executable_mode))?;
+        if has_dev_depends || test_mode.ignore_policy_violations {
             // don't run any tests, we don't want extra B-D on dev-depends
             // this could potentially cause B-D cycles so we avoid it
             //
@@ -410,7 +466,7 @@ echo "takopack testing: suppressing dh-cargo-built-using";;
             // some crates need nightly to compile, annoyingly. only do this in
             // testing; outside of testing the user should explicitly override
             // takopack/rules to do this
-            if testing_ignore_debpolv() {
+            if test_mode.ignore_policy_violations {
                 writeln!(rules, "export RUSTC_BOOTSTRAP := 1")?;
                 writeln!(
                     rules,
@@ -462,12 +518,15 @@ echo "takopack testing: suppressing dh-cargo-built-using";;
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 fn prepare_takopack_control<F: FnMut(&str) -> std::result::Result<fs::File, io::Error>>(
     deb_info: &DebInfo,
     crate_info: &CrateInfo,
     config: &Config,
     sha256: Option<String>, // SHA256 hash of downloaded crate
     lockfile_deps: Option<&HashMap<String, semver::Version>>, // Optional lockfile dependencies
+    changelog: &[ChangelogEntry],
+    templates: &SpecTemplates,
     mut file: F,
     with_spdx: bool,
 ) -> Result<(Source, bool, bool)> {
@@ -478,15 +537,39 @@ fn prepare_takopack_control<F: FnMut(&str) -> std::result::Result<fs::File, io::
 
     let lib = crate_info.is_lib();
     let (bins, bin_name) = selected_binary_targets(crate_info, deb_info, config, lib);
-    let prepared =
-        prepare_control_source(deb_info, crate_info, config, sha256, lib, &bins, with_spdx)?;
+    let prepared = prepare_control_source(
+        deb_info,
+        crate_info,
+        config,
+        sha256,
+        lockfile_deps,
+        lib,
+        &bins,
+        with_spdx,
+    )?;
+    // Floors taken from the index replace the locked versions
+    let lockfile_deps = lockfile_deps.filter(|_| config.requires_floor == RequiresFloor::Lockfile);
 
-    let output_names = util::rust_crate_output_names(crate_name, crate_info.version());
+    let no_std = if lib {
+        no_std::detect_no_std(crate_info)
+    } else {
+        None
+    };
+    if let Some(support) = &no_std {
+        log::info!("{} supports no_std: {:?}", crate_name, support);
+    }
+
+    let output_names = config.output_names(crate_name, crate_info.version());
     let mut control = io::BufWriter::new(file(&output_names.spec_file)?);
-    write!(control, "{}", prepared.source)?;
+    write!(
+        control,
+        "{}",
+        templates.source(&prepared.source.spec_source())?
+    )?;
 
+    let mut checks = Vec::new();
     if lib {
-        write_library_packages(
+        checks = write_library_packages(
             &mut control,
             &mut file,
             &prepared.source,
@@ -501,7 +584,11 @@ fn prepare_takopack_control<F: FnMut(&str) -> std::result::Result<fs::File, io::
             &prepared.description_prefix,
             &prepared.test_deps,
             lockfile_deps,
+            templates,
         )?;
+        if let Some(support) = no_std.as_ref().filter(|_| config.no_std.subpackage()) {
+            write_no_std_package(&mut control, config, &prepared, support, templates)?;
+        }
     } else if !bins.is_empty() {
         write_binary_only_package(
             &mut control,
@@ -515,11 +602,15 @@ fn prepare_takopack_control<F: FnMut(&str) -> std::result::Result<fs::File, io::
             &prepared.summary_prefix,
             &prepared.description_prefix,
             lockfile_deps,
+            templates,
         )?;
     }
 
-    if !bins.is_empty() {
-        prepare_binary_package_for_overrides(
+    let bin_package = if bins.is_empty() {
+        None
+    } else {
+        Some(write_binary_package(
+            &mut control,
             config,
             lib,
             bin_name,
@@ -529,11 +620,44 @@ fn prepare_takopack_control<F: FnMut(&str) -> std::result::Result<fs::File, io::
             &prepared.summary_prefix,
             &prepared.description_prefix,
             lockfile_deps,
-        );
-    }
+            templates,
+        )?)
+    };
 
-    write_extra_packages(&mut control, config)?;
-    write_trailing_spec_sections(&mut control)?;
+    let target_files =
+        write_target_packages(&mut control, crate_info, config, &prepared, templates)?;
+    write_extra_packages(&mut control, config, templates)?;
+    let license_files = license_files::find_license_files(crate_info);
+    warn_on_missing_license_files(crate_info, &license_files);
+    // Without default features to turn off, a run without them would repeat
+    // the one with them.
+    let has_default_features = prepared
+        .features_with_deps
+        .get("default")
+        .is_some_and(|(features, deps)| !features.is_empty() || !deps.is_empty());
+    let no_default_features = |check: &CheckRun| check.args == ["-n"];
+    if !has_default_features {
+        checks.retain(|check| !no_default_features(check));
+    } else if no_std.is_some() && config.no_std.check() && !checks.iter().any(no_default_features) {
+        if checks.is_empty() {
+            checks.push(CheckRun::default());
+        }
+        checks.push(CheckRun {
+            args: vec!["-n".to_string()],
+            ..CheckRun::default()
+        });
+    }
+    write_trailing_spec_sections(
+        &mut control,
+        config,
+        &prepared.source.spec_source(),
+        &license_files,
+        changelog,
+        &checks,
+        bin_package.as_deref(),
+        target_files,
+        templates,
+    )?;
 
     let default_test_broken =
         feature_test_is_broken(config, &prepared.features_with_deps, "default")?;
@@ -576,20 +700,39 @@ fn selected_binary_targets<'a>(
     (bins, bin_name)
 }
 
+#[allow(clippy::too_many_arguments)]
 fn prepare_control_source(
     deb_info: &DebInfo,
     crate_info: &CrateInfo,
     config: &Config,
     sha256: Option<String>,
+    lockfile_deps: Option<&HashMap<String, semver::Version>>,
     lib: bool,
     bins: &[&str],
     with_spdx: bool,
 ) -> Result<PreparedControl> {
     let crate_name = crate_info.crate_name();
-    let features_with_deps = all_dependencies_and_features(crate_info.manifest())?;
+    let mut features_with_deps = all_dependencies_and_features(crate_info.manifest())?;
+    ToolchainCrates::from_config(config)?.retain_packaged(&mut features_with_deps);
+    coerce_prerelease_deps(config, &mut features_with_deps);
+    resolve_wildcard_deps(
+        config.wildcard_deps,
+        crate_name,
+        &mut features_with_deps,
+        lockfile_deps,
+    )?;
+    let build_dependencies = if config.build_deps_in_requires.contains(crate_name) {
+        Vec::new()
+    } else {
+        split_build_dependencies(&mut features_with_deps)?
+    };
     log_feature_deps("features_with_deps", &features_with_deps);
 
-    let dev_depends = deb_deps(config.allow_prerelease_deps, &crate_info.dev_dependencies())?;
+    let dev_depends = deb_deps(
+        config.allow_prerelease_deps,
+        &crate_info.dev_dependencies(),
+        config.test_mode,
+    )?;
     let has_dev_deps = !dev_depends.is_empty();
     let build_deps = build_deps_for_source(config, crate_info, &features_with_deps, lib, bins)?;
     let test_deps: Vec<String> = Some(rustc_dep(&crate_info.rust_version(), false))
@@ -618,8 +761,48 @@ fn prepare_control_source(
         build_deps,
         full_version,
         sha256,
+        config.test_mode,
     )?;
     source.apply_overrides(config, with_spdx);
+    source.set_toolchain(crate_info.edition(), crate_info.rust_version());
+    source.set_yanked(crate_info.is_yanked());
+    source.set_binaries(bins);
+    source.set_bin_assets(bin_assets::detect(crate_info, config, bins));
+    match targets::c_library(crate_info) {
+        Some(library) if config.target_packages.c_api => source.set_c_library(Some(library)),
+        Some(library) => takopack_info!(
+            "{} builds the C library lib{}; set c_api = true in [target_packages] to package it.",
+            crate_name,
+            library.name
+        ),
+        None => {}
+    }
+    if config.test_build_requires.enabled {
+        let requirements = control::test_build_requirements(
+            &crate_info.dev_dependencies(),
+            crate_name,
+            &config.test_build_requires,
+        );
+        source.set_test_build_requires(
+            requirements
+                .iter()
+                .map(|r| spec::render_crate_requirement(config.crate_namespace(), r))
+                .collect(),
+        );
+    }
+    if !build_dependencies.is_empty() {
+        let requirements =
+            control::crate_requirements_from_cargo_deps(&build_dependencies, crate_name, false);
+        source.set_build_dependency_requires(
+            requirements
+                .iter()
+                .map(|r| spec::render_crate_requirement(config.crate_namespace(), r))
+                .collect(),
+        );
+    }
+    if let Some(url) = crate_info.download_url() {
+        source.set_source_url(url);
+    }
 
     let (crate_summary, crate_description) = crate_info.get_summary_description();
     let summary_prefix = crate_summary.unwrap_or(format!("Rust crate \"{}\"", crate_name));
@@ -665,7 +848,11 @@ fn build_deps_for_source(
     );
     let build_deps_arch = toolchain_deps(&crate_info.rust_version())
         .into_iter()
-        .chain(deb_deps(config.allow_prerelease_deps, &default_deps)?)
+        .chain(deb_deps(
+            config.allow_prerelease_deps,
+            &default_deps,
+            config.test_mode,
+        )?)
         .chain(extra_override_deps);
 
     if !bins.is_empty() {
@@ -746,11 +933,13 @@ fn write_library_packages<F>(
     description_prefix: &str,
     test_deps: &[String],
     lockfile_deps: Option<&HashMap<String, semver::Version>>,
-) -> Result<()>
+    templates: &SpecTemplates,
+) -> Result<Vec<CheckRun>>
 where
     F: FnMut(&str) -> std::result::Result<fs::File, io::Error>,
 {
     let all_features: Vec<&str> = features_with_deps.keys().copied().collect();
+    let floors = requires_floors(config, crate_info)?;
     let all_features_test_broken = match config.package_test_is_broken(PackageKey::feature("@")) {
         Some(v) => v,
         None => all_features.iter().any(|f| {
@@ -777,26 +966,25 @@ where
     let all_features_test_depends =
         generate_test_dependencies("@", &all_features, config, test_deps);
     let mut testctl = io::BufWriter::new(file("tests/control")?);
-    write!(
-        testctl,
-        "{}",
-        PkgTest::new(
-            source.name(),
-            crate_name,
-            "@",
-            deb_upstream_version,
-            vec!["--all-features"],
-            &all_features_test_depends,
-            if all_features_test_broken {
-                vec!["flaky"]
-            } else {
-                vec![]
-            },
-            all_features_test_arch.deref(),
-        )?
+    let all_features_test = PkgTest::new(
+        source.name(),
+        crate_name,
+        "@",
+        deb_upstream_version,
+        vec!["--all-features"],
+        &all_features_test_depends,
+        if all_features_test_broken {
+            vec!["flaky"]
+        } else {
+            vec![]
+        },
+        all_features_test_arch.deref(),
     )?;
+    write!(testctl, "{}", all_features_test)?;
+    let mut checks = vec![all_features_test.check_run()];
 
-    let transformed = transform_feature_packages(features_with_deps.clone(), config)?;
+    let collapse = config.collapse_features || config.provides_only_features(crate_name);
+    let transformed = transform_feature_packages(features_with_deps.clone(), collapse)?;
     let mut provides = transformed.provides;
     let reduced_features_with_deps = transformed.reduced_features_with_deps;
     let original_features = transformed.original_features;
@@ -804,6 +992,21 @@ where
     let no_features_edge_case = is_no_features_edge_case(features_with_deps);
     let all_subpackage_features =
         collect_subpackage_features(&reduced_features_with_deps, &provides);
+    let feature_docs = feature_docs::collect_feature_docs(crate_info);
+    let weak_features = weak_dependency_features(crate_info.manifest());
+    let exclusive_features = config.exclusive_features(crate_name);
+    for feature in exclusive_features.iter().flatten() {
+        let known = original_features
+            .iter()
+            .any(|f| spec::normalize_feature_name(f) == spec::normalize_feature_name(feature));
+        if !known {
+            takopack_warn!(
+                "exclusive_features for {}: no feature named {:?}",
+                crate_name,
+                feature
+            );
+        }
+    }
 
     for (feature, (f_deps, o_deps)) in reduced_features_with_deps.into_iter() {
         let pk = PackageKey::feature(feature);
@@ -812,7 +1015,12 @@ where
         crate_features.push(feature);
 
         let summary_suffix = package_summary_suffix(feature, &f_provides);
-        let description_suffix = package_description_suffix(crate_name, feature, &f_provides);
+        let description_suffix = package_description_suffix(
+            crate_name,
+            feature,
+            &f_provides,
+            feature_docs.get(feature).map(String::as_str),
+        );
         let package_all_features = if feature.is_empty() {
             original_features
                 .iter()
@@ -841,7 +1049,7 @@ where
                 Some(feature)
             },
             f_deps,
-            deb_deps(config.allow_prerelease_deps, &o_deps)?,
+            deb_deps(config.allow_prerelease_deps, &o_deps, config.test_mode)?,
             o_deps.clone(),
             f_provides.clone(),
             if feature.is_empty() {
@@ -855,13 +1063,23 @@ where
                 vec![]
             },
             package_all_features,
+            config.test_mode,
         )?;
 
+        let weak_deps: Vec<_> = crate_features
+            .iter()
+            .filter_map(|f| weak_features.get(f))
+            .flatten()
+            .cloned()
+            .collect();
+        package.apply_weak_dependency_features(&weak_deps);
         if let Some(lockfile) = lockfile_deps {
             package.apply_lockfile_deps(lockfile);
         }
+        package.apply_requires_floors(&floors);
         package.apply_overrides(config, pk, f_provides);
-        write!(control, "{}", package)?;
+        package.apply_exclusive_features(exclusive_features);
+        write!(control, "{}", templates.package(&package.spec_package()?)?)?;
 
         if !feature.is_empty() {
             let mut overrides =
@@ -876,6 +1094,7 @@ where
         if !no_features_edge_case {
             write_feature_tests(
                 &mut testctl,
+                &mut checks,
                 &package,
                 crate_name,
                 deb_upstream_version,
@@ -887,7 +1106,9 @@ where
         }
     }
     assert!(provides.is_empty());
-    Ok(())
+    // the default features first, as the run that matters most
+    checks.sort_by_key(|check| !check.args.is_empty());
+    Ok(checks)
 }
 
 struct TransformedFeatures {
@@ -898,7 +1119,7 @@ struct TransformedFeatures {
 
 fn transform_feature_packages(
     mut working_features_with_deps: CrateDepInfo,
-    config: &Config,
+    collapse: bool,
 ) -> Result<TransformedFeatures> {
     let potential_corner_case = working_features_with_deps
         .keys()
@@ -953,7 +1174,7 @@ fn transform_feature_packages(
         .filter(|&k| !k.is_empty())
         .map(|k| k.to_string())
         .collect();
-    let (provides, reduced_features_with_deps) = if config.collapse_features {
+    let (provides, reduced_features_with_deps) = if collapse {
         collapse_features(working_features_with_deps)
     } else {
         reduce_provides(working_features_with_deps)
@@ -968,6 +1189,82 @@ fn transform_feature_packages(
     })
 }
 
+/// Apply `policy` to the dependencies declared as `*`. Pinned ones require
+/// the version `lockfile_deps` locks, in its compat stream.
+fn resolve_wildcard_deps(
+    policy: WildcardDeps,
+    crate_name: &str,
+    features_with_deps: &mut CrateDepInfo,
+    lockfile_deps: Option<&HashMap<String, semver::Version>>,
+) -> Result<()> {
+    let mut warned = BTreeSet::new();
+    for (_, deps) in features_with_deps.values_mut() {
+        for dep in deps.iter_mut() {
+            if dep.version_req().to_string() != "*" {
+                continue;
+            }
+            let name = dep.package_name().to_string();
+            match policy {
+                WildcardDeps::Unversioned => {}
+                WildcardDeps::Error => takopack_bail!(
+                    "{} depends on {} = \"*\", which wildcard_deps = \"error\" does not allow",
+                    crate_name,
+                    name
+                ),
+                WildcardDeps::Warn => {
+                    if warned.insert(name.clone()) {
+                        takopack_warn!(
+                            "{} depends on {} = \"*\"; it is required without a version",
+                            crate_name,
+                            name
+                        );
+                    }
+                }
+                WildcardDeps::PinToLockfile => {
+                    let Some(locked) = lockfile_deps.and_then(|locked| {
+                        locked
+                            .get(&name)
+                            .or_else(|| locked.get(&name.replace('_', "-")))
+                    }) else {
+                        takopack_bail!(
+                            "{} depends on {} = \"*\" and no Cargo.lock locks it to pin it to",
+                            crate_name,
+                            name
+                        );
+                    };
+                    let pinned = semver::Comparator {
+                        op: semver::Op::Caret,
+                        major: locked.major,
+                        minor: Some(locked.minor),
+                        patch: Some(locked.patch),
+                        pre: locked.pre.clone(),
+                    };
+                    dep.set_version_req(cargo::util::OptVersionReq::Req(semver::VersionReq {
+                        comparators: vec![pinned],
+                    }));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Turn requirements on pre-releases into requirements on the release, for
+/// the dependencies [`Config::allow_prerelease_dep`] does not allow them.
+fn coerce_prerelease_deps(config: &Config, features_with_deps: &mut CrateDepInfo) {
+    for (feature, (_, deps)) in features_with_deps.iter_mut() {
+        let key = PackageKey::feature(feature);
+        for dep in deps.iter_mut() {
+            if config.allow_prerelease_dep(key, dep.package_name().as_str()) {
+                continue;
+            }
+            if let Some(release) = coerce_prerelease(dep) {
+                *dep = release;
+            }
+        }
+    }
+}
+
 fn log_feature_deps(label: &str, features_with_deps: &CrateDepInfo) {
     log::trace!(
         "{}: {:?}",
@@ -1032,14 +1329,23 @@ fn package_summary_suffix(feature: &str, f_provides: &[&str]) -> String {
     }
 }
 
-fn package_description_suffix(crate_name: &str, feature: &str, f_provides: &[&str]) -> String {
+fn package_description_suffix(
+    crate_name: &str,
+    feature: &str,
+    f_provides: &[&str],
+    feature_doc: Option<&str>,
+) -> String {
     if feature.is_empty() {
         format!("Source code for takopackized Rust crate \"{}\"", crate_name)
     } else {
         format!(
-            "This metapackage enables feature \"{}\" for the \
+            "{}This metapackage enables feature \"{}\" for the \
              Rust {} crate, by pulling in any additional \
              dependencies needed by that feature.{}",
+            match feature_doc {
+                Some(doc) => format!("Feature \"{}\": {}\n\n", feature, doc),
+                None => "".to_string(),
+            },
             feature,
             crate_name,
             match f_provides.len() {
@@ -1063,6 +1369,7 @@ fn package_description_suffix(crate_name: &str, feature: &str, f_provides: &[&st
 #[allow(clippy::too_many_arguments)]
 fn write_feature_tests(
     testctl: &mut io::BufWriter<fs::File>,
+    checks: &mut Vec<CheckRun>,
     package: &Package,
     crate_name: &str,
     deb_upstream_version: &str,
@@ -1102,6 +1409,10 @@ fn write_feature_tests(
             test_arch.deref(),
         )?;
         write!(testctl, "\n{}", pkgtest)?;
+        let check = pkgtest.check_run();
+        if !checks.iter().any(|c| c.args == check.args) {
+            checks.push(check);
+        }
     }
     Ok(())
 }
@@ -1119,6 +1430,7 @@ fn write_binary_only_package(
     summary_prefix: &str,
     description_prefix: &str,
     lockfile_deps: Option<&HashMap<String, semver::Version>>,
+    templates: &SpecTemplates,
 ) -> Result<()> {
     let empty_deps = (vec![], vec![]);
     let (_, base_deps) = features_with_deps.get("").unwrap_or(&empty_deps);
@@ -1138,24 +1450,28 @@ fn write_binary_only_package(
         },
         None,
         vec![],
-        deb_deps(config.allow_prerelease_deps, base_deps)?,
+        deb_deps(config.allow_prerelease_deps, base_deps, config.test_mode)?,
         base_deps.clone(),
         vec![],
         vec![],
         vec![],
         vec![],
+        config.test_mode,
     )?;
 
     if let Some(lockfile) = lockfile_deps {
         package.apply_lockfile_deps(lockfile);
     }
+    package.apply_requires_floors(&requires_floors(config, crate_info)?);
     package.apply_overrides(config, PackageKey::feature(""), vec![]);
-    write!(control, "{}", package)?;
+    write!(control, "{}", templates.package(&package.spec_package()?)?)?;
     Ok(())
 }
 
+/// The package of the crate's executables. Returns its name.
 #[allow(clippy::too_many_arguments)]
-fn prepare_binary_package_for_overrides(
+fn write_binary_package(
+    control: &mut io::BufWriter<fs::File>,
     config: &Config,
     lib: bool,
     bin_name: &str,
@@ -1165,7 +1481,8 @@ fn prepare_binary_package_for_overrides(
     summary_prefix: &str,
     description_prefix: &str,
     lockfile_deps: Option<&HashMap<String, semver::Version>>,
-) {
+    templates: &SpecTemplates,
+) -> Result<String> {
     let mut bin_pkg = Package::new_bin(
         bin_name,
         name_suffix,
@@ -1188,7 +1505,30 @@ fn prepare_binary_package_for_overrides(
         bin_pkg.apply_lockfile_deps(lockfile);
     }
     bin_pkg.apply_overrides(config, PackageKey::Bin, vec![]);
-    // Skip bin package output for RPM spec - we only need library packages.
+    write!(
+        control,
+        "{}",
+        templates.package(&bin_pkg.spec_binary_package())?
+    )?;
+    Ok(bin_pkg.name().to_string())
+}
+
+/// Floors replacing those of the Cargo requirements, with
+/// `requires_floor = "oldest"`.
+fn requires_floors(
+    config: &Config,
+    crate_info: &CrateInfo,
+) -> Result<HashMap<String, semver::Version>> {
+    match config.requires_floor {
+        RequiresFloor::Lockfile => Ok(HashMap::new()),
+        RequiresFloor::Oldest => {
+            let index = SparseIndex::crates_io()?;
+            Ok(control::oldest_requires_floors(
+                crate_info.dependencies(),
+                |name| index.versions(name),
+            ))
+        }
+    }
 }
 
 fn binary_description_suffix(crate_name: &str, bins: &[&str]) -> String {
@@ -1199,30 +1539,170 @@ fn binary_description_suffix(crate_name: &str, bins: &[&str]) -> String {
     )
 }
 
-fn write_extra_packages(control: &mut io::BufWriter<fs::File>, config: &Config) -> Result<()> {
+/// The `+no-std` subpackage, a marker for crates usable without the standard
+/// library. Skipped when a feature of the crate would get the same name.
+fn write_no_std_package(
+    control: &mut io::BufWriter<fs::File>,
+    config: &Config,
+    prepared: &PreparedControl,
+    support: &NoStdSupport,
+    templates: &SpecTemplates,
+) -> Result<()> {
+    if prepared
+        .features_with_deps
+        .keys()
+        .any(|f| spec::normalize_feature_name(f) == no_std::NO_STD_FEATURE)
+    {
+        takopack_warn!(
+            "Not generating a +{} subpackage: the crate has a feature of that name.",
+            no_std::NO_STD_FEATURE
+        );
+        return Ok(());
+    }
+    let package = SpecPackage {
+        feature: Some(no_std::NO_STD_FEATURE.to_string()),
+        summary: format!("{} - no_std support", prepared.summary_prefix),
+        description: format!("{}{}", prepared.description_prefix, support.description()),
+        requires: vec![CrateRequirement::same_crate(None)],
+        provides: vec![CrateCapability::package_feature(Some(
+            no_std::NO_STD_FEATURE.to_string(),
+        ))],
+        crate_namespace: config.crate_namespace.clone(),
+        requires_style: config.requires_style,
+        ..SpecPackage::default()
+    };
+    write!(control, "{}", templates.package(&package)?)?;
+    Ok(())
+}
+
+/// The packages of the C API library and of the examples, see
+/// [`targets`]. Returns their `%files`.
+fn write_target_packages(
+    control: &mut io::BufWriter<fs::File>,
+    crate_info: &CrateInfo,
+    config: &Config,
+    prepared: &PreparedControl,
+    templates: &SpecTemplates,
+) -> Result<Vec<SpecFiles>> {
+    let mut packages = Vec::new();
+    if let Some(library) = prepared.source.c_library() {
+        packages.extend(targets::c_library_packages(
+            library,
+            &prepared.summary_prefix,
+            &prepared.description_prefix,
+        ));
+    }
+    if config.target_packages.examples {
+        let examples = targets::examples(crate_info);
+        if !examples.is_empty() {
+            packages.push(targets::examples_package(
+                &examples,
+                &prepared.summary_prefix,
+                &prepared.description_prefix,
+            ));
+        }
+    }
+    let mut files = Vec::new();
+    for (package, package_files) in packages {
+        write!(control, "{}", templates.package(&package)?)?;
+        files.push(package_files);
+    }
+    Ok(files)
+}
+
+fn write_extra_packages(
+    control: &mut io::BufWriter<fs::File>,
+    config: &Config,
+    templates: &SpecTemplates,
+) -> Result<()> {
     for configured in config.configured_packages() {
         if let PackageKey::Extra(package) = configured {
             let mut extra_pkg = Package::new_extra(package.to_string());
             extra_pkg.apply_overrides(config, configured, vec![]);
-            write!(control, "\n{}", extra_pkg)?;
+            write!(
+                control,
+                "\n{}",
+                templates.package(&extra_pkg.spec_package()?)?
+            )?;
         }
     }
     Ok(())
 }
 
-fn write_trailing_spec_sections(control: &mut io::BufWriter<fs::File>) -> Result<()> {
+fn warn_on_missing_license_files(crate_info: &CrateInfo, license_files: &[String]) {
+    let Some(license) = crate_info.metadata().license.as_deref() else {
+        return;
+    };
+    if license_files.is_empty() {
+        takopack_warn!(
+            "{} declares license {} but ships no license files",
+            crate_info.crate_name(),
+            license
+        );
+        return;
+    }
+    let missing = license_files::licenses_without_files(license, license_files);
+    if !missing.is_empty() {
+        takopack_warn!(
+            "{}: no license file found for {} (shipped: {})",
+            crate_info.crate_name(),
+            missing.join(", "),
+            license_files.join(", ")
+        );
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn write_trailing_spec_sections(
+    control: &mut io::BufWriter<fs::File>,
+    config: &Config,
+    source: &SpecSource,
+    license_files: &[String],
+    changelog: &[ChangelogEntry],
+    checks: &[CheckRun],
+    bin_package: Option<&str>,
+    target_files: Vec<SpecFiles>,
+    templates: &SpecTemplates,
+) -> Result<()> {
     writeln!(control)?;
     let mut trailing_sections = String::new();
-    render_patch_prep_placeholder(&mut trailing_sections)?;
-    render_build_check_install_placeholder(&mut trailing_sections)?;
-    render_files_section(
-        &mut trailing_sections,
-        &[SpecFiles {
-            package: None,
-            entries: vec!["%{_datadir}/cargo/registry/%{crate_name}-%{version}/".to_string()],
-        }],
-    )?;
-    render_changelog_section(&mut trailing_sections)?;
+    render_prep_section(&mut trailing_sections, config.macro_flavor)?;
+    trailing_sections.push_str(&templates.check(
+        config.macro_flavor,
+        config.tests,
+        checks,
+        &source.bins,
+        &source.bin_assets,
+        source.c_library.as_ref(),
+    )?);
+    let mut entries = license_files::license_entries(license_files);
+    entries.push("%{_datadir}/cargo/registry/%{crate_name}-%{version}/".to_string());
+    let mut files = vec![SpecFiles {
+        package: None,
+        entries,
+    }];
+    if let Some(package) = bin_package {
+        let mut entries = license_files::license_entries(license_files);
+        entries.extend(
+            source
+                .bins
+                .iter()
+                .map(|bin| format!("%{{_bindir}}/{}", bin)),
+        );
+        entries.extend(source.bin_assets.iter().map(SpecAsset::files_entry));
+        files.push(SpecFiles {
+            package: Some(package.to_string()),
+            entries,
+        });
+    }
+    for mut target in target_files {
+        let mut entries = license_files::license_entries(license_files);
+        entries.append(&mut target.entries);
+        target.entries = entries;
+        files.push(target);
+    }
+    trailing_sections.push_str(&templates.files(&files)?);
+    render_changelog_section(&mut trailing_sections, changelog, config.autochangelog)?;
     write!(control, "{}", trailing_sections)?;
     Ok(())
 }
@@ -1359,7 +1839,15 @@ fn rustc_dep(min_ver: &Option<String>, native: bool) -> String {
 
 #[cfg(test)]
 mod test {
-    use super::rustc_dep;
+    use super::{
+        coerce_prerelease_deps, package_description_suffix, resolve_wildcard_deps, rustc_dep,
+        transform_feature_packages,
+    };
+    use crate::config::{Config, WildcardDeps};
+    use crate::crates::CrateDepInfo;
+    use cargo::core::{Dependency, SourceId};
+    use cargo::GlobalContext;
+    use std::collections::HashMap;
 
     #[test]
     fn rustc_dep_includes_minver() {
@@ -1386,4 +1874,176 @@ mod test {
     fn rustc_dep_excludes_minver_autopkgtest() {
         assert_eq!("rustc", rustc_dep(&None, false));
     }
+
+    #[test]
+    fn feature_description_leads_with_documented_purpose() {
+        let documented =
+            package_description_suffix("demo", "tls", &[], Some("Enable TLS via rustls."));
+        assert!(documented.starts_with("Feature \"tls\": Enable TLS via rustls.\n\n"));
+        assert!(documented.contains("enables feature \"tls\""));
+
+        let plain = package_description_suffix("demo", "tls", &[], None);
+        assert!(plain.starts_with("This metapackage enables feature \"tls\""));
+    }
+
+    #[test]
+    fn prerelease_requirements_are_kept_only_where_allowed() {
+        let crates_io = SourceId::crates_io(&GlobalContext::default().unwrap()).unwrap();
+        let dep = |name: &str| Dependency::parse(name, Some("^0.26.0-beta.1"), crates_io).unwrap();
+        let requirements = |config: &Config| {
+            let mut features_with_deps = CrateDepInfo::new();
+            features_with_deps.insert("", (vec![], vec![dep("libbpf-rs"), dep("libbpf-sys")]));
+            features_with_deps.insert("tls", (vec![""], vec![dep("libbpf-sys")]));
+            coerce_prerelease_deps(config, &mut features_with_deps);
+            features_with_deps
+                .into_iter()
+                .flat_map(|(feature, (_, deps))| {
+                    deps.into_iter().map(move |d| {
+                        format!("{} {} {}", feature, d.package_name(), d.version_req())
+                    })
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert!(requirements(&Config::default())
+            .iter()
+            .all(|r| r.ends_with("-beta.1")));
+        let config: Config = toml::from_str(
+            r#"
+allow_prerelease_deps = false
+[packages.lib]
+allow_prerelease = ["libbpf-rs"]
+[packages."lib+tls"]
+allow_prerelease = ["libbpf_sys"]
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            requirements(&config),
+            [
+                " libbpf-rs ^0.26.0-beta.1",
+                " libbpf-sys ^0.26.0",
+                "tls libbpf-sys ^0.26.0-beta.1"
+            ]
+        );
+    }
+
+    #[test]
+    fn wildcard_dependencies_follow_the_policy() {
+        let crates_io = SourceId::crates_io(&GlobalContext::default().unwrap()).unwrap();
+        let features = || {
+            let mut features_with_deps = CrateDepInfo::new();
+            features_with_deps.insert(
+                "",
+                (
+                    vec![],
+                    vec![
+                        Dependency::parse("log", Some("*"), crates_io).unwrap(),
+                        Dependency::parse("serde", Some("1"), crates_io).unwrap(),
+                    ],
+                ),
+            );
+            features_with_deps
+        };
+        let locked = HashMap::from([("log".to_string(), semver::Version::new(0, 4, 22))]);
+        let resolved = |policy, lockfile: Option<&HashMap<String, semver::Version>>| {
+            let mut features_with_deps = features();
+            resolve_wildcard_deps(policy, "demo", &mut features_with_deps, lockfile).map(|()| {
+                features_with_deps[""]
+                    .1
+                    .iter()
+                    .map(|dep| dep.version_req().to_string())
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        assert_eq!(
+            resolved(WildcardDeps::Unversioned, None).unwrap(),
+            ["*", "^1"]
+        );
+        assert_eq!(resolved(WildcardDeps::Warn, None).unwrap(), ["*", "^1"]);
+        assert!(resolved(WildcardDeps::Error, Some(&locked)).is_err());
+        assert_eq!(
+            resolved(WildcardDeps::PinToLockfile, Some(&locked)).unwrap(),
+            ["^0.4.22", "^1"]
+        );
+        assert!(resolved(WildcardDeps::PinToLockfile, None).is_err());
+        let config: Config = toml::from_str(r#"wildcard_deps = "pin-to-lockfile""#).unwrap();
+        assert_eq!(config.wildcard_deps, WildcardDeps::PinToLockfile);
+    }
+
+    #[test]
+    fn provides_only_is_selectable_per_crate() {
+        let config: Config = toml::from_str(r#"provides_only = ["windows_sys"]"#).unwrap();
+        assert!(config.provides_only_features("windows-sys"));
+        assert!(!config.provides_only_features("serde"));
+
+        let config: Config = toml::from_str("provides_only = true").unwrap();
+        assert!(config.provides_only_features("serde"));
+        assert!(!Config::default().provides_only_features("serde"));
+    }
+
+    #[test]
+    fn crate_namespace_defaults_to_crate_and_is_validated() {
+        assert_eq!(Config::default().crate_namespace(), "crate");
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("takopack.toml");
+        std::fs::write(&path, "crate_namespace = \"mycorp-crate\"\n").unwrap();
+        assert_eq!(
+            Config::parse(&path).unwrap().crate_namespace(),
+            "mycorp-crate"
+        );
+
+        std::fs::write(&path, "crate_namespace = \"my crate\"\n").unwrap();
+        assert!(Config::parse(&path).is_err());
+    }
+
+    #[test]
+    fn profile_settings_sit_under_takopack_toml() {
+        use crate::config::{MacroFlavor, TestStyle};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("takopack.toml");
+        std::fs::write(
+            &path,
+            "profile = \"fedora\"\nrelease = \"2%{?dist}\"\n\n[profiles.opensuse]\ntests = \"skip\"\n",
+        )
+        .unwrap();
+
+        let fedora = Config::parse(&path).unwrap();
+        assert_eq!(fedora.macro_flavor, MacroFlavor::Cargo);
+        assert_eq!(fedora.tests, TestStyle::Bcond);
+        assert_eq!(fedora.release(), "2%{?dist}");
+        assert_eq!(fedora.macros_package(), "cargo-rpm-macros >= 24");
+
+        let opensuse = Config::parse_with_profile(&path, Some("opensuse")).unwrap();
+        assert_eq!(opensuse.macros_package(), "cargo-packaging");
+        assert_eq!(opensuse.tests, TestStyle::Skip);
+        assert_eq!(opensuse.release(), "2%{?dist}");
+        assert!(!opensuse.autochangelog);
+
+        assert!(Config::parse_with_profile(&path, Some("nope")).is_err());
+        assert_eq!(Config::default().macro_flavor, MacroFlavor::Rustcrates);
+    }
+
+    #[test]
+    fn collapsed_features_leave_only_the_main_package() {
+        let mut features: CrateDepInfo = CrateDepInfo::new();
+        features.insert("", (vec![], vec![]));
+        features.insert("default", (vec!["std"], vec![]));
+        features.insert("std", (vec![""], vec![]));
+        features.insert("serde", (vec![""], vec![]));
+
+        let transformed = transform_feature_packages(features, true).unwrap();
+
+        assert_eq!(
+            transformed
+                .reduced_features_with_deps
+                .keys()
+                .collect::<Vec<_>>(),
+            vec![&""]
+        );
+        assert_eq!(transformed.provides[""], vec!["default", "serde", "std"]);
+    }
 }
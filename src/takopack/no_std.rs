@@ -0,0 +1,134 @@
+//! Detection of crates that build without the Rust standard library.
+//!
+//! A crate counts as `no_std` when the root of its library declares
+//! `#![no_std]`, directly or through `cfg_attr` (typically
+//! `#![cfg_attr(not(feature = "std"), no_std)]`), or when it lists itself in
+//! the `no-std` category of crates.io. What the spec gets for such crates is
+//! chosen with the `no_std` setting, see [`crate::config::NoStdStyle`].
+
+use std::fs;
+
+use regex::Regex;
+
+use crate::crates::CrateInfo;
+
+/// Name of the marker subpackage, and of the feature it provides.
+pub(crate) const NO_STD_FEATURE: &str = "no-std";
+
+/// How a crate supports `no_std`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum NoStdSupport {
+    /// `#![no_std]`.
+    Always,
+    /// `no_std` as long as the named feature is disabled.
+    WithoutFeature(String),
+    /// `no_std` under some other `cfg_attr` condition.
+    Conditional,
+    /// Only the `no-std` category says so.
+    Documented,
+}
+
+impl NoStdSupport {
+    /// Paragraph for the `%description` of the `+no-std` subpackage.
+    pub(crate) fn description(&self) -> String {
+        match self {
+            NoStdSupport::Always => {
+                "This crate does not use the Rust standard library.".to_string()
+            }
+            NoStdSupport::WithoutFeature(feature) => format!(
+                "This crate builds without the Rust standard library when its \
+                 \"{}\" feature is disabled.",
+                feature
+            ),
+            NoStdSupport::Conditional => {
+                "This crate can be built without the Rust standard library.".to_string()
+            }
+            NoStdSupport::Documented => {
+                "This crate is documented to support environments without the \
+                 Rust standard library."
+                    .to_string()
+            }
+        }
+    }
+}
+
+/// How `crate_info` supports `no_std`, or `None` when it does not appear to.
+pub(crate) fn detect_no_std(crate_info: &CrateInfo) -> Option<NoStdSupport> {
+    let lib_root = crate_info
+        .targets()
+        .iter()
+        .find(|target| target.is_lib())
+        .and_then(|target| target.src_path().path());
+    if let Some(source) = lib_root.and_then(|path| fs::read_to_string(path).ok()) {
+        if let Some(support) = no_std_attribute(&source) {
+            return Some(support);
+        }
+    }
+    crate_info
+        .metadata()
+        .categories
+        .iter()
+        .any(|category| category == "no-std" || category.starts_with("no-std::"))
+        .then_some(NoStdSupport::Documented)
+}
+
+/// The `no_std` inner attribute of a library root, if any.
+fn no_std_attribute(source: &str) -> Option<NoStdSupport> {
+    // Comments are dropped so that doc examples showing the attribute do not
+    // count, and whitespace so that the attribute reads the same however it
+    // is formatted.
+    let code: String = source
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("//"))
+        .flat_map(str::chars)
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    if code.contains("#![no_std]") {
+        return Some(NoStdSupport::Always);
+    }
+
+    let cfg_attr = Regex::new(r"#!\[cfg_attr\(([^\[\]]*?),no_std\)\]").unwrap();
+    let condition = cfg_attr.captures(&code)?.get(1)?.as_str();
+    let without_feature = Regex::new(r#"^(?:all\()?not\((?:any\()?feature="([^"]+)""#).unwrap();
+    Some(match without_feature.captures(condition) {
+        Some(captures) => NoStdSupport::WithoutFeature(captures[1].to_string()),
+        None if !condition.contains("feature=") => NoStdSupport::Always,
+        None => NoStdSupport::Conditional,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{no_std_attribute, NoStdSupport};
+
+    #[test]
+    fn no_std_attributes_are_recognized() {
+        assert_eq!(
+            no_std_attribute("//! Docs\n\n#![no_std]\n#![deny(missing_docs)]\n"),
+            Some(NoStdSupport::Always)
+        );
+        assert_eq!(
+            no_std_attribute("#![cfg_attr(not(feature = \"std\"), no_std)]\n"),
+            Some(NoStdSupport::WithoutFeature("std".to_string()))
+        );
+        assert_eq!(
+            no_std_attribute(
+                "#![cfg_attr(\n    not(any(feature = \"use_std\", test)),\n    no_std\n)]\n"
+            ),
+            Some(NoStdSupport::WithoutFeature("use_std".to_string()))
+        );
+        assert_eq!(
+            no_std_attribute("#![cfg_attr(not(test), no_std)]\n"),
+            Some(NoStdSupport::Always)
+        );
+        assert_eq!(
+            no_std_attribute("#![cfg_attr(feature = \"no_std\", no_std)]\n"),
+            Some(NoStdSupport::Conditional)
+        );
+        assert_eq!(
+            no_std_attribute("//! ```\n//! #![no_std]\n//! ```\npub fn f() {}\n"),
+            None
+        );
+        assert_eq!(no_std_attribute("#![forbid(unsafe_code)]\n"), None);
+    }
+}
@@ -1,79 +1,205 @@
 use std::fmt::{self, Write};
 
+use serde::Serialize;
+
+use crate::config::{MacroFlavor, RequiresStyle, TestStyle};
+use crate::takopack::control::deb_name;
+use crate::test_mode::TestMode;
+
 const SPDX_HEADER: &str = r#"# SPDX-FileCopyrightText: (C) 2026 Institute of Software, Chinese Academy of Sciences (ISCAS)
 # SPDX-FileCopyrightText: (C) 2026 openRuyi Project Contributors
 #
 # SPDX-License-Identifier: MulanPSL-2.0
 "#;
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum CapabilityVersion {
     None,
     Exact(String),
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum RequirementVersion {
     None,
     Exact(String),
     Range(String),
+    /// At least the first version and below the second, as a rich dependency
+    Between(String, String),
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
 pub struct CrateCapability {
     pub crate_name: String,
     pub feature: Option<String>,
     pub version: CapabilityVersion,
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
 pub struct CrateRequirement {
     pub crate_name: String,
     pub feature: Option<String>,
     pub requirement: RequirementVersion,
+    /// For a dependency of `[target.'cfg(..)'.dependencies]`, the
+    /// architectures whose Linux target it is built for
+    #[serde(skip)]
+    pub target_arches: Option<Vec<String>>,
+    /// Architectures the requirement is limited to with `%ifarch`; empty
+    /// for all of them
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub arches: Vec<String>,
+    /// For the feature of a weak dependency (`bar?/baz`): required only
+    /// when the crate is installed anyway, as `(crate(bar-1/baz) if
+    /// crate(bar-1))`
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub weak: bool,
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
 pub struct SpecSource {
     pub crate_name: String,
     pub full_version: String,
     pub pkgname: String,
     pub rpm_name: String,
     pub rpm_version: String,
+    pub release: String,
     pub summary: String,
     pub license: String,
     pub url: String,
     pub source_url: String,
     pub sha256: Option<String>,
     pub build_requires: Vec<String>,
+    /// `BuildRequires:` only the tests need; left out when `%check` is.
+    pub test_build_requires: Vec<String>,
+    /// `edition` of the crate, noted in a comment.
+    pub edition: Option<String>,
+    /// `rust-version` (MSRV) of the crate, required of the `rust` package.
+    pub rust_version: Option<String>,
+    /// The version is yanked on crates.io and packaged as an exception.
+    pub yanked: bool,
+    /// Executables installed into `%{_bindir}`; a crate with any is built
+    /// for each architecture instead of as `noarch`.
+    pub bins: Vec<String>,
+    /// The C API library installed into `%{_libdir}`, which also makes the
+    /// crate architecture-specific.
+    pub c_library: Option<SpecCLibrary>,
+    /// Man pages and shell completions installed with the executables.
+    pub bin_assets: Vec<SpecAsset>,
     pub with_spdx: bool,
+    pub macro_flavor: MacroFlavor,
+    pub tests: TestStyle,
 }
 
-#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
 pub struct SpecPackage {
     pub feature: Option<String>,
+    /// Name of the package of the crate's executables; `None` for the
+    /// packages of its source.
+    pub binary: Option<String>,
     pub summary: String,
     pub description: String,
     pub requires: Vec<CrateRequirement>,
     pub provides: Vec<CrateCapability>,
     pub obsoletes: Vec<String>,
     pub conflicts: Vec<String>,
+    /// Packages for `Recommends:`.
+    pub recommends: Vec<String>,
+    /// Packages for `Suggests:`.
+    pub suggests: Vec<String>,
     pub extra_lines: Vec<String>,
+    /// Namespace of the rendered capabilities; `None` is
+    /// [`DEFAULT_CRATE_NAMESPACE`].
+    pub crate_namespace: Option<String>,
+    pub requires_style: RequiresStyle,
+    /// Picks the prefix of package names in package requirements
+    #[serde(skip)]
+    pub test_mode: TestMode,
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
 pub struct SpecFiles {
     pub package: Option<String>,
     pub entries: Vec<String>,
 }
 
+/// A C API library the crate builds, from `crate-type = ["cdylib"]` or
+/// `["staticlib"]`.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct SpecCLibrary {
+    /// Name of the library target; its files are `lib<name>.so` and
+    /// `lib<name>.a`
+    pub name: String,
+    /// Version in the soname `lib<name>.so.<soversion>`
+    pub soversion: String,
+    pub cdylib: bool,
+    pub staticlib: bool,
+}
+
+impl SpecCLibrary {
+    pub fn soname(&self) -> String {
+        format!("lib{}.so.{}", self.name, self.soversion)
+    }
+}
+
+/// A file installed with the executables, see
+/// [`crate::takopack::bin_assets`].
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct SpecAsset {
+    /// Shell word naming the file in the build directory
+    pub source: String,
+    /// Where it is installed, without `%{buildroot}`
+    pub destination: String,
+    /// A man page, which RPM compresses when installing
+    pub man_page: bool,
+}
+
+impl SpecAsset {
+    /// Entry of the asset in `%files`.
+    pub fn files_entry(&self) -> String {
+        if self.man_page {
+            format!("{}*", self.destination)
+        } else {
+            self.destination.clone()
+        }
+    }
+}
+
+/// One `%cargo_test` run of `%check`.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct CheckRun {
+    /// Arguments of `%cargo_test`: `-n` for no default features, `-f` and
+    /// a feature, `-a` for all features
+    pub args: Vec<String>,
+    /// The tests are known to fail (`test_is_broken`), so failures do not
+    /// fail the build
+    pub broken: bool,
+    /// Architectures to run the tests on (`test_architecture`); empty for
+    /// all of them
+    pub architectures: Vec<String>,
+}
+
+/// One `%changelog` entry.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ChangelogEntry {
+    pub date: chrono::NaiveDate,
+    pub author: String,
+    pub version: String,
+    pub text: String,
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct RpmSpec {
     pub source: SpecSource,
     pub main_package: SpecPackage,
     pub feature_packages: Vec<SpecPackage>,
+    /// The package of the crate's executables, see [`SpecSource::bins`].
+    pub binary_package: Option<SpecPackage>,
     pub files: Vec<SpecFiles>,
     pub changelog: bool,
+    /// Test runs of `%check`; without any the tests run once with the
+    /// default features.
+    pub checks: Vec<CheckRun>,
 }
 
 impl CrateCapability {
@@ -92,10 +218,16 @@ impl CrateRequirement {
             crate_name: "%{pkgname}".to_string(),
             feature,
             requirement: RequirementVersion::Exact("%{version}".to_string()),
+            target_arches: None,
+            arches: Vec::new(),
+            weak: false,
         }
     }
 }
 
+/// The `crate` in `crate(foo/bar)`.
+pub const DEFAULT_CRATE_NAMESPACE: &str = "crate";
+
 pub fn normalize_crate_name(crate_name: &str) -> String {
     if crate_name.starts_with("%{") {
         crate_name.to_string()
@@ -112,29 +244,81 @@ pub fn normalize_feature_name(feature: &str) -> String {
         .to_string()
 }
 
-pub fn render_crate_capability(cap: &CrateCapability) -> String {
-    let capability = render_crate_name_feature(&cap.crate_name, cap.feature.as_deref());
+pub fn render_crate_capability(namespace: &str, cap: &CrateCapability) -> String {
+    let capability = render_crate_name_feature(namespace, &cap.crate_name, cap.feature.as_deref());
     match &cap.version {
         CapabilityVersion::None => capability,
         CapabilityVersion::Exact(version) => format!("{} = {}", capability, version),
     }
 }
 
-pub fn render_crate_requirement(req: &CrateRequirement) -> String {
-    let requirement = render_crate_name_feature(&req.crate_name, req.feature.as_deref());
-    match &req.requirement {
+pub fn render_crate_requirement(namespace: &str, req: &CrateRequirement) -> String {
+    let requirement = render_crate_name_feature(namespace, &req.crate_name, req.feature.as_deref());
+    let rendered = match &req.requirement {
         RequirementVersion::None => requirement,
         RequirementVersion::Exact(version) => format!("{} = {}", requirement, version),
         RequirementVersion::Range(version) => format!("{} {}", requirement, version),
+        RequirementVersion::Between(lower, upper) => format!(
+            "({} >= {} with {} < {})",
+            requirement, lower, requirement, upper
+        ),
+    };
+    if req.weak {
+        let installed = render_crate_name_feature(namespace, &req.crate_name, None);
+        format!("({} if {})", rendered, installed)
+    } else {
+        rendered
+    }
+}
+
+/// `req` as a dependency on the package carrying the capability, e.g.
+/// `rust-base64-0.22+std >= 0.22.1`. Prerelease versions use `~` so that
+/// they sort below the release the package is versioned as.
+pub fn render_package_requirement(req: &CrateRequirement, test_mode: TestMode) -> String {
+    let crate_name = normalize_crate_name(&req.crate_name);
+    let installed = if crate_name == "%{pkgname}" {
+        "%{name}".to_string()
+    } else {
+        deb_name(&crate_name, test_mode)
+    };
+    let mut package = installed.clone();
+    if let Some(feature) = &req.feature {
+        package.push('+');
+        package.push_str(&normalize_feature_name(feature));
+    }
+    let rendered = match &req.requirement {
+        RequirementVersion::None => package,
+        RequirementVersion::Exact(version) => format!("{} = {}", package, version),
+        RequirementVersion::Range(version) => {
+            format!("{} {}", package, version.replacen('-', "~", 1))
+        }
+        RequirementVersion::Between(lower, upper) => format!(
+            "({} >= {} with {} < {})",
+            package,
+            lower.replacen('-', "~", 1),
+            package,
+            upper.replacen('-', "~", 1)
+        ),
+    };
+    if req.weak {
+        format!("({} if {})", rendered, installed)
+    } else {
+        rendered
     }
 }
 
-pub fn render_crate_provides(cap: &CrateCapability) -> String {
-    format!("Provides:       {}", render_crate_capability(cap))
+pub fn render_crate_provides(namespace: &str, cap: &CrateCapability) -> String {
+    format!(
+        "Provides:       {}",
+        render_crate_capability(namespace, cap)
+    )
 }
 
-pub fn render_crate_requires(req: &CrateRequirement) -> String {
-    format!("Requires:       {}", render_crate_requirement(req))
+pub fn render_crate_requires(namespace: &str, req: &CrateRequirement) -> String {
+    format!(
+        "Requires:       {}",
+        render_crate_requirement(namespace, req)
+    )
 }
 
 pub fn render_header_section<W: Write>(out: &mut W, source: &SpecSource) -> fmt::Result {
@@ -142,24 +326,47 @@ pub fn render_header_section<W: Write>(out: &mut W, source: &SpecSource) -> fmt:
         write!(out, "{}", SPDX_HEADER)?;
         writeln!(out)?;
     }
+    if source.yanked {
+        writeln!(
+            out,
+            "# WARNING: {} {} is yanked on crates.io. It is packaged as an",
+            source.crate_name, source.full_version
+        )?;
+        writeln!(
+            out,
+            "# exception (takopack --allow-yanked); move to a version that is"
+        )?;
+        writeln!(out, "# not yanked as soon as possible.")?;
+        writeln!(out)?;
+    }
     writeln!(out, "%global crate_name {}", source.crate_name)?;
     writeln!(out, "%global full_version {}", source.full_version)?;
     writeln!(out, "%global pkgname {}", source.pkgname)?;
+    if source.macro_flavor == MacroFlavor::Cargo && source.tests == TestStyle::Bcond {
+        writeln!(out)?;
+        writeln!(out, "%bcond check 1")?;
+    }
     writeln!(out)?;
     writeln!(out, "Name:           {}", source.rpm_name)?;
     writeln!(out, "Version:        {}", source.rpm_version)?;
-    writeln!(out, "Release:        %autorelease")?;
-    writeln!(out, "Summary:        {}", source.summary)?;
+    writeln!(out, "Release:        {}", source.release)?;
+    writeln!(out, "Summary:        {}", spec_tag_value(&source.summary))?;
     writeln!(out, "License:        {}", source.license)?;
-    writeln!(out, "URL:            {}", source.url)?;
-    if let Some(ref hash) = source.sha256 {
-        writeln!(out, "#!RemoteAsset:  sha256:{}", hash)?;
-    } else {
-        writeln!(out, "#!RemoteAsset:  sha256:")?;
+    writeln!(out, "URL:            {}", spec_tag_value(&source.url))?;
+    if source.macro_flavor == MacroFlavor::Rustcrates {
+        if let Some(ref hash) = source.sha256 {
+            writeln!(out, "#!RemoteAsset:  sha256:{}", hash)?;
+        } else {
+            writeln!(out, "#!RemoteAsset:  sha256:")?;
+        }
     }
     writeln!(out, "Source:         {}", source.source_url)?;
-    writeln!(out, "BuildArch:      noarch")?;
-    writeln!(out, "BuildSystem:    rustcrates")?;
+    if source.bins.is_empty() && source.c_library.is_none() {
+        writeln!(out, "BuildArch:      noarch")?;
+    }
+    if source.macro_flavor == MacroFlavor::Rustcrates {
+        writeln!(out, "BuildSystem:    rustcrates")?;
+    }
     writeln!(out)?;
     Ok(())
 }
@@ -168,9 +375,32 @@ pub fn render_source_requirements_section<W: Write>(
     out: &mut W,
     source: &SpecSource,
 ) -> fmt::Result {
+    match (&source.edition, &source.rust_version) {
+        (Some(edition), Some(msrv)) => writeln!(out, "# Rust edition {}, MSRV {}", edition, msrv)?,
+        (Some(edition), None) => writeln!(out, "# Rust edition {}", edition)?,
+        (None, Some(msrv)) => writeln!(out, "# MSRV {}", msrv)?,
+        (None, None) => {}
+    }
     for requirement in &source.build_requires {
         writeln!(out, "BuildRequires:  {}", requirement)?;
     }
+    if let Some(msrv) = &source.rust_version {
+        writeln!(out, "BuildRequires:  rust >= {}", msrv)?;
+    }
+    let cargo = source.macro_flavor == MacroFlavor::Cargo;
+    let tests_run = !(cargo && source.tests == TestStyle::Skip);
+    if tests_run && !source.test_build_requires.is_empty() {
+        let bcond = cargo && source.tests == TestStyle::Bcond;
+        if bcond {
+            writeln!(out, "%if %{{with check}}")?;
+        }
+        for requirement in &source.test_build_requires {
+            writeln!(out, "BuildRequires:  {}", requirement)?;
+        }
+        if bcond {
+            writeln!(out, "%endif")?;
+        }
+    }
     writeln!(out)?;
     Ok(())
 }
@@ -180,6 +410,16 @@ pub fn render_main_package_section<W: Write>(out: &mut W, package: &SpecPackage)
     render_description(out, None, &package.description)
 }
 
+/// The `%package` section of the package of the crate's executables.
+pub fn render_binary_package_section<W: Write>(out: &mut W, package: &SpecPackage) -> fmt::Result {
+    let name = package.binary.as_deref().unwrap_or("%{name}");
+    writeln!(out)?;
+    writeln!(out, "%package     -n {}", name)?;
+    writeln!(out, "Summary:        {}", spec_tag_value(&package.summary))?;
+    render_package_metadata(out, package)?;
+    render_description(out, Some(name), &package.description)
+}
+
 pub fn render_feature_package_section<W: Write>(out: &mut W, package: &SpecPackage) -> fmt::Result {
     let feature = package
         .feature
@@ -188,16 +428,178 @@ pub fn render_feature_package_section<W: Write>(out: &mut W, package: &SpecPacka
         .unwrap_or_default();
     writeln!(out)?;
     writeln!(out, "%package     -n %{{name}}+{}", feature)?;
-    writeln!(out, "Summary:        {}", package.summary)?;
+    writeln!(out, "Summary:        {}", spec_tag_value(&package.summary))?;
     render_package_metadata(out, package)?;
-    render_description(out, Some(&feature), &package.description)
+    render_description(
+        out,
+        Some(&format!("%{{name}}+{}", feature)),
+        &package.description,
+    )
+}
+
+/// `%prep`; the `rustcrates` build system brings its own.
+pub fn render_prep_section<W: Write>(out: &mut W, flavor: MacroFlavor) -> fmt::Result {
+    if flavor == MacroFlavor::Rustcrates {
+        return Ok(());
+    }
+    writeln!(out, "%prep")?;
+    writeln!(out, "%autosetup -n %{{crate_name}}-%{{full_version}} -p1")?;
+    writeln!(out, "%cargo_prep")?;
+    writeln!(out)
+}
+
+/// `%build`, `%install` and `%check`; the `rustcrates` build system brings
+/// its own, which only get the building and installing of `bins`,
+/// `bin_assets` and `c_library` appended.
+/// `%check` has one `%cargo_test` for each of `checks`, or a single one with
+/// the default features when there are none.
+pub fn render_build_check_install_section<W: Write>(
+    out: &mut W,
+    flavor: MacroFlavor,
+    tests: TestStyle,
+    checks: &[CheckRun],
+    bins: &[String],
+    bin_assets: &[SpecAsset],
+    c_library: Option<&SpecCLibrary>,
+) -> fmt::Result {
+    if flavor == MacroFlavor::Rustcrates {
+        if !bins.is_empty() || c_library.is_some() {
+            writeln!(out, "%build -a")?;
+            render_artifacts_build(out, bins, c_library)?;
+            writeln!(out)?;
+            writeln!(out, "%install -a")?;
+            render_artifacts_install(out, bins, bin_assets, c_library)?;
+            writeln!(out)?;
+        }
+        return Ok(());
+    }
+    writeln!(out, "%build")?;
+    writeln!(out, "%cargo_build")?;
+    render_artifacts_build(out, bins, c_library)?;
+    writeln!(out)?;
+    writeln!(out, "%install")?;
+    writeln!(out, "%cargo_install")?;
+    render_artifacts_install(out, bins, bin_assets, c_library)?;
+    writeln!(out)?;
+    let cargo_test = |out: &mut W| -> fmt::Result {
+        if checks.is_empty() {
+            return writeln!(out, "%cargo_test");
+        }
+        for check in checks {
+            render_check_run(out, check)?;
+        }
+        Ok(())
+    };
+    match tests {
+        TestStyle::Run => {
+            writeln!(out, "%check")?;
+            cargo_test(out)?;
+            writeln!(out)?;
+        }
+        TestStyle::Bcond => {
+            writeln!(out, "%check")?;
+            writeln!(out, "%if %{{with check}}")?;
+            cargo_test(out)?;
+            writeln!(out, "%endif")?;
+            writeln!(out)?;
+        }
+        TestStyle::Skip => {}
+    }
+    Ok(())
+}
+
+fn render_artifacts_build<W: Write>(
+    out: &mut W,
+    bins: &[String],
+    c_library: Option<&SpecCLibrary>,
+) -> fmt::Result {
+    if !bins.is_empty() {
+        write!(out, "cargo build --release --offline")?;
+        for bin in bins {
+            write!(out, " --bin {}", bin)?;
+        }
+        writeln!(out)?;
+    }
+    match c_library {
+        // cargo gives shared libraries no soname of their own
+        Some(library) if library.cdylib => writeln!(
+            out,
+            "cargo rustc --release --offline --lib -- -C link-arg=-Wl,-soname,{}",
+            library.soname()
+        ),
+        Some(_) => writeln!(out, "cargo build --release --offline --lib"),
+        None => Ok(()),
+    }
 }
 
-pub fn render_patch_prep_placeholder<W: Write>(_out: &mut W) -> fmt::Result {
+fn render_artifacts_install<W: Write>(
+    out: &mut W,
+    bins: &[String],
+    bin_assets: &[SpecAsset],
+    c_library: Option<&SpecCLibrary>,
+) -> fmt::Result {
+    if !bins.is_empty() {
+        write!(out, "install -Dpm 0755 -t %{{buildroot}}%{{_bindir}}")?;
+        for bin in bins {
+            write!(out, " target/release/{}", bin)?;
+        }
+        writeln!(out)?;
+    }
+    for asset in bin_assets {
+        writeln!(
+            out,
+            "install -Dpm 0644 {} %{{buildroot}}{}",
+            asset.source, asset.destination
+        )?;
+    }
+    let Some(library) = c_library else {
+        return Ok(());
+    };
+    if library.cdylib {
+        let file = format!("lib{}.so", library.name);
+        writeln!(
+            out,
+            "install -Dpm 0755 target/release/{0} %{{buildroot}}%{{_libdir}}/{0}.%{{full_version}}",
+            file
+        )?;
+        writeln!(
+            out,
+            "ln -s {}.%{{full_version}} %{{buildroot}}%{{_libdir}}/{}",
+            file,
+            library.soname()
+        )?;
+        writeln!(
+            out,
+            "ln -s {} %{{buildroot}}%{{_libdir}}/{}",
+            library.soname(),
+            file
+        )?;
+    }
+    if library.staticlib {
+        writeln!(
+            out,
+            "install -Dpm 0644 target/release/lib{0}.a %{{buildroot}}%{{_libdir}}/lib{0}.a",
+            library.name
+        )?;
+    }
     Ok(())
 }
 
-pub fn render_build_check_install_placeholder<W: Write>(_out: &mut W) -> fmt::Result {
+fn render_check_run<W: Write>(out: &mut W, check: &CheckRun) -> fmt::Result {
+    if !check.architectures.is_empty() {
+        writeln!(out, "%ifarch {}", check.architectures.join(" "))?;
+    }
+    write!(out, "%cargo_test")?;
+    for arg in &check.args {
+        write!(out, " {}", arg)?;
+    }
+    if check.broken {
+        write!(out, " || :")?;
+    }
+    writeln!(out)?;
+    if !check.architectures.is_empty() {
+        writeln!(out, "%endif")?;
+    }
     Ok(())
 }
 
@@ -215,9 +617,35 @@ pub fn render_files_section<W: Write>(out: &mut W, files: &[SpecFiles]) -> fmt::
     Ok(())
 }
 
-pub fn render_changelog_section<W: Write>(out: &mut W) -> fmt::Result {
+/// `%changelog` with `entries` in the given order. Without entries it is
+/// `%autochangelog`, or left empty for distros that keep the changelog
+/// outside the spec.
+pub fn render_changelog_section<W: Write>(
+    out: &mut W,
+    entries: &[ChangelogEntry],
+    autochangelog: bool,
+) -> fmt::Result {
     writeln!(out, "%changelog")?;
-    writeln!(out, "%autochangelog")
+    if entries.is_empty() {
+        if autochangelog {
+            writeln!(out, "%autochangelog")?;
+        }
+        return Ok(());
+    }
+    for (i, entry) in entries.iter().enumerate() {
+        if i > 0 {
+            writeln!(out)?;
+        }
+        writeln!(
+            out,
+            "* {} {} - {}",
+            entry.date.format("%a %b %d %Y"),
+            spec_tag_value(&entry.author),
+            entry.version
+        )?;
+        writeln!(out, "- {}", spec_tag_value(&entry.text))?;
+    }
+    Ok(())
 }
 
 impl RpmSpec {
@@ -228,12 +656,23 @@ impl RpmSpec {
         for feature_package in &self.feature_packages {
             render_feature_package_section(out, feature_package)?;
         }
+        if let Some(binary_package) = &self.binary_package {
+            render_binary_package_section(out, binary_package)?;
+        }
         writeln!(out)?;
-        render_patch_prep_placeholder(out)?;
-        render_build_check_install_placeholder(out)?;
+        render_prep_section(out, self.source.macro_flavor)?;
+        render_build_check_install_section(
+            out,
+            self.source.macro_flavor,
+            self.source.tests,
+            &self.checks,
+            &self.source.bins,
+            &self.source.bin_assets,
+            self.source.c_library.as_ref(),
+        )?;
         render_files_section(out, &self.files)?;
         if self.changelog {
-            render_changelog_section(out)?;
+            render_changelog_section(out, &[], true)?;
         }
         Ok(())
     }
@@ -246,22 +685,47 @@ impl RpmSpec {
     }
 }
 
-fn render_crate_name_feature(crate_name: &str, feature: Option<&str>) -> String {
+fn render_crate_name_feature(namespace: &str, crate_name: &str, feature: Option<&str>) -> String {
     let crate_name = normalize_crate_name(crate_name);
     if let Some(feature) = feature {
         let feature = normalize_feature_name(feature);
-        format!("crate({}/{})", crate_name, feature)
+        format!("{}({}/{})", namespace, crate_name, feature)
     } else {
-        format!("crate({})", crate_name)
+        format!("{}({})", namespace, crate_name)
     }
 }
 
 fn render_package_metadata<W: Write>(out: &mut W, package: &SpecPackage) -> fmt::Result {
+    let namespace = package
+        .crate_namespace
+        .as_deref()
+        .unwrap_or(DEFAULT_CRATE_NAMESPACE);
     for requirement in &package.requires {
-        writeln!(out, "{}", render_crate_requires(requirement))?;
+        if !requirement.arches.is_empty() {
+            writeln!(out, "%ifarch {}", requirement.arches.join(" "))?;
+        }
+        if package.requires_style.crate_requires() {
+            writeln!(out, "{}", render_crate_requires(namespace, requirement))?;
+        }
+        if package.requires_style.package_requires() {
+            writeln!(
+                out,
+                "Requires:       {}",
+                render_package_requirement(requirement, package.test_mode)
+            )?;
+        }
+        if !requirement.arches.is_empty() {
+            writeln!(out, "%endif")?;
+        }
+    }
+    for recommend in &package.recommends {
+        writeln!(out, "Recommends:     {}", recommend)?;
+    }
+    for suggest in &package.suggests {
+        writeln!(out, "Suggests:       {}", suggest)?;
     }
     for capability in &package.provides {
-        writeln!(out, "{}", render_crate_provides(capability))?;
+        writeln!(out, "{}", render_crate_provides(namespace, capability))?;
     }
     for obsolete in &package.obsoletes {
         writeln!(out, "Obsoletes:      {}", obsolete)?;
@@ -275,29 +739,81 @@ fn render_package_metadata<W: Write>(out: &mut W, package: &SpecPackage) -> fmt:
     Ok(())
 }
 
+/// `%description` of the main package, or of the subpackage `package`.
 fn render_description<W: Write>(
     out: &mut W,
-    feature: Option<&str>,
+    package: Option<&str>,
     description: &str,
 ) -> fmt::Result {
     writeln!(out)?;
-    if let Some(feature) = feature {
-        writeln!(out, "%description -n %{{name}}+{}", feature)?;
+    if let Some(package) = package {
+        writeln!(out, "%description -n {}", package)?;
     } else {
         writeln!(out, "%description")?;
     }
     for line in description.lines() {
-        writeln!(out, "{}", line.trim())?;
+        writeln!(out, "{}", spec_description_line(line))?;
     }
     Ok(())
 }
 
+/// Escape free text taken from crate metadata so rpmbuild reads it
+/// literally: `%` starts a macro, `%%` is a plain percent sign.
+pub fn escape_rpm_macros(text: &str) -> String {
+    text.replace('%', "%%")
+}
+
+/// Drop characters that have no business in a spec file: control
+/// characters (NUL, escapes, ...) and invisible bidi/BOM marks that would
+/// make the text render differently from what rpm stores. Tabs become spaces.
+fn clean_spec_text(text: &str) -> String {
+    text.chars()
+        .filter_map(|c| match c {
+            '\t' => Some(' '),
+            '\u{200E}' | '\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}' => None,
+            '\u{FEFF}' => None,
+            c if c.is_control() => None,
+            c => Some(c),
+        })
+        .collect()
+}
+
+/// Value of a single-line tag such as `Summary:`; embedded newlines would
+/// end the tag early, so lines are joined. The value is plain text, so `%`
+/// is escaped here rather than by whoever filled it in.
+fn spec_tag_value(value: &str) -> String {
+    let lines: Vec<&str> = value
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .collect();
+    escape_rpm_macros(clean_spec_text(&lines.join(" ")).trim())
+}
+
+/// One line of a `%description` body. After macro expansion rpm treats a
+/// line starting with `%` as a new section and one starting with `#` as a
+/// comment, so such lines are indented or lose their Markdown heading mark.
+fn spec_description_line(line: &str) -> String {
+    let line = clean_spec_text(line);
+    let line = escape_rpm_macros(line.trim());
+    if line.starts_with('#') {
+        line.trim_start_matches('#').trim_start().to_string()
+    } else if line.starts_with('%') {
+        format!(" {}", line)
+    } else {
+        line
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::{
-        CapabilityVersion, CrateCapability, CrateRequirement, RequirementVersion, RpmSpec,
-        SpecFiles, SpecPackage, SpecSource,
+        escape_rpm_macros, render_changelog_section, render_description,
+        render_feature_package_section, render_main_package_section, spec_tag_value,
+        CapabilityVersion, ChangelogEntry, CheckRun, CrateCapability, CrateRequirement,
+        RequirementVersion, RpmSpec, SpecAsset, SpecCLibrary, SpecFiles, SpecPackage, SpecSource,
     };
+    use crate::config::{MacroFlavor, RequiresStyle, TestStyle};
 
     #[test]
     fn renders_versioned_crate_capabilities_and_requirements() {
@@ -308,13 +824,23 @@ mod tests {
                 pkgname: "serde-with-3".to_string(),
                 rpm_name: "rust-serde-with-3".to_string(),
                 rpm_version: "3.18.0".to_string(),
+                release: "%autorelease".to_string(),
                 summary: "Rust crate \"serde_with\"".to_string(),
                 license: "MIT OR Apache-2.0".to_string(),
                 url: "https://example.invalid/serde_with".to_string(),
                 source_url: "https://static.crates.io/crates/%{crate_name}/%{full_version}/download#/%{name}-%{version}.tar.gz".to_string(),
                 sha256: None,
                 build_requires: vec!["rust-rpm-macros".to_string()],
+                test_build_requires: vec![],
+                edition: None,
+                rust_version: None,
+                yanked: false,
+                bins: vec![],
+                c_library: None,
+                bin_assets: vec![],
                 with_spdx: false,
+                macro_flavor: MacroFlavor::Rustcrates,
+                tests: TestStyle::Run,
             },
             main_package: SpecPackage {
                 description: "Main package".to_string(),
@@ -322,6 +848,9 @@ mod tests {
                     crate_name: "base64-0.22".to_string(),
                     feature: None,
                     requirement: RequirementVersion::Range(">= 0.22.1".to_string()),
+                    target_arches: None,
+                    arches: vec![],
+                    weak: false,
                 }],
                 provides: vec![CrateCapability::package_feature(None)],
                 ..SpecPackage::default()
@@ -348,11 +877,13 @@ mod tests {
                     ..SpecPackage::default()
                 },
             ],
+            binary_package: None,
             files: vec![SpecFiles {
                 package: None,
                 entries: vec!["%{_datadir}/cargo/registry/%{crate_name}-%{version}/".to_string()],
             }],
             changelog: true,
+            checks: vec![],
         };
 
         let rendered = spec.render();
@@ -366,6 +897,95 @@ mod tests {
         assert!(rendered.contains("Requires:       crate(base64-0.22) >= 0.22.1"));
     }
 
+    #[test]
+    fn renders_capabilities_in_configured_namespace() {
+        let package = SpecPackage {
+            feature: Some("std".to_string()),
+            requires: vec![CrateRequirement {
+                crate_name: "libc-0.2".to_string(),
+                feature: Some("std".to_string()),
+                requirement: RequirementVersion::Range(">= 0.2.150".to_string()),
+                target_arches: None,
+                arches: vec![],
+                weak: false,
+            }],
+            provides: vec![CrateCapability::package_feature(Some("std".to_string()))],
+            crate_namespace: Some("mycorp-crate".to_string()),
+            ..SpecPackage::default()
+        };
+        let mut rendered = String::new();
+        render_feature_package_section(&mut rendered, &package).unwrap();
+        assert!(rendered.contains("Requires:       mycorp-crate(libc-0.2/std) >= 0.2.150"));
+        assert!(rendered.contains("Provides:       mycorp-crate(%{pkgname}/std) = %{version}"));
+        assert!(!rendered.contains(" crate("));
+    }
+
+    #[test]
+    fn requires_style_selects_crate_and_package_requirements() {
+        let mut package = SpecPackage {
+            requires: vec![
+                CrateRequirement::same_crate(None),
+                CrateRequirement {
+                    crate_name: "tokio-0.3.0-beta.1".to_string(),
+                    feature: Some("rt_multi_thread".to_string()),
+                    requirement: RequirementVersion::Range(">= 0.3.0-beta.1".to_string()),
+                    target_arches: None,
+                    arches: vec!["x86_64".to_string(), "aarch64".to_string()],
+                    weak: false,
+                },
+            ],
+            requires_style: RequiresStyle::Package,
+            ..SpecPackage::default()
+        };
+        let mut rendered = String::new();
+        render_main_package_section(&mut rendered, &package).unwrap();
+        assert!(rendered.contains("Requires:       %{name} = %{version}\n"));
+        assert!(rendered.contains(
+            "%ifarch x86_64 aarch64\nRequires:       rust-tokio-0.3.0-beta.1+rt-multi-thread >= 0.3.0~beta.1\n%endif\n"
+        ));
+        assert!(!rendered.contains("crate("));
+
+        package.requires_style = RequiresStyle::Both;
+        let mut rendered = String::new();
+        render_main_package_section(&mut rendered, &package).unwrap();
+        assert!(rendered.contains("Requires:       crate(%{pkgname}) = %{version}\n"));
+        assert!(rendered.contains("Requires:       %{name} = %{version}\n"));
+    }
+
+    #[test]
+    fn changelog_lists_entries_or_falls_back_to_autochangelog() {
+        let mut out = String::new();
+        render_changelog_section(&mut out, &[], true).unwrap();
+        assert_eq!(out, "%changelog\n%autochangelog\n");
+
+        let mut out = String::new();
+        render_changelog_section(&mut out, &[], false).unwrap();
+        assert_eq!(out, "%changelog\n");
+
+        let entry = |date: &str, version: &str| ChangelogEntry {
+            date: date.parse().unwrap(),
+            author: "takopack Team <takopack@iscas.ac.cn>".to_string(),
+            version: version.to_string(),
+            text: format!("Update to version {}", version),
+        };
+        let mut out = String::new();
+        render_changelog_section(
+            &mut out,
+            &[entry("2024-03-02", "1.1.0"), entry("2023-12-31", "1.0.0")],
+            true,
+        )
+        .unwrap();
+        assert_eq!(
+            out,
+            "%changelog\n\
+             * Sat Mar 02 2024 takopack Team <takopack@iscas.ac.cn> - 1.1.0\n\
+             - Update to version 1.1.0\n\
+             \n\
+             * Sun Dec 31 2023 takopack Team <takopack@iscas.ac.cn> - 1.0.0\n\
+             - Update to version 1.0.0\n"
+        );
+    }
+
     #[test]
     fn renders_spdx_header_only_when_enabled() {
         let mut spec = RpmSpec {
@@ -375,25 +995,225 @@ mod tests {
                 pkgname: "serde-1".to_string(),
                 rpm_name: "rust-serde-1".to_string(),
                 rpm_version: "1.0.0".to_string(),
+                release: "%autorelease".to_string(),
                 summary: "Rust crate \"serde\"".to_string(),
                 license: "MIT OR Apache-2.0".to_string(),
                 url: "https://example.invalid/serde".to_string(),
                 source_url: "https://example.invalid/source".to_string(),
                 sha256: None,
                 build_requires: vec![],
+                test_build_requires: vec![],
+                edition: None,
+                rust_version: None,
+                yanked: false,
+                bins: vec![],
+                c_library: None,
+                bin_assets: vec![],
                 with_spdx: false,
+                macro_flavor: MacroFlavor::Rustcrates,
+                tests: TestStyle::Run,
             },
             main_package: SpecPackage {
                 description: "Main package".to_string(),
                 ..SpecPackage::default()
             },
             feature_packages: vec![],
+            binary_package: None,
             files: vec![],
             changelog: false,
+            checks: vec![],
         };
 
         assert!(!spec.render().contains("SPDX-License-Identifier"));
         spec.source.with_spdx = true;
         assert!(spec.render().starts_with("# SPDX-FileCopyrightText:"));
     }
+
+    #[test]
+    fn cargo_flavor_writes_build_sections() {
+        let mut spec = RpmSpec {
+            source: SpecSource {
+                crate_name: "serde".to_string(),
+                full_version: "1.0.0".to_string(),
+                pkgname: "serde-1".to_string(),
+                rpm_name: "rust-serde-1".to_string(),
+                rpm_version: "1.0.0".to_string(),
+                release: "0".to_string(),
+                summary: "Rust crate \"serde\"".to_string(),
+                license: "MIT OR Apache-2.0".to_string(),
+                url: "https://example.invalid/serde".to_string(),
+                source_url: "https://example.invalid/source".to_string(),
+                sha256: None,
+                build_requires: vec!["cargo-packaging".to_string()],
+                test_build_requires: vec![],
+                edition: None,
+                rust_version: None,
+                yanked: false,
+                bins: vec![],
+                c_library: None,
+                bin_assets: vec![],
+                with_spdx: false,
+                macro_flavor: MacroFlavor::Cargo,
+                tests: TestStyle::Bcond,
+            },
+            main_package: SpecPackage {
+                description: "Main package".to_string(),
+                ..SpecPackage::default()
+            },
+            feature_packages: vec![],
+            binary_package: None,
+            files: vec![],
+            changelog: false,
+            checks: vec![],
+        };
+
+        let rendered = spec.render();
+        assert!(rendered.contains("%global pkgname serde-1\n\n%bcond check 1\n"));
+        assert!(rendered.contains("Release:        0\n"));
+        assert!(!rendered.contains("BuildSystem:"));
+        assert!(!rendered.contains("#!RemoteAsset:"));
+        assert!(rendered
+            .contains("%prep\n%autosetup -n %{crate_name}-%{full_version} -p1\n%cargo_prep\n"));
+        assert!(rendered.contains("%check\n%if %{with check}\n%cargo_test\n%endif\n"));
+        assert!(!rendered.contains("rust >="));
+
+        spec.source.edition = Some("2021".to_string());
+        spec.source.rust_version = Some("1.70".to_string());
+        assert!(spec.render().contains(
+            "# Rust edition 2021, MSRV 1.70\n\
+             BuildRequires:  cargo-packaging\n\
+             BuildRequires:  rust >= 1.70\n"
+        ));
+        spec.source.edition = None;
+        spec.source.rust_version = None;
+
+        spec.source.yanked = true;
+        assert!(spec
+            .render()
+            .starts_with("# WARNING: serde 1.0.0 is yanked on crates.io. It is packaged as an\n"));
+        spec.source.yanked = false;
+
+        spec.source.test_build_requires = vec!["crate(proptest-1/default) >= 1.4.0".to_string()];
+        assert!(spec.render().contains(
+            "BuildRequires:  cargo-packaging\n%if %{with check}\n\
+             BuildRequires:  crate(proptest-1/default) >= 1.4.0\n%endif\n"
+        ));
+
+        spec.checks = vec![
+            CheckRun::default(),
+            CheckRun {
+                args: vec!["-n".to_string()],
+                ..CheckRun::default()
+            },
+        ];
+        assert!(spec
+            .render()
+            .contains("%if %{with check}\n%cargo_test\n%cargo_test -n\n%endif\n"));
+
+        spec.checks = vec![
+            CheckRun::default(),
+            CheckRun {
+                args: vec!["-f".to_string(), "rc".to_string()],
+                broken: true,
+                architectures: vec!["x86_64".to_string(), "aarch64".to_string()],
+            },
+        ];
+        assert!(spec.render().contains(
+            "%cargo_test\n%ifarch x86_64 aarch64\n%cargo_test -f rc || :\n%endif\n%endif\n"
+        ));
+
+        assert!(spec.render().contains("BuildArch:      noarch\n"));
+        spec.source.bins = vec!["serde-gen".to_string(), "serde-check".to_string()];
+        spec.binary_package = Some(SpecPackage {
+            binary: Some("serde-tools".to_string()),
+            summary: "Serde tools".to_string(),
+            description: "The serde-gen and serde-check executables.".to_string(),
+            ..SpecPackage::default()
+        });
+        let rendered = spec.render();
+        assert!(!rendered.contains("BuildArch:"));
+        assert!(rendered.contains(
+            "%package     -n serde-tools\nSummary:        Serde tools\n\n\
+             %description -n serde-tools\n"
+        ));
+        assert!(rendered.contains(
+            "%build\n%cargo_build\ncargo build --release --offline --bin serde-gen --bin serde-check\n\n\
+             %install\n%cargo_install\n\
+             install -Dpm 0755 -t %{buildroot}%{_bindir} target/release/serde-gen target/release/serde-check\n"
+        ));
+        spec.source.bin_assets = vec![SpecAsset {
+            source: "man/serde-gen.1".to_string(),
+            destination: "%{_mandir}/man1/serde-gen.1".to_string(),
+            man_page: true,
+        }];
+        assert!(spec.render().contains(
+            "target/release/serde-check\n\
+             install -Dpm 0644 man/serde-gen.1 %{buildroot}%{_mandir}/man1/serde-gen.1\n"
+        ));
+        assert_eq!(
+            spec.source.bin_assets[0].files_entry(),
+            "%{_mandir}/man1/serde-gen.1*"
+        );
+        spec.source.bin_assets = vec![];
+        spec.source.bins = vec![];
+        spec.binary_package = None;
+
+        spec.source.c_library = Some(SpecCLibrary {
+            name: "serde_ffi".to_string(),
+            soversion: "1".to_string(),
+            cdylib: true,
+            staticlib: false,
+        });
+        let rendered = spec.render();
+        assert!(!rendered.contains("BuildArch:"));
+        assert!(rendered.contains(
+            "%cargo_build\n\
+             cargo rustc --release --offline --lib -- -C link-arg=-Wl,-soname,libserde_ffi.so.1\n\n\
+             %install\n%cargo_install\n\
+             install -Dpm 0755 target/release/libserde_ffi.so %{buildroot}%{_libdir}/libserde_ffi.so.%{full_version}\n\
+             ln -s libserde_ffi.so.%{full_version} %{buildroot}%{_libdir}/libserde_ffi.so.1\n\
+             ln -s libserde_ffi.so.1 %{buildroot}%{_libdir}/libserde_ffi.so\n"
+        ));
+        spec.source.c_library = None;
+
+        spec.source.tests = TestStyle::Skip;
+        let rendered = spec.render();
+        assert!(!rendered.contains("%check"));
+        assert!(!rendered.contains("proptest"));
+
+        spec.source.macro_flavor = MacroFlavor::Rustcrates;
+        let rendered = spec.render();
+        assert!(rendered.contains("BuildSystem:    rustcrates\n"));
+        assert!(!rendered.contains("%prep"));
+    }
+
+    #[test]
+    fn description_keeps_multibyte_text_and_guards_rpm_syntax() {
+        let text = format!(
+            "{}\n{}\n%files\n# Heading\nbad\u{202E}txt\u{0}\tend",
+            "高性能 🦀 parser for %{name}-style templates", "日本語の説明",
+        );
+        let mut out = String::new();
+        render_description(&mut out, None, &text).unwrap();
+
+        assert_eq!(
+            out,
+            "\n%description\n\
+             高性能 🦀 parser for %%{name}-style templates\n\
+             日本語の説明\n \
+             %%files\n\
+             Heading\n\
+             badtxt end\n"
+        );
+    }
+
+    #[test]
+    fn tag_values_stay_on_one_line() {
+        assert_eq!(
+            spec_tag_value("Ünïcödé summary\r\n  continued\u{1b}[0m"),
+            "Ünïcödé summary continued[0m"
+        );
+        assert_eq!(spec_tag_value("100% pure Rust"), "100%% pure Rust");
+        assert_eq!(escape_rpm_macros("100% %{?x}"), "100%% %%{?x}");
+    }
 }
@@ -0,0 +1,222 @@
+//! Subpackages for the C API library and the examples of a crate.
+//!
+//! FFI crates declare `crate-type = ["cdylib"]` or `["staticlib"]` for a
+//! library that C programs link against, which `%cargo_install` of the
+//! source package never builds. With `c_api = true` in the
+//! `[target_packages]` table, the spec builds it as well: the shared library
+//! gets the soname `lib<name>.so.<compat version>` (cargo sets none) and is
+//! packaged as `lib<name>`, while the `lib<name>.so` link and the static
+//! library go into `lib<name>-devel`. With `examples = true`, the crate's
+//! examples are shipped as documentation in `%{name}-examples`.
+
+use std::path::Path;
+
+use cargo::core::compiler::CrateType;
+use cargo::core::TargetKind;
+use serde::Deserialize;
+
+use crate::crates::CrateInfo;
+use crate::takopack::spec::{SpecCLibrary, SpecFiles, SpecPackage};
+use crate::version::compat_version;
+
+/// The `[target_packages]` table of takopack.toml.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TargetPackages {
+    /// Build and package the `cdylib`/`staticlib` of the crate.
+    pub c_api: bool,
+    /// Ship the crate's examples in `%{name}-examples`.
+    pub examples: bool,
+}
+
+/// The C API library of `crate_info`, if its library target builds one.
+pub fn c_library(crate_info: &CrateInfo) -> Option<SpecCLibrary> {
+    crate_info
+        .targets()
+        .iter()
+        .find_map(|target| match target.kind() {
+            TargetKind::Lib(crate_types) => {
+                let cdylib = crate_types.contains(&CrateType::Cdylib);
+                let staticlib = crate_types.contains(&CrateType::Staticlib);
+                (cdylib || staticlib).then(|| SpecCLibrary {
+                    name: target.crate_name(),
+                    soversion: compat_version(crate_info.version()),
+                    cdylib,
+                    staticlib,
+                })
+            }
+            _ => None,
+        })
+}
+
+/// The examples of `crate_info`, relative to the crate root. Examples of
+/// several files are given by their directory.
+pub fn examples(crate_info: &CrateInfo) -> Vec<String> {
+    let root = crate_info.manifest_path().parent().unwrap_or(Path::new(""));
+    let mut examples: Vec<String> = crate_info
+        .targets()
+        .iter()
+        .filter(|target| target.is_example())
+        .filter_map(|target| example_path(root, target.src_path().path()?))
+        .collect();
+    examples.sort_unstable();
+    examples.dedup();
+    examples
+}
+
+fn example_path(root: &Path, src_path: &Path) -> Option<String> {
+    let relative = src_path.strip_prefix(root).ok()?;
+    let path = if relative.file_name()? == "main.rs" {
+        relative.parent()?
+    } else {
+        relative
+    };
+    Some(path.to_string_lossy().into_owned())
+}
+
+/// The packages of `library`: `lib<name>` with the shared library, and
+/// `lib<name>-devel` with what programs link against.
+pub fn c_library_packages(
+    library: &SpecCLibrary,
+    summary_prefix: &str,
+    description_prefix: &str,
+) -> Vec<(SpecPackage, SpecFiles)> {
+    let runtime = format!("lib{}", library.name);
+    let devel = format!("{}-devel", runtime);
+    let mut packages = Vec::new();
+    let mut devel_lines = Vec::new();
+    let mut devel_entries = Vec::new();
+    if library.cdylib {
+        packages.push((
+            SpecPackage {
+                binary: Some(runtime.clone()),
+                summary: format!("{} - C library", summary_prefix),
+                description: format!(
+                    "{}This package contains the shared library {}.",
+                    description_prefix,
+                    library.soname()
+                ),
+                ..SpecPackage::default()
+            },
+            SpecFiles {
+                package: Some(runtime.clone()),
+                entries: vec![
+                    format!("%{{_libdir}}/{}", library.soname()),
+                    format!("%{{_libdir}}/lib{}.so.%{{full_version}}", library.name),
+                ],
+            },
+        ));
+        devel_lines.push(format!(
+            "Requires:       {}%{{?_isa}} = %{{version}}-%{{release}}",
+            runtime
+        ));
+        devel_entries.push(format!("%{{_libdir}}/lib{}.so", library.name));
+    }
+    if library.staticlib {
+        devel_entries.push(format!("%{{_libdir}}/lib{}.a", library.name));
+    }
+    packages.push((
+        SpecPackage {
+            binary: Some(devel.clone()),
+            summary: format!("{} - C library development files", summary_prefix),
+            description: format!(
+                "{}This package contains the files for linking against lib{}.",
+                description_prefix, library.name
+            ),
+            extra_lines: devel_lines,
+            ..SpecPackage::default()
+        },
+        SpecFiles {
+            package: Some(devel),
+            entries: devel_entries,
+        },
+    ));
+    packages
+}
+
+/// The `%{name}-examples` package of `examples`.
+pub fn examples_package(
+    examples: &[String],
+    summary_prefix: &str,
+    description_prefix: &str,
+) -> (SpecPackage, SpecFiles) {
+    let name = "%{name}-examples".to_string();
+    (
+        SpecPackage {
+            binary: Some(name.clone()),
+            summary: format!("{} - examples", summary_prefix),
+            description: format!(
+                "{}This package contains the examples of the crate.",
+                description_prefix
+            ),
+            extra_lines: vec!["BuildArch:      noarch".to_string()],
+            ..SpecPackage::default()
+        },
+        SpecFiles {
+            package: Some(name),
+            entries: examples
+                .iter()
+                .map(|example| format!("%doc {}", example))
+                .collect(),
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{c_library_packages, example_path, examples_package};
+    use crate::takopack::spec::SpecCLibrary;
+    use std::path::Path;
+
+    #[test]
+    fn c_library_and_examples_get_their_own_packages() {
+        let library = SpecCLibrary {
+            name: "foo_ffi".to_string(),
+            soversion: "0.4".to_string(),
+            cdylib: true,
+            staticlib: true,
+        };
+        let packages = c_library_packages(&library, "Rust crate", "");
+        let names: Vec<_> = packages
+            .iter()
+            .map(|(package, _)| package.binary.as_deref().unwrap())
+            .collect();
+        assert_eq!(names, ["libfoo_ffi", "libfoo_ffi-devel"]);
+        assert_eq!(
+            packages[0].1.entries,
+            [
+                "%{_libdir}/libfoo_ffi.so.0.4",
+                "%{_libdir}/libfoo_ffi.so.%{full_version}"
+            ]
+        );
+        assert_eq!(
+            packages[1].0.extra_lines,
+            ["Requires:       libfoo_ffi%{?_isa} = %{version}-%{release}"]
+        );
+        assert_eq!(
+            packages[1].1.entries,
+            ["%{_libdir}/libfoo_ffi.so", "%{_libdir}/libfoo_ffi.a"]
+        );
+
+        let static_only = SpecCLibrary {
+            cdylib: false,
+            ..library
+        };
+        let packages = c_library_packages(&static_only, "Rust crate", "");
+        assert_eq!(packages.len(), 1);
+        assert!(packages[0].0.extra_lines.is_empty());
+
+        let root = Path::new("/src/foo");
+        assert_eq!(
+            example_path(root, Path::new("/src/foo/examples/demo.rs")).as_deref(),
+            Some("examples/demo.rs")
+        );
+        assert_eq!(
+            example_path(root, Path::new("/src/foo/examples/server/main.rs")).as_deref(),
+            Some("examples/server")
+        );
+        let (_, files) = examples_package(&["examples/demo.rs".to_string()], "Rust crate", "");
+        assert_eq!(files.package.as_deref(), Some("%{name}-examples"));
+        assert_eq!(files.entries, ["%doc examples/demo.rs"]);
+    }
+}
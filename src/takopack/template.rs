@@ -0,0 +1,244 @@
+//! User templates for sections of the generated spec.
+//!
+//! A template directory (`--template-dir`, or `template_dir` in
+//! takopack.toml) may hold any of
+//!
+//! - `source.spec`: the header, up to and including `BuildRequires:`
+//! - `package.spec`: the main package, every feature subpackage and the
+//!   package of the crate's executables
+//! - `check.spec`: `%build`, `%install` and `%check`
+//! - `files.spec`: `%files`
+//!
+//! written in the Jinja syntax of minijinja. Sections without a template are
+//! rendered as before. A template gets the data of its section (`source`,
+//! `package`, `flavor`/`tests`/`checks`/`bins`/`bin_assets`/`c_library` or `files`, with the fields of
+//! [`SpecSource`], [`SpecPackage`], [`CheckRun`] and [`SpecFiles`]) and
+//! `default`, the section as takopack renders it, so a template may also
+//! just add lines around it. Text fields such as `summary` are plain text;
+//! a template that prints them must double any `%` itself.
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use anyhow::Context;
+use minijinja::{Environment, UndefinedBehavior, Value};
+
+use crate::config::{MacroFlavor, TestStyle};
+use crate::errors::*;
+use crate::takopack::spec::{
+    self, CheckRun, SpecAsset, SpecCLibrary, SpecFiles, SpecPackage, SpecSource,
+};
+
+const SOURCE_TEMPLATE: &str = "source.spec";
+const PACKAGE_TEMPLATE: &str = "package.spec";
+const CHECK_TEMPLATE: &str = "check.spec";
+const FILES_TEMPLATE: &str = "files.spec";
+
+/// The templates of one template directory.
+pub struct SpecTemplates {
+    env: Environment<'static>,
+}
+
+impl Default for SpecTemplates {
+    /// No templates; every section is rendered by takopack.
+    fn default() -> Self {
+        let mut env = Environment::new();
+        env.set_keep_trailing_newline(true);
+        env.set_undefined_behavior(UndefinedBehavior::Strict);
+        SpecTemplates { env }
+    }
+}
+
+impl SpecTemplates {
+    /// The templates in `dir`, which must exist.
+    pub fn load(dir: &Path) -> Result<Self> {
+        if !dir.is_dir() {
+            takopack_bail!("template directory {} does not exist", dir.display());
+        }
+        let mut templates = Self::default();
+        for name in [
+            SOURCE_TEMPLATE,
+            PACKAGE_TEMPLATE,
+            CHECK_TEMPLATE,
+            FILES_TEMPLATE,
+        ] {
+            let path = dir.join(name);
+            let text = match fs::read_to_string(&path) {
+                Ok(text) => text,
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Failed to read {}", path.display()))
+                }
+            };
+            templates
+                .env
+                .add_template_owned(name, text)
+                .with_context(|| format!("Invalid template {}", path.display()))?;
+        }
+        Ok(templates)
+    }
+
+    /// The templates in `dir`, or none.
+    pub fn load_optional(dir: Option<&Path>) -> Result<Self> {
+        dir.map_or_else(|| Ok(Self::default()), Self::load)
+    }
+
+    pub fn source(&self, source: &SpecSource) -> Result<String> {
+        let mut default = String::new();
+        spec::render_header_section(&mut default, source)?;
+        spec::render_source_requirements_section(&mut default, source)?;
+        self.render(
+            SOURCE_TEMPLATE,
+            default,
+            [("source", Value::from_serialize(source))],
+        )
+    }
+
+    /// The main package section, or a `%package` section for a feature or
+    /// the crate's executables.
+    pub fn package(&self, package: &SpecPackage) -> Result<String> {
+        let mut default = String::new();
+        if package.binary.is_some() {
+            spec::render_binary_package_section(&mut default, package)?;
+        } else if package.feature.is_some() {
+            spec::render_feature_package_section(&mut default, package)?;
+        } else {
+            spec::render_main_package_section(&mut default, package)?;
+        }
+        self.render(
+            PACKAGE_TEMPLATE,
+            default,
+            [("package", Value::from_serialize(package))],
+        )
+    }
+
+    pub fn check(
+        &self,
+        flavor: MacroFlavor,
+        tests: TestStyle,
+        checks: &[CheckRun],
+        bins: &[String],
+        bin_assets: &[SpecAsset],
+        c_library: Option<&SpecCLibrary>,
+    ) -> Result<String> {
+        let mut default = String::new();
+        spec::render_build_check_install_section(
+            &mut default,
+            flavor,
+            tests,
+            checks,
+            bins,
+            bin_assets,
+            c_library,
+        )?;
+        self.render(
+            CHECK_TEMPLATE,
+            default,
+            [
+                ("flavor", Value::from_serialize(flavor)),
+                ("tests", Value::from_serialize(tests)),
+                ("checks", Value::from_serialize(checks)),
+                ("bins", Value::from_serialize(bins)),
+                ("bin_assets", Value::from_serialize(bin_assets)),
+                ("c_library", Value::from_serialize(c_library)),
+            ],
+        )
+    }
+
+    pub fn files(&self, files: &[SpecFiles]) -> Result<String> {
+        let mut default = String::new();
+        spec::render_files_section(&mut default, files)?;
+        self.render(
+            FILES_TEMPLATE,
+            default,
+            [("files", Value::from_serialize(files))],
+        )
+    }
+
+    fn render<const N: usize>(
+        &self,
+        name: &str,
+        default: String,
+        data: [(&str, Value); N],
+    ) -> Result<String> {
+        let Ok(template) = self.env.get_template(name) else {
+            return Ok(default);
+        };
+        let context: Value = data
+            .into_iter()
+            .chain([("default", Value::from(default))])
+            .collect();
+        template
+            .render(context)
+            .with_context(|| format!("Failed to render template {}", name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SpecTemplates;
+    use crate::config::{MacroFlavor, TestStyle};
+    use crate::takopack::spec::{CheckRun, SpecFiles, SpecPackage};
+    use std::fs;
+
+    #[test]
+    fn templates_replace_only_their_sections() {
+        let files = [SpecFiles {
+            package: None,
+            entries: vec!["%license LICENSE".to_string()],
+        }];
+        let checks = [CheckRun {
+            args: vec!["-n".to_string()],
+            ..CheckRun::default()
+        }];
+        let package = SpecPackage {
+            feature: Some("std".to_string()),
+            summary: "Rust crate \"log\" - feature \"std\"".to_string(),
+            description: "This package contains the std feature.".to_string(),
+            ..SpecPackage::default()
+        };
+
+        let builtin = SpecTemplates::default();
+        let default_files = builtin.files(&files).unwrap();
+        assert_eq!(default_files, "%files\n%license LICENSE\n\n");
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("check.spec"),
+            "{% if tests == \"skip\" %}{{ default }}{% else %}%check\n\
+             {% for check in checks %}%cargo_test {{ check.args | join(\" \") }} -- --skip net\n\
+             {% endfor %}\n{% endif %}",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("package.spec"),
+            "{{ default }}{% if package.feature %}# feature {{ package.feature }}\n{% endif %}",
+        )
+        .unwrap();
+        let templates = SpecTemplates::load(dir.path()).unwrap();
+
+        assert_eq!(templates.files(&files).unwrap(), default_files);
+        assert_eq!(
+            templates
+                .check(MacroFlavor::Cargo, TestStyle::Run, &checks, &[], &[], None)
+                .unwrap(),
+            "%check\n%cargo_test -n -- --skip net\n\n"
+        );
+        assert_eq!(
+            templates
+                .package(&package)
+                .unwrap()
+                .strip_prefix(&builtin.package(&package).unwrap()),
+            Some("# feature std\n")
+        );
+
+        fs::write(dir.path().join("files.spec"), "{{ no_such_field }}").unwrap();
+        let err = SpecTemplates::load(dir.path())
+            .unwrap()
+            .files(&files)
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("files.spec"), "{:#}", err);
+        assert!(SpecTemplates::load(&dir.path().join("missing")).is_err());
+    }
+}
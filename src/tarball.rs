@@ -0,0 +1,394 @@
+//! Checks applied to the entries of crate tarballs before they are unpacked
+//! or repacked.
+//!
+//! `cargo package` only produces plain files and directories, but crates
+//! published by other means occasionally carry entries that do not belong
+//! in a source tree. [`check_entry`] sorts them out: names that climb out
+//! of the archive are an error, while non-UTF-8 names, links pointing
+//! outside the tree and special files are left out with a warning. pax
+//! global headers and other metadata records are ignored.
+//!
+//! What an archive may unpack to is also bounded, see [`ExtractionLimits`],
+//! so that a crate inflating to gigabytes fails instead of filling the disk.
+
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::Context;
+use serde::Deserialize;
+use tar::{Entry, EntryType};
+
+use crate::errors::*;
+
+/// Bounds on what unpacking one archive may write, set in the `[extraction]`
+/// table of takopack.toml.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExtractionLimits {
+    /// Total size of all files, in bytes.
+    pub max_unpacked_size: u64,
+    /// Number of files, directories and links.
+    pub max_files: usize,
+    /// Size of a single file, in bytes.
+    pub max_file_size: u64,
+}
+
+impl Default for ExtractionLimits {
+    fn default() -> Self {
+        ExtractionLimits {
+            max_unpacked_size: 2 << 30,
+            max_files: 100_000,
+            max_file_size: 512 << 20,
+        }
+    }
+}
+
+impl ExtractionLimits {
+    /// Start accounting for one extraction.
+    pub fn budget(&self) -> ExtractionBudget {
+        ExtractionBudget {
+            limits: *self,
+            files: 0,
+            size: 0,
+        }
+    }
+}
+
+/// Running totals of an extraction, checked against its limits.
+#[derive(Debug, Clone)]
+pub struct ExtractionBudget {
+    limits: ExtractionLimits,
+    files: usize,
+    size: u64,
+}
+
+impl ExtractionBudget {
+    /// Account for `entry` before it is unpacked, failing once it would
+    /// exceed a limit.
+    pub fn admit<R: Read>(&mut self, entry: &Entry<'_, R>) -> Result<()> {
+        let name = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+        let size = entry.size();
+        if size > self.limits.max_file_size {
+            takopack_bail!(
+                "{} is {} bytes, more than the {} allowed for a single file \
+                 ([extraction] max_file_size)",
+                name,
+                size,
+                self.limits.max_file_size
+            );
+        }
+        self.files += 1;
+        if self.files > self.limits.max_files {
+            takopack_bail!(
+                "more than {} entries ([extraction] max_files)",
+                self.limits.max_files
+            );
+        }
+        self.size = self.size.saturating_add(size);
+        if self.size > self.limits.max_unpacked_size {
+            takopack_bail!(
+                "unpacks to more than {} bytes ([extraction] max_unpacked_size)",
+                self.limits.max_unpacked_size
+            );
+        }
+        Ok(())
+    }
+}
+
+/// What to do with a tarball entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntryCheck {
+    /// Keep the entry, at this path relative to the archive root.
+    Keep(PathBuf),
+    /// Leave out an entry that only carries archive metadata.
+    Ignore,
+    /// Leave out an entry that cannot be unpacked safely, for this reason.
+    Skip(String),
+}
+
+/// Decide whether `entry` may be unpacked. Fails for names that escape the
+/// archive through `..`.
+pub fn check_entry<R: Read>(entry: &Entry<'_, R>) -> Result<EntryCheck> {
+    let kind = entry.header().entry_type();
+    if matches!(
+        kind,
+        EntryType::XGlobalHeader
+            | EntryType::XHeader
+            | EntryType::GNULongName
+            | EntryType::GNULongLink
+    ) {
+        return Ok(EntryCheck::Ignore);
+    }
+
+    let raw = entry.path_bytes();
+    let Ok(name) = std::str::from_utf8(&raw) else {
+        return Ok(EntryCheck::Skip("file name is not valid UTF-8".to_string()));
+    };
+    let Some(path) = sanitize_path(Path::new(name))? else {
+        return Ok(EntryCheck::Ignore);
+    };
+
+    match kind {
+        EntryType::Regular | EntryType::Continuous | EntryType::Directory => {}
+        EntryType::Symlink | EntryType::Link => {
+            let Some(target) = entry.link_name_bytes() else {
+                return Ok(EntryCheck::Skip("link without a target".to_string()));
+            };
+            let Ok(target) = std::str::from_utf8(&target) else {
+                return Ok(EntryCheck::Skip(
+                    "link target is not valid UTF-8".to_string(),
+                ));
+            };
+            // Symbolic links are relative to their directory, hard links
+            // to the archive root.
+            let base = match kind {
+                EntryType::Symlink => path.parent().unwrap_or(Path::new("")),
+                _ => Path::new(""),
+            };
+            if !link_stays_inside(base, Path::new(target)) {
+                return Ok(EntryCheck::Skip(format!(
+                    "link to {} points outside the crate",
+                    target
+                )));
+            }
+        }
+        other => {
+            return Ok(EntryCheck::Skip(format!(
+                "unsupported entry type {:?}",
+                other
+            )))
+        }
+    }
+    Ok(EntryCheck::Keep(path))
+}
+
+/// Unpack `entry` under `dest` if [`check_entry`] keeps it and `budget`
+/// allows, warning about skipped entries of `archive`. Returns the path the
+/// entry was unpacked at, relative to `dest`.
+pub fn unpack_checked<R: Read>(
+    entry: &mut Entry<'_, R>,
+    dest: &Path,
+    archive: &Path,
+    budget: &mut ExtractionBudget,
+) -> Result<Option<PathBuf>> {
+    match check_entry(entry)? {
+        EntryCheck::Keep(path) => {
+            budget
+                .admit(entry)
+                .with_context(|| format!("{} exceeds the extraction limits", archive.display()))?;
+            if !entry.unpack_in(dest)? {
+                takopack_bail!("{} contained path traversals via '..'", archive.display());
+            }
+            Ok(Some(path))
+        }
+        EntryCheck::Ignore => Ok(None),
+        EntryCheck::Skip(reason) => {
+            warn_skipped(entry, archive, &reason);
+            Ok(None)
+        }
+    }
+}
+
+/// Warn that `entry` of `archive` is left out.
+pub fn warn_skipped<R: Read>(entry: &Entry<'_, R>, archive: &Path, reason: &str) {
+    takopack_warn!(
+        "skipping {} in {}: {}",
+        String::from_utf8_lossy(&entry.path_bytes()),
+        archive.display(),
+        reason
+    );
+}
+
+/// `path` without leading `/` and `.` components, or `None` when nothing
+/// is left. Fails on `..`.
+fn sanitize_path(path: &Path) -> Result<Option<PathBuf>> {
+    let mut clean = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => clean.push(part),
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+            Component::ParentDir => {
+                takopack_bail!("unsafe path in crate archive: {}", path.display())
+            }
+        }
+    }
+    Ok((!clean.as_os_str().is_empty()).then_some(clean))
+}
+
+/// Whether `target`, resolved from the directory `base`, stays inside the
+/// archive.
+fn link_stays_inside(base: &Path, target: &Path) -> bool {
+    let mut depth = base.components().count();
+    for component in target.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir => match depth.checked_sub(1) {
+                Some(up) => depth = up,
+                None => return false,
+            },
+            Component::RootDir | Component::Prefix(_) => return false,
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_entry, unpack_checked, EntryCheck, ExtractionLimits};
+    use std::path::{Path, PathBuf};
+    use tar::{EntryType, Header};
+
+    /// A `(type, name, link target, data)` entry of [`crafted`].
+    type CraftedEntry<'a> = (EntryType, &'a [u8], &'a [u8], &'a [u8]);
+
+    /// A tar archive of `entries`. Names are written into the header as is,
+    /// bypassing the checks of [`Header::set_path`].
+    fn crafted(entries: &[CraftedEntry]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (kind, name, link, data) in entries {
+            let mut header = Header::new_gnu();
+            header.set_entry_type(*kind);
+            header.set_mode(0o644);
+            header.set_size(data.len() as u64);
+            let old = header.as_old_mut();
+            old.name[..name.len()].copy_from_slice(name);
+            old.linkname[..link.len()].copy_from_slice(link);
+            header.set_cksum();
+            builder.append(&header, *data).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn checks(archive: &[u8]) -> Vec<Result<EntryCheck, String>> {
+        let mut archive = tar::Archive::new(archive);
+        archive
+            .entries()
+            .unwrap()
+            .map(|entry| check_entry(&entry.unwrap()).map_err(|e| e.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn unusual_entries_are_skipped() {
+        let archive = crafted(&[
+            (EntryType::Directory, b"foo-1.0/", b"", b""),
+            (
+                EntryType::Regular,
+                b"./foo-1.0/src/lib.rs",
+                b"",
+                b"pub fn f() {}\n",
+            ),
+            (EntryType::Regular, b"foo-1.0/caf\xe9.txt", b"", b""),
+            (EntryType::Symlink, b"foo-1.0/src/main.rs", b"lib.rs", b""),
+            (EntryType::Symlink, b"foo-1.0/up", b"../../etc/passwd", b""),
+            (EntryType::Symlink, b"foo-1.0/abs", b"/etc/passwd", b""),
+            (EntryType::Link, b"foo-1.0/hard", b"foo-1.0/src/lib.rs", b""),
+            (EntryType::Fifo, b"foo-1.0/pipe", b"", b""),
+            (EntryType::XGlobalHeader, b"pax_global_header", b"", b""),
+        ]);
+        let skipped = |reason: &str| Ok(EntryCheck::Skip(reason.to_string()));
+        assert_eq!(
+            checks(&archive),
+            vec![
+                Ok(EntryCheck::Keep(PathBuf::from("foo-1.0"))),
+                Ok(EntryCheck::Keep(PathBuf::from("foo-1.0/src/lib.rs"))),
+                skipped("file name is not valid UTF-8"),
+                Ok(EntryCheck::Keep(PathBuf::from("foo-1.0/src/main.rs"))),
+                skipped("link to ../../etc/passwd points outside the crate"),
+                skipped("link to /etc/passwd points outside the crate"),
+                Ok(EntryCheck::Keep(PathBuf::from("foo-1.0/hard"))),
+                skipped("unsupported entry type Fifo"),
+                Ok(EntryCheck::Ignore),
+            ]
+        );
+    }
+
+    #[test]
+    fn traversal_is_rejected() {
+        let archive = crafted(&[(EntryType::Regular, b"foo-1.0/../../evil", b"", b"x")]);
+        let err = checks(&archive).remove(0).unwrap_err();
+        assert!(err.contains("unsafe path in crate archive"), "{}", err);
+    }
+
+    #[test]
+    fn only_safe_entries_are_unpacked() {
+        let archive = crafted(&[
+            (
+                EntryType::Regular,
+                b"foo-1.0/Cargo.toml",
+                b"",
+                b"[package]\n",
+            ),
+            (EntryType::Symlink, b"foo-1.0/escape", b"../../..", b""),
+            (EntryType::Regular, b"foo-1.0/\xff", b"", b"x"),
+            (EntryType::XGlobalHeader, b"pax_global_header", b"", b""),
+        ]);
+        let dest = tempfile::tempdir().unwrap();
+        let mut budget = ExtractionLimits::default().budget();
+        let mut unpacked = Vec::new();
+        for entry in tar::Archive::new(&archive[..]).entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let crate_file = Path::new("foo-1.0.crate");
+            if let Some(path) =
+                unpack_checked(&mut entry, dest.path(), crate_file, &mut budget).unwrap()
+            {
+                unpacked.push(path);
+            }
+        }
+        assert_eq!(unpacked, vec![PathBuf::from("foo-1.0/Cargo.toml")]);
+        assert!(dest.path().join("foo-1.0/Cargo.toml").is_file());
+        assert!(!dest.path().join("foo-1.0/escape").exists());
+        assert!(!dest.path().join("pax_global_header").exists());
+    }
+
+    #[test]
+    fn extraction_stops_at_the_limits() {
+        let archive = crafted(&[
+            (EntryType::Directory, b"foo-1.0/", b"", b""),
+            (EntryType::Regular, b"foo-1.0/a", b"", &[0; 600]),
+            (EntryType::Regular, b"foo-1.0/b", b"", &[0; 600]),
+        ]);
+        let unpack = |limits: ExtractionLimits| -> Result<(), String> {
+            let dest = tempfile::tempdir().unwrap();
+            let mut budget = limits.budget();
+            for entry in tar::Archive::new(&archive[..]).entries().unwrap() {
+                let mut entry = entry.unwrap();
+                unpack_checked(
+                    &mut entry,
+                    dest.path(),
+                    Path::new("foo-1.0.crate"),
+                    &mut budget,
+                )
+                .map_err(|e| format!("{:#}", e))?;
+            }
+            Ok(())
+        };
+        let limits = ExtractionLimits {
+            max_unpacked_size: 2000,
+            max_files: 3,
+            max_file_size: 1000,
+        };
+
+        assert_eq!(unpack(limits), Ok(()));
+        let err = unpack(ExtractionLimits {
+            max_files: 2,
+            ..limits
+        })
+        .unwrap_err();
+        assert!(err.contains("max_files"), "{}", err);
+        let err = unpack(ExtractionLimits {
+            max_file_size: 500,
+            ..limits
+        })
+        .unwrap_err();
+        assert!(err.contains("foo-1.0/a is 600 bytes"), "{}", err);
+        let err = unpack(ExtractionLimits {
+            max_unpacked_size: 1000,
+            ..limits
+        })
+        .unwrap_err();
+        assert!(err.starts_with("foo-1.0.crate exceeds"), "{}", err);
+        assert!(err.contains("max_unpacked_size"), "{}", err);
+    }
+}
@@ -0,0 +1,54 @@
+//! Behavior changes for takopack's own integration tests.
+//!
+//! These used to be switched on by the `takopack_TESTING_RUZT` and
+//! `takopack_TESTING_IGNORE_takopack_POLICY_VIOLATION` environment
+//! variables, checked separately wherever they mattered. A variable left in
+//! a packager's environment silently renamed every package or rewrote the
+//! Debian-era rules file. The test mode is now one [`TestMode`], built from
+//! the global `--test-mode` flag and handed to a packaging run in
+//! [`crate::package::PackageExecuteArgs`]. From there it travels in the run's
+//! [`crate::config::Config`] to the code that behaves differently; normal
+//! runs and library callers get [`TestMode::default`] and never see it.
+
+/// What an integration test run changes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TestMode {
+    /// Name packages `ruzt-*` instead of `rust-*`, so that tests never pull
+    /// in the distro's packages.
+    pub ruzt_prefix: bool,
+    /// Only warn about suspicious files in crates, and let the rules file
+    /// skip crate tests and allow crates that need nightly.
+    pub ignore_policy_violations: bool,
+}
+
+impl TestMode {
+    /// Everything `--test-mode` turns on.
+    pub fn integration() -> Self {
+        TestMode {
+            ruzt_prefix: true,
+            ignore_policy_violations: true,
+        }
+    }
+
+    /// Prefix of package names.
+    pub fn pkg_prefix(&self) -> &'static str {
+        if self.ruzt_prefix {
+            "ruzt"
+        } else {
+            "rust"
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TestMode;
+
+    #[test]
+    fn only_the_integration_mode_changes_behavior() {
+        assert_eq!(TestMode::default().pkg_prefix(), "rust");
+        let integration = TestMode::integration();
+        assert_eq!(integration.pkg_prefix(), "ruzt");
+        assert!(integration.ignore_policy_violations);
+    }
+}
@@ -0,0 +1,123 @@
+//! Crates that come with the Rust toolchain packages instead of being
+//! packaged from crates.io.
+//!
+//! The standard library is built from crates such as
+//! `rustc-std-workspace-core`, whose crates.io releases are placeholders;
+//! crates that can be built as part of it depend on them behind a feature.
+//! No package will ever provide `crate(rustc-std-workspace-core-1)`, so
+//! such dependencies are neither packaged by `vendor` nor written into
+//! `Requires:`.
+//!
+//! The built-in table can be extended with `toolchain_crates` in
+//! takopack.toml, using the `name` / `name@version` entries of
+//! [`SkipList`]. The version is the one the toolchain ships; a dependency
+//! is only dropped when that version satisfies its requirement.
+
+use std::sync::OnceLock;
+
+use anyhow::Context;
+use cargo::core::Dependency;
+
+use crate::config::Config;
+use crate::crates::CrateDepInfo;
+use crate::dep_filter::{Decision, DependencyFilter, Edge, SkipReason};
+use crate::errors::*;
+use crate::skip_list::SkipList;
+
+/// Crates shipped with the toolchain, with the version the sysroot has.
+const BUILTIN: &[&str] = &[
+    "rustc-std-workspace-core@1.99.0",
+    "rustc-std-workspace-alloc@1.99.0",
+    "rustc-std-workspace-std@1.99.0",
+    "compiler_builtins",
+];
+
+/// Table of the crates provided by the toolchain.
+#[derive(Debug, Clone)]
+pub struct ToolchainCrates {
+    crates: SkipList,
+}
+
+impl ToolchainCrates {
+    /// The built-in table.
+    pub fn builtin() -> &'static ToolchainCrates {
+        static BUILTIN_CRATES: OnceLock<ToolchainCrates> = OnceLock::new();
+        BUILTIN_CRATES.get_or_init(|| {
+            let mut crates = SkipList::default();
+            for entry in BUILTIN {
+                crates
+                    .add(entry)
+                    .expect("built-in toolchain crate entries should parse");
+            }
+            ToolchainCrates { crates }
+        })
+    }
+
+    /// The built-in table plus the `toolchain_crates` of `config`.
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let mut table = Self::builtin().clone();
+        for entry in &config.toolchain_crates {
+            table
+                .crates
+                .add(entry)
+                .with_context(|| format!("invalid toolchain_crates entry {:?}", entry))?;
+        }
+        Ok(table)
+    }
+
+    /// Whether the toolchain provides `crate_name` at a version satisfying
+    /// `req` (`None` for any version).
+    pub fn provides(&self, crate_name: &str, req: Option<&str>) -> bool {
+        self.crates.matches(crate_name, req)
+    }
+
+    /// Whether the toolchain provides what `dep` asks for.
+    pub fn provides_dependency(&self, dep: &Dependency) -> bool {
+        self.provides(
+            dep.package_name().as_str(),
+            Some(&dep.version_req().to_string()),
+        )
+    }
+
+    /// Drop the dependencies provided by the toolchain from `deps`.
+    pub fn retain_packaged(&self, deps: &mut CrateDepInfo) {
+        for (_, feature_deps) in deps.values_mut() {
+            feature_deps.retain(|dep| !self.provides_dependency(dep));
+        }
+    }
+}
+
+impl DependencyFilter for ToolchainCrates {
+    fn decide(&self, edge: &Edge<'_>) -> Option<Decision> {
+        self.provides(edge.crate_name, edge.req)
+            .then_some(Decision::Skip(SkipReason::Toolchain))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ToolchainCrates;
+    use crate::config::Config;
+
+    #[test]
+    fn configured_entries_extend_the_builtin_table() {
+        let builtin = ToolchainCrates::builtin();
+        assert!(builtin.provides("rustc-std-workspace-core", Some("^1.0.0")));
+        assert!(builtin.provides("rustc_std_workspace_alloc", None));
+        assert!(!builtin.provides("rustc-std-workspace-core", Some("^2")));
+        assert!(builtin.provides("compiler_builtins", Some("^0.1.2")));
+        assert!(!builtin.provides("serde", Some("^1")));
+
+        let config: Config =
+            toml::from_str(r#"toolchain_crates = ["rustc-demangle@0.1.24", "my-sysroot-shim"]"#)
+                .unwrap();
+        let table = ToolchainCrates::from_config(&config).unwrap();
+        assert!(table.provides("rustc-demangle", Some("^0.1.21")));
+        assert!(!table.provides("rustc-demangle", Some("^0.1.25")));
+        assert!(table.provides("my_sysroot_shim", Some("1")));
+        assert!(table.provides("rustc-std-workspace-std", Some("1")));
+
+        let config: Config = toml::from_str(r#"toolchain_crates = ["core@1.x"]"#).unwrap();
+        assert!(ToolchainCrates::from_config(&config).is_err());
+    }
+}
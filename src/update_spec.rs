@@ -0,0 +1,304 @@
+//! Regenerating a packaged spec for a new version without losing edits.
+//!
+//! Generated specs are often tuned by hand: an extra `Requires:`, a test
+//! skipped in `%check`. Packaging the next version with `pkg` starts from
+//! scratch and drops those edits. `cargo update-spec` reads the crate from
+//! the spec in a package directory, generates the spec of the new version
+//! and puts back every region between a `# takopack:keep` and a
+//! `# takopack:end-keep` line. A region goes after the line it followed
+//! before, looked up in the same section; if that line is gone, it goes to
+//! the start of the section, and if the section is gone too, to the end of
+//! the spec with a warning.
+
+use anyhow::Context;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::errors::*;
+use crate::events::{self, Event};
+use crate::package::{DownloadArgs, PackageExecuteArgs, PackageInitArgs};
+use crate::spec_diff;
+
+/// Starts a region of the spec that `update-spec` keeps.
+pub const KEEP_BEGIN: &str = "# takopack:keep";
+/// Ends a region started with [`KEEP_BEGIN`].
+pub const KEEP_END: &str = "# takopack:end-keep";
+
+/// Lines starting a section of a spec.
+const SECTIONS: &[&str] = &[
+    "%package",
+    "%description",
+    "%prep",
+    "%build",
+    "%install",
+    "%check",
+    "%files",
+    "%changelog",
+];
+
+/// Lines kept between the markers, and where they were.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct KeptRegion {
+    /// The header of the section the region was in; `None` in the preamble
+    section: Option<String>,
+    /// The last non-empty line before the region in that section
+    after: Option<String>,
+    /// The region, markers included
+    lines: Vec<String>,
+}
+
+/// Regenerate the spec in `dir` for `version` of its crate (the latest
+/// without it), keeping the marked regions.
+pub fn update_spec_dir(
+    dir: &Path,
+    version: Option<String>,
+    download: DownloadArgs,
+    finish: PackageExecuteArgs,
+) -> Result<PathBuf> {
+    let spec = crate::rpm_build::find_spec(dir)?;
+    let old =
+        fs::read_to_string(&spec).with_context(|| format!("Failed to read {}", spec.display()))?;
+    let Some(crate_name) = global(&old, "crate_name") else {
+        takopack_bail!(
+            "{} has no `%global crate_name`; is it a takopack spec?",
+            spec.display()
+        );
+    };
+    let regions = kept_regions(&old)?;
+
+    let workdir = tempfile::Builder::new()
+        .prefix("takopack-update-spec-")
+        .tempdir()
+        .context("Failed to create temporary directory")?;
+    let init = PackageInitArgs {
+        crate_name,
+        version,
+        offline: false,
+        mirror: None,
+        registry: None,
+        index_url: None,
+        allow_yanked: false,
+        crate_file: None,
+        download,
+    };
+    let (_, new) = spec_diff::generate_spec(init, finish, workdir.path())?;
+    let (updated, misplaced) = reapply_regions(&new, &regions);
+    for region in misplaced {
+        takopack_warn!(
+            "Section {} is gone from the new spec; kept lines moved to its end:\n{}",
+            region.section.as_deref().unwrap_or("(preamble)"),
+            region.lines.join("\n")
+        );
+    }
+
+    fs::write(&spec, &updated).with_context(|| format!("Failed to write {}", spec.display()))?;
+    crate::util::copy_normalized_cargo_toml_to_dir(workdir.path(), dir)?;
+    let diff = spec_diff::diff_specs(
+        &spec_diff::parse_spec(&old),
+        &spec_diff::parse_spec(&updated),
+    );
+    events::emit(Event::note(diff.to_string()));
+    events::emit(Event::SpecWritten { spec: spec.clone() });
+    Ok(spec)
+}
+
+/// The value of `%global name` in `spec`.
+fn global(spec: &str, name: &str) -> Option<String> {
+    spec.lines().find_map(|line| {
+        let mut words = line.split_whitespace();
+        (words.next() == Some("%global") && words.next() == Some(name))
+            .then(|| words.next())
+            .flatten()
+            .map(str::to_string)
+    })
+}
+
+/// Whether `line`, trimmed, starts a section of a spec.
+pub fn is_section(line: &str) -> bool {
+    SECTIONS.iter().any(|section| {
+        line.strip_prefix(section)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+    })
+}
+
+fn kept_regions(spec: &str) -> Result<Vec<KeptRegion>> {
+    let mut regions = Vec::new();
+    let mut section = None;
+    let mut after = None;
+    let mut current: Option<KeptRegion> = None;
+    for (number, line) in spec.lines().enumerate() {
+        let trimmed = line.trim();
+        if let Some(region) = &mut current {
+            region.lines.push(line.to_string());
+            if trimmed.starts_with(KEEP_END) {
+                regions.extend(current.take());
+            } else if trimmed.starts_with(KEEP_BEGIN) {
+                takopack_bail!("line {}: `{}` inside a kept region", number + 1, KEEP_BEGIN);
+            }
+        } else if trimmed.starts_with(KEEP_BEGIN) {
+            current = Some(KeptRegion {
+                section: section.clone(),
+                after: after.clone(),
+                lines: vec![line.to_string()],
+            });
+        } else if trimmed.starts_with(KEEP_END) {
+            takopack_bail!(
+                "line {}: `{}` without `{}`",
+                number + 1,
+                KEEP_END,
+                KEEP_BEGIN
+            );
+        } else if is_section(trimmed) {
+            section = Some(trimmed.to_string());
+            after = None;
+        } else if !trimmed.is_empty() {
+            after = Some(trimmed.to_string());
+        }
+    }
+    if current.is_some() {
+        takopack_bail!("`{}` without `{}`", KEEP_BEGIN, KEEP_END);
+    }
+    Ok(regions)
+}
+
+/// `new` with `regions` put back, and the regions whose section is gone.
+fn reapply_regions<'a>(new: &str, regions: &'a [KeptRegion]) -> (String, Vec<&'a KeptRegion>) {
+    let lines: Vec<&str> = new.lines().collect();
+    let section_end = |start: usize| {
+        lines[start..]
+            .iter()
+            .position(|line| is_section(line.trim()))
+            .map_or(lines.len(), |i| start + i)
+    };
+    // Regions by the index of the line they go before
+    let mut inserts: BTreeMap<usize, Vec<&KeptRegion>> = BTreeMap::new();
+    let mut misplaced = Vec::new();
+    for region in regions {
+        let start = match &region.section {
+            None => Some(0),
+            Some(header) => lines
+                .iter()
+                .position(|line| line.trim() == header)
+                .map(|i| i + 1),
+        };
+        let Some(start) = start else {
+            misplaced.push(region);
+            inserts.entry(lines.len()).or_default().push(region);
+            continue;
+        };
+        let end = section_end(start);
+        let at = region
+            .after
+            .as_ref()
+            .and_then(|after| {
+                lines[start..end]
+                    .iter()
+                    .position(|line| line.trim() == after)
+            })
+            .map_or(start, |i| start + i + 1);
+        inserts.entry(at).or_default().push(region);
+    }
+
+    let mut out = String::new();
+    for index in 0..=lines.len() {
+        for region in inserts.get(&index).into_iter().flatten() {
+            for line in &region.lines {
+                out.push_str(line);
+                out.push('\n');
+            }
+        }
+        if let Some(line) = lines.get(index) {
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+    (out, misplaced)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{global, kept_regions, reapply_regions};
+
+    #[test]
+    fn kept_regions_survive_regeneration() {
+        let old = "\
+%global crate_name demo
+%global full_version 1.0.0
+Name:           rust-demo
+BuildRequires:  cargo-rpm-macros
+# takopack:keep
+BuildRequires:  openssl-devel
+# takopack:end-keep
+
+%package     -n %{name}+tls
+Requires:       crate(%{pkgname}) = %{version}
+Requires:       crate(native-tls/default) >= 0.2.11
+# takopack:keep
+Requires:       ca-certificates
+# takopack:end-keep
+
+%check
+# takopack:keep
+# needs network
+%cargo_test -- -- --skip online
+# takopack:end-keep
+%cargo_test
+
+%package     -n %{name}+gone
+# takopack:keep
+Requires:       gone
+# takopack:end-keep
+";
+        let new = "\
+%global crate_name demo
+%global full_version 1.1.0
+Name:           rust-demo
+BuildRequires:  cargo-rpm-macros
+
+%package     -n %{name}+tls
+Requires:       crate(%{pkgname}) = %{version}
+Requires:       crate(native-tls/default) >= 0.2.12
+
+%check
+%cargo_test
+";
+        assert_eq!(global(old, "crate_name").as_deref(), Some("demo"));
+        let regions = kept_regions(old).unwrap();
+        assert_eq!(regions.len(), 4);
+        let (updated, misplaced) = reapply_regions(new, &regions);
+        assert_eq!(misplaced.len(), 1);
+        assert_eq!(
+            updated,
+            "\
+%global crate_name demo
+%global full_version 1.1.0
+Name:           rust-demo
+BuildRequires:  cargo-rpm-macros
+# takopack:keep
+BuildRequires:  openssl-devel
+# takopack:end-keep
+
+%package     -n %{name}+tls
+# takopack:keep
+Requires:       ca-certificates
+# takopack:end-keep
+Requires:       crate(%{pkgname}) = %{version}
+Requires:       crate(native-tls/default) >= 0.2.12
+
+%check
+# takopack:keep
+# needs network
+%cargo_test -- -- --skip online
+# takopack:end-keep
+%cargo_test
+# takopack:keep
+Requires:       gone
+# takopack:end-keep
+"
+        );
+
+        assert!(kept_regions("# takopack:keep\nfoo\n").is_err());
+        assert!(kept_regions("foo\n# takopack:end-keep\n").is_err());
+    }
+}
@@ -12,8 +12,15 @@ use std::os::unix::fs::symlink;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
+use crate::cancel::CancellationToken;
+use crate::failure_report::Phase;
 use crate::lockfile_parser::DependencyGraph;
-use crate::package::{PackageExecuteArgs, PackageExtractArgs, PackageInitArgs, PackageProcess};
+use crate::name_collision::NameRegistry;
+use crate::package::{
+    DownloadArgs, PackageExecuteArgs, PackageExtractArgs, PackageInitArgs, PackageProcess,
+};
+use crate::takopack::changelog::ChangelogSource;
+use crate::test_mode::TestMode;
 use anyhow::{bail, Context, Result};
 use itertools::Itertools;
 use semver::Version;
@@ -26,43 +33,21 @@ pub struct RustCrateOutputNames {
     pub spec_file: String,
 }
 
-fn cargo_back_dir() -> Result<PathBuf, anyhow::Error> {
-    use anyhow::Context;
-
-    let data_home = std::env::var_os("XDG_DATA_HOME")
-        .map(PathBuf::from)
-        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
-        .or_else(|| std::env::var_os("USERPROFILE").map(PathBuf::from))
-        .context("Failed to get XDG_DATA_HOME or HOME")?;
-
-    Ok(data_home.join("takopack").join("cargo_back"))
-}
-
-/// Calculate compatibility branch following openRuyi Rust crate naming policy.
-/// - Prerelease versions (e.g., 0.26.0-beta.1) -> full version (0.26.0-beta.1)
-/// - Build metadata is ignored by semver compatibility and does not affect the branch
-/// - 0.x.y -> 0.x (0.x series, minor version compatibility)
-/// - 1.x.y+ -> 1 (major version compatibility)
-/// - 0.0.x+ -> 0.0.x (0.0.x series, patch version compatibility)
-pub fn calculate_compat_version(version: &Version) -> String {
-    // For prerelease versions, use the full version including prerelease tag
-    if !version.pre.is_empty() {
-        format!(
-            "{}.{}.{}-{}",
-            version.major, version.minor, version.patch, version.pre
-        )
-    } else if version.major > 0 {
-        version.major.to_string()
-    } else if version.minor > 0 {
-        format!("0.{}", version.minor)
-    } else {
-        format!("0.0.{}", version.patch)
+impl RustCrateOutputNames {
+    /// The names with `suffix` appended to the package name.
+    pub fn with_suffix(self, suffix: &str) -> Self {
+        let directory = format!("{}{}", self.directory, suffix);
+        let spec_file = format!("{}.spec", directory);
+        RustCrateOutputNames {
+            directory,
+            spec_file,
+        }
     }
 }
 
 pub fn rust_crate_output_names(crate_name: &str, version: &Version) -> RustCrateOutputNames {
     let crate_name = crate_name.replace('_', "-");
-    let compat_version = calculate_compat_version(version);
+    let compat_version = crate::version::compat_version(version);
     let directory = format!("rust-{}-{}", crate_name, compat_version);
     let spec_file = format!("{}.spec", directory);
 
@@ -201,25 +186,38 @@ pub fn rel_p<'a>(path: &'a Path, base: &'a Path) -> Cow<'a, str> {
     path.strip_prefix(base).unwrap_or(path).to_string_lossy()
 }
 
-pub fn copy_tree(oldtree: &Path, newtree: &Path) -> Result<(), Error> {
+pub fn copy_tree(oldtree: &Path, newtree: &Path) -> Result<()> {
     for entry in WalkDir::new(oldtree) {
-        let entry = entry?;
+        let entry = entry.with_context(|| format!("Failed to walk {}", oldtree.display()))?;
         if entry.depth() == 0 {
             continue;
         }
         let oldpath = entry.path();
-        let newpath = newtree.join(oldpath.strip_prefix(oldtree).unwrap());
+        let relative = oldpath
+            .strip_prefix(oldtree)
+            .with_context(|| format!("{} is not under {}", oldpath.display(), oldtree.display()))?;
+        let newpath = newtree.join(relative);
         let ftype = entry.file_type();
         match ftype {
             f if f.is_dir() => {
-                fs::create_dir(newpath)?;
+                fs::create_dir(&newpath)
+                    .with_context(|| format!("Failed to create {}", newpath.display()))?;
             }
             f if f.is_file() => {
-                fs::copy(oldpath, newpath)?;
+                fs::copy(oldpath, &newpath).with_context(|| {
+                    format!(
+                        "Failed to copy {} to {}",
+                        oldpath.display(),
+                        newpath.display()
+                    )
+                })?;
             }
             #[cfg(unix)]
             f if f.is_symlink() => {
-                symlink(fs::read_link(oldpath)?, newpath)?;
+                let target = fs::read_link(oldpath)
+                    .with_context(|| format!("Failed to read link {}", oldpath.display()))?;
+                symlink(target, &newpath)
+                    .with_context(|| format!("Failed to create link {}", newpath.display()))?;
             }
             _ => {}
         }
@@ -418,39 +416,20 @@ where
     }
 }
 
-/// Backup Cargo.toml to ${XDG_DATA_HOME:-$HOME/.local/share}/takopack/cargo_back directory
-/// File will be named as: crate_name-version.toml
-/// If subdir is provided, file will be saved in the matching subdirectory
-pub fn backup_cargo_toml(
-    cargo_toml_path: &Path,
-    crate_name: &str,
-    version: &str,
-    subdir: Option<&str>,
-) -> Result<(), anyhow::Error> {
-    use anyhow::Context;
-
-    let mut backup_dir = cargo_back_dir()?;
-
-    // Add subdirectory if specified (e.g., "patch" for local packages)
-    if let Some(sub) = subdir {
-        backup_dir = backup_dir.join(sub);
-    }
-
-    fs::create_dir_all(&backup_dir)
-        .with_context(|| format!("Failed to create backup directory: {:?}", backup_dir))?;
-
-    let backup_filename = format!("{}-{}.toml", crate_name.replace('_', "-"), version);
-    let backup_path = backup_dir.join(&backup_filename);
-
-    if cargo_toml_path.exists() {
-        fs::copy(cargo_toml_path, &backup_path)
-            .with_context(|| format!("Failed to backup Cargo.toml to {:?}", backup_path))?;
-        log::info!("Backed up Cargo.toml to: {:?}", backup_path);
-    } else {
-        log::warn!("Cargo.toml not found at: {:?}", cargo_toml_path);
+/// Copy the `takopack/copyright` report written by `--copyright-guess-harder`
+/// next to the spec, if there is one.
+pub fn copy_copyright_report_to_dir(
+    source_dir: &Path,
+    target_dir: &Path,
+) -> Result<Option<PathBuf>> {
+    let report = source_dir.join("takopack").join("copyright");
+    if !report.is_file() {
+        return Ok(None);
     }
-
-    Ok(())
+    let target = target_dir.join("copyright");
+    fs::copy(&report, &target)
+        .with_context(|| format!("Failed to copy copyright report to {:?}", target))?;
+    Ok(Some(target))
 }
 
 pub fn copy_normalized_cargo_toml_to_dir(source_dir: &Path, target_dir: &Path) -> Result<PathBuf> {
@@ -516,56 +495,56 @@ pub fn cargo_toml_has_workspace_inheritance(contents: &str) -> bool {
     })
 }
 
-/// Backup Cargo.lock to ${XDG_DATA_HOME:-$HOME/.local/share}/takopack/cargo_back directory
-/// File will be named as: crate_name-version.lock
-/// If subdir is provided, file will be saved in the matching subdirectory
-pub fn backup_cargo_lock(
-    cargo_lock_path: &Path,
-    crate_name: &str,
-    version: &str,
-    subdir: Option<&str>,
-) -> Result<PathBuf, anyhow::Error> {
-    use anyhow::Context;
-
-    let mut backup_dir = cargo_back_dir()?;
-
-    // Add subdirectory if specified (e.g., "origin" for downloaded crates)
-    if let Some(sub) = subdir {
-        backup_dir = backup_dir.join(sub);
-    }
-
-    fs::create_dir_all(&backup_dir)
-        .with_context(|| format!("Failed to create backup directory: {:?}", backup_dir))?;
-
-    let backup_filename = format!("{}-{}.lock", crate_name.replace('_', "-"), version);
-    let backup_path = backup_dir.join(&backup_filename);
-
-    if cargo_lock_path.exists() {
-        fs::copy(cargo_lock_path, &backup_path)
-            .with_context(|| format!("Failed to backup Cargo.lock to {:?}", backup_path))?;
-        log::info!("Backed up Cargo.lock to: {:?}", backup_path);
-    } else {
-        log::warn!("Cargo.lock not found at: {:?}", cargo_lock_path);
-        bail!("Cargo.lock not found at {:?}", cargo_lock_path);
-    }
-
-    Ok(backup_path)
+/// Directory under `base_dir` that [`process_single_crate`] works in for
+/// `crate_name`. A takopack.toml placed there is the one the crate is
+/// packaged with.
+pub fn crate_work_dir(base_dir: &Path, crate_name: &str) -> PathBuf {
+    base_dir.join(format!(".work_{}", crate_name.replace('/', "_")))
 }
 
-/// Process a single crate
+/// Process a single crate and return the package directory written
 /// If dep_graph is provided, use Cargo.lock dependencies for spec generation
 pub fn process_single_crate(
     crate_name: &str,
     version: &str,
     base_dir: &PathBuf,
     dep_graph: Option<&DependencyGraph>,
-) -> Result<()> {
+) -> Result<PathBuf> {
+    process_single_crate_cancellable(
+        crate_name,
+        version,
+        base_dir,
+        dep_graph,
+        &DownloadArgs::default(),
+        &CancellationToken::new(),
+        &mut NameRegistry::default(),
+        TestMode::default(),
+    )
+}
+
+/// Same as [`process_single_crate`], checking `cancel` between pipeline phases.
+/// `download` overrides the download settings of takopack.toml, and the
+/// package name is claimed in `names`, and the spec is generated in
+/// `test_mode`.
+#[allow(clippy::too_many_arguments)]
+pub fn process_single_crate_cancellable(
+    crate_name: &str,
+    version: &str,
+    base_dir: &PathBuf,
+    dep_graph: Option<&DependencyGraph>,
+    download: &DownloadArgs,
+    cancel: &CancellationToken,
+    names: &mut NameRegistry,
+    test_mode: TestMode,
+) -> Result<PathBuf> {
+    cancel.check()?;
+
     // Convert base_dir to absolute path before changing directory
     let base_dir_abs = fs::canonicalize(base_dir)
         .with_context(|| format!("Failed to get absolute path for: {:?}", base_dir))?;
 
     // Create a unique working directory for this crate to avoid conflicts
-    let work_dir = base_dir_abs.join(format!(".work_{}", crate_name.replace('/', "_")));
+    let work_dir = crate_work_dir(&base_dir_abs, crate_name);
     fs::create_dir_all(&work_dir)?;
 
     // Save current directory
@@ -574,11 +553,18 @@ pub fn process_single_crate(
     // Change to working directory
     std::env::set_current_dir(&work_dir)
         .with_context(|| format!("Failed to change to work directory: {:?}", work_dir))?;
-    let result = (|| -> Result<()> {
+    let result = (|| -> Result<PathBuf> {
         // Initialize package process
         let init_args = PackageInitArgs {
             crate_name: crate_name.to_string(),
             version: Some(version.to_string()),
+            offline: false,
+            mirror: None,
+            registry: None,
+            index_url: None,
+            allow_yanked: false,
+            crate_file: None,
+            download: download.clone(),
         };
 
         let extract_args = PackageExtractArgs {
@@ -596,30 +582,54 @@ pub fn process_single_crate(
         });
         let finish_args = PackageExecuteArgs {
             changelog_ready: false,
+            changelog_from: ChangelogSource::None,
             copyright_guess_harder: false,
             no_overlay_write_back: false,
             with_spdx: false,
+            with_tests: false,
+            template_dir: None,
+            profile: None,
             lockfile_deps, // Pass lockfile dependencies
+            test_mode,
         };
 
-        let mut process = PackageProcess::init(init_args)?;
+        let mut process = PackageProcess::init(
+            init_args,
+            finish_args.profile.as_deref(),
+            finish_args.test_mode,
+        )
+        .context(Phase::Download)?;
+        let real_name = process.crate_info().crate_name().to_string();
+        let real_version = process.crate_info().version().clone();
+        let output_names = names
+            .claim(&real_name, &real_version, &mut process.config)
+            .context(Phase::Spec)?;
 
         // Extract crate (will create directory in work dir)
-        process.extract(extract_args)?;
+        cancel.check()?;
+        process.extract(extract_args).context(Phase::Extract)?;
 
         // Apply overrides
-        process.apply_overrides()?;
+        cancel.check()?;
+        process.apply_overrides().context(Phase::Spec)?;
 
         // Prepare orig tarball
-        process.prepare_orig_tarball()?;
+        cancel.check()?;
+        process.prepare_orig_tarball().context(Phase::Spec)?;
 
         // Prepare takopack folder
-        process.prepare_takopack_folder(finish_args)?;
+        cancel.check()?;
+        process
+            .prepare_takopack_folder(finish_args)
+            .context(Phase::Spec)?;
 
         // Copy spec file to base_dir (use absolute path)
-        let output_path = process.output_dir.as_ref().unwrap();
+        let output_path = process
+            .output_dir
+            .as_ref()
+            .context("The crate was not extracted to an output directory")
+            .context(Phase::Spec)?;
         let takopack_dir = output_path.join("takopack");
-        let output_names = rust_crate_output_names(crate_name, process.crate_info().version());
         let source_spec = takopack_dir.join(&output_names.spec_file);
 
         // Create target directory in base_dir_abs (not work_dir)
@@ -631,6 +641,7 @@ pub fn process_single_crate(
         if source_spec.exists() {
             fs::copy(&source_spec, &final_spec)?;
             copy_normalized_cargo_toml_to_dir(output_path, &target_dir)?;
+            process.config.permissions.apply_to_dir(&target_dir)?;
             log::debug!("Copied spec file to: {:?}", final_spec);
         } else {
             return Err(anyhow::anyhow!(
@@ -639,7 +650,7 @@ pub fn process_single_crate(
             ));
         }
 
-        Ok(())
+        Ok(target_dir)
     })();
 
     // Always restore original directory
@@ -658,33 +669,13 @@ pub fn process_single_crate(
 #[cfg(test)]
 mod tests {
     use super::{
-        calculate_compat_version, cargo_toml_has_workspace_inheritance,
-        copy_normalized_cargo_toml_to_dir, package_final_output_dir_with_base,
-        resolve_output_dir_with_base, rust_crate_output_names,
+        cargo_toml_has_workspace_inheritance, copy_normalized_cargo_toml_to_dir, copy_tree,
+        package_final_output_dir_with_base, resolve_output_dir_with_base, rust_crate_output_names,
     };
     use semver::Version;
     use std::fs;
     use std::path::Path;
 
-    #[test]
-    fn calculate_compat_version_uses_openruyi_policy() {
-        for (version, expected) in [
-            ("1.0.228", "1"),
-            ("1.2.3", "1"),
-            ("2.0.0", "2"),
-            ("3.18.0", "3"),
-            ("4.6.1", "4"),
-            ("0.22.1", "0.22"),
-            ("0.9.3", "0.9"),
-            ("0.0.7", "0.0.7"),
-        ] {
-            assert_eq!(
-                calculate_compat_version(&Version::parse(version).unwrap()),
-                expected
-            );
-        }
-    }
-
     #[test]
     fn rust_crate_output_names_follow_compat_directory() {
         assert_eq!(
@@ -704,6 +695,18 @@ mod tests {
         );
     }
 
+    #[test]
+    fn copy_tree_errors_name_the_path() {
+        let source = tempfile::tempdir().unwrap();
+        let out = tempfile::tempdir().unwrap();
+        fs::create_dir(source.path().join("src")).unwrap();
+        fs::write(source.path().join("src/lib.rs"), "").unwrap();
+        copy_tree(source.path(), out.path()).unwrap();
+        assert!(out.path().join("src/lib.rs").is_file());
+        let error = copy_tree(source.path(), out.path()).unwrap_err();
+        assert!(format!("{:#}", error).contains("Failed to create"));
+    }
+
     #[test]
     fn copy_normalized_cargo_toml_prefers_cargo_toml_over_orig() {
         let source = tempfile::tempdir().unwrap();
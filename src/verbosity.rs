@@ -0,0 +1,125 @@
+//! How much a run prints besides its events.
+//!
+//! Logging used to be configured only through `RUST_LOG`, and the hints of
+//! `takopack_info!`, the lines hidden behind `--progress` and the rpmbuild
+//! output of failed builds were printed the same way in every run. The
+//! global `--quiet` and `--verbose` flags now set one [`Verbosity`]:
+//! `--quiet` leaves only warnings, failures and errors, for scripts, and
+//! every `--verbose` shows more, from the info log up to the trace log.
+//! Without either flag `RUST_LOG` is honoured as before. Like the output
+//! format of [`crate::events`], it is installed once with
+//! [`Verbosity::install`].
+
+use std::sync::Mutex;
+
+use log::LevelFilter;
+
+use crate::events::OutputFormat;
+
+/// How much is printed, from `--quiet` to `-vvv`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Only warnings, failures and errors
+    Quiet,
+    /// What takopack always printed
+    #[default]
+    Normal,
+    /// The info log, lines hidden behind the progress bars and all of the
+    /// output of failed builds
+    Verbose,
+    /// The debug log as well
+    Debug,
+    /// Everything
+    Trace,
+}
+
+static CURRENT: Mutex<Verbosity> = Mutex::new(Verbosity::Normal);
+
+impl Verbosity {
+    /// The verbosity of `--quiet` and `verbose` times `--verbose`.
+    pub fn from_flags(quiet: bool, verbose: u8) -> Self {
+        match (quiet, verbose) {
+            (true, _) => Verbosity::Quiet,
+            (false, 0) => Verbosity::Normal,
+            (false, 1) => Verbosity::Verbose,
+            (false, 2) => Verbosity::Debug,
+            (false, _) => Verbosity::Trace,
+        }
+    }
+
+    /// Use this verbosity from now on.
+    pub fn install(self) {
+        *CURRENT.lock().unwrap_or_else(|e| e.into_inner()) = self;
+    }
+
+    /// The installed verbosity; normal unless `--quiet` or `--verbose` was
+    /// given.
+    pub fn current() -> Self {
+        *CURRENT.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn is_quiet(self) -> bool {
+        self == Verbosity::Quiet
+    }
+
+    pub fn is_verbose(self) -> bool {
+        self >= Verbosity::Verbose
+    }
+
+    /// The level logged for every module; `None` leaves it to `RUST_LOG`.
+    pub fn log_level(self) -> Option<LevelFilter> {
+        match self {
+            Verbosity::Quiet => Some(LevelFilter::Error),
+            Verbosity::Normal => None,
+            Verbosity::Verbose => Some(LevelFilter::Info),
+            Verbosity::Debug => Some(LevelFilter::Debug),
+            Verbosity::Trace => Some(LevelFilter::Trace),
+        }
+    }
+
+    /// The output format for `requested`: `--quiet` turns `human` into
+    /// `quiet`, while JSON lines stay what tools asked for.
+    pub fn output_format(self, requested: OutputFormat) -> OutputFormat {
+        match requested {
+            OutputFormat::Human if self.is_quiet() => OutputFormat::Quiet,
+            other => other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Verbosity;
+    use crate::events::OutputFormat;
+    use log::LevelFilter;
+
+    #[test]
+    fn flags_map_to_log_levels_and_output_formats() {
+        assert_eq!(Verbosity::from_flags(false, 0), Verbosity::Normal);
+        assert_eq!(Verbosity::from_flags(false, 5), Verbosity::Trace);
+        assert_eq!(Verbosity::Normal.log_level(), None);
+        assert_eq!(
+            Verbosity::from_flags(false, 1).log_level(),
+            Some(LevelFilter::Info)
+        );
+        assert_eq!(
+            Verbosity::from_flags(true, 0).log_level(),
+            Some(LevelFilter::Error)
+        );
+        assert!(Verbosity::Debug.is_verbose());
+        assert!(!Verbosity::Normal.is_verbose());
+
+        assert_eq!(
+            Verbosity::Quiet.output_format(OutputFormat::Human),
+            OutputFormat::Quiet
+        );
+        assert_eq!(
+            Verbosity::Quiet.output_format(OutputFormat::Json),
+            OutputFormat::Json
+        );
+        assert_eq!(
+            Verbosity::Verbose.output_format(OutputFormat::Human),
+            OutputFormat::Human
+        );
+    }
+}
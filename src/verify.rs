@@ -0,0 +1,441 @@
+//! Whether the specs of a `vendor` run satisfy each other.
+//!
+//! Every crate of a vendored graph gets its own spec, and each one requires
+//! the `crate()` capabilities of its dependencies. A dependency that was
+//! skipped, resolved to another version or lost a feature leaves a
+//! requirement that no package in the output provides, which only shows up
+//! when the packages are installed. `cargo verify` reads all specs in a
+//! directory, collects their `Provides:` and checks every `crate()`
+//! `Requires:` against them, reporting requirements nothing provides and
+//! requirements only other versions provide. Requirements on anything other
+//! than crates, such as system libraries, are left to the distro.
+
+use anyhow::Context;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::errors::*;
+use crate::events::{self, Event};
+use crate::lint::{find_specs, Severity};
+use crate::update_spec::is_section;
+
+/// Words joining the terms of rich dependencies that all have to hold.
+const ALL_OF: &[&str] = &["with", "and"];
+
+/// A `crate()` requirement nothing in the directory satisfies.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unsatisfied {
+    pub spec: PathBuf,
+    /// 1-based line of the `Requires:`
+    pub line: usize,
+    /// The requirement, with macros expanded
+    pub requirement: String,
+    /// The versions that are provided, if only the version is wrong
+    pub provided: Vec<String>,
+}
+
+/// One capability of a requirement, with its version constraint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Term {
+    capability: String,
+    constraint: Option<(String, String)>,
+}
+
+/// `Requires:` and `Provides:` of one spec, with macros expanded.
+#[derive(Debug, Default)]
+struct SpecCapabilities {
+    /// Line index and value of every `Requires:`
+    requires: Vec<(usize, String)>,
+    /// Capability and version of every `Provides:`
+    provides: Vec<(String, Option<String>)>,
+}
+
+/// Check the specs under `dir` against each other and report what is not
+/// satisfied. Returns the number of unsatisfied requirements.
+pub fn run_verify(dir: &Path) -> Result<usize> {
+    let paths = find_specs(dir)?;
+    if paths.is_empty() {
+        takopack_bail!("no spec files in {}", dir.display());
+    }
+    let mut specs = Vec::new();
+    for path in paths {
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        specs.push((path, text));
+    }
+    let unsatisfied = verify_specs(&specs);
+    for problem in &unsatisfied {
+        let (rule, message) = if problem.provided.is_empty() {
+            (
+                "unsatisfied",
+                format!("nothing provides `{}`", problem.requirement),
+            )
+        } else {
+            (
+                "version-mismatch",
+                format!(
+                    "`{}` is not satisfied by the provided {}",
+                    problem.requirement,
+                    problem.provided.join(", ")
+                ),
+            )
+        };
+        events::emit(Event::LintIssue {
+            spec: problem.spec.clone(),
+            line: Some(problem.line),
+            severity: Severity::Error,
+            rule: rule.to_string(),
+            message,
+        });
+    }
+    events::emit(Event::note(format!(
+        "Checked {} specs: {} unsatisfied crate requirements",
+        specs.len(),
+        unsatisfied.len()
+    )));
+    Ok(unsatisfied.len())
+}
+
+/// The `crate()` requirements of `specs` (path and text) that none of them
+/// satisfies.
+pub fn verify_specs(specs: &[(PathBuf, String)]) -> Vec<Unsatisfied> {
+    let parsed: Vec<SpecCapabilities> = specs.iter().map(|(_, text)| parse_spec(text)).collect();
+    let mut provides: HashMap<&str, Vec<Option<&str>>> = HashMap::new();
+    for spec in &parsed {
+        for (capability, version) in &spec.provides {
+            provides
+                .entry(capability.as_str())
+                .or_default()
+                .push(version.as_deref());
+        }
+    }
+
+    let mut unsatisfied = Vec::new();
+    for ((path, _), spec) in specs.iter().zip(&parsed) {
+        for (line, requirement) in &spec.requires {
+            let (terms, all) = parse_requirement(requirement);
+            if !terms
+                .iter()
+                .any(|term| is_crate_capability(&term.capability))
+            {
+                continue;
+            }
+            let holds = |term: &Term| {
+                provides
+                    .get(term.capability.as_str())
+                    .is_some_and(|versions| versions.iter().any(|v| satisfies(*v, term)))
+            };
+            let satisfied = if all {
+                terms.iter().all(holds)
+            } else {
+                terms.iter().any(holds)
+            };
+            if satisfied {
+                continue;
+            }
+            let mut provided: Vec<String> = terms
+                .iter()
+                .filter_map(|term| provides.get(term.capability.as_str()))
+                .flatten()
+                .flatten()
+                .map(|version| version.to_string())
+                .collect();
+            provided.sort_unstable();
+            provided.dedup();
+            unsatisfied.push(Unsatisfied {
+                spec: path.clone(),
+                line: line + 1,
+                requirement: requirement.clone(),
+                provided,
+            });
+        }
+    }
+    unsatisfied
+}
+
+fn parse_spec(text: &str) -> SpecCapabilities {
+    let mut macros: HashMap<String, String> = HashMap::new();
+    let mut capabilities = SpecCapabilities::default();
+    // Tags are only read in the preamble and in `%package` sections
+    let mut in_preamble = true;
+    for (number, line) in text.lines().enumerate() {
+        let trimmed = line.trim();
+        if is_section(trimmed) {
+            in_preamble = trimmed.starts_with("%package");
+            continue;
+        }
+        if !in_preamble {
+            continue;
+        }
+        let mut words = trimmed.split_whitespace();
+        if let (Some("%global" | "%define"), Some(name), Some(value)) =
+            (words.next(), words.next(), words.next())
+        {
+            let value = expand(value, &macros);
+            macros.insert(name.to_string(), value);
+            continue;
+        }
+        let Some((tag, value)) = trimmed.split_once(':') else {
+            continue;
+        };
+        let value = expand(value.trim(), &macros);
+        match tag {
+            "Name" | "Version" | "Release" => {
+                macros.insert(tag.to_lowercase(), value);
+            }
+            "Provides" => {
+                let mut words = value.split_whitespace();
+                if let Some(capability) = words.next() {
+                    let version = match (words.next(), words.next()) {
+                        (Some("=" | "=="), Some(version)) => Some(version.to_string()),
+                        _ => None,
+                    };
+                    capabilities
+                        .provides
+                        .push((capability.to_string(), version));
+                }
+            }
+            tag if tag == "Requires" || tag.starts_with("Requires(") => {
+                capabilities.requires.push((number, value));
+            }
+            _ => {}
+        }
+    }
+    capabilities
+}
+
+/// `value` with the `%{name}` macros of `macros` expanded.
+fn expand(value: &str, macros: &HashMap<String, String>) -> String {
+    let mut value = value.to_string();
+    // Macros defined in terms of others are expanded when defined, but a
+    // few rounds cover tags using macros defined later
+    for _ in 0..4 {
+        let before = value.clone();
+        for (name, replacement) in macros {
+            value = value.replace(&format!("%{{{}}}", name), replacement);
+        }
+        if value == before {
+            break;
+        }
+    }
+    value
+}
+
+fn is_crate_capability(capability: &str) -> bool {
+    capability.ends_with(')')
+        && capability
+            .split_once('(')
+            .is_some_and(|(namespace, _)| namespace.ends_with("crate"))
+}
+
+/// The terms of `requirement`, and whether all of them have to hold (or
+/// any).
+fn parse_requirement(requirement: &str) -> (Vec<Term>, bool) {
+    let rich = requirement.starts_with('(') && requirement.ends_with(')');
+    let inner = if rich {
+        &requirement[1..requirement.len() - 1]
+    } else {
+        requirement
+    };
+    let words: Vec<&str> = inner.split_whitespace().collect();
+    let all = !words.contains(&"or");
+    let terms = words
+        .split(|word| ALL_OF.contains(word) || *word == "or")
+        .filter_map(|term| match term {
+            [capability] => Some(Term {
+                capability: capability.to_string(),
+                constraint: None,
+            }),
+            [capability, operator, version] => Some(Term {
+                capability: capability.to_string(),
+                constraint: Some((operator.to_string(), version.to_string())),
+            }),
+            _ => None,
+        })
+        .collect();
+    (terms, all)
+}
+
+/// Whether a capability provided at `provided` satisfies `term`.
+fn satisfies(provided: Option<&str>, term: &Term) -> bool {
+    let (Some(provided), Some((operator, required))) = (provided, &term.constraint) else {
+        return true;
+    };
+    let ordering = compare_evr(provided, required);
+    match operator.as_str() {
+        "=" | "==" => ordering == Ordering::Equal,
+        ">=" => ordering != Ordering::Less,
+        ">" => ordering == Ordering::Greater,
+        "<=" => ordering != Ordering::Greater,
+        "<" => ordering == Ordering::Less,
+        _ => false,
+    }
+}
+
+/// Compare `[epoch:]version[-release]` strings like rpm: the release is
+/// only compared when both have one.
+fn compare_evr(a: &str, b: &str) -> Ordering {
+    fn split(evr: &str) -> (u64, &str, Option<&str>) {
+        let (epoch, rest) = match evr.split_once(':') {
+            Some((epoch, rest)) => (epoch.parse().unwrap_or(0), rest),
+            None => (0, evr),
+        };
+        match rest.rsplit_once('-') {
+            Some((version, release)) => (epoch, version, Some(release)),
+            None => (epoch, rest, None),
+        }
+    }
+    let (a_epoch, a_version, a_release) = split(a);
+    let (b_epoch, b_version, b_release) = split(b);
+    a_epoch
+        .cmp(&b_epoch)
+        .then_with(|| rpmvercmp(a_version, b_version))
+        .then_with(|| match (a_release, b_release) {
+            (Some(a), Some(b)) => rpmvercmp(a, b),
+            _ => Ordering::Equal,
+        })
+}
+
+/// rpm's comparison of version strings: alternating numeric and alphabetic
+/// segments, where `~` sorts before anything and `^` after the version.
+fn rpmvercmp(a: &str, b: &str) -> Ordering {
+    let separator = |c: char| !c.is_ascii_alphanumeric() && c != '~' && c != '^';
+    let (mut a, mut b) = (a, b);
+    loop {
+        a = a.trim_start_matches(separator);
+        b = b.trim_start_matches(separator);
+
+        match (a.strip_prefix('~'), b.strip_prefix('~')) {
+            (Some(rest_a), Some(rest_b)) => {
+                (a, b) = (rest_a, rest_b);
+                continue;
+            }
+            (Some(_), None) => return Ordering::Less,
+            (None, Some(_)) => return Ordering::Greater,
+            (None, None) => {}
+        }
+        match (a.strip_prefix('^'), b.strip_prefix('^')) {
+            (Some(rest_a), Some(rest_b)) => {
+                (a, b) = (rest_a, rest_b);
+                continue;
+            }
+            (Some(_), None) if b.is_empty() => return Ordering::Greater,
+            (Some(_), None) => return Ordering::Less,
+            (None, Some(_)) if a.is_empty() => return Ordering::Less,
+            (None, Some(_)) => return Ordering::Greater,
+            (None, None) => {}
+        }
+        if a.is_empty() || b.is_empty() {
+            break;
+        }
+
+        let numeric = a.starts_with(|c: char| c.is_ascii_digit());
+        let segment = |s: &str| -> usize {
+            s.find(|c: char| {
+                if numeric {
+                    !c.is_ascii_digit()
+                } else {
+                    !c.is_ascii_alphabetic()
+                }
+            })
+            .unwrap_or(s.len())
+        };
+        let (a_len, b_len) = (segment(a), segment(b));
+        if b_len == 0 {
+            // A number is newer than letters
+            return if numeric {
+                Ordering::Greater
+            } else {
+                Ordering::Less
+            };
+        }
+        let (a_segment, b_segment) = (&a[..a_len], &b[..b_len]);
+        let ordering = if numeric {
+            let a_segment = a_segment.trim_start_matches('0');
+            let b_segment = b_segment.trim_start_matches('0');
+            a_segment
+                .len()
+                .cmp(&b_segment.len())
+                .then_with(|| a_segment.cmp(b_segment))
+        } else {
+            a_segment.cmp(b_segment)
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+        (a, b) = (&a[a_len..], &b[b_len..]);
+    }
+    match (a.is_empty(), b.is_empty()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Less,
+        _ => Ordering::Greater,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{rpmvercmp, verify_specs};
+    use std::cmp::Ordering;
+    use std::path::PathBuf;
+
+    const SERDE: &str = "\
+%global crate_name serde
+%global full_version 1.0.200
+%global pkgname serde
+
+Name:           rust-%{pkgname}
+Version:        1.0.200
+Provides:       crate(%{pkgname}) = %{version}
+
+%description
+Requires: crate(nothing) in a description
+
+%package     -n %{name}+std
+Provides:       crate(%{pkgname}/std) = %{version}
+Requires:       crate(%{pkgname}) = %{version}
+";
+
+    const APP: &str = "\
+%global pkgname app
+Name:           rust-%{pkgname}
+Version:        0.1.0
+Requires:       crate(serde/std) >= 1.0.100
+Requires:       (crate(serde/default) >= 1.0.0 with crate(serde/default) < 2.0.0~)
+Requires:       crate(serde) >= 1.0.300
+Requires:       (crate(serde) >= 1.0.0 with crate(serde) < 2.0.0~)
+Requires:       openssl-libs
+";
+
+    #[test]
+    fn requirements_are_checked_against_all_provides() {
+        let specs = [
+            (PathBuf::from("rust-serde.spec"), SERDE.to_string()),
+            (PathBuf::from("rust-app.spec"), APP.to_string()),
+        ];
+        let unsatisfied = verify_specs(&specs);
+        let found: Vec<_> = unsatisfied
+            .iter()
+            .map(|u| (u.line, u.requirement.as_str(), u.provided.clone()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    5,
+                    "(crate(serde/default) >= 1.0.0 with crate(serde/default) < 2.0.0~)",
+                    vec![]
+                ),
+                (6, "crate(serde) >= 1.0.300", vec!["1.0.200".to_string()]),
+            ]
+        );
+
+        assert_eq!(rpmvercmp("1.0.10", "1.0.9"), Ordering::Greater);
+        assert_eq!(rpmvercmp("1.0.0~beta.1", "1.0.0"), Ordering::Less);
+        assert_eq!(rpmvercmp("2.0.0~", "2.0.0~beta"), Ordering::Less);
+        assert_eq!(rpmvercmp("1.0^git1", "1.0"), Ordering::Greater);
+        assert_eq!(rpmvercmp("1.0.0", "1.0.0"), Ordering::Equal);
+        assert_eq!(rpmvercmp("1.0a", "1.0.1"), Ordering::Less);
+    }
+}
@@ -0,0 +1,124 @@
+//! How crate versions are written in package names, `Version:` and
+//! requirements.
+//!
+//! Semver versions may carry a pre-release (`0.26.0-beta.1`) and build
+//! metadata (`0.7.5+spec-1.1.0`), and rpm has room for neither in the same
+//! places Cargo does. The policy, in one place:
+//!
+//! - the compat version naming packages and capabilities (`foo-1`,
+//!   `foo-0.26`) ignores build metadata, like semver compatibility does,
+//!   and is the whole version for pre-releases, which are compatible with
+//!   nothing else ([`compat_version`]);
+//! - requirements and the upstream version drop the build metadata but
+//!   keep the pre-release ([`upstream_version`]);
+//! - the rpm `Version:` tag is `major.minor.patch`, since rpm does not
+//!   allow `-` there ([`rpm_version`]);
+//! - only `%{full_version}`, which names the download, keeps everything.
+
+use semver::{BuildMetadata, Op, Version, VersionReq};
+
+/// The compat version of `version`, following the openRuyi naming policy:
+/// - `1.x.y` and later -> `1` (major version compatibility)
+/// - `0.x.y` -> `0.x` (minor version compatibility)
+/// - `0.0.x` -> `0.0.x` (patch version compatibility)
+/// - pre-releases (`0.26.0-beta.1`) -> the full version
+///
+/// Build metadata is ignored.
+pub fn compat_version(version: &Version) -> String {
+    if !version.pre.is_empty() {
+        upstream_version(version)
+    } else if version.major > 0 {
+        version.major.to_string()
+    } else if version.minor > 0 {
+        format!("0.{}", version.minor)
+    } else {
+        format!("0.0.{}", version.patch)
+    }
+}
+
+/// The compat version (see [`compat_version`]) that every version matching
+/// `req` falls in, e.g. `1` for `^1.0.100` and `0.3` for `=0.3.5`. `None`
+/// when the requirement spans several, like `*`, `>=1` or `^0`.
+pub fn requirement_compat_version(req: &str) -> Option<String> {
+    let req = VersionReq::parse(req).ok()?;
+    let [comparator] = req.comparators.as_slice() else {
+        return None;
+    };
+    if !matches!(
+        comparator.op,
+        Op::Exact | Op::Caret | Op::Tilde | Op::Wildcard
+    ) {
+        return None;
+    }
+    match (comparator.major, comparator.minor, comparator.patch) {
+        (0, None, _) | (0, Some(0), None) => return None,
+        _ => {}
+    }
+    Some(compat_version(&Version {
+        major: comparator.major,
+        minor: comparator.minor.unwrap_or(0),
+        patch: comparator.patch.unwrap_or(0),
+        pre: comparator.pre.clone(),
+        build: BuildMetadata::EMPTY,
+    }))
+}
+
+/// `version` without its build metadata, e.g. `0.7.5` for
+/// `0.7.5+spec-1.1.0` and `0.26.0-beta.1` unchanged. Requirements are
+/// written with it.
+pub fn upstream_version(version: &Version) -> String {
+    if version.pre.is_empty() {
+        rpm_version(version)
+    } else {
+        format!(
+            "{}.{}.{}-{}",
+            version.major, version.minor, version.patch, version.pre
+        )
+    }
+}
+
+/// The rpm `Version:` of `version`: `major.minor.patch`.
+pub fn rpm_version(version: &Version) -> String {
+    format!("{}.{}.{}", version.major, version.minor, version.patch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compat_version, requirement_compat_version, rpm_version, upstream_version};
+    use semver::Version;
+
+    #[test]
+    fn versions_follow_one_policy() {
+        // version, compat version, upstream version, rpm Version:
+        for (version, compat, upstream, rpm) in [
+            ("1.0.228", "1", "1.0.228", "1.0.228"),
+            ("2.0.0", "2", "2.0.0", "2.0.0"),
+            ("4.6.1", "4", "4.6.1", "4.6.1"),
+            ("0.22.1", "0.22", "0.22.1", "0.22.1"),
+            ("0.9.3", "0.9", "0.9.3", "0.9.3"),
+            ("0.0.7", "0.0.7", "0.0.7", "0.0.7"),
+            ("0.7.5+spec-1.1.0", "0.7", "0.7.5", "0.7.5"),
+            ("1.0.0+20240101", "1", "1.0.0", "1.0.0"),
+            ("0.0.3+build.5", "0.0.3", "0.0.3", "0.0.3"),
+            ("0.26.0-beta.1", "0.26.0-beta.1", "0.26.0-beta.1", "0.26.0"),
+            ("1.0.0-rc.1+abc", "1.0.0-rc.1", "1.0.0-rc.1", "1.0.0"),
+        ] {
+            let parsed = Version::parse(version).unwrap();
+            assert_eq!(compat_version(&parsed), compat, "{}", version);
+            assert_eq!(upstream_version(&parsed), upstream, "{}", version);
+            assert_eq!(rpm_version(&parsed), rpm, "{}", version);
+        }
+
+        let compat = |req: &str| requirement_compat_version(req);
+        assert_eq!(compat("^1.0.100").as_deref(), Some("1"));
+        assert_eq!(compat("=2.0.1").as_deref(), Some("2"));
+        assert_eq!(compat("0.3").as_deref(), Some("0.3"));
+        assert_eq!(compat("~0.4.2").as_deref(), Some("0.4"));
+        assert_eq!(compat("^0.0.7").as_deref(), Some("0.0.7"));
+        assert_eq!(compat("1.*").as_deref(), Some("1"));
+        assert_eq!(compat("^0.26.0-beta.1").as_deref(), Some("0.26.0-beta.1"));
+        for spanning in ["*", ">=1", ">=1.2, <3", "^0", "0.0", "latest"] {
+            assert_eq!(compat(spanning), None, "{}", spanning);
+        }
+    }
+}
@@ -0,0 +1,822 @@
+use anyhow::{Context, Result};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::value::Table;
+use toml::Value;
+
+/// `[package]` keys that a member may inherit from `[workspace.package]`.
+const INHERITABLE_PACKAGE_KEYS: &[&str] = &[
+    "authors",
+    "badges",
+    "categories",
+    "description",
+    "documentation",
+    "edition",
+    "exclude",
+    "homepage",
+    "include",
+    "keywords",
+    "license",
+    "license-file",
+    "publish",
+    "readme",
+    "repository",
+    "rust-version",
+    "version",
+];
+
+const DEPENDENCY_TABLES: &[&str] = &[
+    "dependencies",
+    "dev-dependencies",
+    "dev_dependencies",
+    "build-dependencies",
+    "build_dependencies",
+];
+
+/// A dependency that a root manifest replaces with `[patch]` or `[replace]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatchedDependency {
+    /// Name of the replaced crate.
+    pub name: String,
+    /// The table replacing it, e.g. `[patch.crates-io]`.
+    pub table: String,
+    /// Where the replacement comes from, e.g. `git https://… (branch fix)`.
+    pub source: String,
+}
+
+/// A parsed workspace root manifest (a Cargo.toml containing `[workspace]`).
+#[derive(Debug, Clone)]
+pub struct WorkspaceRoot {
+    /// Directory containing the workspace root Cargo.toml.
+    pub dir: PathBuf,
+    /// Path of the workspace root Cargo.toml.
+    pub manifest_path: PathBuf,
+    manifest: Value,
+}
+
+impl WorkspaceRoot {
+    /// Load `cargo_toml` as a workspace root. Returns `None` when the manifest
+    /// has no `[workspace]` table.
+    pub fn load(cargo_toml: &Path) -> Result<Option<WorkspaceRoot>> {
+        let content = fs::read_to_string(cargo_toml)
+            .with_context(|| format!("Failed to read Cargo.toml: {:?}", cargo_toml))?;
+        let manifest: Value = toml::from_str(&content)
+            .with_context(|| format!("Failed to parse Cargo.toml: {:?}", cargo_toml))?;
+        Ok(Self::from_manifest(cargo_toml, manifest))
+    }
+
+    pub fn from_manifest(cargo_toml: &Path, manifest: Value) -> Option<WorkspaceRoot> {
+        manifest.get("workspace").and_then(Value::as_table)?;
+        let dir = cargo_toml
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| PathBuf::from("."));
+        Some(WorkspaceRoot {
+            dir,
+            manifest_path: cargo_toml.to_path_buf(),
+            manifest,
+        })
+    }
+
+    /// Locate the workspace root that `member_toml` inherits from.
+    ///
+    /// Honors an explicit `package.workspace` path, otherwise walks up from the
+    /// member's directory (the member itself included) to the first Cargo.toml
+    /// that has a `[workspace]` table.
+    pub fn discover(member_toml: &Path) -> Result<Option<WorkspaceRoot>> {
+        let member_toml = &fs::canonicalize(member_toml)
+            .with_context(|| format!("Failed to resolve path: {:?}", member_toml))?;
+        let content = fs::read_to_string(member_toml)
+            .with_context(|| format!("Failed to read Cargo.toml: {:?}", member_toml))?;
+        let member: Value = toml::from_str(&content)
+            .with_context(|| format!("Failed to parse Cargo.toml: {:?}", member_toml))?;
+        let member_dir = member_toml.parent().unwrap_or_else(|| Path::new("."));
+
+        let explicit = member
+            .get("package")
+            .and_then(|package| package.get("workspace"))
+            .and_then(Value::as_str);
+        if let Some(root) = explicit {
+            let root_toml = member_dir.join(root).join("Cargo.toml");
+            return match Self::load(&root_toml)? {
+                Some(workspace) => Ok(Some(workspace)),
+                None => anyhow::bail!(
+                    "package.workspace of {:?} points to {:?}, which has no [workspace]",
+                    member_toml,
+                    root_toml
+                ),
+            };
+        }
+
+        if let Some(workspace) = Self::from_manifest(member_toml, member) {
+            return Ok(Some(workspace));
+        }
+        for dir in member_dir.ancestors().skip(1) {
+            let candidate = dir.join("Cargo.toml");
+            if candidate.is_file() {
+                if let Some(workspace) = Self::load(&candidate)? {
+                    return Ok(Some(workspace));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Whether the root manifest is virtual, i.e. it has no `[package]` of its own.
+    pub fn is_virtual(&self) -> bool {
+        self.manifest.get("package").is_none()
+    }
+
+    /// The dependencies the root manifest patches, see
+    /// [`patched_dependencies`].
+    pub fn patched_dependencies(&self) -> Vec<PatchedDependency> {
+        patched_dependencies(&self.manifest)
+    }
+
+    fn workspace(&self) -> &Value {
+        // `from_manifest` guarantees the table exists.
+        &self.manifest["workspace"]
+    }
+
+    fn workspace_table(&self, key: &str) -> Option<&Table> {
+        self.workspace().get(key).and_then(Value::as_table)
+    }
+
+    /// Enumerate the Cargo.toml of every workspace member, expanding globs in
+    /// `workspace.members` and dropping anything listed in `workspace.exclude`.
+    /// A non-virtual root is reported first as a member of itself.
+    pub fn member_manifests(&self) -> Result<Vec<PathBuf>> {
+        let excluded: BTreeSet<PathBuf> = self
+            .workspace()
+            .get("exclude")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .map(|path| normalize_member_dir(&self.dir.join(path)))
+            .collect();
+
+        let mut seen = BTreeSet::new();
+        let mut manifests = Vec::new();
+        let mut push_member = |dir: PathBuf| {
+            let dir = normalize_member_dir(&dir);
+            let manifest = dir.join("Cargo.toml");
+            if excluded.contains(&dir) || !manifest.is_file() || !seen.insert(dir) {
+                return;
+            }
+            manifests.push(manifest);
+        };
+
+        if !self.is_virtual() {
+            push_member(self.dir.clone());
+        }
+
+        let members = self
+            .workspace()
+            .get("members")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str);
+        for member in members {
+            if member.contains(['*', '?', '[']) {
+                let pattern = self.dir.join(member);
+                let pattern = pattern.to_string_lossy();
+                let mut matches: Vec<PathBuf> = glob::glob(&pattern)
+                    .with_context(|| format!("Invalid workspace member pattern: {}", member))?
+                    .filter_map(|entry| entry.ok())
+                    .filter(|path| path.is_dir())
+                    .collect();
+                matches.sort();
+                for path in matches {
+                    push_member(path);
+                }
+            } else {
+                let dir = self.dir.join(member);
+                if !dir.join("Cargo.toml").is_file() {
+                    takopack_warn!(
+                        "workspace member {} has no Cargo.toml under {}",
+                        member,
+                        self.dir.display()
+                    );
+                }
+                push_member(dir);
+            }
+        }
+
+        Ok(manifests)
+    }
+
+    /// Substitute every `workspace = true` field of a member manifest with the
+    /// value declared by this workspace, so the member can be parsed on its own.
+    ///
+    /// `member_dir` is the directory holding the member's Cargo.toml; inherited
+    /// `readme` and `license-file` paths are rewritten relative to it.
+    pub fn inherit_into(&self, member: &mut Value, member_dir: &Path) -> Result<()> {
+        let Some(member) = member.as_table_mut() else {
+            anyhow::bail!("member manifest is not a TOML table");
+        };
+
+        if let Some(package) = member.get_mut("package").and_then(Value::as_table_mut) {
+            // The member is materialized standalone, so drop any pointer back
+            // to its workspace root.
+            package.remove("workspace");
+            self.inherit_package_fields(package, member_dir)?;
+        }
+
+        for key in DEPENDENCY_TABLES {
+            if let Some(deps) = member.get_mut(*key).and_then(Value::as_table_mut) {
+                self.inherit_dependencies(deps)?;
+            }
+        }
+        if let Some(targets) = member.get_mut("target").and_then(Value::as_table_mut) {
+            for target in targets.iter_mut().filter_map(|(_, v)| v.as_table_mut()) {
+                for key in DEPENDENCY_TABLES {
+                    if let Some(deps) = target.get_mut(*key).and_then(Value::as_table_mut) {
+                        self.inherit_dependencies(deps)?;
+                    }
+                }
+            }
+        }
+
+        if member.get("lints").is_some_and(is_workspace_inherited) {
+            match self.workspace().get("lints") {
+                Some(lints) => {
+                    member.insert("lints".to_string(), lints.clone());
+                }
+                None => {
+                    member.remove("lints");
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn inherit_package_fields(&self, package: &mut Table, member_dir: &Path) -> Result<()> {
+        let workspace_package = self.workspace_table("package");
+        for (key, value) in package.iter_mut() {
+            if !is_workspace_inherited(value) {
+                continue;
+            }
+            if !INHERITABLE_PACKAGE_KEYS.contains(&key.as_str()) {
+                anyhow::bail!("package.{} cannot be inherited from the workspace", key);
+            }
+            let Some(inherited) = workspace_package.and_then(|p| p.get(key)) else {
+                anyhow::bail!(
+                    "package.{} is inherited but [workspace.package] in {:?} does not define it",
+                    key,
+                    self.manifest_path
+                );
+            };
+            *value = match (key.as_str(), inherited) {
+                // Like cargo, paths in [workspace.package] are relative to the
+                // workspace root, not to the member inheriting them.
+                ("readme" | "license-file", Value::String(path)) => {
+                    Value::String(self.rebase_path(path, member_dir))
+                }
+                _ => inherited.clone(),
+            };
+        }
+        Ok(())
+    }
+
+    /// Rewrite `path`, relative to the workspace root, so that it is relative
+    /// to `member_dir` instead.
+    fn rebase_path(&self, path: &str, member_dir: &Path) -> String {
+        let target = normalize_member_dir(&self.dir).join(path);
+        let base = normalize_member_dir(member_dir);
+        relative_path(&target, &base)
+            .unwrap_or(target)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn inherit_dependencies(&self, deps: &mut Table) -> Result<()> {
+        let workspace_deps = self.workspace_table("dependencies");
+        for (name, dep) in deps.iter_mut() {
+            if !is_workspace_inherited(dep) {
+                continue;
+            }
+            let Some(inherited) = workspace_deps.and_then(|d| d.get(name)) else {
+                anyhow::bail!(
+                    "dependency `{}` uses workspace = true but [workspace.dependencies] in {:?} does not define it",
+                    name,
+                    self.manifest_path
+                );
+            };
+            *dep = Value::Table(self.merge_inherited_dependency(inherited, dep));
+        }
+        Ok(())
+    }
+
+    /// Combine a `[workspace.dependencies]` entry with the member-local keys.
+    /// Cargo lets the member add `features` and choose `optional`; everything
+    /// else comes from the workspace.
+    fn merge_inherited_dependency(&self, inherited: &Value, member: &Value) -> Table {
+        let mut merged = match inherited {
+            Value::String(version) => {
+                let mut table = Table::new();
+                table.insert("version".to_string(), Value::String(version.clone()));
+                table
+            }
+            Value::Table(table) => table.clone(),
+            _ => Table::new(),
+        };
+
+        if let Some(Value::String(path)) = merged.get("path") {
+            let path = self.dir.join(path).to_string_lossy().into_owned();
+            merged.insert("path".to_string(), Value::String(path));
+        }
+
+        let Some(member) = member.as_table() else {
+            return merged;
+        };
+        for (key, value) in member {
+            match key.as_str() {
+                "workspace" => {}
+                "features" => {
+                    let mut features: Vec<Value> = merged
+                        .get("features")
+                        .and_then(Value::as_array)
+                        .cloned()
+                        .unwrap_or_default();
+                    for feature in value.as_array().into_iter().flatten() {
+                        if !features.contains(feature) {
+                            features.push(feature.clone());
+                        }
+                    }
+                    merged.insert("features".to_string(), Value::Array(features));
+                }
+                _ => {
+                    merged.insert(key.clone(), value.clone());
+                }
+            }
+        }
+        merged
+    }
+}
+
+/// Whether a manifest value is `{ workspace = true }`.
+pub fn is_workspace_inherited(value: &Value) -> bool {
+    value
+        .get("workspace")
+        .and_then(Value::as_bool)
+        .unwrap_or(false)
+}
+
+/// Whether any field of `manifest` is inherited with `workspace = true`.
+pub fn manifest_inherits_from_workspace(manifest: &Value) -> bool {
+    let inherits_deps = |table: &Value| {
+        DEPENDENCY_TABLES.iter().any(|key| {
+            table
+                .get(*key)
+                .and_then(Value::as_table)
+                .is_some_and(|deps| deps.values().any(is_workspace_inherited))
+        })
+    };
+
+    manifest
+        .get("package")
+        .and_then(Value::as_table)
+        .is_some_and(|package| package.values().any(is_workspace_inherited))
+        || inherits_deps(manifest)
+        || manifest
+            .get("target")
+            .and_then(Value::as_table)
+            .is_some_and(|targets| targets.values().any(inherits_deps))
+        || manifest.get("lints").is_some_and(is_workspace_inherited)
+}
+
+/// The dependencies that `manifest` replaces in `[patch.<registry>]` and
+/// `[replace]`. Cargo only honors these tables in the root manifest of a
+/// workspace, or of a package outside any workspace.
+pub fn patched_dependencies(manifest: &Value) -> Vec<PatchedDependency> {
+    let mut patched = Vec::new();
+    let registries = manifest
+        .get("patch")
+        .and_then(Value::as_table)
+        .into_iter()
+        .flatten();
+    for (registry, patches) in registries {
+        for (key, value) in patches.as_table().into_iter().flatten() {
+            // `foo2 = { git = "…", package = "foo" }` patches foo
+            let name = value.get("package").and_then(Value::as_str).unwrap_or(key);
+            patched.push(PatchedDependency {
+                name: name.to_string(),
+                table: format!("[patch.{}]", registry),
+                source: replacement_source(value),
+            });
+        }
+    }
+    let replaced = manifest
+        .get("replace")
+        .and_then(Value::as_table)
+        .into_iter()
+        .flatten();
+    for (spec, value) in replaced {
+        // The key is a package id spec, `foo:1.2.3` or `foo@1.2.3`
+        let name = spec.split([':', '@']).next().unwrap_or(spec);
+        patched.push(PatchedDependency {
+            name: name.to_string(),
+            table: "[replace]".to_string(),
+            source: replacement_source(value),
+        });
+    }
+    patched
+}
+
+fn replacement_source(value: &Value) -> String {
+    if let Some(git) = value.get("git").and_then(Value::as_str) {
+        let reference = ["branch", "tag", "rev"].iter().find_map(|key| {
+            value
+                .get(*key)
+                .and_then(Value::as_str)
+                .map(|reference| format!(" ({} {})", key, reference))
+        });
+        format!("git {}{}", git, reference.unwrap_or_default())
+    } else if let Some(path) = value.get("path").and_then(Value::as_str) {
+        format!("path {}", path)
+    } else if let Some(version) = value
+        .as_str()
+        .or_else(|| value.get("version").and_then(Value::as_str))
+    {
+        format!("version {}", version)
+    } else {
+        "an unknown source".to_string()
+    }
+}
+
+fn normalize_member_dir(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Lexically express `target` relative to `base`. Returns `None` when no such
+/// path exists, e.g. between a relative and an absolute path.
+fn relative_path(target: &Path, base: &Path) -> Option<PathBuf> {
+    use std::path::Component;
+
+    fn resolve(path: &Path) -> Vec<Component<'_>> {
+        let mut parts: Vec<Component> = Vec::new();
+        for component in path.components() {
+            match component {
+                Component::CurDir => {}
+                Component::ParentDir if matches!(parts.last(), Some(Component::Normal(_))) => {
+                    parts.pop();
+                }
+                other => parts.push(other),
+            }
+        }
+        parts
+    }
+
+    if target.is_absolute() != base.is_absolute() {
+        return None;
+    }
+    let target = resolve(target);
+    let base = resolve(base);
+
+    let common = target.iter().zip(&base).take_while(|(a, b)| a == b).count();
+    let mut relative = PathBuf::new();
+    for component in &base[common..] {
+        if !matches!(component, Component::Normal(_)) {
+            return None;
+        }
+        relative.push("..");
+    }
+    for component in &target[common..] {
+        relative.push(component.as_os_str());
+    }
+    if relative.as_os_str().is_empty() {
+        relative.push(".");
+    }
+    Some(relative)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{manifest_inherits_from_workspace, patched_dependencies, WorkspaceRoot};
+    use std::fs;
+    use toml::Value;
+
+    fn write_workspace(root: &std::path::Path) {
+        fs::write(
+            root.join("Cargo.toml"),
+            r#"
+[workspace]
+members = ["crates/*", "tools/cli"]
+exclude = ["crates/skipped"]
+
+[workspace.package]
+version = "1.4.0"
+edition = "2021"
+license = "MIT"
+
+[workspace.dependencies]
+serde = { version = "1.0.200", default-features = false, features = ["derive"] }
+anyhow = "1"
+shared = { path = "crates/shared" }
+"#,
+        )
+        .unwrap();
+        for member in [
+            "crates/core",
+            "crates/shared",
+            "crates/skipped",
+            "tools/cli",
+        ] {
+            let dir = root.join(member);
+            fs::create_dir_all(&dir).unwrap();
+            let name = member.rsplit('/').next().unwrap();
+            fs::write(
+                dir.join("Cargo.toml"),
+                format!("[package]\nname = \"{name}\"\nversion.workspace = true\n"),
+            )
+            .unwrap();
+        }
+        fs::create_dir_all(root.join("crates/not-a-crate")).unwrap();
+    }
+
+    #[test]
+    fn member_manifests_expand_globs_and_honor_exclude() {
+        let temp = tempfile::tempdir().unwrap();
+        write_workspace(temp.path());
+
+        let workspace = WorkspaceRoot::load(&temp.path().join("Cargo.toml"))
+            .unwrap()
+            .unwrap();
+        assert!(workspace.is_virtual());
+
+        let root = fs::canonicalize(temp.path()).unwrap();
+        let members: Vec<_> = workspace
+            .member_manifests()
+            .unwrap()
+            .into_iter()
+            .map(|path| path.strip_prefix(&root).unwrap().to_path_buf())
+            .collect();
+        assert_eq!(
+            members,
+            vec![
+                std::path::PathBuf::from("crates/core/Cargo.toml"),
+                std::path::PathBuf::from("crates/shared/Cargo.toml"),
+                std::path::PathBuf::from("tools/cli/Cargo.toml"),
+            ]
+        );
+    }
+
+    #[test]
+    fn load_returns_none_for_plain_package() {
+        let temp = tempfile::tempdir().unwrap();
+        fs::write(
+            temp.path().join("Cargo.toml"),
+            "[package]\nname = \"plain\"\nversion = \"0.1.0\"\n",
+        )
+        .unwrap();
+
+        assert!(WorkspaceRoot::load(&temp.path().join("Cargo.toml"))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn inherit_into_resolves_package_and_dependency_fields() {
+        let temp = tempfile::tempdir().unwrap();
+        write_workspace(temp.path());
+        let workspace = WorkspaceRoot::load(&temp.path().join("Cargo.toml"))
+            .unwrap()
+            .unwrap();
+
+        let mut member: Value = toml::from_str(
+            r#"
+[package]
+name = "core"
+version.workspace = true
+edition = { workspace = true }
+workspace = "../.."
+
+[dependencies]
+serde = { workspace = true, features = ["rc"], optional = true }
+anyhow.workspace = true
+shared = { workspace = true }
+
+[target.'cfg(unix)'.dev-dependencies]
+anyhow = { workspace = true }
+"#,
+        )
+        .unwrap();
+
+        workspace
+            .inherit_into(&mut member, &temp.path().join("crates/core"))
+            .unwrap();
+
+        assert_eq!(member["package"]["version"].as_str(), Some("1.4.0"));
+        assert_eq!(member["package"]["edition"].as_str(), Some("2021"));
+        assert!(member["package"].get("workspace").is_none());
+
+        let serde = &member["dependencies"]["serde"];
+        assert_eq!(serde["version"].as_str(), Some("1.0.200"));
+        assert_eq!(serde["default-features"].as_bool(), Some(false));
+        assert_eq!(serde["optional"].as_bool(), Some(true));
+        let features: Vec<_> = serde["features"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(Value::as_str)
+            .collect();
+        assert_eq!(features, vec!["derive", "rc"]);
+        assert!(serde.get("workspace").is_none());
+
+        assert_eq!(
+            member["dependencies"]["anyhow"]["version"].as_str(),
+            Some("1")
+        );
+        assert!(member["dependencies"]["shared"]["path"]
+            .as_str()
+            .unwrap()
+            .ends_with("crates/shared"));
+        assert_eq!(
+            member["target"]["cfg(unix)"]["dev-dependencies"]["anyhow"]["version"].as_str(),
+            Some("1")
+        );
+    }
+
+    #[test]
+    fn inherit_into_rejects_missing_workspace_dependency() {
+        let temp = tempfile::tempdir().unwrap();
+        write_workspace(temp.path());
+        let workspace = WorkspaceRoot::load(&temp.path().join("Cargo.toml"))
+            .unwrap()
+            .unwrap();
+
+        let mut member: Value =
+            toml::from_str("[package]\nname = \"core\"\n\n[dependencies]\nrand.workspace = true\n")
+                .unwrap();
+
+        let err = workspace
+            .inherit_into(&mut member, &temp.path().join("crates/core"))
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("dependency `rand` uses workspace = true"));
+    }
+
+    #[test]
+    fn inherit_into_rebases_readme_and_license_file() {
+        let temp = tempfile::tempdir().unwrap();
+        fs::write(
+            temp.path().join("Cargo.toml"),
+            r#"
+[workspace]
+members = ["crates/nested/deep"]
+
+[workspace.package]
+readme = "README.md"
+license-file = "docs/LICENSE"
+"#,
+        )
+        .unwrap();
+        let member_dir = temp.path().join("crates/nested/deep");
+        fs::create_dir_all(&member_dir).unwrap();
+        let workspace = WorkspaceRoot::load(&temp.path().join("Cargo.toml"))
+            .unwrap()
+            .unwrap();
+
+        let mut member: Value = toml::from_str(
+            "[package]\nname = \"deep\"\nreadme.workspace = true\nlicense-file.workspace = true\n",
+        )
+        .unwrap();
+        workspace.inherit_into(&mut member, &member_dir).unwrap();
+
+        assert_eq!(
+            member["package"]["readme"].as_str(),
+            Some("../../../README.md")
+        );
+        assert_eq!(
+            member["package"]["license-file"].as_str(),
+            Some("../../../docs/LICENSE")
+        );
+
+        // A member at the workspace root keeps the paths as written.
+        let mut root_package: Value =
+            toml::from_str("[package]\nname = \"root\"\nreadme.workspace = true\n").unwrap();
+        workspace
+            .inherit_into(&mut root_package, temp.path())
+            .unwrap();
+        assert_eq!(
+            root_package["package"]["readme"].as_str(),
+            Some("README.md")
+        );
+    }
+
+    #[test]
+    fn discover_walks_up_to_the_workspace_root() {
+        let temp = tempfile::tempdir().unwrap();
+        write_workspace(temp.path());
+
+        let root = fs::canonicalize(temp.path()).unwrap();
+        let member = temp.path().join("crates/core/Cargo.toml");
+        let workspace = WorkspaceRoot::discover(&member).unwrap().unwrap();
+        assert_eq!(workspace.manifest_path, root.join("Cargo.toml"));
+
+        let outside = tempfile::tempdir().unwrap();
+        fs::write(
+            outside.path().join("Cargo.toml"),
+            "[package]\nname = \"lonely\"\nversion = \"0.1.0\"\n",
+        )
+        .unwrap();
+        assert!(WorkspaceRoot::discover(&outside.path().join("Cargo.toml"))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn discover_honors_explicit_package_workspace() {
+        let temp = tempfile::tempdir().unwrap();
+        write_workspace(temp.path());
+        let nested = temp.path().join("elsewhere/deep");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(
+            nested.join("Cargo.toml"),
+            "[package]\nname = \"deep\"\nworkspace = \"../..\"\nversion.workspace = true\n",
+        )
+        .unwrap();
+
+        let workspace = WorkspaceRoot::discover(&nested.join("Cargo.toml"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            fs::canonicalize(&workspace.dir).unwrap(),
+            fs::canonicalize(temp.path()).unwrap()
+        );
+        assert!(workspace.is_virtual());
+    }
+
+    #[test]
+    fn manifest_inheritance_detection_covers_targets() {
+        let plain: Value =
+            toml::from_str("[package]\nname = \"a\"\n\n[dependencies]\nserde = \"1\"\n").unwrap();
+        assert!(!manifest_inherits_from_workspace(&plain));
+
+        let target: Value =
+            toml::from_str("[target.'cfg(unix)'.dependencies]\nlibc.workspace = true\n").unwrap();
+        assert!(manifest_inherits_from_workspace(&target));
+
+        let package: Value =
+            toml::from_str("[package]\nname = \"a\"\nlicense.workspace = true\n").unwrap();
+        assert!(manifest_inherits_from_workspace(&package));
+    }
+
+    #[test]
+    fn patched_dependencies_cover_patch_and_replace() {
+        let manifest: Value = toml::from_str(
+            r#"
+[patch.crates-io]
+serde = { git = "https://github.com/me/serde", branch = "fix" }
+log2 = { path = "../log", package = "log" }
+
+[patch."https://github.com/rust-lang/regex"]
+regex = { path = "vendor/regex" }
+
+[replace]
+"libc:0.2.150" = { git = "https://github.com/me/libc", rev = "abc123" }
+"#,
+        )
+        .unwrap();
+        let patched: Vec<(String, String, String)> = patched_dependencies(&manifest)
+            .into_iter()
+            .map(|p| (p.name, p.table, p.source))
+            .collect();
+        let expected = [
+            ("log", "[patch.crates-io]", "path ../log"),
+            (
+                "serde",
+                "[patch.crates-io]",
+                "git https://github.com/me/serde (branch fix)",
+            ),
+            (
+                "regex",
+                "[patch.https://github.com/rust-lang/regex]",
+                "path vendor/regex",
+            ),
+            (
+                "libc",
+                "[replace]",
+                "git https://github.com/me/libc (rev abc123)",
+            ),
+        ];
+        assert_eq!(patched.len(), expected.len());
+        for (name, table, source) in expected {
+            assert!(
+                patched.contains(&(name.to_string(), table.to_string(), source.to_string())),
+                "{} {} {}",
+                name,
+                table,
+                source
+            );
+        }
+
+        let plain: Value = toml::from_str("[package]\nname = \"a\"\n").unwrap();
+        assert!(patched_dependencies(&plain).is_empty());
+    }
+}
@@ -0,0 +1,240 @@
+//! Packaging versions that crates.io lists as yanked.
+//!
+//! Cargo does not resolve yanked versions, yet a distro sometimes needs one,
+//! e.g. because an already shipped package pins it. `pkg --allow-yanked`
+//! therefore fetches the exact version's `.crate` itself: from the archive
+//! in the `[yanked]` table of takopack.toml (static.crates.io keeps yanked
+//! crates by default), or from the file given with `--crate-file`. The
+//! archive must match the sha256 the index lists, the spec gets a warning
+//! comment, and every such exception is recorded in a JSON file next to
+//! the other takopack data so it can be reviewed later.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+
+use crate::crates::{spec_download_url, verify_crate_checksum, CrateInfo, SparseIndex};
+use crate::errors::*;
+
+/// The `[yanked]` table of takopack.toml.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct YankedArchive {
+    /// Where yanked crates are downloaded from, with `{crate}` and
+    /// `{version}` markers. static.crates.io by default.
+    pub archive_url: String,
+}
+
+impl Default for YankedArchive {
+    fn default() -> Self {
+        YankedArchive {
+            archive_url: "https://static.crates.io/crates/{crate}/{crate}-{version}.crate"
+                .to_string(),
+        }
+    }
+}
+
+impl YankedArchive {
+    /// Archive URL of `crate_name` `version`.
+    pub fn url(&self, crate_name: &str, version: &Version) -> String {
+        self.archive_url
+            .replace("{crate}", crate_name)
+            .replace("{version}", &version.to_string())
+    }
+}
+
+/// A yanked version that was packaged anyway.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct YankedException {
+    pub crate_name: String,
+    pub version: String,
+    /// Archive URL or file the crate was packaged from
+    pub source: String,
+    pub sha256: String,
+    /// When the exception was recorded, RFC 3339
+    pub recorded: String,
+}
+
+/// Packaged yanked versions keyed by `{crate}-{version}`, persisted as JSON.
+#[derive(Debug, Default)]
+pub struct ExceptionStore {
+    path: PathBuf,
+    records: BTreeMap<String, YankedException>,
+}
+
+impl ExceptionStore {
+    /// Load the store at `path`; a missing file is an empty store.
+    pub fn load(path: &Path) -> Result<Self> {
+        let records = match fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text)
+                .with_context(|| format!("Failed to parse {}", path.display()))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        Ok(ExceptionStore {
+            path: path.to_path_buf(),
+            records,
+        })
+    }
+
+    pub fn get(&self, crate_name: &str, version: &str) -> Option<&YankedException> {
+        self.records.get(&format!("{}-{}", crate_name, version))
+    }
+
+    /// Remember `exception`, replacing an earlier record of the version.
+    pub fn record(&mut self, exception: YankedException) {
+        self.records.insert(
+            format!("{}-{}", exception.crate_name, exception.version),
+            exception,
+        );
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let text = serde_json::to_string_pretty(&self.records)?;
+        fs::write(&self.path, text)
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+}
+
+/// The exact version `version` names, with or without a leading `=`.
+fn exact_version(crate_name: &str, version: Option<&str>) -> Result<Version> {
+    let given = version.unwrap_or("").trim();
+    match Version::parse(given.trim_start_matches('=').trim()) {
+        Ok(version) => Ok(version),
+        Err(_) => takopack_bail!(
+            "--allow-yanked needs the exact version of {}, e.g. `{} 1.2.3`; got `{}`",
+            crate_name,
+            crate_name,
+            given
+        ),
+    }
+}
+
+/// [`CrateInfo`] of `crate_name` `version` from the archive of `archive`,
+/// or from `crate_file`. Yanked versions are marked as such and recorded
+/// in the exception store.
+pub fn crate_info(
+    crate_name: &str,
+    version: Option<&str>,
+    archive: &YankedArchive,
+    crate_file: Option<&Path>,
+) -> Result<CrateInfo> {
+    let version = exact_version(crate_name, version)?;
+    let entry = SparseIndex::crates_io()?
+        .versions(crate_name)?
+        .into_iter()
+        .find(|v| v.version == version)
+        .ok_or_else(|| {
+            format_err!(
+                "{} {} was never published on crates.io",
+                crate_name,
+                version
+            )
+        })?;
+    if !entry.yanked {
+        takopack_warn!(
+            "{} {} is not yanked; --allow-yanked is not needed for it",
+            crate_name,
+            version
+        );
+    }
+
+    let stem = format!("{}-{}", crate_name, version);
+    let dir = std::env::temp_dir().join("takopack-yanked").join(&stem);
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let archive_path = dir.join(format!("{}.crate", stem));
+    let origin = match crate_file {
+        Some(file) => {
+            fs::copy(file, &archive_path)
+                .with_context(|| format!("Failed to copy {}", file.display()))?;
+            file.display().to_string()
+        }
+        None => {
+            let url = archive.url(crate_name, &version);
+            log::info!("Downloading yanked {} from {}", stem, url);
+            download(&url, &archive_path)?;
+            url
+        }
+    };
+    verify_crate_checksum(&archive_path, &entry.cksum)?;
+
+    let mut info = CrateInfo::new_from_mirror(crate_name, Some(&format!("={}", version)), &dir)?;
+    if crate_file.is_none() {
+        info.set_download_url(&spec_download_url(
+            &archive.archive_url,
+            crate_name,
+            Some(&entry.cksum),
+        ));
+    }
+    if entry.yanked {
+        info.mark_yanked();
+        let mut store = ExceptionStore::load(&crate::paths::yanked_exceptions_file()?)?;
+        store.record(YankedException {
+            crate_name: crate_name.to_string(),
+            version: version.to_string(),
+            source: origin,
+            sha256: entry.cksum,
+            recorded: chrono::Local::now().to_rfc3339(),
+        });
+        store.save()?;
+    }
+    Ok(info)
+}
+
+fn download(url: &str, destination: &Path) -> Result<()> {
+    let response = crate::http::agent()
+        .get(url)
+        .call()
+        .with_context(|| format!("Failed to download {}", url))?;
+    let mut file = fs::File::create(destination)
+        .with_context(|| format!("Failed to create {}", destination.display()))?;
+    io::copy(&mut response.into_reader(), &mut file)
+        .with_context(|| format!("Failed to download {}", url))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{exact_version, ExceptionStore, YankedArchive, YankedException};
+    use semver::Version;
+
+    #[test]
+    fn yanked_exceptions_are_recorded_for_exact_versions() {
+        let version = exact_version("log", Some("=0.4.11")).unwrap();
+        assert_eq!(version, Version::new(0, 4, 11));
+        assert!(exact_version("log", Some("^0.4")).is_err());
+        assert!(exact_version("log", None).is_err());
+        assert_eq!(
+            YankedArchive::default().url("log", &version),
+            "https://static.crates.io/crates/log/log-0.4.11.crate"
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data/yanked-exceptions.json");
+        let mut store = ExceptionStore::load(&path).unwrap();
+        assert!(store.get("log", "0.4.11").is_none());
+        store.record(YankedException {
+            crate_name: "log".to_string(),
+            version: "0.4.11".to_string(),
+            source: "/tmp/log-0.4.11.crate".to_string(),
+            sha256: "abc".to_string(),
+            recorded: "2024-01-01T00:00:00+00:00".to_string(),
+        });
+        store.save().unwrap();
+
+        let store = ExceptionStore::load(&path).unwrap();
+        assert_eq!(
+            store.get("log", "0.4.11").map(|e| e.source.as_str()),
+            Some("/tmp/log-0.4.11.crate")
+        );
+    }
+}